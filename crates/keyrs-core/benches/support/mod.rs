@@ -0,0 +1,84 @@
+// Production-like transform fixture shared by benches/transform.rs and
+// tests/latency_budget_test.rs
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+use keyrs_core::config::Config;
+use keyrs_core::transform::engine::TransformEngine;
+use keyrs_core::{Action, Key};
+
+/// Synthetic app keymaps appended to the shipped config
+pub const SYNTHETIC_KEYMAPS: usize = 300;
+
+/// Condition shaped like the generated ones, matching the last synthetic app
+pub const DEEP_CONDITION: &str = "(wm_class =~ '(?i)^app299$|^org.example.app299$' or wm_name =~ 'Project 299') \
+     and not settings.BenchOff4 and not (numlk or capslk) and not (devn =~ 'Virtual')";
+
+const LETTERS: &[char] = &['A', 'B', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'O', 'P', 'R', 'U'];
+
+/// The repository's `config.toml` plus `SYNTHETIC_KEYMAPS` conditional keymaps
+pub fn production_config() -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../config.toml");
+    let mut content = fs::read_to_string(path).expect("failed to read config.toml");
+
+    for i in 0..SYNTHETIC_KEYMAPS {
+        content.push_str(&format!(
+            "\n[[keymap]]\nname = \"bench_app_{i}\"\n\
+             condition = \"(wm_class =~ '(?i)^app{i}$|^org.example.app{i}$' or wm_name =~ 'Project {i}') \
+             and not settings.BenchOff{} and not (numlk or capslk) and not (devn =~ 'Virtual')\"\n\n\
+             [keymap.mappings]\n",
+            i % 7
+        ));
+        for (n, letter) in LETTERS.iter().enumerate().skip(i % 5).take(4) {
+            let output = LETTERS[(n + i) % LETTERS.len()];
+            content.push_str(&format!("Super-Alt-{letter} = \"C-Shift-{output}\"\n"));
+        }
+    }
+    content
+}
+
+/// Engine for `production_config` focused on the last synthetic app
+pub fn production_engine() -> TransformEngine {
+    let config = Config::from_toml(&production_config()).expect("failed to parse bench config");
+    let mut engine = TransformEngine::new(config.to_transform_config());
+    engine.update_window_context(
+        Some("app299".to_string()),
+        Some("Project 299 - Editor".to_string()),
+    );
+    engine
+}
+
+/// A typing burst: plain letters, a shortcut, a synthetic-app shortcut and an
+/// unmapped chord, each pressed and released
+pub fn typing_burst() -> Vec<(Key, Action)> {
+    const LEFT_CTRL: u16 = 29;
+    const LEFT_SHIFT: u16 = 42;
+    const LEFT_ALT: u16 = 56;
+    const LEFT_META: u16 = 125;
+    const KEY_C: u16 = 46;
+    const KEY_H: u16 = 35;
+    const KEY_T: u16 = 20;
+    const KEY_E: u16 = 18;
+    const KEY_Q: u16 = 16;
+
+    let mut events = Vec::new();
+    let tap = |events: &mut Vec<(Key, Action)>, mods: &[u16], key: u16| {
+        for m in mods {
+            events.push((Key::from(*m), Action::Press));
+        }
+        events.push((Key::from(key), Action::Press));
+        events.push((Key::from(key), Action::Release));
+        for m in mods.iter().rev() {
+            events.push((Key::from(*m), Action::Release));
+        }
+    };
+    for key in [KEY_T, KEY_H, KEY_E] {
+        tap(&mut events, &[], key);
+    }
+    tap(&mut events, &[LEFT_META], KEY_C);
+    tap(&mut events, &[LEFT_META, LEFT_ALT], KEY_H);
+    tap(&mut events, &[LEFT_CTRL, LEFT_SHIFT], KEY_Q);
+    events
+}
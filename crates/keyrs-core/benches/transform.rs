@@ -0,0 +1,90 @@
+// Keyrs transform hot path benchmarks
+//
+// Run with `cargo bench -p keyrs-core --features pure-rust`. The latency
+// budget these numbers must stay under is asserted by
+// tests/latency_budget_test.rs.
+
+mod support;
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use keyrs_core::config::{expand_combo, parse_combo_string};
+use keyrs_core::transform::engine::WindowContext;
+use keyrs_core::{Action, Combo};
+
+fn bench_process_event(c: &mut Criterion) {
+    let mut engine = support::production_engine();
+    let burst = support::typing_burst();
+
+    c.bench_function("process_event/typing_burst", |b| {
+        b.iter(|| {
+            for &(key, action) in &burst {
+                black_box(engine.process_event(key, action));
+            }
+        })
+    });
+
+    // Parsing the whole config takes long enough that fewer samples do
+    let mut load = c.benchmark_group("engine");
+    load.sample_size(10);
+    load.bench_function("load_production_config", |b| {
+        let content = support::production_config();
+        b.iter_batched(
+            || content.clone(),
+            |content| {
+                let config = keyrs_core::config::Config::from_toml(&content).unwrap();
+                black_box(keyrs_core::transform::engine::TransformEngine::new(
+                    config.to_transform_config(),
+                ))
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    load.finish();
+
+    // Conditions are re-evaluated by the first key after the switch
+    c.bench_function("engine/window_switch", |b| {
+        let (key, _) = burst[0];
+        let mut flip = false;
+        b.iter(|| {
+            flip = !flip;
+            let class = if flip { "app150" } else { "app299" };
+            engine.update_window_context(Some(class.to_string()), None);
+            black_box(engine.process_event(key, Action::Press));
+            black_box(engine.process_event(key, Action::Release));
+        })
+    });
+}
+
+fn bench_combo_expansion(c: &mut Criterion) {
+    let parsed = parse_combo_string("Ctrl-Alt-Shift-Super-K").unwrap();
+    let combo = Combo::new(parsed.modifiers, parsed.key);
+
+    c.bench_function("combo/parse", |b| {
+        b.iter(|| black_box(parse_combo_string(black_box("Ctrl-Alt-Shift-Super-K"))))
+    });
+    c.bench_function("combo/expand_four_modifiers", |b| {
+        b.iter(|| black_box(expand_combo(black_box(&combo))))
+    });
+}
+
+fn bench_condition_evaluation(c: &mut Criterion) {
+    let mut context = WindowContext::new();
+    context.update(Some("app299".to_string()), Some("Project 299 - Editor".to_string()));
+
+    c.bench_function("condition/deep_match", |b| {
+        b.iter(|| black_box(context.matches_condition(black_box(support::DEEP_CONDITION))))
+    });
+    c.bench_function("condition/simple_miss", |b| {
+        b.iter(|| black_box(context.matches_condition(black_box("wm_class =~ '(?i)firefox'"))))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_process_event,
+    bench_combo_expansion,
+    bench_condition_evaluation
+);
+criterion_main!(benches);
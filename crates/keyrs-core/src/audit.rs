@@ -0,0 +1,379 @@
+// Keyrs Audit Log
+// Ring buffer of the last emitted outputs and the inputs behind them, with replay
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::ManualClock;
+use crate::transform::{TransformEngine, TransformResult};
+use crate::{Action, Key};
+
+/// Entries kept by `[audit]` unless `capacity` says otherwise
+pub const DEFAULT_AUDIT_CAPACITY: usize = 500;
+
+/// Format version written to dumps
+pub const AUDIT_DUMP_VERSION: u32 = 1;
+
+/// Errors reading an audit dump
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid audit dump: {0}")]
+    Invalid(String),
+}
+
+/// Key event that produced an entry's outputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditInput {
+    /// Key code (after `[scancodes]`)
+    pub code: u16,
+    /// Key name, for reading the dump
+    #[serde(default)]
+    pub key: String,
+    /// `press`, `release` or `repeat`
+    pub action: String,
+}
+
+impl AuditInput {
+    fn new(key: Key, action: Action) -> Self {
+        Self {
+            code: key.code(),
+            key: key.to_string(),
+            action: action.to_string(),
+        }
+    }
+
+    fn parse(&self) -> Result<(Key, Action), AuditError> {
+        let action = match self.action.as_str() {
+            "press" => Action::Press,
+            "release" => Action::Release,
+            "repeat" => Action::Repeat,
+            other => return Err(AuditError::Invalid(format!("unknown action '{}'", other))),
+        };
+        Ok((Key::from(self.code), action))
+    }
+}
+
+/// One processed input, or one timer firing, and what it emitted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Microseconds since the log was started
+    pub t_us: u64,
+    /// `None` when a timer fired (tap/hold, autoshift, repeat, dead key) or
+    /// the focus change suspended remapping
+    #[serde(default)]
+    pub input: Option<AuditInput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wm_class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wm_name: Option<String>,
+    /// Outputs in emission order, e.g. `press combo Ctrl-C`
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+impl fmt::Display for AuditEntry {
+    /// `  1234.567ms press A -> press passthrough A`; timer entries show `timer`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let input = match &self.input {
+            Some(input) => format!("{} {}", input.action, Key::from(input.code)),
+            None => "timer".to_string(),
+        };
+        let outputs = if self.outputs.is_empty() { "nothing".to_string() } else { self.outputs.join("; ") };
+        write!(f, "{:>10.3}ms {} -> {}", self.t_us as f64 / 1000.0, input, outputs)
+    }
+}
+
+/// Focused window and event device an entry was processed in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditContext {
+    pub device: Option<String>,
+    pub wm_class: Option<String>,
+    pub wm_name: Option<String>,
+}
+
+/// The last `capacity` entries, oldest first (`[audit]`)
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    capacity: usize,
+    started: Instant,
+    entries: VecDeque<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Empty log whose times count from `started`
+    pub fn new(capacity: usize, started: Instant) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            started,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Add an entry, dropping the oldest when full; suppressed results are left out
+    pub fn record(
+        &mut self,
+        at: Instant,
+        input: Option<(Key, Action)>,
+        context: AuditContext,
+        outputs: &[(TransformResult, Action)],
+    ) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        let elapsed = at.saturating_duration_since(self.started).as_micros();
+        self.entries.push_back(AuditEntry {
+            t_us: elapsed.min(u64::MAX as u128) as u64,
+            input: input.map(|(key, action)| AuditInput::new(key, action)),
+            device: context.device,
+            wm_class: context.wm_class,
+            wm_name: context.wm_name,
+            outputs: outputs
+                .iter()
+                .filter(|(result, _)| *result != TransformResult::Suppress)
+                .map(|(result, action)| describe_output(result, *action))
+                .collect(),
+        });
+    }
+
+    /// Entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Readable form of an emitted result, compared between a dump and its replay
+pub fn describe_output(result: &TransformResult, action: Action) -> String {
+    match result {
+        TransformResult::Passthrough(key) => format!("{} passthrough {}", action, key),
+        TransformResult::Remapped(key) => format!("{} remapped {}", action, key),
+        TransformResult::ComboKey(key) => format!("{} combo {}", action, key),
+        TransformResult::Combo(combo) => format!("{} combo {}", action, combo),
+        TransformResult::Sequence(steps) => {
+            let steps: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+            format!("{} sequence {}", action, steps.join(", "))
+        }
+        TransformResult::Hint(hint) => format!("{} hint {}", action, hint),
+        TransformResult::Unicode(codepoint) => format!("{} unicode U+{:04X}", action, codepoint),
+        TransformResult::Text(text) => format!("{} text {:?}", action, text),
+        TransformResult::Suppress => "suppress".to_string(),
+        TransformResult::Suspend => "suspend".to_string(),
+    }
+}
+
+/// Audit log written on the diagnostics key or sent over the control socket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditDump {
+    pub version: u32,
+    /// Active `[profiles]` name when dumped
+    pub profile: String,
+    /// Default keyboard type when dumped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyboard_type: Option<String>,
+    /// Feature settings when dumped
+    #[serde(default)]
+    pub settings: BTreeMap<String, bool>,
+    pub entries: Vec<AuditEntry>,
+}
+
+impl AuditDump {
+    /// Default dump path (`$XDG_RUNTIME_DIR/keyrs-audit.json`); fails without `XDG_RUNTIME_DIR`
+    pub fn default_path() -> std::io::Result<PathBuf> {
+        crate::runtime::runtime_path("keyrs-audit.json")
+    }
+
+    /// Pretty-printed JSON, as written by `write`
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("audit dumps have string keys")
+    }
+
+    /// Write the dump as JSON, readable by the owner only (it holds what was typed)
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        writeln!(file, "{}", self.to_json())
+    }
+
+    /// Read a dump written by `write` or saved from `keyrs audit`
+    pub fn read(path: &Path) -> Result<Self, AuditError> {
+        let dump: Self = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| AuditError::Invalid(e.to_string()))?;
+        if dump.version != AUDIT_DUMP_VERSION {
+            return Err(AuditError::Invalid(format!("unsupported version {}", dump.version)));
+        }
+        Ok(dump)
+    }
+}
+
+/// Feed the inputs of `dump` through `engine` as the daemon did and return
+/// the entries this run recorded.
+///
+/// `clock` must be the engine's. It is moved to each entry's time, the
+/// timers due by then fire, and each input's window and device are restored
+/// before it is processed. The engine starts from a clean state, so a dump
+/// that begins with keys already held can differ at its first entries.
+pub fn replay(
+    engine: &mut TransformEngine,
+    clock: &ManualClock,
+    dump: &AuditDump,
+) -> Result<Vec<AuditEntry>, AuditError> {
+    engine.enable_audit(usize::MAX);
+    let base = dump.entries.first().map_or(0, |entry| entry.t_us);
+    let mut elapsed = 0;
+    let mut window = None;
+    for entry in &dump.entries {
+        let at = entry.t_us.saturating_sub(base);
+        if at > elapsed {
+            clock.advance(Duration::from_micros(at - elapsed));
+            elapsed = at;
+        }
+
+        let context = Some((entry.wm_class.clone(), entry.wm_name.clone()));
+        if window != context {
+            engine.update_window_context(entry.wm_class.clone(), entry.wm_name.clone());
+            engine.check_window_suspend();
+            window = context;
+        }
+        fire_due_timers(engine);
+
+        if let Some(input) = &entry.input {
+            let (key, action) = input.parse()?;
+            engine.set_device_name(entry.device.clone());
+            engine.process_event(key, action);
+            engine.take_deferred_results();
+            engine.take_pending_outputs();
+            engine.take_combo_style();
+        }
+    }
+    Ok(engine.audit().map(|log| log.entries().cloned().collect()).unwrap_or_default())
+}
+
+/// Run every timer check, in the daemon's order
+fn fire_due_timers(engine: &mut TransformEngine) {
+    engine.check_multipurpose_timeouts();
+    engine.check_layer_rollover();
+    engine.check_autoshift();
+    engine.check_tap_hold();
+    engine.check_dead_key_timeout();
+    engine.check_synthetic_repeat();
+}
+
+/// Index of the first entry whose input or outputs differ between a dump
+/// and its replay; times and window details are not compared
+pub fn first_divergence(recorded: &[AuditEntry], replayed: &[AuditEntry]) -> Option<usize> {
+    let differs = |(a, b): (&AuditEntry, &AuditEntry)| a.input != b.input || a.outputs != b.outputs;
+    match recorded.iter().zip(replayed).position(differs) {
+        Some(index) => Some(index),
+        None if recorded.len() != replayed.len() => Some(recorded.len().min(replayed.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::settings::Settings;
+    use std::sync::Arc;
+
+    fn manual_engine(toml: &str) -> (TransformEngine, ManualClock) {
+        let clock = ManualClock::new();
+        let config = Config::from_toml(toml).unwrap().to_transform_config();
+        let engine = TransformEngine::with_clock(config, Settings::new(), Arc::new(clock.clone()));
+        (engine, clock)
+    }
+
+    const CONFIG: &str = r#"
+        [[multipurpose]]
+        name = "Caps"
+        trigger = "CAPSLOCK"
+        tap = "ESC"
+        hold = "LEFT_CTRL"
+
+        [[keymap]]
+        name = "general"
+        [keymap.mappings]
+        "Alt-c" = "C-c"
+    "#;
+
+    #[test]
+    fn test_audit_log_is_a_ring_buffer() {
+        let (mut engine, _clock) = manual_engine(CONFIG);
+        assert!(engine.audit().is_none());
+        engine.enable_audit(3);
+        for _ in 0..2 {
+            engine.process_event(Key::from(30), Action::Press);
+            engine.process_event(Key::from(30), Action::Release);
+        }
+        let log = engine.audit().unwrap();
+        assert_eq!(log.len(), 3);
+        let first = log.entries().next().unwrap();
+        assert_eq!(first.input.as_ref().unwrap().action, "release");
+        assert_eq!(first.outputs, vec!["release passthrough A".to_string()]);
+        assert_eq!(first.to_string(), "     0.000ms release A -> release passthrough A");
+    }
+
+    #[test]
+    fn test_replay_reproduces_dump() {
+        let (mut engine, clock) = manual_engine(CONFIG);
+        engine.enable_audit(DEFAULT_AUDIT_CAPACITY);
+        let (caps, alt, c) = (Key::from(58), Key::from(56), Key::from(46));
+
+        // Caps held to its timeout: the hold fires from the timer
+        engine.process_event(caps, Action::Press);
+        clock.advance(Duration::from_millis(300));
+        assert!(engine.check_multipurpose_timeouts().is_some());
+        engine.process_event(caps, Action::Release);
+        clock.advance(Duration::from_millis(20));
+        engine.update_window_context(Some("kitty".to_string()), None);
+        engine.process_event(alt, Action::Press);
+        engine.process_event(c, Action::Press);
+        engine.process_event(c, Action::Release);
+        engine.process_event(alt, Action::Release);
+
+        let dump = engine.audit_dump().unwrap();
+        let entries = &dump.entries;
+        assert_eq!(entries.len(), 7);
+        assert!(entries[1].input.is_none());
+        assert_eq!(entries[1].outputs, vec!["press remapped LEFT_CTRL".to_string()]);
+        assert_eq!(entries[4].outputs, vec!["press combo Ctrl-C".to_string()]);
+        assert_eq!(entries[4].wm_class.as_deref(), Some("kitty"));
+
+        let path = std::env::temp_dir().join(format!("keyrs-audit-test-{}.json", std::process::id()));
+        dump.write(&path).unwrap();
+        let read = AuditDump::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read, dump);
+
+        let (mut fresh, fresh_clock) = manual_engine(CONFIG);
+        let replayed = replay(&mut fresh, &fresh_clock, &read).unwrap();
+        assert_eq!(first_divergence(&read.entries, &replayed), None);
+
+        // A config that maps differently shows where the runs part
+        let (mut other, other_clock) = manual_engine("");
+        let replayed = replay(&mut other, &other_clock, &read).unwrap();
+        assert_eq!(first_divergence(&read.entries, &replayed), Some(0));
+    }
+}
@@ -16,6 +16,8 @@ pub enum ComboHint {
     EscapeNextKey = 2,
     Ignore = 3,
     EscapeNextCombo = 4,
+    /// Leave every nested keymap entered so far
+    Reset = 5,
 }
 
 impl ComboHint {
@@ -26,6 +28,7 @@ impl ComboHint {
             2 => Some(ComboHint::EscapeNextKey),
             3 => Some(ComboHint::Ignore),
             4 => Some(ComboHint::EscapeNextCombo),
+            5 => Some(ComboHint::Reset),
             _ => None,
         }
     }
@@ -43,6 +46,7 @@ impl fmt::Display for ComboHint {
             ComboHint::EscapeNextKey => write!(f, "ESCAPE_NEXT_KEY"),
             ComboHint::Ignore => write!(f, "IGNORE"),
             ComboHint::EscapeNextCombo => write!(f, "ESCAPE_NEXT_COMBO"),
+            ComboHint::Reset => write!(f, "RESET"),
         }
     }
 }
@@ -196,6 +200,7 @@ mod tests {
     fn test_combo_hint_from_i32() {
         assert_eq!(ComboHint::from_i32(1), Some(ComboHint::Bind));
         assert_eq!(ComboHint::from_i32(2), Some(ComboHint::EscapeNextKey));
-        assert_eq!(ComboHint::from_i32(5), None);
+        assert_eq!(ComboHint::from_i32(5), Some(ComboHint::Reset));
+        assert_eq!(ComboHint::from_i32(6), None);
     }
 }
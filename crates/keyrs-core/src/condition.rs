@@ -0,0 +1,553 @@
+// Keyrs Condition Language
+// Parse `condition = "..."` expressions once, at config load
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A condition string together with its parsed expression.
+///
+/// Parsing happens when the mapping is built, so event handling only walks
+/// the tree. A string that doesn't parse never matches; `validate_condition`
+/// reports why (as `--check-config` does).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Option<ConditionExpr>,
+}
+
+impl Condition {
+    /// Parse `source`; unparseable conditions are kept but never match
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let expr = parse_condition(&source).ok();
+        Self { source, expr }
+    }
+
+    /// The condition as written in the config
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The parsed expression (None if the source doesn't parse)
+    pub fn expr(&self) -> Option<&ConditionExpr> {
+        self.expr.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ConditionToken {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Eq,
+    Match,
+    Ident(String),
+    StringLit(String),
+    Bool(bool),
+}
+
+/// Comparison operator of a predicate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionOp {
+    /// `==` (case-insensitive equality)
+    Eq,
+    /// `=~` (pattern match)
+    Match,
+}
+
+/// Parsed condition expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionExpr {
+    And(Box<ConditionExpr>, Box<ConditionExpr>),
+    Or(Box<ConditionExpr>, Box<ConditionExpr>),
+    Not(Box<ConditionExpr>),
+    Predicate {
+        field: String,
+        op: Option<ConditionOp>,
+        value: Option<String>,
+    },
+}
+
+impl fmt::Display for ConditionExpr {
+    /// Renders an expression that parses back to `self`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionExpr::And(left, right) => {
+                write_operand(f, left, true)?;
+                f.write_str(" and ")?;
+                write_operand(f, right, true)
+            }
+            ConditionExpr::Or(left, right) => write!(f, "{} or {}", left, right),
+            ConditionExpr::Not(inner) => {
+                f.write_str("not ")?;
+                write_operand(f, inner, false)
+            }
+            ConditionExpr::Predicate { field, op, value } => {
+                f.write_str(field)?;
+                if let (Some(op), Some(value)) = (op, value) {
+                    let op = match op {
+                        ConditionOp::Eq => "==",
+                        ConditionOp::Match => "=~",
+                    };
+                    let quote = if value.contains('\'') { '"' } else { '\'' };
+                    write!(f, " {} {}{}{}", op, quote, value, quote)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parenthesize `expr` where its own operator binds looser than the context
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &ConditionExpr, and_allowed: bool) -> fmt::Result {
+    match expr {
+        ConditionExpr::Or(..) => write!(f, "({})", expr),
+        ConditionExpr::And(..) if !and_allowed => write!(f, "({})", expr),
+        _ => write!(f, "{}", expr),
+    }
+}
+
+struct ConditionParser {
+    tokens: Vec<ConditionToken>,
+    pos: usize,
+}
+
+impl ConditionParser {
+    fn new(tokens: Vec<ConditionToken>) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    fn parse(mut self) -> Option<ConditionExpr> {
+        let expr = self.parse_or()?;
+        if self.peek().is_some() {
+            return None;
+        }
+        Some(expr)
+    }
+
+    fn parse_or(&mut self) -> Option<ConditionExpr> {
+        let mut left = self.parse_and()?;
+        while matches!(self.peek(), Some(ConditionToken::Or)) {
+            self.next();
+            let right = self.parse_and()?;
+            left = ConditionExpr::Or(Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_and(&mut self) -> Option<ConditionExpr> {
+        let mut left = self.parse_not()?;
+        while matches!(self.peek(), Some(ConditionToken::And)) {
+            self.next();
+            let right = self.parse_not()?;
+            left = ConditionExpr::And(Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_not(&mut self) -> Option<ConditionExpr> {
+        if matches!(self.peek(), Some(ConditionToken::Not)) {
+            self.next();
+            let inner = self.parse_not()?;
+            return Some(ConditionExpr::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Option<ConditionExpr> {
+        match self.peek()? {
+            ConditionToken::LParen => {
+                self.next();
+                let expr = self.parse_or()?;
+                if !matches!(self.next(), Some(ConditionToken::RParen)) {
+                    return None;
+                }
+                Some(expr)
+            }
+            ConditionToken::Ident(_) => self.parse_predicate(),
+            _ => None,
+        }
+    }
+
+    fn parse_predicate(&mut self) -> Option<ConditionExpr> {
+        let field = match self.next()? {
+            ConditionToken::Ident(s) => s,
+            _ => return None,
+        };
+
+        match self.peek() {
+            Some(ConditionToken::Eq) => {
+                self.next();
+                let value = self.parse_value()?;
+                Some(ConditionExpr::Predicate {
+                    field,
+                    op: Some(ConditionOp::Eq),
+                    value: Some(value),
+                })
+            }
+            Some(ConditionToken::Match) => {
+                self.next();
+                let value = self.parse_value()?;
+                Some(ConditionExpr::Predicate {
+                    field,
+                    op: Some(ConditionOp::Match),
+                    value: Some(value),
+                })
+            }
+            _ => Some(ConditionExpr::Predicate {
+                field,
+                op: None,
+                value: None,
+            }),
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<String> {
+        match self.next()? {
+            ConditionToken::StringLit(s) => Some(s),
+            ConditionToken::Ident(s) => Some(s),
+            ConditionToken::Bool(b) => Some(if b { "true".to_string() } else { "false".to_string() }),
+            _ => None,
+        }
+    }
+
+    fn peek(&self) -> Option<&ConditionToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<ConditionToken> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+}
+
+fn tokenize_condition(condition: &str) -> Option<Vec<ConditionToken>> {
+    let chars: Vec<char> = condition.chars().collect();
+    let mut i = 0usize;
+    let mut out = Vec::new();
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        match ch {
+            '(' => {
+                out.push(ConditionToken::LParen);
+                i += 1;
+            }
+            ')' => {
+                out.push(ConditionToken::RParen);
+                i += 1;
+            }
+            '=' => {
+                if i + 1 >= chars.len() {
+                    return None;
+                }
+                if chars[i + 1] == '=' {
+                    out.push(ConditionToken::Eq);
+                    i += 2;
+                } else if chars[i + 1] == '~' {
+                    out.push(ConditionToken::Match);
+                    i += 2;
+                } else {
+                    return None;
+                }
+            }
+            '\'' | '"' => {
+                let quote = ch;
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i] != quote {
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return None;
+                }
+                let value: String = chars[start..i].iter().collect();
+                out.push(ConditionToken::StringLit(value));
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() {
+                    let c = chars[i];
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '=' {
+                        break;
+                    }
+                    i += 1;
+                }
+                if start == i {
+                    return None;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let lowered = word.to_lowercase();
+                match lowered.as_str() {
+                    "and" => out.push(ConditionToken::And),
+                    "or" => out.push(ConditionToken::Or),
+                    "not" => out.push(ConditionToken::Not),
+                    "true" => out.push(ConditionToken::Bool(true)),
+                    "false" => out.push(ConditionToken::Bool(false)),
+                    _ => out.push(ConditionToken::Ident(word)),
+                }
+            }
+        }
+    }
+
+    Some(out)
+}
+
+/// Condition fields understood by `WindowContext::matches`
+const CONDITION_FIELDS: &[&str] = &[
+    "wm_class",
+    "wm_name",
+    "device_name",
+    "devn",
+    "numlock",
+    "numlk",
+    "capslock",
+    "capslk",
+    "keyboard_type",
+    "layout",
+];
+
+/// Check that a condition expression parses and only uses known fields
+pub fn validate_condition(condition: &str) -> Result<(), String> {
+    let expr = parse_condition(condition)?;
+    check_condition_fields(&expr)
+}
+
+/// Parse every named condition (`[conditions]`), with references to other
+/// named conditions replaced by their expressions
+pub fn resolve_named_conditions(
+    definitions: &HashMap<String, String>,
+) -> Result<HashMap<String, ConditionExpr>, String> {
+    definitions
+        .keys()
+        .map(|name| Ok((name.clone(), resolve_named_condition(name, definitions)?)))
+        .collect()
+}
+
+/// Parse one named condition, following references to other named conditions.
+///
+/// Names must be plain identifiers that are not a condition field or keyword,
+/// and references may not form a cycle.
+pub fn resolve_named_condition(name: &str, definitions: &HashMap<String, String>) -> Result<ConditionExpr, String> {
+    resolve_chain(name, definitions, &mut Vec::new())
+}
+
+fn resolve_chain(name: &str, definitions: &HashMap<String, String>, chain: &mut Vec<String>) -> Result<ConditionExpr, String> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let lowered = name.to_lowercase();
+    if !valid || ["and", "or", "not", "true", "false"].contains(&lowered.as_str()) {
+        return Err(format!("'{}' is not a valid condition name", name));
+    }
+    if CONDITION_FIELDS.contains(&lowered.as_str()) {
+        return Err(format!("'{}' is already a condition field", name));
+    }
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(format!("cycle {}", chain.join(" -> ")));
+    }
+    let source = definitions
+        .get(name)
+        .ok_or_else(|| format!("undefined condition '{}'", name))?;
+    let expr = parse_condition(source).map_err(|e| format!("'{}': {}", name, e))?;
+
+    chain.push(name.to_string());
+    let resolved = substitute_names(expr, &mut |field| {
+        if definitions.contains_key(field) {
+            resolve_chain(field, definitions, chain).map(Some)
+        } else {
+            Ok(None)
+        }
+    })?;
+    chain.pop();
+    Ok(resolved)
+}
+
+/// `condition` with references to named conditions replaced by their
+/// expressions. Conditions that use no names, or don't parse, are returned
+/// unchanged.
+pub fn expand_named_conditions<'a>(condition: &'a str, named: &HashMap<String, ConditionExpr>) -> Cow<'a, str> {
+    if named.is_empty() {
+        return Cow::Borrowed(condition);
+    }
+    let Ok(expr) = parse_condition(condition) else {
+        return Cow::Borrowed(condition);
+    };
+    let mut used = false;
+    let expanded = substitute_names(expr, &mut |field| {
+        let found = named.get(field).cloned();
+        used |= found.is_some();
+        Ok::<_, String>(found)
+    });
+    match expanded {
+        Ok(expr) if used => Cow::Owned(expr.to_string()),
+        _ => Cow::Borrowed(condition),
+    }
+}
+
+/// Replace bare predicates (`terminals`) for which `lookup` returns an expression
+fn substitute_names<E>(
+    expr: ConditionExpr,
+    lookup: &mut dyn FnMut(&str) -> Result<Option<ConditionExpr>, E>,
+) -> Result<ConditionExpr, E> {
+    Ok(match expr {
+        ConditionExpr::And(left, right) => ConditionExpr::And(
+            Box::new(substitute_names(*left, lookup)?),
+            Box::new(substitute_names(*right, lookup)?),
+        ),
+        ConditionExpr::Or(left, right) => ConditionExpr::Or(
+            Box::new(substitute_names(*left, lookup)?),
+            Box::new(substitute_names(*right, lookup)?),
+        ),
+        ConditionExpr::Not(inner) => ConditionExpr::Not(Box::new(substitute_names(*inner, lookup)?)),
+        ConditionExpr::Predicate { field, op: None, value: None } => match lookup(&field)? {
+            Some(named) => named,
+            None => ConditionExpr::Predicate { field, op: None, value: None },
+        },
+        predicate => predicate,
+    })
+}
+
+fn parse_condition(condition: &str) -> Result<ConditionExpr, String> {
+    let tokens = tokenize_condition(condition.trim())
+        .ok_or_else(|| "unterminated string or stray '=' in condition".to_string())?;
+    if tokens.is_empty() {
+        return Err("empty condition".to_string());
+    }
+    ConditionParser::new(tokens)
+        .parse()
+        .ok_or_else(|| "malformed condition expression".to_string())
+}
+
+fn check_condition_fields(expr: &ConditionExpr) -> Result<(), String> {
+    match expr {
+        ConditionExpr::And(left, right) | ConditionExpr::Or(left, right) => {
+            check_condition_fields(left)?;
+            check_condition_fields(right)
+        }
+        ConditionExpr::Not(inner) => check_condition_fields(inner),
+        ConditionExpr::Predicate { field, op, value } => {
+            // `settings.optspec_layout == 'XYZ'` could never be true
+            #[cfg(feature = "pure-rust")]
+            if let (Some(name), Some(ConditionOp::Eq), Some(value)) = (field.strip_prefix("settings."), op, value) {
+                if let Some(kind) = crate::settings::setting_kind(name) {
+                    kind.parse_str(value).map_err(|e| format!("{}: {}", field, e))?;
+                }
+            }
+            #[cfg(not(feature = "pure-rust"))]
+            let _ = (op, value);
+
+            let lowered = field.to_lowercase();
+            if field.starts_with("settings.") || CONDITION_FIELDS.contains(&lowered.as_str()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "unknown condition field '{}' (expected one of {}, settings.<name>)",
+                    field,
+                    CONDITION_FIELDS.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_parses_once() {
+        let condition = Condition::new("wm_class =~ 'firefox' and not settings.Caps2Esc_Cmd");
+        assert_eq!(condition.source(), "wm_class =~ 'firefox' and not settings.Caps2Esc_Cmd");
+        let Some(ConditionExpr::And(left, right)) = condition.expr() else {
+            panic!("expected an And expression");
+        };
+        assert_eq!(
+            **left,
+            ConditionExpr::Predicate {
+                field: "wm_class".to_string(),
+                op: Some(ConditionOp::Match),
+                value: Some("firefox".to_string()),
+            }
+        );
+        assert!(matches!(**right, ConditionExpr::Not(_)));
+
+        let broken = Condition::new("wm_class =~ 'firefox");
+        assert_eq!(broken.expr(), None);
+        assert!(validate_condition(broken.source()).is_err());
+    }
+
+    #[test]
+    fn test_validate_condition_reports_syntax_and_unknown_fields() {
+        assert!(validate_condition("settings.x and (wm_class =~ 'a' or devn == 'b')").is_ok());
+        assert!(validate_condition("not CapsLock").is_ok());
+        assert!(validate_condition("wm_class =~ 'unterminated").is_err());
+        assert!(validate_condition("(wm_class == 'a'").is_err());
+        assert!(validate_condition("").is_err());
+        let err = validate_condition("wm_clas =~ 'firefox'").unwrap_err();
+        assert!(err.contains("wm_clas"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_validate_condition_checks_typed_setting_values() {
+        assert!(validate_condition("settings.optspec_layout == 'abc'").is_ok());
+        let err = validate_condition("settings.optspec_layout == 'UK'").unwrap_err();
+        assert!(err.contains("must be one of ABC, US"), "{}", err);
+        assert!(validate_condition("settings.anything == 'UK'").is_ok());
+    }
+
+    #[test]
+    fn test_display_parses_back() {
+        for source in [
+            "wm_class =~ 'a' and (wm_name == \"it's\" or not settings.x)",
+            "not (capslock and numlock) or keyboard_type == 'Mac'",
+            "(wm_class =~ 'a' or wm_class =~ 'b') and (devn == 'c' or devn == 'd')",
+        ] {
+            let expr = parse_condition(source).unwrap();
+            assert_eq!(parse_condition(&expr.to_string()).unwrap(), expr, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_named_conditions() {
+        let definitions: HashMap<String, String> = [
+            ("terminals", "wm_class =~ 'kitty|alacritty' or wm_name =~ 'tmux'"),
+            ("dev", "terminals or wm_class =~ 'code'"),
+        ]
+        .into_iter()
+        .map(|(name, source)| (name.to_string(), source.to_string()))
+        .collect();
+        let named = resolve_named_conditions(&definitions).unwrap();
+
+        let expanded = expand_named_conditions("terminals and settings.Enter2Ent_Cmd", &named);
+        assert_eq!(
+            expanded,
+            "(wm_class =~ 'kitty|alacritty' or wm_name =~ 'tmux') and settings.Enter2Ent_Cmd"
+        );
+        assert!(validate_condition(&expanded).is_ok());
+        assert!(expand_named_conditions("not dev", &named).starts_with("not (wm_class =~ 'kitty|alacritty'"));
+        assert!(matches!(expand_named_conditions("capslock", &named), Cow::Borrowed(_)));
+
+        let cycle: HashMap<String, String> = [("a", "b and capslock"), ("b", "not a")]
+            .into_iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        let err = resolve_named_conditions(&cycle).unwrap_err();
+        assert!(err.starts_with("cycle "), "{}", err);
+        let shadow: HashMap<String, String> = [("wm_class".to_string(), "capslock".to_string())].into();
+        assert!(resolve_named_conditions(&shadow).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+// Keyrs Cheat Sheet
+// Markdown/HTML tables of the bindings a config defines, per keymap and layer
+
+use std::fmt::Write;
+
+use super::parser::{Config, KeymapEntry, KeymapOutput};
+use crate::mapping::LayerMode;
+
+/// Output format of `keyrs cheatsheet`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheatsheetFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl CheatsheetFormat {
+    /// Parse a format name as used in `keyrs cheatsheet --format`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// One table of the cheat sheet
+struct Section<'a> {
+    title: String,
+    scope: String,
+    entry: &'a KeymapEntry,
+}
+
+/// Readable form of a mapping's output
+fn describe_output(output: &KeymapOutput) -> String {
+    match output {
+        KeymapOutput::Key(key) => key.to_string(),
+        KeymapOutput::Combo(keys) => keys.iter().map(|key| key.to_string()).collect::<Vec<_>>().join("+"),
+        KeymapOutput::Sequence(steps) => steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(", "),
+        KeymapOutput::ComboHint(hint) => hint.to_string(),
+        KeymapOutput::Unicode(codepoint) => match char::from_u32(*codepoint) {
+            Some(c) => format!("{} (U+{:04X})", c, codepoint),
+            None => format!("U+{:04X}", codepoint),
+        },
+        KeymapOutput::Text(text) => format!("types \"{}\"", text),
+        KeymapOutput::Layer(layer) => {
+            let mode = match layer.mode {
+                LayerMode::Momentary => "while held",
+                LayerMode::Toggle => "toggle",
+                LayerMode::OneShot => "next key",
+            };
+            format!("layer {} ({})", layer.name, mode)
+        }
+        KeymapOutput::EnterKeymap(name) => format!("then {}", name),
+        KeymapOutput::Passthrough => "unmapped".to_string(),
+    }
+}
+
+/// Rows of `entry`: (combo, output, description), sorted by combo
+fn rows(entry: &KeymapEntry) -> Vec<(&str, String, &str)> {
+    let mut rows: Vec<_> = entry
+        .mappings
+        .iter()
+        .map(|(combo, output)| {
+            let description = entry
+                .descriptions
+                .iter()
+                .find(|(described, _)| described == combo)
+                .map_or("", |(_, description)| description.as_str());
+            let does = match entry.holds.iter().find(|(held, _)| held == combo) {
+                Some((_, hold)) => format!("tap: {}, hold: {}", describe_output(output), describe_output(hold)),
+                None => describe_output(output),
+            };
+            (combo.as_str(), does, description)
+        })
+        .collect();
+    rows.sort_by(|a, b| a.0.cmp(b.0));
+    rows
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Combos that enter the nested keymap `name`, sorted
+fn entered_by<'a>(config: &'a Config, name: &str) -> Vec<&'a str> {
+    let mut combos: Vec<&str> = config
+        .keymaps
+        .iter()
+        .chain(&config.layers)
+        .flat_map(|entry| &entry.mappings)
+        .filter(|(_, output)| matches!(output, KeymapOutput::EnterKeymap(entered) if entered == name))
+        .map(|(combo, _)| combo.as_str())
+        .collect();
+    combos.sort();
+    combos
+}
+
+/// Render the keymaps (in match order) and layers of `config`
+pub fn render_cheatsheet(config: &Config, format: CheatsheetFormat) -> String {
+    let sections: Vec<Section> = config
+        .keymaps
+        .iter()
+        .map(|entry| {
+            let prefixes = entered_by(config, &entry.name);
+            let scope = match &entry.condition {
+                _ if !prefixes.is_empty() => format!("After {}", prefixes.join(" or ")),
+                Some(condition) => format!("When {}", condition),
+                None => "Everywhere".to_string(),
+            };
+            Section {
+                title: entry.name.clone(),
+                scope,
+                entry,
+            }
+        })
+        .chain(config.layers.iter().map(|entry| Section {
+            title: format!("Layer {}", entry.name),
+            scope: match &entry.condition {
+                Some(condition) => format!("While the layer is active, when {}", condition),
+                None => "While the layer is active".to_string(),
+            },
+            entry,
+        }))
+        .filter(|section| !section.entry.mappings.is_empty())
+        .collect();
+
+    let mut out = String::new();
+    match format {
+        CheatsheetFormat::Markdown => {
+            out.push_str("# keyrs cheat sheet\n");
+            for section in &sections {
+                let _ = write!(
+                    out,
+                    "\n## {}\n\n_{}_\n\n| Shortcut | Does | Description |\n|---|---|---|\n",
+                    section.title,
+                    markdown_cell(&section.scope)
+                );
+                for (combo, output, description) in rows(section.entry) {
+                    let _ = writeln!(
+                        out,
+                        "| `{}` | {} | {} |",
+                        markdown_cell(combo),
+                        markdown_cell(&output),
+                        markdown_cell(description)
+                    );
+                }
+            }
+        }
+        CheatsheetFormat::Html => {
+            out.push_str(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>keyrs cheat sheet</title>\n</head>\n<body>\n<h1>keyrs cheat sheet</h1>\n",
+            );
+            for section in &sections {
+                let _ = write!(
+                    out,
+                    "<h2>{}</h2>\n<p><em>{}</em></p>\n<table>\n<tr><th>Shortcut</th><th>Does</th><th>Description</th></tr>\n",
+                    html_escape(&section.title),
+                    html_escape(&section.scope)
+                );
+                for (combo, output, description) in rows(section.entry) {
+                    let _ = writeln!(
+                        out,
+                        "<tr><td><kbd>{}</kbd></td><td>{}</td><td>{}</td></tr>",
+                        html_escape(combo),
+                        html_escape(&output),
+                        html_escape(description)
+                    );
+                }
+                out.push_str("</table>\n");
+            }
+            out.push_str("</body>\n</html>\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"Super-c" = { output = "C-c", description = "Copy" }
+"Super-v" = "C-v"
+"Super-Enter" = { tap = "C-n", hold = "Super-Up" }
+
+[[keymap]]
+name = "Terminals"
+condition = "wm_class =~ 'kitty|alacritty'"
+[keymap.mappings]
+"Super-c" = { output = "C-Shift-c", description = "Copy <selection>" }
+
+[[keymap]]
+name = "Leader"
+[keymap.mappings]
+"s" = "C-s"
+
+[[keymap]]
+name = "Emacs"
+[keymap.mappings]
+"C-x" = "keymap(Leader)"
+
+[[keymap]]
+name = "Off"
+enabled = false
+[keymap.mappings]
+"Super-q" = "C-q"
+"#;
+
+    #[test]
+    fn test_markdown_cheatsheet() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let markdown = render_cheatsheet(&config, CheatsheetFormat::Markdown);
+
+        assert!(markdown.contains("## General\n\n_Everywhere_"));
+        assert!(markdown.contains("| `Super-c` | LEFT_CTRL+C | Copy |"), "{}", markdown);
+        assert!(markdown.contains("| `Super-v` | LEFT_CTRL+V |  |"));
+        assert!(markdown.contains("| `Super-Enter` | tap: LEFT_CTRL+N, hold: LEFT_META+UP |  |"), "{}", markdown);
+        assert!(markdown.contains("_When wm_class =~ 'kitty\\|alacritty'_"));
+        assert!(markdown.find("## General").unwrap() < markdown.find("## Terminals").unwrap());
+        assert!(!markdown.contains("Super-q"));
+        assert!(markdown.contains("## Leader\n\n_After C-x_"), "{}", markdown);
+        assert!(markdown.contains("| `C-x` | then Leader |  |"));
+
+        let html = render_cheatsheet(&config, CheatsheetFormat::Html);
+        assert!(html.contains("<td>Copy &lt;selection&gt;</td>"));
+        assert_eq!(CheatsheetFormat::from_name("MD"), Some(CheatsheetFormat::Markdown));
+        assert_eq!(CheatsheetFormat::from_name("pdf"), None);
+    }
+}
@@ -0,0 +1,141 @@
+// Keyrs Config - Chromebook Top Row
+// Built-in modmap and keymaps giving the top-row action keys ChromeOS behavior
+
+use super::parser::{KeymapEntry, KeymapOutput, KeymapTomlOutput, ModmapEntry};
+use crate::Key;
+
+/// Setting that makes the top row send F1..F12 (Search+key sends the action),
+/// like ChromeOS' "Treat top-row keys as function keys"
+pub const CHROMEBOOK_FKEYS_SETTING: &str = "chromebook_fkeys";
+
+/// Action keys of the classic Chromebook top row, left to right:
+/// BACK, FORWARD, REFRESH, ZOOM (fullscreen), SCALE (overview),
+/// BRIGHTNESSDOWN, BRIGHTNESSUP, MUTE, VOLUMEDOWN, VOLUMEUP
+pub const DEFAULT_TOP_ROW: &[u16] = &[158, 159, 173, 372, 120, 224, 225, 113, 114, 115];
+
+/// Longest top row that can be translated (F1..F15)
+pub const MAX_TOP_ROW: usize = 15;
+
+/// F1..F15 key codes
+const FUNCTION_KEYS: [u16; MAX_TOP_ROW] = [59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 87, 88, 183, 184, 185];
+
+/// Modifiers carried over from a Search+top-row combo to its output
+const CARRIED_MODIFIERS: [&str; 3] = ["Ctrl", "Alt", "Shift"];
+
+/// Modmap and keymaps for `top_row`: in action mode Search+key sends its F-key;
+/// with `settings.chromebook_fkeys` the top row sends F-keys and Search+key
+/// sends the action. Only keyboards whose type is Chromebook are affected.
+pub(crate) fn top_row_entries(top_row: &[Key]) -> (ModmapEntry, Vec<KeymapEntry>) {
+    let chromebook = "keyboard_type =~ 'Chromebook'";
+    let fkeys_condition = format!("{} and settings.{}", chromebook, CHROMEBOOK_FKEYS_SETTING);
+    let actions_condition = format!("{} and not settings.{}", chromebook, CHROMEBOOK_FKEYS_SETTING);
+
+    let pairs: Vec<(Key, Key)> = top_row
+        .iter()
+        .zip(FUNCTION_KEYS)
+        .map(|(action, function)| (*action, Key::from(function)))
+        .collect();
+
+    let modmap = ModmapEntry {
+        name: "Chromebook top row (function keys)".to_string(),
+        mappings: pairs.clone(),
+        condition: Some(fkeys_condition.clone()),
+    };
+
+    let keymap = |name: &str, condition: String, from: fn(&(Key, Key)) -> Key, to: fn(&(Key, Key)) -> Key| {
+        let mut mappings = Vec::new();
+        for pair in &pairs {
+            for mask in 0..(1 << CARRIED_MODIFIERS.len()) {
+                let modifiers: String = CARRIED_MODIFIERS
+                    .iter()
+                    .enumerate()
+                    .filter(|(bit, _)| mask & (1 << bit) != 0)
+                    .map(|(_, modifier)| format!("{}-", modifier))
+                    .collect();
+                let output: KeymapOutput =
+                    KeymapTomlOutput::Single(format!("{}{}", modifiers, to(pair).name())).into();
+                mappings.push((format!("Super-{}{}", modifiers, from(pair).name()), output));
+            }
+        }
+        KeymapEntry {
+            name: name.to_string(),
+            mappings,
+            condition: Some(condition),
+            repeats: Vec::new(),
+            descriptions: Vec::new(),
+            holds: Vec::new(),
+            nested_timeout: None,
+            combo_style: None,
+        }
+    };
+
+    let keymaps = vec![
+        keymap("Chromebook top row", actions_condition, |pair| pair.0, |pair| pair.1),
+        keymap("Chromebook top row (function keys)", fkeys_condition, |pair| pair.1, |pair| pair.0),
+    ];
+    (modmap, keymaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_row_entries() {
+        let top_row: Vec<Key> = DEFAULT_TOP_ROW.iter().map(|code| Key::from(*code)).collect();
+        let (modmap, keymaps) = top_row_entries(&top_row);
+
+        assert_eq!(modmap.mappings.len(), 10);
+        assert!(modmap.mappings.contains(&(Key::from(158), Key::from(59)))); // BACK -> F1
+        assert!(modmap.mappings.contains(&(Key::from(115), Key::from(68)))); // VOLUMEUP -> F10
+        assert!(modmap.condition.as_deref().unwrap().contains("settings.chromebook_fkeys"));
+
+        let [actions, fkeys] = &keymaps[..] else { panic!("expected two keymaps") };
+        assert_eq!(actions.mappings.len(), 10 * 8);
+        assert!(actions.condition.as_deref().unwrap().contains("not settings.chromebook_fkeys"));
+        let output = |entry: &KeymapEntry, combo: &str| {
+            entry.mappings.iter().find(|(c, _)| c == combo).map(|(_, output)| format!("{:?}", output))
+        };
+        assert_eq!(output(actions, "Super-BACK"), Some(format!("{:?}", KeymapOutput::Key(Key::from(59)))));
+        assert!(output(actions, "Super-Ctrl-Shift-REFRESH").is_some());
+        assert_eq!(output(fkeys, "Super-F4"), Some(format!("{:?}", KeymapOutput::Key(Key::from(372)))));
+    }
+
+    #[test]
+    fn test_chromebook_section_drives_the_engine() {
+        use crate::config::Config;
+        use crate::input::KeyboardType;
+        use crate::transform::engine::{TransformEngine, TransformResult};
+        use crate::Action;
+
+        let config = Config::from_toml("[chromebook]\n").unwrap();
+        assert_eq!(config.chromebook_top_row.len(), DEFAULT_TOP_ROW.len());
+        assert_eq!(config.modmaps[0].name, "default");
+        let mut engine = TransformEngine::new(config.to_transform_config());
+        let (search, back, refresh) = (Key::from(125), Key::from(158), Key::from(173));
+
+        // Other keyboards are left alone
+        engine.process_event(search, Action::Press);
+        assert_eq!(engine.process_event(back, Action::Press), TransformResult::Passthrough(back));
+        engine.process_event(back, Action::Release);
+        engine.process_event(search, Action::Release);
+
+        engine.set_keyboard_type(KeyboardType::Chromebook);
+        assert_eq!(engine.process_event(refresh, Action::Press), TransformResult::Passthrough(refresh));
+        engine.process_event(refresh, Action::Release);
+        engine.process_event(search, Action::Press);
+        assert_eq!(engine.process_event(refresh, Action::Press), TransformResult::ComboKey(Key::from(61))); // F3
+        engine.process_event(refresh, Action::Release);
+        engine.process_event(search, Action::Release);
+
+        engine.set_setting(CHROMEBOOK_FKEYS_SETTING, true);
+        assert_eq!(engine.process_event(refresh, Action::Press), TransformResult::Remapped(Key::from(61)));
+        engine.process_event(refresh, Action::Release);
+        engine.process_event(search, Action::Press);
+        assert_eq!(engine.process_event(refresh, Action::Press), TransformResult::ComboKey(refresh));
+
+        assert!(Config::from_toml("[chromebook]\ntop_row = []\n").is_err());
+        assert!(Config::from_toml("[chromebook]\ntop_row = [\"NOPE\"]\n").is_err());
+        assert!(Config::from_toml("[chromebook]\nenabled = false\n").unwrap().chromebook_top_row.is_empty());
+    }
+}
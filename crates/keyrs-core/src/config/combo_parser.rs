@@ -1,7 +1,7 @@
 // Keyrs Config API - Combo String Parser
 // Parses combo strings like "Ctrl-Shift-A" into structured components
 
-use crate::{Key, Modifier};
+use crate::{Key, KeyNames, Modifier};
 use std::collections::HashSet;
 
 /// Result of parsing a combo string
@@ -11,8 +11,23 @@ pub struct ParsedCombo {
     pub modifiers: Vec<Modifier>,
     /// The key (the last component after hyphens)
     pub key: Key,
+    /// Whether the combo fires on press or, with a `release:` prefix, on release
+    pub trigger: ComboTrigger,
 }
 
+/// When a keymap combo fires
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComboTrigger {
+    /// When its key is pressed
+    #[default]
+    Press,
+    /// When its key is released (`release:Super-Space`)
+    Release,
+}
+
+/// Prefix of combos that fire on key release
+pub const RELEASE_PREFIX: &str = "release:";
+
 /// Errors that can occur during combo parsing
 #[derive(Debug, Clone, PartialEq)]
 pub enum ComboParseError {
@@ -39,7 +54,8 @@ impl std::fmt::Display for ComboParseError {
 
 impl std::error::Error for ComboParseError {}
 
-/// Parse a combo string like "Ctrl-Shift-A" into modifiers and key
+/// Parse a combo string like "Ctrl-Shift-A" into modifiers and key, with
+/// an optional `release:` prefix
 ///
 /// # Arguments
 /// * `exp` - The combo expression string to parse
@@ -56,11 +72,24 @@ impl std::error::Error for ComboParseError {}
 /// assert_eq!(parsed.key, Key::from(30)); // Key::A
 /// ```
 pub fn parse_combo_string(exp: &str) -> Result<ParsedCombo, ComboParseError> {
+    parse_combo_with(exp, &KeyNames::default())
+}
+
+/// Parse a combo string with the key names (aliases, layout) of a config
+pub fn parse_combo_with(exp: &str, names: &KeyNames) -> Result<ParsedCombo, ComboParseError> {
     if exp.is_empty() {
         return Err(ComboParseError::EmptyInput);
     }
 
-    let trimmed = exp.trim();
+    let mut trimmed = exp.trim();
+    let mut trigger = ComboTrigger::Press;
+    if trimmed
+        .get(..RELEASE_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(RELEASE_PREFIX))
+    {
+        trimmed = trimmed[RELEASE_PREFIX.len()..].trim();
+        trigger = ComboTrigger::Release;
+    }
     if trimmed.is_empty() {
         return Err(ComboParseError::EmptyInput);
     }
@@ -79,8 +108,7 @@ pub fn parse_combo_string(exp: &str) -> Result<ParsedCombo, ComboParseError> {
 
     // The last part is always the key
     let key_str = parts.last().unwrap();
-    let key =
-        key_from_name(key_str).ok_or_else(|| ComboParseError::UnknownKey(key_str.to_string()))?;
+    let key = names.key(key_str).ok_or_else(|| ComboParseError::UnknownKey(key_str.to_string()))?;
 
     // Everything before the last part are modifiers
     let mut modifiers = Vec::new();
@@ -92,25 +120,154 @@ pub fn parse_combo_string(exp: &str) -> Result<ParsedCombo, ComboParseError> {
             break;
         }
 
-        // Try to parse as a modifier alias
-        let modifier = Modifier::from_alias(modifier_str)
+        // A user alias for a modifier key wins over the built-in modifier aliases
+        let modifier = names
+            .alias(modifier_str)
+            .and_then(Modifier::from_key)
+            .or_else(|| Modifier::from_alias(modifier_str))
             .ok_or_else(|| ComboParseError::UnknownModifier(modifier_str.to_string()))?;
 
-        // Avoid duplicate modifiers
-        if !seen_modifiers.contains(&modifier) {
-            seen_modifiers.insert(modifier.clone());
-            modifiers.push(modifier);
+        // A compound modifier (`Hyper-a`) stands for each of its components;
+        // avoid duplicate modifiers
+        for modifier in Modifier::expand(std::slice::from_ref(&modifier)) {
+            if !seen_modifiers.contains(&modifier) {
+                seen_modifiers.insert(modifier.clone());
+                modifiers.push(modifier);
+            }
+        }
+    }
+
+    Ok(ParsedCombo { modifiers, key, trigger })
+}
+
+/// Combo parse error enriched with "did you mean" suggestions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComboValidationError {
+    /// The underlying parse error
+    pub error: ComboParseError,
+    /// Closest known names for the unrecognized key/modifier (best first)
+    pub suggestions: Vec<String>,
+}
+
+impl std::fmt::Display for ComboValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.suggestions.is_empty() {
+            write!(f, " (did you mean {}?)", self.suggestions.join(", "))?;
         }
+        Ok(())
     }
+}
+
+impl std::error::Error for ComboValidationError {}
+
+/// Parse a combo string, attaching name suggestions to unknown key/modifier errors
+///
+/// # Examples
+/// ```
+/// use keyrs_core::config::validate_combo;
+/// let err = validate_combo("Ctrl-LEFT_CTL").unwrap_err();
+/// assert_eq!(err.suggestions.first().map(String::as_str), Some("LEFT_CTRL"));
+/// ```
+pub fn validate_combo(exp: &str) -> Result<ParsedCombo, ComboValidationError> {
+    validate_combo_with(exp, &KeyNames::default())
+}
 
-    Ok(ParsedCombo { modifiers, key })
+/// `validate_combo` with the key names (aliases, layout) of a config
+pub fn validate_combo_with(exp: &str, names: &KeyNames) -> Result<ParsedCombo, ComboValidationError> {
+    parse_combo_with(exp, names).map_err(|error| {
+        let suggestions = match &error {
+            ComboParseError::UnknownKey(name) => suggest_key_names(name),
+            ComboParseError::UnknownModifier(name) => suggest_modifier_aliases(name),
+            _ => Vec::new(),
+        };
+        ComboValidationError { error, suggestions }
+    })
 }
 
-/// Get a Key from its name
-fn key_from_name(name: &str) -> Option<Key> {
-    let upper = name.to_uppercase();
-    // Try the key module's from_name function
-    crate::key::key_from_name(&upper)
+/// Suggest known key names close to `name`
+pub fn suggest_key_names(name: &str) -> Vec<String> {
+    closest_names(&name.to_uppercase(), crate::key::all_key_names())
+}
+
+/// Suggest known modifier aliases close to `name`
+pub fn suggest_modifier_aliases(name: &str) -> Vec<String> {
+    let aliases = Modifier::all_aliases();
+    closest_names(name, aliases.iter().map(String::as_str))
+}
+
+/// Completions of the last part of a partially typed combo: modifier aliases
+/// (with the `-` that follows them) and key names starting with it, ignoring
+/// case. Used by `keyrs keys --complete` and the TUI mapping prompt.
+///
+/// # Examples
+/// ```
+/// use keyrs_core::config::complete_combo;
+/// assert!(complete_combo("Ctrl-BACKS").contains(&"Ctrl-BACKSPACE".to_string()));
+/// assert!(complete_combo("Shi").contains(&"Shift-".to_string()));
+/// ```
+pub fn complete_combo(partial: &str) -> Vec<String> {
+    let (head, last) = match partial.rfind('-') {
+        Some(i) => partial.split_at(i + 1),
+        None => partial.split_at(0),
+    };
+    let prefix = last.to_lowercase();
+    let mut completions: Vec<String> = Modifier::all_aliases()
+        .iter()
+        .filter(|alias| alias.to_lowercase().starts_with(&prefix))
+        .map(|alias| format!("{}{}-", head, alias))
+        .chain(
+            crate::key::all_key_names()
+                .into_iter()
+                .filter(|name| name.to_lowercase().starts_with(&prefix))
+                .map(|name| format!("{}{}", head, name)),
+        )
+        .collect();
+    completions.sort();
+    completions.dedup();
+    completions
+}
+
+/// Up to three candidates within a small edit distance, best first
+fn closest_names<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    const MAX_SUGGESTIONS: usize = 3;
+    let max_distance = (input.chars().count() / 3).max(2);
+    let input_lower = input.to_lowercase();
+
+    let mut scored: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&input_lower, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+
+    let mut suggestions: Vec<String> = Vec::new();
+    for (_, candidate) in scored {
+        if !suggestions.iter().any(|s| s == candidate) {
+            suggestions.push(candidate.to_string());
+        }
+        if suggestions.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+    suggestions
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j + 1] + 1).min(curr[j] + 1).min(prev[j] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b_chars.len()]
 }
 
 #[cfg(test)]
@@ -203,6 +360,52 @@ mod tests {
         assert!(matches!(result, Err(ComboParseError::UnknownModifier(_))));
     }
 
+    #[test]
+    fn test_validate_combo_suggests_key_names() {
+        let err = validate_combo("Ctrl-LEFT_CTL").unwrap_err();
+        assert_eq!(err.error, ComboParseError::UnknownKey("LEFT_CTL".to_string()));
+        assert_eq!(err.suggestions[0], "LEFT_CTRL");
+        assert!(err.to_string().contains("did you mean LEFT_CTRL"));
+    }
+
+    #[test]
+    fn test_validate_combo_suggests_modifiers() {
+        let err = validate_combo("Crtl-a").unwrap_err();
+        assert!(err.suggestions.contains(&"Ctrl".to_string()));
+
+        let err = validate_combo("Ctrl-").unwrap_err();
+        assert!(err.suggestions.is_empty());
+        assert!(validate_combo("Ctrl-a").is_ok());
+    }
+
+    #[test]
+    fn test_complete_combo() {
+        let completions = complete_combo("C-Shift-page_u");
+        assert_eq!(completions, ["C-Shift-PAGE_UP"]);
+        assert!(complete_combo("al").contains(&"Alt-".to_string()));
+        assert!(complete_combo("").len() > 100);
+        assert!(complete_combo("Ctrl-nosuchkey").is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("ctrl", "ctrl"), 0);
+    }
+
+    #[test]
+    fn test_parse_release_trigger() {
+        let parsed = parse_combo_string("release:Super-Space").unwrap();
+        assert_eq!(parsed.trigger, ComboTrigger::Release);
+        assert_eq!(parsed.modifiers.len(), 1);
+        assert_eq!(parsed.key, Key::from(57));
+        assert_eq!(parse_combo_string("Release: CapsLock").unwrap().trigger, ComboTrigger::Release);
+        assert_eq!(parse_combo_string("Super-Space").unwrap().trigger, ComboTrigger::Press);
+        assert_eq!(parse_combo_string("release:"), Err(ComboParseError::EmptyInput));
+        assert!(matches!(parse_combo_string("release-a"), Err(ComboParseError::UnknownModifier(_))));
+    }
+
     #[test]
     fn test_parse_duplicate_modifiers() {
         // Duplicate modifiers should be deduplicated
@@ -210,6 +413,16 @@ mod tests {
         assert_eq!(parsed.modifiers.len(), 1);
     }
 
+    #[test]
+    fn test_parse_compound_modifiers() {
+        let hyper = parse_combo_string("Hyper-a").unwrap();
+        assert_eq!(
+            hyper.modifiers,
+            parse_combo_string("Ctrl-Alt-Shift-Super-a").unwrap().modifiers
+        );
+        assert_eq!(parse_combo_string("Meh-Ctrl-a").unwrap().modifiers.len(), 3);
+    }
+
     #[test]
     fn test_parse_special_keys() {
         let parsed = parse_combo_string("Ctrl-ENTER").unwrap();
@@ -0,0 +1,365 @@
+// Keyrs Config Composition
+// Merges modular TOML fragments (config.d/*.toml and `include = [...]`)
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::map::Map;
+use toml::Value;
+
+use super::ConfigError;
+
+/// Root key listing fragment files to merge at load time
+pub const INCLUDE_KEY: &str = "include";
+
+fn merge_table_entries(dst: &mut Map<String, Value>, src: Map<String, Value>) {
+    for (k, v) in src {
+        dst.insert(k, v);
+    }
+}
+
+fn merge_modmap(root: &mut Map<String, Value>, src: Map<String, Value>) {
+    let modmap = root
+        .entry("modmap".to_string())
+        .or_insert_with(|| Value::Table(Map::new()));
+    let modmap_tbl = modmap.as_table_mut().expect("modmap must be table");
+
+    for (k, v) in src {
+        match (k.as_str(), v) {
+            ("default", Value::Table(default_src)) => {
+                let default_dst = modmap_tbl
+                    .entry("default".to_string())
+                    .or_insert_with(|| Value::Table(Map::new()));
+                let default_tbl = default_dst.as_table_mut().expect("modmap.default must be table");
+                merge_table_entries(default_tbl, default_src);
+            }
+            ("conditionals", Value::Array(src_items)) => {
+                let cond_dst = modmap_tbl
+                    .entry("conditionals".to_string())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                let cond_array = cond_dst.as_array_mut().expect("modmap.conditionals must be array");
+                cond_array.extend(src_items);
+            }
+            (other, value) => {
+                modmap_tbl.insert(other.to_string(), value);
+            }
+        }
+    }
+}
+
+/// Merge a config fragment into the root table.
+///
+/// `general`/`timeouts`/`modtap`/`scancodes`/`aliases`/`vars`/`conditions` keys and `modmap.default` entries are overridden
+/// per key, array sections (`multipurpose`, `tapdance`, `keymap`,
+/// `modmap.conditionals`) are appended, and any other section is replaced.
+pub fn merge_config_fragment(root: &mut Map<String, Value>, fragment: Map<String, Value>) {
+    for (k, v) in fragment {
+        match (k.as_str(), v) {
+            ("general", Value::Table(src))
+            | ("timeouts", Value::Table(src))
+            | ("modtap", Value::Table(src))
+            | ("scancodes", Value::Table(src))
+            | ("aliases", Value::Table(src))
+            | ("vars", Value::Table(src))
+            | ("conditions", Value::Table(src)) => {
+                let dst = root
+                    .entry(k.clone())
+                    .or_insert_with(|| Value::Table(Map::new()));
+                let dst_tbl = dst.as_table_mut().expect("section must be table");
+                merge_table_entries(dst_tbl, src);
+            }
+            ("modmap", Value::Table(src)) => merge_modmap(root, src),
+            ("multipurpose", Value::Array(items))
+            | ("tapdance", Value::Array(items))
+            | ("keymap", Value::Array(items)) => {
+                let dst = root
+                    .entry(k.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                let dst_arr = dst.as_array_mut().expect("section must be array");
+                dst_arr.extend(items);
+            }
+            (_, value) => {
+                root.insert(k, value);
+            }
+        }
+    }
+}
+
+/// Read a TOML file into its root table
+pub fn read_fragment(path: &Path) -> Result<Map<String, Value>, ConfigError> {
+    let content = fs::read_to_string(path)?;
+    let content = super::migrate::upgrade_for_load(&content, &path.display().to_string())?;
+    let value: Value = toml::from_str(&content)
+        .map_err(|e| ConfigError::TomlParse(format!("{}: {}", path.display(), e)))?;
+    match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(ConfigError::TomlParse(format!(
+            "{}: root must be a TOML table",
+            path.display()
+        ))),
+    }
+}
+
+/// Load a config file and merge every fragment named by its `include` list.
+///
+/// Patterns are relative to the including file; `*` and `?` wildcards are
+/// supported in the file name component and matches are merged in filename
+/// order, after the including file, so fragments take precedence exactly as
+/// with `--compose-config`. Included files may include further fragments; a
+/// fragment reached through several includes is merged once, at its first
+/// inclusion, and a file including itself (directly or not) is an error.
+pub fn load_with_includes(path: &Path) -> Result<Map<String, Value>, ConfigError> {
+    let mut stack = IncludeStack::default();
+    let mut root = Map::new();
+    merge_file_with_includes(&mut root, path, &mut stack)?;
+    Ok(root)
+}
+
+/// Files being included (to detect cycles) and files already read (so a
+/// diamond include is taken once)
+#[derive(Debug, Default)]
+pub(crate) struct IncludeStack {
+    open: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+}
+
+impl IncludeStack {
+    /// Enter `canonical`: `Ok(false)` when it was already taken through
+    /// another include, an error when it is still being included
+    pub(crate) fn enter(&mut self, canonical: PathBuf) -> Result<bool, String> {
+        if self.open.contains(&canonical) {
+            return Err("include cycle detected".to_string());
+        }
+        if !self.seen.insert(canonical.clone()) {
+            return Ok(false);
+        }
+        self.open.push(canonical);
+        Ok(true)
+    }
+
+    /// Leave the file most recently entered
+    pub(crate) fn leave(&mut self) {
+        self.open.pop();
+    }
+}
+
+fn merge_file_with_includes(
+    root: &mut Map<String, Value>,
+    path: &Path,
+    stack: &mut IncludeStack,
+) -> Result<(), ConfigError> {
+    let canonical = fs::canonicalize(path)?;
+    match stack.enter(canonical) {
+        Ok(true) => {}
+        Ok(false) => return Ok(()),
+        Err(e) => return Err(ConfigError::TomlParse(format!("{}: {}", path.display(), e))),
+    }
+    let merged = merge_file_entered(root, path, stack);
+    stack.leave();
+    merged
+}
+
+fn merge_file_entered(
+    root: &mut Map<String, Value>,
+    path: &Path,
+    stack: &mut IncludeStack,
+) -> Result<(), ConfigError> {
+
+    let mut table = read_fragment(path)?;
+    let includes = match table.remove(INCLUDE_KEY) {
+        None => Vec::new(),
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(pattern) => Ok(pattern),
+                other => Err(ConfigError::TomlParse(format!(
+                    "{}: include entries must be strings, got {}",
+                    path.display(),
+                    other
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(other) => {
+            return Err(ConfigError::TomlParse(format!(
+                "{}: include must be an array of strings, got {}",
+                path.display(),
+                other
+            )))
+        }
+    };
+
+    merge_config_fragment(root, table);
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for pattern in includes {
+        for fragment in expand_include_pattern(base_dir, &pattern)? {
+            merge_file_with_includes(root, &fragment, stack)?;
+        }
+    }
+    Ok(())
+}
+
+/// Expand an include pattern relative to `base_dir`.
+///
+/// Patterns without wildcards must name an existing file; wildcard patterns
+/// may match nothing.
+pub fn expand_include_pattern(base_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, ConfigError> {
+    let full = base_dir.join(pattern);
+    let file_pattern = full
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_string();
+
+    if !file_pattern.contains(['*', '?']) {
+        if !full.is_file() {
+            return Err(ConfigError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("included file not found: {}", full.display()),
+            )));
+        }
+        return Ok(vec![full]);
+    }
+
+    let dir = full.parent().unwrap_or(base_dir);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| wildcard_match(&file_pattern, n))
+                .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Match a file name against a pattern with `*` and `?` wildcards
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "keyrs-{}-{}",
+            tag,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("time")
+                .as_nanos()
+        ));
+        fs::create_dir_all(&dir).expect("create temp dir");
+        dir
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.toml", "kitty.toml"));
+        assert!(wildcard_match("1?0_*.toml", "100_base.toml"));
+        assert!(!wildcard_match("*.toml", "kitty.toml.bak"));
+        assert!(!wildcard_match("a?.toml", "a.toml"));
+    }
+
+    #[test]
+    fn test_load_with_includes_merges_in_filename_order() {
+        let base = temp_dir("include-test");
+        let apps = base.join("apps");
+        fs::create_dir_all(&apps).expect("create apps dir");
+
+        fs::write(
+            base.join("config.toml"),
+            "include = [\"apps/*.toml\"]\n[timeouts]\nmultipurpose = 300\n[modmap.default]\nCAPSLOCK = \"ESC\"\n",
+        )
+        .expect("write base");
+        fs::write(
+            apps.join("200_term.toml"),
+            "[timeouts]\nmultipurpose = 500\n[[keymap]]\nname = \"term\"\n[keymap.mappings]\n\"Super-c\" = \"Ctrl-Shift-c\"\n",
+        )
+        .expect("write term");
+        fs::write(
+            apps.join("100_browser.toml"),
+            "[[keymap]]\nname = \"browser\"\n[keymap.mappings]\n\"Super-t\" = \"Ctrl-t\"\n",
+        )
+        .expect("write browser");
+
+        let root = load_with_includes(&base.join("config.toml")).expect("load");
+        assert!(root.get(INCLUDE_KEY).is_none());
+        assert_eq!(root["timeouts"]["multipurpose"].as_integer(), Some(500));
+        let names: Vec<&str> = root["keymap"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|k| k["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["browser", "term"]);
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_load_with_includes_rejects_cycles_and_missing_files() {
+        let base = temp_dir("include-cycle-test");
+        fs::write(base.join("a.toml"), "include = [\"b.toml\"]\n").expect("write a");
+        fs::write(base.join("b.toml"), "include = [\"a.toml\"]\n").expect("write b");
+        assert!(load_with_includes(&base.join("a.toml")).is_err());
+
+        fs::write(base.join("c.toml"), "include = [\"missing.toml\"]\n").expect("write c");
+        assert!(matches!(
+            load_with_includes(&base.join("c.toml")),
+            Err(ConfigError::Io(_))
+        ));
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_load_with_includes_merges_diamond_once() {
+        let base = temp_dir("include-diamond-test");
+        fs::write(base.join("main.toml"), "include = [\"left.toml\", \"right.toml\"]\n").expect("write main");
+        fs::write(base.join("left.toml"), "include = [\"common.toml\"]\n").expect("write left");
+        fs::write(base.join("right.toml"), "include = [\"common.toml\"]\n").expect("write right");
+        fs::write(
+            base.join("common.toml"),
+            "[[keymap]]\nname = \"common\"\n[keymap.mappings]\n\"C-a\" = \"Home\"\n",
+        )
+        .expect("write common");
+
+        let root = load_with_includes(&base.join("main.toml")).expect("diamond include should load");
+        assert_eq!(root["keymap"].as_array().map(Vec::len), Some(1));
+
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,404 @@
+// Keyrs Keymap Conflicts
+// Combos mapped differently by keymaps whose conditions can hold at the same time
+//
+// Keymaps are tried in file order and the first match wins, so a later
+// keymap mapping the same combo is shadowed wherever both conditions hold.
+// A later keymap without a condition is the usual fallback and is not
+// reported; neither are mappings with the same output, nor combos an
+// earlier keymap deliberately leaves unmapped (`passthrough`). Nested keymaps
+// (entered with `keymap(name)`) apply on their own and never compete.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::parser::{KeymapEntry, KeymapOutput};
+use crate::condition::{ConditionExpr, ConditionOp};
+use crate::mapping::KeymapValue;
+use crate::{Combo, Condition, KeyNames};
+
+/// Upper bound on the disjunctive form of a condition; bigger ones are assumed to overlap
+const MAX_TERMS: usize = 64;
+
+/// Combos that two keymaps map to different outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeymapConflict {
+    /// The combos as written in the shadowed keymap
+    pub combos: Vec<String>,
+    /// Keymap that comes first and wins
+    pub winner: String,
+    /// Keymap whose mapping is shadowed
+    pub shadowed: String,
+    /// The winner has no condition, so the shadowed mapping never applies
+    pub unconditional: bool,
+}
+
+impl fmt::Display for KeymapConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let combos = self.combos.iter().map(|combo| format!("'{}'", combo)).collect::<Vec<_>>().join(", ");
+        if self.unconditional {
+            write!(
+                f,
+                "{} in keymap '{}' never applies: keymap '{}' comes first, has no condition and maps it differently",
+                combos, self.shadowed, self.winner
+            )
+        } else {
+            write!(
+                f,
+                "{} mapped differently by keymaps '{}' and '{}' whose conditions can both match; '{}' wins there because it comes first",
+                combos, self.winner, self.shadowed, self.winner
+            )
+        }
+    }
+}
+
+/// One alternative of a `=~`/`==` pattern, lowercased
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    /// `^value$` or `== value`
+    Exact(String),
+    /// Unanchored: the value contains it
+    Contains(String),
+}
+
+impl Pattern {
+    fn can_match_same(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Exact(a), Pattern::Exact(b)) => a == b,
+            (Pattern::Exact(exact), Pattern::Contains(part)) | (Pattern::Contains(part), Pattern::Exact(exact)) => {
+                exact.contains(part.as_str())
+            }
+            // Treated as one application when one name contains the other
+            (Pattern::Contains(a), Pattern::Contains(b)) => a.contains(b.as_str()) || b.contains(a.as_str()),
+        }
+    }
+}
+
+/// A predicate of a condition in normalized form
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    /// Boolean state (`settings.x`, `numlock`, `capslock`) required to be on or off
+    Flag(String, bool),
+    /// String field matching any of the patterns
+    Field(String, Vec<Pattern>),
+}
+
+/// An atom or its negation
+type Literal = (Atom, bool);
+
+fn canonical_field(field: &str) -> String {
+    match field.to_lowercase().as_str() {
+        "devn" => "device_name".to_string(),
+        "numlk" => "numlock".to_string(),
+        "capslk" => "capslock".to_string(),
+        _ if field.starts_with("settings.") => field.to_string(),
+        lowered => lowered.to_string(),
+    }
+}
+
+fn patterns(op: ConditionOp, value: &str) -> Vec<Pattern> {
+    if op == ConditionOp::Eq {
+        return vec![Pattern::Exact(value.to_lowercase())];
+    }
+    value
+        .split('|')
+        .filter_map(|raw| {
+            let token = raw.trim().to_lowercase();
+            let token = token.strip_prefix("(?i)").unwrap_or(&token).trim();
+            if token.len() >= 2 && token.starts_with('^') && token.ends_with('$') {
+                let exact = &token[1..token.len() - 1];
+                return (!exact.is_empty()).then(|| Pattern::Exact(exact.to_string()));
+            }
+            let token = token.trim_start_matches('^').trim_end_matches('$');
+            (!token.is_empty()).then(|| Pattern::Contains(token.to_string()))
+        })
+        .collect()
+}
+
+fn literal(field: &str, op: Option<ConditionOp>, value: Option<&str>, positive: bool) -> Literal {
+    let field = canonical_field(field);
+    let is_flag = field.starts_with("settings.") || field == "numlock" || field == "capslock";
+    match op {
+        None => (Atom::Flag(field, positive), true),
+        Some(ConditionOp::Eq) if is_flag => {
+            let on = matches!(value.unwrap_or_default().to_lowercase().as_str(), "true" | "1" | "yes" | "on");
+            (Atom::Flag(field, on == positive), true)
+        }
+        Some(op) => (Atom::Field(field, patterns(op, value.unwrap_or_default())), positive),
+    }
+}
+
+/// Disjunctive normal form of `expr` (negated when `positive` is false)
+fn terms(expr: &ConditionExpr, positive: bool) -> Option<Vec<Vec<Literal>>> {
+    let both = |left: &ConditionExpr, right: &ConditionExpr| -> Option<Vec<Vec<Literal>>> {
+        let left = terms(left, positive)?;
+        let right = terms(right, positive)?;
+        if left.len() * right.len() > MAX_TERMS {
+            return None;
+        }
+        Some(
+            left.iter()
+                .flat_map(|l| right.iter().map(move |r| l.iter().chain(r).cloned().collect()))
+                .collect(),
+        )
+    };
+    let either = |left: &ConditionExpr, right: &ConditionExpr| -> Option<Vec<Vec<Literal>>> {
+        let mut out = terms(left, positive)?;
+        out.extend(terms(right, positive)?);
+        (out.len() <= MAX_TERMS).then_some(out)
+    };
+    match (expr, positive) {
+        (ConditionExpr::And(left, right), true) | (ConditionExpr::Or(left, right), false) => both(left, right),
+        (ConditionExpr::Or(left, right), true) | (ConditionExpr::And(left, right), false) => either(left, right),
+        (ConditionExpr::Not(inner), _) => terms(inner, !positive),
+        (ConditionExpr::Predicate { field, op, value }, _) => {
+            Some(vec![vec![literal(field, *op, value.as_deref(), positive)]])
+        }
+    }
+}
+
+/// Whether all literals can hold for one window
+fn satisfiable(literals: &[&Literal]) -> bool {
+    for (i, (atom, positive)) in literals.iter().enumerate() {
+        for (other, other_positive) in &literals[i + 1..] {
+            let clash = match (atom, other) {
+                (Atom::Flag(a, on), Atom::Flag(b, other_on)) => a == b && on != other_on,
+                (Atom::Field(a, _), Atom::Field(b, _)) if atom == other => a == b && positive != other_positive,
+                (Atom::Field(a, pa), Atom::Field(b, pb)) => {
+                    a == b && *positive && *other_positive && !pa.iter().any(|p| pb.iter().any(|q| p.can_match_same(q)))
+                }
+                _ => false,
+            };
+            if clash {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Whether a condition can hold for some window and settings (false if it doesn't parse)
+pub(crate) fn condition_can_match(condition: &str) -> bool {
+    let condition = Condition::new(condition);
+    let Some(expr) = condition.expr() else {
+        return false;
+    };
+    match terms(expr, true) {
+        Some(terms) => terms.iter().any(|term| satisfiable(&term.iter().collect::<Vec<_>>())),
+        None => true,
+    }
+}
+
+/// Whether two keymap conditions can match the same window (None is always true)
+pub(crate) fn conditions_overlap(a: Option<&str>, b: Option<&str>) -> bool {
+    let (Some(a), Some(b)) = (a, b) else {
+        return true;
+    };
+    let (a, b) = (Condition::new(a), Condition::new(b));
+    // Conditions that don't parse never match
+    let (Some(a), Some(b)) = (a.expr(), b.expr()) else {
+        return false;
+    };
+    let (Some(a), Some(b)) = (terms(a, true), terms(b, true)) else {
+        return true;
+    };
+    a.iter()
+        .any(|ta| b.iter().any(|tb| satisfiable(&ta.iter().chain(tb).collect::<Vec<_>>())))
+}
+
+/// Parsed mappings of a keymap; combos that don't parse are skipped like at runtime
+fn parsed_mappings<'a>(entry: &'a KeymapEntry, names: &KeyNames) -> Vec<(&'a str, Combo, KeymapValue)> {
+    let mut mappings: Vec<_> = entry
+        .mappings
+        .iter()
+        .filter_map(|(combo_str, output)| {
+            let parsed = super::validate_combo_with(combo_str, names).ok()?;
+            Some((combo_str.as_str(), Combo::new(parsed.modifiers, parsed.key), output.clone().into()))
+        })
+        .collect();
+    mappings.sort_by(|a, b| a.0.cmp(b.0));
+    mappings
+}
+
+/// Conflicts between `keymaps`, grouped per pair of keymaps in file order;
+/// each shadowed combo is reported once, against the first keymap that wins over it
+pub(crate) fn find_keymap_conflicts(keymaps: &[KeymapEntry], names: &KeyNames) -> Vec<KeymapConflict> {
+    let parsed: Vec<_> = keymaps.iter().map(|entry| parsed_mappings(entry, names)).collect();
+    let nested: HashSet<&str> = keymaps
+        .iter()
+        .flat_map(|entry| &entry.mappings)
+        .filter_map(|(_, output)| match output {
+            KeymapOutput::EnterKeymap(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut conflicts = Vec::new();
+    for (later, later_entry) in keymaps.iter().enumerate() {
+        let Some(later_condition) = later_entry.condition.as_deref() else {
+            continue;
+        };
+        if nested.contains(later_entry.name.as_str()) {
+            continue;
+        }
+        let overlapping: Vec<bool> = keymaps[..later]
+            .iter()
+            .map(|earlier| {
+                !nested.contains(earlier.name.as_str())
+                    && conditions_overlap(earlier.condition.as_deref(), Some(later_condition))
+            })
+            .collect();
+        let mut shadowed: Vec<(usize, &str)> = Vec::new();
+        for (combo_str, combo, value) in &parsed[later] {
+            let winner = (0..later).find(|&earlier| {
+                overlapping[earlier]
+                    && parsed[earlier]
+                        .iter()
+                        .any(|(_, other_combo, other_value)| {
+                            other_combo == combo && other_value != value && *other_value != KeymapValue::Passthrough
+                        })
+            });
+            if let Some(winner) = winner {
+                shadowed.push((winner, combo_str));
+            }
+        }
+        shadowed.sort_by_key(|(winner, _)| *winner);
+        for group in shadowed.chunk_by(|a, b| a.0 == b.0) {
+            let winner = &keymaps[group[0].0];
+            conflicts.push(KeymapConflict {
+                combos: group.iter().map(|(_, combo)| combo.to_string()).collect(),
+                winner: winner.name.clone(),
+                shadowed: later_entry.name.clone(),
+                unconditional: winner.condition.is_none(),
+            });
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn conflicts(source: &str) -> Vec<KeymapConflict> {
+        Config::from_toml(source).unwrap().keymap_conflicts()
+    }
+
+    #[test]
+    fn test_condition_overlap() {
+        assert!(conditions_overlap(None, Some("wm_class =~ 'kitty'")));
+        assert!(conditions_overlap(Some("wm_class =~ 'kitty|alacritty'"), Some("wm_class == 'Kitty'")));
+        assert!(conditions_overlap(Some("wm_class =~ 'code'"), Some("wm_class =~ '^vscode$'")));
+        assert!(!conditions_overlap(Some("wm_class =~ '^firefox$'"), Some("wm_class =~ '^chromium$'")));
+        assert!(!conditions_overlap(Some("wm_class =~ 'firefox'"), Some("wm_class =~ 'kitty'")));
+        assert!(!conditions_overlap(Some("settings.Foo"), Some("not settings.Foo")));
+        assert!(!conditions_overlap(Some("settings.Foo"), Some("settings.Foo == false")));
+        assert!(!conditions_overlap(Some("wm_class =~ 'kitty'"), Some("not wm_class =~ 'kitty'")));
+        assert!(conditions_overlap(
+            Some("wm_class =~ 'kitty' and settings.Foo"),
+            Some("not (wm_class =~ 'firefox' or settings.Bar)")
+        ));
+        assert!(!conditions_overlap(Some("wm_class =~ 'kitty'"), Some("wm_class =~ (")));
+    }
+
+    #[test]
+    fn test_keymap_conflicts() {
+        let found = conflicts(
+            r#"
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"Super-c" = "C-c"
+"Super-v" = "C-v"
+
+[[keymap]]
+name = "Terminals"
+condition = "wm_class =~ 'kitty'"
+[keymap.mappings]
+"Super-c" = "C-Shift-c"
+"Super-v" = "C-v"
+
+[[keymap]]
+name = "Kitty extras"
+condition = "wm_class == 'kitty' and settings.Extras"
+[keymap.mappings]
+"Super-c" = "C-Insert"
+
+[[keymap]]
+name = "Browsers"
+condition = "wm_class =~ '^firefox$'"
+[keymap.mappings]
+"Super-t" = "C-t"
+
+[[keymap]]
+name = "Browsers 2"
+condition = "wm_class =~ '^chromium$'"
+[keymap.mappings]
+"Super-t" = "C-Shift-t"
+"#,
+        );
+        assert_eq!(
+            found,
+            vec![
+                KeymapConflict {
+                    combos: vec!["Super-c".to_string()],
+                    winner: "General".to_string(),
+                    shadowed: "Terminals".to_string(),
+                    unconditional: true,
+                },
+                KeymapConflict {
+                    combos: vec!["Super-c".to_string()],
+                    winner: "General".to_string(),
+                    shadowed: "Kitty extras".to_string(),
+                    unconditional: true,
+                },
+            ]
+        );
+        assert!(found[0].to_string().contains("never applies"));
+
+        // Specific keymaps before the fallback: only overlapping specific ones clash
+        let found = conflicts(
+            r#"
+[[keymap]]
+name = "Terminals"
+condition = "wm_class =~ 'kitty|alacritty'"
+[keymap.mappings]
+"C-Alt-t" = "C-Shift-t"
+"C-Alt-w" = "C-Shift-w"
+
+[[keymap]]
+name = "Kitty"
+condition = "wm_class =~ '^kitty$'"
+[keymap.mappings]
+"Alt-Ctrl-t" = "C-t"
+"C-Alt-w" = "C-w"
+
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"C-Alt-t" = "C-t"
+"#,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].winner.as_str(), found[0].shadowed.as_str()), ("Terminals", "Kitty"));
+        assert_eq!(found[0].combos, vec!["Alt-Ctrl-t".to_string(), "C-Alt-w".to_string()]);
+        assert!(found[0].to_string().contains("'Terminals' wins"));
+
+        // An explicit passthrough is a deliberate opt-out, not a conflict
+        let found = conflicts(
+            r#"
+[[keymap]]
+name = "Terminals"
+condition = "wm_class =~ 'kitty'"
+[keymap.mappings]
+"Super-c" = "passthrough"
+
+[[keymap]]
+name = "Kitty"
+condition = "wm_class =~ '^kitty$'"
+[keymap.mappings]
+"Super-c" = "C-Shift-c"
+"#,
+        );
+        assert_eq!(found, Vec::new());
+    }
+}
@@ -0,0 +1,279 @@
+// Keyrs Config Editing
+// Outline of a config file and comment-preserving edits (used by the TUI)
+
+use toml_edit::{Decor, DocumentMut, Item, Key, Table, Value};
+
+use super::ConfigError;
+
+/// Kind of entry listed in a config outline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineKind {
+    Modmap,
+    Multipurpose,
+    TapDance,
+    Keymap,
+}
+
+impl OutlineKind {
+    /// Short label for display
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Modmap => "modmap",
+            Self::Multipurpose => "multipurpose",
+            Self::TapDance => "tapdance",
+            Self::Keymap => "keymap",
+        }
+    }
+}
+
+/// A single modmap/multipurpose/tapdance/keymap entry of a config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    pub kind: OutlineKind,
+    pub name: String,
+    pub condition: Option<String>,
+    /// Number of mappings (1 for multipurpose/tapdance entries)
+    pub mappings: usize,
+    /// Only keymaps can be disabled; other entries are always enabled
+    pub enabled: bool,
+    /// Position within its TOML array (`[[keymap]]` index for keymaps)
+    pub index: usize,
+}
+
+fn parse_document(content: &str) -> Result<DocumentMut, ConfigError> {
+    content
+        .parse::<DocumentMut>()
+        .map_err(|e| ConfigError::TomlParse(e.to_string()))
+}
+
+fn table_str(table: &Table, key: &str) -> Option<String> {
+    table.get(key).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+fn table_len(table: &Table, key: &str) -> usize {
+    table
+        .get(key)
+        .and_then(|v| v.as_table_like())
+        .map(|t| t.len())
+        .unwrap_or(0)
+}
+
+/// List the entries defined in a config file, in file order per section
+pub fn config_outline(content: &str) -> Result<Vec<OutlineEntry>, ConfigError> {
+    let doc = parse_document(content)?;
+    let mut entries = Vec::new();
+
+    if let Some(modmap) = doc.get("modmap").and_then(|m| m.as_table()) {
+        if let Some(default) = modmap.get("default").and_then(|d| d.as_table_like()) {
+            entries.push(OutlineEntry {
+                kind: OutlineKind::Modmap,
+                name: "default".to_string(),
+                condition: None,
+                mappings: default.len(),
+                enabled: true,
+                index: 0,
+            });
+        }
+        if let Some(conditionals) = modmap.get("conditionals").and_then(|c| c.as_array_of_tables()) {
+            for (index, table) in conditionals.iter().enumerate() {
+                entries.push(OutlineEntry {
+                    kind: OutlineKind::Modmap,
+                    name: table_str(table, "name").unwrap_or_default(),
+                    condition: table_str(table, "condition"),
+                    mappings: table_len(table, "mappings"),
+                    enabled: true,
+                    index,
+                });
+            }
+        }
+    }
+
+    for (section, kind) in [
+        ("multipurpose", OutlineKind::Multipurpose),
+        ("tapdance", OutlineKind::TapDance),
+    ] {
+        if let Some(items) = doc.get(section).and_then(|i| i.as_array_of_tables()) {
+            for (index, table) in items.iter().enumerate() {
+                entries.push(OutlineEntry {
+                    kind,
+                    name: table_str(table, "name").unwrap_or_default(),
+                    condition: table_str(table, "condition"),
+                    mappings: 1,
+                    enabled: true,
+                    index,
+                });
+            }
+        }
+    }
+
+    if let Some(keymaps) = doc.get("keymap").and_then(|k| k.as_array_of_tables()) {
+        for (index, table) in keymaps.iter().enumerate() {
+            entries.push(OutlineEntry {
+                kind: OutlineKind::Keymap,
+                name: table_str(table, "name").unwrap_or_else(|| format!("keymap #{}", index + 1)),
+                condition: table_str(table, "condition"),
+                mappings: table_len(table, "mappings"),
+                enabled: table.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true),
+                index,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Enable or disable the `[[keymap]]` at `index`, preserving comments and layout.
+///
+/// Disabling writes `enabled = false`; enabling removes the key again.
+pub fn set_keymap_enabled(content: &str, index: usize, enabled: bool) -> Result<String, ConfigError> {
+    let mut doc = parse_document(content)?;
+    let table = doc
+        .get_mut("keymap")
+        .and_then(|k| k.as_array_of_tables_mut())
+        .and_then(|keymaps| keymaps.get_mut(index))
+        .ok_or_else(|| ConfigError::InvalidValue(format!("no [[keymap]] at index {}", index)))?;
+
+    if enabled {
+        table.remove("enabled");
+    } else {
+        // Plain values render in the [[keymap]] body, before its mappings subtable.
+        table.insert("enabled", Item::Value(false.into()));
+    }
+
+    Ok(doc.to_string())
+}
+
+/// Add `trigger = output` to the mappings of the `[[keymap]]` at `index`,
+/// preserving comments and layout.
+///
+/// A trigger the keymap already maps is an error rather than replaced.
+pub fn add_keymap_mapping(
+    content: &str,
+    index: usize,
+    trigger: &str,
+    output: &str,
+) -> Result<String, ConfigError> {
+    let mut doc = parse_document(content)?;
+    let table = doc
+        .get_mut("keymap")
+        .and_then(|k| k.as_array_of_tables_mut())
+        .and_then(|keymaps| keymaps.get_mut(index))
+        .ok_or_else(|| ConfigError::InvalidValue(format!("no [[keymap]] at index {}", index)))?;
+
+    let mappings = table
+        .entry("mappings")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| ConfigError::InvalidValue("keymap mappings is not a table".to_string()))?;
+    if mappings.contains_key(trigger) {
+        return Err(ConfigError::InvalidValue(format!(
+            "'{}' is already mapped in this keymap",
+            trigger
+        )));
+    }
+    // Quote the combo like the rest of a config does; a bare key would be valid too
+    let key = Key::parse(&Value::from(trigger).to_string())
+        .ok()
+        .and_then(|mut keys| (keys.len() == 1).then(|| keys.remove(0)))
+        .map(|key| key.with_leaf_decor(Decor::new("", " ")))
+        .ok_or_else(|| ConfigError::InvalidValue(format!("invalid mapping key '{}'", trigger)))?;
+    mappings.entry_format(&key).or_insert(Item::Value(output.into()));
+
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"# keyrs config
+[modmap.default]
+CAPSLOCK = "LEFT_CTRL"
+
+[[multipurpose]]
+name = "Caps"
+trigger = "CAPSLOCK"
+tap = "ESC"
+hold = "LEFT_CTRL"
+
+# Browser shortcuts
+[[keymap]]
+name = "browser"
+condition = "wm_class =~ 'firefox'"
+[keymap.mappings]
+"Super-t" = "Ctrl-t" # new tab
+"Super-w" = "Ctrl-w"
+
+[[keymap]]
+name = "general"
+[keymap.mappings]
+"Super-c" = "Ctrl-c"
+"#;
+
+    #[test]
+    fn test_config_outline_lists_entries() {
+        let outline = config_outline(SAMPLE).unwrap();
+        let summary: Vec<(OutlineKind, &str, usize)> = outline
+            .iter()
+            .map(|e| (e.kind, e.name.as_str(), e.mappings))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (OutlineKind::Modmap, "default", 1),
+                (OutlineKind::Multipurpose, "Caps", 1),
+                (OutlineKind::Keymap, "browser", 2),
+                (OutlineKind::Keymap, "general", 1),
+            ]
+        );
+        assert_eq!(outline[2].condition.as_deref(), Some("wm_class =~ 'firefox'"));
+        assert!(outline.iter().all(|e| e.enabled));
+    }
+
+    #[test]
+    fn test_set_keymap_enabled_preserves_comments() {
+        let disabled = set_keymap_enabled(SAMPLE, 0, false).unwrap();
+        assert!(disabled.contains("# Browser shortcuts"));
+        assert!(disabled.contains("# new tab"));
+        assert!(disabled.contains("enabled = false"));
+
+        let outline = config_outline(&disabled).unwrap();
+        assert!(!outline[2].enabled);
+        assert!(outline[3].enabled);
+
+        let config = crate::config::Config::from_toml(&disabled).unwrap();
+        assert_eq!(config.keymaps.len(), 1);
+        assert_eq!(config.keymaps[0].name, "general");
+
+        let restored = set_keymap_enabled(&disabled, 0, true).unwrap();
+        assert_eq!(restored, SAMPLE);
+    }
+
+    #[test]
+    fn test_add_keymap_mapping() {
+        let added = add_keymap_mapping(SAMPLE, 1, "Super-Shift-t", "Ctrl-Shift-t").unwrap();
+        assert!(added.contains("# Browser shortcuts"));
+        assert!(added.contains("\"Super-Shift-t\" = \"Ctrl-Shift-t\""));
+
+        let outline = config_outline(&added).unwrap();
+        assert_eq!(outline[3].mappings, 2);
+        assert!(crate::config::Config::from_toml(&added).is_ok());
+
+        assert!(matches!(
+            add_keymap_mapping(SAMPLE, 0, "Super-t", "Ctrl-n"),
+            Err(ConfigError::InvalidValue(_))
+        ));
+
+        let bare = "[[keymap]]\nname = \"new\"\n";
+        let created = add_keymap_mapping(bare, 0, "F5", "Ctrl-r").unwrap();
+        assert_eq!(config_outline(&created).unwrap()[0].mappings, 1);
+    }
+
+    #[test]
+    fn test_set_keymap_enabled_rejects_missing_index() {
+        assert!(matches!(
+            set_keymap_enabled(SAMPLE, 5, false),
+            Err(ConfigError::InvalidValue(_))
+        ));
+    }
+}
@@ -31,7 +31,10 @@ pub fn expand_keymap_entries(entries: &[(String, u16)]) -> Vec<(String, u16)> {
 
             for expanded_combo in expanded_combos {
                 // Convert back to string representation
-                let combo_str = combo_to_string(&expanded_combo);
+                let mut combo_str = combo_to_string(&expanded_combo);
+                if parsed.trigger == super::ComboTrigger::Release {
+                    combo_str.insert_str(0, super::combo_parser::RELEASE_PREFIX);
+                }
                 result.push((combo_str, *key_code));
             }
         } else {
@@ -0,0 +1,262 @@
+// Keyrs Config Lint
+// Mappings that load fine but can never do what they say
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::conflicts::{condition_can_match, conditions_overlap};
+use super::parser::{Config, KeymapEntry, KeymapOutput};
+use crate::mapping::ActionStep;
+use crate::Key;
+
+/// What a lint finding is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// A condition that can never be true, so its entry never applies
+    UnreachableCondition,
+    /// An output key the detected keyboard doesn't have
+    MissingKey,
+    /// A mapping whose output sequence has no steps
+    EmptySequence,
+    /// A modmap remapping a key that is also a multipurpose trigger
+    ModmapOnMultipurposeTrigger,
+}
+
+/// One lint finding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    pub kind: LintKind,
+    /// Entry the finding is about, e.g. `keymap 'Terminals'`
+    pub subject: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.subject, self.message)
+    }
+}
+
+/// Keys an output emits (modifiers of a combo as their first key)
+fn step_keys(step: &ActionStep, keys: &mut Vec<Key>) {
+    match step {
+        ActionStep::Combo(combo) => {
+            keys.extend(combo.modifiers().iter().filter_map(|modifier| modifier.keys().first().copied()));
+            keys.push(combo.key());
+        }
+        ActionStep::After { steps, .. } | ActionStep::Repeat { steps, .. } => {
+            for step in steps {
+                step_keys(step, keys);
+            }
+        }
+        ActionStep::Press(key) | ActionStep::Release(key) | ActionStep::Hold { key, .. } => keys.push(*key),
+        ActionStep::Text(_)
+        | ActionStep::DelayMs(_)
+        | ActionStep::Ignore
+        | ActionStep::Bind
+        | ActionStep::SetSetting { .. }
+        | ActionStep::SetMark(_)
+        | ActionStep::KeepModifiers(_)
+        | ActionStep::Run(_)
+        | ActionStep::Profile(_) => {}
+    }
+}
+
+fn output_keys(output: &KeymapOutput) -> Vec<Key> {
+    let mut keys = Vec::new();
+    match output {
+        KeymapOutput::Key(key) => keys.push(*key),
+        KeymapOutput::Combo(combo) => keys.extend(combo),
+        KeymapOutput::Sequence(steps) => {
+            for step in steps {
+                step_keys(step, &mut keys);
+            }
+        }
+        KeymapOutput::ComboHint(_)
+        | KeymapOutput::Unicode(_)
+        | KeymapOutput::Text(_)
+        | KeymapOutput::Layer(_)
+        | KeymapOutput::EnterKeymap(_)
+        | KeymapOutput::Passthrough => {}
+    }
+    keys
+}
+
+/// Whether an output (or a nested `After`/`Repeat`) has no steps
+fn has_empty_sequence(output: &KeymapOutput) -> bool {
+    fn empty(steps: &[ActionStep]) -> bool {
+        steps.is_empty()
+            || steps.iter().any(|step| match step {
+                ActionStep::After { steps, .. } | ActionStep::Repeat { steps, .. } => empty(steps),
+                _ => false,
+            })
+    }
+    match output {
+        KeymapOutput::Combo(keys) => keys.is_empty(),
+        KeymapOutput::Sequence(steps) => empty(steps),
+        _ => false,
+    }
+}
+
+struct Linter<'a> {
+    keyboard_keys: Option<&'a HashSet<u16>>,
+    findings: Vec<LintFinding>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, kind: LintKind, subject: &str, message: String) {
+        self.findings.push(LintFinding {
+            kind,
+            subject: subject.to_string(),
+            message,
+        });
+    }
+
+    fn check_condition(&mut self, subject: &str, condition: Option<&str>) {
+        if let Some(condition) = condition.filter(|condition| !condition_can_match(condition)) {
+            self.report(
+                LintKind::UnreachableCondition,
+                subject,
+                format!("condition \"{}\" can never be true, so it never applies", condition),
+            );
+        }
+    }
+
+    fn check_keys(&mut self, subject: &str, what: &str, keys: impl IntoIterator<Item = Key>) {
+        let Some(available) = self.keyboard_keys else {
+            return;
+        };
+        let mut missing: Vec<String> = Vec::new();
+        for key in keys {
+            let name = key.to_string();
+            if key.code() != 0 && !available.contains(&key.code()) && !missing.contains(&name) {
+                missing.push(name);
+            }
+        }
+        if !missing.is_empty() {
+            self.report(
+                LintKind::MissingKey,
+                subject,
+                format!("{} outputs {}, which the detected keyboard doesn't have", what, missing.join(", ")),
+            );
+        }
+    }
+
+    fn check_keymap(&mut self, kind: &str, entry: &KeymapEntry) {
+        let subject = format!("{} '{}'", kind, entry.name);
+        self.check_condition(&subject, entry.condition.as_deref());
+        let mut mappings: Vec<_> = entry.mappings.iter().collect();
+        mappings.sort_by(|a, b| a.0.cmp(&b.0));
+        for (combo, output) in mappings {
+            if has_empty_sequence(output) {
+                self.report(
+                    LintKind::EmptySequence,
+                    &subject,
+                    format!("'{}' maps to a sequence with no steps and does nothing", combo),
+                );
+            }
+            self.check_keys(&subject, &format!("'{}'", combo), output_keys(output));
+        }
+    }
+}
+
+/// Lint `config`; output keys are checked against `keyboard_keys` when given
+pub fn lint_config(config: &Config, keyboard_keys: Option<&HashSet<u16>>) -> Vec<LintFinding> {
+    let mut linter = Linter {
+        keyboard_keys,
+        findings: Vec::new(),
+    };
+
+    for modmap in &config.modmaps {
+        let subject = format!("modmap '{}'", modmap.name);
+        linter.check_condition(&subject, modmap.condition.as_deref());
+        for (from, to) in &modmap.mappings {
+            linter.check_keys(&subject, &from.to_string(), [*to]);
+            // Multipurpose sees the physical key first; an identity modmap changes nothing anyway
+            let trigger = config.multipurpose.iter().find(|entry| {
+                from != to
+                    && entry.trigger == *from
+                    && conditions_overlap(entry.condition.as_deref(), modmap.condition.as_deref())
+            });
+            if let Some(entry) = trigger {
+                linter.report(
+                    LintKind::ModmapOnMultipurposeTrigger,
+                    &subject,
+                    format!(
+                        "remaps {}, which is also the trigger of multipurpose '{}'; where both apply, the multipurpose entry wins and this mapping has no effect on a lone press",
+                        from, entry.name
+                    ),
+                );
+            }
+        }
+    }
+    for entry in &config.multipurpose {
+        let subject = format!("multipurpose '{}'", entry.name);
+        linter.check_condition(&subject, entry.condition.as_deref());
+        linter.check_keys(&subject, &entry.trigger.to_string(), [entry.tap, entry.hold]);
+    }
+    for entry in &config.tapdance {
+        let subject = format!("tapdance '{}'", entry.name);
+        linter.check_condition(&subject, entry.condition.as_deref());
+        linter.check_keys(&subject, &entry.trigger.to_string(), entry.taps.iter().copied().chain(entry.hold));
+    }
+    for entry in &config.keymaps {
+        linter.check_keymap("keymap", entry);
+    }
+    for entry in &config.layers {
+        linter.check_keymap("layer", entry);
+    }
+    linter.findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[modmap.default]
+CAPSLOCK = "ESC"
+
+[[multipurpose]]
+name = "caps"
+trigger = "CAPSLOCK"
+tap = "ESC"
+hold = "LEFT_CTRL"
+
+[[keymap]]
+name = "Never"
+condition = "settings.Foo and not settings.Foo"
+[keymap.mappings]
+"Super-a" = "C-a"
+
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"Super-e" = []
+"Super-f" = "F20"
+"#;
+
+    #[test]
+    fn test_lint_config() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let keyboard: HashSet<u16> = (1..=127).collect();
+        let findings = lint_config(&config, Some(&keyboard));
+        let kinds: Vec<_> = findings.iter().map(|finding| finding.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LintKind::ModmapOnMultipurposeTrigger,
+                LintKind::UnreachableCondition,
+                LintKind::EmptySequence,
+                LintKind::MissingKey,
+            ],
+            "{:#?}",
+            findings
+        );
+        assert_eq!(findings[0].to_string(), "modmap 'default': remaps CAPSLOCK, which is also the trigger of multipurpose 'caps'; where both apply, the multipurpose entry wins and this mapping has no effect on a lone press");
+        assert!(findings[3].message.contains("F20"));
+
+        // Without a keyboard to compare against, output keys are not checked
+        assert_eq!(lint_config(&config, None).len(), 3);
+    }
+}
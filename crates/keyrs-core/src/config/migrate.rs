@@ -0,0 +1,263 @@
+// Keyrs Config Migration
+// Upgrades configs written for an older `schema_version` to the current format
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use super::compose::expand_include_pattern;
+use super::ConfigError;
+
+/// `schema_version` of the config format this keyrs reads; a config without
+/// the field is version 1
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+/// One format change, upgrading a version `from` config to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    /// Edits the document, returning one summary line per change made
+    pub apply: fn(&mut DocumentMut) -> Vec<String>,
+}
+
+/// Format changes in version order. Every change of the config format adds
+/// one here and bumps `CURRENT_SCHEMA_VERSION`.
+const MIGRATIONS: &[Migration] = &[];
+
+/// A config brought to the current schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migrated {
+    /// Schema version the config was written for
+    pub from_version: u32,
+    /// The upgraded config, with `schema_version` set
+    pub source: String,
+    /// What was changed, one line per edit
+    pub changes: Vec<String>,
+}
+
+/// Upgrade `source` to the current schema, preserving comments and layout
+pub fn migrate(source: &str) -> Result<Migrated, ConfigError> {
+    migrate_with(source, MIGRATIONS, CURRENT_SCHEMA_VERSION)
+}
+
+/// `source` as the parser should read it: upgraded (with a logged summary)
+/// when it was written for an older schema
+pub(crate) fn upgrade_for_load<'a>(source: &'a str, origin: &str) -> Result<Cow<'a, str>, ConfigError> {
+    // Most configs never mention the field; skip parsing them twice
+    if CURRENT_SCHEMA_VERSION == 1 && !source.contains("schema_version") {
+        return Ok(Cow::Borrowed(source));
+    }
+    let migrated = migrate(source).map_err(|e| in_file(origin, e))?;
+    if migrated.from_version == CURRENT_SCHEMA_VERSION {
+        return Ok(Cow::Borrowed(source));
+    }
+    log::warn!(
+        "{}: upgraded from config schema {} to {}; run `keyrs migrate-config` to update the file",
+        origin,
+        migrated.from_version,
+        CURRENT_SCHEMA_VERSION
+    );
+    for change in &migrated.changes {
+        log::warn!("{}:   {}", origin, change);
+    }
+    Ok(Cow::Owned(migrated.source))
+}
+
+/// Upgrade the config at `path` and every fragment it includes, in file order
+pub fn migrate_files(path: &Path) -> Result<Vec<(PathBuf, Migrated)>, ConfigError> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    migrate_file_with_includes(path, &mut visited, &mut files)?;
+    Ok(files)
+}
+
+fn migrate_file_with_includes(
+    path: &Path,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<(PathBuf, Migrated)>,
+) -> Result<(), ConfigError> {
+    if !visited.insert(fs::canonicalize(path)?) {
+        return Ok(());
+    }
+    let source = fs::read_to_string(path)?;
+    let migrated = migrate(&source).map_err(|e| in_file(&path.display().to_string(), e))?;
+    let document = parse_document(&migrated.source)?;
+    let includes: Vec<String> = document
+        .get("include")
+        .and_then(Item::as_array)
+        .map(|array| array.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    files.push((path.to_path_buf(), migrated));
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for pattern in includes {
+        for fragment in expand_include_pattern(base_dir, &pattern)? {
+            migrate_file_with_includes(&fragment, visited, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Name the file a schema error is in
+fn in_file(origin: &str, error: ConfigError) -> ConfigError {
+    match error {
+        ConfigError::InvalidValue(message) => ConfigError::InvalidValue(format!("{}: {}", origin, message)),
+        other => other,
+    }
+}
+
+fn parse_document(source: &str) -> Result<DocumentMut, ConfigError> {
+    source
+        .parse::<DocumentMut>()
+        .map_err(|e| ConfigError::TomlParse(e.to_string()))
+}
+
+fn migrate_with(source: &str, migrations: &[Migration], current: u32) -> Result<Migrated, ConfigError> {
+    let mut doc = parse_document(source)?;
+    let from_version = match doc.get("schema_version") {
+        None => 1,
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| {
+                ConfigError::InvalidValue("schema_version must be a positive integer".to_string())
+            })?,
+    };
+    if from_version > current {
+        return Err(ConfigError::InvalidValue(format!(
+            "schema_version {} is newer than this keyrs supports ({}); update keyrs",
+            from_version, current
+        )));
+    }
+
+    let mut changes = Vec::new();
+    for version in from_version..current {
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| {
+                ConfigError::InvalidValue(format!("no migration from schema_version {}", version))
+            })?;
+        changes.extend((migration.apply)(&mut doc));
+    }
+
+    if doc.get("schema_version").and_then(Item::as_integer) != Some(i64::from(current)) {
+        doc.insert("schema_version", Item::Value(i64::from(current).into()));
+    }
+    Ok(Migrated {
+        from_version,
+        source: doc.to_string(),
+        changes,
+    })
+}
+
+/// Dotted name of `key` in the table at `path` (`window.poll_ms`)
+fn dotted(path: &[&str], key: &str) -> String {
+    path.iter().copied().chain([key]).collect::<Vec<_>>().join(".")
+}
+
+/// Table at `path` (`&["window"]` for `[window]`), if present
+fn table_mut<'a>(doc: &'a mut DocumentMut, path: &[&str]) -> Option<&'a mut Table> {
+    let mut table = doc.as_table_mut();
+    for key in path {
+        table = table.get_mut(key)?.as_table_mut()?;
+    }
+    Some(table)
+}
+
+/// Rename key `old` of the table at `path` to `new`, keeping its value and
+/// comments; for use in `MIGRATIONS`
+pub fn rename_key(doc: &mut DocumentMut, path: &[&str], old: &str, new: &str) -> Option<String> {
+    let table = table_mut(doc, path)?;
+    if table.contains_key(new) {
+        return None;
+    }
+    let (key, item) = table.remove_entry(old)?;
+    let decor = key.leaf_decor().clone();
+    table.insert(new, item);
+    if let Some(mut key) = table.key_mut(new) {
+        *key.leaf_decor_mut() = decor;
+    }
+    Some(format!("renamed {} to {}", dotted(path, old), dotted(path, new)))
+}
+
+/// Move the table or value at `from` to `to` (a missing parent table is
+/// created); for use in `MIGRATIONS`
+pub fn move_item(doc: &mut DocumentMut, from: &[&str], to: &[&str]) -> Option<String> {
+    let (from_key, from_parent) = from.split_last()?;
+    let (to_key, to_parent) = to.split_last()?;
+    if table_mut(doc, to_parent).is_some_and(|table| table.contains_key(to_key)) {
+        return None;
+    }
+    let item = table_mut(doc, from_parent)?.remove(from_key)?;
+
+    let mut table = doc.as_table_mut();
+    for key in to_parent {
+        let entry = table.entry(key).or_insert_with(|| {
+            let mut parent = Table::new();
+            parent.set_implicit(true);
+            Item::Table(parent)
+        });
+        table = entry.as_table_mut()?;
+    }
+    table.insert(to_key, item);
+    Some(format!("moved {} to {}", from.join("."), to.join(".")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_poll(doc: &mut DocumentMut) -> Vec<String> {
+        rename_key(doc, &["window"], "poll_ms", "poll_timeout_ms").into_iter().collect()
+    }
+
+    fn move_general_delays(doc: &mut DocumentMut) -> Vec<String> {
+        move_item(doc, &["general", "delays"], &["delays"]).into_iter().collect()
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration { from: 1, apply: rename_poll },
+        Migration { from: 2, apply: move_general_delays },
+    ];
+
+    #[test]
+    fn test_migrate_applies_each_step() {
+        let old = "# my config\n[window]\npoll_ms = 50 # fast\n\n[general.delays]\nkey_post_delay_ms = 5\n";
+        let migrated = migrate_with(old, TEST_MIGRATIONS, 3).unwrap();
+        assert_eq!(migrated.from_version, 1);
+        assert_eq!(
+            migrated.changes,
+            vec![
+                "renamed window.poll_ms to window.poll_timeout_ms",
+                "moved general.delays to delays",
+            ]
+        );
+        assert!(migrated.source.starts_with("schema_version = 3\n# my config"));
+        assert!(migrated.source.contains("poll_timeout_ms = 50 # fast"));
+        let config = crate::config::Config::from_toml(&migrated.source.replace("schema_version = 3", "")).unwrap();
+        assert_eq!(config.poll_timeout_ms, Some(50));
+        assert_eq!(config.key_post_delay_ms, Some(5));
+
+        // Starting later skips the steps already applied
+        let partial = migrate_with("schema_version = 2\n[general.delays]\nkey_post_delay_ms = 5\n", TEST_MIGRATIONS, 3).unwrap();
+        assert_eq!(partial.changes, vec!["moved general.delays to delays"]);
+    }
+
+    #[test]
+    fn test_migrate_current_and_newer() {
+        let current = migrate("[window]\nupdate_interval_ms = 100\n").unwrap();
+        assert_eq!(current.from_version, CURRENT_SCHEMA_VERSION);
+        assert!(current.changes.is_empty());
+        assert!(current.source.contains(&format!("schema_version = {}", CURRENT_SCHEMA_VERSION)));
+        assert!(crate::config::Config::from_toml(&current.source).is_ok());
+
+        let newer = format!("schema_version = {}\n", CURRENT_SCHEMA_VERSION + 1);
+        assert!(matches!(migrate(&newer), Err(ConfigError::InvalidValue(_))));
+        assert!(crate::config::Config::from_toml(&newer).is_err());
+        assert!(migrate("schema_version = \"one\"\n").is_err());
+    }
+}
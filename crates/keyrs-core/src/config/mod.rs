@@ -4,11 +4,58 @@
 pub mod combo_parser;
 pub mod keymap_expander;
 
+#[cfg(feature = "pure-rust")]
+pub mod cheatsheet;
+#[cfg(feature = "pure-rust")]
+pub mod chromebook;
+#[cfg(feature = "pure-rust")]
+pub mod compose;
+#[cfg(feature = "pure-rust")]
+pub mod conflicts;
+#[cfg(feature = "pure-rust")]
+pub mod edit;
+#[cfg(feature = "pure-rust")]
+pub mod lint;
+#[cfg(feature = "pure-rust")]
+pub mod migrate;
+#[cfg(feature = "pure-rust")]
+pub mod optspec;
 #[cfg(feature = "pure-rust")]
 pub mod parser;
+#[cfg(feature = "pure-rust")]
+pub mod schema;
+#[cfg(feature = "pure-rust")]
+pub mod toshy;
+#[cfg(feature = "pure-rust")]
+pub mod validate;
+#[cfg(feature = "pure-rust")]
+pub mod vars;
 
-pub use combo_parser::{parse_combo_string, ComboParseError, ParsedCombo};
+pub use combo_parser::{
+    complete_combo, parse_combo_string, parse_combo_with, suggest_key_names, suggest_modifier_aliases,
+    validate_combo, validate_combo_with, ComboParseError, ComboTrigger, ComboValidationError, ParsedCombo,
+};
 pub use keymap_expander::{expand_combo, expand_keymap_entries};
 
 #[cfg(feature = "pure-rust")]
-pub use parser::{Config, ConfigError, KeymapEntry, KeymapOutput, ModmapEntry, MultipurposeEntry};
+pub use cheatsheet::{render_cheatsheet, CheatsheetFormat};
+#[cfg(feature = "pure-rust")]
+pub use compose::{load_with_includes, merge_config_fragment};
+#[cfg(feature = "pure-rust")]
+pub use conflicts::KeymapConflict;
+#[cfg(feature = "pure-rust")]
+pub use edit::{add_keymap_mapping, config_outline, set_keymap_enabled, OutlineEntry, OutlineKind};
+#[cfg(feature = "pure-rust")]
+pub use lint::{lint_config, LintFinding, LintKind};
+#[cfg(feature = "pure-rust")]
+pub use migrate::{migrate, migrate_files, Migrated, CURRENT_SCHEMA_VERSION};
+#[cfg(feature = "pure-rust")]
+pub use parser::{
+    Config, ConfigError, KeymapEntry, KeymapOutput, ModmapEntry, MultipurposeEntry, TapDanceEntry,
+};
+#[cfg(feature = "pure-rust")]
+pub use schema::{config_schema, config_schema_json};
+#[cfg(feature = "pure-rust")]
+pub use toshy::{import_toshy, ImportWarning, ToshyImport};
+#[cfg(feature = "pure-rust")]
+pub use validate::{validate_file, validate_str, Diagnostic};
@@ -0,0 +1,187 @@
+// Keyrs Config - Option-Key Special Characters
+// Built-in keymaps typing the macOS Option-key symbols (Alt-s = ß, Alt-e = dead acute)
+
+use super::parser::{ConfigError, KeymapEntry, KeymapOutput};
+use crate::key::key_from_name;
+use crate::transform::deadkeys::DeadKeyKind;
+use crate::Key;
+
+/// Setting that turns the Option-key characters on and off
+pub const OPTSPEC_SETTING: &str = "optspec_chars";
+
+/// Option-layer tables shipped with keyrs, by `settings.optspec_layout` value
+const LAYOUT_TABLES: [(&str, &str); 2] = [
+    ("ABC", include_str!("../../data/optspec/abc.tsv")),
+    ("US", include_str!("../../data/optspec/us.tsv")),
+];
+
+/// What an Option combo types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionChar {
+    /// A character
+    Char(char),
+    /// A dead key accenting the next letter
+    Dead(DeadKeyKind),
+}
+
+impl OptionChar {
+    fn output(self) -> KeymapOutput {
+        match self {
+            // Starts the engine's built-in dead key
+            OptionChar::Dead(kind) => KeymapOutput::Unicode(kind.display_codepoint()),
+            // A spacing accent (Shift-Option-e = ´) is typed, not turned into a dead key
+            OptionChar::Char(ch) if DeadKeyKind::from_codepoint(ch as u32).is_some() => {
+                KeymapOutput::Text(ch.to_string())
+            }
+            OptionChar::Char(ch) => KeymapOutput::Unicode(ch as u32),
+        }
+    }
+}
+
+/// One key of an Option-layer table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionRow {
+    pub key: Key,
+    /// Typed by Option-key
+    pub option: OptionChar,
+    /// Typed by Shift-Option-key
+    pub shift_option: OptionChar,
+}
+
+/// Parse an Option-layer table: `KEY option shift-option` rows, `#` comments
+pub fn parse_layout_table(source: &str) -> Result<Vec<OptionRow>, ConfigError> {
+    let mut rows = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |what: &str| ConfigError::InvalidValue(format!("optspec table line {}: {}", index + 1, what));
+        let cells: Vec<&str> = line.split_whitespace().collect();
+        let [key, option, shift_option] = cells[..] else {
+            return Err(invalid("expected a key and two characters"));
+        };
+        rows.push(OptionRow {
+            key: key_from_name(key).ok_or_else(|| invalid(&format!("unknown key '{}'", key)))?,
+            option: parse_cell(option).ok_or_else(|| invalid(&format!("bad character '{}'", option)))?,
+            shift_option: parse_cell(shift_option)
+                .ok_or_else(|| invalid(&format!("bad character '{}'", shift_option)))?,
+        });
+    }
+    Ok(rows)
+}
+
+/// `ß`, `U+F8FF` or `dead:acute`
+fn parse_cell(cell: &str) -> Option<OptionChar> {
+    if let Some(name) = cell.strip_prefix("dead:") {
+        return DeadKeyKind::from_name(name).map(OptionChar::Dead);
+    }
+    if let Some(hex) = cell.strip_prefix("U+") {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(OptionChar::Char);
+    }
+    let mut chars = cell.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(OptionChar::Char(ch)),
+        _ => None,
+    }
+}
+
+/// One keymap per layout table, active while `settings.optspec_chars` is on
+/// and `settings.optspec_layout` names the table; `condition` further limits
+/// the windows they apply in
+pub(crate) fn optspec_keymaps(condition: Option<&str>) -> Result<Vec<KeymapEntry>, ConfigError> {
+    let mut keymaps = Vec::new();
+    for (layout, source) in LAYOUT_TABLES {
+        let mut mappings = Vec::new();
+        for row in parse_layout_table(source)? {
+            let name = row.key.name();
+            mappings.push((format!("Alt-{}", name), row.option.output()));
+            mappings.push((format!("Alt-Shift-{}", name), row.shift_option.output()));
+        }
+        let gate = format!("settings.{} and settings.optspec_layout == '{}'", OPTSPEC_SETTING, layout);
+        keymaps.push(KeymapEntry {
+            name: format!("Option special characters ({})", layout),
+            mappings,
+            condition: Some(match condition {
+                Some(condition) => format!("{} and ({})", gate, condition),
+                None => gate,
+            }),
+            repeats: Vec::new(),
+            descriptions: Vec::new(),
+            holds: Vec::new(),
+            nested_timeout: None,
+            combo_style: None,
+        });
+    }
+    Ok(keymaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::transform::engine::{TransformEngine, TransformResult};
+    use crate::Action;
+
+    #[test]
+    fn test_layout_tables_parse() {
+        for (layout, source) in LAYOUT_TABLES {
+            let rows = parse_layout_table(source).unwrap();
+            assert_eq!(rows.len(), 47, "{}", layout);
+            let row = |name: &str| *rows.iter().find(|row| row.key.name() == name).unwrap();
+            assert_eq!(row("S").option, OptionChar::Char('ß'));
+            assert_eq!(row("K").shift_option, OptionChar::Char('\u{F8FF}'));
+            assert_eq!(row("E").option, OptionChar::Dead(DeadKeyKind::Acute));
+        }
+
+        assert!(parse_layout_table("S ß").is_err());
+        assert!(parse_layout_table("NOPE ß Í").is_err());
+        assert!(parse_layout_table("S dead:cedilla Í").is_err());
+        assert!(parse_layout_table("# comment\n\nS U+DF Í\n").is_ok());
+    }
+
+    #[test]
+    fn test_optspec_keymaps() {
+        let keymaps = optspec_keymaps(Some("not wm_class =~ 'term'")).unwrap();
+        let [abc, us] = &keymaps[..] else { panic!("expected two keymaps") };
+        assert_eq!(us.mappings.len(), 47 * 2);
+        assert_eq!(
+            us.condition.as_deref(),
+            Some("settings.optspec_chars and settings.optspec_layout == 'US' and (not wm_class =~ 'term')")
+        );
+        assert!(abc.condition.as_deref().unwrap().contains("== 'ABC'"));
+        let output = |combo: &str| {
+            us.mappings.iter().find(|(c, _)| c == combo).map(|(_, output)| format!("{:?}", output))
+        };
+        assert_eq!(output("Alt-F"), Some(format!("{:?}", KeymapOutput::Unicode('ƒ' as u32))));
+        assert_eq!(output("Alt-U"), Some(format!("{:?}", KeymapOutput::Unicode(0x00A8))));
+        assert_eq!(output("Alt-Shift-E"), Some(format!("{:?}", KeymapOutput::Text("´".to_string()))));
+    }
+
+    #[test]
+    fn test_optspec_engine() {
+        let config = Config::from_toml("[optspec]\n").unwrap();
+        assert!(config.toggle_settings.contains(&(OPTSPEC_SETTING.to_string(), true)));
+        let mut engine = TransformEngine::new(config.to_transform_config());
+        let alt = Key::from(56);
+        let s = Key::from(31);
+        let e = Key::from(18);
+
+        engine.process_event(alt, Action::Press);
+        assert_eq!(engine.process_event(s, Action::Press), TransformResult::Unicode('ß' as u32));
+        engine.process_event(s, Action::Release);
+
+        // Option-e then e types é
+        assert_eq!(engine.process_event(e, Action::Press), TransformResult::Suppress);
+        engine.process_event(e, Action::Release);
+        engine.process_event(alt, Action::Release);
+        assert_eq!(engine.process_event(e, Action::Press), TransformResult::Unicode('é' as u32));
+        engine.process_event(e, Action::Release);
+
+        engine.set_setting(OPTSPEC_SETTING, false);
+        engine.process_event(alt, Action::Press);
+        assert_ne!(engine.process_event(s, Action::Press), TransformResult::Unicode('ß' as u32));
+
+        assert!(Config::from_toml("[optspec]\nenabled = false\n").unwrap().toggle_settings.is_empty());
+    }
+}
@@ -2,28 +2,35 @@
 // Parses configuration from TOML files
 
 #[cfg(feature = "pure-rust")]
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 #[cfg(feature = "pure-rust")]
 use std::fs;
+use std::net::SocketAddr;
 #[cfg(feature = "pure-rust")]
-use std::path::Path;
-#[cfg(feature = "pure-rust")]
-use std::sync::OnceLock;
-
-use crate::mapping::{ActionStep, Keymap, KeymapValue, Modmap, MultiModmap};
+use std::path::{Path, PathBuf};
+
+use crate::condition::{expand_named_conditions, resolve_named_conditions};
+use crate::input::{parse_scancode, GrabPolicies, GrabPolicy, InternalKeyboardPolicy, SeatFilter, DEFAULT_PANIC_CHORD};
+use crate::key::KeyNames;
+use crate::layout::KeyboardLayout;
+use crate::logging::{self, LogFormat, LogSettings};
+use crate::output::{
+    ComboStyle, DelayOverride, ObserverPrivacy, OutputDelays, RemapRepeat, UnicodeBackend, UnicodeBatch,
+    DEFAULT_UNICODE_BATCH_MIN,
+};
+use crate::mapping::{ActionStep, Keymap, KeymapValue, LayerAction, LayerMode, Modmap, MultiModmap, RepeatRate, TapDance, MAX_REPEAT_EXPANSION, MAX_REPEAT_TIMES};
+use crate::audit::DEFAULT_AUDIT_CAPACITY;
+use crate::metrics::DEFAULT_METRICS_ADDR;
+use crate::transform::engine::DEFAULT_PROFILE;
+use crate::transform::media::{is_media_key, MediaMapping};
+use super::chromebook::{top_row_entries, DEFAULT_TOP_ROW, MAX_TOP_ROW};
+use super::combo_parser::ComboTrigger;
+use super::optspec::{optspec_keymaps, OPTSPEC_SETTING};
+use super::vars::expand_config_vars;
 use crate::{Combo, ComboHint, Key, Modifier};
+use schemars::JsonSchema;
 use serde::Deserialize;
 
-#[cfg(feature = "pure-rust")]
-fn config_debug_enabled() -> bool {
-    static DEBUG_CONFIG: OnceLock<bool> = OnceLock::new();
-    *DEBUG_CONFIG.get_or_init(|| {
-        std::env::var("KEYRS_DEBUG_CONFIG")
-            .map(|v| matches!(v.as_str(), "1" | "true" | "TRUE" | "yes" | "on"))
-            .unwrap_or(false)
-    })
-}
-
 /// Configuration parser errors
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
@@ -44,12 +51,30 @@ pub enum ConfigError {
 
     #[error("Timeout value out of range: {0}")]
     TimeoutOutOfRange(String),
+
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+
+    #[error("Undefined variable: {0}")]
+    UndefinedVariable(String),
 }
 
 /// Main configuration structure (root TOML table)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct ConfigToml {
+    /// Config format version (see `migrate`); absent means 1
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+
+    /// Fragment files merged at load time (globs relative to this file)
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Condition under which remapping is suspended (remote desktops, VMs)
+    #[serde(default)]
+    pub suspend_when: Option<String>,
+
     /// General settings
     #[serde(default)]
     pub general: Option<GeneralConfig>,
@@ -62,14 +87,64 @@ pub struct ConfigToml {
     #[serde(default)]
     pub multipurpose: Vec<MultipurposeTomlEntry>,
 
+    /// Tap-dance entries (N taps -> Nth output)
+    #[serde(default)]
+    pub tapdance: Vec<TapDanceTomlEntry>,
+
+    /// Modifier tapped alone -> key or combo (`LeftMeta = "LAUNCHER"`)
+    #[serde(default)]
+    pub modtap: HashMap<String, String>,
+
+    /// Raw scancode -> key, applied before any other mapping (`"0x70039" = "ESC"`)
+    #[serde(default)]
+    pub scancodes: HashMap<String, String>,
+
+    /// User-defined key names (`Hyper = "F13"`), usable wherever a key name is
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+
+    /// Variables (`terminals = "kitty|foot"`) substituted for `${name}` in
+    /// every other string
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+
+    /// Named conditions (`terminals = "wm_class =~ 'kitty'"`), usable as a
+    /// term of any `condition`
+    #[serde(default)]
+    pub conditions: HashMap<String, String>,
+
     /// Keymaps configuration
     #[serde(default)]
     pub keymap: Vec<KeymapTomlEntry>,
 
+    /// Named layers (`[layers.<name>]`), activated by `Layer(...)` outputs
+    #[serde(default)]
+    pub layers: HashMap<String, LayerTomlEntry>,
+
     /// Timeouts configuration
     #[serde(default)]
     pub timeouts: Option<TimeoutConfig>,
 
+    /// Hold a letter or number to type its shifted form
+    #[serde(default)]
+    pub autoshift: Option<AutoShiftConfig>,
+
+    /// ChromeOS behavior for the top-row keys of Chromebook keyboards
+    #[serde(default)]
+    pub chromebook: Option<ChromebookConfig>,
+
+    /// macOS Option-key special characters (`Alt-s` types `ß`)
+    #[serde(default)]
+    pub optspec: Option<OptspecConfig>,
+
+    /// Media keys renamed or bound to commands
+    #[serde(default)]
+    pub mediamap: Option<MediamapConfig>,
+
+    /// Windows where every key passes through untouched
+    #[serde(default)]
+    pub ignore: Option<IgnoreConfig>,
+
     /// Device filter configuration
     #[serde(default)]
     pub devices: Option<DevicesConfig>,
@@ -81,10 +156,46 @@ pub struct ConfigToml {
     // Main event loop and window polling behavior
     #[serde(default)]
     pub window: Option<WindowConfig>,
+
+    /// Read-only output mirror for key overlay tools
+    #[serde(default)]
+    pub observer: Option<ObserverConfig>,
+
+    /// Output backend selection
+    #[serde(default)]
+    pub output: Option<OutputConfig>,
+
+    /// XCompose sequences for dead keys and the Compose key
+    #[serde(default)]
+    pub compose: Option<ComposeConfig>,
+
+    /// Keyboard layout used for single-character key names and text output
+    #[serde(default)]
+    pub layout: Option<LayoutConfig>,
+
+    /// Event counters and latency histogram
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+
+    /// Ring buffer of the last outputs and their inputs, for `keyrs replay`
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+
+    /// Log levels and format
+    #[serde(default)]
+    pub logging: Option<LoggingConfig>,
+
+    /// Login session integration (pause while locked)
+    #[serde(default)]
+    pub session: Option<SessionConfig>,
+
+    /// Other configs preloaded for switching at runtime
+    #[serde(default)]
+    pub profiles: Option<ProfilesConfig>,
 }
 
 /// General settings
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct GeneralConfig {
     /// Suspend key name
@@ -93,19 +204,50 @@ pub struct GeneralConfig {
     pub diagnostics_key: Option<String>,
     /// Emergency eject key name
     pub emergency_eject_key: Option<String>,
+    /// Keys that stop keyrs when held together (`[]` disables)
+    pub panic_chord: Option<Vec<String>>,
+    /// How long the panic chord must be held (milliseconds)
+    pub panic_hold_ms: Option<u64>,
 }
 
 /// Device filtering configuration
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct DevicesConfig {
     /// Explicit device names/paths to use
     #[serde(default)]
     pub only: Vec<String>,
+
+    /// When to ignore the internal keyboard
+    /// ("always", "lid_closed", "external_present", "lid_closed_or_external")
+    pub internal_policy: Option<String>,
+
+    /// Names/paths identifying the internal keyboard (empty = autodetect)
+    #[serde(default)]
+    pub internal: Vec<String>,
+
+    /// Also autodetect gamepads/joysticks so their buttons can be remapped
+    pub include_gamepads: Option<bool>,
+
+    /// Show the same Num/Caps Lock LEDs on every grabbed keyboard
+    pub mirror_lock_leds: Option<bool>,
+
+    /// Wait for held keys to be released before grabbing at startup
+    pub wait_for_release: Option<bool>,
+
+    /// Longest wait for held keys at startup (milliseconds)
+    pub release_timeout_ms: Option<u64>,
+
+    /// How devices are grabbed ("full", "keys-only", "observe")
+    pub grab: Option<String>,
+
+    /// Per-device grab policy (device name/path -> policy), overriding `grab`
+    #[serde(default)]
+    pub grab_devices: HashMap<String, String>,
 }
 
 /// Modmap configuration (supports default and conditional modmaps)
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ModmapConfig {
     /// Default modmap (applies to all windows)
@@ -117,7 +259,7 @@ pub struct ModmapConfig {
 }
 
 /// Conditional modmap entry
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct ConditionalModmap {
     /// Name identifier for this modmap
@@ -131,7 +273,7 @@ pub struct ConditionalModmap {
 }
 
 /// Multipurpose modmap entry (tap/hold behavior)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct MultipurposeTomlEntry {
     /// Name identifier for this multipurpose modmap
@@ -148,10 +290,36 @@ pub struct MultipurposeTomlEntry {
 
     /// Optional condition string (window-specific)
     pub condition: Option<String>,
+
+    /// Settings flag the entry is gated on, listed as a toggle in keyrs-tui
+    pub toggle_setting: Option<String>,
+
+    /// Value of `toggle_setting` while settings.toml doesn't set it (default false)
+    pub toggle_default: Option<bool>,
+}
+
+/// Tap-dance entry (tap count selects the output, optional hold)
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TapDanceTomlEntry {
+    /// Name identifier for this tap-dance
+    pub name: String,
+
+    /// Trigger key (the key being remapped)
+    pub trigger: String,
+
+    /// Output keys by tap count (first = single tap, second = double tap, ...)
+    pub taps: Vec<String>,
+
+    /// Optional output key for hold (long press)
+    pub hold: Option<String>,
+
+    /// Optional condition string (window-specific)
+    pub condition: Option<String>,
 }
 
 /// Keymap entry (can be array of tables or single table)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct KeymapTomlEntry {
     /// Optional name for this keymap
@@ -163,10 +331,48 @@ pub struct KeymapTomlEntry {
 
     /// Optional condition string (window-specific)
     pub condition: Option<String>,
+
+    /// Set to false to keep the keymap in the file but skip it
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// How long this keymap stays entered as a nested keymap (milliseconds),
+    /// overriding `[timeouts] nested_keymap`
+    pub nested_timeout: Option<u64>,
+
+    /// Order in which combo modifiers are pressed (released in reverse),
+    /// e.g. `["ctrl", "alt", "shift", "meta"]`
+    pub modifier_order: Option<Vec<String>>,
+
+    /// Pause between the events of each combo (milliseconds),
+    /// overriding `[delays] combo_delay_ms`
+    pub combo_delay_ms: Option<u64>,
+
+    /// Settings flag the keymap is gated on, listed as a toggle in keyrs-tui
+    pub toggle_setting: Option<String>,
+
+    /// Value of `toggle_setting` while settings.toml doesn't set it (default false)
+    pub toggle_default: Option<bool>,
+}
+
+/// Layer entry (`[layers.<name>]`)
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LayerTomlEntry {
+    /// Combo-to-output mappings active while the layer is on
+    #[serde(default)]
+    pub mappings: HashMap<String, KeymapTomlOutput>,
+
+    /// Optional condition string (window-specific)
+    pub condition: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
 }
 
 /// Output side of a keymap entry (supports various formats)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum KeymapTomlOutput {
     /// Single key as string
@@ -174,10 +380,76 @@ pub enum KeymapTomlOutput {
 
     /// List of outputs (for sequences)
     Multiple(Vec<String>),
+
+    /// Output with per-mapping options (`{ output = "Down", repeat = { ... }, description = "..." }`,
+    /// `{ tap = "...", hold = "..." }`)
+    Detailed(KeymapTomlDetailed),
+}
+
+/// Keymap output table form
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KeymapTomlDetailed {
+    /// The output, in string or list form (`tap` when there is a `hold`)
+    #[serde(alias = "tap")]
+    pub output: Box<KeymapTomlOutput>,
+    /// Output when the combo is held past the multipurpose timeout; `output`
+    /// is then the tap
+    pub hold: Option<Box<KeymapTomlOutput>>,
+    /// Repeat the output while the combo is held
+    pub repeat: Option<RepeatToml>,
+    /// What the mapping does, shown by `keyrs cheatsheet`
+    pub description: Option<String>,
+}
+
+/// Synthetic repeat settings for a mapping
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RepeatToml {
+    /// Delay before the first repeat (default 250)
+    pub delay_ms: Option<u64>,
+    /// Interval between repeats (default 30)
+    pub interval_ms: Option<u64>,
+}
+
+/// Default `[devices] release_timeout_ms`
+const DEFAULT_RELEASE_WAIT_MS: u64 = 1000;
+
+/// Default `[window] update_interval_ms`
+const DEFAULT_WINDOW_UPDATE_INTERVAL_MS: u64 = 500;
+/// Longest window polling interval with `[window] title_sensitive`
+const TITLE_SENSITIVE_UPDATE_INTERVAL_MS: u64 = 100;
+
+/// Defaults for `repeat = { ... }` fields left out
+const DEFAULT_REPEAT_DELAY_MS: u64 = 250;
+const DEFAULT_REPEAT_INTERVAL_MS: u64 = 30;
+
+impl RepeatToml {
+    /// Validate into a runtime repeat rate
+    pub(crate) fn to_rate(&self) -> Result<RepeatRate, ConfigError> {
+        let delay_ms = self.delay_ms.unwrap_or(DEFAULT_REPEAT_DELAY_MS);
+        let interval_ms = self.interval_ms.unwrap_or(DEFAULT_REPEAT_INTERVAL_MS);
+        if delay_ms > 5000 {
+            return Err(ConfigError::TimeoutOutOfRange(format!(
+                "repeat.delay_ms must be 0-5000ms, got {}",
+                delay_ms
+            )));
+        }
+        if !(5..=5000).contains(&interval_ms) {
+            return Err(ConfigError::TimeoutOutOfRange(format!(
+                "repeat.interval_ms must be 5-5000ms, got {}",
+                interval_ms
+            )));
+        }
+        Ok(RepeatRate {
+            delay_ms,
+            interval_ms,
+        })
+    }
 }
 
 /// Timeout configuration
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct TimeoutConfig {
     /// Multipurpose modmap timeout (milliseconds)
@@ -185,20 +457,155 @@ pub struct TimeoutConfig {
 
     /// Suspend timeout (milliseconds)
     pub suspend: Option<u64>,
+
+    /// How long a nested keymap stays entered without a key (milliseconds)
+    pub nested_keymap: Option<u64>,
+
+    /// Longest modifier tap that still emits its `[modtap]` output (milliseconds)
+    pub modtap: Option<u64>,
+
+    /// Window in which a momentary layer trigger released before the next key
+    /// counts as typing, not layer use (milliseconds)
+    pub layer_rollover: Option<u64>,
+
+    /// Longest an output key may stay down without a repeat before it is
+    /// force-released (milliseconds; unset or 0 = never)
+    pub stuck_key: Option<u64>,
+
+    /// A dead key left this long without a letter types its bare accent
+    /// (milliseconds)
+    pub dead_key: Option<u64>,
+}
+
+/// Autoshift configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AutoShiftConfig {
+    /// How long a key must be held to type its shifted form (milliseconds)
+    pub timeout: Option<u64>,
+    /// Keyboard rows to autoshift ("number", "top", "home", "bottom"; default all)
+    pub rows: Option<Vec<String>>,
+    /// Extra keys to autoshift
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Keys left alone even though their row is enabled
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Media key remapping (`[mediamap]`)
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MediamapConfig {
+    /// Media keys skip modmaps, keymaps and layers (default false)
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Media key name -> key name or `Run(command)`
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Chromebook top-row translation (`[chromebook]`)
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChromebookConfig {
+    /// Translate the top row (default true once the section exists)
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Action keys of the top row, left to right (default: the classic Chromebook row)
+    pub top_row: Option<Vec<String>>,
+}
+
+/// Option-key special characters (`[optspec]`)
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OptspecConfig {
+    /// Generate the Option-key keymaps (default true once the section exists)
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Windows the characters are typed in (default: all)
+    pub condition: Option<String>,
+}
+
+/// Windows keyrs leaves alone
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IgnoreConfig {
+    /// Patterns matched like `wm_class =~ '...'`
+    #[serde(default)]
+    pub wm_class: Vec<String>,
+    /// Patterns matched like `wm_name =~ '...'`
+    #[serde(default)]
+    pub wm_name: Vec<String>,
+}
+
+/// Keys of each `[autoshift]` row (US positions)
+pub(crate) const AUTOSHIFT_ROWS: &[(&str, &[u16])] = &[
+    // GRAVE, 1-0, MINUS, EQUAL
+    ("number", &[41, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]),
+    // Q-P, LEFT_BRACE, RIGHT_BRACE, BACKSLASH
+    ("top", &[16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 43]),
+    // A-L, SEMICOLON, APOSTROPHE
+    ("home", &[30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40]),
+    // Z-M, COMMA, DOT, SLASH
+    ("bottom", &[44, 45, 46, 47, 48, 49, 50, 51, 52, 53]),
+];
+
+/// Why an `[ignore]` pattern can never match, if it can't
+pub(crate) fn ignore_pattern_error(field: &str, pattern: &str) -> Option<String> {
+    let bare = pattern.trim().trim_start_matches("(?i)").trim_matches(['^', '$']);
+    if bare.split('|').any(|alternative| alternative.trim().is_empty()) {
+        Some(format!("ignore.{}: empty pattern in '{}'", field, pattern))
+    } else {
+        None
+    }
+}
+
+/// Keys of one `[autoshift]` row
+pub(crate) fn autoshift_row(name: &str) -> Result<&'static [u16], ConfigError> {
+    AUTOSHIFT_ROWS
+        .iter()
+        .find(|(row, _)| row.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, codes)| *codes)
+        .ok_or_else(|| {
+            ConfigError::InvalidValue(format!(
+                "autoshift.rows must be among number, top, home, bottom, got '{}'",
+                name
+            ))
+        })
 }
 
 /// Output delay configuration (milliseconds)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct DelayConfig {
     /// Delay before key output
     pub key_pre_delay_ms: Option<u64>,
     /// Delay after key output
     pub key_post_delay_ms: Option<u64>,
+    /// Pause between the events of one combo
+    pub combo_delay_ms: Option<u64>,
+    /// Delays for windows matching a condition
+    #[serde(default)]
+    pub overrides: Vec<DelayOverrideToml>,
+}
+
+/// `[[delays.overrides]]` entry
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DelayOverrideToml {
+    /// Window context condition
+    pub condition: String,
+    /// Delay before key output
+    pub key_pre_delay_ms: Option<u64>,
+    /// Delay after key output
+    pub key_post_delay_ms: Option<u64>,
+    /// Pause between the events of one combo
+    pub combo_delay_ms: Option<u64>,
 }
 
 /// Main loop / window polling configuration (milliseconds)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct WindowConfig {
     /// Timeout passed to evdev poll loop
@@ -207,6 +614,120 @@ pub struct WindowConfig {
     pub update_interval_ms: Option<u64>,
     /// Sleep duration after a no-event poll error path
     pub idle_sleep_ms: Option<u64>,
+    /// Poll often enough for `wm_name` conditions to follow title changes
+    #[serde(default)]
+    pub title_sensitive: bool,
+}
+
+/// Output mirror configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ObserverConfig {
+    /// Accept `subscribe` requests on the control socket
+    #[serde(default)]
+    pub enabled: bool,
+    /// Redaction level ("off", "text", "typing")
+    pub privacy: Option<String>,
+}
+
+/// Output backend configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Unicode output backend ("compose", "virtual_keyboard")
+    pub unicode: Option<String>,
+    /// Text output strategy for long non-ASCII text ("off", "clipboard")
+    pub unicode_batch: Option<String>,
+    /// Non-ASCII characters from which `unicode_batch` applies
+    pub unicode_batch_min: Option<usize>,
+    /// Combo that pastes batched text (default Ctrl-v)
+    pub paste: Option<String>,
+    /// Repeat events of remapped keys ("forward", "drop")
+    pub remap_repeat: Option<String>,
+}
+
+/// XCompose table configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ComposeConfig {
+    /// Load compose sequences
+    #[serde(default)]
+    pub enabled: bool,
+    /// Compose file (default: $XCOMPOSEFILE, ~/.XCompose, then the locale's system table)
+    pub file: Option<String>,
+}
+
+/// Metrics configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Collect metrics (dumped on the diagnostics key)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Serve OpenMetrics over HTTP on this address ("127.0.0.1:9187"), or "" to disable
+    pub listen: Option<String>,
+}
+
+/// Audit log configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Record the last outputs with the inputs that produced them (dumped on
+    /// the diagnostics key and by `keyrs audit`)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Entries kept (default 500)
+    pub capacity: Option<usize>,
+}
+
+/// Login session configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Pass keys through unchanged while logind reports the session locked (default true)
+    pub pause_on_lock: Option<bool>,
+    /// How often to query the lock state (milliseconds)
+    pub lock_poll_ms: Option<u64>,
+    /// Release devices and pause while another session is in the foreground (default true)
+    pub pause_when_inactive: Option<bool>,
+    /// Seat whose devices are used: "auto" (the session's), "any", or a seat name
+    pub seat: Option<String>,
+}
+
+/// Named configs to switch between at runtime
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilesConfig {
+    /// Name of this config's own profile (default "default")
+    pub name: Option<String>,
+    /// Other profiles: name -> config file, relative to this one
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Default level ("off", "error", "warn", "info", "debug", "trace")
+    pub level: Option<String>,
+    /// Per-subsystem levels (`transform = "debug"`); keys may also be module paths
+    #[serde(default)]
+    pub modules: HashMap<String, String>,
+    /// Line format ("text", "json")
+    pub format: Option<String>,
+}
+
+/// Keyboard layout configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// XKB layout name ("fr", "de", "us"), or "auto" to detect the session layout
+    pub name: String,
+    /// XKB variant ("dvorak", "colemak", "bepo")
+    pub variant: Option<String>,
+    /// Whether Right Alt is AltGr (default: what the layout says)
+    pub altgr: Option<bool>,
 }
 
 // Use TimeoutConfig directly (serde handles both singular and plural)
@@ -219,30 +740,133 @@ pub struct Config {
     pub modmaps: Vec<ModmapEntry>,
     /// Multipurpose modmaps (tap/hold behavior)
     pub multipurpose: Vec<MultipurposeEntry>,
+    /// Tap-dance entries
+    pub tapdance: Vec<TapDanceEntry>,
+    /// Modifier-only taps (trigger modifier, output), sorted by trigger
+    pub modtap: Vec<(Key, Combo)>,
+    /// Scancode remaps (MSC_SCAN value, key), sorted by scancode
+    pub scancodes: Vec<(u32, Key)>,
     /// Keymaps
     pub keymaps: Vec<KeymapEntry>,
+    /// Layer keymaps (entry name = layer name)
+    pub layers: Vec<KeymapEntry>,
     /// Optional suspend key
     pub suspend_key: Option<Key>,
     /// Multipurpose key timeout (milliseconds)
     pub multipurpose_timeout: Option<u64>,
     /// Suspend timeout (milliseconds)
     pub suspend_timeout: Option<u64>,
+    /// How long a nested keymap stays entered (milliseconds, `None` = until left)
+    pub nested_keymap_timeout: Option<u64>,
+    /// Modifier tap timeout (milliseconds)
+    pub modtap_timeout: Option<u64>,
+    /// Momentary layer roll-over window (milliseconds, off when unset)
+    pub layer_rollover_timeout: Option<u64>,
+    /// Keys typed shifted when held (empty = autoshift off), sorted
+    pub autoshift_keys: Vec<Key>,
+    /// Hold time before an autoshift key types its shifted form (milliseconds)
+    pub autoshift_timeout: Option<u64>,
+    /// Pending time after which a dead key types its bare accent (milliseconds)
+    pub dead_key_timeout: Option<u64>,
+    /// Chromebook top-row action keys, left to right (empty = translation off)
+    pub chromebook_top_row: Vec<Key>,
+    /// `[mediamap]` entries, by media key
+    pub mediamap: Vec<(Key, MediaMapping)>,
+    /// Media keys skip modmaps, keymaps and layers
+    pub media_passthrough: bool,
+    /// `[ignore]` wm_class patterns
+    pub ignore_wm_class: Vec<String>,
+    /// `[ignore]` wm_name patterns
+    pub ignore_wm_name: Vec<String>,
+    /// `suspend_when` condition, named conditions expanded
+    pub suspend_when: Option<String>,
     /// Diagnostics key (optional)
     pub diagnostics_key: Option<Key>,
     /// Emergency eject key (optional)
     pub emergency_eject_key: Option<Key>,
+    /// Panic chord keys (empty = disabled)
+    pub panic_chord: Vec<Key>,
+    /// Panic chord hold time in milliseconds
+    pub panic_hold_ms: Option<u64>,
     /// Device name/path filter (empty = autodetect keyboards)
     pub device_filter: Vec<String>,
+    /// When to ignore the internal keyboard
+    pub internal_keyboard_policy: InternalKeyboardPolicy,
+    /// Names/paths identifying the internal keyboard (empty = autodetect)
+    pub internal_keyboards: Vec<String>,
+    /// Also grab gamepads/joysticks when autodetecting devices
+    pub include_gamepads: bool,
+    /// Keep the lock LEDs of all grabbed keyboards in step
+    pub mirror_lock_leds: bool,
+    /// Longest wait for held keys before grabbing, in milliseconds (None = grab at once)
+    pub release_wait_ms: Option<u64>,
+    /// How each device is grabbed
+    pub grab_policies: GrabPolicies,
     /// Pre-key output delay in milliseconds
     pub key_pre_delay_ms: Option<u64>,
     /// Post-key output delay in milliseconds
     pub key_post_delay_ms: Option<u64>,
+    /// Pause between combo events in milliseconds
+    pub combo_delay_ms: Option<u64>,
+    /// Delays replacing the ones above while a condition holds
+    pub delay_overrides: Vec<DelayOverride>,
     // Event poll timeout in milliseconds
     pub poll_timeout_ms: Option<u64>,
     // Window context refresh interval in milliseconds
     pub window_update_interval_ms: Option<u64>,
+    /// Whether window polling is capped for title changes
+    pub window_title_sensitive: bool,
     // Idle loop sleep in milliseconds
     pub idle_sleep_ms: Option<u64>,
+    /// Whether control clients may subscribe to the output mirror
+    pub observer_enabled: bool,
+    /// Observer redaction level
+    pub observer_privacy: ObserverPrivacy,
+    /// How Unicode characters are emitted
+    pub unicode_backend: UnicodeBackend,
+    /// How `Text(...)` with many non-ASCII characters is emitted
+    pub unicode_batch: UnicodeBatch,
+    /// Non-ASCII characters in one `Text(...)` that select `unicode_batch`
+    pub unicode_batch_min: usize,
+    /// Combo sent to paste batched text
+    pub paste_combo: Combo,
+    /// Whether repeats of remapped keys are forwarded
+    pub remap_repeat: RemapRepeat,
+    /// Stuck output key timeout in milliseconds (None = watchdog off)
+    pub stuck_key_timeout: Option<u64>,
+    /// Whether XCompose sequences are loaded
+    pub compose_enabled: bool,
+    /// Explicit XCompose file (None = default lookup)
+    pub compose_file: Option<PathBuf>,
+    /// Keyboard layout (None = US QWERTY)
+    pub layout: Option<KeyboardLayout>,
+    /// Whether Right Alt is AltGr, so generic `Alt-` combos don't match it
+    pub altgr: bool,
+    /// Resolved `[aliases]` (upper-case alias name -> key)
+    pub key_aliases: HashMap<String, Key>,
+    /// Whether metrics are collected
+    pub metrics_enabled: bool,
+    /// Metrics HTTP endpoint address (None = no endpoint)
+    pub metrics_listen: Option<SocketAddr>,
+    /// Audit log entries kept (None = no audit log)
+    pub audit_capacity: Option<usize>,
+    /// Log levels and format from `[logging]`
+    pub logging: LogSettings,
+    /// Stop remapping while the session is locked
+    pub pause_on_lock: bool,
+    /// Session lock query interval in milliseconds
+    pub lock_poll_ms: Option<u64>,
+    /// Release devices while the session is not the active one of its seat
+    pub pause_when_inactive: bool,
+    /// Seat whose devices are used
+    pub seat: SeatFilter,
+    /// Settings flags entries are gated on (`toggle_setting`) with their
+    /// defaults, sorted by name
+    pub toggle_settings: Vec<(String, bool)>,
+    /// Name of this config among the profiles
+    pub profile: String,
+    /// `[profiles.files]`: other profiles and their config files, by name
+    pub profiles: Vec<(String, PathBuf)>,
 }
 
 impl Default for Config {
@@ -250,18 +874,69 @@ impl Default for Config {
         Self {
             modmaps: vec![],
             multipurpose: vec![],
+            tapdance: vec![],
+            modtap: vec![],
+            scancodes: vec![],
             keymaps: vec![],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: None,
             suspend_timeout: None,
+            nested_keymap_timeout: None,
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            dead_key_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            chromebook_top_row: vec![],
+            mediamap: vec![],
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
             diagnostics_key: None,
             emergency_eject_key: None,
+            panic_chord: DEFAULT_PANIC_CHORD.iter().map(|code| Key::from(*code)).collect(),
+            panic_hold_ms: None,
             device_filter: vec![],
+            internal_keyboard_policy: InternalKeyboardPolicy::default(),
+            internal_keyboards: vec![],
+            include_gamepads: false,
+            mirror_lock_leds: false,
+            release_wait_ms: Some(DEFAULT_RELEASE_WAIT_MS),
+            grab_policies: GrabPolicies::default(),
             key_pre_delay_ms: None,
             key_post_delay_ms: None,
+            combo_delay_ms: None,
+            delay_overrides: vec![],
             poll_timeout_ms: None,
             window_update_interval_ms: None,
+            window_title_sensitive: false,
             idle_sleep_ms: None,
+            observer_enabled: false,
+            observer_privacy: ObserverPrivacy::default(),
+            unicode_backend: UnicodeBackend::default(),
+            unicode_batch: UnicodeBatch::default(),
+            unicode_batch_min: DEFAULT_UNICODE_BATCH_MIN,
+            paste_combo: default_paste_combo(),
+            remap_repeat: RemapRepeat::default(),
+            stuck_key_timeout: None,
+            compose_enabled: false,
+            compose_file: None,
+            layout: None,
+            altgr: false,
+            key_aliases: HashMap::new(),
+            metrics_enabled: false,
+            metrics_listen: None,
+            audit_capacity: None,
+            logging: LogSettings::default(),
+            pause_on_lock: true,
+            lock_poll_ms: None,
+            pause_when_inactive: true,
+            seat: SeatFilter::default(),
+            toggle_settings: vec![],
+            profile: DEFAULT_PROFILE.to_string(),
+            profiles: vec![],
         }
     }
 }
@@ -281,26 +956,92 @@ pub struct MultipurposeEntry {
     pub condition: Option<String>,
 }
 
+/// Tap-dance entry for internal use
+#[derive(Debug, Clone)]
+pub struct TapDanceEntry {
+    /// Name identifier
+    pub name: String,
+    /// Trigger key
+    pub trigger: Key,
+    /// Output keys by tap count
+    pub taps: Vec<Key>,
+    /// Optional hold output key
+    pub hold: Option<Key>,
+    /// Optional condition
+    pub condition: Option<String>,
+}
+
 impl Config {
     /// Parse a TOML configuration file
+    ///
+    /// Files listed in `include` are resolved relative to `path` and merged
+    /// with the same precedence rules as `--compose-config`.
     #[cfg(feature = "pure-rust")]
     pub fn from_toml_path<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)?;
-        Self::from_toml(&content)
+        let content = super::migrate::upgrade_for_load(&content, &path.display().to_string())?;
+        let toml_config: ConfigToml =
+            toml::from_str(&content).map_err(|e| ConfigError::TomlParse(e.to_string()))?;
+        if toml_config.include.is_empty() {
+            return ConfigToml::with_vars_expanded(toml_config, &content)?.to_config();
+        }
+
+        let mut merged = super::compose::load_with_includes(path)?;
+        expand_config_vars(&mut merged)?;
+        let toml_config: ConfigToml = toml::Value::Table(merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| {
+                ConfigError::TomlParse(format!("{} (after includes): {}", path.display(), e))
+            })?;
+        toml_config.to_config()
     }
 
     /// Parse configuration from TOML string
+    ///
+    /// `include` entries cannot be resolved without a base path and are ignored;
+    /// use `from_toml_path` for modular configs.
     pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
         // Parse TOML
+        let content = &*super::migrate::upgrade_for_load(content, "config")?;
         let toml_config: ConfigToml =
             toml::from_str(content).map_err(|e| ConfigError::TomlParse(e.to_string()))?;
+        if !toml_config.include.is_empty() {
+            log::warn!("Ignoring include directive: includes are only resolved when loading from a file");
+        }
 
         // Convert to internal Config
-        toml_config.to_config()
+        ConfigToml::with_vars_expanded(toml_config, content)?.to_config()
+    }
+
+    /// Interval between window context polls when the window provider can't
+    /// report changes; `title_sensitive` caps it so title changes apply quickly
+    pub fn window_poll_interval_ms(&self) -> u64 {
+        let interval = self.window_update_interval_ms.unwrap_or(DEFAULT_WINDOW_UPDATE_INTERVAL_MS);
+        if self.window_title_sensitive {
+            interval.min(TITLE_SENSITIVE_UPDATE_INTERVAL_MS)
+        } else {
+            interval
+        }
+    }
+
+    /// Combos that an earlier keymap maps differently wherever both keymaps apply
+    pub fn keymap_conflicts(&self) -> Vec<super::KeymapConflict> {
+        super::conflicts::find_keymap_conflicts(&self.keymaps, &self.key_names())
+    }
+
+    /// Key names of this config: its aliases and layout
+    pub fn key_names(&self) -> KeyNames {
+        KeyNames::new(self.layout.clone()).with_aliases(self.key_aliases.clone())
     }
 
     /// Convert to TransformConfig for use in TransformEngine
     pub fn to_transform_config(&self) -> TransformConfig {
+        let names = self.key_names();
+        self.build_transform_config(&names)
+    }
+
+    fn build_transform_config(&self, names: &KeyNames) -> TransformConfig {
         use std::collections::HashMap;
 
         log::debug!("to_transform_config called with {} keymaps", self.keymaps.len());
@@ -334,63 +1075,218 @@ impl Config {
                     }
                 })
                 .collect(),
-            keymaps: self
-                .keymaps
+            tapdances: self
+                .tapdance
                 .iter()
                 .map(|entry| {
-                    let mut mappings = HashMap::new();
-                    for (combo_str, output) in &entry.mappings {
-                        // Parse combo string
-                        match super::parse_combo_string(combo_str) {
-                            Ok(parsed) => {
-                                let combo = Combo::new(parsed.modifiers, parsed.key);
-                                let value: KeymapValue = output.clone().into();
-                                mappings.insert(combo, value);
-                            }
-                            Err(e) => {
-                                log::warn!(
-                                    "Failed to parse input combo '{}' in keymap '{}': {}",
-                                    combo_str, entry.name, e
-                                );
-                            }
-                        }
-                    }
-
-                    log::debug!(
-                        "Keymap '{}' converted with {} mappings",
-                        entry.name,
-                        mappings.len()
-                    );
-
                     if let Some(condition) = &entry.condition {
-                        Keymap::with_conditional(&entry.name, mappings, condition.clone())
+                        TapDance::with_conditional(
+                            &entry.name,
+                            entry.trigger,
+                            entry.taps.clone(),
+                            entry.hold,
+                            condition.clone(),
+                        )
                     } else {
-                        Keymap::with_mappings(&entry.name, mappings)
+                        TapDance::new(&entry.name, entry.trigger, entry.taps.clone(), entry.hold)
                     }
                 })
                 .collect(),
+            modtaps: self.modtap.clone(),
+            keymaps: self.keymaps.iter().map(|keymap| keymap.to_keymap(names)).collect(),
+            layers: self.layers.iter().map(|layer| layer.to_keymap(names)).collect(),
             suspend_key: self.suspend_key,
             multipurpose_timeout: self.multipurpose_timeout,
             suspend_timeout: self.suspend_timeout,
+            nested_keymap_timeout: self.nested_keymap_timeout,
+            modtap_timeout: self.modtap_timeout,
+            layer_rollover_timeout: self.layer_rollover_timeout,
+            autoshift_keys: self.autoshift_keys.clone(),
+            autoshift_timeout: self.autoshift_timeout,
+            dead_key_timeout: self.dead_key_timeout,
+            ignore_wm_class: self.ignore_wm_class.clone(),
+            ignore_wm_name: self.ignore_wm_name.clone(),
+            suspend_when: self.suspend_when.clone().map(crate::Condition::new),
+            altgr: self.altgr,
+            output_delays: OutputDelays {
+                key_pre_delay_ms: self.key_pre_delay_ms.unwrap_or(0),
+                key_post_delay_ms: self.key_post_delay_ms.unwrap_or(0),
+                combo_delay_ms: self.combo_delay_ms.unwrap_or(0),
+            },
+            delay_overrides: self.delay_overrides.clone(),
+            setting_defaults: self.toggle_settings.clone(),
+            mediamap: self.mediamap.iter().cloned().collect(),
+            media_passthrough: self.media_passthrough,
+            profile: self.profile.clone(),
+        }
+    }
+}
+
+/// Allowed millisecond ranges for timing settings: (section, field, label, min, max)
+pub(crate) const TIMING_LIMITS: &[(&str, &str, &str, u64, u64)] = &[
+    ("general", "panic_hold_ms", "general.panic_hold_ms", 500, 10000),
+    ("devices", "release_timeout_ms", "devices.release_timeout_ms", 100, 10000),
+    ("timeouts", "multipurpose", "multipurpose", 100, 5000),
+    ("timeouts", "suspend", "suspend", 100, 10000),
+    ("timeouts", "nested_keymap", "nested_keymap", 100, 60000),
+    ("timeouts", "modtap", "modtap", 100, 5000),
+    ("timeouts", "layer_rollover", "layer_rollover", 20, 1000),
+    ("timeouts", "stuck_key", "stuck_key", 1000, 3600000),
+    ("timeouts", "dead_key", "dead_key", 500, 60000),
+    ("autoshift", "timeout", "autoshift.timeout", 100, 1000),
+    ("delays", "key_pre_delay_ms", "key_pre_delay_ms", 0, 150),
+    ("delays", "key_post_delay_ms", "key_post_delay_ms", 0, 150),
+    ("delays", "combo_delay_ms", "combo_delay_ms", 0, 50),
+    ("window", "poll_timeout_ms", "window.poll_timeout_ms", 1, 5000),
+    ("window", "update_interval_ms", "window.update_interval_ms", 10, 10000),
+    ("window", "idle_sleep_ms", "window.idle_sleep_ms", 0, 1000),
+    ("session", "lock_poll_ms", "session.lock_poll_ms", 100, 10000),
+];
+
+/// Timing settings where 0 turns the feature off instead of being out of range
+const TIMING_ZERO_DISABLES: &[(&str, &str)] = &[("timeouts", "stuck_key")];
+
+/// Check a timing setting against `TIMING_LIMITS`
+pub(crate) fn check_timing(section: &str, field: &str, value: u64) -> Result<u64, ConfigError> {
+    if value == 0 && TIMING_ZERO_DISABLES.contains(&(section, field)) {
+        return Ok(0);
+    }
+    match TIMING_LIMITS
+        .iter()
+        .find(|(s, f, ..)| *s == section && *f == field)
+    {
+        Some((_, _, label, min, max)) if value < *min || value > *max => Err(
+            ConfigError::TimeoutOutOfRange(format!("{} must be {}-{}ms, got {}", label, min, max, value)),
+        ),
+        _ => Ok(value),
+    }
+}
+
+/// Resolve every `[aliases]` entry to a key (keyed by upper-case alias name)
+pub(crate) fn resolve_aliases(aliases: &HashMap<String, String>, names: &KeyNames) -> Result<HashMap<String, Key>, ConfigError> {
+    aliases
+        .keys()
+        .map(|name| Ok((name.to_uppercase(), resolve_alias(name, aliases, names)?)))
+        .collect()
+}
+
+/// Resolve one alias, following aliases of aliases.
+///
+/// Alias names may not contain `-` (the combo separator) or shadow a key
+/// name; a chain that leads back to itself is an error. An alias may reuse a
+/// modifier name (`Cmd = "LEFT_META"`) and then wins over it in combos.
+pub(crate) fn resolve_alias(name: &str, aliases: &HashMap<String, String>, names: &KeyNames) -> Result<Key, ConfigError> {
+    if name.is_empty() || name.contains(|c: char| c == '-' || c.is_whitespace()) {
+        return Err(ConfigError::InvalidValue(format!(
+            "aliases: '{}' is not a valid alias name",
+            name
+        )));
+    }
+    if names.key(name).is_some() {
+        return Err(ConfigError::InvalidValue(format!(
+            "aliases: '{}' is already a key name",
+            name
+        )));
+    }
+
+    let lookup = |alias: &str| {
+        aliases
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(alias))
+            .map(|(_, target)| target.trim())
+    };
+    let mut chain = vec![name];
+    let mut target = lookup(name).unwrap_or_default();
+    while let Some(next) = lookup(target) {
+        let cycle = chain.iter().any(|n| n.eq_ignore_ascii_case(target));
+        chain.push(target);
+        if cycle {
+            return Err(ConfigError::InvalidValue(format!(
+                "aliases: cycle {}",
+                chain.join(" -> ")
+            )));
         }
+        target = next;
     }
+    parse_key(target, names).map_err(|e| match e {
+        ConfigError::InvalidKey(message) => {
+            ConfigError::InvalidKey(format!("{} (alias '{}')", message, name))
+        }
+        other => other,
+    })
+}
+
+/// Load the `[layout]` section's XKB layout
+fn load_layout(layout: &LayoutConfig) -> Result<KeyboardLayout, ConfigError> {
+    let result = if layout.name.eq_ignore_ascii_case("auto") {
+        KeyboardLayout::detect()
+    } else {
+        KeyboardLayout::load(&layout.name, layout.variant.as_deref())
+    };
+    result.map_err(|e| ConfigError::InvalidValue(format!("layout: {}", e)))
 }
 
 impl ConfigToml {
+    /// Re-read `content` with its `[vars]` substituted, if it has any
+    fn with_vars_expanded(parsed: Self, content: &str) -> Result<Self, ConfigError> {
+        if parsed.vars.is_empty() {
+            return Ok(parsed);
+        }
+        let mut root: toml::map::Map<String, toml::Value> =
+            toml::from_str(content).map_err(|e| ConfigError::TomlParse(e.to_string()))?;
+        expand_config_vars(&mut root)?;
+        toml::Value::Table(root)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::TomlParse(e.to_string()))
+    }
+
     /// Convert parsed TOML to internal Config structure
     fn to_config(&self) -> Result<Config, ConfigError> {
+        let layout = self.layout.as_ref().map(load_layout).transpose()?;
+        let names = KeyNames::new(layout.clone());
+        let aliases = resolve_aliases(&self.aliases, &names)?;
+        let mut config = self.build_config(&names.with_aliases(aliases.clone()))?;
+        config.key_aliases = aliases;
+        config.altgr = self
+            .layout
+            .as_ref()
+            .and_then(|layout| layout.altgr)
+            .unwrap_or_else(|| layout.as_ref().is_some_and(KeyboardLayout::has_altgr));
+        config.layout = layout;
+        // Overlapping conditions are often deliberate (specific keymaps before
+        // broader ones); only mappings that can never apply are warned about
+        for conflict in config.keymap_conflicts() {
+            if conflict.unconditional {
+                log::warn!("Keymap conflict: {}", conflict);
+            } else {
+                log::debug!("Keymap conflict: {}", conflict);
+            }
+        }
+        Ok(config)
+    }
+
+    fn build_config(&self, names: &KeyNames) -> Result<Config, ConfigError> {
         let mut config = Config::default();
+        let named_conditions = resolve_named_conditions(&self.conditions)
+            .map_err(|e| ConfigError::InvalidValue(format!("conditions: {}", e)))?;
+        let condition = |source: &str| expand_named_conditions(source, &named_conditions).into_owned();
 
         // Parse suspend key
         if let Some(general) = &self.general {
             if let Some(key_str) = &general.suspend_key {
-                config.suspend_key = Some(parse_key(key_str)?);
+                config.suspend_key = Some(parse_key(key_str, names)?);
             }
             if let Some(key_str) = &general.diagnostics_key {
-                config.diagnostics_key = Some(parse_key(key_str)?);
+                config.diagnostics_key = Some(parse_key(key_str, names)?);
             }
             if let Some(key_str) = &general.emergency_eject_key {
-                config.emergency_eject_key = Some(parse_key(key_str)?);
+                config.emergency_eject_key = Some(parse_key(key_str, names)?);
+            }
+            if let Some(chord) = &general.panic_chord {
+                config.panic_chord = chord.iter().map(|k| parse_key(k, names)).collect::<Result<_, _>>()?;
+            }
+            if let Some(hold) = general.panic_hold_ms {
+                config.panic_hold_ms = Some(check_timing("general", "panic_hold_ms", hold)?);
             }
         }
 
@@ -398,8 +1294,8 @@ impl ConfigToml {
         if let Some(default_mappings) = &self.modmap.default {
             let mut mappings = HashMap::new();
             for (from_str, to_str) in default_mappings {
-                let from_key = parse_key(from_str)?;
-                let to_key = parse_key(to_str)?;
+                let from_key = parse_key(from_str, names)?;
+                let to_key = parse_modmap_target(to_str, names)?;
                 mappings.insert(from_key, to_key);
             }
             config.modmaps.push(ModmapEntry {
@@ -413,38 +1309,78 @@ impl ConfigToml {
         for conditional in &self.modmap.conditionals {
             let mut mappings = HashMap::new();
             for (from_str, to_str) in &conditional.mappings {
-                let from_key = parse_key(from_str)?;
-                let to_key = parse_key(to_str)?;
+                let from_key = parse_key(from_str, names)?;
+                let to_key = parse_modmap_target(to_str, names)?;
                 mappings.insert(from_key, to_key);
             }
             config.modmaps.push(ModmapEntry {
                 name: conditional.name.clone(),
                 mappings: mappings.into_iter().collect(),
-                condition: Some(conditional.condition.clone()),
+                condition: Some(condition(&conditional.condition)),
             });
         }
 
         // Parse multipurpose modmaps
+        let mut toggles = BTreeMap::new();
         for mp_entry in &self.multipurpose {
-            let trigger = parse_key(&mp_entry.trigger)?;
-            let tap = parse_key(&mp_entry.tap)?;
-            let hold = parse_key(&mp_entry.hold)?;
+            let trigger = parse_key(&mp_entry.trigger, names)?;
+            let tap = parse_key(&mp_entry.tap, names)?;
+            let hold = parse_key(&mp_entry.hold, names)?;
+            let toggle = mp_entry.toggle_setting.as_deref();
+            register_toggle(&mut toggles, &mp_entry.name, toggle, mp_entry.toggle_default)?;
             config.multipurpose.push(MultipurposeEntry {
                 name: mp_entry.name.clone(),
                 trigger,
                 tap,
                 hold,
-                condition: mp_entry.condition.clone(),
+                condition: gate_on_setting(mp_entry.condition.as_deref().map(condition), toggle),
             });
         }
 
-        // Parse keymaps
-        for keymap_entry in &self.keymap {
-            let mut mappings = HashMap::new();
-            let keymap_name = keymap_entry.name.clone().unwrap_or_else(|| {
-                format!(
-                    "keymap_{}",
-                    keymap_entry
+        // Parse tap-dance entries
+        for td_entry in &self.tapdance {
+            if td_entry.taps.is_empty() {
+                return Err(ConfigError::InvalidKey(format!(
+                    "tapdance '{}' must define at least one tap output",
+                    td_entry.name
+                )));
+            }
+            let trigger = parse_key(&td_entry.trigger, names)?;
+            let taps = td_entry
+                .taps
+                .iter()
+                .map(|tap| parse_key(tap, names))
+                .collect::<Result<Vec<_>, _>>()?;
+            let hold = td_entry.hold.as_deref().map(|hold| parse_key(hold, names)).transpose()?;
+            config.tapdance.push(TapDanceEntry {
+                name: td_entry.name.clone(),
+                trigger,
+                taps,
+                hold,
+                condition: td_entry.condition.as_deref().map(condition),
+            });
+        }
+
+        // Parse modifier taps
+        for (trigger_str, output_str) in &self.modtap {
+            let trigger = parse_modtap_trigger(trigger_str, names)?;
+            let output = parse_modtap_output(output_str, names)?;
+            config.modtap.push((trigger, output));
+        }
+        config.modtap.sort_by_key(|(trigger, _)| trigger.code());
+
+        // Parse scancode remaps
+        for (scancode_str, key_str) in &self.scancodes {
+            config.scancodes.push((parse_scancode_entry(scancode_str)?, parse_key(key_str, names)?));
+        }
+        config.scancodes.sort_by_key(|(scancode, _)| *scancode);
+
+        // Parse keymaps
+        for keymap_entry in self.keymap.iter().filter(|k| k.enabled) {
+            let keymap_name = keymap_entry.name.clone().unwrap_or_else(|| {
+                format!(
+                    "keymap_{}",
+                    keymap_entry
                         .mappings
                         .keys()
                         .next()
@@ -452,84 +1388,7 @@ impl ConfigToml {
                 )
             });
 
-            for (combo_str, output) in &keymap_entry.mappings {
-                match output {
-                    KeymapTomlOutput::Single(s) => {
-                        if let Some(text) = parse_text_output(s) {
-                            mappings.insert(combo_str.clone(), KeymapOutput::Text(text));
-                            continue;
-                        }
-                        if let Some(codepoint) = parse_unicode_output(s) {
-                            mappings.insert(combo_str.clone(), KeymapOutput::Unicode(codepoint));
-                            continue;
-                        }
-
-                        // Try parsing as a combo first (e.g., "Ctrl-c" or "Ctrl-Shift-c")
-                        match super::parse_combo_string(s) {
-                            Ok(parsed) => {
-                                // Output is a combo - convert to sequence of keys
-                                let mut keys = Vec::new();
-
-                                // Add modifier keys (use first key from each modifier)
-                                for modifier in &parsed.modifiers {
-                                    if let Some(&first_key) = modifier.keys().first() {
-                                        keys.push(first_key);
-                                    }
-                                }
-
-                                // Add the final key
-                                keys.push(parsed.key);
-
-                                mappings.insert(combo_str.clone(), KeymapOutput::Combo(keys));
-                            }
-                            Err(e) => {
-                                // Try parsing as a single key instead
-                                match parse_key(s) {
-                                    Ok(key) => {
-                                        mappings.insert(combo_str.clone(), KeymapOutput::Key(key));
-                                    }
-                                    Err(_) => {
-                                        log::warn!(
-                                            "Failed to parse keymap output '{}' in keymap '{}': {}",
-                                            s, keymap_name, e
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    KeymapTomlOutput::Multiple(list) => {
-                        let keys: Vec<Key> = list.iter().filter_map(|s| parse_key(s).ok()).collect();
-                        if keys.len() == list.len() {
-                            mappings.insert(combo_str.clone(), KeymapOutput::Combo(keys));
-                            continue;
-                        }
-
-                        let mut steps = Vec::with_capacity(list.len());
-                        let mut invalid = false;
-                        for item in list {
-                            if let Some(step) = parse_sequence_step(item) {
-                                steps.push(step);
-                            } else {
-                                invalid = true;
-                                log::warn!(
-                                    "Invalid sequence step '{}' in keymap '{}' mapping '{}'",
-                                    item, keymap_name, combo_str
-                                );
-                            }
-                        }
-
-                        if !invalid && !steps.is_empty() {
-                            mappings.insert(combo_str.clone(), KeymapOutput::Sequence(steps));
-                        } else {
-                            log::warn!(
-                                "Invalid sequence in keymap '{}' mapping '{}'",
-                                keymap_name, combo_str
-                            );
-                        }
-                    }
-                }
-            }
+            let mappings = parse_keymap_mappings(&keymap_name, &keymap_entry.mappings, names);
 
             log::debug!(
                 "Loaded keymap '{}' with {} mappings, conditional={}",
@@ -538,8 +1397,7 @@ impl ConfigToml {
                 keymap_entry.condition.is_some()
             );
 
-            #[cfg(feature = "pure-rust")]
-            if config_debug_enabled() {
+            if log::log_enabled!(log::Level::Trace) {
                 for (combo, output) in &mappings {
                     log::trace!(
                         "keymap='{}' combo='{}' output={:?}",
@@ -548,91 +1406,414 @@ impl ConfigToml {
                 }
             }
 
+            let repeats = parse_keymap_repeats(&keymap_name, &keymap_entry.mappings)?;
+            let holds = parse_keymap_holds(&keymap_name, &keymap_entry.mappings, &mappings, names);
+            let combo_style = parse_combo_style(&keymap_name, keymap_entry)?;
+            let toggle = keymap_entry.toggle_setting.as_deref();
+            register_toggle(&mut toggles, &keymap_name, toggle, keymap_entry.toggle_default)?;
             config.keymaps.push(KeymapEntry {
                 name: keymap_name,
                 mappings: mappings.into_iter().collect(),
-                condition: keymap_entry.condition.clone(),
+                condition: gate_on_setting(keymap_entry.condition.as_deref().map(condition), toggle),
+                repeats,
+                descriptions: parse_keymap_descriptions(&keymap_entry.mappings),
+                holds,
+                nested_timeout: keymap_entry
+                    .nested_timeout
+                    .map(|ms| check_timing("timeouts", "nested_keymap", ms))
+                    .transpose()?,
+                combo_style,
+            });
+        }
+
+        // Built-in Option-key characters, below the user's own keymaps
+        if let Some(optspec) = self.optspec.as_ref().filter(|o| o.enabled) {
+            config.keymaps.extend(optspec_keymaps(optspec.condition.as_deref().map(condition).as_deref())?);
+            register_toggle(&mut toggles, "optspec", Some(OPTSPEC_SETTING), Some(true))?;
+        }
+        config.toggle_settings = toggles
+            .into_iter()
+            .map(|(name, default)| (name, default.unwrap_or(false)))
+            .collect();
+
+        if let Some(mediamap) = &self.mediamap {
+            config.media_passthrough = mediamap.passthrough;
+            for (name, target) in &mediamap.keys {
+                let key = parse_key(name, names)?;
+                if !is_media_key(key) {
+                    return Err(ConfigError::InvalidValue(format!(
+                        "mediamap: '{}' is not a media key",
+                        name
+                    )));
+                }
+                let mapping = match parse_run_step(target) {
+                    Some(ActionStep::Run(command)) => MediaMapping::Run(command),
+                    _ => MediaMapping::Key(parse_key(target, names)?),
+                };
+                config.mediamap.push((key, mapping));
+            }
+        }
+
+        // Built-in Chromebook top row, below the user's own modmaps and keymaps
+        if let Some(chromebook) = self.chromebook.as_ref().filter(|c| c.enabled) {
+            config.chromebook_top_row = match &chromebook.top_row {
+                Some(top_row) => top_row.iter().map(|name| parse_key(name, names)).collect::<Result<_, _>>()?,
+                None => DEFAULT_TOP_ROW.iter().map(|code| Key::from(*code)).collect(),
+            };
+            if config.chromebook_top_row.is_empty() || config.chromebook_top_row.len() > MAX_TOP_ROW {
+                return Err(ConfigError::InvalidValue(format!(
+                    "chromebook.top_row must list 1-{} keys, got {}",
+                    MAX_TOP_ROW,
+                    config.chromebook_top_row.len()
+                )));
+            }
+            let (modmap, keymaps) = top_row_entries(&config.chromebook_top_row);
+            if config.modmaps.is_empty() {
+                // The first modmap is the unconditional default
+                config.modmaps.push(ModmapEntry {
+                    name: "default".to_string(),
+                    mappings: Vec::new(),
+                    condition: None,
+                });
+            }
+            config.modmaps.push(modmap);
+            config.keymaps.extend(keymaps);
+        }
+
+        // Parse layers (sorted so the resulting config is deterministic)
+        let mut layer_names: Vec<&String> = self.layers.keys().collect();
+        layer_names.sort();
+        for name in layer_names {
+            let layer = &self.layers[name];
+            let mappings = parse_keymap_mappings(name, &layer.mappings, names);
+            let holds = parse_keymap_holds(name, &layer.mappings, &mappings, names);
+            config.layers.push(KeymapEntry {
+                name: name.clone(),
+                mappings: mappings.into_iter().collect(),
+                condition: layer.condition.as_deref().map(condition),
+                repeats: parse_keymap_repeats(name, &layer.mappings)?,
+                descriptions: parse_keymap_descriptions(&layer.mappings),
+                holds,
+                nested_timeout: None,
+                combo_style: None,
             });
         }
+        for entry in config.keymaps.iter().chain(config.layers.iter()) {
+            for (combo_str, output) in &entry.mappings {
+                if let KeymapOutput::Layer(layer) = output {
+                    if !self.layers.contains_key(&layer.name) {
+                        return Err(ConfigError::InvalidValue(format!(
+                            "mapping '{}' in '{}' activates undefined layer '{}'",
+                            combo_str, entry.name, layer.name
+                        )));
+                    }
+                }
+                if let KeymapOutput::EnterKeymap(name) = output {
+                    if !config.keymaps.iter().any(|keymap| keymap.name == *name) {
+                        return Err(ConfigError::InvalidValue(format!(
+                            "mapping '{}' in '{}' enters undefined keymap '{}'",
+                            combo_str, entry.name, name
+                        )));
+                    }
+                }
+            }
+        }
 
         // Parse timeouts
         if let Some(timeouts) = &self.timeouts {
             if let Some(mp) = timeouts.multipurpose {
-                if mp < 100 || mp > 5000 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "multipurpose must be 100-5000ms, got {}",
-                        mp
-                    )));
-                }
-                config.multipurpose_timeout = Some(mp);
+                config.multipurpose_timeout = Some(check_timing("timeouts", "multipurpose", mp)?);
             }
             if let Some(st) = timeouts.suspend {
-                if st < 100 || st > 10000 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "suspend must be 100-10000ms, got {}",
-                        st
-                    )));
-                }
-                config.suspend_timeout = Some(st);
+                config.suspend_timeout = Some(check_timing("timeouts", "suspend", st)?);
+            }
+            if let Some(nk) = timeouts.nested_keymap {
+                config.nested_keymap_timeout = Some(check_timing("timeouts", "nested_keymap", nk)?);
+            }
+            if let Some(mt) = timeouts.modtap {
+                config.modtap_timeout = Some(check_timing("timeouts", "modtap", mt)?);
+            }
+            if let Some(lr) = timeouts.layer_rollover {
+                config.layer_rollover_timeout =
+                    Some(check_timing("timeouts", "layer_rollover", lr)?);
+            }
+            if let Some(sk) = timeouts.stuck_key {
+                config.stuck_key_timeout =
+                    Some(check_timing("timeouts", "stuck_key", sk)?).filter(|&sk| sk > 0);
+            }
+            if let Some(dk) = timeouts.dead_key {
+                config.dead_key_timeout = Some(check_timing("timeouts", "dead_key", dk)?);
             }
         }
 
         // Parse devices
         if let Some(devices) = &self.devices {
             config.device_filter = devices.only.clone();
+            config.internal_keyboards = devices.internal.clone();
+            config.include_gamepads = devices.include_gamepads.unwrap_or(false);
+            config.mirror_lock_leds = devices.mirror_lock_leds.unwrap_or(false);
+            let release_timeout = devices
+                .release_timeout_ms
+                .map(|ms| check_timing("devices", "release_timeout_ms", ms))
+                .transpose()?
+                .unwrap_or(DEFAULT_RELEASE_WAIT_MS);
+            config.release_wait_ms = devices.wait_for_release.unwrap_or(true).then_some(release_timeout);
+            if let Some(policy) = &devices.internal_policy {
+                config.internal_keyboard_policy = InternalKeyboardPolicy::from_name(policy)
+                    .ok_or_else(|| {
+                        ConfigError::InvalidValue(format!(
+                            "devices.internal_policy must be one of always, lid_closed, external_present, lid_closed_or_external, got '{}'",
+                            policy
+                        ))
+                    })?;
+            }
+            let grab_policy = |field: &str, policy: &str| {
+                GrabPolicy::from_name(policy).ok_or_else(|| {
+                    ConfigError::InvalidValue(format!(
+                        "{} must be one of full, keys-only, observe, got '{}'",
+                        field, policy
+                    ))
+                })
+            };
+            if let Some(policy) = &devices.grab {
+                config.grab_policies.default = grab_policy("devices.grab", policy)?;
+            }
+            let mut grab_devices: Vec<_> = devices.grab_devices.iter().collect();
+            grab_devices.sort();
+            for (device, policy) in grab_devices {
+                let policy = grab_policy(&format!("devices.grab_devices.\"{}\"", device), policy)?;
+                config.grab_policies.devices.push((device.clone(), policy));
+            }
+        }
+
+        // Parse output mirror settings
+        if let Some(observer) = &self.observer {
+            if let Some(privacy) = &observer.privacy {
+                config.observer_privacy = ObserverPrivacy::from_name(privacy).ok_or_else(|| {
+                    ConfigError::InvalidValue(format!(
+                        "observer.privacy must be one of off, text, typing, got '{}'",
+                        privacy
+                    ))
+                })?;
+            }
+            config.observer_enabled = observer.enabled;
+        }
+
+        // Parse metrics settings
+        if let Some(metrics) = &self.metrics {
+            config.metrics_enabled = metrics.enabled;
+            if metrics.enabled {
+                let listen = metrics.listen.as_deref().unwrap_or(DEFAULT_METRICS_ADDR);
+                if !listen.is_empty() {
+                    config.metrics_listen = Some(listen.parse().map_err(|_| {
+                        ConfigError::InvalidValue(format!(
+                            "metrics.listen must be an address like '{}', got '{}'",
+                            DEFAULT_METRICS_ADDR, listen
+                        ))
+                    })?);
+                }
+            }
+        }
+
+        // Parse audit log settings
+        if let Some(audit) = self.audit.as_ref().filter(|audit| audit.enabled) {
+            let capacity = audit.capacity.unwrap_or(DEFAULT_AUDIT_CAPACITY);
+            if capacity == 0 {
+                return Err(ConfigError::InvalidValue("audit.capacity must be at least 1".to_string()));
+            }
+            config.audit_capacity = Some(capacity);
+        }
+
+        // Parse logging settings
+        if let Some(logging) = &self.logging {
+            let invalid = |e: String| ConfigError::InvalidValue(format!("logging: {}", e));
+            if let Some(level) = &logging.level {
+                config.logging.level = Some(logging::parse_level(level).map_err(invalid)?);
+            }
+            let mut modules: Vec<_> = logging.modules.iter().collect();
+            modules.sort();
+            for (name, level) in modules {
+                let level = logging::parse_level(level).map_err(invalid)?;
+                config
+                    .logging
+                    .modules
+                    .push((logging::subsystem_target(name).to_string(), level));
+            }
+            if let Some(format) = &logging.format {
+                config.logging.format = Some(LogFormat::from_name(format).ok_or_else(|| {
+                    ConfigError::InvalidValue(format!(
+                        "logging.format must be one of text, json, got '{}'",
+                        format
+                    ))
+                })?);
+            }
+        }
+
+        // Parse autoshift keys (rows, plus extra keys, minus exclusions)
+        if let Some(autoshift) = &self.autoshift {
+            let mut keys = Vec::new();
+            match &autoshift.rows {
+                Some(rows) => {
+                    for row in rows {
+                        keys.extend(autoshift_row(row)?.iter().map(|code| Key::from(*code)));
+                    }
+                }
+                None => keys.extend(
+                    AUTOSHIFT_ROWS
+                        .iter()
+                        .flat_map(|(_, codes)| codes.iter().map(|code| Key::from(*code))),
+                ),
+            }
+            for name in &autoshift.keys {
+                keys.push(parse_key(name, names)?);
+            }
+            let excluded = autoshift
+                .exclude
+                .iter()
+                .map(|name| parse_key(name, names))
+                .collect::<Result<Vec<_>, _>>()?;
+            keys.retain(|key| !excluded.contains(key));
+            keys.sort();
+            keys.dedup();
+            config.autoshift_keys = keys;
+            if let Some(timeout) = autoshift.timeout {
+                config.autoshift_timeout = Some(check_timing("autoshift", "timeout", timeout)?);
+            }
+        }
+
+        config.suspend_when = self.suspend_when.as_deref().map(condition);
+
+        // Parse ignored windows
+        if let Some(ignore) = &self.ignore {
+            for (field, patterns) in [("wm_class", &ignore.wm_class), ("wm_name", &ignore.wm_name)] {
+                if let Some(err) = patterns.iter().find_map(|p| ignore_pattern_error(field, p)) {
+                    return Err(ConfigError::InvalidValue(err));
+                }
+            }
+            config.ignore_wm_class = ignore.wm_class.clone();
+            config.ignore_wm_name = ignore.wm_name.clone();
+        }
+
+        // Parse session settings
+        if let Some(session) = &self.session {
+            if let Some(pause) = session.pause_on_lock {
+                config.pause_on_lock = pause;
+            }
+            if let Some(poll) = session.lock_poll_ms {
+                config.lock_poll_ms = Some(check_timing("session", "lock_poll_ms", poll)?);
+            }
+            if let Some(pause) = session.pause_when_inactive {
+                config.pause_when_inactive = pause;
+            }
+            if let Some(seat) = &session.seat {
+                config.seat = SeatFilter::from_name(seat);
+            }
+        }
+
+        // Parse profiles
+        if let Some(profiles) = &self.profiles {
+            if let Some(name) = &profiles.name {
+                config.profile = name.trim().to_string();
+            }
+            let mut names = std::iter::once(&config.profile).chain(profiles.files.keys());
+            if let Some(name) = names.find(|name| name.trim().is_empty()) {
+                return Err(ConfigError::InvalidValue(format!("profiles: invalid profile name '{}'", name)));
+            }
+            if profiles.files.contains_key(&config.profile) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "profiles.files: '{}' is this config's own profile name",
+                    config.profile
+                )));
+            }
+            config.profiles = profiles
+                .files
+                .iter()
+                .map(|(name, file)| (name.clone(), PathBuf::from(file)))
+                .collect();
+        }
+
+        // Parse compose table settings
+        if let Some(compose) = &self.compose {
+            config.compose_enabled = compose.enabled;
+            config.compose_file = compose.file.as_ref().map(PathBuf::from);
+        }
+
+        // Parse output backends
+        if let Some(unicode) = self.output.as_ref().and_then(|o| o.unicode.as_ref()) {
+            config.unicode_backend = UnicodeBackend::from_name(unicode).ok_or_else(|| {
+                ConfigError::InvalidValue(format!(
+                    "output.unicode must be one of compose, virtual_keyboard, got '{}'",
+                    unicode
+                ))
+            })?;
+        }
+        if let Some(batch) = self.output.as_ref().and_then(|o| o.unicode_batch.as_ref()) {
+            config.unicode_batch = UnicodeBatch::from_name(batch).ok_or_else(|| {
+                ConfigError::InvalidValue(format!(
+                    "output.unicode_batch must be one of off, clipboard, got '{}'",
+                    batch
+                ))
+            })?;
+        }
+        if let Some(min) = self.output.as_ref().and_then(|o| o.unicode_batch_min) {
+            if min == 0 {
+                return Err(ConfigError::InvalidValue(
+                    "output.unicode_batch_min must be at least 1".to_string(),
+                ));
+            }
+            config.unicode_batch_min = min;
+        }
+        if let Some(paste) = self.output.as_ref().and_then(|o| o.paste.as_ref()) {
+            config.paste_combo = parse_combo_step(paste, names).ok_or_else(|| {
+                ConfigError::InvalidValue(format!("output.paste: invalid combo '{}'", paste))
+            })?;
+        }
+        if let Some(remap_repeat) = self.output.as_ref().and_then(|o| o.remap_repeat.as_ref()) {
+            config.remap_repeat = RemapRepeat::from_name(remap_repeat).ok_or_else(|| {
+                ConfigError::InvalidValue(format!(
+                    "output.remap_repeat must be one of forward, drop, got '{}'",
+                    remap_repeat
+                ))
+            })?;
         }
 
         // Parse output delays
         if let Some(delays) = &self.delays {
             if let Some(pre) = delays.key_pre_delay_ms {
-                if pre > 150 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "key_pre_delay_ms must be 0-150ms, got {}",
-                        pre
-                    )));
-                }
-                config.key_pre_delay_ms = Some(pre);
+                config.key_pre_delay_ms = Some(check_timing("delays", "key_pre_delay_ms", pre)?);
             }
             if let Some(post) = delays.key_post_delay_ms {
-                if post > 150 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "key_post_delay_ms must be 0-150ms, got {}",
-                        post
-                    )));
-                }
-                config.key_post_delay_ms = Some(post);
+                config.key_post_delay_ms = Some(check_timing("delays", "key_post_delay_ms", post)?);
+            }
+            if let Some(combo) = delays.combo_delay_ms {
+                config.combo_delay_ms = Some(check_timing("delays", "combo_delay_ms", combo)?);
+            }
+            for entry in &delays.overrides {
+                let check = |field: &str, value: Option<u64>| {
+                    value.map(|value| check_timing("delays", field, value)).transpose()
+                };
+                config.delay_overrides.push(DelayOverride {
+                    condition: crate::Condition::new(condition(&entry.condition)),
+                    key_pre_delay_ms: check("key_pre_delay_ms", entry.key_pre_delay_ms)?,
+                    key_post_delay_ms: check("key_post_delay_ms", entry.key_post_delay_ms)?,
+                    combo_delay_ms: check("combo_delay_ms", entry.combo_delay_ms)?,
+                });
             }
         }
 
         // Parse window loop timing controls
         if let Some(window) = &self.window {
             if let Some(poll) = window.poll_timeout_ms {
-                if poll == 0 || poll > 5000 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "window.poll_timeout_ms must be 1-5000ms, got {}",
-                        poll
-                    )));
-                }
-                config.poll_timeout_ms = Some(poll);
+                config.poll_timeout_ms = Some(check_timing("window", "poll_timeout_ms", poll)?);
             }
             if let Some(update) = window.update_interval_ms {
-                if update < 10 || update > 10000 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "window.update_interval_ms must be 10-10000ms, got {}",
-                        update
-                    )));
-                }
-                config.window_update_interval_ms = Some(update);
+                config.window_update_interval_ms =
+                    Some(check_timing("window", "update_interval_ms", update)?);
             }
             if let Some(idle) = window.idle_sleep_ms {
-                if idle > 1000 {
-                    return Err(ConfigError::TimeoutOutOfRange(format!(
-                        "window.idle_sleep_ms must be 0-1000ms, got {}",
-                        idle
-                    )));
-                }
-                config.idle_sleep_ms = Some(idle);
+                config.idle_sleep_ms = Some(check_timing("window", "idle_sleep_ms", idle)?);
             }
+            config.window_title_sensitive = window.title_sensitive;
         }
 
         Ok(config)
@@ -659,6 +1840,73 @@ pub struct KeymapEntry {
     pub mappings: Vec<(String, KeymapOutput)>,
     /// Optional window condition
     pub condition: Option<String>,
+    /// Synthetic repeat per combo (combo_str -> rate)
+    pub repeats: Vec<(String, RepeatRate)>,
+    /// Description per combo (combo_str -> text), sorted by combo
+    pub descriptions: Vec<(String, String)>,
+    /// Hold output per tap/hold combo (combo_str -> output), sorted by combo
+    pub holds: Vec<(String, KeymapOutput)>,
+    /// Per-keymap nested keymap timeout (milliseconds)
+    pub nested_timeout: Option<u64>,
+    /// Per-keymap combo modifier order and delay
+    pub combo_style: Option<ComboStyle>,
+}
+
+impl KeymapEntry {
+    /// Build the runtime keymap, skipping combos that fail to parse
+    fn to_keymap(&self, names: &KeyNames) -> Keymap {
+        use std::collections::HashMap;
+
+        let mut mappings = HashMap::new();
+        let mut release_triggers = Vec::new();
+        for (combo_str, output) in &self.mappings {
+            // Parse combo string
+            match super::validate_combo_with(combo_str, names) {
+                Ok(parsed) => {
+                    let combo = Combo::new(parsed.modifiers, parsed.key);
+                    if parsed.trigger == ComboTrigger::Release {
+                        release_triggers.push(combo.clone());
+                    }
+                    let value: KeymapValue = output.clone().into();
+                    mappings.insert(combo, value);
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Failed to parse input combo '{}' in keymap '{}': {}",
+                        combo_str, self.name, e
+                    );
+                }
+            }
+        }
+
+        log::debug!(
+            "Keymap '{}' converted with {} mappings",
+            self.name,
+            mappings.len()
+        );
+
+        let mut keymap = if let Some(condition) = &self.condition {
+            Keymap::with_conditional(&self.name, mappings, condition.clone())
+        } else {
+            Keymap::with_mappings(&self.name, mappings)
+        };
+        for (combo_str, output) in &self.holds {
+            if let Ok(parsed) = super::validate_combo_with(combo_str, names) {
+                keymap.set_hold(Combo::new(parsed.modifiers, parsed.key), output.clone().into());
+            }
+        }
+        for (combo_str, rate) in &self.repeats {
+            if let Ok(parsed) = super::validate_combo_with(combo_str, names) {
+                keymap.set_repeat(Combo::new(parsed.modifiers, parsed.key), *rate);
+            }
+        }
+        for combo in release_triggers {
+            keymap.set_release_trigger(combo);
+        }
+        keymap.set_nested_timeout(self.nested_timeout);
+        keymap.set_combo_style(self.combo_style.clone());
+        keymap
+    }
 }
 
 /// Output side of a keymap entry
@@ -670,6 +1918,11 @@ pub enum KeymapOutput {
     ComboHint(ComboHint),
     Unicode(u32),
     Text(String),
+    Layer(LayerAction),
+    /// Enter a nested keymap (`keymap(name)`)
+    EnterKeymap(String),
+    /// Leave the combo unmapped, overriding later keymaps (`passthrough`)
+    Passthrough,
 }
 
 impl From<Key> for KeymapOutput {
@@ -678,17 +1931,25 @@ impl From<Key> for KeymapOutput {
     }
 }
 
+/// Built-in key names only; config outputs go through `parse_keymap_output`
 impl From<KeymapTomlOutput> for KeymapOutput {
     fn from(value: KeymapTomlOutput) -> Self {
+        let names = &KeyNames::default();
         match value {
             KeymapTomlOutput::Single(s) => {
                 // Try parsing as key, then combo hint, then combo
-                if let Ok(key) = parse_key(&s) {
+                if let Ok(key) = parse_key(&s, names) {
                     KeymapOutput::Key(key)
+                } else if let Some(layer) = parse_layer_output(&s) {
+                    KeymapOutput::Layer(layer)
+                } else if let Some(name) = parse_enter_keymap_output(&s) {
+                    KeymapOutput::EnterKeymap(name)
                 } else if let Some(codepoint) = parse_unicode_output(&s) {
                     KeymapOutput::Unicode(codepoint)
                 } else if let Some(text) = parse_text_output(&s) {
                     KeymapOutput::Text(text)
+                } else if is_passthrough_output(&s) {
+                    KeymapOutput::Passthrough
                 } else if let Ok(hint) = parse_combo_hint(&s) {
                     KeymapOutput::ComboHint(hint)
                 } else {
@@ -696,13 +1957,14 @@ impl From<KeymapTomlOutput> for KeymapOutput {
                     KeymapOutput::Key(Key::from(0))
                 }
             }
+            KeymapTomlOutput::Detailed(detailed) => (*detailed.output).into(),
             KeymapTomlOutput::Multiple(list) => {
-                let keys: Vec<Key> = list.iter().filter_map(|s| parse_key(s).ok()).collect();
+                let keys: Vec<Key> = list.iter().filter_map(|s| parse_key(s, names).ok()).collect();
                 if keys.len() == list.len() {
                     KeymapOutput::Combo(keys)
                 } else {
                     let steps: Vec<ActionStep> =
-                        list.iter().filter_map(|s| parse_sequence_step(s)).collect();
+                        list.iter().filter_map(|s| parse_sequence_step(s, names)).collect();
                     KeymapOutput::Sequence(steps)
                 }
             }
@@ -741,17 +2003,337 @@ impl Into<KeymapValue> for KeymapOutput {
             KeymapOutput::ComboHint(h) => KeymapValue::ComboHint(h),
             KeymapOutput::Unicode(codepoint) => KeymapValue::Unicode(codepoint),
             KeymapOutput::Text(text) => KeymapValue::Text(text),
+            KeymapOutput::Layer(layer) => KeymapValue::Layer(layer),
+            KeymapOutput::EnterKeymap(name) => KeymapValue::EnterKeymap(name),
+            KeymapOutput::Passthrough => KeymapValue::Passthrough,
+        }
+    }
+}
+
+/// Parse the output side of a keymap or layer's mappings
+fn parse_keymap_mappings(
+    keymap_name: &str,
+    entries: &HashMap<String, KeymapTomlOutput>,
+    names: &KeyNames,
+) -> HashMap<String, KeymapOutput> {
+    let mut mappings = HashMap::new();
+    for (combo_str, output) in entries {
+        match parse_keymap_output(output, names) {
+            // Layers, nested keymaps and hints act on the press itself
+            Ok(parsed) if !is_deferrable_output(&parsed) && is_release_combo(combo_str, names) => {
+                log::warn!(
+                    "Skipping mapping '{}' in keymap '{}': a release: combo needs a key, combo, sequence, text or unicode output",
+                    combo_str, keymap_name
+                );
+            }
+            Ok(parsed) => {
+                mappings.insert(combo_str.clone(), parsed);
+            }
+            Err(e) => {
+                log::warn!(
+                    "Skipping mapping '{}' in keymap '{}': {}",
+                    combo_str, keymap_name, e
+                );
+            }
+        }
+    }
+
+    mappings
+}
+
+/// Outputs that can fire after their key's press: on its release or hold
+fn is_deferrable_output(output: &KeymapOutput) -> bool {
+    matches!(
+        output,
+        KeymapOutput::Key(_)
+            | KeymapOutput::Combo(_)
+            | KeymapOutput::Sequence(_)
+            | KeymapOutput::Unicode(_)
+            | KeymapOutput::Text(_)
+    )
+}
+
+/// Hold outputs of a keymap's `{ tap = ..., hold = ... }` mappings; like
+/// unparsable mappings, invalid ones are skipped with a warning
+fn parse_keymap_holds(
+    keymap_name: &str,
+    entries: &HashMap<String, KeymapTomlOutput>,
+    mappings: &HashMap<String, KeymapOutput>,
+    names: &KeyNames,
+) -> Vec<(String, KeymapOutput)> {
+    let mut holds = Vec::new();
+    for (combo_str, output) in entries {
+        let KeymapTomlOutput::Detailed(KeymapTomlDetailed { hold: Some(hold), .. }) = output else {
+            continue;
+        };
+        let hold = match hold.as_ref() {
+            KeymapTomlOutput::Detailed(_) => Err("'hold' must be a string or a list".to_string()),
+            hold => parse_keymap_output(hold, names),
+        };
+        let tap_deferrable = mappings.get(combo_str).is_some_and(is_deferrable_output);
+        match hold {
+            Ok(hold) if tap_deferrable && is_deferrable_output(&hold) => holds.push((combo_str.clone(), hold)),
+            Ok(_) => log::warn!(
+                "Skipping hold of '{}' in keymap '{}': tap and hold need a key, combo, sequence, text or unicode output",
+                combo_str, keymap_name
+            ),
+            Err(e) => log::warn!("Skipping hold of '{}' in keymap '{}': {}", combo_str, keymap_name, e),
+        }
+    }
+    holds.sort_by(|a, b| a.0.cmp(&b.0));
+    holds
+}
+
+/// Whether `combo_str` is a `release:` combo
+fn is_release_combo(combo_str: &str, names: &KeyNames) -> bool {
+    super::parse_combo_with(combo_str, names).is_ok_and(|parsed| parsed.trigger == ComboTrigger::Release)
+}
+
+/// Parse one keymap output value
+pub(crate) fn parse_keymap_output(output: &KeymapTomlOutput, names: &KeyNames) -> Result<KeymapOutput, String> {
+    match output {
+        KeymapTomlOutput::Single(s) => {
+            if let Some(layer) = parse_layer_output(s) {
+                return Ok(KeymapOutput::Layer(layer));
+            }
+            if let Some(name) = parse_enter_keymap_output(s) {
+                return Ok(KeymapOutput::EnterKeymap(name));
+            }
+            if let Some(text) = parse_text_output(s) {
+                return Ok(KeymapOutput::Text(text));
+            }
+            if let Some(codepoint) = parse_unicode_output(s) {
+                return Ok(KeymapOutput::Unicode(codepoint));
+            }
+            if let Some(step) = parse_set_mark_step(s).or_else(|| parse_run_step(s)).or_else(|| parse_profile_step(s)) {
+                return Ok(KeymapOutput::Sequence(vec![step]));
+            }
+            if is_passthrough_output(s) {
+                return Ok(KeymapOutput::Passthrough);
+            }
+            // `Reset` leaves every nested keymap; the escape hints let the
+            // next key or combo through untouched
+            if let Ok(hint @ (ComboHint::Reset | ComboHint::EscapeNextKey | ComboHint::EscapeNextCombo)) =
+                parse_combo_hint(s)
+            {
+                return Ok(KeymapOutput::ComboHint(hint));
+            }
+
+            // Try parsing as a combo first (e.g., "Ctrl-c" or "Ctrl-Shift-c")
+            match super::validate_combo_with(s, names) {
+                Ok(parsed) if parsed.trigger == ComboTrigger::Release => {
+                    Err(format!("invalid output '{}': release: only applies to input combos", s))
+                }
+                Ok(parsed) => {
+                    // Output is a combo - convert to sequence of keys
+                    let mut keys = Vec::new();
+
+                    // Add modifier keys (use first key from each modifier)
+                    for modifier in &parsed.modifiers {
+                        if let Some(&first_key) = modifier.keys().first() {
+                            keys.push(first_key);
+                        }
+                    }
+
+                    // Add the final key
+                    keys.push(parsed.key);
+
+                    Ok(KeymapOutput::Combo(keys))
+                }
+                // Try parsing as a single key instead
+                Err(e) => parse_key(s, names)
+                    .map(KeymapOutput::Key)
+                    .map_err(|_| format!("invalid output '{}': {}", s, e)),
+            }
+        }
+        KeymapTomlOutput::Multiple(list) => {
+            let keys: Vec<Key> = list.iter().filter_map(|s| parse_key(s, names).ok()).collect();
+            if keys.len() == list.len() {
+                return Ok(KeymapOutput::Combo(keys));
+            }
+
+            let mut steps = Vec::with_capacity(list.len());
+            for item in list {
+                let step = parse_sequence_step(item, names)
+                    .ok_or_else(|| format!("invalid sequence step '{}'", item))?;
+                steps.push(step);
+            }
+            Ok(KeymapOutput::Sequence(steps))
+        }
+        KeymapTomlOutput::Detailed(detailed) => match detailed.output.as_ref() {
+            KeymapTomlOutput::Detailed(_) => Err("'output' must be a string or a list".to_string()),
+            output => parse_keymap_output(output, names),
+        },
+    }
+}
+
+/// Synthetic repeat rates set on a keymap's mappings
+fn parse_keymap_repeats(
+    keymap_name: &str,
+    entries: &HashMap<String, KeymapTomlOutput>,
+) -> Result<Vec<(String, RepeatRate)>, ConfigError> {
+    let mut repeats = Vec::new();
+    for (combo_str, output) in entries {
+        if let KeymapTomlOutput::Detailed(KeymapTomlDetailed {
+            repeat: Some(repeat),
+            ..
+        }) = output
+        {
+            let rate = repeat.to_rate().map_err(|e| match e {
+                ConfigError::TimeoutOutOfRange(msg) => ConfigError::TimeoutOutOfRange(format!(
+                    "mapping '{}' in '{}': {}",
+                    combo_str, keymap_name, msg
+                )),
+                other => other,
+            })?;
+            repeats.push((combo_str.clone(), rate));
+        }
+    }
+    repeats.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(repeats)
+}
+
+/// Combo style from a keymap's `modifier_order` and `combo_delay_ms`
+fn parse_combo_style(keymap_name: &str, entry: &KeymapTomlEntry) -> Result<Option<ComboStyle>, ConfigError> {
+    if entry.modifier_order.is_none() && entry.combo_delay_ms.is_none() {
+        return Ok(None);
+    }
+    let modifier_order = match &entry.modifier_order {
+        Some(names) => ComboStyle::parse_modifier_order(names).map_err(|e| {
+            ConfigError::InvalidValue(format!("modifier_order in '{}': {}", keymap_name, e))
+        })?,
+        None => Vec::new(),
+    };
+    let delay_ms = entry
+        .combo_delay_ms
+        .map(|ms| check_timing("delays", "combo_delay_ms", ms))
+        .transpose()?;
+    Ok(Some(ComboStyle { modifier_order, delay_ms }))
+}
+
+/// Record the `toggle_setting` of entry `owner`; a flag shared by several
+/// entries may set its `toggle_default` in any of them, but only one way
+fn register_toggle(
+    toggles: &mut BTreeMap<String, Option<bool>>,
+    owner: &str,
+    toggle: Option<&str>,
+    default: Option<bool>,
+) -> Result<(), ConfigError> {
+    let Some(name) = toggle else {
+        if default.is_some() {
+            return Err(ConfigError::InvalidValue(format!(
+                "toggle_default in '{}' needs a toggle_setting",
+                owner
+            )));
+        }
+        return Ok(());
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(ConfigError::InvalidValue(format!(
+            "toggle_setting in '{}' must be a settings name (letters, digits, _), got '{}'",
+            owner, name
+        )));
+    }
+    let registered = toggles.entry(name.to_string()).or_insert(default);
+    match (*registered, default) {
+        (Some(a), Some(b)) if a != b => Err(ConfigError::InvalidValue(format!(
+            "toggle_default of '{}' in '{}' contradicts an earlier entry",
+            name, owner
+        ))),
+        (None, Some(_)) => {
+            *registered = default;
+            Ok(())
         }
+        _ => Ok(()),
+    }
+}
+
+/// `condition` that also requires `settings.<toggle>`
+fn gate_on_setting(condition: Option<String>, toggle: Option<&str>) -> Option<String> {
+    match (toggle, condition) {
+        (None, condition) => condition,
+        (Some(name), None) => Some(format!("settings.{}", name)),
+        (Some(name), Some(condition)) => Some(format!("settings.{} and ({})", name, condition)),
     }
 }
 
+/// Descriptions set on a keymap's mappings
+fn parse_keymap_descriptions(entries: &HashMap<String, KeymapTomlOutput>) -> Vec<(String, String)> {
+    let mut descriptions: Vec<(String, String)> = entries
+        .iter()
+        .filter_map(|(combo_str, output)| match output {
+            KeymapTomlOutput::Detailed(KeymapTomlDetailed {
+                description: Some(description),
+                ..
+            }) => Some((combo_str.clone(), description.trim().to_string())),
+            _ => None,
+        })
+        .filter(|(_, description)| !description.is_empty())
+        .collect();
+    descriptions.sort();
+    descriptions
+}
+
+/// Trigger of a `[modtap]` entry; only modifiers can be tapped alone
+pub(crate) fn parse_modtap_trigger(name: &str, names: &KeyNames) -> Result<Key, ConfigError> {
+    let key = parse_key(name, names)?;
+    if !Modifier::is_key_modifier(key) {
+        return Err(ConfigError::InvalidValue(format!(
+            "modtap trigger '{}' is not a modifier key",
+            name.trim()
+        )));
+    }
+    Ok(key)
+}
+
+/// Output of a `[modtap]` entry: a key or a combo (`Ctrl-Alt-t`)
+pub(crate) fn parse_modtap_output(output: &str, names: &KeyNames) -> Result<Combo, ConfigError> {
+    parse_combo_step(output, names).ok_or_else(|| {
+        ConfigError::InvalidCombo(format!("invalid modtap output '{}'", output.trim()))
+    })
+}
+
+/// Scancode of a `[scancodes]` entry (hex `0x...` or decimal)
+pub(crate) fn parse_scancode_entry(text: &str) -> Result<u32, ConfigError> {
+    parse_scancode(text).ok_or_else(|| {
+        ConfigError::InvalidValue(format!(
+            "invalid scancode '{}' (expected hex like 0x70039 or a decimal number)",
+            text.trim()
+        ))
+    })
+}
+
 /// Configuration for transform engine
 pub use crate::transform::TransformConfig;
 
 /// Parse a key name into a Key
-fn parse_key(name: &str) -> Result<Key, ConfigError> {
+pub(crate) fn parse_key(name: &str, names: &KeyNames) -> Result<Key, ConfigError> {
+    let trimmed = name.trim();
+    names.key(trimmed).ok_or_else(|| {
+        let suggestions = super::suggest_key_names(trimmed);
+        if suggestions.is_empty() {
+            ConfigError::InvalidKey(trimmed.to_string())
+        } else {
+            ConfigError::InvalidKey(format!(
+                "{} (did you mean {}?)",
+                trimmed,
+                suggestions.join(", ")
+            ))
+        }
+    })
+}
+
+/// Parse a modmap target: a key name, or a compound modifier (`Hyper`,
+/// `Meh`) whose virtual key presses all of its components. A key alias of
+/// the same name wins.
+fn parse_modmap_target(name: &str, names: &KeyNames) -> Result<Key, ConfigError> {
     let trimmed = name.trim();
-    crate::key::key_from_name(trimmed).ok_or_else(|| ConfigError::InvalidKey(trimmed.to_string()))
+    if names.key(trimmed).is_none() {
+        if let Some(compound) = Modifier::from_alias(trimmed).filter(Modifier::is_compound) {
+            return Ok(compound.key());
+        }
+    }
+    parse_key(name, names)
 }
 
 /// Parse Unicode output syntax.
@@ -785,6 +2367,50 @@ fn parse_unicode_output(s: &str) -> Option<u32> {
     None
 }
 
+/// Parse layer output syntax.
+///
+/// Supported formats:
+/// - `Layer(nav)` (momentary, active while held)
+/// - `Layer(nav, toggle)`
+/// - `Layer(nav, oneshot)`
+fn parse_layer_output(s: &str) -> Option<LayerAction> {
+    let trimmed = s.trim();
+    if trimmed.len() < 7 || !trimmed[..6].eq_ignore_ascii_case("layer(") || !trimmed.ends_with(')') {
+        return None;
+    }
+
+    let inner = &trimmed[6..trimmed.len() - 1];
+    let mut parts = inner.split(',').map(str::trim);
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    let mode = match parts.next() {
+        Some(mode) => LayerMode::from_name(mode)?,
+        None => LayerMode::Momentary,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(LayerAction {
+        name: name.to_string(),
+        mode,
+    })
+}
+
+/// Parse nested keymap output syntax: `keymap(emacs-cx)`
+/// `passthrough`: the combo stays unmapped even where a later keymap maps it
+fn is_passthrough_output(s: &str) -> bool {
+    s.trim().eq_ignore_ascii_case("passthrough")
+}
+
+fn parse_enter_keymap_output(s: &str) -> Option<String> {
+    let trimmed = s.trim();
+    if trimmed.len() < 8 || !trimmed[..7].eq_ignore_ascii_case("keymap(") || !trimmed.ends_with(')') {
+        return None;
+    }
+    let name = trimmed[7..trimmed.len() - 1].trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
 /// Parse text output syntax.
 ///
 /// Supported formats:
@@ -865,28 +2491,226 @@ fn parse_set_setting_step(s: &str) -> Option<ActionStep> {
     })
 }
 
-fn parse_combo_step(s: &str) -> Option<Combo> {
-    let trimmed = s.trim();
-    let combo_expr = if trimmed.len() >= 7
-        && trimmed[..6].eq_ignore_ascii_case("combo(")
-        && trimmed.ends_with(')')
-    {
-        trimmed[6..trimmed.len() - 1].trim()
-    } else {
-        trimmed
-    };
-
-    if let Ok(parsed) = super::parse_combo_string(combo_expr) {
-        return Some(Combo::new(parsed.modifiers, parsed.key));
+/// `SetMark` (toggle), `SetMark(on)` / `SetMark(off)`; `set_mark` also works
+fn parse_set_mark_step(s: &str) -> Option<ActionStep> {
+    let lower = s.trim().to_ascii_lowercase().replace('_', "");
+    if lower == "setmark" {
+        return Some(ActionStep::SetMark(None));
+    }
+    let value = lower.strip_prefix("setmark(")?.strip_suffix(')')?.trim().to_string();
+    match value.as_str() {
+        "" => Some(ActionStep::SetMark(None)),
+        "true" | "1" | "yes" | "on" => Some(ActionStep::SetMark(Some(true))),
+        "false" | "0" | "no" | "off" => Some(ActionStep::SetMark(Some(false))),
+        _ => None,
     }
-    parse_key(combo_expr).ok().map(|k| Combo::new(Vec::new(), k))
 }
 
-fn parse_sequence_step(s: &str) -> Option<ActionStep> {
-    if let Some(ms) = parse_delay_step(s) {
-        return Some(ActionStep::DelayMs(ms));
+/// `KeepModifiers(Shift)` or `KeepModifiers(Shift, Ctrl)`
+fn parse_keep_modifiers_step(s: &str) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    let open = trimmed.find('(')?;
+    if !trimmed[..open].replace('_', "").eq_ignore_ascii_case("keepmodifiers") || !trimmed.ends_with(')') {
+        return None;
     }
-    if let Some(step) = parse_set_setting_step(s) {
+    let modifiers = split_top_level_args(&trimmed[open + 1..trimmed.len() - 1])
+        .into_iter()
+        .map(Modifier::from_alias)
+        .collect::<Option<Vec<_>>>()?;
+    (!modifiers.is_empty()).then_some(ActionStep::KeepModifiers(modifiers))
+}
+
+/// `Run(playerctl play-pause)`: a shell command, optionally quoted
+fn parse_run_step(s: &str) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    if !trimmed.get(..4)?.eq_ignore_ascii_case("run(") || !trimmed.ends_with(')') {
+        return None;
+    }
+    let inner = trimmed[4..trimmed.len() - 1].trim();
+    let command = inner
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .or_else(|| inner.strip_prefix('\'').and_then(|x| x.strip_suffix('\'')))
+        .unwrap_or(inner);
+    (!command.trim().is_empty()).then(|| ActionStep::Run(command.to_string()))
+}
+
+/// `Profile` (the next profile) or `Profile(name)`
+fn parse_profile_step(s: &str) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    if trimmed.eq_ignore_ascii_case("profile") {
+        return Some(ActionStep::Profile(None));
+    }
+    if !trimmed.get(..8)?.eq_ignore_ascii_case("profile(") || !trimmed.ends_with(')') {
+        return None;
+    }
+    let name = trimmed[8..trimmed.len() - 1].trim();
+    (!name.is_empty()).then(|| ActionStep::Profile(Some(name.to_string())))
+}
+
+/// Ctrl-v, the default `[output].paste`
+fn default_paste_combo() -> Combo {
+    let ctrl = Modifier::from_alias("Ctrl").expect("Ctrl is a built-in modifier");
+    Combo::new(vec![ctrl], Key::from(47))
+}
+
+fn parse_combo_step(s: &str, names: &KeyNames) -> Option<Combo> {
+    let trimmed = s.trim();
+    let combo_expr = if trimmed.len() >= 7
+        && trimmed[..6].eq_ignore_ascii_case("combo(")
+        && trimmed.ends_with(')')
+    {
+        trimmed[6..trimmed.len() - 1].trim()
+    } else {
+        trimmed
+    };
+
+    if let Ok(parsed) = super::parse_combo_with(combo_expr, names) {
+        return (parsed.trigger == ComboTrigger::Press).then(|| Combo::new(parsed.modifiers, parsed.key));
+    }
+    parse_key(combo_expr, names).ok().map(|k| Combo::new(Vec::new(), k))
+}
+
+/// Split on commas that are not nested inside parentheses or double quotes
+fn split_top_level_args(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+/// Parse `After(<ms>, <step>, ...)` into a deferred step list
+fn parse_after_step(s: &str, names: &KeyNames) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    if trimmed.len() < 7
+        || !trimmed[..6].eq_ignore_ascii_case("after(")
+        || !trimmed.ends_with(')')
+    {
+        return None;
+    }
+    let args = split_top_level_args(&trimmed[6..trimmed.len() - 1]);
+    let (delay, rest) = args.split_first()?;
+    let delay_ms = delay.parse::<u64>().ok()?;
+    if rest.is_empty() {
+        return None;
+    }
+    let steps = rest
+        .iter()
+        .map(|step| parse_sequence_step(step, names))
+        .collect::<Option<Vec<_>>>()?;
+    Some(ActionStep::After { delay_ms, steps })
+}
+
+/// Parse `Repeat(<times>, <step>, ...)`; counts above `MAX_REPEAT_TIMES` are
+/// clamped, and so are counts that would expand nested repeats to more than
+/// `MAX_REPEAT_EXPANSION` steps
+fn parse_repeat_step(s: &str, names: &KeyNames) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    if trimmed.len() < 8
+        || !trimmed[..7].eq_ignore_ascii_case("repeat(")
+        || !trimmed.ends_with(')')
+    {
+        return None;
+    }
+    let args = split_top_level_args(&trimmed[7..trimmed.len() - 1]);
+    let (count, rest) = args.split_first()?;
+    let mut times = count.parse::<u32>().ok()?;
+    if rest.is_empty() {
+        return None;
+    }
+    if times > MAX_REPEAT_TIMES {
+        log::warn!(
+            "Repeat count {} in '{}' exceeds the maximum of {}, clamping",
+            times,
+            trimmed,
+            MAX_REPEAT_TIMES
+        );
+        times = MAX_REPEAT_TIMES;
+    }
+    let steps = rest
+        .iter()
+        .map(|step| parse_sequence_step(step, names))
+        .collect::<Option<Vec<_>>>()?;
+    // Inner repeats are already within the budget, so one pass is enough
+    let per_pass = steps.iter().fold(0u32, |total, step| total.saturating_add(step.expanded_len()));
+    if times.saturating_mul(per_pass) > MAX_REPEAT_EXPANSION {
+        let clamped = (MAX_REPEAT_EXPANSION / per_pass.max(1)).max(1);
+        log::warn!(
+            "Repeat in '{}' expands to {} steps, more than the maximum of {}; clamping the count to {}",
+            trimmed,
+            u64::from(times) * u64::from(per_pass),
+            MAX_REPEAT_EXPANSION,
+            clamped
+        );
+        times = clamped;
+    }
+    Some(ActionStep::Repeat { times, steps })
+}
+
+/// Parse `Press(<key>)`, `Release(<key>)` and `Hold(<key>, <ms>)`
+fn parse_key_state_step(s: &str, names: &KeyNames) -> Option<ActionStep> {
+    let trimmed = s.trim();
+    let open = trimmed.find('(')?;
+    if !trimmed.ends_with(')') {
+        return None;
+    }
+    let name = &trimmed[..open];
+    let args = split_top_level_args(&trimmed[open + 1..trimmed.len() - 1]);
+    match args.as_slice() {
+        [key] if name.eq_ignore_ascii_case("press") => parse_key(key, names).ok().map(ActionStep::Press),
+        [key] if name.eq_ignore_ascii_case("release") => {
+            parse_key(key, names).ok().map(ActionStep::Release)
+        }
+        [key, ms] if name.eq_ignore_ascii_case("hold") => Some(ActionStep::Hold {
+            key: parse_key(key, names).ok()?,
+            ms: ms.parse::<u64>().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_sequence_step(s: &str, names: &KeyNames) -> Option<ActionStep> {
+    if let Some(step) = parse_after_step(s, names) {
+        return Some(step);
+    }
+    if let Some(step) = parse_key_state_step(s, names) {
+        return Some(step);
+    }
+    if let Some(step) = parse_repeat_step(s, names) {
+        return Some(step);
+    }
+    if let Some(ms) = parse_delay_step(s) {
+        return Some(ActionStep::DelayMs(ms));
+    }
+    if let Some(step) = parse_set_setting_step(s) {
+        return Some(step);
+    }
+    if let Some(step) = parse_set_mark_step(s) {
+        return Some(step);
+    }
+    if let Some(step) = parse_keep_modifiers_step(s) {
+        return Some(step);
+    }
+    if let Some(step) = parse_run_step(s) {
+        return Some(step);
+    }
+    if let Some(step) = parse_profile_step(s) {
         return Some(step);
     }
     if parse_bind_step(s) {
@@ -898,7 +2722,7 @@ fn parse_sequence_step(s: &str) -> Option<ActionStep> {
     if let Some(text) = parse_text_output(s) {
         return Some(ActionStep::Text(text));
     }
-    parse_combo_step(s).map(ActionStep::Combo)
+    parse_combo_step(s, names).map(ActionStep::Combo)
 }
 
 /// Parse a combo hint string (e.g., "combo(bind)")
@@ -906,7 +2730,7 @@ fn parse_combo_hint(s: &str) -> Result<ComboHint, ConfigError> {
     let trimmed = s.trim().to_lowercase();
 
     // Match against known hints
-    // Note: ComboHint enum uses Bind=1, EscapeNextKey=2, Ignore=3, EscapeNextCombo=4
+    // Note: ComboHint enum uses Bind=1, EscapeNextKey=2, Ignore=3, EscapeNextCombo=4, Reset=5
     if trimmed == "combo(bind)" || trimmed == "bind" {
         Ok(ComboHint::Bind)
     } else if trimmed == "escape_next" || trimmed == "escapenext" || trimmed == "escape_next_key" {
@@ -915,6 +2739,8 @@ fn parse_combo_hint(s: &str) -> Result<ComboHint, ConfigError> {
         Ok(ComboHint::Ignore)
     } else if trimmed == "escape_next_combo" {
         Ok(ComboHint::EscapeNextCombo)
+    } else if trimmed == "reset" {
+        Ok(ComboHint::Reset)
     } else {
         Err(ConfigError::InvalidCombo(format!("unknown hint: {}", s)))
     }
@@ -926,9 +2752,9 @@ mod tests {
 
     #[test]
     fn test_parse_key() {
-        assert_eq!(parse_key("a").unwrap(), Key::from(30));
-        assert_eq!(parse_key("left_ctrl").unwrap(), Key::from(29));
-        assert!(parse_key("notakey").is_err());
+        assert_eq!(parse_key("a", &KeyNames::default()).unwrap(), Key::from(30));
+        assert_eq!(parse_key("left_ctrl", &KeyNames::default()).unwrap(), Key::from(29));
+        assert!(parse_key("notakey", &KeyNames::default()).is_err());
     }
 
     #[test]
@@ -975,6 +2801,15 @@ mod tests {
         assert!(config.modmaps[1].condition.is_some());
     }
 
+    #[test]
+    fn test_modmap_to_compound_modifier() {
+        let config = Config::from_toml("[modmap.default]\ncapslock = \"Hyper\"\nf13 = \"Meh\"\n").unwrap();
+        let mappings = &config.modmaps[0].mappings;
+        let target = |code| mappings.iter().find(|(from, _)| *from == Key::from(code)).map(|(_, to)| *to);
+        assert_eq!(target(58), Some(Modifier::from_alias("Hyper").unwrap().key()));
+        assert_eq!(target(183), Some(Modifier::from_alias("Meh").unwrap().key()));
+    }
+
     #[test]
     fn test_parse_combo_hint() {
         assert_eq!(parse_combo_hint("combo(bind)").unwrap(), ComboHint::Bind);
@@ -1005,35 +2840,253 @@ mod tests {
         assert_eq!(parse_text_output("Unicode(00E9)"), None);
     }
 
+    #[test]
+    fn test_parse_after_step() {
+        assert_eq!(
+            parse_sequence_step("After(2000, Combo(Esc))", &KeyNames::default()),
+            Some(ActionStep::After {
+                delay_ms: 2000,
+                steps: vec![ActionStep::Combo(Combo::new(Vec::new(), parse_key("Esc", &KeyNames::default()).unwrap()))],
+            })
+        );
+        assert_eq!(
+            parse_sequence_step("after(50, Text(\"a, b\"), Delay(10))", &KeyNames::default()),
+            Some(ActionStep::After {
+                delay_ms: 50,
+                steps: vec![ActionStep::Text("a, b".to_string()), ActionStep::DelayMs(10)],
+            })
+        );
+        assert_eq!(parse_sequence_step("After(abc, Combo(Esc))", &KeyNames::default()), None);
+        assert_eq!(parse_sequence_step("After(100)", &KeyNames::default()), None);
+    }
+
+    #[test]
+    fn test_parse_repeat_step() {
+        let down = Combo::new(Vec::new(), parse_key("Down", &KeyNames::default()).unwrap());
+        assert_eq!(
+            parse_sequence_step("Repeat(5, Combo(Down))", &KeyNames::default()),
+            Some(ActionStep::Repeat {
+                times: 5,
+                steps: vec![ActionStep::Combo(down.clone())],
+            })
+        );
+        assert_eq!(
+            parse_sequence_step("repeat(100000, Down, Delay(5))", &KeyNames::default()),
+            Some(ActionStep::Repeat {
+                times: MAX_REPEAT_TIMES,
+                steps: vec![ActionStep::Combo(down), ActionStep::DelayMs(5)],
+            })
+        );
+        assert!(matches!(
+            parse_sequence_step("Repeat(2, Repeat(3, Text(x)))", &KeyNames::default()),
+            Some(ActionStep::Repeat { times: 2, .. })
+        ));
+        // Nested counts multiply; the outer count is clamped to the budget
+        let nested = parse_sequence_step("Repeat(100, Repeat(100, Repeat(100, Down)))", &KeyNames::default()).unwrap();
+        assert!(matches!(&nested, ActionStep::Repeat { times: 1, .. }));
+        assert_eq!(nested.expanded_len(), MAX_REPEAT_EXPANSION);
+        let nested = parse_sequence_step("Repeat(50, Repeat(10, Down, Delay(5)))", &KeyNames::default()).unwrap();
+        assert!(matches!(&nested, ActionStep::Repeat { times: 50, .. }));
+        assert!(nested.expanded_len() <= MAX_REPEAT_EXPANSION);
+        assert_eq!(parse_sequence_step("Repeat(-1, Down)", &KeyNames::default()), None);
+        assert_eq!(parse_sequence_step("Repeat(3)", &KeyNames::default()), None);
+    }
+
+    #[test]
+    fn test_parse_key_state_steps() {
+        let alt = parse_key("LEFT_ALT", &KeyNames::default()).unwrap();
+        assert_eq!(parse_sequence_step("Press(LEFT_ALT)", &KeyNames::default()), Some(ActionStep::Press(alt)));
+        assert_eq!(parse_sequence_step("release( left_alt )", &KeyNames::default()), Some(ActionStep::Release(alt)));
+        assert_eq!(
+            parse_sequence_step("Hold(Space, 300)", &KeyNames::default()),
+            Some(ActionStep::Hold { key: parse_key("Space", &KeyNames::default()).unwrap(), ms: 300 })
+        );
+        assert_eq!(parse_sequence_step("Press(Ctrl-a)", &KeyNames::default()), None);
+        assert_eq!(parse_sequence_step("Hold(Space)", &KeyNames::default()), None);
+        assert_eq!(parse_sequence_step("Hold(Space, -5)", &KeyNames::default()), None);
+
+        let output = KeymapTomlOutput::Multiple(vec![
+            "Press(LEFT_ALT)".to_string(),
+            "Tab".to_string(),
+            "Tab".to_string(),
+            "Release(LEFT_ALT)".to_string(),
+        ]);
+        assert!(matches!(parse_keymap_output(&output, &KeyNames::default()), Ok(KeymapOutput::Sequence(steps)) if steps.len() == 4));
+    }
+
     #[test]
     fn test_parse_sequence_step() {
-        assert_eq!(parse_sequence_step("Delay(200)"), Some(ActionStep::DelayMs(200)));
+        assert_eq!(parse_sequence_step("Delay(200)", &KeyNames::default()), Some(ActionStep::DelayMs(200)));
         assert_eq!(
-            parse_sequence_step("SetSetting(Enter2Ent_Cmd=true)"),
+            parse_sequence_step("SetSetting(Enter2Ent_Cmd=true)", &KeyNames::default()),
             Some(ActionStep::SetSetting {
                 name: "Enter2Ent_Cmd".to_string(),
                 value: true
             })
         );
         assert_eq!(
-            parse_sequence_step("set(Enter2Ent_Cmd=off)"),
+            parse_sequence_step("set(Enter2Ent_Cmd=off)", &KeyNames::default()),
             Some(ActionStep::SetSetting {
                 name: "Enter2Ent_Cmd".to_string(),
                 value: false
             })
         );
-        assert_eq!(parse_sequence_step("bind"), Some(ActionStep::Bind));
-        assert_eq!(parse_sequence_step("Ignore"), Some(ActionStep::Ignore));
+        assert_eq!(parse_sequence_step("bind", &KeyNames::default()), Some(ActionStep::Bind));
+        assert_eq!(parse_sequence_step("Ignore", &KeyNames::default()), Some(ActionStep::Ignore));
         assert_eq!(
-            parse_sequence_step("Text(hello)"),
+            parse_sequence_step("Text(hello)", &KeyNames::default()),
             Some(ActionStep::Text("hello".to_string()))
         );
         assert!(matches!(
-            parse_sequence_step("Ctrl-t"),
+            parse_sequence_step("Ctrl-t", &KeyNames::default()),
             Some(ActionStep::Combo(_))
         ));
     }
 
+    #[test]
+    fn test_parse_set_mark_step() {
+        assert_eq!(parse_sequence_step("SetMark", &KeyNames::default()), Some(ActionStep::SetMark(None)));
+        assert_eq!(parse_sequence_step("set_mark(off)", &KeyNames::default()), Some(ActionStep::SetMark(Some(false))));
+        assert_eq!(parse_sequence_step("SetMark(maybe)", &KeyNames::default()), None);
+        assert!(matches!(
+            parse_keymap_output(&KeymapTomlOutput::Single("SetMark(on)".to_string()), &KeyNames::default()),
+            Ok(KeymapOutput::Sequence(steps)) if steps == [ActionStep::SetMark(Some(true))]
+        ));
+    }
+
+    #[test]
+    fn test_parse_keep_modifiers_step() {
+        let keep = parse_sequence_step("keep_modifiers(Shift, Ctrl)", &KeyNames::default()).unwrap();
+        assert_eq!(keep.to_string(), "KeepModifiers(Shift, Ctrl)");
+        assert_eq!(parse_sequence_step(&keep.to_string(), &KeyNames::default()), Some(keep));
+        assert_eq!(parse_sequence_step("KeepModifiers()", &KeyNames::default()), None);
+        assert_eq!(parse_sequence_step("KeepModifiers(Shift, Nope)", &KeyNames::default()), None);
+    }
+
+    #[test]
+    fn test_parse_run_step() {
+        let run = parse_sequence_step("Run(\"playerctl next\")", &KeyNames::default()).unwrap();
+        assert_eq!(run, ActionStep::Run("playerctl next".to_string()));
+        assert_eq!(parse_sequence_step(&run.to_string(), &KeyNames::default()), Some(run));
+        assert_eq!(parse_sequence_step("run( )", &KeyNames::default()), None);
+    }
+
+    #[test]
+    fn test_release_combo_config() {
+        let config = Config::from_toml(
+            r#"
+[[keymap]]
+name = "layouts"
+[keymap.mappings]
+"release:Super-Space" = "Super-F1"
+"release:Super-l" = "Layer(nav)"
+"Super-k" = "Ctrl-k"
+"#,
+        )
+        .unwrap();
+        let keymap = config.keymaps[0].to_keymap(&KeyNames::default());
+        let meta = Modifier::from_alias("Super").unwrap();
+        assert!(keymap.fires_on_release(&Combo::new(vec![meta.clone()], Key::from(57))));
+        assert!(!keymap.fires_on_release(&Combo::new(vec![meta.clone()], Key::from(37))));
+        // Layers act on the press, so a release: layer is skipped
+        assert!(keymap.get(&Combo::new(vec![meta], Key::from(38))).is_none());
+
+        assert!(parse_keymap_output(&KeymapTomlOutput::Single("release:Ctrl-c".to_string()), &KeyNames::default()).is_err());
+        assert_eq!(parse_sequence_step("Combo(release:Ctrl-c)", &KeyNames::default()), None);
+    }
+
+    #[test]
+    fn test_keymap_tap_hold_config() {
+        let config = Config::from_toml(
+            r#"
+[[keymap]]
+name = "windows"
+[keymap.mappings]
+"Super-Enter" = { tap = "Run(kitty)", hold = "Super-Up" }
+"Super-m" = { output = "Super-Down", hold = ["Super-Down", "Super-Down"] }
+"Super-l" = { tap = "passthrough", hold = "Super-Up" }
+"Super-k" = { output = "Ctrl-k", hold = { output = "Ctrl-l" } }
+"#,
+        )
+        .unwrap();
+        let entry = &config.keymaps[0];
+        let combos: Vec<&str> = entry.holds.iter().map(|(combo, _)| combo.as_str()).collect();
+        assert_eq!(combos, ["Super-Enter", "Super-m"]);
+
+        let keymap = entry.to_keymap(&KeyNames::default());
+        let meta = Modifier::from_alias("Super").unwrap();
+        let enter = Combo::new(vec![meta.clone()], Key::from(28));
+        assert_eq!(keymap.get(&enter), Some(&KeymapValue::Sequence(vec![ActionStep::Run("kitty".to_string())])));
+        assert!(matches!(keymap.hold(&enter), Some(KeymapValue::Combo(_))));
+        assert!(keymap.hold(&Combo::new(vec![meta], Key::from(38))).is_none());
+    }
+
+    #[test]
+    fn test_profiles_config() {
+        let config = Config::from_toml(
+            r#"
+[profiles]
+name = "mac-style"
+[profiles.files]
+vanilla = "vanilla.toml"
+
+[[keymap]]
+name = "switch"
+[keymap.mappings]
+"Ctrl-Alt-p" = "Profile"
+"Ctrl-Alt-v" = "Profile(vanilla)"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.profile, "mac-style");
+        assert_eq!(config.profiles, [("vanilla".to_string(), PathBuf::from("vanilla.toml"))]);
+        assert_eq!(config.to_transform_config().profile, "mac-style");
+        let outputs: Vec<String> = config.keymaps[0].mappings.iter().map(|(_, output)| format!("{:?}", output)).collect();
+        assert!(outputs.contains(&format!("{:?}", KeymapOutput::Sequence(vec![ActionStep::Profile(None)]))));
+        assert_eq!(parse_sequence_step("profile(vanilla)", &KeyNames::default()), Some(ActionStep::Profile(Some("vanilla".to_string()))));
+        assert_eq!(ActionStep::Profile(Some("vanilla".to_string())).to_string(), "Profile(vanilla)");
+
+        assert_eq!(Config::from_toml("").unwrap().profile, DEFAULT_PROFILE);
+        assert!(Config::from_toml("[profiles]\nname = \"a\"\n[profiles.files]\na = \"a.toml\"\n").is_err());
+    }
+
+    #[test]
+    fn test_suspend_when_config() {
+        let config = Config::from_toml("suspend_when = \"wm_class =~ 'remmina'\"\n[[keymap]]\nname = \"x\"\n").unwrap();
+        assert_eq!(config.suspend_when.as_deref(), Some("wm_class =~ 'remmina'"));
+        assert!(config.to_transform_config().suspend_when.is_some());
+        assert!(Config::from_toml("").unwrap().to_transform_config().suspend_when.is_none());
+    }
+
+    #[test]
+    fn test_mediamap_config() {
+        let config = Config::from_toml(
+            r#"
+[mediamap]
+passthrough = true
+
+[mediamap.keys]
+BrightnessUp = "VolumeUp"
+XF86AudioRaiseVolume = "XF86MonBrightnessUp"
+PlayPause = "Run(playerctl play-pause)"
+"#,
+        )
+        .unwrap();
+        assert!(config.media_passthrough);
+        assert_eq!(config.mediamap.len(), 3);
+        let transform = config.to_transform_config();
+        assert_eq!(transform.mediamap.get(&Key::from(225)), Some(&MediaMapping::Key(Key::from(115))));
+        assert_eq!(transform.mediamap.get(&Key::from(115)), Some(&MediaMapping::Key(Key::from(225))));
+        assert_eq!(
+            transform.mediamap.get(&Key::from(164)),
+            Some(&MediaMapping::Run("playerctl play-pause".to_string()))
+        );
+
+        let err = Config::from_toml("[mediamap.keys]\na = \"b\"\n").unwrap_err();
+        assert!(err.to_string().contains("'a' is not a media key"), "{}", err);
+        assert!(Config::from_toml("[mediamap.keys]\nVolumeUp = \"Nope\"\n").is_err());
+    }
+
     #[test]
     fn test_keymap_unicode_output_conversion() {
         use crate::mapping::KeymapValue;
@@ -1125,6 +3178,796 @@ mod tests {
         assert_eq!(config.multipurpose[1].condition, Some("wm_class =~ 'Firefox'".to_string()));
     }
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_toggle_setting_gates_entries() {
+        let toml = r#"
+            [[multipurpose]]
+            name = "Caps2Esc"
+            trigger = "capslock"
+            tap = "escape"
+            hold = "right_ctrl"
+            toggle_setting = "Caps2Esc_Cmd"
+            toggle_default = true
+
+            [[keymap]]
+            name = "Terminal"
+            condition = "wm_class =~ 'kitty' or wm_class =~ 'foot'"
+            toggle_setting = "Caps2Esc_Cmd"
+            mappings = { "Super-c" = "Ctrl-Shift-c" }
+
+            [[keymap]]
+            name = "Vim arrows"
+            toggle_setting = "VimArrows"
+            mappings = { "Alt-h" = "Left" }
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.multipurpose[0].condition.as_deref(), Some("settings.Caps2Esc_Cmd"));
+        assert_eq!(
+            config.keymaps[0].condition.as_deref(),
+            Some("settings.Caps2Esc_Cmd and (wm_class =~ 'kitty' or wm_class =~ 'foot')")
+        );
+        assert_eq!(
+            config.toggle_settings,
+            vec![("Caps2Esc_Cmd".to_string(), true), ("VimArrows".to_string(), false)]
+        );
+
+        // The engine falls back to the registered defaults
+        let engine = crate::transform::TransformEngine::with_clock(
+            config.to_transform_config(),
+            crate::settings::Settings::new(),
+            std::sync::Arc::new(crate::ManualClock::new()),
+        );
+        assert!(engine.get_setting("Caps2Esc_Cmd"));
+        assert!(!engine.get_setting("VimArrows"));
+
+        let contradiction = r#"
+            [[keymap]]
+            toggle_setting = "X"
+            toggle_default = true
+            mappings = { "Alt-h" = "Left" }
+            [[keymap]]
+            toggle_setting = "X"
+            toggle_default = false
+            mappings = { "Alt-l" = "Right" }
+        "#;
+        assert!(matches!(Config::from_toml(contradiction), Err(ConfigError::InvalidValue(_))));
+        let bad_name = "[[keymap]]\ntoggle_setting = \"a b\"\nmappings = { \"Alt-h\" = \"Left\" }\n";
+        assert!(Config::from_toml(bad_name).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_config_with_tapdance() {
+        let toml = r#"
+            [[tapdance]]
+            name = "CapsDance"
+            trigger = "capslock"
+            taps = ["escape", "capslock"]
+            hold = "left_ctrl"
+            condition = "wm_class =~ 'kitty'"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.tapdance.len(), 1);
+        assert_eq!(config.tapdance[0].trigger, Key::from(58)); // CAPSLOCK
+        assert_eq!(config.tapdance[0].taps, vec![Key::from(1), Key::from(58)]);
+        assert_eq!(config.tapdance[0].hold, Some(Key::from(29))); // LEFT_CTRL
+
+        let transform = config.to_transform_config();
+        assert_eq!(transform.tapdances.len(), 1);
+        assert_eq!(transform.tapdances[0].conditional(), Some("wm_class =~ 'kitty'"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_tapdance_requires_taps() {
+        let toml = r#"
+            [[tapdance]]
+            name = "Empty"
+            trigger = "capslock"
+            taps = []
+        "#;
+
+        assert!(matches!(Config::from_toml(toml), Err(ConfigError::InvalidKey(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_devices_internal_policy() {
+        let toml = r#"
+            [devices]
+            internal_policy = "lid_closed_or_external"
+            internal = ["AT Translated Set 2 keyboard"]
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.internal_keyboard_policy, InternalKeyboardPolicy::LidClosedOrExternal);
+        assert_eq!(config.internal_keyboards, vec!["AT Translated Set 2 keyboard".to_string()]);
+        assert!(!config.include_gamepads);
+        let gamepads = Config::from_toml("[devices]\ninclude_gamepads = true\n").unwrap();
+        assert!(gamepads.include_gamepads);
+        assert!(!config.mirror_lock_leds);
+        let mirror = Config::from_toml("[devices]\nmirror_lock_leds = true\n").unwrap();
+        assert!(mirror.mirror_lock_leds);
+        assert_eq!(config.release_wait_ms, Some(1000));
+        let release = Config::from_toml("[devices]\nrelease_timeout_ms = 3000\n").unwrap();
+        assert_eq!(release.release_wait_ms, Some(3000));
+        let no_wait = Config::from_toml("[devices]\nwait_for_release = false\n").unwrap();
+        assert_eq!(no_wait.release_wait_ms, None);
+        assert!(Config::from_toml("[devices]\nrelease_timeout_ms = 50\n").is_err());
+        assert_eq!(Config::default().internal_keyboard_policy, InternalKeyboardPolicy::Always);
+
+        let invalid = "[devices]\ninternal_policy = \"sometimes\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_devices_grab_policy() {
+        let toml = r#"
+            [devices]
+            grab = "keys-only"
+            [devices.grab_devices]
+            "AT Translated Set 2 keyboard" = "full"
+            "/dev/input/event9" = "observe"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        let policies = &config.grab_policies;
+        assert_eq!(policies.default, GrabPolicy::KeysOnly);
+        assert_eq!(policies.for_device("AT Translated Set 2 keyboard", "/dev/input/event0"), GrabPolicy::Full);
+        assert_eq!(policies.for_device("Receiver", "/dev/input/event9"), GrabPolicy::Observe);
+        assert_eq!(policies.for_device("Receiver", "/dev/input/event3"), GrabPolicy::KeysOnly);
+        assert_eq!(Config::default().grab_policies.default, GrabPolicy::Full);
+
+        let invalid = "[devices.grab_devices]\n\"Receiver\" = \"mice\"\n";
+        let err = Config::from_toml(invalid).unwrap_err();
+        assert!(err.to_string().contains("devices.grab_devices.\"Receiver\""), "{}", err);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_keymap_repeat_option() {
+        let toml = r#"
+            [[keymap]]
+            name = "nav"
+            [keymap.mappings]
+            "C-j" = { output = "Down", repeat = { delay_ms = 200, interval_ms = 25 } }
+            "C-k" = { output = "Up", repeat = {} }
+            "C-h" = { output = "Left" }
+            "C-l" = "Right"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        let entry = &config.keymaps[0];
+        assert_eq!(entry.mappings.len(), 4);
+        assert_eq!(
+            entry.repeats,
+            vec![
+                ("C-j".to_string(), RepeatRate { delay_ms: 200, interval_ms: 25 }),
+                ("C-k".to_string(), RepeatRate { delay_ms: 250, interval_ms: 30 }),
+            ]
+        );
+
+        let transform = config.to_transform_config();
+        let keymap = &transform.keymaps[0];
+        let combo = |s: &str| {
+            let parsed = crate::config::validate_combo(s).unwrap();
+            Combo::new(parsed.modifiers, parsed.key)
+        };
+        assert_eq!(keymap.repeat(&combo("C-j")).map(|r| r.interval_ms), Some(25));
+        assert_eq!(keymap.repeat(&combo("C-h")), None);
+
+        let too_fast = r#"
+            [[keymap]]
+            name = "nav"
+            [keymap.mappings]
+            "C-j" = { output = "Down", repeat = { interval_ms = 1 } }
+        "#;
+        assert!(matches!(Config::from_toml(too_fast), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_scancodes_table() {
+        let toml = r#"
+            [scancodes]
+            "0x70039" = "ESC"
+            "58" = "LEFT_CTRL"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.scancodes, vec![(58, Key::from(29)), (0x70039, Key::from(1))]);
+
+        let bad_scancode = "[scancodes]\n\"0xnope\" = \"ESC\"\n";
+        assert!(matches!(Config::from_toml(bad_scancode), Err(ConfigError::InvalidValue(_))));
+        let bad_key = "[scancodes]\n\"0x70039\" = \"NOPE\"\n";
+        assert!(matches!(Config::from_toml(bad_key), Err(ConfigError::InvalidKey(_))));
+    }
+
+    #[test]
+    fn test_autoshift_section() {
+        let toml = r#"
+            [autoshift]
+            timeout = 200
+            rows = ["number", "home"]
+            keys = ["TAB"]
+            exclude = ["GRAVE", "APOSTROPHE"]
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.autoshift_timeout, Some(200));
+        assert_eq!(config.autoshift_keys.len(), 12 + 10 + 1);
+        assert!(config.autoshift_keys.contains(&Key::from(15))); // TAB
+        assert!(config.autoshift_keys.contains(&Key::from(30))); // A
+        assert!(!config.autoshift_keys.contains(&Key::from(41))); // GRAVE
+        assert!(!config.autoshift_keys.contains(&Key::from(16))); // Q
+        assert!(config.autoshift_keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(config.to_transform_config().autoshift_keys, config.autoshift_keys);
+
+        let all_rows = Config::from_toml("[autoshift]\n").unwrap();
+        assert_eq!(all_rows.autoshift_keys.len(), 47);
+        assert!(Config::default().autoshift_keys.is_empty());
+
+        let bad_row = "[autoshift]\nrows = [\"middle\"]\n";
+        assert!(matches!(Config::from_toml(bad_row), Err(ConfigError::InvalidValue(_))));
+        let too_short = "[autoshift]\ntimeout = 10\n";
+        assert!(matches!(Config::from_toml(too_short), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_ignore_section() {
+        let toml = r#"
+            [ignore]
+            wm_class = ["virt-manager", "VirtualBox"]
+            wm_name = ["(?i)remote desktop"]
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.ignore_wm_class, vec!["virt-manager".to_string(), "VirtualBox".to_string()]);
+        let transform = config.to_transform_config();
+        assert_eq!(transform.ignore_wm_name, vec!["(?i)remote desktop".to_string()]);
+        assert!(Config::default().ignore_wm_class.is_empty());
+
+        let blank = "[ignore]\nwm_class = [\"^$\"]\n";
+        assert!(matches!(Config::from_toml(blank), Err(ConfigError::InvalidValue(_))));
+        let unknown = "[ignore]\nwm_title = [\"x\"]\n";
+        assert!(Config::from_toml(unknown).is_err());
+    }
+
+    #[test]
+    fn test_aliases_table() {
+        let toml = r#"
+            [aliases]
+            Hyper = "F13"
+            Cmd = "LEFT_META"
+            Launcher = "hyper"
+
+            [modmap.default]
+            CAPSLOCK = "Hyper"
+
+            [[keymap]]
+            name = "mac"
+            [keymap.mappings]
+            "Cmd-c" = "C-c"
+            "C-Launcher" = "Cmd-Space"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.key_aliases.get("LAUNCHER"), Some(&Key::from(183)));
+        assert_eq!(config.modmaps[0].mappings, vec![(Key::from(58), Key::from(183))]);
+
+        let transform = config.to_transform_config();
+        let meta = Modifier::from_key(Key::from(125)).unwrap();
+        let ctrl = Modifier::from_alias("C").unwrap();
+        assert!(transform.keymaps[0].get(&Combo::new(vec![meta], Key::from(46))).is_some());
+        assert!(transform.keymaps[0].get(&Combo::new(vec![ctrl], Key::from(183))).is_some());
+        // Aliases only apply while their config is being parsed
+        assert_eq!(crate::key::key_from_name("Hyper"), None);
+
+        let cycle = "[aliases]\nA1 = \"A2\"\nA2 = \"A3\"\nA3 = \"a1\"\n";
+        let err = Config::from_toml(cycle).unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+        let bad_target = "[aliases]\nHyper = \"F99\"\n";
+        assert!(matches!(Config::from_toml(bad_target), Err(ConfigError::InvalidKey(_))));
+        let shadowing = "[aliases]\nEsc = \"CAPSLOCK\"\n";
+        assert!(matches!(Config::from_toml(shadowing), Err(ConfigError::InvalidValue(_))));
+        let hyphen = "[aliases]\n\"My-Key\" = \"F13\"\n";
+        assert!(matches!(Config::from_toml(hyphen), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn test_vars_table() {
+        let toml = r#"
+            [vars]
+            terminals = "kitty|alacritty|foot"
+            copy = "C-Shift-c"
+            not_terminal = "not wm_class =~ '^(${terminals})$'"
+
+            [[keymap]]
+            name = "terminals"
+            condition = "wm_class =~ '^(${terminals})$'"
+            [keymap.mappings]
+            "Super-c" = "${copy}"
+
+            [[keymap]]
+            name = "general"
+            condition = "${not_terminal}"
+            [keymap.mappings]
+            "Super-c" = "C-c"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.keymaps[0].condition.as_deref(), Some("wm_class =~ '^(kitty|alacritty|foot)$'"));
+        assert_eq!(
+            config.keymaps[1].condition.as_deref(),
+            Some("not wm_class =~ '^(kitty|alacritty|foot)$'")
+        );
+        let transform = config.to_transform_config();
+        let meta = Modifier::from_key(Key::from(125)).unwrap();
+        let output = transform.keymaps[0].get(&Combo::new(vec![meta], Key::from(46)));
+        assert!(matches!(output, Some(KeymapValue::Combo(combo)) if combo.modifiers().len() == 2));
+
+        let undefined = "[vars]\nx = \"a\"\n[[keymap]]\nname = \"k\"\ncondition = \"wm_class =~ '${y}'\"\n";
+        let err = Config::from_toml(undefined).unwrap_err();
+        assert!(matches!(&err, ConfigError::UndefinedVariable(name) if name.contains("keymap[0].condition")), "{}", err);
+    }
+
+    #[test]
+    fn test_named_conditions() {
+        let toml = r#"
+            [conditions]
+            terminals = "wm_class =~ 'kitty|alacritty' or wm_name =~ 'tmux'"
+            not_terminal = "not terminals"
+
+            [[multipurpose]]
+            name = "Enter2Cmd"
+            trigger = "ENTER"
+            tap = "ENTER"
+            hold = "RIGHT_CTRL"
+            condition = "terminals and settings.Enter2Ent_Cmd"
+
+            [[keymap]]
+            name = "general"
+            condition = "not_terminal"
+            [keymap.mappings]
+            "Super-c" = "C-c"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(
+            config.multipurpose[0].condition.as_deref(),
+            Some("(wm_class =~ 'kitty|alacritty' or wm_name =~ 'tmux') and settings.Enter2Ent_Cmd")
+        );
+        assert_eq!(
+            config.keymaps[0].condition.as_deref(),
+            Some("not (wm_class =~ 'kitty|alacritty' or wm_name =~ 'tmux')")
+        );
+
+        let cycle = "[conditions]\na = \"b\"\nb = \"a or capslock\"\n";
+        let err = Config::from_toml(cycle).unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+    }
+
+    #[test]
+    fn test_modtap_table() {
+        let toml = r#"
+            [modtap]
+            LEFT_META = "MENU"
+            RIGHT_ALT = "Ctrl-Alt-t"
+
+            [timeouts]
+            modtap = 300
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.modtap_timeout, Some(300));
+        assert_eq!(config.modtap.len(), 2);
+        assert_eq!(config.modtap[0].0, Key::from(100)); // RIGHT_ALT
+        assert_eq!(config.modtap[0].1.key(), Key::from(20)); // T
+        assert_eq!(config.modtap[0].1.modifiers().len(), 2);
+        assert_eq!(config.modtap[1].0, Key::from(125)); // LEFT_META
+        assert_eq!(config.modtap[1].1, Combo::new(vec![], Key::from(139)));
+        assert_eq!(config.to_transform_config().modtaps, config.modtap);
+
+        let not_modifier = "[modtap]\nCAPSLOCK = \"ESC\"\n";
+        assert!(matches!(Config::from_toml(not_modifier), Err(ConfigError::InvalidValue(_))));
+        let bad_output = "[modtap]\nLEFT_META = \"Nope-z\"\n";
+        assert!(matches!(Config::from_toml(bad_output), Err(ConfigError::InvalidCombo(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_logging_section() {
+        let toml = r#"
+            [logging]
+            level = "info"
+            format = "json"
+
+            [logging.modules]
+            transform = "debug"
+            "keyrs_core::output::uinput" = "trace"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.logging.format, Some(LogFormat::Json));
+        assert_eq!(
+            config.logging.directives(),
+            "info,keyrs_core::output::uinput=trace,keyrs_core::transform=debug"
+        );
+
+        let invalid = "[logging.modules]\ntransform = \"verbose\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+        let invalid = "[logging]\nformat = \"xml\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_metrics_section() {
+        let config = Config::from_toml("[metrics]\nenabled = true\n").unwrap();
+        assert!(config.metrics_enabled);
+        assert_eq!(config.metrics_listen, Some("127.0.0.1:9187".parse().unwrap()));
+
+        let config = Config::from_toml("[metrics]\nenabled = true\nlisten = \"\"\n").unwrap();
+        assert!(config.metrics_enabled);
+        assert!(config.metrics_listen.is_none());
+
+        let config = Config::from_toml("[metrics]\nlisten = \"0.0.0.0:9000\"\n").unwrap();
+        assert!(!config.metrics_enabled);
+        assert!(config.metrics_listen.is_none());
+
+        let invalid = "[metrics]\nenabled = true\nlisten = \"localhost\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_audit_section() {
+        assert_eq!(Config::from_toml("").unwrap().audit_capacity, None);
+        assert_eq!(Config::from_toml("[audit]\nenabled = true\n").unwrap().audit_capacity, Some(500));
+        let config = Config::from_toml("[audit]\nenabled = true\ncapacity = 50\n").unwrap();
+        assert_eq!(config.audit_capacity, Some(50));
+        assert_eq!(Config::from_toml("[audit]\ncapacity = 50\n").unwrap().audit_capacity, None);
+
+        let invalid = "[audit]\nenabled = true\ncapacity = 0\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn test_panic_chord_settings() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.panic_chord, vec![Key::from(29), Key::from(97), Key::from(14)]);
+
+        let toml = "[general]\npanic_chord = [\"LEFT_SHIFT\", \"RIGHT_SHIFT\", \"ESC\"]\npanic_hold_ms = 3000\n";
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.panic_chord, vec![Key::from(42), Key::from(54), Key::from(1)]);
+        assert_eq!(config.panic_hold_ms, Some(3000));
+
+        let config = Config::from_toml("[general]\npanic_chord = []\n").unwrap();
+        assert!(config.panic_chord.is_empty());
+
+        let too_short = "[general]\npanic_hold_ms = 100\n";
+        assert!(matches!(Config::from_toml(too_short), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_session_section() {
+        let config = Config::from_toml("").unwrap();
+        assert!(config.pause_on_lock);
+        assert!(config.lock_poll_ms.is_none());
+
+        let config =
+            Config::from_toml("[session]\npause_on_lock = false\nlock_poll_ms = 500\n").unwrap();
+        assert!(!config.pause_on_lock);
+        assert_eq!(config.lock_poll_ms, Some(500));
+        assert!(config.pause_when_inactive);
+        assert_eq!(config.seat, SeatFilter::Session);
+
+        let config = Config::from_toml("[session]\npause_when_inactive = false\nseat = \"seat1\"\n").unwrap();
+        assert!(!config.pause_when_inactive);
+        assert_eq!(config.seat, SeatFilter::Named("seat1".to_string()));
+
+        let too_fast = "[session]\nlock_poll_ms = 10\n";
+        assert!(matches!(Config::from_toml(too_fast), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_observer_section() {
+        let toml = r#"
+            [observer]
+            enabled = true
+            privacy = "typing"
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert!(config.observer_enabled);
+        assert_eq!(config.observer_privacy, ObserverPrivacy::Typing);
+
+        let disabled = Config::from_toml("[observer]
+privacy = \"off\"\n").unwrap();
+        assert!(!disabled.observer_enabled);
+        assert!(!Config::default().observer_enabled);
+        // Subscriptions go through the control socket; there is no socket of its own
+        assert!(Config::from_toml("[observer]\nenabled = true\nsocket = \"/tmp/osd.sock\"\n").is_err());
+
+        let invalid = "[observer]\nenabled = true\nprivacy = \"everything\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_layers_section() {
+        let toml = r#"
+            [[keymap]]
+            name = "base"
+            [keymap.mappings]
+            "Space" = "Layer(nav)"
+            "F13" = "layer(nav, toggle)"
+            "F14" = "Layer(nav, oneshot)"
+
+            [layers.nav.mappings]
+            "h" = "Left"
+            "Ctrl-h" = "Ctrl-Left"
+
+            [timeouts]
+            layer_rollover = 150
+        "#;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.layers.len(), 1);
+        assert_eq!(config.layers[0].name, "nav");
+        assert_eq!(config.layers[0].mappings.len(), 2);
+
+        let transform = config.to_transform_config();
+        let base = &transform.keymaps[0];
+        let space = Combo::new(vec![], Key::from(57));
+        assert_eq!(
+            base.get(&space),
+            Some(&KeymapValue::Layer(LayerAction {
+                name: "nav".to_string(),
+                mode: LayerMode::Momentary,
+            }))
+        );
+        assert!(matches!(
+            base.get(&Combo::new(vec![], Key::from(183))),
+            Some(KeymapValue::Layer(LayerAction { mode: LayerMode::Toggle, .. }))
+        ));
+        assert!(matches!(
+            base.get(&Combo::new(vec![], Key::from(184))),
+            Some(KeymapValue::Layer(LayerAction { mode: LayerMode::OneShot, .. }))
+        ));
+        assert_eq!(transform.layers[0].name(), "nav");
+        assert_eq!(transform.layer_rollover_timeout, Some(150));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_layer_output_rejects_undefined_layer_and_bad_mode() {
+        let undefined = "[[keymap]]\n[keymap.mappings]\n\"Space\" = \"Layer(missing)\"\n";
+        assert!(matches!(Config::from_toml(undefined), Err(ConfigError::InvalidValue(_))));
+
+        assert!(parse_layer_output("Layer(nav, sometimes)").is_none());
+        assert!(parse_layer_output("Layer()").is_none());
+        assert!(parse_layer_output("Layer(nav, toggle, extra)").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_layout_section() {
+        let config = Config::from_toml("[layout]\nname = \"us\"\n").unwrap();
+        let layout = config.layout.as_ref().unwrap();
+        assert_eq!(layout.key_for_char('a'), Some((Key::from(30), false)));
+
+        let err = Config::from_toml("[layout]\nname = \"no-such-layout\"\n").unwrap_err();
+        assert!(err.to_string().contains("no-such-layout"));
+        // AltGr follows the layout unless set explicitly
+        assert!(!Config::default().altgr);
+        let config = Config::from_toml("[layout]\nname = \"us\"\naltgr = true\n").unwrap();
+        assert!(config.altgr);
+        assert!(config.to_transform_config().altgr);
+        let config = Config::from_toml("[[keymap]]\nname = \"General\"\n[keymap.mappings]\n\"AltGr-e\" = \"Alt-e\"\n").unwrap();
+        let keymap = &config.to_transform_config().keymaps[0];
+        assert!(keymap.get(&Combo::new(vec![Modifier::altgr()], Key::from(18))).is_some());
+        assert!(keymap.get(&Combo::new(vec![Modifier::from_alias("RAlt").unwrap()], Key::from(18))).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_compose_section() {
+        assert!(!Config::default().compose_enabled);
+
+        let config = Config::from_toml("[compose]\nenabled = true\nfile = \"/tmp/XCompose\"\n").unwrap();
+        assert!(config.compose_enabled);
+        assert_eq!(config.compose_file, Some(PathBuf::from("/tmp/XCompose")));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_output_unicode_backend() {
+        assert_eq!(Config::default().unicode_backend, UnicodeBackend::Compose);
+
+        let config = Config::from_toml("[output]\nunicode = \"virtual_keyboard\"\n").unwrap();
+        assert_eq!(config.unicode_backend, UnicodeBackend::VirtualKeyboard);
+
+        let invalid = "[output]\nunicode = \"ibus\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_output_unicode_batch() {
+        let config = Config::default();
+        assert_eq!(config.unicode_batch, UnicodeBatch::Clipboard);
+        assert_eq!(config.paste_combo.to_string(), "Ctrl-V");
+        let config = Config::from_toml("[output]\nunicode_batch = \"off\"\n").unwrap();
+        assert_eq!(config.unicode_batch, UnicodeBatch::Off);
+
+        let config = Config::from_toml(
+            "[output]\nunicode_batch = \"clipboard\"\nunicode_batch_min = 2\npaste = \"Ctrl-Shift-v\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.unicode_batch, UnicodeBatch::Clipboard);
+        assert_eq!(config.unicode_batch_min, 2);
+        assert_eq!(config.paste_combo.modifiers().len(), 2);
+
+        assert!(Config::from_toml("[output]\nunicode_batch = \"ibus\"\n").is_err());
+        assert!(Config::from_toml("[output]\nunicode_batch_min = 0\n").is_err());
+        assert!(Config::from_toml("[output]\npaste = \"Ctrl-Nope\"\n").is_err());
+    }
+
+    #[test]
+    fn test_nested_keymap_timeout() {
+        let config = Config::from_toml(
+            r#"
+[timeouts]
+suspend = 1000
+nested_keymap = 2000
+
+[[keymap]]
+name = "Leader"
+nested_timeout = 5000
+[keymap.mappings]
+"C-Space" = "F13"
+"C-r" = "reset"
+
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"C-x" = "keymap(Leader)"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.suspend_timeout, Some(1000));
+        assert_eq!(config.nested_keymap_timeout, Some(2000));
+        assert_eq!(config.keymaps[0].nested_timeout, Some(5000));
+        assert!(config
+            .keymaps[0]
+            .mappings
+            .iter()
+            .any(|(_, output)| matches!(output, KeymapOutput::ComboHint(ComboHint::Reset))));
+        let transform = config.to_transform_config();
+        assert_eq!(transform.nested_keymap_timeout, Some(2000));
+        assert_eq!(transform.keymaps[0].nested_timeout(), Some(5000));
+
+        assert!(matches!(
+            &config.keymaps[1].mappings[0].1,
+            KeymapOutput::EnterKeymap(name) if name == "Leader"
+        ));
+        assert!(matches!(
+            transform.keymaps[1].mappings().values().next(),
+            Some(KeymapValue::EnterKeymap(name)) if name == "Leader"
+        ));
+        let err = Config::from_toml("[[keymap]]\n[keymap.mappings]\n\"C-x\" = \"keymap(nope)\"\n").unwrap_err();
+        assert!(err.to_string().contains("enters undefined keymap 'nope'"), "{}", err);
+
+        assert!(Config::from_toml("[timeouts]\nnested_keymap = 10\n").is_err());
+        assert!(Config::from_toml("[[keymap]]\nnested_timeout = 99999999\n").is_err());
+    }
+
+    #[test]
+    fn test_keymap_combo_style() {
+        let config = Config::from_toml(
+            r#"
+[delays]
+combo_delay_ms = 2
+
+[[keymap]]
+name = "Remote"
+modifier_order = ["Ctrl", "alt", "shift", "meta"]
+combo_delay_ms = 10
+[keymap.mappings]
+"C-c" = "C-Shift-c"
+
+[[keymap]]
+name = "General"
+[keymap.mappings]
+"C-x" = "C-Shift-x"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.combo_delay_ms, Some(2));
+        let style = config.keymaps[0].combo_style.as_ref().unwrap();
+        assert_eq!(style.modifier_order, vec!["ctrl", "alt", "shift", "meta"]);
+        assert_eq!(style.delay_ms, Some(10));
+        assert!(config.keymaps[1].combo_style.is_none());
+        let transform = config.to_transform_config();
+        assert_eq!(transform.keymaps[0].combo_style(), Some(style));
+
+        let err = Config::from_toml("[[keymap]]\nname = \"X\"\nmodifier_order = [\"ctrl\", \"hyper\"]\n").unwrap_err();
+        assert!(err.to_string().contains("modifier_order in 'X'"), "{}", err);
+        assert!(Config::from_toml("[[keymap]]\nmodifier_order = [\"ctrl\", \"ctrl\"]\n").is_err());
+        assert!(matches!(
+            Config::from_toml("[[keymap]]\ncombo_delay_ms = 500\n"),
+            Err(ConfigError::TimeoutOutOfRange(_))
+        ));
+        assert!(Config::from_toml("[delays]\ncombo_delay_ms = 51\n").is_err());
+    }
+
+    #[test]
+    fn test_stuck_key_timeout() {
+        assert_eq!(Config::default().stuck_key_timeout, None);
+
+        let config = Config::from_toml("[timeouts]\nstuck_key = 60000\n").unwrap();
+        assert_eq!(config.stuck_key_timeout, Some(60_000));
+        let config = Config::from_toml("[timeouts]\nstuck_key = 0\n").unwrap();
+        assert_eq!(config.stuck_key_timeout, None);
+
+        let invalid = "[timeouts]\nstuck_key = 500\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_dead_key_timeout() {
+        assert_eq!(Config::default().to_transform_config().dead_key_timeout, None);
+        let config = Config::from_toml("[timeouts]\ndead_key = 3000\n").unwrap();
+        assert_eq!(config.to_transform_config().dead_key_timeout, Some(3000));
+        let invalid = "[timeouts]\ndead_key = 100\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_delay_overrides() {
+        let config = Config::from_toml(
+            r#"
+[conditions]
+remote = "wm_class =~ 'remmina|xfreerdp'"
+
+[delays]
+key_post_delay_ms = 2
+
+[[delays.overrides]]
+condition = "remote"
+key_post_delay_ms = 20
+combo_delay_ms = 5
+"#,
+        )
+        .unwrap();
+        let transform = config.to_transform_config();
+        assert_eq!(transform.output_delays.key_post_delay_ms, 2);
+        let [remote] = &transform.delay_overrides[..] else { panic!("expected one override") };
+        assert_eq!(remote.condition.source(), "wm_class =~ 'remmina|xfreerdp'");
+        assert!(remote.condition.expr().is_some());
+        assert_eq!(remote.apply(transform.output_delays).combo_delay_ms, 5);
+
+        let too_slow = "[[delays.overrides]]\ncondition = \"wm_class =~ 'x'\"\nkey_post_delay_ms = 999\n";
+        assert!(matches!(Config::from_toml(too_slow), Err(ConfigError::TimeoutOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_output_remap_repeat() {
+        assert_eq!(Config::default().remap_repeat, RemapRepeat::Forward);
+
+        let config = Config::from_toml("[output]\nremap_repeat = \"drop\"\n").unwrap();
+        assert_eq!(config.remap_repeat, RemapRepeat::Drop);
+
+        let invalid = "[output]\nremap_repeat = \"sometimes\"\n";
+        assert!(matches!(Config::from_toml(invalid), Err(ConfigError::InvalidValue(_))));
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_config_full_with_multipurpose() {
@@ -1186,6 +4029,27 @@ mod tests {
         assert_eq!(config.idle_sleep_ms, Some(7));
     }
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_poll_interval_follows_update_interval() {
+        let config = Config::from_toml("[window]\nupdate_interval_ms = 450\n").unwrap();
+        assert!(!config.window_title_sensitive);
+        assert_eq!(config.window_poll_interval_ms(), 450);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_title_sensitive() {
+        assert_eq!(Config::default().window_poll_interval_ms(), 500);
+
+        let config = Config::from_toml("[window]\ntitle_sensitive = true\n").unwrap();
+        assert!(config.window_title_sensitive);
+        assert_eq!(config.window_poll_interval_ms(), 100);
+
+        let config = Config::from_toml("[window]\ntitle_sensitive = true\nupdate_interval_ms = 50\n").unwrap();
+        assert_eq!(config.window_poll_interval_ms(), 50);
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_multipurpose_invalid_key() {
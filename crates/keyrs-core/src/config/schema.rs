@@ -0,0 +1,55 @@
+// Keyrs Config Schema
+// JSON Schema of the TOML config format, for editor completion and validation
+
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+
+use super::parser::ConfigToml;
+
+/// Schema of `config.toml` and its fragments, generated from the parser's
+/// serde structs (draft-07, as taplo / Even Better TOML read it)
+pub fn config_schema() -> RootSchema {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            // TOML has no null: a field is either set or left out
+            settings.option_nullable = false;
+            settings.option_add_null_type = false;
+        })
+        .into_generator();
+    let mut schema = generator.into_root_schema_for::<ConfigToml>();
+    schema.schema.metadata().title = Some("keyrs config".to_string());
+    schema
+}
+
+/// `config_schema` as pretty-printed JSON
+pub fn config_schema_json() -> String {
+    serde_json::to_string_pretty(&config_schema()).expect("schema serializes to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_config_schema_describes_sections() {
+        let schema: Value = serde_json::from_str(&config_schema_json()).unwrap();
+        assert_eq!(schema["title"], "keyrs config");
+        assert_eq!(schema["additionalProperties"], false);
+        for section in ["include", "general", "modmap", "keymap", "layers", "devices", "delays", "logging"] {
+            assert!(schema["properties"][section].is_object(), "missing {}", section);
+        }
+
+        // Optional fields are plain types, not `null` unions
+        let general = &schema["definitions"]["GeneralConfig"];
+        assert_eq!(general["properties"]["panic_hold_ms"]["type"], "integer");
+        assert_eq!(general["additionalProperties"], false);
+
+        // Keymap outputs accept a string, a list, or the detailed table
+        let output = &schema["definitions"]["KeymapTomlOutput"];
+        assert_eq!(output["anyOf"].as_array().map(Vec::len), Some(3));
+        assert!(schema["definitions"]["KeymapTomlEntry"]["properties"]["mappings"]["description"]
+            .as_str()
+            .is_some_and(|text| text.contains("Combo-to-output")));
+    }
+}
@@ -0,0 +1,911 @@
+// Keyrs Toshy/Kinto Importer
+// Converts the xwaykeyz/Toshy Python config DSL to keyrs TOML
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use regex::Regex;
+
+/// Something in the Python config that could not be converted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportWarning {
+    /// 1-based line in the Python source
+    pub line: usize,
+    pub message: String,
+}
+
+/// Result of importing a Toshy/Kinto config
+#[derive(Debug, Clone)]
+pub struct ToshyImport {
+    /// Generated keyrs TOML
+    pub toml: String,
+    /// Constructs that were skipped or imported disabled
+    pub warnings: Vec<ImportWarning>,
+}
+
+/// Convert a Toshy/Kinto/xwaykeyz Python config into keyrs TOML.
+///
+/// Handles `modmap`, `multipurpose_modmap` and `keymap` calls built from
+/// `C()`/`K()`/`Key.*`, `ST()`, `UC()`, `sleep()`, `bind` and `ignore_key`,
+/// with `when=matchProps(...)` or `lambda ctx: ctx.wm_class... in <list>`
+/// conditions. Anything else is left out and reported; keymaps whose
+/// condition cannot be translated are imported with `enabled = false`.
+pub fn import_toshy(source: &str) -> ToshyImport {
+    let tokens = tokenize(source);
+    let mut parser = Parser {
+        source,
+        tokens: &tokens,
+        pos: 0,
+    };
+    let mut importer = Importer::default();
+
+    while let Some(tok) = parser.peek() {
+        let at_statement_start =
+            parser.pos == 0 || matches!(tokens[parser.pos - 1].token, Token::Newline);
+        match &tok.token {
+            Token::Ident(name) if at_statement_start && parser.peek_punct_at(1, '=') => {
+                let name = name.clone();
+                parser.pos += 2;
+                let value = parser.parse_expr();
+                importer.vars.insert(name, value);
+            }
+            Token::Ident(name)
+                if matches!(name.as_str(), "keymap" | "modmap" | "multipurpose_modmap")
+                    && parser.peek_punct_at(1, '(') =>
+            {
+                let line = tok.line;
+                if let Expr::Call { func, args, kwargs } = parser.parse_expr() {
+                    importer.import_call(&func, &args, &kwargs, line);
+                }
+            }
+            _ => parser.pos += 1,
+        }
+    }
+
+    importer.finish()
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Punct(char),
+    Op(String),
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+struct Tok {
+    token: Token,
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Tok> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        let start = i;
+        match c {
+            '\n' => {
+                if depth == 0 && !matches!(tokens.last(), Some(Tok { token: Token::Newline, .. })) {
+                    tokens.push(Tok { token: Token::Newline, line, start, end: i + 1 });
+                }
+                line += 1;
+                i += 1;
+            }
+            '\\' if bytes.get(i + 1) == Some(&b'\n') => {
+                line += 1;
+                i += 2;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            '#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            '\'' | '"' => {
+                let (value, end, lines) = read_string(source, i, false);
+                tokens.push(Tok { token: Token::Str(value), line, start, end });
+                line += lines;
+                i = end;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() => {
+                while i < bytes.len() {
+                    let b = bytes[i];
+                    let dotted = b == b'.'
+                        && bytes.get(i + 1).is_some_and(|n| n.is_ascii_alphabetic() || *n == b'_');
+                    if b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80 || dotted {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let word = &source[start..i];
+                let is_prefix = word.len() <= 2 && word.chars().all(|c| "rRbBuUfF".contains(c));
+                if is_prefix && matches!(bytes.get(i), Some(b'\'' | b'"')) {
+                    let raw = word.contains(['r', 'R']);
+                    let (value, end, lines) = read_string(source, i, raw);
+                    tokens.push(Tok { token: Token::Str(value), line, start, end });
+                    line += lines;
+                    i = end;
+                } else {
+                    tokens.push(Tok { token: Token::Ident(word.to_string()), line, start, end: i });
+                }
+            }
+            c if c.is_ascii_digit() => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.' || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push(Tok { token: Token::Num(source[start..i].to_string()), line, start, end: i });
+            }
+            '(' | '[' | '{' | ')' | ']' | '}' | ',' | ':' => {
+                match c {
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                tokens.push(Tok { token: Token::Punct(c), line, start, end: i + 1 });
+                i += 1;
+            }
+            '=' if bytes.get(i + 1) != Some(&b'=') => {
+                tokens.push(Tok { token: Token::Punct('='), line, start, end: i + 1 });
+                i += 1;
+            }
+            _ => {
+                i += 1;
+                while i < bytes.len() && b"=<>!*&|+-/%^~@".contains(&bytes[i]) {
+                    i += 1;
+                }
+                tokens.push(Tok { token: Token::Op(source[start..i].to_string()), line, start, end: i });
+            }
+        }
+    }
+    tokens
+}
+
+/// Read a Python string literal starting at `start`; returns (value, end, newlines)
+fn read_string(source: &str, start: usize, raw: bool) -> (String, usize, usize) {
+    let rest = &source[start..];
+    let quote = &rest[..1];
+    let triple = rest.starts_with(&quote.repeat(3));
+    let delimiter = if triple { quote.repeat(3) } else { quote.to_string() };
+
+    let mut value = String::new();
+    let mut chars = rest[delimiter.len()..].char_indices();
+    while let Some((offset, c)) = chars.next() {
+        let remaining = &rest[delimiter.len() + offset..];
+        if remaining.starts_with(&delimiter) {
+            let end = start + delimiter.len() + offset + delimiter.len();
+            return (value, end, source[start..end].matches('\n').count());
+        }
+        if c == '\\' && !raw {
+            match chars.next().map(|(_, e)| e) {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('\n') => {}
+                Some(other) => value.push(other),
+                None => {}
+            }
+        } else if c == '\\' {
+            value.push(c);
+            if let Some((_, e)) = chars.next() {
+                value.push(e);
+            }
+        } else if c == '\n' && !triple {
+            break;
+        } else {
+            value.push(c);
+        }
+    }
+    (value, source.len(), source[start..].matches('\n').count())
+}
+
+// ---------------------------------------------------------------------------
+// Expression parser
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Str(String),
+    Num(String),
+    Name(String),
+    Call {
+        func: String,
+        args: Vec<Expr>,
+        kwargs: Vec<(String, Expr)>,
+    },
+    List(Vec<Expr>),
+    /// Dict entries with the source line of each key
+    Dict(Vec<(Expr, Expr, usize)>),
+    /// Anything else, as Python source text
+    Other(String),
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [Tok],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct_at(&self, offset: usize, punct: char) -> bool {
+        matches!(self.tokens.get(self.pos + offset), Some(Tok { token: Token::Punct(p), .. }) if *p == punct)
+    }
+
+    fn eat_punct(&mut self, punct: char) -> bool {
+        if self.peek_punct_at(0, punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_delimiter(&self) -> bool {
+        match self.peek().map(|t| &t.token) {
+            None | Some(Token::Newline) => true,
+            Some(Token::Punct(p)) => matches!(p, ',' | ')' | ']' | '}' | ':' | '='),
+            _ => false,
+        }
+    }
+
+    /// Skip to the next delimiter at the current nesting level
+    fn skip_expression(&mut self) {
+        let mut depth = 0usize;
+        while let Some(tok) = self.peek() {
+            match tok.token {
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']' | '}') if depth == 0 => return,
+                Token::Punct(')' | ']' | '}') => depth -= 1,
+                Token::Punct(',' | ':') | Token::Newline if depth == 0 => return,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn text_since(&self, start: usize) -> String {
+        match (self.tokens.get(start), self.tokens.get(self.pos.saturating_sub(1))) {
+            (Some(first), Some(last)) if self.pos > start => {
+                self.source[first.start..last.end].split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Expr {
+        let start = self.pos;
+        let expr = self.parse_primary();
+        if self.at_delimiter() {
+            return expr;
+        }
+        // Operators, comparisons and the like are kept as source text
+        self.skip_expression();
+        Expr::Other(self.text_since(start))
+    }
+
+    fn parse_primary(&mut self) -> Expr {
+        let start = self.pos;
+        let Some(tok) = self.peek().cloned() else {
+            return Expr::Other(String::new());
+        };
+        match tok.token {
+            Token::Str(mut value) => {
+                self.pos += 1;
+                while let Some(Tok { token: Token::Str(next), .. }) = self.peek() {
+                    value.push_str(next);
+                    self.pos += 1;
+                }
+                Expr::Str(value)
+            }
+            Token::Num(value) => {
+                self.pos += 1;
+                Expr::Num(value)
+            }
+            Token::Ident(name) if name == "lambda" || name == "not" => {
+                self.skip_expression_including_colons();
+                Expr::Other(self.text_since(start))
+            }
+            Token::Ident(name) => {
+                self.pos += 1;
+                if !self.eat_punct('(') {
+                    return Expr::Name(name);
+                }
+                let mut args = Vec::new();
+                let mut kwargs = Vec::new();
+                while !self.eat_punct(')') {
+                    if self.peek().is_none() {
+                        break;
+                    }
+                    match self.peek().map(|t| &t.token) {
+                        Some(Token::Ident(key)) if self.peek_punct_at(1, '=') => {
+                            let key = key.clone();
+                            self.pos += 2;
+                            kwargs.push((key, self.parse_expr()));
+                        }
+                        _ => args.push(self.parse_expr()),
+                    }
+                    if !self.eat_punct(',') && !self.peek_punct_at(0, ')') {
+                        self.skip_expression();
+                        if !self.eat_punct(',') && !self.peek_punct_at(0, ')') {
+                            self.pos += 1;
+                        }
+                    }
+                }
+                Expr::Call { func: name, args, kwargs }
+            }
+            Token::Punct(open @ ('[' | '(')) => {
+                self.pos += 1;
+                let close = if open == '[' { ']' } else { ')' };
+                let mut items = Vec::new();
+                while !self.eat_punct(close) {
+                    if self.peek().is_none() {
+                        break;
+                    }
+                    items.push(self.parse_expr());
+                    if !self.eat_punct(',') && !self.peek_punct_at(0, close) {
+                        self.skip_expression();
+                        self.pos += 1;
+                    }
+                }
+                Expr::List(items)
+            }
+            Token::Punct('{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                while !self.eat_punct('}') {
+                    let Some(line) = self.peek().map(|t| t.line) else {
+                        break;
+                    };
+                    let key = self.parse_expr();
+                    let value = if self.eat_punct(':') {
+                        self.parse_expr()
+                    } else {
+                        Expr::Other(String::new())
+                    };
+                    entries.push((key, value, line));
+                    if !self.eat_punct(',') && !self.peek_punct_at(0, '}') {
+                        self.skip_expression();
+                        self.pos += 1;
+                    }
+                }
+                Expr::Dict(entries)
+            }
+            _ => {
+                self.pos += 1;
+                self.skip_expression();
+                Expr::Other(self.text_since(start))
+            }
+        }
+    }
+
+    /// Like `skip_expression`, but a lambda's own `:` does not end it
+    fn skip_expression_including_colons(&mut self) {
+        loop {
+            self.skip_expression();
+            if !self.peek_punct_at(0, ':') {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Conversion
+
+#[derive(Default)]
+struct Importer {
+    vars: HashMap<String, Expr>,
+    default_modmap: Vec<(String, String)>,
+    sections: Vec<String>,
+    warnings: Vec<ImportWarning>,
+}
+
+enum Condition {
+    Always,
+    When(String),
+    Unsupported(String),
+}
+
+impl Importer {
+    fn warn(&mut self, line: usize, message: String) {
+        self.warnings.push(ImportWarning { line, message });
+    }
+
+    fn resolve<'e>(&'e self, expr: &'e Expr) -> &'e Expr {
+        let mut current = expr;
+        for _ in 0..8 {
+            match current {
+                Expr::Name(name) => match self.vars.get(name) {
+                    Some(value) => current = value,
+                    None => return current,
+                },
+                _ => return current,
+            }
+        }
+        current
+    }
+
+    fn string_value(&self, expr: &Expr) -> Option<String> {
+        match self.resolve(expr) {
+            Expr::Str(s) => Some(s.clone()),
+            Expr::Call { func, args, .. } if func == "toRgxStr" && args.len() == 1 => {
+                self.pattern_from_list(&args[0])
+            }
+            _ => None,
+        }
+    }
+
+    /// `^a$|^b$` from a list of class names or patterns
+    fn pattern_from_list(&self, expr: &Expr) -> Option<String> {
+        let Expr::List(items) = self.resolve(expr) else {
+            return None;
+        };
+        let patterns: Option<Vec<String>> = items
+            .iter()
+            .map(|item| {
+                self.string_value(item).map(|s| {
+                    if s.starts_with('^') || s.ends_with('$') {
+                        s
+                    } else {
+                        format!("^{}$", s)
+                    }
+                })
+            })
+            .collect();
+        patterns.map(|p| p.join("|"))
+    }
+
+    fn condition(&self, kwargs: &[(String, Expr)]) -> Condition {
+        let Some((_, when)) = kwargs.iter().find(|(k, _)| k == "when") else {
+            return Condition::Always;
+        };
+        let when = self.resolve(when);
+        match when {
+            Expr::Call { func, args, kwargs } if func == "matchProps" && args.is_empty() => {
+                self.match_props(kwargs)
+            }
+            Expr::Other(text) => self.lambda_condition(text),
+            Expr::Name(name) if name == "None" => Condition::Always,
+            other => Condition::Unsupported(describe(other)),
+        }
+    }
+
+    fn match_props(&self, kwargs: &[(String, Expr)]) -> Condition {
+        let mut parts = Vec::new();
+        for (key, value) in kwargs {
+            let (negate, field) = match key.strip_prefix("not_") {
+                Some(field) => (true, field),
+                None => (false, key.as_str()),
+            };
+            let part = match field {
+                "clas" | "name" | "devn" => {
+                    let Some(pattern) = self.string_value(value) else {
+                        return Condition::Unsupported(format!("matchProps({}=...)", key));
+                    };
+                    let field = match field {
+                        "clas" => "wm_class",
+                        "name" => "wm_name",
+                        _ => "device_name",
+                    };
+                    format!("{} =~ {}", field, quote_condition_value(&pattern))
+                }
+                "numlk" | "capslk" => {
+                    let field = if field == "numlk" { "numlock" } else { "capslock" };
+                    match self.resolve(value) {
+                        Expr::Name(b) if b == "True" => field.to_string(),
+                        Expr::Name(b) if b == "False" => format!("not {}", field),
+                        _ => return Condition::Unsupported(format!("matchProps({}=...)", key)),
+                    }
+                }
+                // keyrs conditions are always case-insensitive
+                "casein" | "dbg" => continue,
+                _ => return Condition::Unsupported(format!("matchProps({}=...)", key)),
+            };
+            parts.push(if negate { format!("not {}", part) } else { part });
+        }
+        if parts.is_empty() {
+            Condition::Always
+        } else {
+            Condition::When(parts.join(" and "))
+        }
+    }
+
+    /// `lambda ctx: ctx.wm_class[.casefold()] [not] in <list>`
+    fn lambda_condition(&self, text: &str) -> Condition {
+        let pattern = Regex::new(
+            r"^lambda\s+(\w+)\s*:\s*(\w+)\.(wm_class|wm_name)(?:\.casefold\(\)|\.lower\(\))?\s+(not\s+)?in\s+(\w+)$",
+        )
+        .expect("valid lambda pattern");
+        let Some(caps) = pattern.captures(text.trim()) else {
+            return Condition::Unsupported(text.to_string());
+        };
+        if caps[1] != caps[2] {
+            return Condition::Unsupported(text.to_string());
+        }
+        let Some(values) = self.pattern_from_list(&Expr::Name(caps[5].to_string())) else {
+            return Condition::Unsupported(text.to_string());
+        };
+        let condition = format!("{} =~ {}", &caps[3], quote_condition_value(&values));
+        if caps.get(4).is_some() {
+            Condition::When(format!("not {}", condition))
+        } else {
+            Condition::When(condition)
+        }
+    }
+
+    fn key_name(&self, expr: &Expr) -> Option<String> {
+        match self.resolve(expr) {
+            Expr::Name(name) => name.strip_prefix("Key.").map(str::to_string),
+            _ => None,
+        }
+    }
+
+    /// Input or output combo (`C("RC-q")`, `K(...)`, `Key.X`)
+    fn combo(&self, expr: &Expr) -> Option<String> {
+        match self.resolve(expr) {
+            Expr::Call { func, args, .. } if (func == "C" || func == "K") && args.len() == 1 => {
+                self.string_value(&args[0]).map(|s| translate_combo(&s))
+            }
+            other => self.key_name(other),
+        }
+    }
+
+    /// One step of a keymap output sequence
+    fn step(&self, expr: &Expr) -> Option<String> {
+        if let Some(combo) = self.combo(expr) {
+            return Some(combo);
+        }
+        match self.resolve(expr) {
+            Expr::Name(name) if name == "bind" => Some("Bind".to_string()),
+            Expr::Name(name) if name == "ignore_key" => Some("Ignore".to_string()),
+            Expr::Call { func, args, .. } if func == "ST" && args.len() == 1 => {
+                self.string_value(&args[0]).map(|s| format!("Text({})", s))
+            }
+            Expr::Call { func, args, .. } if func == "UC" && args.len() == 1 => {
+                let codepoint = parse_python_int(self.resolve(&args[0]))?;
+                char::from_u32(codepoint).map(|c| format!("Text({})", c))
+            }
+            Expr::Call { func, args, .. } if func == "sleep" && args.len() == 1 => {
+                let Expr::Num(seconds) = self.resolve(&args[0]) else {
+                    return None;
+                };
+                let seconds: f64 = seconds.parse().ok()?;
+                Some(format!("Delay({})", (seconds * 1000.0).round() as u64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Keymap output as a TOML value
+    fn output(&self, expr: &Expr) -> Option<String> {
+        match self.resolve(expr) {
+            Expr::List(items) => {
+                let steps: Option<Vec<String>> = items.iter().map(|item| self.step(item)).collect();
+                let steps = steps?;
+                Some(format!(
+                    "[{}]",
+                    steps.iter().map(|s| toml_str(s)).collect::<Vec<_>>().join(", ")
+                ))
+            }
+            Expr::Call { func, args, .. } if func == "UC" && args.len() == 1 => {
+                let codepoint = parse_python_int(self.resolve(&args[0]))?;
+                Some(toml_str(&format!("Unicode({:04X})", codepoint)))
+            }
+            Expr::Name(name) if name == "ignore_key" => Some(toml_str("Ignore")).map(|s| format!("[{}]", s)),
+            other => {
+                let step = self.step(other)?;
+                if step.starts_with("Delay(") || step == "Bind" {
+                    Some(format!("[{}]", toml_str(&step)))
+                } else {
+                    Some(toml_str(&step))
+                }
+            }
+        }
+    }
+
+    fn import_call(&mut self, func: &str, args: &[Expr], kwargs: &[(String, Expr)], line: usize) {
+        let name = args
+            .first()
+            .and_then(|a| self.string_value(a))
+            .unwrap_or_else(|| format!("{} (line {})", func, line));
+        let Some(Expr::Dict(entries)) = args.get(1).map(|a| self.resolve(a).clone()) else {
+            self.warn(line, format!("{} '{}': mappings are not a dict literal", func, name));
+            return;
+        };
+        let condition = self.condition(kwargs);
+
+        match func {
+            "modmap" => self.import_modmap(&name, &entries, condition, line),
+            "multipurpose_modmap" => self.import_multipurpose(&name, &entries, condition, line),
+            _ => self.import_keymap(&name, &entries, condition, line),
+        }
+    }
+
+    fn import_modmap(&mut self, name: &str, entries: &[(Expr, Expr, usize)], condition: Condition, line: usize) {
+        let mut mappings = Vec::new();
+        for (from, to, entry_line) in entries {
+            match (self.key_name(from), self.key_name(to)) {
+                (Some(from), Some(to)) => mappings.push((from, to)),
+                _ => self.warn(*entry_line, format!("modmap '{}': skipped {}: {}", name, describe(from), describe(to))),
+            }
+        }
+
+        match condition {
+            Condition::Always => self.default_modmap.extend(mappings),
+            Condition::When(condition) => {
+                let mut section = String::from("[[modmap.conditionals]]\n");
+                let _ = writeln!(section, "name = {}", toml_str(name));
+                let _ = writeln!(section, "condition = {}", toml_str(&condition));
+                section.push_str("[modmap.conditionals.mappings]\n");
+                for (from, to) in mappings {
+                    let _ = writeln!(section, "{} = {}", from, toml_str(&to));
+                }
+                self.sections.push(section);
+            }
+            Condition::Unsupported(when) => {
+                self.warn(line, format!("modmap '{}' skipped: unsupported condition `{}`", name, when));
+            }
+        }
+    }
+
+    fn import_multipurpose(&mut self, name: &str, entries: &[(Expr, Expr, usize)], condition: Condition, line: usize) {
+        let condition = match condition {
+            Condition::Always => None,
+            Condition::When(condition) => Some(condition),
+            Condition::Unsupported(when) => {
+                self.warn(line, format!("multipurpose_modmap '{}' skipped: unsupported condition `{}`", name, when));
+                return;
+            }
+        };
+        for (index, (trigger, outputs, entry_line)) in entries.iter().enumerate() {
+            let keys = match self.resolve(outputs) {
+                Expr::List(items) if items.len() == 2 => {
+                    (self.key_name(trigger), self.key_name(&items[0]), self.key_name(&items[1]))
+                }
+                _ => (None, None, None),
+            };
+            let (Some(trigger), Some(tap), Some(hold)) = keys else {
+                self.warn(*entry_line, format!("multipurpose_modmap '{}': skipped {}", name, describe(trigger)));
+                continue;
+            };
+            let entry_name = if entries.len() == 1 { name.to_string() } else { format!("{} {}", name, index + 1) };
+            let mut section = String::from("[[multipurpose]]\n");
+            let _ = writeln!(section, "name = {}", toml_str(&entry_name));
+            let _ = writeln!(section, "trigger = {}", toml_str(&trigger));
+            let _ = writeln!(section, "tap = {}", toml_str(&tap));
+            let _ = writeln!(section, "hold = {}", toml_str(&hold));
+            if let Some(condition) = &condition {
+                let _ = writeln!(section, "condition = {}", toml_str(condition));
+            }
+            self.sections.push(section);
+        }
+    }
+
+    fn import_keymap(&mut self, name: &str, entries: &[(Expr, Expr, usize)], condition: Condition, line: usize) {
+        let mut section = String::from("[[keymap]]\n");
+        let _ = writeln!(section, "name = {}", toml_str(name));
+        match condition {
+            Condition::Always => {}
+            Condition::When(condition) => {
+                let _ = writeln!(section, "condition = {}", toml_str(&condition));
+            }
+            Condition::Unsupported(when) => {
+                self.warn(line, format!("keymap '{}' imported disabled: unsupported condition `{}`", name, when));
+                let _ = writeln!(section, "# keyrs-import: when = {}", when);
+                section.push_str("enabled = false\n");
+            }
+        }
+        section.push_str("[keymap.mappings]\n");
+
+        for (input, output, entry_line) in entries {
+            match (self.combo(input), self.output(output)) {
+                (Some(input), Some(output)) => {
+                    let _ = writeln!(section, "{} = {}", toml_str(&input), output);
+                }
+                _ => {
+                    let skipped = format!("{}: {}", describe(input), describe(output));
+                    self.warn(*entry_line, format!("keymap '{}': skipped {}", name, skipped));
+                    let _ = writeln!(section, "# keyrs-import: skipped {}", skipped);
+                }
+            }
+        }
+        self.sections.push(section);
+    }
+
+    fn finish(self) -> ToshyImport {
+        let mut toml = String::from("# Imported from a Toshy/Kinto config by keyrs --import-toshy\n");
+        if !self.warnings.is_empty() {
+            let _ = writeln!(
+                toml,
+                "# {} construct(s) could not be converted; search for \"keyrs-import\" and review.",
+                self.warnings.len()
+            );
+        }
+        if !self.default_modmap.is_empty() {
+            toml.push_str("\n[modmap.default]\n");
+            for (from, to) in &self.default_modmap {
+                let _ = writeln!(toml, "{} = {}", from, toml_str(to));
+            }
+        }
+        for section in &self.sections {
+            toml.push('\n');
+            toml.push_str(section);
+        }
+        ToshyImport {
+            toml,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// xwaykeyz modifier prefixes that keyrs spells differently
+fn translate_combo(combo: &str) -> String {
+    let parts: Vec<&str> = combo.split('-').collect();
+    if parts.len() < 2 {
+        return combo.to_string();
+    }
+    let (key, modifiers) = parts.split_last().expect("at least two parts");
+    let mut translated: Vec<&str> = modifiers
+        .iter()
+        .map(|m| match *m {
+            "M" => "Alt",
+            "RM" => "RAlt",
+            "LM" => "LAlt",
+            other => other,
+        })
+        .collect();
+    translated.push(key);
+    translated.join("-")
+}
+
+fn parse_python_int(expr: &Expr) -> Option<u32> {
+    let Expr::Num(text) = expr else {
+        return None;
+    };
+    let text = text.replace('_', "");
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn quote_condition_value(value: &str) -> String {
+    if value.contains('\'') {
+        format!("\"{}\"", value)
+    } else {
+        format!("'{}'", value)
+    }
+}
+
+/// TOML basic string
+fn toml_str(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Short Python-like rendering of an expression for warnings
+fn describe(expr: &Expr) -> String {
+    match expr {
+        Expr::Str(s) => format!("{:?}", s),
+        Expr::Num(n) | Expr::Name(n) | Expr::Other(n) => n.clone(),
+        Expr::Call { func, args, .. } => {
+            format!("{}({})", func, args.iter().map(describe).collect::<Vec<_>>().join(", "))
+        }
+        Expr::List(items) => format!("[{}]", items.iter().map(describe).collect::<Vec<_>>().join(", ")),
+        Expr::Dict(_) => "{...}".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOSHY_SAMPLE: &str = r#"
+terminals = [
+    "alacritty",
+    'kitty',   # comment
+]
+termStr = toRgxStr(terminals)
+
+modmap("Cond modmap - GUI", {
+    Key.CAPSLOCK: Key.ESC,
+}, when = lambda ctx: ctx.wm_class.casefold() not in terminals)
+
+modmap("General", {Key.LEFT_META: Key.LEFT_CTRL})
+
+multipurpose_modmap("Enter2Cmd", {
+    Key.ENTER: [Key.ENTER, Key.RIGHT_CTRL]
+}, when = lambda ctx: cnfg.Enter2Ent_Cmd)
+
+keymap("Terminals", {
+    C("RC-c"): C("C-Shift-c"),
+    C("RC-Dot"): [C("C-c"), sleep(0.05), ST("done\n")],
+    C("M-f"): UC(0x00B0),
+    C("RC-q"): {C("a"): C("b")},
+}, when = matchProps(clas=termStr))
+
+keymap("Special", {
+    C("RC-Tab"): [bind, C("Alt-Tab")],
+    Key.F13: ignore_key,
+}, when = lambda ctx: ctx.wm_class == "x" and cnfg.foo)
+"#;
+
+    #[test]
+    fn test_import_converts_supported_constructs() {
+        let import = import_toshy(TOSHY_SAMPLE);
+        let toml = &import.toml;
+
+        assert!(toml.contains("[modmap.default]\nLEFT_META = \"LEFT_CTRL\"\n"));
+        assert!(toml.contains(
+            "name = \"Cond modmap - GUI\"\ncondition = \"not wm_class =~ '^alacritty$|^kitty$'\""
+        ));
+        assert!(toml.contains("condition = \"wm_class =~ '^alacritty$|^kitty$'\""));
+        assert!(toml.contains("\"RC-c\" = \"C-Shift-c\""));
+        assert!(toml.contains("\"RC-Dot\" = [\"C-c\", \"Delay(50)\", \"Text(done\\n)\"]"));
+        assert!(toml.contains("\"Alt-f\" = \"Unicode(00B0)\""));
+        assert!(toml.contains("\"RC-Tab\" = [\"Bind\", \"Alt-Tab\"]"));
+        assert!(toml.contains("\"F13\" = [\"Ignore\"]"));
+        assert!(toml.contains("# keyrs-import: skipped C(\"RC-q\"): {...}"));
+        assert!(toml.contains("enabled = false"));
+    }
+
+    #[test]
+    fn test_import_reports_unsupported_constructs_with_lines() {
+        let import = import_toshy(TOSHY_SAMPLE);
+        let lines: Vec<usize> = import.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, vec![14, 22, 25]);
+        assert!(import.warnings[0].message.contains("Enter2Cmd"));
+        assert!(import.warnings[2].message.contains("imported disabled"));
+    }
+
+    #[test]
+    fn test_imported_config_parses() {
+        let import = import_toshy(TOSHY_SAMPLE);
+        let config = super::super::Config::from_toml(&import.toml).unwrap();
+        assert_eq!(config.keymaps.len(), 1);
+        assert_eq!(config.modmaps.len(), 2);
+        assert!(super::super::validate_str(&import.toml).is_empty());
+    }
+
+    #[test]
+    fn test_translate_combo_and_match_props() {
+        assert_eq!(translate_combo("M-Shift-x"), "Alt-Shift-x");
+        assert_eq!(translate_combo("Enter"), "Enter");
+
+        let import = import_toshy(
+            "keymap('k', {C('C-a'): C('Home')}, when=matchProps(not_clas='^code$', numlk=True))",
+        );
+        assert!(import.warnings.is_empty());
+        assert!(import.toml.contains("condition = \"not wm_class =~ '^code$' and numlock\""));
+    }
+}
@@ -0,0 +1,983 @@
+// Keyrs Config Validation
+// Strict pass reporting every invalid value with its TOML line and column
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use toml_edit::{ImDocument, InlineTable, Item, TableLike, Value};
+
+use super::compose::{expand_include_pattern, load_with_includes, IncludeStack};
+use super::migrate::upgrade_for_load;
+use super::parser::{
+    autoshift_row, check_timing, ignore_pattern_error, parse_key, resolve_alias, parse_keymap_output, parse_modtap_output, parse_modtap_trigger,
+    parse_scancode_entry,
+    Config, ConfigError, ConfigToml, KeymapTomlOutput, RepeatToml, TIMING_LIMITS,
+};
+use super::vars::{expand_vars, resolve_var, VARS_KEY};
+use crate::layout::KeyboardLayout;
+use crate::condition::{expand_named_conditions, resolve_named_condition, validate_condition, ConditionExpr};
+use crate::{Key, KeyNames};
+
+/// One problem found in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// File the problem is in (None for in-memory sources)
+    pub file: Option<PathBuf>,
+    /// 1-based line, or 0 when the problem has no single location
+    pub line: usize,
+    /// 1-based column in characters
+    pub column: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        if self.line > 0 {
+            write!(f, "{}:{}:", self.line, self.column)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+/// Validate a config source, returning every problem found
+pub fn validate_str(source: &str) -> Vec<Diagnostic> {
+    let source = match upgrade_for_load(source, "config") {
+        Ok(source) => source,
+        Err(e) => return vec![whole_file(None, e.to_string())],
+    };
+    let source = &*source;
+    let mut diagnostics = check_source(source, None, &SharedTables::default());
+    if diagnostics.is_empty() {
+        if let Err(e) = Config::from_toml(source) {
+            diagnostics.push(whole_file(None, e.to_string()));
+        }
+    }
+    diagnostics
+}
+
+/// Validate a config file and the fragments it includes.
+///
+/// Each file is checked on its own so locations point into the right file;
+/// checks that need the merged config (such as references to layers defined
+/// in another fragment) run once every file is clean.
+pub fn validate_file(path: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut stack = IncludeStack::default();
+    let shared = SharedTables::load(path);
+    check_file_with_includes(path, &shared, &mut stack, &mut diagnostics);
+    if diagnostics.is_empty() {
+        if let Err(e) = Config::from_toml_path(path) {
+            diagnostics.push(whole_file(Some(path), e.to_string()));
+        }
+    }
+    diagnostics
+}
+
+/// `[vars]` and `[conditions]` of a config and its fragments, as merged at
+/// load time, so a fragment can use definitions from another file
+#[derive(Default)]
+struct SharedTables {
+    vars: HashMap<String, String>,
+    conditions: HashMap<String, String>,
+}
+
+impl SharedTables {
+    fn load(path: &Path) -> Self {
+        let Ok(root) = load_with_includes(path) else {
+            return Self::default();
+        };
+        let strings = |section: &str| -> HashMap<String, String> {
+            root.get(section)
+                .and_then(toml::Value::as_table)
+                .map(|table| {
+                    table
+                        .iter()
+                        .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            vars: strings(VARS_KEY),
+            conditions: strings(CONDITIONS_KEY),
+        }
+    }
+}
+
+/// Root table of named conditions
+const CONDITIONS_KEY: &str = "conditions";
+
+fn whole_file(file: Option<&Path>, message: String) -> Diagnostic {
+    Diagnostic {
+        file: file.map(Path::to_path_buf),
+        line: 0,
+        column: 0,
+        message,
+    }
+}
+
+fn check_file_with_includes(
+    path: &Path,
+    shared: &SharedTables,
+    stack: &mut IncludeStack,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    match stack.enter(canonical) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            diagnostics.push(whole_file(Some(path), e));
+            return;
+        }
+    }
+    check_file_entered(path, shared, stack, diagnostics);
+    stack.leave();
+}
+
+fn check_file_entered(
+    path: &Path,
+    shared: &SharedTables,
+    stack: &mut IncludeStack,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            diagnostics.push(whole_file(Some(path), e.to_string()));
+            return;
+        }
+    };
+    // Older schemas are checked as the parser will read them
+    let source = match upgrade_for_load(&source, "config") {
+        Ok(source) => source.into_owned(),
+        Err(e) => {
+            diagnostics.push(whole_file(Some(path), e.to_string()));
+            return;
+        }
+    };
+    diagnostics.extend(check_source(&source, Some(path), shared));
+
+    let Ok(document) = ImDocument::parse(source.as_str()) else {
+        return;
+    };
+    let Some(includes) = document.get("include").and_then(Item::as_array) else {
+        return;
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for pattern in includes.iter().filter_map(Value::as_str) {
+        match expand_include_pattern(base_dir, pattern) {
+            Ok(fragments) => {
+                for fragment in fragments {
+                    check_file_with_includes(&fragment, shared, stack, diagnostics);
+                }
+            }
+            Err(e) => diagnostics.push(whole_file(Some(path), e.to_string())),
+        }
+    }
+}
+
+/// Check one file's TOML structure and values; `shared` holds definitions
+/// from across all included files
+fn check_source(source: &str, file: Option<&Path>, shared: &SharedTables) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        source,
+        file,
+        diagnostics: Vec::new(),
+        vars: HashMap::new(),
+        broken_vars: HashSet::new(),
+        named_conditions: HashMap::new(),
+        names: KeyNames::default(),
+    };
+
+    let document = match ImDocument::parse(source) {
+        Ok(document) => document,
+        Err(e) => {
+            validator.report(e.span(), e.message().trim().to_string());
+            return validator.diagnostics;
+        }
+    };
+
+    // Unknown sections/fields and wrong value types
+    let layout = match toml::from_str::<ConfigToml>(source) {
+        Ok(parsed) => parsed.layout.and_then(|layout| {
+            if layout.name.eq_ignore_ascii_case("auto") {
+                KeyboardLayout::detect().ok()
+            } else {
+                KeyboardLayout::load(&layout.name, layout.variant.as_deref()).ok()
+            }
+        }),
+        Err(e) => {
+            validator.report(e.span(), e.message().trim().to_string());
+            None
+        }
+    };
+
+    validator.check_vars(document.as_table(), &shared.vars);
+    validator.check_named_conditions(document.as_table(), &shared.conditions);
+    validator.names = KeyNames::new(layout);
+    let aliases = validator.check_aliases(document.as_table());
+    validator.names = std::mem::take(&mut validator.names).with_aliases(aliases);
+    validator.check_document(document.as_table());
+    validator.diagnostics
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    file: Option<&'a Path>,
+    diagnostics: Vec<Diagnostic>,
+    /// Resolved `[vars]`
+    vars: HashMap<String, String>,
+    /// Variables that are defined but failed to resolve (already reported)
+    broken_vars: HashSet<String>,
+    /// Resolved `[conditions]`
+    named_conditions: HashMap<String, ConditionExpr>,
+    /// Key names of the config's `[layout]` and `[aliases]`
+    names: KeyNames,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = match span {
+            Some(span) => {
+                let start = span.start.min(self.source.len());
+                let before = &self.source[..start];
+                let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                (
+                    before.matches('\n').count() + 1,
+                    before[line_start..].chars().count() + 1,
+                )
+            }
+            None => (0, 0),
+        };
+        self.diagnostics.push(Diagnostic {
+            file: self.file.map(Path::to_path_buf),
+            line,
+            column,
+            message,
+        });
+    }
+
+    fn check_document(&mut self, root: &toml_edit::Table) {
+        self.check_condition_field(root, "suspend_when", "suspend_when");
+        if let Some(general) = root.get("general").and_then(Item::as_table_like) {
+            for field in ["suspend_key", "diagnostics_key", "emergency_eject_key"] {
+                self.check_key_value(general, field, "general");
+            }
+            if let Some(chord) = general.get("panic_chord").and_then(Item::as_array) {
+                for key in chord.iter() {
+                    if let Some(name) = self.expand(key.as_str()) {
+                        if let Err(e) = parse_key(&name, &self.names) {
+                            self.report(key.span(), format!("general.panic_chord: {}", e));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(modmap) = root.get("modmap").and_then(Item::as_table_like) {
+            if let Some(default) = modmap.get("default").and_then(Item::as_table_like) {
+                self.check_key_map(default, "modmap.default");
+            }
+            for (index, conditional) in tables(modmap.get("conditionals")).into_iter().enumerate() {
+                let context = format!("modmap.conditionals[{}]", index);
+                if let Some(mappings) = conditional.get("mappings").and_then(Item::as_table_like) {
+                    self.check_key_map(mappings, &context);
+                }
+                self.check_condition(conditional, &context);
+            }
+        }
+
+        for (index, entry) in tables(root.get("multipurpose")).into_iter().enumerate() {
+            let context = format!("multipurpose[{}]", index);
+            for field in ["trigger", "tap", "hold"] {
+                self.check_key_value(entry, field, &context);
+            }
+            self.check_condition(entry, &context);
+        }
+
+        for (index, entry) in tables(root.get("tapdance")).into_iter().enumerate() {
+            let context = format!("tapdance[{}]", index);
+            for field in ["trigger", "hold"] {
+                self.check_key_value(entry, field, &context);
+            }
+            if let Some(taps) = entry.get("taps").and_then(Item::as_array) {
+                for tap in taps.iter() {
+                    if let Some(name) = self.expand(tap.as_str()) {
+                        if let Err(e) = parse_key(&name, &self.names) {
+                            self.report(tap.span(), format!("{}.taps: {}", context, e));
+                        }
+                    }
+                }
+            }
+            self.check_condition(entry, &context);
+        }
+
+        if let Some(modtap) = root.get("modtap").and_then(Item::as_table_like) {
+            for (name, _) in modtap.iter() {
+                let Some((key, item)) = modtap.get_key_value(name) else {
+                    continue;
+                };
+                if let Some(Err(e)) = self.expand(Some(name)).map(|name| parse_modtap_trigger(&name, &self.names)) {
+                    self.report(key.span(), format!("modtap: {}", e));
+                }
+                if let Some(output) = self.expand(item.as_str()) {
+                    if let Err(e) = parse_modtap_output(&output, &self.names) {
+                        self.report(item.span(), format!("modtap: {}", e));
+                    }
+                }
+            }
+        }
+
+        if let Some(scancodes) = root.get("scancodes").and_then(Item::as_table_like) {
+            for (name, _) in scancodes.iter() {
+                let Some((key, item)) = scancodes.get_key_value(name) else {
+                    continue;
+                };
+                if let Err(e) = parse_scancode_entry(name) {
+                    self.report(key.span(), format!("scancodes: {}", e));
+                }
+                if let Some(target) = self.expand(item.as_str()) {
+                    if let Err(e) = parse_key(&target, &self.names) {
+                        self.report(item.span(), format!("scancodes: {}", e));
+                    }
+                }
+            }
+        }
+
+        for (index, entry) in tables(root.get("keymap")).into_iter().enumerate() {
+            let context = match entry.get("name").and_then(Item::as_str) {
+                Some(name) => format!("keymap '{}'", name),
+                None => format!("keymap[{}]", index),
+            };
+            if let Some(mappings) = entry.get("mappings").and_then(Item::as_table_like) {
+                self.check_keymap_mappings(mappings, &context);
+            }
+            self.check_condition(entry, &context);
+        }
+
+        if let Some(delays) = root.get("delays").and_then(Item::as_table_like) {
+            for (index, entry) in tables(delays.get("overrides")).into_iter().enumerate() {
+                self.check_condition(entry, &format!("delays.overrides[{}]", index));
+            }
+        }
+
+        if let Some(layers) = root.get("layers").and_then(Item::as_table_like) {
+            for (name, layer) in layers.iter() {
+                let Some(layer) = layer.as_table_like() else {
+                    continue;
+                };
+                let context = format!("layer '{}'", name);
+                if let Some(mappings) = layer.get("mappings").and_then(Item::as_table_like) {
+                    self.check_keymap_mappings(mappings, &context);
+                }
+                self.check_condition(layer, &context);
+            }
+        }
+
+        if let Some(autoshift) = root.get("autoshift").and_then(Item::as_table_like) {
+            if let Some(rows) = autoshift.get("rows").and_then(Item::as_array) {
+                for row in rows.iter() {
+                    if let Some(Err(e)) = self.expand(row.as_str()).map(|row| autoshift_row(&row)) {
+                        self.report(row.span(), e.to_string());
+                    }
+                }
+            }
+            for field in ["keys", "exclude"] {
+                let Some(keys) = autoshift.get(field).and_then(Item::as_array) else {
+                    continue;
+                };
+                for key in keys.iter() {
+                    if let Some(name) = self.expand(key.as_str()) {
+                        if let Err(e) = parse_key(&name, &self.names) {
+                            self.report(key.span(), format!("autoshift.{}: {}", field, e));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(top_row) = root
+            .get("chromebook")
+            .and_then(Item::as_table_like)
+            .and_then(|chromebook| chromebook.get("top_row"))
+            .and_then(Item::as_array)
+        {
+            for key in top_row.iter() {
+                if let Some(name) = self.expand(key.as_str()) {
+                    if let Err(e) = parse_key(&name, &self.names) {
+                        self.report(key.span(), format!("chromebook.top_row: {}", e));
+                    }
+                }
+            }
+        }
+
+        if let Some(ignore) = root.get("ignore").and_then(Item::as_table_like) {
+            for field in ["wm_class", "wm_name"] {
+                let Some(patterns) = ignore.get(field).and_then(Item::as_array) else {
+                    continue;
+                };
+                for pattern in patterns.iter() {
+                    if let Some(message) = self.expand(pattern.as_str()).and_then(|p| ignore_pattern_error(field, &p)) {
+                        self.report(pattern.span(), message);
+                    }
+                }
+            }
+        }
+
+        for (section, field, ..) in TIMING_LIMITS {
+            let Some(item) = root
+                .get(section)
+                .and_then(Item::as_table_like)
+                .and_then(|table| table.get(field))
+            else {
+                continue;
+            };
+            if let Some(value) = item.as_integer() {
+                let result = u64::try_from(value)
+                    .map_err(|_| format!("{}.{} must not be negative", section, field))
+                    .and_then(|value| check_timing(section, field, value).map_err(|e| e.to_string()));
+                if let Err(message) = result {
+                    self.report(item.span(), message);
+                }
+            }
+        }
+    }
+
+    /// `[vars]` entries, then every `${name}` reference in the rest of the file
+    fn check_vars(&mut self, root: &toml_edit::Table, shared: &HashMap<String, String>) {
+        let own = root.get(VARS_KEY).and_then(Item::as_table_like);
+        let mut raw: HashMap<String, String> = own
+            .into_iter()
+            .flat_map(|table| table.iter())
+            .filter_map(|(name, item)| Some((name.to_string(), item.as_str()?.to_string())))
+            .collect();
+        raw.extend(shared.iter().map(|(name, value)| (name.clone(), value.clone())));
+
+        for name in &definition_order(own, &raw) {
+            match resolve_var(name, &raw) {
+                Ok(value) => {
+                    self.vars.insert(name.clone(), value);
+                }
+                Err(e) => {
+                    self.broken_vars.insert(name.clone());
+                    let Some((key, item)) = own.and_then(|table| table.get_key_value(name)) else {
+                        continue;
+                    };
+                    match e {
+                        ConfigError::UndefinedVariable(_) => self.report(item.span(), format!("vars.{}: {}", name, e)),
+                        _ => self.report(key.span(), e.to_string()),
+                    }
+                }
+            }
+        }
+
+        for (name, item) in root.iter() {
+            if name != VARS_KEY {
+                let key = root.key(name).and_then(|key| key.span());
+                self.check_var_reference(name, key);
+                self.check_item_var_references(item);
+            }
+        }
+    }
+
+    /// `[conditions]` entries, keeping the ones that resolve so conditions
+    /// can use them
+    fn check_named_conditions(&mut self, root: &toml_edit::Table, shared: &HashMap<String, String>) {
+        let own = root.get(CONDITIONS_KEY).and_then(Item::as_table_like);
+        let mut raw: HashMap<String, String> = own
+            .into_iter()
+            .flat_map(|table| table.iter())
+            .filter_map(|(name, item)| Some((name.to_string(), self.expand(item.as_str())?.into_owned())))
+            .collect();
+        raw.extend(shared.iter().map(|(name, source)| {
+            let source = self.expand(Some(source)).map_or_else(|| source.clone(), Cow::into_owned);
+            (name.clone(), source)
+        }));
+
+        for name in &definition_order(own, &raw) {
+            let own_entry = own.and_then(|table| table.get_key_value(name));
+            let result = resolve_named_condition(name, &raw)
+                .and_then(|expr| validate_condition(&expr.to_string()).map(|_| expr));
+            match result {
+                Ok(expr) => {
+                    self.named_conditions.insert(name.clone(), expr);
+                }
+                Err(e) => {
+                    if let Some((key, _)) = own_entry {
+                        self.report(key.span(), format!("conditions.{}: {}", name, e));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_item_var_references(&mut self, item: &Item) {
+        match item {
+            Item::Table(table) => self.check_table_var_references(table),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter() {
+                    self.check_table_var_references(table);
+                }
+            }
+            Item::Value(value) => self.check_value_var_references(value),
+            Item::None => {}
+        }
+    }
+
+    fn check_table_var_references(&mut self, table: &dyn TableLike) {
+        for (name, _) in table.iter() {
+            if let Some((key, item)) = table.get_key_value(name) {
+                self.check_var_reference(name, key.span());
+                self.check_item_var_references(item);
+            }
+        }
+    }
+
+    fn check_value_var_references(&mut self, value: &Value) {
+        match value {
+            Value::String(text) => self.check_var_reference(text.value(), value.span()),
+            Value::Array(array) => {
+                for value in array.iter() {
+                    self.check_value_var_references(value);
+                }
+            }
+            Value::InlineTable(table) => self.check_table_var_references(table),
+            _ => {}
+        }
+    }
+
+    fn check_var_reference(&mut self, text: &str, span: Option<Range<usize>>) {
+        match expand_vars(text, &self.vars) {
+            Err(ConfigError::UndefinedVariable(name)) if self.broken_vars.contains(&name) => {}
+            Err(e) => self.report(span, e.to_string()),
+            Ok(_) => {}
+        }
+    }
+
+    /// `text` with its variables substituted, or None when that fails
+    /// (reported by `check_vars`)
+    fn expand<'s>(&self, text: Option<&'s str>) -> Option<Cow<'s, str>> {
+        expand_vars(text?, &self.vars).ok()
+    }
+
+    fn expand_output(&self, output: KeymapTomlOutput) -> Option<KeymapTomlOutput> {
+        match output {
+            KeymapTomlOutput::Single(text) => Some(KeymapTomlOutput::Single(self.expand(Some(&text))?.into_owned())),
+            KeymapTomlOutput::Multiple(items) => items
+                .iter()
+                .map(|text| Some(self.expand(Some(text))?.into_owned()))
+                .collect::<Option<Vec<String>>>()
+                .map(KeymapTomlOutput::Multiple),
+            other => Some(other),
+        }
+    }
+
+    /// `[aliases]` entries, returning the ones that resolve so the rest of the
+    /// file can use them
+    fn check_aliases(&mut self, root: &toml_edit::Table) -> HashMap<String, Key> {
+        let mut resolved = HashMap::new();
+        let Some(table) = root.get("aliases").and_then(Item::as_table_like) else {
+            return resolved;
+        };
+        let raw: HashMap<String, String> = table
+            .iter()
+            .filter_map(|(name, item)| Some((name.to_string(), item.as_str()?.to_string())))
+            .collect();
+        for (name, _) in table.iter() {
+            let Some((key, item)) = table.get_key_value(name) else {
+                continue;
+            };
+            if !raw.contains_key(name) {
+                continue;
+            }
+            match resolve_alias(name, &raw, &self.names) {
+                Ok(target) => {
+                    resolved.insert(name.to_uppercase(), target);
+                }
+                Err(e @ ConfigError::InvalidKey(_)) => self.report(item.span(), e.to_string()),
+                Err(e) => self.report(key.span(), e.to_string()),
+            }
+        }
+        resolved
+    }
+
+    /// A single key name stored under `field`
+    fn check_key_value(&mut self, table: &dyn TableLike, field: &str, context: &str) {
+        let Some(item) = table.get(field) else {
+            return;
+        };
+        if let Some(name) = self.expand(item.as_str()) {
+            if let Err(e) = parse_key(&name, &self.names) {
+                self.report(item.span(), format!("{}.{}: {}", context, field, e));
+            }
+        }
+    }
+
+    /// A key -> key table (modmaps)
+    fn check_key_map(&mut self, table: &dyn TableLike, context: &str) {
+        for (name, _) in table.iter() {
+            let Some((key, item)) = table.get_key_value(name) else {
+                continue;
+            };
+            if let Some(Err(e)) = self.expand(Some(name)).map(|name| parse_key(&name, &self.names)) {
+                self.report(key.span(), format!("{}: {}", context, e));
+            }
+            if let Some(target) = self.expand(item.as_str()) {
+                if let Err(e) = parse_key(&target, &self.names) {
+                    self.report(item.span(), format!("{}: {}", context, e));
+                }
+            }
+        }
+    }
+
+    /// A combo -> output table (keymaps and layers)
+    fn check_keymap_mappings(&mut self, table: &dyn TableLike, context: &str) {
+        for (combo, _) in table.iter() {
+            let Some((key, item)) = table.get_key_value(combo) else {
+                continue;
+            };
+            let Some(combo) = self.expand(Some(combo)) else {
+                continue;
+            };
+            if let Err(e) = super::validate_combo_with(&combo, &self.names) {
+                self.report(key.span(), format!("{}: invalid combo '{}': {}", context, combo, e));
+            }
+
+            let Some(value) = item.as_value() else {
+                continue;
+            };
+            let output_values: Vec<&Value> = match value {
+                Value::InlineTable(table) => {
+                    self.check_mapping_repeat(table, context, &combo);
+                    ["output", "tap", "hold"].iter().filter_map(|name| table.get(name)).collect()
+                }
+                other => vec![other],
+            };
+            for output_value in output_values {
+                let Some(output) = plain_keymap_output(output_value).and_then(|output| self.expand_output(output))
+                else {
+                    continue;
+                };
+                if let Err(e) = parse_keymap_output(&output, &self.names) {
+                    self.report(
+                        output_value.span(),
+                        format!("{} mapping '{}': {}", context, combo, e),
+                    );
+                }
+            }
+        }
+    }
+
+    /// `repeat = { delay_ms, interval_ms }` on a table-form mapping
+    fn check_mapping_repeat(&mut self, table: &InlineTable, context: &str, combo: &str) {
+        let Some(repeat) = table.get("repeat").and_then(Value::as_inline_table) else {
+            return;
+        };
+        let field = |name: &str| {
+            repeat
+                .get(name)
+                .and_then(Value::as_integer)
+                .map(|v| v.max(0) as u64)
+        };
+        let settings = RepeatToml {
+            delay_ms: field("delay_ms"),
+            interval_ms: field("interval_ms"),
+        };
+        if let Err(e) = settings.to_rate() {
+            self.report(
+                table.get("repeat").and_then(Value::span),
+                format!("{} mapping '{}': {}", context, combo, e),
+            );
+        }
+    }
+
+    fn check_condition(&mut self, table: &dyn TableLike, context: &str) {
+        self.check_condition_field(table, "condition", context);
+    }
+
+    fn check_condition_field(&mut self, table: &dyn TableLike, field: &str, context: &str) {
+        let Some(item) = table.get(field) else {
+            return;
+        };
+        if let Some(condition) = self.expand(item.as_str()) {
+            if let Err(e) = validate_condition(&expand_named_conditions(&condition, &self.named_conditions)) {
+                self.report(item.span(), format!("{}: invalid condition: {}", context, e));
+            }
+        }
+    }
+}
+
+/// Names in `definitions`, those of `own` first in file order so problems are
+/// reported top to bottom
+fn definition_order(own: Option<&dyn TableLike>, definitions: &HashMap<String, String>) -> Vec<String> {
+    let mut names: Vec<String> = own
+        .into_iter()
+        .flat_map(|table| table.iter())
+        .map(|(name, _)| name.to_string())
+        .filter(|name| definitions.contains_key(name))
+        .collect();
+    let mut rest: Vec<String> = definitions.keys().filter(|name| !names.contains(name)).cloned().collect();
+    rest.sort();
+    names.extend(rest);
+    names
+}
+
+/// String or list output value
+fn plain_keymap_output(value: &Value) -> Option<KeymapTomlOutput> {
+    match value {
+        Value::String(s) => Some(KeymapTomlOutput::Single(s.value().clone())),
+        Value::Array(list) => list
+            .iter()
+            .map(|v| v.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+            .map(KeymapTomlOutput::Multiple),
+        _ => None,
+    }
+}
+
+/// Tables of an array of tables or an array of inline tables
+fn tables(item: Option<&Item>) -> Vec<&dyn TableLike> {
+    match item {
+        Some(Item::ArrayOfTables(array)) => {
+            array.iter().map(|table| table as &dyn TableLike).collect()
+        }
+        Some(Item::Value(Value::Array(array))) => array
+            .iter()
+            .filter_map(|value| value.as_inline_table().map(|t| t as &dyn TableLike))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locations(diagnostics: &[Diagnostic]) -> Vec<(usize, usize)> {
+        diagnostics.iter().map(|d| (d.line, d.column)).collect()
+    }
+
+    #[test]
+    fn test_valid_config_has_no_diagnostics() {
+        let source = r#"
+[modmap.default]
+CAPSLOCK = "ESC"
+
+[[keymap]]
+name = "Editing"
+condition = "wm_class =~ 'firefox'"
+[keymap.mappings]
+"C-a" = "Home"
+"C-b" = ["C-x", "Text(hi)"]
+"#;
+        assert_eq!(validate_str(source), vec![]);
+    }
+
+    #[test]
+    fn test_collects_every_problem_with_locations() {
+        let source = r#"[general]
+suspend_key = "F99"
+
+[modmap.default]
+CAPSLOK = "ESC"
+
+[[keymap]]
+name = "Broken"
+condition = "wm_clas =~ 'x'"
+[keymap.mappings]
+"C-Foo" = "Home"
+"C-a" = "Nope-z"
+"C-b" = ["Delay(x)"]
+
+[timeouts]
+multipurpose = 20
+"#;
+        let diagnostics = validate_str(source);
+        assert_eq!(
+            locations(&diagnostics),
+            vec![(2, 15), (5, 1), (11, 1), (12, 9), (13, 9), (9, 13), (16, 16)]
+        );
+        assert!(diagnostics[0].message.contains("general.suspend_key"));
+        assert!(diagnostics[2].message.contains("keymap 'Broken': invalid combo 'C-Foo'"));
+        assert!(diagnostics[4].message.contains("invalid sequence step 'Delay(x)'"));
+        assert!(diagnostics[5].message.contains("wm_clas"));
+        assert!(diagnostics[6].message.contains("multipurpose must be 100-5000ms"));
+    }
+
+    #[test]
+    fn test_syntax_and_schema_errors_have_locations() {
+        let diagnostics = validate_str("[general]\nsuspend_key = \n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+
+        let diagnostics = validate_str("[general]\nsuspend_kye = \"F12\"\n");
+        assert_eq!(locations(&diagnostics), vec![(2, 1)]);
+        assert!(diagnostics[0].message.contains("suspend_kye"));
+    }
+
+    #[test]
+    fn test_table_mapping_output_and_repeat_are_checked() {
+        let source = r#"[[keymap]]
+name = "Repeat"
+[keymap.mappings]
+"C-j" = { output = "Down", repeat = { interval_ms = 1 } }
+"C-k" = { output = "Nope-z" }
+"#;
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(4, 37), (5, 20)]);
+        assert!(diagnostics[0].message.contains("interval_ms"));
+    }
+
+    #[test]
+    fn test_modtap_entries_are_checked() {
+        let source = "[modtap]\nLEFT_META = \"Alt-F1\"\nCAPSLOCK = \"ESC\"\nRIGHT_ALT = \"Nope-z\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 1), (4, 13)]);
+        assert!(diagnostics[0].message.contains("not a modifier"));
+    }
+
+    #[test]
+    fn test_scancode_entries_are_checked() {
+        let source = "[scancodes]\n0x70039 = \"ESC\"\n\"0xzz\" = \"ESC\"\n\"58\" = \"NOPE\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 1), (4, 8)]);
+        assert!(diagnostics[0].message.contains("invalid scancode"));
+    }
+
+    #[test]
+    fn test_chromebook_top_row_is_checked() {
+        let source = "[chromebook]\ntop_row = [\"BACK\", \"NOPE\"]\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(2, 20)]);
+        assert!(diagnostics[0].message.starts_with("chromebook.top_row"));
+    }
+
+    #[test]
+    fn test_var_references_are_checked() {
+        let source = "[vars]\nterms = \"kitty|foot\"\nloop = \"${loop}\"\n[[keymap]]\nname = \"t\"\ncondition = \"wm_class =~ '${terms}'\"\n[keymap.mappings]\n\"C-${key}\" = \"${terms\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 1), (8, 1), (8, 14)]);
+        assert!(diagnostics[0].message.contains("cycle loop -> loop"));
+        assert!(diagnostics[1].message.contains("Undefined variable: key"));
+        assert!(diagnostics[2].message.contains("unterminated"));
+
+        let clean = "[vars]\nterms = \"kitty|foot\"\nall = \"${terms}|code\"\n[[keymap]]\nname = \"t\"\ncondition = \"wm_class =~ '^(${all})$'\"\n";
+        assert_eq!(validate_str(clean), Vec::new());
+    }
+
+    #[test]
+    fn test_named_conditions_are_checked() {
+        let source = "[conditions]\nterms = \"wm_class =~ 'kitty'\"\nloop = \"not loop\"\nbad = \"wm_clas =~ 'x'\"\n[[keymap]]\nname = \"t\"\ncondition = \"terms and not settings.x\"\n[[keymap]]\nname = \"u\"\ncondition = \"term\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 1), (4, 1), (10, 13)]);
+        assert!(diagnostics[0].message.contains("cycle loop -> loop"));
+        assert!(diagnostics[1].message.contains("wm_clas"));
+        assert!(diagnostics[2].message.contains("unknown condition field 'term'"));
+    }
+
+    #[test]
+    fn test_delay_override_conditions_are_checked() {
+        let source = "[[delays.overrides]]\ncondition = \"wm_clas =~ 'remmina'\"\nkey_post_delay_ms = 20\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(2, 13)]);
+        assert!(diagnostics[0].message.starts_with("delays.overrides[0]: invalid condition"));
+    }
+
+    #[test]
+    fn test_alias_entries_are_checked() {
+        let source = "[aliases]\nHyper = \"F13\"\nLoop = \"Loop\"\nBad = \"NOPE\"\n[modmap.default]\nCAPSLOCK = \"Hyper\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 1), (4, 7)]);
+        assert!(diagnostics[0].message.contains("cycle Loop -> Loop"));
+    }
+
+    #[test]
+    fn test_autoshift_entries_are_checked() {
+        let source = "[autoshift]\ntimeout = 50\nrows = [\"home\", \"middle\"]\nkeys = [\"NOPE\"]\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(3, 17), (4, 9), (2, 11)]);
+        assert!(diagnostics[0].message.contains("middle"));
+        assert!(diagnostics[2].message.contains("autoshift.timeout must be 100-1000ms"));
+    }
+
+    #[test]
+    fn test_ignore_patterns_are_checked() {
+        let source = "[ignore]\nwm_class = [\"virt-manager\", \"\"]\nwm_name = [\"Remmina|\"]\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(locations(&diagnostics), vec![(2, 29), (3, 12)]);
+        assert!(diagnostics[1].message.contains("ignore.wm_name"));
+    }
+
+    #[test]
+    fn test_merged_config_errors_are_reported_without_location() {
+        let source = "[[keymap]]\nname = \"a\"\n[keymap.mappings]\n\"C-a\" = \"Layer(nav)\"\n";
+        let diagnostics = validate_str(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 0);
+        assert!(diagnostics[0].to_string().contains("nav"));
+    }
+
+    #[test]
+    fn test_validate_file_points_into_included_fragment() {
+        let dir = std::env::temp_dir().join(format!("keyrs-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("config.toml");
+        let fragment = dir.join("apps.toml");
+        fs::write(&main, "include = [\"apps.toml\"]\n").unwrap();
+        fs::write(&fragment, "[[keymap]]\nname = \"x\"\n[keymap.mappings]\n\"C-a\" = \"Bogus\"\n").unwrap();
+
+        let diagnostics = validate_file(&main);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file.as_deref(), Some(fragment.as_path()));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, 9));
+    }
+
+    #[test]
+    fn test_validate_file_accepts_diamond_includes() {
+        let dir = std::env::temp_dir().join(format!("keyrs-validate-diamond-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("config.toml");
+        fs::write(&main, "include = [\"left.toml\", \"right.toml\"]\n").unwrap();
+        fs::write(dir.join("left.toml"), "include = [\"common.toml\"]\n").unwrap();
+        fs::write(dir.join("right.toml"), "include = [\"common.toml\"]\n").unwrap();
+        fs::write(dir.join("common.toml"), "[[keymap]]\nname = \"x\"\n[keymap.mappings]\n\"C-a\" = \"Bogus\"\n").unwrap();
+        fs::write(dir.join("loop.toml"), "include = [\"loop.toml\"]\n").unwrap();
+
+        let diagnostics = validate_file(&main);
+        let looped = validate_file(&dir.join("loop.toml"));
+        fs::remove_dir_all(&dir).unwrap();
+        // The shared fragment is checked once; only a real cycle is reported
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].message.contains("cycle"));
+        assert!(looped.iter().any(|d| d.message.contains("include cycle")));
+    }
+
+    #[test]
+    fn test_fragment_can_use_vars_of_the_including_file() {
+        let dir = std::env::temp_dir().join(format!("keyrs-validate-vars-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("config.toml");
+        fs::write(&main, "include = [\"apps.toml\"]\n[vars]\nterms = \"kitty|foot\"\n").unwrap();
+        fs::write(
+            dir.join("apps.toml"),
+            "[[keymap]]\nname = \"x\"\ncondition = \"wm_class =~ '${terms}'\"\n[keymap.mappings]\n\"C-a\" = \"C-b\"\n",
+        )
+        .unwrap();
+
+        let diagnostics = validate_file(&main);
+        let config = Config::from_toml_path(&main);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(diagnostics, Vec::new());
+        assert_eq!(config.unwrap().keymaps[0].condition.as_deref(), Some("wm_class =~ 'kitty|foot'"));
+    }
+}
@@ -0,0 +1,208 @@
+// Keyrs Config - Variables
+// `[vars]` table and `${name}` substitution in config strings
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use toml::map::Map;
+use toml::Value;
+
+use super::ConfigError;
+
+/// Root table holding the variables
+pub const VARS_KEY: &str = "vars";
+
+/// Replace every `${name}` in `text` with its value from `vars`.
+///
+/// Values are used as written; resolve references between variables first
+/// with `resolve_vars`.
+pub fn expand_vars<'a>(text: &'a str, vars: &HashMap<String, String>) -> Result<Cow<'a, str>, ConfigError> {
+    if !text.contains("${") {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut expanded = String::with_capacity(text.len());
+    for part in split_references(text)? {
+        match part {
+            Part::Text(text) => expanded.push_str(text),
+            Part::Var(name) => match vars.get(name) {
+                Some(value) => expanded.push_str(value),
+                None => return Err(ConfigError::UndefinedVariable(name.to_string())),
+            },
+        }
+    }
+    Ok(Cow::Owned(expanded))
+}
+
+/// Expand references between variables (`all = "${terminals}|code"`)
+pub fn resolve_vars(vars: &HashMap<String, String>) -> Result<HashMap<String, String>, ConfigError> {
+    vars.keys()
+        .map(|name| Ok((name.clone(), resolve_var(name, vars)?)))
+        .collect()
+}
+
+/// Value of one variable, following references to other variables
+pub fn resolve_var(name: &str, vars: &HashMap<String, String>) -> Result<String, ConfigError> {
+    resolve_chain(name, vars, &mut Vec::new())
+}
+
+fn resolve_chain(name: &str, vars: &HashMap<String, String>, chain: &mut Vec<String>) -> Result<String, ConfigError> {
+    if !is_var_name(name) {
+        return Err(ConfigError::InvalidValue(format!(
+            "vars: '{}' is not a valid variable name",
+            name
+        )));
+    }
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(ConfigError::InvalidValue(format!("vars: cycle {}", chain.join(" -> "))));
+    }
+    let value = vars
+        .get(name)
+        .ok_or_else(|| ConfigError::UndefinedVariable(name.to_string()))?;
+
+    chain.push(name.to_string());
+    let mut resolved = String::with_capacity(value.len());
+    for part in split_references(value)? {
+        match part {
+            Part::Text(text) => resolved.push_str(text),
+            Part::Var(inner) => resolved.push_str(&resolve_chain(inner, vars, chain)?),
+        }
+    }
+    chain.pop();
+    Ok(resolved)
+}
+
+/// Take the `[vars]` table out of a config root and expand its variables in
+/// every other string and table key
+pub fn expand_config_vars(root: &mut Map<String, Value>) -> Result<(), ConfigError> {
+    let vars = match root.remove(VARS_KEY) {
+        None => return Ok(()),
+        Some(Value::Table(table)) => table
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => Ok((name, value)),
+                other => Err(ConfigError::InvalidValue(format!(
+                    "vars.{}: expected a string, got {}",
+                    name, other
+                ))),
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?,
+        Some(other) => {
+            return Err(ConfigError::InvalidValue(format!("vars must be a table, got {}", other)));
+        }
+    };
+    let vars = resolve_vars(&vars)?;
+
+    for (section, value) in root.iter_mut() {
+        expand_value(value, &vars, section)?;
+    }
+    Ok(())
+}
+
+fn expand_value(value: &mut Value, vars: &HashMap<String, String>, path: &str) -> Result<(), ConfigError> {
+    match value {
+        Value::String(text) => {
+            if let Cow::Owned(expanded) = expand_vars(text, vars).map_err(|e| in_field(e, path))? {
+                *text = expanded;
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                expand_value(item, vars, &format!("{}[{}]", path, index))?;
+            }
+        }
+        Value::Table(table) => {
+            let mut expanded = Map::new();
+            for (key, mut item) in std::mem::take(table) {
+                let key = expand_vars(&key, vars)
+                    .map_err(|e| in_field(e, path))?
+                    .into_owned();
+                expand_value(&mut item, vars, &format!("{}.{}", path, key))?;
+                expanded.insert(key, item);
+            }
+            *table = expanded;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn in_field(error: ConfigError, path: &str) -> ConfigError {
+    match error {
+        ConfigError::UndefinedVariable(name) => ConfigError::UndefinedVariable(format!("{} (in {})", name, path)),
+        ConfigError::InvalidValue(message) => ConfigError::InvalidValue(format!("{}: {}", path, message)),
+        other => other,
+    }
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Var(&'a str),
+}
+
+fn split_references(text: &str) -> Result<Vec<Part<'_>>, ConfigError> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        parts.push(Part::Text(&rest[..start]));
+        let after = &rest[start + 2..];
+        let name = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|name| is_var_name(name))
+            .ok_or_else(|| {
+                ConfigError::InvalidValue(format!("'{}' has an unterminated or invalid ${{...}} reference", text))
+            })?;
+        parts.push(Part::Var(name));
+        rest = &after[name.len() + 1..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let vars = vars(&[("terminals", "kitty|foot")]);
+        assert_eq!(
+            expand_vars("wm_class =~ '${terminals}'", &vars).unwrap(),
+            "wm_class =~ 'kitty|foot'"
+        );
+        assert!(matches!(expand_vars("no refs$", &vars).unwrap(), Cow::Borrowed(_)));
+        assert!(matches!(
+            expand_vars("${nope}", &vars),
+            Err(ConfigError::UndefinedVariable(name)) if name == "nope"
+        ));
+        assert!(expand_vars("${terminals", &vars).is_err());
+        assert!(expand_vars("${1st}", &vars).is_err());
+    }
+
+    #[test]
+    fn test_resolve_vars() {
+        let resolved = resolve_vars(&vars(&[("terminals", "kitty|foot"), ("all", "${terminals}|code")])).unwrap();
+        assert_eq!(resolved["all"], "kitty|foot|code");
+
+        let cycle = resolve_vars(&vars(&[("a", "${b}"), ("b", "${a}")])).unwrap_err();
+        assert!(cycle.to_string().contains("cycle"));
+        assert!(resolve_vars(&vars(&[("a", "${missing}")])).is_err());
+        assert!(resolve_vars(&vars(&[("my-var", "x")])).is_err());
+    }
+}
@@ -0,0 +1,516 @@
+// Keyrs Control Socket
+// Line-based requests to read and change the running daemon's settings
+
+#![cfg(feature = "pure-rust")]
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audit::AuditDump;
+use crate::output::{parse_subscription, ObserverHub};
+use crate::runtime::{bind_private_socket, remove_socket, runtime_path, socket_id, SocketId};
+use crate::status::LiveStatus;
+
+/// How long a client waits for the daemon to answer
+pub const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A request read from a control client, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlRequest {
+    /// `list`: every feature setting with its live value
+    List,
+    /// `get <name>`: live value of one setting
+    Get(String),
+    /// `set <name> <bool>`: change a setting in the running daemon only
+    Set(String, bool),
+    /// `status`: suspend state, keyboard type, focused window and devices
+    Status,
+    /// `type <text>`: type text on the daemon's virtual keyboard (`\n`, `\t`, `\\` escapes)
+    Type(String),
+    /// `key <combo>`: tap a combo such as `ctrl+alt+t` on the daemon's virtual keyboard
+    Key(String),
+    /// `profile [name]`: switch to a `[profiles]` config, or the next one
+    Profile(Option<String>),
+    /// `audit`: the `[audit]` log of recent outputs and their inputs
+    Audit,
+}
+
+/// Answer to a control request
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlReply {
+    /// `ok name=value ...`
+    Settings(BTreeMap<String, bool>),
+    /// `ok <json>`
+    Status(LiveStatus),
+    /// `ok <json>`
+    Audit(AuditDump),
+}
+
+impl From<BTreeMap<String, bool>> for ControlReply {
+    fn from(values: BTreeMap<String, bool>) -> Self {
+        Self::Settings(values)
+    }
+}
+
+impl ControlRequest {
+    /// Parse a request line; the error is sent back to the client
+    pub fn parse(line: &str) -> Result<Self, String> {
+        // Text is taken verbatim, so its spaces survive
+        if let Some(text) = line.trim_end_matches(['\r', '\n']).strip_prefix("type ") {
+            return Ok(Self::Type(unescape_text(text)));
+        }
+        let mut words = line.split_whitespace();
+        let request = match (words.next(), words.next(), words.next()) {
+            (Some("list"), None, _) => Self::List,
+            (Some("status"), None, _) => Self::Status,
+            (Some("audit"), None, _) => Self::Audit,
+            (Some("key"), Some(combo), None) => Self::Key(combo.to_string()),
+            (Some("profile"), name, None) => Self::Profile(name.map(str::to_string)),
+            (Some("get"), Some(name), None) => Self::Get(name.to_string()),
+            (Some("set"), Some(name), Some(value)) => Self::Set(name.to_string(), parse_bool(value)?),
+            (Some(command), ..) => return Err(format!("unknown or malformed request '{}'", command)),
+            (None, ..) => return Err("empty request".to_string()),
+        };
+        if words.next().is_some() {
+            return Err("too many arguments".to_string());
+        }
+        Ok(request)
+    }
+
+    /// Encode as a request line
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::List => "list\n".to_string(),
+            Self::Status => "status\n".to_string(),
+            Self::Audit => "audit\n".to_string(),
+            Self::Type(text) => format!("type {}\n", escape_text(text)),
+            Self::Key(combo) => format!("key {}\n", combo),
+            Self::Profile(None) => "profile\n".to_string(),
+            Self::Profile(Some(name)) => format!("profile {}\n", name),
+            Self::Get(name) => format!("get {}\n", name),
+            Self::Set(name, value) => format!("set {} {}\n", name, value),
+        }
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t").replace('\r', "\\r")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => Err(format!("'{}' is not a boolean", value)),
+    }
+}
+
+/// Answer line: `ok name=value ...`, `ok <json>` or `err <message>`
+fn response_line(result: Result<ControlReply, String>) -> String {
+    match result {
+        Ok(ControlReply::Status(status)) => match serde_json::to_string(&status) {
+            Ok(json) => format!("ok {}\n", json),
+            Err(e) => format!("err {}\n", e),
+        },
+        Ok(ControlReply::Audit(dump)) => match serde_json::to_string(&dump) {
+            Ok(json) => format!("ok {}\n", json),
+            Err(e) => format!("err {}\n", e),
+        },
+        Ok(ControlReply::Settings(values)) => {
+            let mut line = "ok".to_string();
+            for (name, value) in values {
+                line.push_str(&format!(" {}={}", name, value));
+            }
+            line.push('\n');
+            line
+        }
+        Err(message) => format!("err {}\n", message.replace('\n', " ")),
+    }
+}
+
+/// Payload of an `ok` answer, or the error of an `err` answer
+fn response_payload(line: &str) -> std::io::Result<&str> {
+    let line = line.trim_end();
+    if let Some(message) = line.strip_prefix("err ") {
+        return Err(std::io::Error::other(message.to_string()));
+    }
+    line.strip_prefix("ok")
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, format!("bad response '{}'", line)))
+}
+
+fn parse_response(line: &str) -> std::io::Result<BTreeMap<String, bool>> {
+    response_payload(line)?
+        .split_whitespace()
+        .map(|pair| {
+            let parsed = pair
+                .split_once('=')
+                .and_then(|(name, value)| Some((name.to_string(), parse_bool(value).ok()?)));
+            parsed.ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, format!("bad value '{}'", pair)))
+        })
+        .collect()
+}
+
+fn parse_status_response(line: &str) -> std::io::Result<LiveStatus> {
+    serde_json::from_str(response_payload(line)?.trim()).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+}
+
+struct Connection {
+    stream: UnixStream,
+    pending: Vec<u8>,
+}
+
+/// Unix socket the daemon answers control requests on.
+///
+/// It never blocks the event loop: connections are accepted and requests
+/// read only when `poll` is called between events. A connection that sends
+/// `subscribe [kinds]` is answered `ok` and handed to the output mirror
+/// (`[observer]`), receiving emitted output from then on.
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+    /// The socket file bound, removed on drop only while it is still there
+    socket: Option<SocketId>,
+    connections: Vec<Connection>,
+    /// Output mirror subscriptions go here; `None` while `[observer]` is off
+    observers: Option<ObserverHub>,
+}
+
+impl ControlServer {
+    /// Default socket path (`$XDG_RUNTIME_DIR/keyrs-control.sock`); fails without `XDG_RUNTIME_DIR`
+    pub fn default_path() -> std::io::Result<PathBuf> {
+        runtime_path("keyrs-control.sock")
+    }
+
+    /// Bind the control socket (owner-only permissions), replacing a stale
+    /// socket file but not one a running daemon listens on
+    pub fn bind(path: &Path) -> std::io::Result<Self> {
+        let listener = bind_private_socket(path)?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            socket: socket_id(path),
+            connections: Vec::new(),
+            observers: None,
+        })
+    }
+
+    /// Accept `subscribe` requests, handing their connections to `observers`
+    pub fn set_observers(&mut self, observers: ObserverHub) {
+        self.observers = Some(observers);
+    }
+
+    /// Socket path this server is bound to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The listening socket and open connections; `poll` has work when one is readable
+    pub fn fds(&self) -> Vec<RawFd> {
+        std::iter::once(self.listener.as_raw_fd())
+            .chain(self.connections.iter().map(|connection| connection.stream.as_raw_fd()))
+            .collect()
+    }
+
+    /// Answer every complete request line received so far.
+    ///
+    /// `handle` returns the settings (`name=value` pairs) or status to report,
+    /// or an error message.
+    pub fn poll<R: Into<ControlReply>>(&mut self, mut handle: impl FnMut(ControlRequest) -> Result<R, String>) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.connections.push(Connection {
+                            stream,
+                            pending: Vec::new(),
+                        });
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+
+        self.connections.retain_mut(|connection| {
+            let mut buf = [0u8; 256];
+            let open = loop {
+                match connection.stream.read(&mut buf) {
+                    Ok(0) => break false,
+                    Ok(n) => connection.pending.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break true,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break false,
+                }
+            };
+
+            while let Some(pos) = connection.pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = connection.pending.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if let Some(filter) = parse_subscription(&line) {
+                    let Some(observers) = &self.observers else {
+                        let refused = response_line(Err("the output mirror is off ([observer] enabled = false)".to_string()));
+                        if connection.stream.write_all(refused.as_bytes()).is_err() {
+                            return false;
+                        }
+                        continue;
+                    };
+                    // The connection leaves the control protocol for good
+                    if connection.stream.write_all(b"ok\n").is_ok() {
+                        if let Ok(stream) = connection.stream.try_clone() {
+                            observers.add(stream, filter, std::mem::take(&mut connection.pending));
+                        }
+                    }
+                    return false;
+                }
+                let result = ControlRequest::parse(&line)
+                    .and_then(&mut handle)
+                    .map(Into::into);
+                if connection.stream.write_all(response_line(result).as_bytes()).is_err() {
+                    return false;
+                }
+            }
+            open
+        });
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        remove_socket(&self.path, self.socket);
+    }
+}
+
+/// Blocking client for the control socket (used by `keyrs-tui` and `keyrs status`)
+pub struct ControlClient {
+    reader: BufReader<UnixStream>,
+}
+
+impl ControlClient {
+    /// Connect to a running daemon
+    pub fn connect(path: &Path) -> std::io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONTROL_TIMEOUT))?;
+        Ok(Self {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Send a request and wait for its answer
+    pub fn request(&mut self, request: &ControlRequest) -> std::io::Result<BTreeMap<String, bool>> {
+        parse_response(&self.exchange(request)?)
+    }
+
+    /// Live daemon status
+    pub fn status(&mut self) -> std::io::Result<LiveStatus> {
+        parse_status_response(&self.exchange(&ControlRequest::Status)?)
+    }
+
+    /// Type text through the daemon's virtual keyboard
+    pub fn type_text(&mut self, text: &str) -> std::io::Result<()> {
+        self.request(&ControlRequest::Type(text.to_string())).map(|_| ())
+    }
+
+    /// Tap a combo (`ctrl+alt+t`, `C-A-t`) through the daemon's virtual keyboard
+    pub fn key(&mut self, combo: &str) -> std::io::Result<()> {
+        self.request(&ControlRequest::Key(combo.to_string())).map(|_| ())
+    }
+
+    /// The daemon's `[audit]` log
+    pub fn audit(&mut self) -> std::io::Result<AuditDump> {
+        let line = self.exchange(&ControlRequest::Audit)?;
+        serde_json::from_str(response_payload(&line)?.trim()).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Switch the daemon to the profile `name`, or the next one; answers
+    /// the status after the switch
+    pub fn profile(&mut self, name: Option<&str>) -> std::io::Result<LiveStatus> {
+        parse_status_response(&self.exchange(&ControlRequest::Profile(name.map(str::to_string)))?)
+    }
+
+    fn exchange(&mut self, request: &ControlRequest) -> std::io::Result<String> {
+        self.reader.get_mut().write_all(request.to_line().as_bytes())?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "daemon closed the connection"));
+        }
+        Ok(line)
+    }
+
+    /// Live value of every feature setting
+    pub fn list(&mut self) -> std::io::Result<BTreeMap<String, bool>> {
+        self.request(&ControlRequest::List)
+    }
+
+    /// Live value of one setting
+    pub fn get(&mut self, name: &str) -> std::io::Result<bool> {
+        let values = self.request(&ControlRequest::Get(name.to_string()))?;
+        values
+            .get(name)
+            .copied()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, format!("no value for '{}'", name)))
+    }
+
+    /// Change a setting in the running daemon (settings.toml is left alone)
+    pub fn set(&mut self, name: &str, value: bool) -> std::io::Result<()> {
+        self.request(&ControlRequest::Set(name.to_string(), value)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_control_requests() {
+        assert_eq!(ControlRequest::parse("list\n"), Ok(ControlRequest::List));
+        assert_eq!(ControlRequest::parse("status"), Ok(ControlRequest::Status));
+        assert_eq!(
+            ControlRequest::parse("get Enter2Ent_Cmd"),
+            Ok(ControlRequest::Get("Enter2Ent_Cmd".to_string()))
+        );
+        assert_eq!(
+            ControlRequest::parse(" set forced_numpad on "),
+            Ok(ControlRequest::Set("forced_numpad".to_string(), true))
+        );
+        assert!(ControlRequest::parse("set forced_numpad maybe").is_err());
+        assert!(ControlRequest::parse("get").is_err());
+        assert!(ControlRequest::parse("list extra").is_err());
+        assert!(ControlRequest::parse("").is_err());
+
+        let set = ControlRequest::Set("a".to_string(), false);
+        assert_eq!(ControlRequest::parse(&set.to_line()), Ok(set));
+
+        assert_eq!(ControlRequest::parse("key ctrl+alt+t\n"), Ok(ControlRequest::Key("ctrl+alt+t".to_string())));
+        assert_eq!(ControlRequest::parse("type  two  spaces\n"), Ok(ControlRequest::Type(" two  spaces".to_string())));
+        let text = ControlRequest::Type("line\n\tC:\\dir".to_string());
+        assert_eq!(text.to_line().matches('\n').count(), 1);
+        assert_eq!(ControlRequest::parse(&text.to_line()), Ok(text));
+
+        assert_eq!(ControlRequest::parse("profile\n"), Ok(ControlRequest::Profile(None)));
+        let profile = ControlRequest::Profile(Some("vanilla".to_string()));
+        assert_eq!(ControlRequest::parse(&profile.to_line()), Ok(profile));
+        assert!(ControlRequest::parse("profile a b").is_err());
+
+        assert_eq!(ControlRequest::parse(&ControlRequest::Audit.to_line()), Ok(ControlRequest::Audit));
+        assert!(ControlRequest::parse("audit all").is_err());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let mut values = BTreeMap::new();
+        values.insert("a".to_string(), true);
+        values.insert("b".to_string(), false);
+        assert_eq!(response_line(Ok(values.clone().into())), "ok a=true b=false\n");
+        assert_eq!(parse_response("ok a=true b=false\n").unwrap(), values);
+        assert!(parse_response("ok\n").unwrap().is_empty());
+        assert!(parse_response("err no such setting\n").is_err());
+        assert!(parse_response("ok a=maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_client_and_server_exchange_settings() {
+        let path = std::env::temp_dir().join(format!("keyrs-control-test-{}.sock", std::process::id()));
+        let mut server = ControlServer::bind(&path).expect("bind control socket");
+        let mut client = ControlClient::connect(&path).expect("connect");
+
+        let mut live = BTreeMap::new();
+        live.insert("forced_numpad".to_string(), false);
+        let mut handle = |request| match request {
+            ControlRequest::List => Ok(live.clone()),
+            ControlRequest::Get(name) => match live.get(&name) {
+                Some(value) => Ok(BTreeMap::from([(name, *value)])),
+                None => Err(format!("unknown setting '{}'", name)),
+            },
+            ControlRequest::Set(name, value) => {
+                live.insert(name.clone(), value);
+                Ok(BTreeMap::from([(name, value)]))
+            }
+            ControlRequest::Status
+            | ControlRequest::Type(_)
+            | ControlRequest::Key(_)
+            | ControlRequest::Profile(_)
+            | ControlRequest::Audit => Err("unsupported".to_string()),
+        };
+
+        // The server only answers when polled, so send each request by hand first
+        let stream = client.reader.get_mut();
+        stream.write_all(b"set forced_numpad true\nget missing\n").unwrap();
+        server.poll(&mut handle);
+        let mut line = String::new();
+        client.reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "ok forced_numpad=true\n");
+        line.clear();
+        client.reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("err unknown setting"));
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_subscribe_hands_connection_to_observers() {
+        use crate::output::{ObserverEvent, ObserverEventKind, ObserverPrivacy};
+        use crate::Action;
+
+        let path = std::env::temp_dir().join(format!("keyrs-control-subscribe-{}.sock", std::process::id()));
+        let mut server = ControlServer::bind(&path).expect("bind control socket");
+        let mut handle = |_| Err::<BTreeMap<String, bool>, _>("unsupported".to_string());
+
+        // Refused while the mirror is off
+        let mut client = ControlClient::connect(&path).expect("connect");
+        client.reader.get_mut().write_all(b"subscribe\n").unwrap();
+        server.poll(&mut handle);
+        let mut line = String::new();
+        client.reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("err "));
+
+        let observers = ObserverHub::new(ObserverPrivacy::Off);
+        server.set_observers(observers.clone());
+        client.reader.get_mut().write_all(b"subscribe combo\n").unwrap();
+        server.poll(&mut handle);
+        line.clear();
+        client.reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "ok\n");
+        assert_eq!(observers.subscriber_count(), 1);
+
+        observers.publish(ObserverEvent::new(ObserverEventKind::Combo, Action::Press, "Ctrl-C"), None);
+        line.clear();
+        client.reader.read_line(&mut line).unwrap();
+        assert!(line.contains("Ctrl-C"));
+    }
+
+    #[test]
+    fn test_status_reply_round_trip() {
+        let mut status = LiveStatus::default();
+        status.daemon.enabled = true;
+        status.wm_class = Some("org.gnome.Terminal".to_string());
+        let line = response_line(Ok(ControlReply::Status(status.clone())));
+        assert!(line.starts_with("ok {") && line.ends_with("}\n"));
+        assert_eq!(parse_status_response(&line).unwrap(), status);
+        assert!(parse_status_response("err busy\n").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+// Keyrs Device Guard
+// Hands the keyboards back and releases every virtual key however the daemon stops
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::reactor::Waker;
+use super::source::InputSource;
+use crate::output::{OutputThread, UInputError};
+
+/// Owns the grabbed input and the output thread for the daemon's lifetime.
+///
+/// Dropping the guard ungrabs every device and releases every held virtual
+/// key. That runs on a normal return, an early `?` return and while
+/// unwinding from a panic, so a crash never leaves the keyboard grabbed.
+pub struct DeviceGuard {
+    input: Box<dyn InputSource>,
+    /// Taken on the way out
+    output: Option<OutputThread>,
+}
+
+impl DeviceGuard {
+    /// Guard `input`, grabbed already, and the thread writing the output
+    pub fn new(input: Box<dyn InputSource>, output: OutputThread) -> Self {
+        Self { input, output: Some(output) }
+    }
+
+    /// The input and the output, for the main loop
+    pub fn parts(&mut self) -> (&mut dyn InputSource, &OutputThread) {
+        let output = self.output.as_ref().expect("output is only taken on the way out");
+        (self.input.as_mut(), output)
+    }
+
+    /// Ungrab, finish the queued output, release every key and close the
+    /// virtual device, reporting a failure to close it
+    pub fn finish(mut self) -> Result<(), UInputError> {
+        let output = self.output.take();
+        drop(self);
+        match output.and_then(OutputThread::join) {
+            Some(device) => device.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DeviceGuard {
+    fn drop(&mut self) {
+        self.input.ungrab_all();
+        if let Some(output) = self.output.take() {
+            // Queued output is stale on the way out; don't wait behind it
+            output.abort();
+            if let Some(mut device) = output.join() {
+                let _ = device.release_all();
+            }
+        }
+    }
+}
+
+/// Stop the daemon when any thread panics: `running` is cleared and
+/// `shutdown` woken, so the main loop returns and its `DeviceGuard` cleans up.
+/// A panic on the main thread unwinds through the guard directly.
+pub fn stop_on_panic(running: Arc<AtomicBool>, shutdown: Waker) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        log::error!("keyrs panicked; releasing keys and ungrabbing devices");
+        running.store(false, Ordering::SeqCst);
+        shutdown.wake();
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{EventLoopResult, PolledEvent};
+    use crate::input::{KeyboardDeviceInfo, LockState};
+    use crate::mapping::ActionStep;
+    use crate::output::{OutputDeviceKind, TransformResultOutput, VirtualDevice};
+    use crate::{Action, Key};
+    use std::os::unix::io::RawFd;
+    use std::time::Duration;
+
+    /// Input that only counts `ungrab_all` calls
+    struct CountingInput(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl InputSource for CountingInput {
+        fn waker(&self) -> Waker {
+            Waker::new().unwrap()
+        }
+        fn set_watched_fds(&mut self, _fds: &[RawFd]) {}
+        fn wait_for_events(&mut self, _timeout: Option<Duration>) -> EventLoopResult<Vec<PolledEvent>> {
+            Ok(Vec::new())
+        }
+        fn take_forwarded(&mut self) -> Vec<Vec<(u16, u16, i32)>> {
+            Vec::new()
+        }
+        fn device_names(&self) -> Vec<String> {
+            Vec::new()
+        }
+        fn device_count(&self) -> usize {
+            0
+        }
+        fn keyboard_detection_infos(&self) -> Vec<KeyboardDeviceInfo> {
+            Vec::new()
+        }
+        fn lock_state(&self) -> Option<LockState> {
+            None
+        }
+        fn set_lock_leds(&mut self, _state: LockState) {}
+        fn set_paused(&mut self, _paused: bool) {}
+        fn ungrab_all(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_guard_ungrabs_on_panic() {
+        let (first, events) = VirtualDevice::recording();
+        let (second, _) = VirtualDevice::recording();
+        let ungrabs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let input = CountingInput(ungrabs.clone());
+        let written = events.clone();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let mut guard = DeviceGuard::new(Box::new(input), OutputThread::spawn(first).unwrap());
+            let (_, output) = guard.parts();
+            output.emit(TransformResultOutput::Passthrough(Key::from(30)), Action::Press);
+            // Would hold the thread for a minute without the abort
+            output.emit(TransformResultOutput::Sequence(vec![ActionStep::DelayMs(60_000)]), Action::Press);
+            output.emit(TransformResultOutput::Passthrough(Key::from(31)), Action::Press);
+            while !written.lock().unwrap().contains(&(OutputDeviceKind::Keyboard, 1, 30, 1)) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            panic!("main loop failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(ungrabs.load(Ordering::SeqCst), 1);
+        let events = events.lock().unwrap();
+        let key_values = |code: u16| -> Vec<i32> {
+            events
+                .iter()
+                .filter(|(_, kind, key, _)| *kind == 1 && *key == code)
+                .map(|(_, _, _, value)| *value)
+                .collect()
+        };
+        // Pressed before the panic and released by the abort; queued work dropped
+        assert_eq!(key_values(30), vec![1, 0]);
+        assert!(key_values(31).is_empty());
+        drop(events);
+
+        let output = OutputThread::spawn(second).unwrap();
+        DeviceGuard::new(Box::new(CountingInput(ungrabs.clone())), output).finish().unwrap();
+        assert_eq!(ungrabs.load(Ordering::SeqCst), 2);
+    }
+}
@@ -2,11 +2,20 @@
 // Direct evdev event handling for low-latency input processing
 
 #[cfg(feature = "pure-rust")]
-use evdev::{Device, EventType, InputEvent, Key};
+use evdev::{Device, EventType, InputEvent, Key, LedType, SwitchType};
 #[cfg(feature = "pure-rust")]
-use std::os::unix::io::AsRawFd;
+use std::collections::{HashMap, HashSet};
 #[cfg(feature = "pure-rust")]
-use crate::input::{is_virtual_device, matches_device_filter};
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(feature = "pure-rust")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "pure-rust")]
+use super::reactor::{Epoll, Waker};
+#[cfg(feature = "pure-rust")]
+use crate::input::{
+    is_gamepad, is_internal_keyboard, is_on_seat, is_virtual_device, matches_device_filter,
+    DeviceCapabilities, GrabPolicies, GrabPolicy, InternalKeyboardPolicy, LockState, DEFAULT_SEAT,
+};
 
 #[cfg(feature = "pure-rust")]
 use udev::MonitorSocket;
@@ -25,6 +34,9 @@ pub enum EventLoopError {
 
     #[error("Event device error: {0}")]
     Evdev(String),
+
+    #[error("Input helper disconnected: {0}")]
+    HelperDisconnected(String),
 }
 
 /// Device information for listing devices
@@ -36,6 +48,10 @@ pub struct DeviceInfo {
     pub name: String,
     /// Device path (if available)
     pub path: Option<String>,
+    /// USB vendor id
+    pub vendor_id: u16,
+    /// USB product id
+    pub product_id: u16,
 }
 
 /// Input event annotated with source device metadata.
@@ -55,12 +71,90 @@ pub struct PolledEvent {
 pub struct EventLoop {
     devices: Vec<Device>,
     device_paths: Vec<String>,
-    poll_fds: Vec<libc::pollfd>,
+    /// Grab policy of each device (same order as `devices`)
+    policies: Vec<GrabPolicy>,
+    /// Grab policy for devices, including ones plugged in later
+    grab_policies: GrabPolicies,
+    /// Frames of non-key events read from grabbed devices, to re-emit
+    forwarded: Vec<Vec<(u16, u16, i32)>>,
+    /// Readiness of the devices, the udev monitor, the waker and watched fds
+    epoll: Epoll,
+    /// Wakes `wait_for_events` from other threads
+    waker: Waker,
+    /// Extra fds (sockets, inotify) that end a wait when readable
+    watched: Vec<RawFd>,
     grabbed: bool,
-    /// udev monitor for hotplug detection (fd is at poll_fds[0])
+    /// udev monitor for hotplug detection
     udev_monitor: Option<MonitorSocket>,
     /// Device filter for hotplug matching
     device_filter: Vec<String>,
+    /// When to ignore the internal keyboard
+    internal_policy: InternalKeyboardPolicy,
+    /// Explicit internal keyboard names/paths (empty = autodetect)
+    internal_names: Vec<String>,
+    /// Lid switch device (opened read-only, never grabbed, watched by epoll)
+    lid_switch: Option<Device>,
+    /// Last known lid state, updated when the lid switch reports a change
+    lid_closed: bool,
+    /// Whether the internal keyboard is currently being ignored
+    internal_ignored: bool,
+    /// Also use gamepads/joysticks when autodetecting devices
+    include_gamepads: bool,
+    /// Only use devices of this seat (`None` = any seat)
+    seat: Option<String>,
+    /// Devices released and their events dropped (inactive session)
+    paused: bool,
+    /// Key codes each device (by path) holds down, released if it vanishes
+    held_keys: HashMap<String, Vec<u16>>,
+    /// Grab devices that vanished again when they return, even at a new path
+    regrab_departed: bool,
+    /// Devices that vanished while in use
+    departed: Vec<DeviceIdentity>,
+}
+
+/// What identifies a device across an unplug: its event node can change
+#[cfg(feature = "pure-rust")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeviceIdentity {
+    name: String,
+    vendor: u16,
+    product: u16,
+}
+
+#[cfg(feature = "pure-rust")]
+impl DeviceIdentity {
+    fn of(device: &Device) -> Self {
+        let input_id = device.input_id();
+        Self {
+            name: device.name().unwrap_or("Unknown").to_string(),
+            vendor: input_id.vendor(),
+            product: input_id.product(),
+        }
+    }
+}
+
+/// Record a key event (`value` 1 press, 0 release) in the keys a device holds
+#[cfg(feature = "pure-rust")]
+fn track_held_key(held: &mut Vec<u16>, code: u16, value: i32) {
+    match value {
+        1 if !held.contains(&code) => held.push(code),
+        0 => held.retain(|held_code| *held_code != code),
+        _ => {}
+    }
+}
+
+/// Releases, then a report, for the keys a vanished device still held
+#[cfg(feature = "pure-rust")]
+fn release_events(held: &[u16]) -> Vec<InputEvent> {
+    let mut events: Vec<InputEvent> = held
+        .iter()
+        .rev()
+        .map(|code| InputEvent::new(EventType::KEY, *code, 0))
+        .collect();
+    if !events.is_empty() {
+        events.push(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0));
+    }
+    events
 }
 
 #[cfg(feature = "pure-rust")]
@@ -68,45 +162,46 @@ impl EventLoop {
     /// Virtual device prefix to filter out
     const VIRT_DEVICE_PREFIX: &str = "Keyrs (virtual)";
 
-    /// Poll flags indicating device disconnection
-    const DISCONNECT_FLAGS: libc::c_short =
-        libc::POLLHUP | libc::POLLERR | libc::POLLNVAL;
-
     /// Create a new event loop by finding keyboard devices
     pub fn new() -> EventLoopResult<Self> {
         Self::new_filtered(&[])
     }
 
     /// Create a new event loop with device filtering (no grab)
-    fn new_filtered(filter_names: &[String]) -> EventLoopResult<Self> {
+    pub fn new_filtered(filter_names: &[String]) -> EventLoopResult<Self> {
         let keyboards_with_paths = Self::find_keyboards_with_paths(filter_names)?;
         let udev_monitor = Self::create_udev_monitor()?;
-        let mut poll_fds = Vec::new();
-        
-        // udev fd at index 0 if available
-        if let Some(ref monitor) = udev_monitor {
-            poll_fds.push(libc::pollfd {
-                fd: monitor.as_raw_fd(),
-                events: libc::POLLIN,
-                revents: 0,
-            });
-        }
-        
+
         // Extract devices and paths
         let (device_paths, devices): (Vec<String>, Vec<Device>) = keyboards_with_paths
             .into_iter()
             .unzip();
-        
-        // Device fds follow
-        poll_fds.extend(Self::create_poll_fds(&devices));
-        
+
+        let (epoll, waker) = Self::create_epoll(udev_monitor.as_ref(), &devices)?;
+
         Ok(Self {
+            policies: vec![GrabPolicy::default(); devices.len()],
+            grab_policies: GrabPolicies::default(),
+            forwarded: Vec::new(),
             devices,
             device_paths,
-            poll_fds,
+            epoll,
+            waker,
+            watched: Vec::new(),
             grabbed: false,
             udev_monitor,
             device_filter: filter_names.to_vec(),
+            internal_policy: InternalKeyboardPolicy::default(),
+            internal_names: Vec::new(),
+            lid_switch: None,
+            lid_closed: false,
+            internal_ignored: false,
+            include_gamepads: false,
+            seat: None,
+            paused: false,
+            held_keys: HashMap::new(),
+            regrab_departed: false,
+            departed: Vec::new(),
         })
     }
 
@@ -120,6 +215,19 @@ impl EventLoop {
 
     /// Create a new event loop and grab filtered keyboard devices.
     pub fn new_with_grab_filtered(filter_names: &[String]) -> EventLoopResult<Self> {
+        Self::new_with_grab_after_release(filter_names, None)
+    }
+
+    /// Create a new event loop and grab filtered keyboard devices once no
+    /// key on them is held, waiting at most `release_wait` (`None` = grab
+    /// at once).
+    ///
+    /// A key grabbed while down (the Enter that started keyrs) would have
+    /// its release swallowed, leaving it stuck for the desktop.
+    pub fn new_with_grab_after_release(
+        filter_names: &[String],
+        release_wait: Option<Duration>,
+    ) -> EventLoopResult<Self> {
         let keyboards_with_paths = Self::find_keyboards_with_paths(filter_names)?;
         
         // Extract devices and paths
@@ -127,6 +235,12 @@ impl EventLoop {
             .into_iter()
             .unzip();
 
+        if let Some(timeout) = release_wait {
+            if !Self::wait_for_released_keys(&devices, timeout) {
+                log::warn!("Keys still held after {:?}; grabbing anyway", timeout);
+            }
+        }
+
         // Defensive: First try to ungrab all devices to handle the case where
         // a previous instance crashed. This ensures we start with a clean state.
         for device in &mut devices {
@@ -140,30 +254,60 @@ impl EventLoop {
         }
 
         let udev_monitor = Self::create_udev_monitor()?;
-        let mut poll_fds = Vec::new();
-        
-        // udev fd at index 0 if available
-        if let Some(ref monitor) = udev_monitor {
-            poll_fds.push(libc::pollfd {
-                fd: monitor.as_raw_fd(),
-                events: libc::POLLIN,
-                revents: 0,
-            });
-        }
-        
-        // Device fds follow
-        poll_fds.extend(Self::create_poll_fds(&devices));
-        
+        let (epoll, waker) = Self::create_epoll(udev_monitor.as_ref(), &devices)?;
+
         Ok(Self {
+            policies: vec![GrabPolicy::default(); devices.len()],
+            grab_policies: GrabPolicies::default(),
+            forwarded: Vec::new(),
             devices,
             device_paths,
-            poll_fds,
+            epoll,
+            waker,
+            watched: Vec::new(),
             grabbed: true,
             udev_monitor,
             device_filter: filter_names.to_vec(),
+            internal_policy: InternalKeyboardPolicy::default(),
+            internal_names: Vec::new(),
+            lid_switch: None,
+            lid_closed: false,
+            internal_ignored: false,
+            include_gamepads: false,
+            seat: None,
+            paused: false,
+            held_keys: HashMap::new(),
+            regrab_departed: false,
+            departed: Vec::new(),
         })
     }
 
+    /// Wait until no key is down on `devices`, for at most `timeout`;
+    /// false when keys are still held
+    fn wait_for_released_keys(devices: &[Device], timeout: Duration) -> bool {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+        let deadline = Instant::now() + timeout;
+        let mut logged = false;
+        loop {
+            let held: usize = devices
+                .iter()
+                .filter_map(|device| device.get_key_state().ok())
+                .map(|keys| keys.iter().count())
+                .sum();
+            if held == 0 {
+                return true;
+            }
+            if !logged {
+                log::info!("Waiting for {} held key(s) to be released before grabbing", held);
+                logged = true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
     /// Create udev monitor for hotplug detection
     fn create_udev_monitor() -> EventLoopResult<Option<MonitorSocket>> {
         let socket = udev::MonitorBuilder::new()
@@ -176,18 +320,321 @@ impl EventLoop {
         Ok(Some(socket))
     }
 
-    /// Create poll file descriptors from devices
-    fn create_poll_fds(devices: &[Device]) -> Vec<libc::pollfd> {
-        devices
+    /// Create the epoll set with the udev monitor, a waker and every device
+    fn create_epoll(udev_monitor: Option<&MonitorSocket>, devices: &[Device]) -> EventLoopResult<(Epoll, Waker)> {
+        let epoll = Epoll::new()?;
+        let waker = Waker::new()?;
+        epoll.add(waker.as_raw_fd())?;
+        if let Some(monitor) = udev_monitor {
+            epoll.add(monitor.as_raw_fd())?;
+        }
+        for device in devices {
+            epoll.add(device.as_raw_fd())?;
+        }
+        Ok((epoll, waker))
+    }
+
+    /// A handle that makes a blocked `wait_for_events` return early
+    pub fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    /// Also end a wait when one of `fds` becomes readable.
+    ///
+    /// Pass the full current set each time (e.g. a control socket and its
+    /// connections); fds no longer listed stop being watched. The owners
+    /// must drain them after each wait, or the wait returns at once.
+    pub fn set_watched_fds(&mut self, fds: &[RawFd]) {
+        let owned: Vec<RawFd> = self
+            .devices
+            .iter()
+            .map(|device| device.as_raw_fd())
+            .chain(self.udev_monitor.as_ref().map(|monitor| monitor.as_raw_fd()))
+            .chain([self.waker.as_raw_fd()])
+            .collect();
+        // A closed fd leaves the epoll set by itself, and its number may
+        // have been reused by a device since: never remove those
+        for fd in &self.watched {
+            if !fds.contains(fd) && !owned.contains(fd) {
+                self.epoll.remove(*fd);
+            }
+        }
+        for fd in fds {
+            if !self.watched.contains(fd) && !owned.contains(fd) {
+                if let Err(e) = self.epoll.add(*fd) {
+                    log::debug!("Could not watch fd {}: {}", fd, e);
+                }
+            }
+        }
+        self.watched = fds.to_vec();
+    }
+
+    /// Configure when events from the internal keyboard are ignored.
+    ///
+    /// The internal keyboard stays grabbed while ignored so a faulty
+    /// built-in keyboard cannot leak ghost input in clamshell mode.
+    pub fn set_internal_keyboard_policy(
+        &mut self,
+        policy: InternalKeyboardPolicy,
+        internal_names: &[String],
+    ) {
+        self.internal_policy = policy;
+        self.internal_names = internal_names.to_vec();
+        self.internal_ignored = false;
+        if let Some(lid) = self.lid_switch.take() {
+            self.epoll.remove(lid.as_raw_fd());
+        }
+        self.lid_closed = false;
+        if !policy.uses_lid() {
+            return;
+        }
+        let Some(lid) = Self::find_lid_switch() else {
+            log::warn!("internal_policy needs a lid switch, but none was found");
+            return;
+        };
+        if let Err(e) = self.epoll.add(lid.as_raw_fd()) {
+            log::warn!("Could not watch the lid switch: {}", e);
+            return;
+        }
+        self.lid_closed = Self::lid_state(&lid);
+        self.lid_switch = Some(lid);
+    }
+
+    /// Set how each device is grabbed.
+    ///
+    /// Devices this loop grabbed are released when their policy is
+    /// `observe`; later devices follow the policies when plugged in.
+    pub fn set_grab_policies(&mut self, policies: GrabPolicies) {
+        for (i, device) in self.devices.iter_mut().enumerate() {
+            let name = device.name().unwrap_or("Unknown").to_string();
+            let policy = policies.for_device(&name, &self.device_paths[i]);
+            if self.grabbed && !policy.grabs() {
+                if let Err(e) = device.ungrab() {
+                    log::warn!("Could not release {}: {}", name, e);
+                }
+            }
+            if policy != GrabPolicy::Full {
+                log::info!("Device {} grab policy: {:?}", name, policy);
+            }
+            self.policies[i] = policy;
+        }
+        self.grab_policies = policies;
+    }
+
+    /// Only use devices of `seat` (`None` = any seat).
+    ///
+    /// Devices of other seats are released and dropped now; later ones are
+    /// not picked up.
+    pub fn set_seat(&mut self, seat: Option<String>) {
+        self.seat = seat;
+        let Some(seat) = self.seat.as_deref() else {
+            return;
+        };
+        for i in (0..self.devices.len()).rev() {
+            let device_seat = Self::device_seat(&self.device_paths[i]);
+            if is_on_seat(device_seat.as_deref(), seat) {
+                continue;
+            }
+            let mut device = self.devices.remove(i);
+            log::info!(
+                "Not using {} ({}): it belongs to {}",
+                device.name().unwrap_or("Unknown"),
+                self.device_paths[i],
+                device_seat.as_deref().unwrap_or(DEFAULT_SEAT)
+            );
+            if self.grabbed {
+                let _ = device.ungrab();
+            }
+            self.epoll.remove(device.as_raw_fd());
+            self.device_paths.remove(i);
+            self.policies.remove(i);
+        }
+    }
+
+    /// Seat udev assigns the input device at `path` (`ID_SEAT` of the event
+    /// node or its input device), `None` when unassigned
+    fn device_seat(path: &str) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let devnum = std::fs::metadata(path).ok()?.rdev();
+        let device = udev::Device::from_devnum(udev::DeviceType::Character, devnum).ok()?;
+        let seat_of = |device: &udev::Device| {
+            device
+                .property_value("ID_SEAT")
+                .map(|seat| seat.to_string_lossy().into_owned())
+        };
+        seat_of(&device).or_else(|| device.parent().as_ref().and_then(seat_of))
+    }
+
+    /// Release the devices (so another session on the seat gets its input)
+    /// and drop their events while `paused`; grab them again when resumed
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+        self.paused = paused;
+        if !self.grabbed {
+            return;
+        }
+        for (device, policy) in self.devices.iter_mut().zip(&self.policies) {
+            if !policy.grabs() {
+                continue;
+            }
+            let result = if paused { device.ungrab() } else { device.grab() };
+            if let Err(e) = result {
+                log::warn!(
+                    "Could not {} {}: {}",
+                    if paused { "release" } else { "grab" },
+                    device.name().unwrap_or("Unknown"),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Grab a device that vanished again when it comes back with the same
+    /// name and USB ids, even if its new event node does not match the
+    /// device filter (`--watch`)
+    pub fn set_regrab_departed(&mut self, regrab: bool) {
+        self.regrab_departed = regrab;
+        if !regrab {
+            self.departed.clear();
+        }
+    }
+
+    /// Frames of non-key events (scancodes, switches, pointer motion of
+    /// keys-only devices) read since the last call, as (type, code, value)
+    /// triples to re-emit
+    pub fn take_forwarded(&mut self) -> Vec<Vec<(u16, u16, i32)>> {
+        std::mem::take(&mut self.forwarded)
+    }
+
+    /// Switches (`SW_*` codes) the current devices report, for the virtual
+    /// keyboard to advertise
+    pub fn supported_switches(&self) -> Vec<u16> {
+        let mut switches: Vec<u16> = self
+            .devices
             .iter()
-            .map(|d| libc::pollfd {
-                fd: d.as_raw_fd(),
-                events: libc::POLLIN,
-                revents: 0,
+            .zip(&self.policies)
+            .filter(|(_, policy)| policy.grabs())
+            .filter_map(|(device, _)| device.supported_switches())
+            .flat_map(|switches| switches.iter().map(|switch| switch.0))
+            .collect();
+        switches.sort_unstable();
+        switches.dedup();
+        switches
+    }
+
+    /// Also autodetect gamepads and joysticks (BTN_GAMEPAD/BTN_JOYSTICK).
+    ///
+    /// Connected gamepads are added (and grabbed, if this loop grabs) now;
+    /// later ones are picked up by hotplug.
+    pub fn set_include_gamepads(&mut self, include: bool) {
+        self.include_gamepads = include;
+        if !include {
+            return;
+        }
+        let paths: Vec<String> = evdev::enumerate()
+            .filter_map(|(path, _)| path.to_str().map(|s| s.to_string()))
+            .collect();
+        for path in paths {
+            self.try_add_device(&path);
+        }
+    }
+
+    /// Find a device reporting the lid switch (SW_LID)
+    fn find_lid_switch() -> Option<Device> {
+        evdev::enumerate()
+            .map(|(_, device)| device)
+            .find(|device| {
+                device
+                    .supported_switches()
+                    .map(|switches| switches.contains(SwitchType::SW_LID))
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Query whether the lid is closed
+    fn lid_state(lid: &Device) -> bool {
+        lid.get_switch_state()
+            .map(|state| state.contains(SwitchType::SW_LID))
+            .unwrap_or(false)
+    }
+
+    /// Drain the lid switch after epoll reported it readable and take its state
+    fn handle_lid_events(&mut self) {
+        let Some(lid) = self.lid_switch.as_mut() else { return };
+        // The events themselves may have been dropped on overflow, so take
+        // the state the kernel holds once they are drained
+        let error = match lid.fetch_events() {
+            Ok(events) => {
+                events.for_each(drop);
+                None
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return,
+            Err(e) => Some(e),
+        };
+        match error {
+            None => self.lid_closed = Self::lid_state(lid),
+            Some(e) => {
+                log::warn!("Lid switch read failed, no longer watching it: {}", e);
+                self.epoll.remove(lid.as_raw_fd());
+                self.lid_switch = None;
+                self.lid_closed = false;
+            }
+        }
+    }
+
+    /// Release, as events of the device at `path`, the keys it holds down
+    fn release_held_keys(&mut self, path: &str, device_name: &str) -> Vec<PolledEvent> {
+        let held = self.held_keys.remove(path).unwrap_or_default();
+        if !held.is_empty() {
+            log::info!("Releasing {} key(s) held on {}", held.len(), device_name);
+        }
+        release_events(&held)
+            .into_iter()
+            .map(|event| PolledEvent {
+                event,
+                device_name: device_name.to_string(),
             })
             .collect()
     }
 
+    fn is_internal_device(&self, device: &Device, path: &str) -> bool {
+        is_internal_keyboard(
+            device.name().unwrap_or("Unknown"),
+            path,
+            device.physical_path(),
+            &self.internal_names,
+        )
+    }
+
+    /// Evaluate the internal keyboard policy against current lid/device state
+    fn internal_keyboard_ignored(&mut self) -> bool {
+        if self.internal_policy == InternalKeyboardPolicy::Always {
+            return false;
+        }
+
+        let lid_closed = self.lid_closed;
+        let external_present = self
+            .devices
+            .iter()
+            .zip(&self.device_paths)
+            .any(|(device, path)| !self.is_internal_device(device, path));
+
+        let ignore = self.internal_policy.should_ignore(lid_closed, external_present);
+        if ignore != self.internal_ignored {
+            log::info!(
+                "Internal keyboard {} (lid_closed={}, external_present={})",
+                if ignore { "ignored" } else { "active" },
+                lid_closed,
+                external_present
+            );
+            self.internal_ignored = ignore;
+        }
+        ignore
+    }
+
     /// Ungrab all devices (called on shutdown)
     pub fn ungrab_all(&mut self) {
         if self.grabbed {
@@ -209,10 +656,13 @@ impl EventLoop {
             if Self::is_keyboard_device(&device) {
                 let name = device.name().unwrap_or("Unknown").to_string();
                 let device_path = path.to_str().map(|s| s.to_string());
+                let input_id = device.input_id();
                 devices_info.push(DeviceInfo {
                     index,
                     name,
                     path: device_path,
+                    vendor_id: input_id.vendor(),
+                    product_id: input_id.product(),
                 });
                 index += 1;
             }
@@ -227,6 +677,25 @@ impl EventLoop {
         Ok(devices_info)
     }
 
+    /// Key codes supported by the keyboards that would be grabbed with `filter_names`
+    pub fn keyboard_keys(filter_names: &[String]) -> EventLoopResult<HashSet<u16>> {
+        let mut codes = HashSet::new();
+        for (_, device) in Self::find_keyboards_with_paths(filter_names)? {
+            if let Some(keys) = device.supported_keys() {
+                codes.extend(keys.iter().map(|key| key.code()));
+            }
+        }
+        Ok(codes)
+    }
+
+    /// Device nodes of the keyboards that would be grabbed with `filter_names`
+    pub fn keyboard_paths(filter_names: &[String]) -> EventLoopResult<Vec<String>> {
+        Ok(Self::find_keyboards_with_paths(filter_names)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
     /// Find keyboard devices honoring explicit filter names/paths.
     /// Returns (device_node_path, device) pairs.
     fn find_keyboards_with_paths(filter_names: &[String]) -> EventLoopResult<Vec<(String, Device)>> {
@@ -296,9 +765,26 @@ impl EventLoop {
         qwerty_present && az_present
     }
 
+    /// Check if a device is a gamepad or joystick (never the keyrs virtual device)
+    fn is_gamepad_device(device: &Device) -> bool {
+        let device_name = device.name().unwrap_or("");
+        if is_virtual_device(device_name, Self::VIRT_DEVICE_PREFIX) {
+            return false;
+        }
+        let supported_keys = device
+            .supported_keys()
+            .map(|keys| keys.iter().map(|key| key.code()).collect())
+            .unwrap_or_default();
+        let capabilities = DeviceCapabilities::new(
+            device.supported_events().contains(EventType::KEY),
+            supported_keys,
+        );
+        is_gamepad(&capabilities)
+    }
+
     /// Poll for events with timeout (non-blocking)
     ///
-    /// This method uses libc::poll() to efficiently wait for events
+    /// This method uses epoll to efficiently wait for events
     /// across multiple devices without busy-waiting.
     ///
     /// # Arguments
@@ -320,51 +806,66 @@ impl EventLoop {
         &mut self,
         timeout_ms: i32,
     ) -> EventLoopResult<Vec<PolledEvent>> {
-        let mut events = Vec::new();
-
-        // Wait for events
-        let poll_result = unsafe {
-            libc::poll(
-                self.poll_fds.as_mut_ptr(),
-                self.poll_fds.len() as libc::nfds_t,
-                timeout_ms,
-            )
-        };
-
-        if poll_result < 0 {
-            let err = std::io::Error::last_os_error();
-            // EINTR (Interrupted system call) is not a fatal error - it just means
-            // a signal was delivered (e.g., Ctrl+C). We should treat it like
-            // a timeout and return no events. The caller will check running flag
-            // and exit if needed.
-            if err.raw_os_error() == Some(4) {
-                // EINTR on Linux
-                return Ok(events);
-            }
-            return Err(EventLoopError::Io(err));
-        }
+        self.wait_for_events(u64::try_from(timeout_ms).ok().map(Duration::from_millis))
+    }
 
-        if poll_result == 0 {
-            // Timeout - no events
+    /// Wait until input arrives, a watched fd is readable, the waker fires or
+    /// `timeout` passes (`None` = no timeout), then read every ready device.
+    ///
+    /// Returns the events read, which is empty when the wait ended for any
+    /// other reason. An interrupting signal also ends the wait with no events.
+    pub fn wait_for_events(&mut self, timeout: Option<Duration>) -> EventLoopResult<Vec<PolledEvent>> {
+        let mut events = Vec::new();
+        let ready = self.epoll.wait(timeout)?;
+        if ready.is_empty() {
             return Ok(events);
         }
 
-        // Check udev events first (index 0 if present)
-        let udev_offset = if self.udev_monitor.is_some() { 1 } else { 0 };
-        if udev_offset > 0 && self.poll_fds[0].revents & libc::POLLIN != 0 {
+        if ready.iter().any(|r| r.fd == self.waker.as_raw_fd()) {
+            self.waker.drain();
+        }
+        let udev_fd = self.udev_monitor.as_ref().map(|monitor| monitor.as_raw_fd());
+        if ready.iter().any(|r| Some(r.fd) == udev_fd && r.readable) {
             self.handle_udev_events();
         }
+        let lid_fd = self.lid_switch.as_ref().map(|lid| lid.as_raw_fd());
+        if ready.iter().any(|r| Some(r.fd) == lid_fd) {
+            self.handle_lid_events();
+        }
+
+        // Devices whose events are drained but dropped (internal keyboard policy)
+        let was_ignored = self.internal_ignored;
+        let ignored: Vec<bool> = if self.internal_keyboard_ignored() {
+            self.devices
+                .iter()
+                .zip(&self.device_paths)
+                .map(|(device, path)| self.is_internal_device(device, path))
+                .collect()
+        } else {
+            vec![false; self.devices.len()]
+        };
+        // Keys the internal keyboard held when it became ignored would never
+        // see their release, so release them now
+        if !was_ignored && self.internal_ignored {
+            for i in (0..self.devices.len()).filter(|i| ignored[*i]) {
+                let path = self.device_paths[i].clone();
+                let device_name = self.devices[i].name().unwrap_or("Unknown").to_string();
+                events.extend(self.release_held_keys(&path, &device_name));
+            }
+        }
 
         // Read events from devices that have data available
         // Track disconnected devices for removal
         let mut disconnected_indices: Vec<usize> = Vec::new();
 
         for (i, device) in self.devices.iter_mut().enumerate() {
-            let poll_idx = i + udev_offset;
-            let revents = self.poll_fds[poll_idx].revents;
+            let fd = device.as_raw_fd();
+            let Some(readiness) = ready.iter().find(|r| r.fd == fd) else {
+                continue;
+            };
 
             // Check for device disconnection first
-            if revents & Self::DISCONNECT_FLAGS != 0 {
+            if readiness.hangup {
                 let device_name = device.name().unwrap_or("Unknown");
                 log::warn!("Device disconnected: {}", device_name);
                 disconnected_indices.push(i);
@@ -372,25 +873,72 @@ impl EventLoop {
             }
 
             // Normal event processing
-            if revents & libc::POLLIN != 0 {
+            if readiness.readable {
                 let device_name = device.name().unwrap_or("Unknown").to_string();
-                if let Ok(device_events) = device.fetch_events() {
-                    for event in device_events {
-                        events.push(PolledEvent {
-                            event,
-                            device_name: device_name.clone(),
-                        });
+                let policy = self.policies[i];
+                match device.fetch_events() {
+                    Ok(device_events) => {
+                        // Events were read (drained) but are dropped while
+                        // ignored; an observed device's events already reached the desktop
+                        if ignored[i] || !policy.grabs() || self.paused {
+                            continue;
+                        }
+                        let mut frame = Vec::new();
+                        for event in device_events {
+                            if policy.forwards(event.event_type().0, event.code()) {
+                                frame.push((event.event_type().0, event.code(), event.value()));
+                                // Scancodes and switches are still seen by the caller
+                                if event.event_type() == EventType::RELATIVE {
+                                    continue;
+                                }
+                            }
+                            if event.event_type() == EventType::SYNCHRONIZATION && !frame.is_empty() {
+                                self.forwarded.push(std::mem::take(&mut frame));
+                            }
+                            if event.event_type() == EventType::KEY {
+                                let held = self.held_keys.entry(self.device_paths[i].clone()).or_default();
+                                track_held_key(held, event.code(), event.value());
+                            }
+                            events.push(PolledEvent {
+                                event,
+                                device_name: device_name.clone(),
+                            });
+                        }
+                        if !frame.is_empty() {
+                            self.forwarded.push(frame);
+                        }
+                    }
+                    // ENODEV: unplugged before the hangup was reported
+                    Err(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                        log::warn!("Device disconnected: {}", device_name);
+                        disconnected_indices.push(i);
+                    }
+                    Err(e)
+                        if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted) => {}
+                    // A device that keeps failing would wake every wait
+                    Err(e) => {
+                        log::warn!("Dropping device {} after read error: {}", device_name, e);
+                        disconnected_indices.push(i);
                     }
                 }
             }
         }
 
-        // Remove disconnected devices (reverse order to maintain valid indices)
+        // Remove disconnected devices (reverse order to maintain valid indices),
+        // releasing the keys they held so none stays down in the output
         for i in disconnected_indices.into_iter().rev() {
-            let poll_idx = i + udev_offset;
-            self.devices.remove(i);
-            self.device_paths.remove(i);
-            self.poll_fds.remove(poll_idx);
+            self.epoll.remove(self.devices[i].as_raw_fd());
+            let device = self.devices.remove(i);
+            let path = self.device_paths.remove(i);
+            self.policies.remove(i);
+
+            let device_name = device.name().unwrap_or("Unknown").to_string();
+            events.extend(self.release_held_keys(&path, &device_name));
+
+            let identity = DeviceIdentity::of(&device);
+            if self.regrab_departed && !self.departed.contains(&identity) {
+                self.departed.push(identity);
+            }
         }
 
         Ok(events)
@@ -437,10 +985,13 @@ impl EventLoop {
         // Check if it's a keyboard device we want
         let device_name = device.name().unwrap_or("Unknown").to_string();
         let device_path = path;
-        let is_keyboard = Self::is_keyboard_device(&device);
+        let is_keyboard = Self::is_keyboard_device(&device)
+            || (self.include_gamepads && Self::is_gamepad_device(&device));
         let is_virtual = is_virtual_device(&device_name, Self::VIRT_DEVICE_PREFIX);
-        
-        if !matches_device_filter(
+        let identity = DeviceIdentity::of(&device);
+        let returning = !is_virtual && self.departed.contains(&identity);
+
+        if !returning && !matches_device_filter(
             &device_name,
             device_path,
             &self.device_filter,
@@ -450,27 +1001,41 @@ impl EventLoop {
         ) {
             return;
         }
+        if let Some(seat) = self.seat.as_deref() {
+            if !is_on_seat(Self::device_seat(path).as_deref(), seat) {
+                log::debug!("Not using {} ({}): not on {}", device_name, path, seat);
+                return;
+            }
+        }
         
         // Grab if needed
-        if self.grabbed {
+        let policy = self.grab_policies.for_device(&device_name, device_path);
+        if self.grabbed && policy.grabs() && !self.paused {
             if let Err(e) = device.grab() {
                 log::warn!("Could not grab new device {}: {}", device_name, e);
                 return;
             }
         }
         
-        log::info!("Device connected: {} ({})", device_name, path);
+        if returning {
+            self.departed.retain(|departed| *departed != identity);
+            log::info!("Device reconnected: {} ({})", device_name, path);
+        } else {
+            log::info!("Device connected: {} ({})", device_name, path);
+        }
+        if policy != GrabPolicy::Full {
+            log::info!("Device {} grab policy: {:?}", device_name, policy);
+        }
         
+        if let Err(e) = self.epoll.add(device.as_raw_fd()) {
+            log::warn!("Could not watch new device {}: {}", device_name, e);
+            return;
+        }
+
         // Track the device path
         self.device_paths.push(path.to_string());
-        
-        // Add to poll_fds (at the end)
-        self.poll_fds.push(libc::pollfd {
-            fd: device.as_raw_fd(),
-            events: libc::POLLIN,
-            revents: 0,
-        });
         self.devices.push(device);
+        self.policies.push(policy);
     }
 
     /// Fetch a single event from any device (blocking)
@@ -535,6 +1100,35 @@ impl EventLoop {
             .collect()
     }
 
+    fn has_lock_leds(device: &Device) -> bool {
+        device.supported_leds().is_some_and(|leds| {
+            leds.contains(LedType::LED_NUML) || leds.contains(LedType::LED_CAPSL)
+        })
+    }
+
+    /// Lock state shown by the LEDs of the first device that has lock LEDs
+    pub fn lock_state(&self) -> Option<LockState> {
+        self.devices
+            .iter()
+            .filter(|device| Self::has_lock_leds(device))
+            .find_map(|device| device.get_led_state().ok())
+            .map(|lit| LockState::from_lit_leds(lit.iter().map(|led| led.0)))
+    }
+
+    /// Show `state` on the lock LEDs of every device that has them
+    pub fn set_lock_leds(&mut self, state: LockState) {
+        let events: Vec<InputEvent> = state
+            .led_values()
+            .iter()
+            .map(|(code, value)| InputEvent::new(EventType::LED, *code, *value))
+            .collect();
+        for device in self.devices.iter_mut().filter(|device| Self::has_lock_leds(device)) {
+            if let Err(e) = device.send_events(&events) {
+                log::debug!("Could not set LEDs on {}: {}", device.name().unwrap_or("Unknown"), e);
+            }
+        }
+    }
+
     /// Get number of devices managed by this event loop
     pub fn device_count(&self) -> usize {
         self.devices.len()
@@ -567,6 +1161,26 @@ impl Default for EventLoop {
 mod tests {
     use super::*;
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_keys_of_vanished_device_are_released() {
+        let mut held = Vec::new();
+        track_held_key(&mut held, 29, 1); // LEFTCTRL
+        track_held_key(&mut held, 30, 1); // A
+        track_held_key(&mut held, 30, 2); // repeat
+        track_held_key(&mut held, 31, 1); // S
+        track_held_key(&mut held, 31, 0);
+        assert_eq!(held, vec![29, 30]);
+
+        let events = release_events(&held);
+        let summary: Vec<(u16, u16, i32)> = events
+            .iter()
+            .map(|event| (event.event_type().0, event.code(), event.value()))
+            .collect();
+        assert_eq!(summary, vec![(1, 30, 0), (1, 29, 0), (0, 0, 0)]);
+        assert!(release_events(&[]).is_empty());
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_event_loop_creation() {
@@ -2,17 +2,27 @@
 // Pure Rust event loop and processing
 
 pub mod batch;
+pub mod reactor;
 #[cfg(feature = "pure-rust")]
 pub mod r#loop;
+#[cfg(feature = "pure-rust")]
+mod source;
+#[cfg(feature = "pure-rust")]
+mod guard;
 
 #[cfg(feature = "python-runtime")]
 pub mod hybrid;
 
 pub use batch::{batch_config, EventBatch};
+pub use reactor::{Epoll, Readiness, Timer, Waker};
 #[cfg(feature = "pure-rust")]
 pub use evdev::InputEvent;
 #[cfg(feature = "pure-rust")]
-pub use r#loop::{DeviceInfo, EventLoop, EventLoopError, EventLoopResult};
+pub use r#loop::{DeviceInfo, EventLoop, EventLoopError, EventLoopResult, PolledEvent};
+#[cfg(feature = "pure-rust")]
+pub use source::InputSource;
+#[cfg(feature = "pure-rust")]
+pub use guard::{stop_on_panic, DeviceGuard};
 
 #[cfg(feature = "python-runtime")]
 pub use hybrid::{EventReader, HybridError, HybridResult, RawInputEvent, TransformResult};
@@ -0,0 +1,242 @@
+// Keyrs Reactor
+// epoll readiness for input devices, sockets and cross-thread wakeups
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A file descriptor epoll reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Readiness {
+    pub fd: RawFd,
+    /// Data can be read
+    pub readable: bool,
+    /// The other end is gone (device unplugged, error)
+    pub hangup: bool,
+}
+
+/// Level-triggered epoll set keyed by file descriptor
+#[derive(Debug)]
+pub struct Epoll {
+    fd: OwnedFd,
+}
+
+impl Epoll {
+    /// Events fetched per `epoll_wait`; the rest stay ready for the next call
+    const MAX_EVENTS: usize = 32;
+
+    pub fn new() -> std::io::Result<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Watch `fd` for input
+    pub fn add(&self, fd: RawFd) -> std::io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: fd as u64,
+        };
+        if unsafe { libc::epoll_ctl(self.fd.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Stop watching `fd` (closing an fd also removes it)
+    pub fn remove(&self, fd: RawFd) {
+        unsafe {
+            libc::epoll_ctl(self.fd.as_raw_fd(), libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut());
+        }
+    }
+
+    /// Wait until a watched fd is ready or `timeout` passes (`None` = no
+    /// timeout). A signal interrupting the wait returns nothing.
+    pub fn wait(&self, timeout: Option<Duration>) -> std::io::Result<Vec<Readiness>> {
+        // Round up so a deadline is never woken for early and then spun on
+        let timeout_ms = timeout.map_or(-1, |timeout| {
+            timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+        });
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; Self::MAX_EVENTS];
+        let count = unsafe {
+            libc::epoll_wait(
+                self.fd.as_raw_fd(),
+                events.as_mut_ptr(),
+                Self::MAX_EVENTS as libc::c_int,
+                timeout_ms,
+            )
+        };
+        if count < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(err);
+        }
+        Ok(events[..count as usize]
+            .iter()
+            .map(|event| {
+                let flags = event.events;
+                Readiness {
+                    fd: event.u64 as RawFd,
+                    readable: flags & libc::EPOLLIN as u32 != 0,
+                    hangup: flags & (libc::EPOLLHUP | libc::EPOLLERR) as u32 != 0,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Wakes an event loop blocked in `epoll_wait` from another thread
+/// (e.g. the Wayland thread on a focus change).
+#[derive(Debug, Clone)]
+pub struct Waker {
+    fd: Arc<OwnedFd>,
+}
+
+impl Waker {
+    pub fn new() -> std::io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: Arc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+        })
+    }
+
+    /// Make the waiting loop return
+    pub fn wake(&self) {
+        let one: u64 = 1;
+        unsafe {
+            libc::write(self.fd.as_raw_fd(), (&one as *const u64).cast(), 8);
+        }
+    }
+
+    /// Reset after a wakeup so the fd stops being ready
+    pub fn drain(&self) {
+        let mut count: u64 = 0;
+        unsafe {
+            libc::read(self.fd.as_raw_fd(), (&mut count as *mut u64).cast(), 8);
+        }
+    }
+}
+
+impl AsRawFd for Waker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// One-shot monotonic timer (timerfd) that becomes readable when it expires,
+/// so a deadline wakes `epoll_wait` exactly instead of by its ms timeout.
+#[derive(Debug)]
+pub struct Timer {
+    fd: OwnedFd,
+}
+
+impl Timer {
+    pub fn new() -> std::io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Fire once after `delay`, replacing any earlier deadline; `None` disarms
+    pub fn arm(&self, delay: Option<Duration>) -> std::io::Result<()> {
+        // An all-zero value disarms, so an overdue deadline fires in 1ns
+        let delay = delay.map_or(Duration::ZERO, |delay| delay.max(Duration::from_nanos(1)));
+        let value = libc::timespec {
+            tv_sec: delay.as_secs() as libc::time_t,
+            tv_nsec: delay.subsec_nanos() as libc::c_long,
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: value,
+        };
+        if unsafe { libc::timerfd_settime(self.fd.as_raw_fd(), 0, &spec, std::ptr::null_mut()) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Reset after expiry so the fd stops being ready
+    pub fn drain(&self) {
+        let mut expirations: u64 = 0;
+        unsafe {
+            libc::read(self.fd.as_raw_fd(), (&mut expirations as *mut u64).cast(), 8);
+        }
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoll_wakes_on_waker() {
+        let epoll = Epoll::new().unwrap();
+        let waker = Waker::new().unwrap();
+        epoll.add(waker.as_raw_fd()).unwrap();
+
+        assert!(epoll.wait(Some(Duration::ZERO)).unwrap().is_empty());
+
+        let remote = waker.clone();
+        std::thread::spawn(move || remote.wake()).join().unwrap();
+        let ready = epoll.wait(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(
+            ready,
+            vec![Readiness {
+                fd: waker.as_raw_fd(),
+                readable: true,
+                hangup: false,
+            }]
+        );
+
+        // Level-triggered until drained
+        assert_eq!(epoll.wait(Some(Duration::ZERO)).unwrap().len(), 1);
+        waker.drain();
+        assert!(epoll.wait(Some(Duration::ZERO)).unwrap().is_empty());
+
+        epoll.remove(waker.as_raw_fd());
+        waker.wake();
+        assert!(epoll.wait(Some(Duration::ZERO)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_timer_fires_once() {
+        let epoll = Epoll::new().unwrap();
+        let timer = Timer::new().unwrap();
+        epoll.add(timer.as_raw_fd()).unwrap();
+
+        timer.arm(Some(Duration::from_millis(5))).unwrap();
+        assert!(epoll.wait(Some(Duration::ZERO)).unwrap().is_empty());
+        assert_eq!(epoll.wait(Some(Duration::from_secs(5))).unwrap().len(), 1);
+        timer.drain();
+        assert!(epoll.wait(Some(Duration::from_millis(20))).unwrap().is_empty());
+
+        // Overdue deadlines still fire; disarming cancels
+        timer.arm(Some(Duration::ZERO)).unwrap();
+        assert_eq!(epoll.wait(Some(Duration::from_secs(5))).unwrap().len(), 1);
+        timer.drain();
+        timer.arm(Some(Duration::from_millis(5))).unwrap();
+        timer.arm(None).unwrap();
+        assert!(epoll.wait(Some(Duration::from_millis(20))).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+// Keyrs Input Source
+// Where the daemon reads input from: its own devices or the input helper
+
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use super::r#loop::{EventLoop, EventLoopResult, PolledEvent};
+use super::reactor::Waker;
+use crate::input::{KeyboardDeviceInfo, LockState};
+
+/// Devices the main loop reads events from and controls.
+///
+/// `EventLoop` reads the devices itself; `RemoteInput` gets their events
+/// from the privileged input helper.
+pub trait InputSource {
+    /// A handle that makes a blocked `wait_for_events` return early
+    fn waker(&self) -> Waker;
+
+    /// Also end a wait when one of `fds` becomes readable
+    fn set_watched_fds(&mut self, fds: &[RawFd]);
+
+    /// Wait for input, a watched fd, the waker or `timeout`, and return the events read
+    fn wait_for_events(&mut self, timeout: Option<Duration>) -> EventLoopResult<Vec<PolledEvent>>;
+
+    /// Frames of non-key events to re-emit, as (type, code, value) triples
+    fn take_forwarded(&mut self) -> Vec<Vec<(u16, u16, i32)>>;
+
+    /// Names of the devices read
+    fn device_names(&self) -> Vec<String>;
+
+    /// Number of devices read
+    fn device_count(&self) -> usize;
+
+    /// Keyboard detection info of the devices read
+    fn keyboard_detection_infos(&self) -> Vec<KeyboardDeviceInfo>;
+
+    /// Lock state shown by the keyboard LEDs, when known
+    fn lock_state(&self) -> Option<LockState>;
+
+    /// Show `state` on the keyboards' lock LEDs
+    fn set_lock_leds(&mut self, state: LockState);
+
+    /// Release the devices and drop their events while `paused`
+    fn set_paused(&mut self, paused: bool);
+
+    /// Release every grabbed device
+    fn ungrab_all(&mut self);
+}
+
+impl InputSource for EventLoop {
+    fn waker(&self) -> Waker {
+        EventLoop::waker(self)
+    }
+
+    fn set_watched_fds(&mut self, fds: &[RawFd]) {
+        EventLoop::set_watched_fds(self, fds)
+    }
+
+    fn wait_for_events(&mut self, timeout: Option<Duration>) -> EventLoopResult<Vec<PolledEvent>> {
+        EventLoop::wait_for_events(self, timeout)
+    }
+
+    fn take_forwarded(&mut self) -> Vec<Vec<(u16, u16, i32)>> {
+        EventLoop::take_forwarded(self)
+    }
+
+    fn device_names(&self) -> Vec<String> {
+        EventLoop::device_names(self)
+    }
+
+    fn device_count(&self) -> usize {
+        EventLoop::device_count(self)
+    }
+
+    fn keyboard_detection_infos(&self) -> Vec<KeyboardDeviceInfo> {
+        EventLoop::keyboard_detection_infos(self)
+    }
+
+    fn lock_state(&self) -> Option<LockState> {
+        EventLoop::lock_state(self)
+    }
+
+    fn set_lock_leds(&mut self, state: LockState) {
+        EventLoop::set_lock_leds(self, state)
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        EventLoop::set_paused(self, paused)
+    }
+
+    fn ungrab_all(&mut self) {
+        EventLoop::ungrab_all(self)
+    }
+}
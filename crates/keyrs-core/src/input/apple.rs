@@ -0,0 +1,144 @@
+// Keyrs Input Layer - Apple Keyboards
+// hid_apple Fn key handling: the fnmode parameter and Fn-translated keys
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::Key;
+
+/// `KEY_FN` in input-event-codes.h
+pub const KEY_FN: u16 = 0x1d0;
+
+/// The hid_apple `fnmode` module parameter
+pub const FNMODE_PATH: &str = "/sys/module/hid_apple/parameters/fnmode";
+
+/// What the F-row of an Apple keyboard sends, per hid_apple's `fnmode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FnMode {
+    /// Fn is ignored; the F-row always sends F1..F12
+    Disabled,
+    /// Media keys by default, F1..F12 with Fn held (the driver default)
+    Media,
+    /// F1..F12 by default, media keys with Fn held
+    FunctionKeys,
+    /// Chosen by the driver per keyboard model
+    Auto,
+}
+
+impl FnMode {
+    /// Parse a mode name (`media`, `fkeys`, `disabled`, `auto`) or its number
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "0" | "disabled" | "off" => Some(Self::Disabled),
+            "1" | "media" => Some(Self::Media),
+            "2" | "fkeys" | "function" => Some(Self::FunctionKeys),
+            "3" | "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    /// Name accepted by `from_name`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Media => "media",
+            Self::FunctionKeys => "fkeys",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// Value of the `fnmode` parameter
+    pub fn value(self) -> u8 {
+        match self {
+            Self::Disabled => 0,
+            Self::Media => 1,
+            Self::FunctionKeys => 2,
+            Self::Auto => 3,
+        }
+    }
+}
+
+impl fmt::Display for FnMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Read the `fnmode` parameter at `path` (usually `FNMODE_PATH`)
+pub fn read_fn_mode(path: &Path) -> io::Result<FnMode> {
+    let content = std::fs::read_to_string(path)?;
+    FnMode::from_name(&content).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("unexpected fnmode '{}'", content.trim()))
+    })
+}
+
+/// Set the `fnmode` parameter at `path`; needs root, and lasts until the
+/// driver is reloaded
+pub fn write_fn_mode(path: &Path, mode: FnMode) -> io::Result<()> {
+    std::fs::write(path, format!("{}\n", mode.value()))
+}
+
+/// Keys hid_apple sends instead of a key pressed together with Fn, as
+/// (sent, physical). The F-row entries apply to whichever half of the row
+/// `fnmode` puts behind Fn.
+const FN_TRANSLATED_KEYS: &[(u16, u16)] = &[
+    (111, 14),  // DELETE <- BACKSPACE
+    (110, 28),  // INSERT <- ENTER
+    (104, 103), // PAGEUP <- UP
+    (109, 108), // PAGEDOWN <- DOWN
+    (102, 105), // HOME <- LEFT
+    (107, 106), // END <- RIGHT
+    (224, 59),  // BRIGHTNESSDOWN <- F1
+    (225, 60),  // BRIGHTNESSUP <- F2
+    (120, 61),  // SCALE <- F3
+    (204, 62),  // DASHBOARD <- F4
+    (229, 63),  // KBDILLUMDOWN <- F5
+    (230, 64),  // KBDILLUMUP <- F6
+    (165, 65),  // PREVIOUSSONG <- F7
+    (164, 66),  // PLAYPAUSE <- F8
+    (163, 67),  // NEXTSONG <- F9
+    (113, 68),  // MUTE <- F10
+    (114, 87),  // VOLUMEDOWN <- F11
+    (115, 88),  // VOLUMEUP <- F12
+];
+
+/// Physical key behind a key hid_apple sent while Fn was held (`HOME` for
+/// Fn+Left), so `Fn-Left` mappings match what was pressed
+pub fn fn_physical_key(sent: Key) -> Option<Key> {
+    FN_TRANSLATED_KEYS
+        .iter()
+        .find(|(translated, _)| *translated == sent.code())
+        .map(|(_, physical)| Key::from(*physical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fn_mode_round_trip() {
+        for mode in [FnMode::Disabled, FnMode::Media, FnMode::FunctionKeys, FnMode::Auto] {
+            assert_eq!(FnMode::from_name(mode.as_str()), Some(mode));
+            assert_eq!(FnMode::from_name(&mode.value().to_string()), Some(mode));
+        }
+        assert_eq!(FnMode::from_name("Fkeys"), Some(FnMode::FunctionKeys));
+        assert_eq!(FnMode::from_name("4"), None);
+
+        let path = std::env::temp_dir().join(format!("keyrs-fnmode-{}", std::process::id()));
+        std::fs::write(&path, "1\n").unwrap();
+        assert_eq!(read_fn_mode(&path).unwrap(), FnMode::Media);
+        write_fn_mode(&path, FnMode::FunctionKeys).unwrap();
+        assert_eq!(read_fn_mode(&path).unwrap(), FnMode::FunctionKeys);
+        std::fs::write(&path, "9\n").unwrap();
+        assert!(read_fn_mode(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fn_physical_key() {
+        assert_eq!(fn_physical_key(Key::from(102)), Some(Key::from(105))); // HOME -> LEFT
+        assert_eq!(fn_physical_key(Key::from(224)), Some(Key::from(59))); // BRIGHTNESSDOWN -> F1
+        assert_eq!(fn_physical_key(Key::from(30)), None); // A
+    }
+}
@@ -0,0 +1,119 @@
+// Keyrs Combo Capture
+// Turns physical key presses into config combo strings (used by the TUI)
+
+use crate::{Key, Modifier};
+
+/// Modifiers a captured combo names, in the order they are written
+const CAPTURE_MODIFIERS: [&str; 5] = ["Ctrl", "Alt", "Shift", "Super", "Fn"];
+
+/// Builds a combo string such as `Ctrl-Shift-t` from key events: the
+/// modifiers held when a non-modifier key goes down, or a modifier key
+/// pressed and released on its own
+#[derive(Debug, Default)]
+pub struct ComboCapture {
+    /// Modifier keys down, in press order
+    held: Vec<Key>,
+    /// Modifier key pressed with nothing else down and no key since
+    lone: Option<Key>,
+}
+
+impl ComboCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one key event (`value` 1 = press, 0 = release, 2 = repeat);
+    /// returns the combo string once it is complete
+    pub fn feed(&mut self, key: Key, value: i32) -> Option<String> {
+        let modifier = capture_modifier(key);
+        match value {
+            1 if modifier.is_some() => {
+                if !self.held.contains(&key) {
+                    self.held.push(key);
+                }
+                self.lone = (self.held.len() == 1).then_some(key);
+                None
+            }
+            1 => {
+                self.lone = None;
+                let mut parts: Vec<&str> = CAPTURE_MODIFIERS
+                    .into_iter()
+                    .filter(|name| self.held.iter().any(|held| capture_modifier(*held) == Some(name)))
+                    .collect();
+                let name = key_string(key);
+                parts.push(&name);
+                Some(parts.join("-"))
+            }
+            0 if modifier.is_some() => {
+                self.held.retain(|held| *held != key);
+                (self.lone.take() == Some(key)).then(|| key.name().to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Forget held keys, e.g. before capturing the next combo
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.lone = None;
+    }
+}
+
+/// Name of the generic modifier `key` belongs to (Right Alt reads as Alt)
+fn capture_modifier(key: Key) -> Option<&'static str> {
+    CAPTURE_MODIFIERS
+        .into_iter()
+        .find(|name| Modifier::from_alias(name).is_some_and(|modifier| modifier.keys().contains(&key)))
+}
+
+/// Key name as written in configs: letters lowercase, other keys by name
+fn key_string(key: Key) -> String {
+    let name = key.name();
+    if name.len() == 1 {
+        name.to_ascii_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_combo_string;
+
+    const LEFT_CTRL: Key = Key(29);
+    const LEFT_SHIFT: Key = Key(42);
+    const RIGHT_ALT: Key = Key(100);
+    const T: Key = Key(20);
+    const F5: Key = Key(63);
+
+    #[test]
+    fn test_capture_combo() {
+        let mut capture = ComboCapture::new();
+        assert_eq!(capture.feed(LEFT_SHIFT, 1), None);
+        assert_eq!(capture.feed(LEFT_CTRL, 1), None);
+        assert_eq!(capture.feed(LEFT_CTRL, 2), None);
+        assert_eq!(capture.feed(T, 1).as_deref(), Some("Ctrl-Shift-t"));
+
+        let parsed = parse_combo_string("Ctrl-Shift-t").unwrap();
+        assert_eq!(parsed.key, T);
+        assert_eq!(parsed.modifiers.len(), 2);
+
+        capture.reset();
+        assert_eq!(capture.feed(RIGHT_ALT, 1), None);
+        assert_eq!(capture.feed(F5, 1).as_deref(), Some("Alt-F5"));
+    }
+
+    #[test]
+    fn test_capture_lone_modifier() {
+        let mut capture = ComboCapture::new();
+        assert_eq!(capture.feed(LEFT_CTRL, 1), None);
+        assert_eq!(capture.feed(LEFT_CTRL, 0).as_deref(), Some("LEFT_CTRL"));
+
+        // A modifier released after another key (or with others held) is not a combo
+        assert_eq!(capture.feed(LEFT_CTRL, 1), None);
+        assert_eq!(capture.feed(LEFT_SHIFT, 1), None);
+        assert_eq!(capture.feed(LEFT_SHIFT, 0), None);
+        assert_eq!(capture.feed(LEFT_CTRL, 0), None);
+    }
+}
@@ -62,6 +62,21 @@ pub fn is_keyboard(capabilities: &DeviceCapabilities) -> bool {
     qwerty_present && az_present
 }
 
+/// First joystick button code (BTN_JOYSTICK, also BTN_TRIGGER)
+const BTN_JOYSTICK: u16 = 0x120;
+
+/// First gamepad button code (BTN_GAMEPAD, also BTN_SOUTH)
+const BTN_GAMEPAD: u16 = 0x130;
+
+/// Determine if a device is a gamepad or joystick.
+///
+/// The kernel's joystick handler uses the same test: the device reports
+/// BTN_GAMEPAD or BTN_JOYSTICK.
+pub fn is_gamepad(capabilities: &DeviceCapabilities) -> bool {
+    capabilities.has_ev_key
+        && (capabilities.supports_key(BTN_GAMEPAD) || capabilities.supports_key(BTN_JOYSTICK))
+}
+
 /// Check if a device is a virtual device based on its name.
 ///
 /// Virtual devices are created by keyrs itself and should be
@@ -145,6 +160,22 @@ mod tests {
         assert!(!is_keyboard(&caps));
     }
 
+    #[test]
+    fn test_is_gamepad() {
+        // BTN_SOUTH..BTN_THUMBR
+        let pad = DeviceCapabilities::new(true, (304..=318).collect());
+        assert!(is_gamepad(&pad));
+        assert!(!is_keyboard(&pad));
+
+        // BTN_TRIGGER..BTN_BASE6
+        let joystick = DeviceCapabilities::new(true, (288..=299).collect());
+        assert!(is_gamepad(&joystick));
+
+        assert!(!is_gamepad(&make_keyboard_caps()));
+        assert!(!is_gamepad(&make_mouse_caps()));
+        assert!(!is_gamepad(&DeviceCapabilities::new(false, vec![304])));
+    }
+
     #[test]
     fn test_is_virtual_device_with_prefix() {
         assert!(is_virtual_device(
@@ -0,0 +1,119 @@
+// Keyrs Input Layer - Grab Policy
+// How much of a device keyrs takes over (all of it, its keys, or nothing)
+
+/// `EV_REL` in input-event-codes.h
+pub const EV_REL: u16 = 0x02;
+/// `EV_MSC` in input-event-codes.h
+pub const EV_MSC: u16 = 0x04;
+/// `EV_SW` in input-event-codes.h
+pub const EV_SW: u16 = 0x05;
+/// `MSC_SCAN` in input-event-codes.h
+const MSC_SCAN: u16 = 0x04;
+
+/// Relative axes the virtual mouse advertises (`REL_X`, `REL_Y`, `REL_HWHEEL`, `REL_WHEEL`)
+const FORWARDED_AXES: [u16; 4] = [0x00, 0x01, 0x06, 0x08];
+
+/// How a device is grabbed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrabPolicy {
+    /// Grab the device; only its keys come out (remapped) (default)
+    #[default]
+    Full,
+    /// Grab the device, remap its keys and re-emit its pointer motion and
+    /// scrolling through the virtual mouse (keyboard/mouse combo receivers)
+    KeysOnly,
+    /// Don't grab: the device keeps working untouched and its events are
+    /// only read (e.g. for lock LEDs and the internal keyboard policy)
+    Observe,
+}
+
+impl GrabPolicy {
+    /// Parse a policy name as used in `[devices].grab`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "full" => Some(Self::Full),
+            "keys-only" => Some(Self::KeysOnly),
+            "observe" => Some(Self::Observe),
+            _ => None,
+        }
+    }
+
+    /// Whether the device is grabbed (and its keys remapped)
+    pub fn grabs(self) -> bool {
+        self != Self::Observe
+    }
+
+    /// Whether a non-key event of a device with this policy is re-emitted:
+    /// scancodes and switches of grabbed devices, and pointer motion of
+    /// keys-only ones
+    pub fn forwards(self, event_type: u16, code: u16) -> bool {
+        match event_type {
+            EV_REL => self == Self::KeysOnly && FORWARDED_AXES.contains(&code),
+            EV_MSC => self.grabs() && code == MSC_SCAN,
+            EV_SW => self.grabs(),
+            _ => false,
+        }
+    }
+}
+
+/// Grab policy for every device, with per-device overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrabPolicies {
+    /// Policy of devices without an override
+    pub default: GrabPolicy,
+    /// (device name or path, policy)
+    pub devices: Vec<(String, GrabPolicy)>,
+}
+
+impl GrabPolicies {
+    /// Policy for the device with `name` at `path`
+    pub fn for_device(&self, name: &str, path: &str) -> GrabPolicy {
+        self.devices
+            .iter()
+            .find(|(device, _)| device == name || device == path)
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grab_policy_from_name() {
+        assert_eq!(GrabPolicy::from_name("full"), Some(GrabPolicy::Full));
+        assert_eq!(GrabPolicy::from_name("Keys_Only"), Some(GrabPolicy::KeysOnly));
+        assert_eq!(GrabPolicy::from_name("observe"), Some(GrabPolicy::Observe));
+        assert_eq!(GrabPolicy::from_name("partial"), None);
+    }
+
+    #[test]
+    fn test_grab_policy_forwards() {
+        assert!(GrabPolicy::KeysOnly.forwards(EV_REL, 0x00)); // REL_X
+        assert!(GrabPolicy::KeysOnly.forwards(EV_REL, 0x08)); // REL_WHEEL
+        assert!(!GrabPolicy::KeysOnly.forwards(EV_REL, 0x0b)); // REL_WHEEL_HI_RES
+        assert!(!GrabPolicy::KeysOnly.forwards(0x03, 0x00)); // ABS_X
+        assert!(!GrabPolicy::Full.forwards(EV_REL, 0x00));
+        assert!(!GrabPolicy::Observe.grabs());
+
+        assert!(GrabPolicy::Full.forwards(EV_MSC, MSC_SCAN));
+        assert!(!GrabPolicy::Full.forwards(EV_MSC, 0x05)); // MSC_TIMESTAMP
+        assert!(GrabPolicy::KeysOnly.forwards(EV_SW, 0x01)); // SW_TABLET_MODE
+        assert!(!GrabPolicy::Observe.forwards(EV_SW, 0x01));
+        assert!(!GrabPolicy::Full.forwards(0x11, 0x01)); // EV_LED
+    }
+
+    #[test]
+    fn test_grab_policies_per_device() {
+        let policies = GrabPolicies {
+            default: GrabPolicy::Full,
+            devices: vec![
+                ("Logitech USB Receiver".to_string(), GrabPolicy::KeysOnly),
+                ("/dev/input/event7".to_string(), GrabPolicy::Observe),
+            ],
+        };
+        assert_eq!(policies.for_device("Logitech USB Receiver", "/dev/input/event3"), GrabPolicy::KeysOnly);
+        assert_eq!(policies.for_device("Other", "/dev/input/event7"), GrabPolicy::Observe);
+        assert_eq!(policies.for_device("AT Translated Set 2 keyboard", "/dev/input/event0"), GrabPolicy::Full);
+    }
+}
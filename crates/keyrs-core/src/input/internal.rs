@@ -0,0 +1,109 @@
+// Keyrs Input Layer - Internal Keyboard Policy
+// Decides when a laptop's built-in keyboard should be ignored (clamshell mode)
+
+/// Device name the kernel gives the built-in i8042 (PS/2) laptop keyboard
+const I8042_KEYBOARD_NAME: &str = "AT Translated Set 2 keyboard";
+
+/// Physical path prefix of the built-in i8042 keyboard port
+const I8042_PHYS_PREFIX: &str = "isa0060/serio0";
+
+/// When to stop processing events from the internal (built-in) keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InternalKeyboardPolicy {
+    /// Always process the internal keyboard (default)
+    #[default]
+    Always,
+    /// Ignore the internal keyboard while the lid is closed
+    LidClosed,
+    /// Ignore the internal keyboard while an external keyboard is connected
+    ExternalPresent,
+    /// Ignore the internal keyboard when the lid is closed or an external keyboard is connected
+    LidClosedOrExternal,
+}
+
+impl InternalKeyboardPolicy {
+    /// Parse a policy name as used in `[devices].internal_policy`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "lid_closed" => Some(Self::LidClosed),
+            "external_present" => Some(Self::ExternalPresent),
+            "lid_closed_or_external" => Some(Self::LidClosedOrExternal),
+            _ => None,
+        }
+    }
+
+    /// Whether the policy needs the lid switch state
+    pub fn uses_lid(self) -> bool {
+        matches!(self, Self::LidClosed | Self::LidClosedOrExternal)
+    }
+
+    /// Whether the internal keyboard should be ignored in the given state
+    pub fn should_ignore(self, lid_closed: bool, external_present: bool) -> bool {
+        match self {
+            Self::Always => false,
+            Self::LidClosed => lid_closed,
+            Self::ExternalPresent => external_present,
+            Self::LidClosedOrExternal => lid_closed || external_present,
+        }
+    }
+}
+
+/// Check whether a device is the internal (built-in) keyboard.
+///
+/// `internal_names` overrides detection when non-empty (matched against the
+/// device name or path); otherwise the i8042 keyboard is assumed internal.
+pub fn is_internal_keyboard(
+    device_name: &str,
+    device_path: &str,
+    phys: Option<&str>,
+    internal_names: &[String],
+) -> bool {
+    if !internal_names.is_empty() {
+        return internal_names
+            .iter()
+            .any(|name| name == device_name || name == device_path);
+    }
+
+    device_name == I8042_KEYBOARD_NAME
+        || phys.map(|p| p.starts_with(I8042_PHYS_PREFIX)).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_from_name() {
+        assert_eq!(InternalKeyboardPolicy::from_name("always"), Some(InternalKeyboardPolicy::Always));
+        assert_eq!(
+            InternalKeyboardPolicy::from_name("Lid_Closed"),
+            Some(InternalKeyboardPolicy::LidClosed)
+        );
+        assert_eq!(InternalKeyboardPolicy::from_name("sometimes"), None);
+    }
+
+    #[test]
+    fn test_policy_should_ignore() {
+        use InternalKeyboardPolicy::*;
+        assert!(!Always.should_ignore(true, true));
+        assert!(LidClosed.should_ignore(true, false));
+        assert!(!LidClosed.should_ignore(false, true));
+        assert!(ExternalPresent.should_ignore(false, true));
+        assert!(!ExternalPresent.should_ignore(true, false));
+        assert!(LidClosedOrExternal.should_ignore(true, false));
+        assert!(LidClosedOrExternal.should_ignore(false, true));
+        assert!(!LidClosedOrExternal.should_ignore(false, false));
+    }
+
+    #[test]
+    fn test_is_internal_keyboard_detection() {
+        assert!(is_internal_keyboard(I8042_KEYBOARD_NAME, "/dev/input/event3", None, &[]));
+        assert!(is_internal_keyboard("Some Keyboard", "/dev/input/event3", Some("isa0060/serio0/input0"), &[]));
+        assert!(!is_internal_keyboard("Logitech USB Keyboard", "/dev/input/event7", Some("usb-0000:00:14.0-1/input0"), &[]));
+
+        let names = vec!["/dev/input/event9".to_string()];
+        assert!(is_internal_keyboard("Framework Keyboard", "/dev/input/event9", None, &names));
+        assert!(!is_internal_keyboard(I8042_KEYBOARD_NAME, "/dev/input/event3", None, &names));
+    }
+}
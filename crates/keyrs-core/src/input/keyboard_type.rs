@@ -104,6 +104,24 @@ impl DeviceInfo {
         self.phys = Some(phys.into());
         self
     }
+
+    /// `vendor:product` id (e.g. `05ac:024f`), if both are known
+    pub fn device_id(&self) -> Option<String> {
+        Some(format_device_id(self.vendor_id?, self.product_id?))
+    }
+}
+
+/// Format a vendor and product id as `vvvv:pppp` in lowercase hex
+pub fn format_device_id(vendor_id: u16, product_id: u16) -> String {
+    format!("{:04x}:{:04x}", vendor_id, product_id)
+}
+
+/// Parse a `vendor:product` id in hex (`05ac:024f`, `5AC:24F`)
+pub fn parse_device_id(id: &str) -> Option<(u16, u16)> {
+    let (vendor, product) = id.trim().split_once(':')?;
+    let vendor = u16::from_str_radix(vendor.trim_start_matches("0x"), 16).ok()?;
+    let product = u16::from_str_radix(product.trim_start_matches("0x"), 16).ok()?;
+    Some((vendor, product))
 }
 
 /// Keyboard detection patterns
@@ -266,6 +284,18 @@ pub fn keyboard_type_matches(kb_type: KeyboardType, condition: &str) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_device_id() {
+        let device = DeviceInfo::new("Magic Keyboard").with_vendor_id(0x05ac).with_product_id(0x024f);
+        assert_eq!(device.device_id().as_deref(), Some("05ac:024f"));
+        assert_eq!(DeviceInfo::new("Unknown").device_id(), None);
+
+        assert_eq!(parse_device_id("05ac:024f"), Some((0x05ac, 0x024f)));
+        assert_eq!(parse_device_id("5AC:0x24F"), Some((0x05ac, 0x024f)));
+        assert_eq!(parse_device_id("Magic Keyboard"), None);
+        assert_eq!(parse_device_id("05ac:"), None);
+    }
+
     #[test]
     fn test_keyboard_type_from_str() {
         assert_eq!(KeyboardType::from_str("IBM"), Some(KeyboardType::IBM));
@@ -0,0 +1,68 @@
+// Keyrs Input Layer - Lock State
+// Num Lock / Caps Lock state as shown by the keyboard LEDs
+
+/// `LED_NUML` in input-event-codes.h
+pub const LED_NUML: u16 = 0x00;
+/// `LED_CAPSL` in input-event-codes.h
+pub const LED_CAPSL: u16 = 0x01;
+
+/// Num Lock and Caps Lock state.
+///
+/// The session (compositor or console) owns the lock state and shows it on
+/// the keyboard LEDs, so the LEDs are the source of truth rather than the
+/// lock keys keyrs has seen since it started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockState {
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl LockState {
+    /// State from the codes of the LEDs that are lit
+    pub fn from_lit_leds(leds: impl IntoIterator<Item = u16>) -> Self {
+        let mut state = Self::default();
+        for led in leds {
+            state.apply_led_event(led, 1);
+        }
+        state
+    }
+
+    /// Apply an `EV_LED` event; returns whether the state changed
+    pub fn apply_led_event(&mut self, code: u16, value: i32) -> bool {
+        let slot = match code {
+            LED_NUML => &mut self.num_lock,
+            LED_CAPSL => &mut self.caps_lock,
+            _ => return false,
+        };
+        let on = value != 0;
+        let changed = *slot != on;
+        *slot = on;
+        changed
+    }
+
+    /// `EV_LED` `(code, value)` pairs that show this state
+    pub fn led_values(&self) -> [(u16, i32); 2] {
+        [
+            (LED_NUML, self.num_lock as i32),
+            (LED_CAPSL, self.caps_lock as i32),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_state_follows_led_events() {
+        let mut state = LockState::from_lit_leds([LED_CAPSL, 0x02]); // Caps + Scroll Lock
+        assert_eq!(state, LockState { num_lock: false, caps_lock: true });
+
+        assert!(state.apply_led_event(LED_NUML, 1));
+        assert!(!state.apply_led_event(LED_NUML, 1));
+        assert!(state.apply_led_event(LED_CAPSL, 0));
+        assert!(!state.apply_led_event(0x02, 0));
+        assert_eq!(state.led_values(), [(LED_NUML, 1), (LED_CAPSL, 0)]);
+        assert_eq!(LockState::from_lit_leds([LED_NUML]), state);
+    }
+}
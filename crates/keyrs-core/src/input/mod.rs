@@ -1,15 +1,31 @@
 // Keyrs Input Layer
 // Device detection and filtering logic
 
+mod apple;
+mod capture;
 mod device;
 mod event;
 mod filter;
+mod grab;
+mod internal;
+mod locks;
+mod panic;
+mod scancode;
+mod seat;
 pub mod keyboard_type;
 
-pub use device::{is_keyboard, is_virtual_device, DeviceCapabilities};
+pub use apple::{fn_physical_key, read_fn_mode, write_fn_mode, FnMode, FNMODE_PATH, KEY_FN};
+pub use capture::ComboCapture;
+pub use device::{is_gamepad, is_keyboard, is_virtual_device, DeviceCapabilities};
 pub use event::{is_emergency_key, is_key_event};
 pub use filter::matches_device_filter;
+pub use grab::{GrabPolicies, GrabPolicy};
+pub use internal::{is_internal_keyboard, InternalKeyboardPolicy};
+pub use locks::{LockState, LED_CAPSL, LED_NUML};
+pub use panic::{PanicChord, DEFAULT_PANIC_CHORD, DEFAULT_PANIC_HOLD_MS};
+pub use scancode::{parse_scancode, ScancodeRemap};
+pub use seat::{is_on_seat, SeatFilter, DEFAULT_SEAT};
 pub use keyboard_type::{
-    detect_keyboard_type, detect_keyboard_type_simple, keyboard_type_matches,
-    DeviceInfo as KeyboardDeviceInfo, KeyboardPatterns, KeyboardType,
+    detect_keyboard_type, detect_keyboard_type_simple, format_device_id, keyboard_type_matches,
+    parse_device_id, DeviceInfo as KeyboardDeviceInfo, KeyboardPatterns, KeyboardType,
 };
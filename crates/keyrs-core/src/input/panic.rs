@@ -0,0 +1,114 @@
+// Keyrs Input Layer - Panic Chord
+// Raw key chord that stops keyrs when held, independent of the transform engine
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::{Action, Key};
+
+/// Default panic chord: both Ctrl keys and Backspace
+pub const DEFAULT_PANIC_CHORD: [u16; 3] = [29, 97, 14]; // LEFT_CTRL, RIGHT_CTRL, BACKSPACE
+
+/// Default time the panic chord must be held (milliseconds)
+pub const DEFAULT_PANIC_HOLD_MS: u64 = 2000;
+
+/// Tracks physical key state for the panic chord.
+///
+/// Fed with raw input events before any remapping, so it keeps working
+/// even if the engine's own key state is wrong.
+#[derive(Debug, Clone)]
+pub struct PanicChord {
+    keys: Vec<Key>,
+    hold: Duration,
+    held: HashSet<Key>,
+    /// When the last chord key went down (None while the chord is incomplete)
+    complete_since: Option<Instant>,
+}
+
+impl PanicChord {
+    /// Chord of `keys` held for `hold`; an empty chord never fires
+    pub fn new(keys: Vec<Key>, hold: Duration) -> Self {
+        Self {
+            keys,
+            hold,
+            held: HashSet::new(),
+            complete_since: None,
+        }
+    }
+
+    /// Record a raw key event
+    pub fn observe(&mut self, key: Key, action: Action) {
+        if !self.keys.contains(&key) {
+            return;
+        }
+        match action {
+            Action::Press => {
+                self.held.insert(key);
+            }
+            Action::Release => {
+                self.held.remove(&key);
+            }
+            Action::Repeat => return,
+        }
+        let complete = !self.keys.is_empty() && self.keys.iter().all(|k| self.held.contains(k));
+        self.complete_since = match (complete, self.complete_since) {
+            (true, Some(since)) => Some(since),
+            (true, None) => Some(Instant::now()),
+            (false, _) => None,
+        };
+    }
+
+    /// Whether the whole chord has been held for the configured time
+    pub fn triggered(&self) -> bool {
+        self.complete_since
+            .is_some_and(|since| since.elapsed() >= self.hold)
+    }
+
+    /// Time left until the held chord fires (None while incomplete)
+    pub fn fires_in(&self) -> Option<Duration> {
+        self.complete_since
+            .map(|since| self.hold.saturating_sub(since.elapsed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(hold_ms: u64) -> PanicChord {
+        PanicChord::new(
+            DEFAULT_PANIC_CHORD.iter().map(|code| Key::from(*code)).collect(),
+            Duration::from_millis(hold_ms),
+        )
+    }
+
+    #[test]
+    fn test_panic_chord_fires_after_hold() {
+        let mut panic = chord(0);
+        panic.observe(Key::from(29), Action::Press);
+        panic.observe(Key::from(97), Action::Press);
+        panic.observe(Key::from(30), Action::Press); // unrelated keys don't matter
+        assert!(!panic.triggered());
+        panic.observe(Key::from(14), Action::Press);
+        assert!(panic.triggered());
+
+        panic.observe(Key::from(97), Action::Release);
+        assert!(!panic.triggered());
+        assert_eq!(panic.fires_in(), None);
+    }
+
+    #[test]
+    fn test_panic_chord_waits_for_hold_time() {
+        let mut panic = chord(60_000);
+        for code in DEFAULT_PANIC_CHORD {
+            panic.observe(Key::from(code), Action::Press);
+        }
+        panic.observe(Key::from(14), Action::Repeat);
+        assert!(!panic.triggered());
+        assert!(panic.fires_in().unwrap() > Duration::from_secs(59));
+
+        let mut disabled = PanicChord::new(Vec::new(), Duration::ZERO);
+        disabled.observe(Key::from(29), Action::Press);
+        assert!(!disabled.triggered());
+    }
+}
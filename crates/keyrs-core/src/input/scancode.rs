@@ -0,0 +1,98 @@
+// Keyrs Input Layer - Scancode Remapping
+// Rewrite key codes by their raw MSC_SCAN value, like a udev hwdb entry
+
+use std::collections::HashMap;
+
+use crate::Key;
+
+/// Parse a scancode written as hex (`0x70039`, as `--show-scancodes` prints) or decimal
+pub fn parse_scancode(text: &str) -> Option<u32> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Replaces the key code of events whose scancode has a mapping.
+///
+/// Drivers report `MSC_SCAN` just before the key event it belongs to, within
+/// the same SYN frame, so the last scancode seen per device is held until
+/// that key event (or the end of the frame).
+#[derive(Debug, Clone, Default)]
+pub struct ScancodeRemap {
+    map: HashMap<u32, Key>,
+    pending: HashMap<String, u32>,
+}
+
+impl ScancodeRemap {
+    /// Remap from `(scancode, key)` pairs
+    pub fn new(entries: &[(u32, Key)]) -> Self {
+        Self {
+            map: entries.iter().copied().collect(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Whether no scancode is remapped
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Record an `MSC_SCAN` value from `device`
+    pub fn observe_scan(&mut self, device: &str, scancode: u32) {
+        if self.map.contains_key(&scancode) {
+            self.pending.insert(device.to_string(), scancode);
+        } else {
+            self.pending.remove(device);
+        }
+    }
+
+    /// Key to use for a key event from `device`
+    pub fn remap(&mut self, device: &str, key: Key) -> Key {
+        self.pending
+            .remove(device)
+            .and_then(|scancode| self.map.get(&scancode).copied())
+            .unwrap_or(key)
+    }
+
+    /// Forget a scancode that wasn't followed by a key event
+    pub fn end_frame(&mut self, device: &str) {
+        self.pending.remove(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scancode() {
+        assert_eq!(parse_scancode("0x70039"), Some(0x70039));
+        assert_eq!(parse_scancode(" 0XC00B6 "), Some(0xc00b6));
+        assert_eq!(parse_scancode("58"), Some(58));
+        assert_eq!(parse_scancode("0xzz"), None);
+        assert_eq!(parse_scancode("capslock"), None);
+    }
+
+    #[test]
+    fn test_scancode_remap_applies_to_following_key_event() {
+        let esc = Key::from(1);
+        let capslock = Key::from(58);
+        let mut remap = ScancodeRemap::new(&[(0x70039, esc)]);
+
+        remap.observe_scan("kbd", 0x70039);
+        assert_eq!(remap.remap("kbd", capslock), esc);
+        // Consumed: a key event without its own scancode is left alone
+        assert_eq!(remap.remap("kbd", capslock), capslock);
+
+        // Scancodes are tracked per device
+        remap.observe_scan("kbd", 0x70039);
+        assert_eq!(remap.remap("other", capslock), capslock);
+        remap.end_frame("kbd");
+        assert_eq!(remap.remap("kbd", capslock), capslock);
+
+        remap.observe_scan("kbd", 0x70004);
+        assert_eq!(remap.remap("kbd", Key::from(30)), Key::from(30));
+    }
+}
@@ -0,0 +1,53 @@
+// Keyrs Input Layer - Seats
+// Which seat's devices keyrs uses on multi-seat systems
+
+/// Seat every device belongs to unless udev assigns another (`ID_SEAT`)
+pub const DEFAULT_SEAT: &str = "seat0";
+
+/// Which devices to use on a multi-seat system (`[session].seat`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SeatFilter {
+    /// Devices of the seat the login session runs on (default)
+    #[default]
+    Session,
+    /// Devices of every seat
+    Any,
+    /// Devices of the named seat
+    Named(String),
+}
+
+impl SeatFilter {
+    /// Parse a value as used in `[session].seat`
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "" | "auto" | "session" => Self::Session,
+            "any" => Self::Any,
+            seat => Self::Named(seat.to_string()),
+        }
+    }
+}
+
+/// Whether a device assigned to `device_seat` (`None` = unassigned) is on `seat`
+pub fn is_on_seat(device_seat: Option<&str>, seat: &str) -> bool {
+    device_seat.unwrap_or(DEFAULT_SEAT) == seat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seat_filter_from_name() {
+        assert_eq!(SeatFilter::from_name("auto"), SeatFilter::Session);
+        assert_eq!(SeatFilter::from_name("any"), SeatFilter::Any);
+        assert_eq!(SeatFilter::from_name("seat1"), SeatFilter::Named("seat1".to_string()));
+    }
+
+    #[test]
+    fn test_is_on_seat() {
+        assert!(is_on_seat(None, "seat0"));
+        assert!(is_on_seat(Some("seat1"), "seat1"));
+        assert!(!is_on_seat(Some("seat1"), "seat0"));
+        assert!(!is_on_seat(None, "seat1"));
+    }
+}
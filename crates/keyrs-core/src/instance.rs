@@ -0,0 +1,167 @@
+// Keyrs Single Instance
+// Abstract sockets that keep a second daemon from grabbing the same devices
+
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::path::Path;
+
+/// Errors taking the instance lock
+#[derive(Debug, thiserror::Error)]
+pub enum InstanceError {
+    #[error(
+        "{device} is already used by keyrs{} (lock @{}); stop it before starting another",
+        .pid.map(|pid| format!(" running as pid {}", pid)).unwrap_or_default(),
+        .name
+    )]
+    AlreadyRunning { pid: Option<u32>, device: String, name: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Held by the process that grabs the devices, for as long as it runs.
+///
+/// There is one listening socket in the abstract namespace per input
+/// device, named after the user and the device number, so two instances
+/// fail to start only when they would share a device, whatever filters
+/// selected it. The kernel frees the names when the process exits, crashed
+/// or not, and nothing is left behind on disk.
+///
+/// Any local user can bind any abstract name. A name held by another user
+/// cannot be one of our instances, so it is skipped with a warning: a
+/// squatter can disable the check, but not keep keyrs from starting.
+#[derive(Debug)]
+pub struct InstanceLock {
+    /// Kept open: closing them releases the names
+    _listeners: Vec<UnixListener>,
+    names: Vec<String>,
+}
+
+impl InstanceLock {
+    /// Lock name for the input device node at `path`
+    pub fn name_for_device(path: &Path) -> std::io::Result<String> {
+        let rdev = std::fs::metadata(path)?.rdev();
+        Ok(format!(
+            "keyrs/{}/input-{}:{}",
+            unsafe { libc::geteuid() },
+            libc::major(rdev),
+            libc::minor(rdev)
+        ))
+    }
+
+    /// Lock every device in `paths` without waiting, failing with the
+    /// running instance's pid when another process of ours holds one of them
+    pub fn acquire_devices(paths: &[String]) -> Result<Self, InstanceError> {
+        let mut lock = Self {
+            _listeners: Vec::new(),
+            names: Vec::new(),
+        };
+        for path in paths {
+            let name = match Self::name_for_device(Path::new(path)) {
+                Ok(name) => name,
+                // Unplugged since it was listed: nothing to share
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if lock.names.contains(&name) {
+                continue;
+            }
+            if let Some(listener) = Self::bind(path, &name)? {
+                lock._listeners.push(listener);
+                lock.names.push(name);
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Take the lock `name` for `device`; `None` when another user holds it
+    fn bind(device: &str, name: &str) -> Result<Option<UnixListener>, InstanceError> {
+        let address = SocketAddr::from_abstract_name(name.as_bytes())?;
+        match UnixListener::bind_addr(&address) {
+            Ok(listener) => Ok(Some(listener)),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                let holder = UnixStream::connect_addr(&address).ok().and_then(|stream| peer_credentials(&stream));
+                match holder {
+                    Some((pid, uid)) if uid != unsafe { libc::geteuid() } => {
+                        log::warn!(
+                            "Instance lock @{} is held by uid {} (pid {}), not by keyrs; not checking {}",
+                            name,
+                            uid,
+                            pid,
+                            device
+                        );
+                        Ok(None)
+                    }
+                    _ => Err(InstanceError::AlreadyRunning {
+                        pid: holder.map(|(pid, _)| pid).filter(|pid| *pid > 0),
+                        device: device.to_string(),
+                        name: name.to_string(),
+                    }),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Names of the locks held
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// Pid (0 in another pid namespace) and uid of the process listening on the
+/// other end
+fn peer_credentials(stream: &UnixStream) -> Option<(u32, u32)> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    (result == 0).then_some((cred.pid.max(0) as u32, cred.uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_instance_fails() {
+        let device = "/dev/null".to_string();
+        let name = InstanceLock::name_for_device(Path::new(&device)).unwrap();
+        let lock = InstanceLock::acquire_devices(&[device.clone(), device.clone()]).unwrap();
+        assert_eq!(lock.names(), std::slice::from_ref(&name));
+
+        match InstanceLock::acquire_devices(std::slice::from_ref(&device)) {
+            Err(InstanceError::AlreadyRunning { pid, device: busy, name: held }) => {
+                assert_eq!(pid, Some(std::process::id()));
+                assert_eq!(busy, device);
+                assert_eq!(held, name);
+            }
+            other => panic!("expected AlreadyRunning, got {:?}", other),
+        }
+
+        // Released with the lock
+        drop(lock);
+        assert!(InstanceLock::acquire_devices(&[device]).is_ok());
+    }
+
+    #[test]
+    fn test_lock_name_follows_device_number() {
+        let uid = unsafe { libc::geteuid() };
+        assert_eq!(
+            InstanceLock::name_for_device(Path::new("/dev/null")).unwrap(),
+            format!("keyrs/{}/input-1:3", uid)
+        );
+        // A vanished device locks nothing
+        let lock = InstanceLock::acquire_devices(&["/dev/input/keyrs-no-such-device".to_string()]).unwrap();
+        assert!(lock.names().is_empty());
+    }
+}
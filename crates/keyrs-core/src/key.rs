@@ -1,18 +1,86 @@
 // Keyrs Key Type
 // Represents a single key code from Linux input-event-codes.h
 
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::OnceLock;
 
+use crate::layout::KeyboardLayout;
+
 include!(concat!(env!("OUT_DIR"), "/key_codes.rs"));
 
+/// Highest key code (`KEY_MAX` in input-event-codes.h)
+pub const KEY_MAX: u16 = 0x2ff;
+
+/// `KEY_FN*` and `KEY_MACRO*` codes, named without their `KEY_` prefix
+const FN_AND_MACRO_KEYS: &[(&str, u16)] = &[
+    ("FN", 0x1d0),
+    ("FN_ESC", 0x1d1),
+    ("FN_F1", 0x1d2),
+    ("FN_F2", 0x1d3),
+    ("FN_F3", 0x1d4),
+    ("FN_F4", 0x1d5),
+    ("FN_F5", 0x1d6),
+    ("FN_F6", 0x1d7),
+    ("FN_F7", 0x1d8),
+    ("FN_F8", 0x1d9),
+    ("FN_F9", 0x1da),
+    ("FN_F10", 0x1db),
+    ("FN_F11", 0x1dc),
+    ("FN_F12", 0x1dd),
+    ("FN_1", 0x1de),
+    ("FN_2", 0x1df),
+    ("FN_D", 0x1e0),
+    ("FN_E", 0x1e1),
+    ("FN_F", 0x1e2),
+    ("FN_S", 0x1e3),
+    ("FN_B", 0x1e4),
+    ("FN_RIGHT_SHIFT", 0x1e5),
+    ("MACRO1", 0x290),
+    ("MACRO2", 0x291),
+    ("MACRO3", 0x292),
+    ("MACRO4", 0x293),
+    ("MACRO5", 0x294),
+    ("MACRO6", 0x295),
+    ("MACRO7", 0x296),
+    ("MACRO8", 0x297),
+    ("MACRO9", 0x298),
+    ("MACRO10", 0x299),
+    ("MACRO11", 0x29a),
+    ("MACRO12", 0x29b),
+    ("MACRO13", 0x29c),
+    ("MACRO14", 0x29d),
+    ("MACRO15", 0x29e),
+    ("MACRO16", 0x29f),
+    ("MACRO17", 0x2a0),
+    ("MACRO18", 0x2a1),
+    ("MACRO19", 0x2a2),
+    ("MACRO20", 0x2a3),
+    ("MACRO21", 0x2a4),
+    ("MACRO22", 0x2a5),
+    ("MACRO23", 0x2a6),
+    ("MACRO24", 0x2a7),
+    ("MACRO25", 0x2a8),
+    ("MACRO26", 0x2a9),
+    ("MACRO27", 0x2aa),
+    ("MACRO28", 0x2ab),
+    ("MACRO29", 0x2ac),
+    ("MACRO30", 0x2ad),
+    ("MACRO_RECORD_START", 0x2b0),
+    ("MACRO_RECORD_STOP", 0x2b1),
+    ("MACRO_PRESET_CYCLE", 0x2b2),
+    ("MACRO_PRESET1", 0x2b3),
+    ("MACRO_PRESET2", 0x2b4),
+    ("MACRO_PRESET3", 0x2b5),
+];
+
 /// Display name for a key code
 pub fn key_name(code: u16) -> &'static str {
     static KEY_NAMES: OnceLock<Vec<&'static str>> = OnceLock::new();
     KEY_NAMES
         .get_or_init(|| {
-            let mut names = vec!["UNKNOWN"; 0x300];
+            let mut names = vec!["UNKNOWN"; KEY_MAX as usize + 1];
             names[0] = "RESERVED";
             names[1] = "ESC";
             names[2] = "KEY_1";
@@ -256,6 +324,60 @@ pub fn key_name(code: u16) -> &'static str {
             names[246] = "WWAN";
             names[247] = "RFKILL";
             names[248] = "MICMUTE";
+            // Mouse buttons
+            names[272] = "BTN_LEFT";
+            names[273] = "BTN_RIGHT";
+            names[274] = "BTN_MIDDLE";
+            names[275] = "BTN_SIDE";
+            names[276] = "BTN_EXTRA";
+            names[277] = "BTN_FORWARD";
+            names[278] = "BTN_BACK";
+            names[279] = "BTN_TASK";
+            // Gamepad and joystick buttons
+            names[288] = "BTN_TRIGGER";
+            names[289] = "BTN_THUMB";
+            names[290] = "BTN_THUMB2";
+            names[291] = "BTN_TOP";
+            names[292] = "BTN_TOP2";
+            names[293] = "BTN_PINKIE";
+            names[294] = "BTN_BASE";
+            names[295] = "BTN_BASE2";
+            names[296] = "BTN_BASE3";
+            names[297] = "BTN_BASE4";
+            names[298] = "BTN_BASE5";
+            names[299] = "BTN_BASE6";
+            names[304] = "BTN_SOUTH";
+            names[305] = "BTN_EAST";
+            names[306] = "BTN_C";
+            names[307] = "BTN_NORTH";
+            names[308] = "BTN_WEST";
+            names[309] = "BTN_Z";
+            names[310] = "BTN_TL";
+            names[311] = "BTN_TR";
+            names[312] = "BTN_TL2";
+            names[313] = "BTN_TR2";
+            names[314] = "BTN_SELECT";
+            names[315] = "BTN_START";
+            names[316] = "BTN_MODE";
+            names[317] = "BTN_THUMBL";
+            names[318] = "BTN_THUMBR";
+            names[544] = "BTN_DPAD_UP";
+            names[545] = "BTN_DPAD_DOWN";
+            names[546] = "BTN_DPAD_LEFT";
+            names[547] = "BTN_DPAD_RIGHT";
+            // Consumer keys above the keyboard range
+            names[372] = "ZOOM";
+            names[418] = "ZOOMIN";
+            names[419] = "ZOOMOUT";
+            names[581] = "SCREENSAVER";
+            names[583] = "ASSISTANT";
+            names[585] = "EMOJI_PICKER";
+            names[586] = "DICTATE";
+            names[592] = "BRIGHTNESS_MIN";
+            names[593] = "BRIGHTNESS_MAX";
+            for (name, code) in FN_AND_MACRO_KEYS {
+                names[*code as usize] = name;
+            }
             names
         })
         .get(code as usize)
@@ -263,12 +385,11 @@ pub fn key_name(code: u16) -> &'static str {
         .unwrap_or("UNKNOWN")
 }
 
-/// Try to parse a key name to a key code
-pub fn key_from_name(name: &str) -> Option<Key> {
-    let name_upper = name.to_uppercase();
+/// Name-to-code table used for parsing key names (includes aliases)
+fn name_to_code_table() -> &'static [(&'static str, u16)] {
     static NAME_TO_CODE: OnceLock<Vec<(&'static str, u16)>> = OnceLock::new();
-    let map = NAME_TO_CODE.get_or_init(|| {
-        vec![
+    NAME_TO_CODE.get_or_init(|| {
+        let mut table = vec![
             ("RESERVED", 0),
             ("ESC", 1),
             ("ESCAPE", 1),
@@ -403,11 +524,152 @@ pub fn key_from_name(name: &str) -> Option<Key> {
             ("STOPCD", 166),
             ("PREVIOUSSONG", 165),
             ("NEXTSONG", 163),
-        ]
-    });
-    map.iter()
-        .find(|(n, _)| *n == name_upper)
-        .map(|(_, code)| Key::from(*code))
+            // Media keys by their XF86 keysym names (`XF86AudioRaiseVolume`)
+            ("XF86AUDIOMUTE", 113),
+            ("XF86AUDIOLOWERVOLUME", 114),
+            ("XF86AUDIORAISEVOLUME", 115),
+            ("XF86AUDIOMICMUTE", 248),
+            ("XF86AUDIOPLAY", 164),
+            ("XF86AUDIOPAUSE", 201),
+            ("XF86AUDIOSTOP", 166),
+            ("XF86AUDIOPREV", 165),
+            ("XF86AUDIONEXT", 163),
+            ("XF86AUDIOREWIND", 168),
+            ("XF86AUDIOFORWARD", 208),
+            ("XF86EJECT", 161),
+            ("XF86MONBRIGHTNESSDOWN", 224),
+            ("XF86MONBRIGHTNESSUP", 225),
+            ("XF86KBDLIGHTONOFF", 228),
+            ("XF86KBDBRIGHTNESSDOWN", 229),
+            ("XF86KBDBRIGHTNESSUP", 230),
+            // and by friendlier spellings
+            ("VOLUME_UP", 115),
+            ("VOLUME_DOWN", 114),
+            ("BRIGHTNESS_UP", 225),
+            ("BRIGHTNESS_DOWN", 224),
+            ("PLAY_PAUSE", 164),
+            ("NEXTTRACK", 163),
+            ("PREVTRACK", 165),
+            ("PREVIOUSTRACK", 165),
+            // Mouse buttons
+            ("BTN_LEFT", 272),
+            ("BTN_RIGHT", 273),
+            ("BTN_MIDDLE", 274),
+            ("BTN_SIDE", 275),
+            ("BTN_EXTRA", 276),
+            ("BTN_FORWARD", 277),
+            ("BTN_BACK", 278),
+            ("BTN_TASK", 279),
+            // Consumer keys above the keyboard range
+            ("ZOOM", 372),
+            ("ZOOMIN", 418),
+            ("ZOOMOUT", 419),
+            ("SCREENSAVER", 581),
+            ("ASSISTANT", 583),
+            ("EMOJI_PICKER", 585),
+            ("DICTATE", 586),
+            ("BRIGHTNESS_MIN", 592),
+            ("BRIGHTNESS_MAX", 593),
+            // Gamepad and joystick buttons (BTN_A/B/X/Y alias the compass names)
+            ("BTN_TRIGGER", 288),
+            ("BTN_THUMB", 289),
+            ("BTN_THUMB2", 290),
+            ("BTN_TOP", 291),
+            ("BTN_TOP2", 292),
+            ("BTN_PINKIE", 293),
+            ("BTN_BASE", 294),
+            ("BTN_BASE2", 295),
+            ("BTN_BASE3", 296),
+            ("BTN_BASE4", 297),
+            ("BTN_BASE5", 298),
+            ("BTN_BASE6", 299),
+            ("BTN_SOUTH", 304),
+            ("BTN_A", 304),
+            ("BTN_EAST", 305),
+            ("BTN_B", 305),
+            ("BTN_C", 306),
+            ("BTN_NORTH", 307),
+            ("BTN_X", 307),
+            ("BTN_WEST", 308),
+            ("BTN_Y", 308),
+            ("BTN_Z", 309),
+            ("BTN_TL", 310),
+            ("BTN_TR", 311),
+            ("BTN_TL2", 312),
+            ("BTN_TR2", 313),
+            ("BTN_SELECT", 314),
+            ("BTN_START", 315),
+            ("BTN_MODE", 316),
+            ("BTN_THUMBL", 317),
+            ("BTN_THUMBR", 318),
+            ("BTN_DPAD_UP", 544),
+            ("BTN_DPAD_DOWN", 545),
+            ("BTN_DPAD_LEFT", 546),
+            ("BTN_DPAD_RIGHT", 547),
+        ];
+        // Every display name parses too, up to KEY_MAX
+        for code in 0..=KEY_MAX {
+            let name = key_name(code);
+            if name != "UNKNOWN" && !table.iter().any(|(n, _)| *n == name) {
+                table.push((name, code));
+            }
+        }
+        table
+    })
+}
+
+/// Key names of one config: its `[aliases]`, single characters typed on its
+/// `[layout]`, then the built-in names of `key_from_name`
+///
+/// Named keys (`SEMICOLON`, `KEY_1`) keep their physical meaning whatever the layout.
+#[derive(Debug, Clone, Default)]
+pub struct KeyNames {
+    layout: Option<KeyboardLayout>,
+    aliases: HashMap<String, Key>,
+}
+
+impl KeyNames {
+    /// Names with single characters resolved through `layout` (US QWERTY when `None`)
+    pub fn new(layout: Option<KeyboardLayout>) -> Self {
+        Self { layout, aliases: HashMap::new() }
+    }
+
+    /// Add user-defined names; keys of `aliases` are upper-case alias names
+    /// already resolved to keys (see the config `[aliases]` table)
+    pub fn with_aliases(mut self, aliases: HashMap<String, Key>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Key for a user-defined alias
+    pub fn alias(&self, name: &str) -> Option<Key> {
+        self.aliases.get(&name.to_uppercase()).copied()
+    }
+
+    /// Key for `name`
+    pub fn key(&self, name: &str) -> Option<Key> {
+        self.alias(name)
+            .or_else(|| self.layout.as_ref().and_then(|layout| layout.key_for_name(name)))
+            .or_else(|| key_from_name(name))
+    }
+}
+
+/// Try to parse a built-in key name to a key code
+pub fn key_from_name(name: &str) -> Option<Key> {
+    let name_upper = name.to_uppercase();
+    let lookup = |name: &str| {
+        name_to_code_table()
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, code)| Key::from(*code))
+    };
+    // input-event-codes.h spellings (`KEY_MICMUTE`) work as well
+    lookup(&name_upper).or_else(|| lookup(name_upper.strip_prefix("KEY_")?))
+}
+
+/// All key names accepted by `key_from_name` (including aliases), in table order
+pub fn all_key_names() -> Vec<&'static str> {
+    name_to_code_table().iter().map(|(n, _)| *n).collect()
 }
 
 /// ASCII character to key code mapping
@@ -444,6 +706,61 @@ mod tests {
         assert_eq!(key_from_name("PAUSE"), Some(Key::from(119)));
     }
 
+    #[test]
+    fn test_mouse_button_key_names() {
+        assert_eq!(key_from_name("btn_left"), Some(Key::from(272)));
+        assert_eq!(key_name(418), "ZOOMIN");
+    }
+
+    #[test]
+    fn test_high_key_names() {
+        assert_eq!(key_from_name("MICMUTE"), Some(Key::from(248)));
+        assert_eq!(key_from_name("KEY_MICMUTE"), Some(Key::from(248)));
+        assert_eq!(key_from_name("key_a"), Some(Key::from(30)));
+        assert_eq!(key_from_name("KEY_FN_F1"), Some(Key::from(0x1d2)));
+        assert_eq!(key_from_name("macro30"), Some(Key::from(0x2ad)));
+        assert_eq!(key_name(0x1d0), "FN");
+        assert_eq!(key_name(KEY_MAX + 1), "UNKNOWN");
+    }
+
+    #[test]
+    fn test_gamepad_key_names() {
+        assert_eq!(key_from_name("btn_south"), Some(Key::from(304)));
+        assert_eq!(key_from_name("BTN_A"), Some(Key::from(304)));
+        assert_eq!(key_name(547), "BTN_DPAD_RIGHT");
+    }
+
+    #[test]
+    fn test_media_key_names() {
+        assert_eq!(key_from_name("XF86AudioRaiseVolume"), Some(Key::from(115)));
+        assert_eq!(key_from_name("XF86MonBrightnessDown"), Some(Key::from(224)));
+        assert_eq!(key_from_name("VolumeUp"), Some(Key::from(115)));
+        assert_eq!(key_from_name("PrevTrack"), Some(Key::from(165)));
+    }
+
+    #[test]
+    fn test_key_names_follow_layout_and_aliases() {
+        let symbols = "key <K16> { [ a, A ] }; key <K30> { [ q, Q ] };";
+        let azerty = KeyboardLayout::from_keymap("fr", &crate::layout::test_keymap(symbols)).unwrap();
+        let names = KeyNames::new(Some(azerty))
+            .with_aliases(HashMap::from([("LAUNCHER".to_string(), Key::from(183))]));
+        assert_eq!(names.key("a"), Some(Key::from(16)));
+        assert_eq!(names.key("ESC"), Some(Key::from(1)));
+        assert_eq!(names.key("launcher"), Some(Key::from(183)));
+        assert_eq!(KeyNames::default().key("a"), Some(Key::from(30)));
+        assert_eq!(key_from_name("a"), Some(Key::from(30)));
+    }
+
+    #[test]
+    fn test_all_key_names_round_trip() {
+        let names = all_key_names();
+        assert!(names.contains(&"LEFT_CTRL"));
+        assert!(names.contains(&"ESCAPE"));
+        for name in names {
+            assert!(key_from_name(name).is_some(), "{} should parse", name);
+        }
+    }
+
     #[test]
     fn test_key_display() {
         assert_eq!(Key::from(30).to_string(), "A");
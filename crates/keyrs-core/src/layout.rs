@@ -0,0 +1,497 @@
+// Keyrs Keyboard Layout
+// Character <-> evdev key mapping compiled from XKB data with libxkbcommon (non-QWERTY support)
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+
+use xkbcommon_dl::{
+    xkb_context, xkb_context_flags, xkb_keymap, xkb_keymap_compile_flags, xkb_keymap_format,
+    xkb_keysym_t, xkb_log_level, xkb_rule_names, xkbcommon_option, XkbCommon,
+};
+
+use crate::Key;
+
+/// Errors loading a keyboard layout
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("XKB symbols for layout '{0}' not found")]
+    NotFound(String),
+
+    #[error("XKB layout '{layout}' has no variant '{variant}'")]
+    UnknownVariant { layout: String, variant: String },
+
+    #[error("Could not detect the active keyboard layout")]
+    NotDetected,
+
+    #[error("XKB keymap '{0}' does not compile")]
+    InvalidKeymap(String),
+
+    #[error("libxkbcommon is not available")]
+    Unavailable,
+}
+
+/// Characters typed by each key of a layout (levels 1 and 2 only)
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardLayout {
+    name: String,
+    chars: HashMap<char, (Key, bool)>,
+    altgr: bool,
+}
+
+/// Characters on levels 1 and 2 of each typing key, by evdev code
+type Levels = BTreeMap<u16, [Option<char>; 2]>;
+
+/// XKB keycodes are evdev codes shifted by 8
+const EVDEV_OFFSET: u32 = 8;
+
+/// Evdev code of Right Alt
+const KEY_RIGHTALT: u16 = 100;
+
+/// Evdev codes of the alphanumeric block: number row, three letter rows,
+/// grave, backslash, space and the ISO key left of Z
+const TYPING_KEYS: &[u16] = &[
+    2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 30,
+    31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 57, 86,
+];
+
+impl KeyboardLayout {
+    /// The US QWERTY layout keyrs assumes when no layout is configured
+    pub fn us() -> Self {
+        let levels = US_KEYS
+            .iter()
+            .map(|&(code, base, shifted)| (code, [Some(base), Some(shifted)]))
+            .collect();
+        Self::from_levels("us".to_string(), &levels, false)
+    }
+
+    /// Compile `layout`/`variant` from the system XKB data (`$XKB_CONFIG_ROOT` or `/usr/share/X11/xkb`)
+    pub fn load(layout: &str, variant: Option<&str>) -> Result<Self, LayoutError> {
+        let name = match variant {
+            Some(variant) => format!("{}({})", layout, variant),
+            None => layout.to_string(),
+        };
+        match compile_names(layout, variant) {
+            Ok(Some((levels, altgr))) => Ok(Self::from_levels(name, &levels, altgr)),
+            // Without XKB data or libxkbcommon, the built-in US layout still works
+            Ok(None) | Err(LayoutError::Unavailable) if layout == "us" && variant.is_none() => Ok(Self::us()),
+            Ok(None) => match variant {
+                Some(variant) if matches!(compile_names(layout, None), Ok(Some(_))) => {
+                    Err(LayoutError::UnknownVariant { layout: layout.to_string(), variant: variant.to_string() })
+                }
+                _ => Err(LayoutError::NotFound(layout.to_string())),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Build a layout from a complete XKB keymap in text form, such as the
+    /// one a Wayland compositor sends (first group only)
+    pub fn from_keymap(name: &str, keymap: &str) -> Result<Self, LayoutError> {
+        let text = CString::new(keymap).map_err(|_| LayoutError::InvalidKeymap(name.to_string()))?;
+        let (levels, altgr) = compile(|xkb, context| unsafe {
+            (xkb.xkb_keymap_new_from_string)(
+                context,
+                text.as_ptr(),
+                xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            )
+        })?
+        .ok_or_else(|| LayoutError::InvalidKeymap(name.to_string()))?;
+        Ok(Self::from_levels(name.to_string(), &levels, altgr))
+    }
+
+    /// Detect and load the session's keyboard layout
+    pub fn detect() -> Result<Self, LayoutError> {
+        let (layout, variant) = detect_layout_name().ok_or(LayoutError::NotDetected)?;
+        log::info!(
+            "Detected keyboard layout '{}'{}",
+            layout,
+            variant.as_deref().map(|v| format!(" ({})", v)).unwrap_or_default()
+        );
+        Self::load(&layout, variant.as_deref())
+    }
+
+    fn from_levels(name: String, levels: &Levels, altgr: bool) -> Self {
+        // Prefer unshifted positions, then the lowest keycode, for characters typed by several keys.
+        let mut chars = HashMap::new();
+        for (level, shifted) in [(0, false), (1, true)] {
+            for (code, entry) in levels {
+                if let Some(ch) = entry[level] {
+                    chars.entry(ch).or_insert((Key::from(*code), shifted));
+                }
+            }
+        }
+        Self { name, chars, altgr }
+    }
+
+    /// Layout name, e.g. `fr` or `us(dvorak)`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether Right Alt is AltGr (the ISO level 3 shift) in this layout
+    pub fn has_altgr(&self) -> bool {
+        self.altgr
+    }
+
+    /// Key (and whether Shift is needed) that types `ch`
+    pub fn key_for_char(&self, ch: char) -> Option<(Key, bool)> {
+        self.chars.get(&ch).copied()
+    }
+
+    /// Key for a single-character config key name (`a`, `;`, `1`)
+    pub fn key_for_name(&self, name: &str) -> Option<Key> {
+        let mut chars = name.chars();
+        let ch = chars.next()?;
+        if chars.next().is_some() {
+            return None;
+        }
+        self.key_for_char(ch.to_lowercase().next().unwrap_or(ch))
+            .or_else(|| self.key_for_char(ch))
+            .map(|(key, _)| key)
+    }
+}
+
+/// Compile `layout`/`variant` with the evdev rules; `None` if it does not compile
+fn compile_names(layout: &str, variant: Option<&str>) -> Result<Option<(Levels, bool)>, LayoutError> {
+    let (Ok(layout), Ok(variant)) = (CString::new(layout), CString::new(variant.unwrap_or_default())) else {
+        return Ok(None);
+    };
+    let rules = c"evdev";
+    let model = c"pc105";
+    let names = xkb_rule_names {
+        rules: rules.as_ptr(),
+        model: model.as_ptr(),
+        layout: layout.as_ptr(),
+        variant: variant.as_ptr(),
+        options: std::ptr::null(),
+    };
+    compile(|xkb, context| unsafe {
+        (xkb.xkb_keymap_new_from_names)(context, &names, xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS)
+    })
+}
+
+/// Compile a keymap with libxkbcommon and read the characters of its first group
+fn compile(
+    new_keymap: impl FnOnce(&XkbCommon, *mut xkb_context) -> *mut xkb_keymap,
+) -> Result<Option<(Levels, bool)>, LayoutError> {
+    let xkb = xkbcommon_option().ok_or(LayoutError::Unavailable)?;
+    // SAFETY: the context and keymap are created and released here and not used afterwards
+    unsafe {
+        let context = (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_ENVIRONMENT_NAMES);
+        if context.is_null() {
+            return Err(LayoutError::Unavailable);
+        }
+        // Unknown layouts are reported through LayoutError, not libxkbcommon's stderr log
+        (xkb.xkb_context_set_log_level)(context, xkb_log_level::XKB_LOG_LEVEL_CRITICAL);
+        let keymap = new_keymap(xkb, context);
+        let result = (!keymap.is_null()).then(|| {
+            let found = read_levels(xkb, keymap);
+            (xkb.xkb_keymap_unref)(keymap);
+            found
+        });
+        (xkb.xkb_context_unref)(context);
+        Ok(result)
+    }
+}
+
+/// Characters of the typing keys and whether Right Alt is the level 3 shift
+///
+/// # Safety
+/// `keymap` must be a valid keymap created by `xkb`.
+unsafe fn read_levels(xkb: &XkbCommon, keymap: *mut xkb_keymap) -> (Levels, bool) {
+    let keysym = |code: u16, level: u32| -> Option<xkb_keysym_t> {
+        let mut syms: *const xkb_keysym_t = std::ptr::null();
+        // SAFETY: libxkbcommon points `syms` at `count` keysyms owned by the keymap
+        unsafe {
+            let count = (xkb.xkb_keymap_key_get_syms_by_level)(
+                keymap,
+                u32::from(code) + EVDEV_OFFSET,
+                0,
+                level,
+                &mut syms,
+            );
+            (count == 1 && !syms.is_null()).then(|| *syms)
+        }
+    };
+    // Dead keys and functions have no character
+    let to_char = |sym: xkb_keysym_t| {
+        // SAFETY: a pure keysym lookup
+        let codepoint = unsafe { (xkb.xkb_keysym_to_utf32)(sym) };
+        char::from_u32(codepoint).filter(|ch| *ch != '\0' && !ch.is_control())
+    };
+
+    let mut levels = Levels::new();
+    for &code in TYPING_KEYS {
+        let entry = [0, 1].map(|level| keysym(code, level).and_then(to_char));
+        if entry.iter().any(Option::is_some) {
+            levels.insert(code, entry);
+        }
+    }
+    let altgr = keysym(KEY_RIGHTALT, 0) == Some(xkbcommon_dl::keysyms::ISO_Level3_Shift);
+    (levels, altgr)
+}
+
+/// Levels 1 and 2 of the US QWERTY typing keys
+const US_KEYS: &[(u16, char, char)] = &[
+    (41, '`', '~'),
+    (2, '1', '!'),
+    (3, '2', '@'),
+    (4, '3', '#'),
+    (5, '4', '$'),
+    (6, '5', '%'),
+    (7, '6', '^'),
+    (8, '7', '&'),
+    (9, '8', '*'),
+    (10, '9', '('),
+    (11, '0', ')'),
+    (12, '-', '_'),
+    (13, '=', '+'),
+    (16, 'q', 'Q'),
+    (17, 'w', 'W'),
+    (18, 'e', 'E'),
+    (19, 'r', 'R'),
+    (20, 't', 'T'),
+    (21, 'y', 'Y'),
+    (22, 'u', 'U'),
+    (23, 'i', 'I'),
+    (24, 'o', 'O'),
+    (25, 'p', 'P'),
+    (26, '[', '{'),
+    (27, ']', '}'),
+    (30, 'a', 'A'),
+    (31, 's', 'S'),
+    (32, 'd', 'D'),
+    (33, 'f', 'F'),
+    (34, 'g', 'G'),
+    (35, 'h', 'H'),
+    (36, 'j', 'J'),
+    (37, 'k', 'K'),
+    (38, 'l', 'L'),
+    (39, ';', ':'),
+    (40, '\'', '"'),
+    (44, 'z', 'Z'),
+    (45, 'x', 'X'),
+    (46, 'c', 'C'),
+    (47, 'v', 'V'),
+    (48, 'b', 'B'),
+    (49, 'n', 'N'),
+    (50, 'm', 'M'),
+    (51, ',', '<'),
+    (52, '.', '>'),
+    (53, '/', '?'),
+    (43, '\\', '|'),
+    (57, ' ', ' '),
+];
+
+/// Layout and variant of the session, from `XKB_DEFAULT_LAYOUT`/`XKB_DEFAULT_VARIANT`,
+/// `/etc/default/keyboard` or the X11 keyboard config written by localectl
+pub fn detect_layout_name() -> Option<(String, Option<String>)> {
+    let first = |value: &str| -> Option<String> {
+        value
+            .split(',')
+            .next()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+
+    if let Ok(layout) = std::env::var("XKB_DEFAULT_LAYOUT") {
+        if let Some(layout) = first(&layout) {
+            let variant = std::env::var("XKB_DEFAULT_VARIANT").ok().and_then(|v| first(&v));
+            return Some((layout, variant));
+        }
+    }
+
+    if let Ok(content) = std::fs::read_to_string("/etc/default/keyboard") {
+        if let Some(found) = parse_default_keyboard(&content) {
+            return Some(found);
+        }
+    }
+
+    let content = std::fs::read_to_string("/etc/X11/xorg.conf.d/00-keyboard.conf").ok()?;
+    parse_xorg_keyboard_conf(&content)
+}
+
+/// Parse `XKBLAYOUT=`/`XKBVARIANT=` from `/etc/default/keyboard`
+fn parse_default_keyboard(content: &str) -> Option<(String, Option<String>)> {
+    let value = |key: &str| -> Option<String> {
+        content.lines().find_map(|line| {
+            let rest = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+            let value = rest.trim().trim_matches('"');
+            value.split(',').next().filter(|v| !v.is_empty()).map(str::to_string)
+        })
+    };
+    Some((value("XKBLAYOUT")?, value("XKBVARIANT")))
+}
+
+/// Parse `Option "XkbLayout"`/`Option "XkbVariant"` from an xorg InputClass section
+fn parse_xorg_keyboard_conf(content: &str) -> Option<(String, Option<String>)> {
+    let value = |key: &str| -> Option<String> {
+        content.lines().find_map(|line| {
+            let parts: Vec<&str> = line.split('"').collect();
+            if parts.len() >= 4 && line.trim_start().starts_with("Option") && parts[1] == key {
+                parts[3].split(',').next().filter(|v| !v.is_empty()).map(str::to_string)
+            } else {
+                None
+            }
+        })
+    };
+    Some((value("XkbLayout")?, value("XkbVariant")))
+}
+
+/// Symbol files pulled into a compiled keymap by XKB options rather than layouts
+const OPTION_SYMBOLS: &[&str] = &[
+    "pc", "inet", "group", "compose", "ctrl", "capslock", "caps", "level3", "level5", "altwin",
+    "terminate", "keypad", "kpdl", "eurosign", "rupeesign", "nbsp", "shift", "srvr_ctrl",
+    "japan", "korean", "parens",
+];
+
+/// Layout names of a compiled XKB keymap, indexed by group
+///
+/// Read from the `xkb_symbols "pc+us+de:2+inet(evdev)"` include string a
+/// compositor sends with its keymap; variants are kept (`us(intl)`). Falls
+/// back to the `name[GroupN]` descriptions when the include is missing.
+pub fn keymap_layout_names(keymap: &str) -> Vec<String> {
+    let quoted = |line: &str| -> Option<String> {
+        let start = line.find('"')? + 1;
+        let end = start + line[start..].find('"')?;
+        Some(line[start..end].to_string())
+    };
+
+    let mut groups: Vec<(u32, String)> = Vec::new();
+    let include = keymap
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("xkb_symbols").and_then(quoted));
+    for part in include.as_deref().unwrap_or_default().split('+') {
+        let (name, group) = match part.rsplit_once(':') {
+            Some((name, group)) => (name, group.parse::<u32>().ok()),
+            None => (part, None),
+        };
+        let base = name.split('(').next().unwrap_or(name);
+        if base.is_empty() || OPTION_SYMBOLS.contains(&base) {
+            continue;
+        }
+        // Only the first layout goes without a `:N` group suffix
+        let group = match group {
+            Some(group) => group,
+            None if groups.iter().all(|(g, _)| *g != 1) => 1,
+            None => continue,
+        };
+        if groups.iter().all(|(g, _)| *g != group) {
+            groups.push((group, name.to_string()));
+        }
+    }
+
+    if groups.is_empty() {
+        for line in keymap.lines() {
+            let Some(rest) = line.trim().strip_prefix("name[Group") else { continue };
+            let group = rest.split(']').next().and_then(|g| g.parse::<u32>().ok());
+            if let (Some(group), Some(name)) = (group, quoted(rest)) {
+                groups.push((group, name));
+            }
+        }
+    }
+
+    groups.sort_by_key(|(group, _)| *group);
+    groups.into_iter().map(|(_, name)| name).collect()
+}
+
+/// A self-contained XKB keymap (no includes) with `symbols` on the typing keys
+#[cfg(test)]
+pub(crate) fn test_keymap(symbols: &str) -> String {
+    let mut keycodes = String::new();
+    for &code in TYPING_KEYS.iter().chain([&KEY_RIGHTALT]) {
+        keycodes.push_str(&format!("        <K{}> = {};\n", code, u32::from(code) + EVDEV_OFFSET));
+    }
+    format!(
+        r#"xkb_keymap {{
+    xkb_keycodes "test" {{
+        minimum = 8;
+        maximum = 255;
+{keycodes}    }};
+    xkb_types "test" {{
+        type "ONE_LEVEL" {{ modifiers = none; level_name[Level1] = "Any"; }};
+        type "TWO_LEVEL" {{ modifiers = Shift; map[Shift] = Level2; level_name[Level1] = "Base"; level_name[Level2] = "Shift"; }};
+        type "ALPHABETIC" {{ modifiers = Shift+Lock; map[Shift] = Level2; map[Lock] = Level2; level_name[Level1] = "Base"; level_name[Level2] = "Caps"; }};
+    }};
+    xkb_compatibility "test" {{ }};
+    xkb_symbols "test" {{
+{symbols}
+    }};
+}};
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Part of French AZERTY: keys named by evdev code (`<K16>` is AD01)
+    const FR_SYMBOLS: &str = r#"
+        key <K3> { [ eacute, 2 ] };
+        key <K16> { [ a, A ] };
+        key <K30> { [ q, Q ] };
+        key <K50> { [ comma, question ] };
+        key <K40> { [ ugrave, percent ] };
+        key <K44> { [ w, W ] };
+        key <K45> { [ dead_acute, x ] };
+        key <K100> { type = "ONE_LEVEL", [ ISO_Level3_Shift ] };
+"#;
+
+    #[test]
+    fn test_us_layout_matches_qwerty() {
+        let us = KeyboardLayout::us();
+        assert_eq!(us.key_for_char('a'), Some((Key::from(30), false)));
+        assert_eq!(us.key_for_char('A'), Some((Key::from(30), true)));
+        assert_eq!(us.key_for_char('_'), Some((Key::from(12), true)));
+        assert_eq!(us.key_for_char(' '), Some((Key::from(57), false)));
+        assert!(!us.has_altgr());
+    }
+
+    #[test]
+    fn test_azerty_keymap_compiles_with_xkbcommon() {
+        let fr = KeyboardLayout::from_keymap("fr", &test_keymap(FR_SYMBOLS)).unwrap();
+        assert_eq!(fr.name(), "fr");
+        assert_eq!(fr.key_for_char('a'), Some((Key::from(16), false)));
+        assert_eq!(fr.key_for_char('q'), Some((Key::from(30), false)));
+        assert_eq!(fr.key_for_char('é'), Some((Key::from(3), false)));
+        assert_eq!(fr.key_for_char('2'), Some((Key::from(3), true)));
+        assert_eq!(fr.key_for_char('ù'), Some((Key::from(40), false)));
+        assert_eq!(fr.key_for_char('?'), Some((Key::from(50), true)));
+        // Dead keys type nothing by themselves
+        assert_eq!(fr.key_for_char('x'), Some((Key::from(45), true)));
+        assert_eq!(fr.key_for_char('m'), None);
+        assert!(fr.has_altgr());
+        assert_eq!(fr.key_for_name("A"), Some(Key::from(16)));
+        assert_eq!(fr.key_for_name("ESC"), None);
+
+        assert!(matches!(
+            KeyboardLayout::from_keymap("broken", "xkb_keymap {"),
+            Err(LayoutError::InvalidKeymap(_))
+        ));
+    }
+
+    #[test]
+    fn test_detect_layout_from_system_files() {
+        let keyboard = "XKBMODEL=\"pc105\"\nXKBLAYOUT=\"fr,us\"\nXKBVARIANT=\"\"\n";
+        assert_eq!(parse_default_keyboard(keyboard), Some(("fr".to_string(), None)));
+
+        let xorg = "Section \"InputClass\"\n    Option \"XkbLayout\" \"us\"\n    Option \"XkbVariant\" \"dvorak\"\nEndSection\n";
+        assert_eq!(
+            parse_xorg_keyboard_conf(xorg),
+            Some(("us".to_string(), Some("dvorak".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_keymap_layout_names() {
+        let keymap = "xkb_keymap {\n\txkb_keycodes \"evdev+aliases(qwerty)\" {\n\t};\n\txkb_symbols \"pc+us(intl)+de:2+inet(evdev)+group(alt_shift_toggle)\" {\n\t\tname[Group1]=\"English (intl)\";\n\t};\n};\n";
+        assert_eq!(keymap_layout_names(keymap), vec!["us(intl)", "de"]);
+
+        let described = "xkb_symbols {\n\tname[Group2]=\"German\";\n\tname[Group1]=\"English (US)\";\n};\n";
+        assert_eq!(keymap_layout_names(described), vec!["English (US)", "German"]);
+        assert!(keymap_layout_names("").is_empty());
+    }
+}
@@ -2,14 +2,21 @@
 // Core data models for keyboard remapping
 
 pub mod action;
+pub mod clock;
 pub mod combo;
+pub mod condition;
 pub mod config;
 pub mod input;
 pub mod key;
+pub mod layout;
+pub mod logging;
 pub mod mapping;
 pub mod modifier;
 pub mod output;
+pub mod runtime;
 pub mod state;
+pub mod status;
+pub mod systemd;
 pub mod transform;
 pub mod trigger;
 pub mod window;
@@ -21,27 +28,49 @@ mod test_minimal;
 #[cfg(feature = "pure-rust")]
 pub mod settings;
 
+#[cfg(feature = "pure-rust")]
+pub mod metrics;
+
+#[cfg(feature = "pure-rust")]
+pub mod audit;
+
+#[cfg(feature = "pure-rust")]
+pub mod control;
+
+#[cfg(feature = "pure-rust")]
+pub mod privsep;
+
+#[cfg(feature = "pure-rust")]
+pub mod instance;
+
 // Event module is available for both pure-rust and python-runtime features
 #[cfg(any(feature = "pure-rust", feature = "python-runtime"))]
 pub mod event;
 
 pub use action::Action;
+pub use clock::{Clock, ManualClock, SystemClock};
 pub use combo::{Combo, ComboHint};
+pub use condition::{
+    expand_named_conditions, resolve_named_conditions, validate_condition, Condition, ConditionExpr,
+};
 pub use config::{
-    expand_combo, expand_keymap_entries, parse_combo_string, ComboParseError, ParsedCombo,
+    expand_combo, expand_keymap_entries, parse_combo_string, validate_combo, ComboParseError,
+    ComboTrigger, ComboValidationError, ParsedCombo,
 };
 pub use input::{
-    is_emergency_key, is_key_event, is_keyboard, is_virtual_device, matches_device_filter,
+    is_emergency_key, is_gamepad, is_key_event, is_keyboard, is_virtual_device, matches_device_filter,
     DeviceCapabilities,
 };
-pub use key::Key;
-pub use mapping::{Keymap, KeymapValue, Keystate, Modmap, MultiModmap, MultipurposeManager, MultipurposeResult};
+pub use key::{Key, KeyNames};
+pub use layout::{KeyboardLayout, LayoutError};
+pub use mapping::{Keymap, KeymapValue, Keystate, LayerAction, LayerMode, Modmap, MultiModmap, MultipurposeManager, MultipurposeResult, RepeatRate, TapDance, TapDanceResult};
 pub use modifier::{Modifier, ModifierError};
 
 #[cfg(feature = "pure-rust")]
-pub use settings::{Settings, SettingsError};
+pub use settings::{write_device_keyboard_type, Settings, SettingsError, SettingsWatcher};
 pub use output::{
-    calculate_combo_actions, CacheData, ComboActionSequence, OutputCache, PressedKeyState,
+    calculate_combo_actions, CacheData, ComboActionSequence, ComboStyle, OutputCache,
+    PressedKeyState,
 };
 pub use state::Keystore;
 pub use transform::combo::{find_combo_match, ComboMatchResult};
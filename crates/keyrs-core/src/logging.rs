@@ -0,0 +1,144 @@
+// Keyrs Logging Settings
+// Per-subsystem log levels and output format shared by config and CLI
+
+use std::str::FromStr;
+
+use log::LevelFilter;
+
+/// Short subsystem names accepted in place of module targets
+pub const SUBSYSTEMS: &[(&str, &str)] = &[
+    ("config", "keyrs_core::config"),
+    ("input", "keyrs_core::input"),
+    ("event", "keyrs_core::event"),
+    ("transform", "keyrs_core::transform"),
+    ("output", "keyrs_core::output"),
+    ("window", "keyrs_core::window"),
+    ("layout", "keyrs_core::layout"),
+    ("metrics", "keyrs_core::metrics"),
+    ("systemd", "keyrs_core::systemd"),
+];
+
+/// Log line format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line, for journald/log shippers
+    Json,
+}
+
+impl LogFormat {
+    /// Parse a format name as used in `[logging].format` and `--log-format`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "text" | "plain" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Logging settings from the `[logging]` config section
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogSettings {
+    /// Default level for every target (None = keep the CLI default)
+    pub level: Option<LevelFilter>,
+    /// Per-target levels, targets already expanded from subsystem names
+    pub modules: Vec<(String, LevelFilter)>,
+    /// Output format (None = keep the CLI default)
+    pub format: Option<LogFormat>,
+}
+
+impl LogSettings {
+    /// Filter directives (`warn,keyrs_core::transform=debug`), empty when nothing is set
+    pub fn directives(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(level) = self.level {
+            parts.push(level_name(level).to_string());
+        }
+        for (target, level) in &self.modules {
+            parts.push(format!("{}={}", target, level_name(*level)));
+        }
+        parts.join(",")
+    }
+}
+
+/// Module target for a subsystem name; other names are used as given
+pub fn subsystem_target(name: &str) -> &str {
+    SUBSYSTEMS
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|(_, target)| *target)
+        .unwrap_or(name)
+}
+
+/// Parse a level name (`off`, `error`, `warn`, `info`, `debug`, `trace`)
+pub fn parse_level(name: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(name.trim()).map_err(|_| {
+        format!(
+            "invalid log level '{}' (expected off, error, warn, info, debug or trace)",
+            name.trim()
+        )
+    })
+}
+
+/// Validate `level` / `target=level` directives and expand subsystem names
+///
+/// `"debug,transform=trace"` becomes `"debug,keyrs_core::transform=trace"`.
+pub fn normalize_directives(directives: &str) -> Result<String, String> {
+    let mut parts = Vec::new();
+    for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((target, level)) => {
+                let target = target.trim();
+                if target.is_empty() {
+                    return Err(format!("missing target in log directive '{}'", directive));
+                }
+                let level = parse_level(level)?;
+                parts.push(format!("{}={}", subsystem_target(target), level_name(level)));
+            }
+            None => parts.push(level_name(parse_level(directive)?).to_string()),
+        }
+    }
+    Ok(parts.join(","))
+}
+
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_directives_expands_subsystems() {
+        assert_eq!(
+            normalize_directives("INFO, transform=debug,keyrs_core::output::uinput=trace").unwrap(),
+            "info,keyrs_core::transform=debug,keyrs_core::output::uinput=trace"
+        );
+        assert!(normalize_directives("transform=loud").is_err());
+        assert!(normalize_directives("=debug").is_err());
+        assert_eq!(normalize_directives("").unwrap(), "");
+    }
+
+    #[test]
+    fn test_settings_directives() {
+        let settings = LogSettings {
+            level: Some(LevelFilter::Warn),
+            modules: vec![("keyrs_core::window".to_string(), LevelFilter::Debug)],
+            format: Some(LogFormat::Json),
+        };
+        assert_eq!(settings.directives(), "warn,keyrs_core::window=debug");
+        assert_eq!(LogSettings::default().directives(), "");
+        assert_eq!(LogFormat::from_name("JSON"), Some(LogFormat::Json));
+    }
+}
@@ -1,20 +1,25 @@
 // Keyrs Mapping Structures
 // Modmap, MultiModmap, Keymap, Keystate
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::clock::{system_clock, Clock};
+use crate::output::ComboStyle;
 use crate::Action;
 use crate::Combo;
+use crate::Condition;
 use crate::Key;
+use crate::Modifier;
 
 /// Simple key remapping (one key to another)
 #[derive(Debug, Clone)]
 pub struct Modmap {
     name: String,
     mappings: HashMap<Key, Key>,
-    conditional: Option<String>,
+    conditional: Option<Condition>,
 }
 
 impl Modmap {
@@ -36,7 +41,7 @@ impl Modmap {
         Self {
             name: name.into(),
             mappings,
-            conditional: Some(conditional),
+            conditional: Some(Condition::new(conditional)),
         }
     }
 
@@ -52,7 +57,12 @@ impl Modmap {
 
     /// Get the conditional (if any)
     pub fn conditional(&self) -> Option<&str> {
-        self.conditional.as_deref()
+        self.conditional.as_ref().map(Condition::source)
+    }
+
+    /// Get the parsed conditional (if any)
+    pub fn condition(&self) -> Option<&Condition> {
+        self.conditional.as_ref()
     }
 
     /// Check if a key is in this modmap
@@ -71,7 +81,7 @@ impl Modmap {
 pub struct MultiModmap {
     name: String,
     mappings: HashMap<Key, (Key, Key)>, // (tap_key, hold_key)
-    conditional: Option<String>,
+    conditional: Option<Condition>,
 }
 
 impl MultiModmap {
@@ -93,7 +103,7 @@ impl MultiModmap {
         Self {
             name: name.into(),
             mappings,
-            conditional: Some(conditional),
+            conditional: Some(Condition::new(conditional)),
         }
     }
 
@@ -109,7 +119,12 @@ impl MultiModmap {
 
     /// Get the conditional (if any)
     pub fn conditional(&self) -> Option<&str> {
-        self.conditional.as_deref()
+        self.conditional.as_ref().map(Condition::source)
+    }
+
+    /// Get the parsed conditional (if any)
+    pub fn condition(&self) -> Option<&Condition> {
+        self.conditional.as_ref()
     }
 
     /// Check if a key is in this multi-modmap
@@ -128,12 +143,114 @@ impl MultiModmap {
     }
 }
 
+/// Tap-dance key mapping (N quick taps select the Nth output, optional hold)
+#[derive(Debug, Clone)]
+pub struct TapDance {
+    name: String,
+    trigger: Key,
+    taps: Vec<Key>,
+    hold: Option<Key>,
+    conditional: Option<Condition>,
+}
+
+impl TapDance {
+    /// Create a new TapDance
+    pub fn new(name: impl Into<String>, trigger: Key, taps: Vec<Key>, hold: Option<Key>) -> Self {
+        Self {
+            name: name.into(),
+            trigger,
+            taps,
+            hold,
+            conditional: None,
+        }
+    }
+
+    /// Create a new TapDance with a conditional
+    pub fn with_conditional(
+        name: impl Into<String>,
+        trigger: Key,
+        taps: Vec<Key>,
+        hold: Option<Key>,
+        conditional: String,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            trigger,
+            taps,
+            hold,
+            conditional: Some(Condition::new(conditional)),
+        }
+    }
+
+    /// Get the name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the trigger key
+    pub fn trigger(&self) -> Key {
+        self.trigger
+    }
+
+    /// Get the tap outputs (index 0 = single tap, 1 = double tap, ...)
+    pub fn taps(&self) -> &[Key] {
+        &self.taps
+    }
+
+    /// Get the hold output (if any)
+    pub fn hold(&self) -> Option<Key> {
+        self.hold
+    }
+
+    /// Get the conditional (if any)
+    pub fn conditional(&self) -> Option<&str> {
+        self.conditional.as_ref().map(Condition::source)
+    }
+
+    /// Get the parsed conditional (if any)
+    pub fn condition(&self) -> Option<&Condition> {
+        self.conditional.as_ref()
+    }
+
+    /// Get the output for a given tap count (clamped to the last entry)
+    pub fn tap_output(&self, count: usize) -> Option<Key> {
+        if count == 0 {
+            return None;
+        }
+        self.taps
+            .get(count - 1)
+            .or_else(|| self.taps.last())
+            .copied()
+    }
+}
+
 /// Keymap for key combinations
 #[derive(Debug, Clone)]
 pub struct Keymap {
     name: String,
     mappings: HashMap<Combo, KeymapValue>,
-    conditional: Option<String>,
+    conditional: Option<Condition>,
+    /// Synthetic repeat for held combos (others are not repeated)
+    repeats: HashMap<Combo, RepeatRate>,
+    /// Combos whose output fires when their key is released (`release:`)
+    release_triggers: HashSet<Combo>,
+    /// Output of combos held past the multipurpose timeout, their plain
+    /// mapping then being the tap output
+    holds: HashMap<Combo, KeymapValue>,
+    /// How long this keymap stays on the nested keymap stack (milliseconds),
+    /// overriding `nested_keymap_timeout`
+    nested_timeout: Option<u64>,
+    /// Modifier order and delay for combos this keymap emits
+    combo_style: Option<ComboStyle>,
+}
+
+/// Synthetic repeat of a keymap output while its input combo is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatRate {
+    /// Time from the press to the first repeat
+    pub delay_ms: u64,
+    /// Time between repeats
+    pub interval_ms: u64,
 }
 
 /// Value in a keymap - can be a Combo, ComboHint, or a key
@@ -145,6 +262,43 @@ pub enum KeymapValue {
     Key(Key),
     Unicode(u32),
     Text(String),
+    /// Activate a layer (see `LayerAction`)
+    Layer(LayerAction),
+    /// Enter the named nested keymap for the next key press
+    EnterKeymap(String),
+    /// Leave the combo unmapped: later keymaps are not consulted and the key
+    /// goes out as if nothing mapped it
+    Passthrough,
+}
+
+/// How a layer stays active after its trigger key is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerMode {
+    /// Active while the trigger key is held
+    Momentary,
+    /// Each press switches the layer on or off
+    Toggle,
+    /// Active for the next non-modifier key press only
+    OneShot,
+}
+
+impl LayerMode {
+    /// Parse a mode name as used in `Layer(name, mode)`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "momentary" | "hold" => Some(Self::Momentary),
+            "toggle" => Some(Self::Toggle),
+            "oneshot" | "one_shot" | "one-shot" => Some(Self::OneShot),
+            _ => None,
+        }
+    }
+}
+
+/// Layer activation bound to a keymap combo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerAction {
+    pub name: String,
+    pub mode: LayerMode,
 }
 
 /// A single step in a keymap output sequence.
@@ -156,6 +310,74 @@ pub enum ActionStep {
     Ignore,
     Bind,
     SetSetting { name: String, value: bool },
+    /// Run `steps` after `delay_ms` on the output timer without blocking
+    After { delay_ms: u64, steps: Vec<ActionStep> },
+    /// Run `steps` `times` times in a row
+    Repeat { times: u32, steps: Vec<ActionStep> },
+    /// Press `key` and keep it down for the following steps
+    Press(Key),
+    /// Release a key held by `Press`
+    Release(Key),
+    /// Press `key`, wait `ms`, release it
+    Hold { key: Key, ms: u64 },
+    /// Set (`Some(true)`), unset (`Some(false)`) or toggle (`None`) the mark
+    SetMark(Option<bool>),
+    /// Leave these held modifiers down for the sequence, `Text(...)` steps
+    /// included, instead of releasing them
+    KeepModifiers(Vec<Modifier>),
+    /// Start a shell command without waiting for it
+    Run(String),
+    /// Switch to the named `[profiles]` config, or the next one (`None`)
+    Profile(Option<String>),
+}
+
+/// Steps in config syntax, e.g. `Combo(C-c)`, `Delay(50)`, `After(100, Text(hi))`
+impl fmt::Display for ActionStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |steps: &[ActionStep]| steps.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ");
+        match self {
+            ActionStep::Combo(combo) if combo.modifiers().is_empty() => write!(f, "Combo({})", combo.key()),
+            ActionStep::Combo(combo) => write!(f, "Combo({})", combo),
+            ActionStep::Text(text) => write!(f, "Text({})", text),
+            ActionStep::DelayMs(ms) => write!(f, "Delay({})", ms),
+            ActionStep::Ignore => write!(f, "Ignore"),
+            ActionStep::Bind => write!(f, "Bind"),
+            ActionStep::SetSetting { name, value } => write!(f, "SetSetting({}={})", name, value),
+            ActionStep::After { delay_ms, steps } => write!(f, "After({}, {})", delay_ms, join(steps)),
+            ActionStep::Repeat { times, steps } => write!(f, "Repeat({}, {})", times, join(steps)),
+            ActionStep::Press(key) => write!(f, "Press({})", key),
+            ActionStep::Release(key) => write!(f, "Release({})", key),
+            ActionStep::Hold { key, ms } => write!(f, "Hold({}, {})", key, ms),
+            ActionStep::SetMark(None) => write!(f, "SetMark"),
+            ActionStep::SetMark(Some(true)) => write!(f, "SetMark(on)"),
+            ActionStep::SetMark(Some(false)) => write!(f, "SetMark(off)"),
+            ActionStep::KeepModifiers(modifiers) => {
+                let names: Vec<&str> = modifiers.iter().map(Modifier::primary_alias).collect();
+                write!(f, "KeepModifiers({})", names.join(", "))
+            }
+            ActionStep::Run(command) => write!(f, "Run({})", command),
+            ActionStep::Profile(None) => write!(f, "Profile"),
+            ActionStep::Profile(Some(name)) => write!(f, "Profile({})", name),
+        }
+    }
+}
+
+/// Upper bound for `ActionStep::Repeat` counts
+pub const MAX_REPEAT_TIMES: u32 = 100;
+
+/// Upper bound for the steps a `Repeat` expands to, nested repeats included
+pub const MAX_REPEAT_EXPANSION: u32 = 1000;
+
+impl ActionStep {
+    /// Number of steps this one runs, with `Repeat` and `After` expanded
+    pub fn expanded_len(&self) -> u32 {
+        let sum = |steps: &[ActionStep]| steps.iter().fold(0u32, |total, step| total.saturating_add(step.expanded_len()));
+        match self {
+            ActionStep::Repeat { times, steps } => times.saturating_mul(sum(steps)),
+            ActionStep::After { steps, .. } => sum(steps).max(1),
+            _ => 1,
+        }
+    }
 }
 
 impl From<Combo> for KeymapValue {
@@ -191,6 +413,11 @@ impl Keymap {
             name: name.into(),
             mappings: HashMap::new(),
             conditional: None,
+            repeats: HashMap::new(),
+            release_triggers: HashSet::new(),
+            holds: HashMap::new(),
+            nested_timeout: None,
+            combo_style: None,
         }
     }
 
@@ -200,6 +427,11 @@ impl Keymap {
             name: name.into(),
             mappings,
             conditional: None,
+            repeats: HashMap::new(),
+            release_triggers: HashSet::new(),
+            holds: HashMap::new(),
+            nested_timeout: None,
+            combo_style: None,
         }
     }
 
@@ -208,7 +440,12 @@ impl Keymap {
         Self {
             name: name.into(),
             mappings,
-            conditional: Some(conditional),
+            conditional: Some(Condition::new(conditional)),
+            repeats: HashMap::new(),
+            release_triggers: HashSet::new(),
+            holds: HashMap::new(),
+            nested_timeout: None,
+            combo_style: None,
         }
     }
 
@@ -224,7 +461,12 @@ impl Keymap {
 
     /// Get the conditional (if any)
     pub fn conditional(&self) -> Option<&str> {
-        self.conditional.as_deref()
+        self.conditional.as_ref().map(Condition::source)
+    }
+
+    /// Get the parsed conditional (if any)
+    pub fn condition(&self) -> Option<&Condition> {
+        self.conditional.as_ref()
     }
 
     /// Check if a combo is in this keymap
@@ -241,6 +483,56 @@ impl Keymap {
     pub fn insert(&mut self, combo: Combo, value: KeymapValue) {
         self.mappings.insert(combo, value);
     }
+
+    /// Repeat the output of `combo` at `rate` while it is held
+    pub fn set_repeat(&mut self, combo: Combo, rate: RepeatRate) {
+        self.repeats.insert(combo, rate);
+    }
+
+    /// Synthetic repeat rate for `combo`, if configured
+    pub fn repeat(&self, combo: &Combo) -> Option<RepeatRate> {
+        self.repeats.get(combo).copied()
+    }
+
+    /// Fire the output of `combo` when its key is released instead of pressed
+    pub fn set_release_trigger(&mut self, combo: Combo) {
+        self.release_triggers.insert(combo);
+    }
+
+    /// Whether `combo` fires on key release
+    pub fn fires_on_release(&self, combo: &Combo) -> bool {
+        self.release_triggers.contains(combo)
+    }
+
+    /// Emit `value` when `combo` is held rather than tapped
+    pub fn set_hold(&mut self, combo: Combo, value: KeymapValue) {
+        self.holds.insert(combo, value);
+    }
+
+    /// Hold output of `combo`, if it has one
+    pub fn hold(&self, combo: &Combo) -> Option<&KeymapValue> {
+        self.holds.get(combo)
+    }
+
+    /// Keep this keymap on the nested keymap stack for `ms` instead of the global timeout
+    pub fn set_nested_timeout(&mut self, ms: Option<u64>) {
+        self.nested_timeout = ms;
+    }
+
+    /// Per-keymap nested keymap timeout (milliseconds), if configured
+    pub fn nested_timeout(&self) -> Option<u64> {
+        self.nested_timeout
+    }
+
+    /// Emit this keymap's combos with `style` instead of the default order and delay
+    pub fn set_combo_style(&mut self, style: Option<ComboStyle>) {
+        self.combo_style = style;
+    }
+
+    /// Per-keymap combo emission style, if configured
+    pub fn combo_style(&self) -> Option<&ComboStyle> {
+        self.combo_style.as_ref()
+    }
 }
 
 /// State of a key during processing
@@ -333,8 +625,53 @@ pub struct MultipurposeManager {
     modmaps: HashMap<Key, MultiModmap>,
     /// Currently active multipurpose state (if any)
     active: Option<ActiveMultipurpose>,
-    /// Timeout duration for tap vs hold decision
+    /// All configured tap-dance entries (trigger_key -> tap-dance)
+    tapdances: HashMap<Key, TapDance>,
+    /// Currently active tap-dance state (if any)
+    active_tapdance: Option<ActiveTapDance>,
+    /// Timeout duration for tap vs hold decision (also the tap-dance tap window)
     timeout: std::time::Duration,
+    /// Time source for press and tap timestamps
+    clock: Arc<dyn Clock>,
+}
+
+/// Runtime state for active tap-dance key
+#[derive(Debug, Clone)]
+struct ActiveTapDance {
+    /// The trigger key
+    trigger_key: Key,
+    /// Number of presses counted so far
+    count: usize,
+    /// Whether the trigger is currently physically held
+    pressed: bool,
+    /// When the trigger was last pressed or released
+    last_event: std::time::Instant,
+    /// Current sub-state
+    state: TapDanceSubState,
+}
+
+/// Sub-states within tap-dance handling
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TapDanceSubState {
+    /// Counting taps
+    Counting,
+    /// Hold mode is active (timeout elapsed or interrupted while held)
+    Hold,
+    /// Tap output already emitted; waiting for the trigger release
+    Resolved,
+}
+
+/// Result of a tap-dance state transition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TapDanceResult {
+    /// Tap count resolved - output this key as a tap
+    Tap(Key),
+    /// Hold threshold reached - press the hold key
+    HoldPress(Key),
+    /// Release the hold key (was being held)
+    HoldRelease(Key),
+    /// Nothing to emit yet (still counting, or swallowed trigger release)
+    Pending,
 }
 
 /// Runtime state for active multipurpose key
@@ -376,7 +713,10 @@ impl MultipurposeManager {
         Self {
             modmaps: HashMap::new(),
             active: None,
+            tapdances: HashMap::new(),
+            active_tapdance: None,
             timeout: std::time::Duration::from_millis(200),
+            clock: system_clock(),
         }
     }
 
@@ -385,10 +725,18 @@ impl MultipurposeManager {
         Self {
             modmaps: HashMap::new(),
             active: None,
+            tapdances: HashMap::new(),
+            active_tapdance: None,
             timeout: std::time::Duration::from_millis(timeout_ms),
+            clock: system_clock(),
         }
     }
 
+    /// Use `clock` for press and tap timestamps
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
     /// Add a multipurpose modmap
     pub fn add_modmap(&mut self, modmap: MultiModmap) {
         // Store all mappings from this modmap
@@ -396,11 +744,11 @@ impl MultipurposeManager {
             let mut mappings = HashMap::new();
             mappings.insert(*trigger, (*tap, *hold));
             
-            // Preserve the conditional from the original modmap
-            let single_modmap = if let Some(cond) = &modmap.conditional {
-                MultiModmap::with_conditional(&modmap.name, mappings, cond.clone())
-            } else {
-                MultiModmap::new(&modmap.name, mappings)
+            // Preserve the (already parsed) conditional from the original modmap
+            let single_modmap = MultiModmap {
+                name: modmap.name.clone(),
+                mappings,
+                conditional: modmap.conditional.clone(),
             };
             
             self.modmaps.insert(*trigger, single_modmap);
@@ -423,8 +771,8 @@ impl MultipurposeManager {
     }
 
     /// Get the conditional string for a trigger key (if any)
-    pub fn get_conditional(&self, key: Key) -> Option<&str> {
-        self.modmaps.get(&key).and_then(|m| m.conditional())
+    pub fn get_conditional(&self, key: Key) -> Option<&Condition> {
+        self.modmaps.get(&key).and_then(|m| m.condition())
     }
 
     /// Check if there's an active multipurpose key
@@ -442,7 +790,7 @@ impl MultipurposeManager {
                     trigger_key: key,
                     tap_output,
                     hold_output,
-                    press_time: std::time::Instant::now(),
+                    press_time: self.clock.now(),
                     state: MultipurposeSubState::Pending,
                 });
                 return true;
@@ -454,9 +802,10 @@ impl MultipurposeManager {
     /// Check if the pending timeout has elapsed
     /// Returns Some(hold_key) if we should transition to hold mode
     pub fn check_timeout(&mut self) -> Option<Key> {
+        let now = self.clock.now();
         if let Some(ref mut active) = self.active {
             if active.state == MultipurposeSubState::Pending {
-                if active.press_time.elapsed() >= self.timeout {
+                if now.saturating_duration_since(active.press_time) >= self.timeout {
                     // Transition to hold
                     active.state = MultipurposeSubState::Hold;
                     return Some(active.hold_output);
@@ -466,6 +815,26 @@ impl MultipurposeManager {
         None
     }
 
+    /// Time until a pending tap/hold or tap-dance decision times out
+    pub fn next_timeout_in(&self) -> Option<std::time::Duration> {
+        let now = self.clock.now();
+        let pending = self
+            .active
+            .as_ref()
+            .filter(|active| active.state == MultipurposeSubState::Pending)
+            .map(|active| active.press_time);
+        let counting = self
+            .active_tapdance
+            .as_ref()
+            .filter(|active| active.state == TapDanceSubState::Counting)
+            .map(|active| active.last_event);
+        [pending, counting]
+            .into_iter()
+            .flatten()
+            .map(|since| self.timeout.saturating_sub(now.saturating_duration_since(since)))
+            .min()
+    }
+
     /// Handle another key being pressed while in pending state
     /// This causes an immediate transition to hold mode
     /// Returns Some((hold_key_press, new_key_to_process))
@@ -484,11 +853,12 @@ impl MultipurposeManager {
     /// Handle release of the multipurpose key
     /// Returns Some(result) - either tap or hold release
     pub fn release(&mut self) -> Option<MultipurposeResult> {
+        let now = self.clock.now();
         if let Some(active) = self.active.take() {
             match active.state {
                 MultipurposeSubState::Pending => {
                     // Short press = tap
-                    let elapsed = active.press_time.elapsed();
+                    let elapsed = now.saturating_duration_since(active.press_time);
                     if elapsed < self.timeout {
                         Some(MultipurposeResult::Tap(active.tap_output))
                     } else {
@@ -537,21 +907,196 @@ impl MultipurposeManager {
             .unwrap_or(false)
     }
 
+    /// Pending or held multipurpose and tap-dance keys, for diagnostics
+    /// (e.g. `CAPSLOCK held as LEFT_CTRL`)
+    pub fn describe_active(&self) -> Vec<String> {
+        let mut active = Vec::new();
+        if let Some(key) = &self.active {
+            active.push(match key.state {
+                MultipurposeSubState::Pending => format!(
+                    "{} pending (tap {}, hold {})",
+                    key.trigger_key, key.tap_output, key.hold_output
+                ),
+                MultipurposeSubState::Hold => format!("{} held as {}", key.trigger_key, key.hold_output),
+            });
+        }
+        if let Some(dance) = &self.active_tapdance {
+            let name = self.tapdances.get(&dance.trigger_key).map_or("", |tapdance| tapdance.name());
+            active.push(match dance.state {
+                TapDanceSubState::Counting => {
+                    format!("{} tap-dance '{}' counting ({} taps)", dance.trigger_key, name, dance.count)
+                }
+                TapDanceSubState::Hold => format!("{} tap-dance '{}' held", dance.trigger_key, name),
+                TapDanceSubState::Resolved => format!("{} tap-dance '{}' resolved", dance.trigger_key, name),
+            });
+        }
+        active
+    }
+
     /// Clear any active state (e.g., on suspend)
     pub fn clear(&mut self) {
         self.active = None;
+        self.active_tapdance = None;
     }
 
     /// Clear active state and return the hold key if in hold state.
     /// This should be used when clearing state to ensure the hold key
     /// is properly released (e.g., on window change).
     pub fn clear_and_get_hold_key(&mut self) -> Option<Key> {
+        let tapdance_hold = self.clear_tapdance_and_get_hold_key();
         if let Some(active) = self.active.take() {
             if active.state == MultipurposeSubState::Hold {
                 return Some(active.hold_output);
             }
         }
-        None
+        tapdance_hold
+    }
+
+    /// Add a tap-dance entry
+    pub fn add_tapdance(&mut self, tapdance: TapDance) {
+        self.tapdances.insert(tapdance.trigger(), tapdance);
+    }
+
+    /// Check if a key is a tap-dance trigger
+    pub fn is_tapdance_trigger(&self, key: Key) -> bool {
+        self.tapdances.contains_key(&key)
+    }
+
+    /// Get the conditional string for a tap-dance trigger key (if any)
+    pub fn get_tapdance_conditional(&self, key: Key) -> Option<&Condition> {
+        self.tapdances.get(&key).and_then(|t| t.condition())
+    }
+
+    /// Check if there's an active tap-dance key
+    pub fn has_active_tapdance(&self) -> bool {
+        self.active_tapdance.is_some()
+    }
+
+    /// Get the trigger key for the active tap-dance
+    pub fn get_tapdance_trigger_key(&self) -> Option<Key> {
+        self.active_tapdance.as_ref().map(|a| a.trigger_key)
+    }
+
+    /// Get the hold key for the active tap-dance if it is in hold state
+    pub fn get_tapdance_hold_key(&self) -> Option<Key> {
+        let active = self.active_tapdance.as_ref()?;
+        if active.state != TapDanceSubState::Hold {
+            return None;
+        }
+        self.tapdances.get(&active.trigger_key).and_then(|t| t.hold())
+    }
+
+    /// Handle a press of a tap-dance trigger
+    /// Starts a new tap-dance or counts another tap on the active one.
+    /// Returns None if the key is not handled as a tap-dance press.
+    pub fn press_tapdance(&mut self, key: Key) -> Option<TapDanceResult> {
+        let now = self.clock.now();
+        if let Some(ref mut active) = self.active_tapdance {
+            if active.trigger_key != key || active.pressed || active.state != TapDanceSubState::Counting {
+                return None;
+            }
+            active.count += 1;
+            active.pressed = true;
+            active.last_event = now;
+            return Some(TapDanceResult::Pending);
+        }
+
+        if !self.tapdances.contains_key(&key) {
+            return None;
+        }
+        self.active_tapdance = Some(ActiveTapDance {
+            trigger_key: key,
+            count: 1,
+            pressed: true,
+            last_event: now,
+            state: TapDanceSubState::Counting,
+        });
+        Some(TapDanceResult::Pending)
+    }
+
+    /// Handle release of the tap-dance trigger
+    /// Resolves immediately when the last configured tap count is reached,
+    /// otherwise keeps counting until the tap window expires.
+    pub fn release_tapdance(&mut self) -> Option<TapDanceResult> {
+        let now = self.clock.now();
+        let active = self.active_tapdance.as_mut()?;
+        let tapdance = self.tapdances.get(&active.trigger_key)?;
+
+        match active.state {
+            TapDanceSubState::Hold => {
+                let hold = tapdance.hold();
+                self.active_tapdance = None;
+                hold.map(TapDanceResult::HoldRelease)
+            }
+            TapDanceSubState::Resolved => {
+                self.active_tapdance = None;
+                Some(TapDanceResult::Pending)
+            }
+            TapDanceSubState::Counting => {
+                if active.count >= tapdance.taps().len() {
+                    let output = tapdance.tap_output(active.count);
+                    self.active_tapdance = None;
+                    return output.map(TapDanceResult::Tap);
+                }
+                active.pressed = false;
+                active.last_event = now;
+                Some(TapDanceResult::Pending)
+            }
+        }
+    }
+
+    /// Check if the tap-dance timeout has elapsed
+    /// Returns HoldPress when the trigger was held past the timeout (and a
+    /// hold key is configured), or Tap when the tap window closed after a release.
+    pub fn check_tapdance_timeout(&mut self) -> Option<TapDanceResult> {
+        let now = self.clock.now();
+        let active = self.active_tapdance.as_mut()?;
+        if active.state != TapDanceSubState::Counting || now.saturating_duration_since(active.last_event) < self.timeout {
+            return None;
+        }
+        let tapdance = self.tapdances.get(&active.trigger_key)?;
+
+        if active.pressed {
+            let hold = tapdance.hold()?;
+            active.state = TapDanceSubState::Hold;
+            Some(TapDanceResult::HoldPress(hold))
+        } else {
+            let output = tapdance.tap_output(active.count);
+            self.active_tapdance = None;
+            output.map(TapDanceResult::Tap)
+        }
+    }
+
+    /// Handle another key being pressed while a tap-dance is counting
+    /// A held trigger switches to hold (if configured); otherwise the current
+    /// tap count is resolved immediately so it is emitted before the new key.
+    pub fn interrupt_tapdance(&mut self) -> Option<TapDanceResult> {
+        let active = self.active_tapdance.as_mut()?;
+        if active.state != TapDanceSubState::Counting {
+            return None;
+        }
+        let tapdance = self.tapdances.get(&active.trigger_key)?;
+
+        if active.pressed {
+            if let Some(hold) = tapdance.hold() {
+                active.state = TapDanceSubState::Hold;
+                return Some(TapDanceResult::HoldPress(hold));
+            }
+            // Trigger release still has to be swallowed later.
+            active.state = TapDanceSubState::Resolved;
+            return tapdance.tap_output(active.count).map(TapDanceResult::Tap);
+        }
+
+        let output = tapdance.tap_output(active.count);
+        self.active_tapdance = None;
+        output.map(TapDanceResult::Tap)
+    }
+
+    /// Clear active tap-dance state and return the hold key if in hold state.
+    pub fn clear_tapdance_and_get_hold_key(&mut self) -> Option<Key> {
+        let hold = self.get_tapdance_hold_key();
+        self.active_tapdance = None;
+        hold
     }
 }
 
@@ -699,6 +1244,21 @@ mod tests {
         assert!(matches!(result, Some(MultipurposeResult::HoldRelease(key)) if key == Key::from(97)));
     }
 
+    #[test]
+    fn test_next_timeout_in() {
+        let mut manager = MultipurposeManager::with_timeout(10_000);
+        manager.add_modmap(create_caps2esc_modmap());
+        assert_eq!(manager.next_timeout_in(), None);
+
+        manager.start(Key::from(58));
+        let due = manager.next_timeout_in().unwrap();
+        assert!(due > std::time::Duration::from_secs(5) && due <= std::time::Duration::from_secs(10));
+
+        // Decided: nothing left to wait for
+        manager.release();
+        assert_eq!(manager.next_timeout_in(), None);
+    }
+
     #[test]
     fn test_interrupt_handling() {
         let mut manager = MultipurposeManager::with_timeout(500); // 500ms timeout
@@ -879,4 +1439,116 @@ mod tests {
         assert!(!manager.is_pending_state());
         assert!(manager.is_hold_state());
     }
+
+    // Tap-dance tests
+    fn create_caps_tapdance() -> TapDance {
+        // CAPSLOCK (58): tap -> ESCAPE (1), double tap -> CAPSLOCK (58), hold -> LEFT_CTRL (29)
+        TapDance::new(
+            "CapsDance",
+            Key::from(58),
+            vec![Key::from(1), Key::from(58)],
+            Some(Key::from(29)),
+        )
+    }
+
+    #[test]
+    fn test_tapdance_output_clamps_to_last_tap() {
+        let tapdance = create_caps_tapdance();
+        assert_eq!(tapdance.tap_output(0), None);
+        assert_eq!(tapdance.tap_output(1), Some(Key::from(1)));
+        assert_eq!(tapdance.tap_output(2), Some(Key::from(58)));
+        assert_eq!(tapdance.tap_output(5), Some(Key::from(58)));
+    }
+
+    #[test]
+    fn test_tapdance_single_tap_resolves_after_window() {
+        let mut manager = MultipurposeManager::with_timeout(10);
+        manager.add_tapdance(create_caps_tapdance());
+        assert!(manager.is_tapdance_trigger(Key::from(58)));
+
+        assert_eq!(manager.press_tapdance(Key::from(58)), Some(TapDanceResult::Pending));
+        assert_eq!(manager.release_tapdance(), Some(TapDanceResult::Pending));
+        assert_eq!(manager.check_tapdance_timeout(), None);
+
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        assert_eq!(manager.check_tapdance_timeout(), Some(TapDanceResult::Tap(Key::from(1))));
+        assert!(!manager.has_active_tapdance());
+    }
+
+    #[test]
+    fn test_tapdance_double_tap_resolves_on_last_release() {
+        let mut manager = MultipurposeManager::with_timeout(500);
+        manager.add_tapdance(create_caps_tapdance());
+
+        manager.press_tapdance(Key::from(58));
+        manager.release_tapdance();
+        assert_eq!(manager.press_tapdance(Key::from(58)), Some(TapDanceResult::Pending));
+
+        // Second tap is the last configured output, so no need to wait
+        assert_eq!(manager.release_tapdance(), Some(TapDanceResult::Tap(Key::from(58))));
+        assert!(!manager.has_active_tapdance());
+    }
+
+    #[test]
+    fn test_describe_active() {
+        let mut manager = MultipurposeManager::with_timeout(500);
+        manager.add_modmap(create_caps2esc_modmap());
+        assert!(manager.describe_active().is_empty());
+
+        manager.start(Key::from(58));
+        assert_eq!(manager.describe_active(), ["CAPSLOCK pending (tap ESC, hold RIGHT_CTRL)"]);
+        manager.clear();
+
+        manager.add_tapdance(create_caps_tapdance());
+        manager.press_tapdance(Key::from(58));
+        assert_eq!(manager.describe_active(), ["CAPSLOCK tap-dance 'CapsDance' counting (1 taps)"]);
+    }
+
+    #[test]
+    fn test_tapdance_hold_via_timeout() {
+        let mut manager = MultipurposeManager::with_timeout(10);
+        manager.add_tapdance(create_caps_tapdance());
+
+        manager.press_tapdance(Key::from(58));
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        assert_eq!(manager.check_tapdance_timeout(), Some(TapDanceResult::HoldPress(Key::from(29))));
+        assert_eq!(manager.get_tapdance_hold_key(), Some(Key::from(29)));
+
+        assert_eq!(manager.release_tapdance(), Some(TapDanceResult::HoldRelease(Key::from(29))));
+        assert!(!manager.has_active_tapdance());
+    }
+
+    #[test]
+    fn test_tapdance_interrupt() {
+        let mut manager = MultipurposeManager::with_timeout(500);
+        manager.add_tapdance(create_caps_tapdance());
+
+        // Interrupt while held -> hold
+        manager.press_tapdance(Key::from(58));
+        assert_eq!(manager.interrupt_tapdance(), Some(TapDanceResult::HoldPress(Key::from(29))));
+        manager.release_tapdance();
+
+        // Interrupt between taps -> resolve current count
+        manager.press_tapdance(Key::from(58));
+        manager.release_tapdance();
+        assert_eq!(manager.interrupt_tapdance(), Some(TapDanceResult::Tap(Key::from(1))));
+        assert!(!manager.has_active_tapdance());
+    }
+
+    #[test]
+    fn test_tapdance_interrupt_without_hold_swallows_release() {
+        let mut manager = MultipurposeManager::with_timeout(500);
+        manager.add_tapdance(TapDance::new(
+            "NoHold",
+            Key::from(58),
+            vec![Key::from(1), Key::from(58)],
+            None,
+        ));
+
+        manager.press_tapdance(Key::from(58));
+        assert_eq!(manager.interrupt_tapdance(), Some(TapDanceResult::Tap(Key::from(1))));
+        assert!(manager.has_active_tapdance());
+        assert_eq!(manager.release_tapdance(), Some(TapDanceResult::Pending));
+        assert!(!manager.has_active_tapdance());
+    }
 }
@@ -0,0 +1,452 @@
+// Keyrs Metrics
+// Event counters and transform latency histogram in OpenMetrics text format,
+// plus the always-on statistics table logged by the diagnostics key
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::Duration;
+
+use crate::transform::TransformResult;
+use crate::Combo;
+
+/// Default listen address for `[metrics]` (loopback only)
+pub const DEFAULT_METRICS_ADDR: &str = "127.0.0.1:9187";
+
+/// Combos listed in the statistics table logged by the diagnostics key
+pub const STATS_TOP_COMBOS: usize = 20;
+
+/// Upper bounds of the latency histogram buckets, in microseconds
+const LATENCY_BUCKETS_US: [u64; 10] = [10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Result categories counted per event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultKind {
+    Passthrough,
+    Remapped,
+    Combo,
+    Text,
+    Hint,
+    Suppressed,
+    Suspend,
+}
+
+impl ResultKind {
+    const ALL: [ResultKind; 7] = [
+        ResultKind::Passthrough,
+        ResultKind::Remapped,
+        ResultKind::Combo,
+        ResultKind::Text,
+        ResultKind::Hint,
+        ResultKind::Suppressed,
+        ResultKind::Suspend,
+    ];
+
+    /// Category of a transform result
+    pub fn of(result: &TransformResult) -> Self {
+        match result {
+            TransformResult::Passthrough(_) => ResultKind::Passthrough,
+            TransformResult::Remapped(_) => ResultKind::Remapped,
+            TransformResult::ComboKey(_)
+            | TransformResult::Combo(_)
+            | TransformResult::Sequence(_) => ResultKind::Combo,
+            TransformResult::Unicode(_) | TransformResult::Text(_) => ResultKind::Text,
+            TransformResult::Hint(_) => ResultKind::Hint,
+            TransformResult::Suppress => ResultKind::Suppressed,
+            TransformResult::Suspend => ResultKind::Suspend,
+        }
+    }
+
+    /// Label value used in the exposition
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResultKind::Passthrough => "passthrough",
+            ResultKind::Remapped => "remapped",
+            ResultKind::Combo => "combo",
+            ResultKind::Text => "text",
+            ResultKind::Hint => "hint",
+            ResultKind::Suppressed => "suppressed",
+            ResultKind::Suspend => "suspend",
+        }
+    }
+}
+
+/// Counters collected by the transform engine while metrics are enabled
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    events: u64,
+    results: [u64; ResultKind::ALL.len()],
+    keymap_hits: BTreeMap<String, u64>,
+    /// Cumulative counts are computed at render time; these are per-bucket
+    latency_buckets: [u64; LATENCY_BUCKETS_US.len() + 1],
+    latency_sum_us: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one processed event with its result and transform time
+    pub fn record_event(&mut self, result: &TransformResult, latency: Duration) {
+        self.events += 1;
+        let kind = ResultKind::of(result);
+        if let Some(index) = ResultKind::ALL.iter().position(|k| *k == kind) {
+            self.results[index] += 1;
+        }
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.latency_buckets[bucket] += 1;
+        self.latency_sum_us = self.latency_sum_us.saturating_add(micros);
+    }
+
+    /// Count a combo matched by the keymap `name`
+    pub fn record_keymap_hit(&mut self, name: &str) {
+        match self.keymap_hits.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.keymap_hits.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    /// Events processed so far
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Events that produced `kind`
+    pub fn result_count(&self, kind: ResultKind) -> u64 {
+        ResultKind::ALL
+            .iter()
+            .position(|k| *k == kind)
+            .map(|index| self.results[index])
+            .unwrap_or(0)
+    }
+
+    /// Combos matched by the keymap `name`
+    pub fn keymap_hits(&self, name: &str) -> u64 {
+        self.keymap_hits.get(name).copied().unwrap_or(0)
+    }
+
+    /// Render in the OpenMetrics text exposition format (ends with `# EOF`)
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE keyrs_events counter");
+        let _ = writeln!(out, "# HELP keyrs_events Key events processed by the transform engine.");
+        let _ = writeln!(out, "keyrs_events_total {}", self.events);
+
+        let _ = writeln!(out, "# TYPE keyrs_results counter");
+        let _ = writeln!(out, "# HELP keyrs_results Transform results by type.");
+        for (kind, count) in ResultKind::ALL.iter().zip(self.results) {
+            let _ = writeln!(out, "keyrs_results_total{{type=\"{}\"}} {}", kind.as_str(), count);
+        }
+
+        let _ = writeln!(out, "# TYPE keyrs_keymap_hits counter");
+        let _ = writeln!(out, "# HELP keyrs_keymap_hits Combos matched per keymap.");
+        for (name, count) in &self.keymap_hits {
+            let _ = writeln!(
+                out,
+                "keyrs_keymap_hits_total{{keymap=\"{}\"}} {}",
+                escape_label(name),
+                count
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE keyrs_transform_latency_seconds histogram");
+        let _ = writeln!(
+            out,
+            "# HELP keyrs_transform_latency_seconds Time spent transforming one key event."
+        );
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS_US.iter().zip(self.latency_buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "keyrs_transform_latency_seconds_bucket{{le=\"{}\"}} {}",
+                *bound as f64 / 1_000_000.0,
+                cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "keyrs_transform_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.events
+        );
+        let _ = writeln!(
+            out,
+            "keyrs_transform_latency_seconds_sum {}",
+            self.latency_sum_us as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "keyrs_transform_latency_seconds_count {}", self.events);
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Counters the transform engine always keeps, `[metrics]` or not, for the
+/// statistics table of the diagnostics key
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    events: u64,
+    results: [u64; ResultKind::ALL.len()],
+    latency_sum_us: u64,
+    keymap_hits: HashMap<String, u64>,
+    /// Matches per combo as written in the keymap
+    combo_hits: HashMap<Combo, u64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one processed event with its result and transform time
+    pub fn record_event(&mut self, result: &TransformResult, latency: Duration) {
+        self.events += 1;
+        let kind = ResultKind::of(result);
+        if let Some(index) = ResultKind::ALL.iter().position(|k| *k == kind) {
+            self.results[index] += 1;
+        }
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        self.latency_sum_us = self.latency_sum_us.saturating_add(micros);
+    }
+
+    /// Count a match of `combo` in the keymap `keymap`
+    pub fn record_match(&mut self, keymap: &str, combo: &Combo) {
+        match self.keymap_hits.get_mut(keymap) {
+            Some(count) => *count += 1,
+            None => {
+                self.keymap_hits.insert(keymap.to_string(), 1);
+            }
+        }
+        match self.combo_hits.get_mut(combo) {
+            Some(count) => *count += 1,
+            None => {
+                self.combo_hits.insert(combo.clone(), 1);
+            }
+        }
+    }
+
+    /// Events processed so far
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Combos matched by the keymap `name`
+    pub fn keymap_hits(&self, name: &str) -> u64 {
+        self.keymap_hits.get(name).copied().unwrap_or(0)
+    }
+
+    /// The `limit` most matched combos, most matched first
+    pub fn top_combos(&self, limit: usize) -> Vec<(String, u64)> {
+        let mut combos: Vec<(String, u64)> = self
+            .combo_hits
+            .iter()
+            .map(|(combo, count)| (combo.to_string(), *count))
+            .collect();
+        combos.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        combos.truncate(limit);
+        combos
+    }
+
+    /// Mean time spent transforming one event (None before the first event)
+    pub fn average_latency(&self) -> Option<Duration> {
+        (self.events > 0).then(|| Duration::from_micros(self.latency_sum_us / self.events))
+    }
+
+    /// Render as a table for the log: totals, matches per keymap (most first)
+    /// and the `top` most matched combos
+    pub fn render_table(&self, top: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Events: {}", self.events);
+        let results: Vec<String> = ResultKind::ALL
+            .iter()
+            .zip(self.results)
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| format!("{} {}", kind.as_str(), count))
+            .collect();
+        if !results.is_empty() {
+            let _ = writeln!(out, "Results: {}", results.join(", "));
+        }
+        if let Some(latency) = self.average_latency() {
+            let _ = writeln!(out, "Average transform latency: {}µs", latency.as_micros());
+        }
+
+        let mut keymaps: Vec<(String, u64)> = self
+            .keymap_hits
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect();
+        keymaps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        write_table(&mut out, "Keymap", &keymaps);
+        write_table(&mut out, &format!("Top {} combos", top), &self.top_combos(top));
+        out
+    }
+}
+
+/// Two-column `name  hits` table; nothing when `rows` is empty
+fn write_table(out: &mut String, title: &str, rows: &[(String, u64)]) {
+    if rows.is_empty() {
+        return;
+    }
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .chain([title.len()])
+        .max()
+        .unwrap_or(0);
+    let _ = writeln!(out, "{:<width$}  {:>8}", title, "Hits", width = width);
+    for (name, count) in rows {
+        let _ = writeln!(out, "{:<width$}  {:>8}", name, count, width = width);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Minimal HTTP endpoint serving [`Metrics::render`] on any path.
+///
+/// Like the control socket it never blocks the event loop: pending
+/// connections are accepted and answered from [`MetricsServer::serve`].
+pub struct MetricsServer {
+    listener: TcpListener,
+}
+
+impl MetricsServer {
+    /// Bind the listener (non-blocking)
+    pub fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener })
+    }
+
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answer every pending scrape with the current metrics
+    pub fn serve(&self, metrics: &Metrics) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = respond(stream, metrics) {
+                        log::debug!("Metrics scrape failed: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl AsRawFd for MetricsServer {
+    /// Readable when a scrape is waiting for `serve`
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    // Scrapers send the request right after connecting; don't wait long for it.
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_millis(50)))?;
+    stream.set_write_timeout(Some(Duration::from_millis(200)))?;
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request);
+
+    let body = metrics.render();
+    let response = format!(
+        "HTTP/1.0 200 OK\r\nContent-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+
+    #[test]
+    fn test_record_and_render() {
+        let mut metrics = Metrics::new();
+        metrics.record_event(&TransformResult::Passthrough(Key::from(30)), Duration::from_micros(5));
+        metrics.record_event(&TransformResult::Suppress, Duration::from_micros(300));
+        metrics.record_event(&TransformResult::ComboKey(Key::from(46)), Duration::from_millis(20));
+        metrics.record_keymap_hit("mac \"style\"");
+
+        assert_eq!(metrics.events(), 3);
+        assert_eq!(metrics.result_count(ResultKind::Suppressed), 1);
+        assert_eq!(metrics.keymap_hits("mac \"style\""), 1);
+
+        let text = metrics.render();
+        assert!(text.contains("keyrs_events_total 3\n"));
+        assert!(text.contains("keyrs_results_total{type=\"combo\"} 1\n"));
+        assert!(text.contains("keyrs_keymap_hits_total{keymap=\"mac \\\"style\\\"\"} 1\n"));
+        assert!(text.contains("keyrs_transform_latency_seconds_bucket{le=\"0.00001\"} 1\n"));
+        assert!(text.contains("keyrs_transform_latency_seconds_bucket{le=\"0.0005\"} 2\n"));
+        assert!(text.contains("keyrs_transform_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_stats_table() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.render_table(20), "Events: 0\n");
+
+        let ctrl = crate::Modifier::from_name("CONTROL").unwrap();
+        let copy = Combo::new(vec![ctrl.clone()], Key::from(46));
+        let paste = Combo::new(vec![ctrl], Key::from(47));
+        stats.record_event(&TransformResult::Combo(copy.clone()), Duration::from_micros(10));
+        stats.record_event(&TransformResult::Passthrough(Key::from(30)), Duration::from_micros(30));
+        for combo in [&copy, &paste, &copy] {
+            stats.record_match("general", combo);
+        }
+        stats.record_match("terminal", &paste);
+        assert_eq!(stats.keymap_hits("general"), 3);
+        assert_eq!(stats.top_combos(1), [("Ctrl-C".to_string(), 2)]);
+        assert_eq!(stats.average_latency(), Some(Duration::from_micros(20)));
+
+        let table = stats.render_table(2);
+        assert!(table.contains("Results: passthrough 1, combo 1\n"));
+        assert!(table.contains("Average transform latency: 20µs\n"));
+        assert!(table.contains("Keymap        Hits\ngeneral          3\nterminal         1\n"));
+        assert!(table.contains("Top 2 combos      Hits\nCtrl-C               2\nCtrl-V               2\n"));
+    }
+
+    #[test]
+    fn test_server_answers_scrape() {
+        let server = MetricsServer::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut metrics = Metrics::new();
+        metrics.record_event(&TransformResult::Suppress, Duration::ZERO);
+        // The accept may race the connect on a busy machine
+        for _ in 0..50 {
+            server.serve(&metrics);
+            client.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+            let mut response = String::new();
+            if client.read_to_string(&mut response).is_ok() && !response.is_empty() {
+                assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+                assert!(response.contains("keyrs_events_total 1\n"));
+                return;
+            }
+        }
+        panic!("no response from metrics server");
+    }
+}
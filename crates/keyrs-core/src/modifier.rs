@@ -1,13 +1,21 @@
 // Keyrs Modifier System
 // Represents keyboard combo modifiers (Shift, Ctrl, Alt, Meta)
+// plus compound modifiers (Hyper, Meh) that stand for several at once
 
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
 
+use crate::key::KEY_MAX;
 use crate::Key;
 
+/// Virtual key code of the `Hyper` compound modifier (Ctrl+Alt+Shift+Meta).
+/// Only modmaps produce it; the engine presses the component keys instead.
+pub const HYPER_KEY_CODE: u16 = KEY_MAX + 1;
+/// Virtual key code of the `Meh` compound modifier (Ctrl+Alt+Shift)
+pub const MEH_KEY_CODE: u16 = KEY_MAX + 2;
+
 /// Global modifier registry
 static MODIFIER_REGISTRY: LazyLock<RwLock<ModifierRegistry>> = LazyLock::new(|| {
     // Initialize default modifiers on first access
@@ -43,6 +51,9 @@ impl ModifierRegistry {
             &["Ctrl", "C"],
             vec![Key::from(29), Key::from(97)],
         );
+        // AltGr (ISO level 3 shift) is Right Alt matched on its own; registered
+        // first so a Right Alt press still reads as Alt
+        registry.add_internal("ALTGR", &["AltGr"], vec![Key::from(100)]);
         registry.add_internal(
             "R_ALT",
             &["RAlt", "RA", "ROpt", "ROption"],
@@ -77,15 +88,32 @@ impl ModifierRegistry {
             vec![Key::from(125), Key::from(126)],
         );
         registry.add_internal("FN", &["Fn"], vec![Key::from(0x1d0)]);
+        registry.add_compound("HYPER", &["Hyper"], HYPER_KEY_CODE, &["CONTROL", "ALT", "SHIFT", "META"]);
+        registry.add_compound("MEH", &["Meh"], MEH_KEY_CODE, &["CONTROL", "ALT", "SHIFT"]);
         registry
     }
 
     fn add_internal(&mut self, name: &str, aliases: &[&str], keys: Vec<Key>) {
+        self.insert_internal(name, aliases, keys, Vec::new());
+    }
+
+    /// Register a compound modifier standing for the named (already
+    /// registered) modifiers, under a virtual key code
+    fn add_compound(&mut self, name: &str, aliases: &[&str], code: u16, components: &[&str]) {
+        let components = components
+            .iter()
+            .map(|component| self.by_name[*component].clone())
+            .collect();
+        self.insert_internal(name, aliases, vec![Key::from(code)], components);
+    }
+
+    fn insert_internal(&mut self, name: &str, aliases: &[&str], keys: Vec<Key>, components: Vec<Modifier>) {
         let modifier = Modifier {
             id: NEXT_MODIFIER_ID.fetch_add(1, Ordering::SeqCst),
             name: name.to_string(),
             aliases: aliases.iter().map(|s| s.to_string()).collect(),
             keys,
+            components,
         };
 
         // Register by name
@@ -138,6 +166,8 @@ pub struct Modifier {
     name: String,
     aliases: Vec<String>,
     keys: Vec<Key>,
+    /// Modifiers a compound modifier stands for (empty for plain ones)
+    components: Vec<Modifier>,
 }
 
 impl Modifier {
@@ -153,10 +183,16 @@ impl Modifier {
             name: name.to_string(),
             aliases,
             keys,
+            components: Vec::new(),
         };
         MODIFIER_REGISTRY.write().add(modifier)
     }
 
+    /// Registered name (e.g. `L_CONTROL`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Get the first alias (string representation)
     pub fn primary_alias(&self) -> &str {
         self.aliases
@@ -170,6 +206,52 @@ impl Modifier {
         self.keys.len() == 1
     }
 
+    /// Whether this is a compound modifier such as `Hyper`
+    pub fn is_compound(&self) -> bool {
+        !self.components.is_empty()
+    }
+
+    /// The modifiers a compound modifier stands for (empty for plain ones)
+    pub fn components(&self) -> &[Modifier] {
+        &self.components
+    }
+
+    /// Replace compound modifiers with their components, dropping duplicates
+    /// and keeping the first-seen order
+    pub fn expand(modifiers: &[Modifier]) -> Vec<Modifier> {
+        let mut expanded: Vec<Modifier> = Vec::with_capacity(modifiers.len());
+        for modifier in modifiers {
+            let parts = if modifier.is_compound() {
+                modifier.components()
+            } else {
+                std::slice::from_ref(modifier)
+            };
+            for part in parts {
+                if !expanded.contains(part) {
+                    expanded.push(part.clone());
+                }
+            }
+        }
+        expanded
+    }
+
+    /// The real modifier keys a key stands for: the components' keys for a
+    /// compound modifier's virtual key, otherwise the key itself
+    pub fn expand_key(key: Key) -> Vec<Key> {
+        match Self::compound_for_key(key) {
+            Some(compound) => compound.components.iter().map(Modifier::key).collect(),
+            None => vec![key],
+        }
+    }
+
+    /// The compound modifier whose virtual key is `key`, if any
+    pub fn compound_for_key(key: Key) -> Option<Modifier> {
+        if !matches!(key.code(), HYPER_KEY_CODE | MEH_KEY_CODE) {
+            return None;
+        }
+        Self::from_key(key).filter(Modifier::is_compound)
+    }
+
     /// Get all keys for this modifier
     pub fn keys(&self) -> &[Key] {
         &self.keys
@@ -198,6 +280,11 @@ impl Modifier {
         MODIFIER_REGISTRY.read().by_name.get(&right_name).cloned()
     }
 
+    /// The AltGr modifier (Right Alt, distinct from `Alt` and `RAlt` in combos)
+    pub fn altgr() -> Modifier {
+        Self::from_name("ALTGR").expect("ALTGR is a default modifier")
+    }
+
     /// Get modifier by key code
     pub fn from_key(key: Key) -> Option<Modifier> {
         MODIFIER_REGISTRY.read().by_key.get(&key).cloned()
@@ -240,7 +327,7 @@ impl Modifier {
             .map(|m| m.name.clone())
     }
 
-    /// Get all aliases
+    /// Get all aliases (sorted, without duplicates)
     pub fn all_aliases() -> Vec<String> {
         let registry = MODIFIER_REGISTRY.read();
         let mut aliases = Vec::new();
@@ -249,6 +336,8 @@ impl Modifier {
                 aliases.push(alias.clone());
             }
         }
+        aliases.sort();
+        aliases.dedup();
         aliases
     }
 }
@@ -310,6 +399,7 @@ const MODIFIER_KEY_CODES: &[u16] = &[
     42, 54, // L_SHIFT, R_SHIFT
     125, 126,   // L_META, R_META
     0x1d0, // FN
+    HYPER_KEY_CODE, MEH_KEY_CODE, // HYPER, MEH (virtual)
 ];
 
 /// Check if a key code is a modifier using static array (O(1) lock-free)
@@ -358,6 +448,41 @@ mod tests {
         assert_eq!(right.name, "R_CONTROL");
     }
 
+    #[test]
+    fn test_compound_modifiers() {
+        let hyper = Modifier::from_alias("Hyper").unwrap();
+        assert!(hyper.is_compound());
+        let names: Vec<&str> = hyper.components().iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["CONTROL", "ALT", "SHIFT", "META"]);
+        assert_eq!(hyper.key(), Key::from(HYPER_KEY_CODE));
+        assert!(is_key_modifier_code(HYPER_KEY_CODE));
+        assert_eq!(
+            Modifier::expand_key(Key::from(MEH_KEY_CODE)),
+            vec![Key::from(29), Key::from(56), Key::from(42)]
+        );
+        assert_eq!(Modifier::expand_key(Key::from(29)), vec![Key::from(29)]);
+
+        // Compounds flatten into their components without duplicates
+        let ctrl = Modifier::from_alias("Ctrl").unwrap();
+        let meh = Modifier::from_alias("Meh").unwrap();
+        let expanded = Modifier::expand(&[ctrl.clone(), meh]);
+        assert_eq!(expanded.len(), 3);
+        assert_eq!(expanded[0], ctrl);
+        assert!(!expanded.iter().any(Modifier::is_compound));
+        assert!(!Modifier::from_key(Key::from(29)).unwrap().is_compound());
+    }
+
+    #[test]
+    fn test_altgr_modifier() {
+        let altgr = Modifier::from_alias("AltGr").unwrap();
+        assert_eq!(altgr, Modifier::altgr());
+        assert!(altgr.is_specific());
+        assert_eq!(altgr.key(), Key::from(100)); // RIGHT_ALT
+        assert_ne!(Some(altgr.clone()), Modifier::from_alias("RAlt"));
+        // A Right Alt press still reads as the generic Alt
+        assert_eq!(Modifier::from_key(Key::from(100)).unwrap().name, "ALT");
+    }
+
     #[test]
     fn test_is_key_modifier() {
         assert!(Modifier::is_key_modifier(Key::from(29))); // LEFT_CTRL
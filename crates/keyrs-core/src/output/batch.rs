@@ -0,0 +1,129 @@
+// Keyrs Output Batching
+// Group key events into SYN frames and split them into bounded writes
+
+use std::ops::Range;
+
+use crate::{Action, Key};
+
+/// Most events (including SYN reports) sent in one uinput write.
+///
+/// evdev clients get a 64-event buffer for a keyboard and cannot drain it
+/// while a write is in progress; staying at half that leaves headroom.
+pub const MAX_EVENTS_PER_WRITE: usize = 32;
+
+/// Key events grouped into frames, each closed by one SYN report.
+///
+/// A frame never carries two events for the same key, so a tap always spans
+/// two frames and clients see the press before the release.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyBatch {
+    frames: Vec<Vec<(Key, Action)>>,
+}
+
+impl KeyBatch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append one key event, starting a new frame if the key is already in the current one
+    pub fn push(&mut self, key: Key, action: Action) {
+        match self.frames.last_mut() {
+            Some(frame) if !frame.iter().any(|(k, _)| *k == key) => frame.push((key, action)),
+            _ => self.frames.push(vec![(key, action)]),
+        }
+    }
+
+    /// Append a key press
+    pub fn press(&mut self, key: Key) {
+        self.push(key, Action::Press);
+    }
+
+    /// Append a key release
+    pub fn release(&mut self, key: Key) {
+        self.push(key, Action::Release);
+    }
+
+    /// Append a press and release of `key`
+    pub fn tap(&mut self, key: Key) {
+        self.press(key);
+        self.release(key);
+    }
+
+    /// Frames in emission order
+    pub fn frames(&self) -> &[Vec<(Key, Action)>] {
+        &self.frames
+    }
+
+    /// Number of key events (SYN reports not counted)
+    pub fn len(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
+    }
+
+    /// Whether the batch holds no events
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Frame ranges to send as separate writes of at most `max_events` events.
+    ///
+    /// Writes break only between frames; a frame larger than the limit is sent alone.
+    pub fn writes(&self, max_events: usize) -> Vec<Range<usize>> {
+        let mut writes = Vec::new();
+        let mut start = 0;
+        let mut events = 0;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let frame_events = frame.len() + 1; // + SYN_REPORT
+            if index > start && events + frame_events > max_events {
+                writes.push(start..index);
+                start = index;
+                events = 0;
+            }
+            events += frame_events;
+        }
+        if start < self.frames.len() {
+            writes.push(start..self.frames.len());
+        }
+        writes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_splits_frames_on_repeated_key() {
+        let (shift, a) = (Key::from(42), Key::from(30));
+        let mut batch = KeyBatch::new();
+        batch.press(shift);
+        batch.tap(a);
+        batch.release(shift);
+        batch.tap(a);
+
+        assert_eq!(
+            batch.frames(),
+            &[
+                vec![(shift, Action::Press), (a, Action::Press)],
+                vec![(a, Action::Release), (shift, Action::Release)],
+                vec![(a, Action::Press)],
+                vec![(a, Action::Release)],
+            ]
+        );
+        assert_eq!(batch.len(), 6);
+    }
+
+    #[test]
+    fn test_batch_writes_respect_event_limit() {
+        let a = Key::from(30);
+        let mut batch = KeyBatch::new();
+        for _ in 0..5 {
+            batch.tap(a);
+        }
+        // Ten frames of two events each (key + SYN)
+        assert_eq!(batch.writes(6), vec![0..3, 3..6, 6..9, 9..10]);
+        assert_eq!(batch.writes(MAX_EVENTS_PER_WRITE), vec![0..10]);
+        assert_eq!(batch.writes(1), (0..10).map(|i| i..i + 1).collect::<Vec<_>>());
+        assert!(KeyBatch::new().writes(6).is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+// Keyrs Clipboard Text Output
+// Pastes long non-ASCII text at once instead of one Ctrl+Shift+U cycle per character
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default `[output].unicode_batch_min`
+pub const DEFAULT_UNICODE_BATCH_MIN: usize = 4;
+/// How long pasted text stays on the clipboard before the previous content
+/// returns; the focused app reads the clipboard after it sees the paste combo
+const RESTORE_DELAY: Duration = Duration::from_millis(500);
+/// Longest wait for the clipboard to take the text before typing it instead
+const COPY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How `Text(...)` output with many non-ASCII characters is produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnicodeBatch {
+    /// Type every character
+    Off,
+    /// Put the whole text on the clipboard and send the paste combo (default)
+    #[default]
+    Clipboard,
+}
+
+impl UnicodeBatch {
+    /// Parse a strategy name as used in `[output].unicode_batch`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "clipboard" | "paste" => Some(Self::Clipboard),
+            _ => None,
+        }
+    }
+
+    /// Whether `text` has at least `min` non-ASCII characters and is
+    /// pasted rather than typed
+    pub fn applies(self, text: &str, min: usize) -> bool {
+        self == Self::Clipboard && text.chars().filter(|ch| !ch.is_ascii()).count() >= min
+    }
+}
+
+/// Command-line tool that sets the clipboard of the user's session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardTool {
+    /// `wl-copy` from wl-clipboard
+    WlCopy,
+    /// `xclip -selection clipboard`
+    Xclip,
+}
+
+impl ClipboardTool {
+    /// `wl-copy` in a Wayland session, `xclip` in an X11 one
+    pub fn detect() -> Option<Self> {
+        Self::for_session(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()))
+    }
+
+    fn for_session(has_var: impl Fn(&str) -> bool) -> Option<Self> {
+        if has_var("WAYLAND_DISPLAY") {
+            Some(Self::WlCopy)
+        } else if has_var("DISPLAY") {
+            Some(Self::Xclip)
+        } else {
+            None
+        }
+    }
+
+    fn copy_command(self) -> Command {
+        match self {
+            Self::WlCopy => Command::new("wl-copy"),
+            Self::Xclip => {
+                let mut command = Command::new("xclip");
+                command.args(["-selection", "clipboard"]);
+                command
+            }
+        }
+    }
+
+    fn paste_command(self) -> Command {
+        match self {
+            Self::WlCopy => {
+                let mut command = Command::new("wl-paste");
+                command.arg("--no-newline");
+                command
+            }
+            Self::Xclip => {
+                let mut command = Command::new("xclip");
+                command.args(["-selection", "clipboard", "-o"]);
+                command
+            }
+        }
+    }
+
+    /// Replace the clipboard content with `data`
+    pub fn copy(self, data: &[u8]) -> io::Result<()> {
+        let mut child = self
+            .copy_command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(data)?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("{:?} exited with {}", self, status)))
+        }
+    }
+
+    /// Current clipboard content (`None` when the clipboard is empty)
+    pub fn read(self) -> io::Result<Option<Vec<u8>>> {
+        let mut child = self
+            .paste_command()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut data = Vec::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_end(&mut data)?;
+        }
+        // Both tools fail when nothing is copied
+        Ok(child.wait()?.success().then_some(data))
+    }
+}
+
+/// Text on the clipboard for a paste, and what to put back afterwards
+struct Pasted {
+    text: Vec<u8>,
+    previous: Option<Vec<u8>>,
+    restore_at: Instant,
+}
+
+/// Sets the clipboard for pasted text on a thread of its own.
+///
+/// The clipboard tools run there, not on the output thread, which only waits
+/// (at most `COPY_TIMEOUT`) for the text to be on the clipboard before
+/// sending the paste combo. The previous content is put back shortly after
+/// the last paste, unless something else was copied in the meantime.
+pub struct ClipboardPaster {
+    requests: Sender<(String, SyncSender<io::Result<()>>)>,
+}
+
+impl ClipboardPaster {
+    pub fn spawn(tool: ClipboardTool) -> io::Result<Self> {
+        let (requests, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("keyrs-clipboard".to_string())
+            .spawn(move || run(tool, receiver))?;
+        Ok(Self { requests })
+    }
+
+    /// Put `text` on the clipboard
+    pub fn copy(&self, text: &str) -> io::Result<()> {
+        let (done, result) = mpsc::sync_channel(1);
+        self.requests
+            .send((text.to_string(), done))
+            .map_err(|_| io::Error::other("the clipboard thread exited"))?;
+        match result.recv_timeout(COPY_TIMEOUT) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the clipboard tool did not finish in time",
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("the clipboard thread exited")),
+        }
+    }
+}
+
+/// Serve copy requests until the paster is dropped, restoring the clipboard when due
+fn run(tool: ClipboardTool, requests: Receiver<(String, SyncSender<io::Result<()>>)>) {
+    let mut pasted: Option<Pasted> = None;
+    loop {
+        let request = match &pasted {
+            Some(current) => {
+                match requests.recv_timeout(current.restore_at.saturating_duration_since(Instant::now())) {
+                    Ok(request) => Some(request),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            },
+        };
+        match request {
+            Some((text, done)) => {
+                let _ = done.send(copy(tool, &mut pasted, text.into_bytes()));
+            }
+            None => restore(tool, pasted.take()),
+        }
+    }
+    restore(tool, pasted.take());
+}
+
+/// Copy `text`, remembering the clipboard content it replaces
+fn copy(tool: ClipboardTool, pasted: &mut Option<Pasted>, text: Vec<u8>) -> io::Result<()> {
+    let (previous, on_clipboard) = match pasted.take() {
+        // The clipboard still holds the text of the previous paste
+        Some(current) => {
+            if current.text == text {
+                *pasted = Some(Pasted {
+                    restore_at: Instant::now() + RESTORE_DELAY,
+                    ..current
+                });
+                return Ok(());
+            }
+            (current.previous, Some(current.text))
+        }
+        None => {
+            let previous = tool.read().unwrap_or_else(|e| {
+                log::debug!("Could not read the clipboard to restore it later: {}", e);
+                None
+            });
+            (previous, None)
+        }
+    };
+    let copied = tool.copy(&text);
+    // On failure the clipboard keeps what it had, so restore from that
+    let text = if copied.is_ok() { Some(text) } else { on_clipboard };
+    *pasted = text.map(|text| Pasted {
+        text,
+        previous,
+        restore_at: Instant::now() + RESTORE_DELAY,
+    });
+    copied
+}
+
+/// Put back what the clipboard held before a paste, unless it changed since
+fn restore(tool: ClipboardTool, pasted: Option<Pasted>) {
+    let Some(Pasted {
+        text,
+        previous: Some(previous),
+        ..
+    }) = pasted
+    else {
+        return;
+    };
+    match tool.read() {
+        Ok(Some(current)) if current == text => {
+            if let Err(e) = tool.copy(&previous) {
+                log::warn!("Could not restore the clipboard after pasting text: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => log::debug!("Could not read the clipboard to restore it: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_batch() {
+        assert_eq!(UnicodeBatch::from_name(" Clipboard "), Some(UnicodeBatch::Clipboard));
+        assert_eq!(UnicodeBatch::from_name("ibus"), None);
+
+        assert_eq!(UnicodeBatch::default(), UnicodeBatch::Clipboard);
+        assert!(UnicodeBatch::Clipboard.applies("déjà vu, ça été", 4));
+        assert!(!UnicodeBatch::Clipboard.applies("déjà vu", 4));
+        assert!(!UnicodeBatch::Off.applies("ééééé", 4));
+    }
+
+    #[test]
+    fn test_clipboard_tool_for_session() {
+        assert_eq!(ClipboardTool::for_session(|name| name == "DISPLAY"), Some(ClipboardTool::Xclip));
+        assert_eq!(ClipboardTool::for_session(|_| true), Some(ClipboardTool::WlCopy));
+        assert_eq!(ClipboardTool::for_session(|_| false), None);
+    }
+}
@@ -59,6 +59,80 @@ impl Default for ComboActionSequence {
     }
 }
 
+/// How the combos of one keymap are emitted
+///
+/// Set per keymap with `modifier_order` and `combo_delay_ms`; the default
+/// presses modifiers in combo order with no extra delay.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComboStyle {
+    /// Modifier families (`ctrl`, `alt`, `altgr`, `shift`, `meta`, `fn`)
+    /// pressed in this order and released in reverse; unlisted modifiers
+    /// follow in combo order
+    pub modifier_order: Vec<String>,
+    /// Pause between the events of one combo, overriding `[delays] combo_delay_ms`
+    pub delay_ms: Option<u64>,
+}
+
+impl ComboStyle {
+    /// Parse `modifier_order` names (any modifier name or alias, e.g. `Ctrl`,
+    /// `LSuper`) into modifier families
+    pub fn parse_modifier_order(names: &[String]) -> Result<Vec<String>, String> {
+        let mut order: Vec<String> = Vec::new();
+        for name in names {
+            let family = modifier_family_for_name(name)
+                .ok_or_else(|| format!("unknown modifier '{}' in modifier_order", name))?;
+            if order.iter().any(|f| f == family) {
+                return Err(format!("modifier '{}' appears twice in modifier_order", name));
+            }
+            order.push(family.to_string());
+        }
+        Ok(order)
+    }
+
+    /// Sort modifier keys into `modifier_order`, keeping unlisted ones in place after them
+    pub fn order_modifiers(&self, keys: &mut [Key]) {
+        if self.modifier_order.is_empty() {
+            return;
+        }
+        keys.sort_by_key(|key| {
+            Modifier::from_key(*key)
+                .and_then(|modifier| {
+                    let family = modifier_family(modifier.name());
+                    self.modifier_order.iter().position(|f| f == family)
+                })
+                .unwrap_or(self.modifier_order.len())
+        });
+    }
+}
+
+/// Family of a registered modifier name, ignoring its side (`L_CONTROL` -> `ctrl`)
+fn modifier_family(name: &str) -> &str {
+    match name.trim_start_matches("L_").trim_start_matches("R_") {
+        "CONTROL" => "ctrl",
+        "ALT" => "alt",
+        "ALTGR" => "altgr",
+        "SHIFT" => "shift",
+        "META" => "meta",
+        "FN" => "fn",
+        _ => name,
+    }
+}
+
+/// Family of a modifier given by name or alias, case-insensitively
+fn modifier_family_for_name(name: &str) -> Option<&'static str> {
+    let modifier = Modifier::from_alias(name)
+        .or_else(|| Modifier::from_alias(&name.to_uppercase()))
+        .or_else(|| {
+            Modifier::all_aliases()
+                .into_iter()
+                .find(|alias| alias.eq_ignore_ascii_case(name))
+                .and_then(|alias| Modifier::from_alias(&alias))
+        })?;
+    ["ctrl", "alt", "altgr", "shift", "meta", "fn"]
+        .into_iter()
+        .find(|family| *family == modifier_family(modifier.name()))
+}
+
 /// Calculate the sequence of actions needed to send a combo
 ///
 /// This implements the modifier arithmetic logic from output.py lines 204-241.
@@ -71,6 +145,8 @@ impl Default for ComboActionSequence {
 /// 3. If it does, remove from "need to lift" and remove that combo modifier from "need to press"
 /// 4. The result is: lift unneeded modifiers, press needed modifiers, send key, restore
 ///
+/// Compound modifiers (`Hyper`, `Meh`) count as all of their components.
+///
 /// # Arguments
 /// * `combo_modifiers` - Modifiers required by the combo
 /// * `combo_key` - The main key of the combo
@@ -83,6 +159,9 @@ pub fn calculate_combo_actions(
     combo_key: Key,
     pressed_modifier_keys: &[Key],
 ) -> ComboActionSequence {
+    let combo_modifiers = Modifier::expand(combo_modifiers);
+    let combo_modifiers = combo_modifiers.as_slice();
+
     // Start with all pressed modifiers in the "need to lift" set
     let mut mod_keys_to_lift: Vec<Key> = pressed_modifier_keys.to_vec();
     let mut mods_to_press: Vec<Modifier> = combo_modifiers.to_vec();
@@ -236,6 +315,21 @@ mod tests {
         assert!(result.modifiers_to_press.is_empty());
     }
 
+    #[test]
+    fn test_combo_compound_modifier() {
+        // Hyper-A with Shift already held presses Ctrl, Alt and Meta
+        let hyper = Modifier::from_alias("Hyper").unwrap();
+        let left_shift = Key::from(42);
+
+        let result = calculate_combo_actions(&[hyper], Key::from(30), &[left_shift]);
+
+        assert!(result.modifiers_to_release.is_empty());
+        assert_eq!(
+            result.modifiers_to_press,
+            vec![Key::from(29), Key::from(56), Key::from(125)]
+        );
+    }
+
     #[test]
     fn test_combo_conflicting_modifiers() {
         // Ctrl-A when Alt is pressed (need to lift Alt, press Ctrl)
@@ -299,4 +393,29 @@ mod tests {
         );
         assert_eq!(seq.modifiers_to_restore, vec![key]);
     }
+
+    #[test]
+    fn test_combo_style_orders_modifiers() {
+        let order = ComboStyle::parse_modifier_order(&[
+            "ctrl".to_string(),
+            "Alt".to_string(),
+            "SHIFT".to_string(),
+            "lsuper".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(order, vec!["ctrl", "alt", "shift", "meta"]);
+        let style = ComboStyle { modifier_order: order, delay_ms: None };
+
+        // Shift, Meta, Ctrl, Alt, and the unlisted Fn stays last
+        let mut keys = vec![Key::from(42), Key::from(0x1d0), Key::from(125), Key::from(97), Key::from(56)];
+        style.order_modifiers(&mut keys);
+        assert_eq!(keys, vec![Key::from(97), Key::from(56), Key::from(42), Key::from(125), Key::from(0x1d0)]);
+
+        let mut keys = vec![Key::from(42), Key::from(29)];
+        ComboStyle::default().order_modifiers(&mut keys);
+        assert_eq!(keys, vec![Key::from(42), Key::from(29)]);
+
+        assert!(ComboStyle::parse_modifier_order(&["Hyper".to_string()]).is_err());
+        assert!(ComboStyle::parse_modifier_order(&["Ctrl".to_string(), "RCtrl".to_string()]).is_err());
+    }
 }
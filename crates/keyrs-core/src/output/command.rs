@@ -0,0 +1,44 @@
+// Keyrs Output Commands
+// `Run(...)` steps: shell commands started from mappings
+
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Start `command` with `sh -c`, without waiting for it; a thread reaps it
+/// so finished commands don't linger as zombies
+pub fn run_command(command: &str) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()?;
+    thread::Builder::new()
+        .name("keyrs-run".to_string())
+        .spawn(move || match child.wait() {
+            Ok(status) if !status.success() => log::debug!("Run command exited with {}", status),
+            Ok(_) => {}
+            Err(e) => log::debug!("Could not wait for Run command: {}", e),
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command() {
+        let marker = std::env::temp_dir().join(format!("keyrs-run-{}", std::process::id()));
+        run_command(&format!("touch '{}'", marker.display())).unwrap();
+        for _ in 0..100 {
+            if marker.exists() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(marker.exists());
+        let _ = std::fs::remove_file(&marker);
+    }
+}
@@ -0,0 +1,75 @@
+// Keyrs Output Delays
+// `[delays]` pacing and its per-application overrides
+
+use crate::Condition;
+
+/// Pauses around output key events, in milliseconds (`[delays]`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputDelays {
+    /// Pause before each output key event
+    pub key_pre_delay_ms: u64,
+    /// Pause after each output key event
+    pub key_post_delay_ms: u64,
+    /// Pause between the events of one combo
+    pub combo_delay_ms: u64,
+}
+
+/// Delays used while `condition` holds (`[[delays.overrides]]`); unset
+/// fields keep the `[delays]` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelayOverride {
+    /// Window context condition selecting the override, parsed at load
+    pub condition: Condition,
+    /// Replaces `key_pre_delay_ms`
+    pub key_pre_delay_ms: Option<u64>,
+    /// Replaces `key_post_delay_ms`
+    pub key_post_delay_ms: Option<u64>,
+    /// Replaces `combo_delay_ms`
+    pub combo_delay_ms: Option<u64>,
+}
+
+impl DelayOverride {
+    /// Override selected by `condition` that changes no delay yet
+    pub fn new(condition: Condition) -> Self {
+        Self {
+            condition,
+            key_pre_delay_ms: None,
+            key_post_delay_ms: None,
+            combo_delay_ms: None,
+        }
+    }
+
+    /// `base` with the fields this override sets
+    pub fn apply(&self, base: OutputDelays) -> OutputDelays {
+        OutputDelays {
+            key_pre_delay_ms: self.key_pre_delay_ms.unwrap_or(base.key_pre_delay_ms),
+            key_post_delay_ms: self.key_post_delay_ms.unwrap_or(base.key_post_delay_ms),
+            combo_delay_ms: self.combo_delay_ms.unwrap_or(base.combo_delay_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_override_apply() {
+        let base = OutputDelays {
+            key_pre_delay_ms: 1,
+            key_post_delay_ms: 2,
+            combo_delay_ms: 3,
+        };
+        let slow = DelayOverride {
+            key_post_delay_ms: Some(20),
+            ..DelayOverride::new(Condition::new("wm_class =~ 'remmina'"))
+        };
+        assert_eq!(
+            slow.apply(base),
+            OutputDelays {
+                key_post_delay_ms: 20,
+                ..base
+            }
+        );
+    }
+}
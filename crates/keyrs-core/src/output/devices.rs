@@ -0,0 +1,99 @@
+// Keyrs Output Devices
+// Which virtual device emits a given key code
+
+use crate::Key;
+
+/// Mouse buttons (`BTN_LEFT`..`BTN_TASK`)
+const MOUSE_BUTTONS: std::ops::RangeInclusive<u16> = 0x110..=0x117;
+
+/// Keys above the keyboard range (`KEY_OK` up to `KEY_MAX`)
+const CONSUMER_KEYS: std::ops::RangeInclusive<u16> = 0x160..=crate::key::KEY_MAX;
+
+/// Virtual devices keyrs writes to.
+///
+/// Clients only accept codes a device advertises, and advertising buttons or
+/// high codes on the keyboard would change how it is classified, so each
+/// group of codes gets its own device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputDeviceKind {
+    /// Keyboard keys (codes 0-255) and anything no other device can carry
+    Keyboard,
+    /// Mouse buttons and relative motion
+    Mouse,
+    /// Consumer and other keys above 255 (zoom, brightness limits, assistant...)
+    Consumer,
+}
+
+impl OutputDeviceKind {
+    /// Every device kind, in creation order
+    pub const ALL: [Self; 3] = [Self::Keyboard, Self::Mouse, Self::Consumer];
+
+    /// Device that emits `key`
+    pub fn for_key(key: Key) -> Self {
+        match key.code() {
+            code if MOUSE_BUTTONS.contains(&code) => Self::Mouse,
+            code if CONSUMER_KEYS.contains(&code) => Self::Consumer,
+            _ => Self::Keyboard,
+        }
+    }
+
+    /// Key codes this device advertises
+    pub fn key_codes(self) -> Vec<u16> {
+        match self {
+            Self::Keyboard => (0..256).collect(),
+            Self::Mouse => MOUSE_BUTTONS.collect(),
+            Self::Consumer => CONSUMER_KEYS.collect(),
+        }
+    }
+
+    /// uinput device name
+    pub fn device_name(self) -> &'static str {
+        match self {
+            Self::Keyboard => "Keyrs (virtual) Keyboard",
+            Self::Mouse => "Keyrs (virtual) Mouse",
+            Self::Consumer => "Keyrs (virtual) Consumer Control",
+        }
+    }
+
+    /// Position in `ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Short name, as used by the input helper protocol
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keyboard => "keyboard",
+            Self::Mouse => "mouse",
+            Self::Consumer => "consumer",
+        }
+    }
+
+    /// Parse a short name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_route_to_device_by_code() {
+        assert_eq!(OutputDeviceKind::for_key(Key::from(30)), OutputDeviceKind::Keyboard);
+        assert_eq!(OutputDeviceKind::for_key(Key::from(115)), OutputDeviceKind::Keyboard); // VOLUMEUP
+        assert_eq!(OutputDeviceKind::for_key(Key::from(272)), OutputDeviceKind::Mouse); // BTN_LEFT
+        assert_eq!(OutputDeviceKind::for_key(Key::from(418)), OutputDeviceKind::Consumer); // ZOOMIN
+        // Gamepad buttons would make the device a joystick; no device carries them
+        assert_eq!(OutputDeviceKind::for_key(Key::from(304)), OutputDeviceKind::Keyboard);
+
+        for kind in OutputDeviceKind::ALL {
+            assert_eq!(OutputDeviceKind::ALL[kind.index()], kind);
+            assert_eq!(OutputDeviceKind::from_name(kind.name()), Some(kind));
+            for code in kind.key_codes() {
+                assert_eq!(OutputDeviceKind::for_key(Key::from(code)), kind, "code {}", code);
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Keyrs Input Injection
+// ydotool-style typing and key combos through the remapper's virtual device
+
+use super::uinput::{TransformResultOutput, UInputError, VirtualDevice};
+use crate::config::{parse_combo_string, ComboTrigger};
+use crate::{Action, Combo, Modifier};
+
+/// Input a script asks keyrs to emit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Injection {
+    /// Type text, honoring the configured layout and Unicode backend
+    Text(String),
+    /// Tap a key combo, e.g. `ctrl+alt+t` or `C-A-t`
+    Key(Combo),
+}
+
+impl Injection {
+    /// Parse a key combo in ydotool/xdotool form (`ctrl+alt+t`, `super+enter`)
+    /// or in config form (`C-A-t`)
+    pub fn parse_key(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let Some((modifiers, key)) = spec.rsplit_once('+') else {
+            let parsed = parse_combo_string(spec).map_err(|e| format!("'{}': {}", spec, e))?;
+            if parsed.trigger == ComboTrigger::Release {
+                return Err(format!("'{}': release: only applies to keymap combos", spec));
+            }
+            return Ok(Self::Key(Combo::new(parsed.modifiers, parsed.key)));
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("'{}': missing key", spec));
+        }
+        let modifiers = modifiers
+            .split('+')
+            .map(|name| modifier_from_name(name.trim()).ok_or_else(|| format!("'{}': unknown modifier '{}'", spec, name.trim())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parsed = parse_combo_string(key).map_err(|e| format!("'{}': {}", spec, e))?;
+        if !parsed.modifiers.is_empty() {
+            return Err(format!("'{}': mixes '+' and '-' separators", spec));
+        }
+        Ok(Self::Key(Combo::new(Modifier::expand(&modifiers), parsed.key)))
+    }
+
+    /// The output the remapper would emit for this injection
+    pub fn to_output(&self) -> TransformResultOutput {
+        match self {
+            Self::Text(text) => TransformResultOutput::Text(text.clone()),
+            Self::Key(combo) => TransformResultOutput::Combo(combo.clone()),
+        }
+    }
+
+    /// Emit on `device`, going through its modifier bookkeeping like remapped output
+    pub fn send(&self, device: &mut VirtualDevice) -> Result<(), UInputError> {
+        device.process_transform_result(&self.to_output(), Action::Press)
+    }
+}
+
+/// Modifier by name or alias, ignoring case (`ctrl`, `Ctrl`, `CONTROL`)
+fn modifier_from_name(name: &str) -> Option<Modifier> {
+    Modifier::from_alias(name)
+        .or_else(|| Modifier::from_name(&name.to_ascii_uppercase()))
+        .or_else(|| {
+            Modifier::all_aliases()
+                .into_iter()
+                .find(|alias| alias.eq_ignore_ascii_case(name))
+                .and_then(|alias| Modifier::from_alias(&alias))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+
+    fn key(spec: &str) -> Combo {
+        match Injection::parse_key(spec) {
+            Ok(Injection::Key(combo)) => combo,
+            other => panic!("{}: {:?}", spec, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_key_specs() {
+        let ctrl = Modifier::from_name("CONTROL").unwrap();
+        let alt = Modifier::from_name("ALT").unwrap();
+        let ctrl_alt_t = Combo::new(vec![ctrl.clone(), alt.clone()], Key::from(20));
+
+        assert_eq!(key("ctrl+alt+t"), ctrl_alt_t);
+        assert_eq!(key("Control + Alt + T"), ctrl_alt_t);
+        assert_eq!(key("C-A-t"), ctrl_alt_t);
+        assert_eq!(key("super+enter"), Combo::new(vec![Modifier::from_name("META").unwrap()], Key::from(28)));
+        assert_eq!(key("Esc"), Combo::new(vec![], Key::from(1)));
+
+        assert_eq!(key("hyper+t"), key("C-A-Shift-Super-t"));
+        assert!(Injection::parse_key("hypr+t").is_err());
+        assert!(Injection::parse_key("ctrl+nope").is_err());
+        assert!(Injection::parse_key("ctrl+").is_err());
+    }
+}
@@ -1,16 +1,50 @@
 // Keyrs Output Layer
 // Virtual keyboard state management and combo calculation
 
+mod batch;
 mod cache;
+mod clipboard;
 mod combo;
+mod command;
+mod delays;
+mod devices;
+mod observer;
+mod repeat;
+mod schedule;
 mod state;
+mod wayland_unicode;
+mod watchdog;
 
+#[cfg(feature = "pure-rust")]
+mod inject;
+#[cfg(feature = "pure-rust")]
+mod sink;
 #[cfg(feature = "pure-rust")]
 mod uinput;
+#[cfg(feature = "pure-rust")]
+mod worker;
 
+pub use batch::{KeyBatch, MAX_EVENTS_PER_WRITE};
 pub use cache::{CacheData, OutputCache};
-pub use combo::{calculate_combo_actions, ComboActionSequence};
+pub use clipboard::{ClipboardPaster, ClipboardTool, UnicodeBatch, DEFAULT_UNICODE_BATCH_MIN};
+pub use combo::{calculate_combo_actions, ComboActionSequence, ComboStyle};
+pub use command::run_command;
+pub use delays::{DelayOverride, OutputDelays};
+pub use devices::OutputDeviceKind;
+pub use observer::{ObserverEvent, ObserverEventKind, ObserverHub, ObserverPrivacy};
+#[cfg(feature = "pure-rust")]
+pub(crate) use observer::parse_subscription;
+pub use repeat::RemapRepeat;
+pub use schedule::OutputSchedule;
 pub use state::PressedKeyState;
+pub use wayland_unicode::{UnicodeBackend, WaylandUnicodeError, WaylandUnicodeOutput};
+pub use watchdog::{StuckKey, StuckKeyWatchdog};
 
+#[cfg(feature = "pure-rust")]
+pub use inject::Injection;
+#[cfg(feature = "pure-rust")]
+pub use sink::RecordedEvents;
 #[cfg(feature = "pure-rust")]
 pub use uinput::{TransformResultOutput, UInputError, VirtualDevice};
+#[cfg(feature = "pure-rust")]
+pub use worker::{OutputCommand, OutputThread, OUTPUT_QUEUE_CAPACITY};
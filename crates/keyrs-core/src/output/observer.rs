@@ -0,0 +1,339 @@
+// Keyrs Output Observer
+// Read-only mirror of emitted output for key overlay (OSD) tools
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{Action, Key};
+
+/// Kind of mirrored output event (subscribers filter on these)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObserverEventKind {
+    /// Single key press/release/repeat
+    Key,
+    /// Combo or sequence step emitted as a whole
+    Combo,
+    /// Text or Unicode output
+    Text,
+}
+
+impl ObserverEventKind {
+    /// Name used on the wire and in subscription lines
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Key => "key",
+            Self::Combo => "combo",
+            Self::Text => "text",
+        }
+    }
+
+    /// Parse a kind name from a subscription line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "key" | "keys" => Some(Self::Key),
+            "combo" | "combos" => Some(Self::Combo),
+            "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        match self {
+            Self::Key => 1,
+            Self::Combo => 2,
+            Self::Text => 4,
+        }
+    }
+}
+
+/// How much typed content the observer stream may reveal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObserverPrivacy {
+    /// Mirror everything as emitted
+    Off,
+    /// Redact the content of text/Unicode output (default)
+    #[default]
+    Text,
+    /// Also redact unmodified printable keys, leaving shortcuts and special keys visible
+    Typing,
+}
+
+impl ObserverPrivacy {
+    /// Parse a privacy level as used in `[observer].privacy`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "text" => Some(Self::Text),
+            "typing" => Some(Self::Typing),
+            _ => None,
+        }
+    }
+}
+
+/// Label sent in place of redacted content
+pub const REDACTED_LABEL: &str = "<redacted>";
+
+/// A single mirrored output event
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObserverEvent {
+    pub kind: ObserverEventKind,
+    pub action: Action,
+    pub label: String,
+}
+
+impl ObserverEvent {
+    /// Create a new event
+    pub fn new(kind: ObserverEventKind, action: Action, label: impl Into<String>) -> Self {
+        Self {
+            kind,
+            action,
+            label: label.into(),
+        }
+    }
+
+    /// Encode as one JSON object per line
+    pub fn to_json_line(&self) -> String {
+        let action = match self.action {
+            Action::Press => "press",
+            Action::Release => "release",
+            Action::Repeat => "repeat",
+        };
+        format!(
+            "{{\"type\":\"{}\",\"action\":\"{}\",\"label\":\"{}\"}}\n",
+            self.kind.as_str(),
+            action,
+            escape_json(&self.label)
+        )
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether a key types a character on its own (letters, digits, punctuation, space)
+fn is_typing_key(key: Key) -> bool {
+    matches!(key.code(), 2..=13 | 16..=27 | 30..=41 | 43..=53 | 57)
+}
+
+/// Apply the privacy level to an event, replacing its label when redacted
+pub fn apply_privacy(event: ObserverEvent, privacy: ObserverPrivacy, key: Option<Key>) -> ObserverEvent {
+    let redact = match (privacy, event.kind) {
+        (ObserverPrivacy::Off, _) => false,
+        (_, ObserverEventKind::Text) => true,
+        (ObserverPrivacy::Typing, ObserverEventKind::Key) => key.map(is_typing_key).unwrap_or(false),
+        _ => false,
+    };
+    if redact {
+        ObserverEvent {
+            label: REDACTED_LABEL.to_string(),
+            ..event
+        }
+    } else {
+        event
+    }
+}
+
+struct Subscriber {
+    stream: UnixStream,
+    /// Bitmask of subscribed `ObserverEventKind`s
+    filter: u8,
+    pending: Vec<u8>,
+}
+
+impl Subscriber {
+    const ALL: u8 = 1 | 2 | 4;
+
+    /// Read subscription lines sent by the client; returns false on disconnect
+    fn read_subscriptions(&mut self) -> bool {
+        let mut buf = [0u8; 256];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return false,
+                Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            if let Some(filter) = parse_subscription(&String::from_utf8_lossy(&line)) {
+                self.filter = filter;
+            }
+        }
+        true
+    }
+}
+
+/// Parse a `subscribe <kind>[,<kind>...]` line (`all` selects every kind)
+pub(crate) fn parse_subscription(line: &str) -> Option<u8> {
+    let rest = line.trim().strip_prefix("subscribe")?.trim();
+    if rest.is_empty() || rest.eq_ignore_ascii_case("all") {
+        return Some(Subscriber::ALL);
+    }
+    let mut filter = 0;
+    for name in rest.split(',') {
+        filter |= ObserverEventKind::from_name(name)?.bit();
+    }
+    Some(filter)
+}
+
+/// Subscribers to the output mirror.
+///
+/// Clients subscribe on the control socket: a connection that sends a
+/// `subscribe` line is handed over here and receives output from then on.
+/// The output thread publishes through a clone of the hub and is never
+/// blocked by it: further subscription lines are read lazily on publish, and
+/// subscribers that cannot keep up are disconnected.
+#[derive(Clone)]
+pub struct ObserverHub {
+    inner: Arc<Mutex<HubState>>,
+}
+
+struct HubState {
+    privacy: ObserverPrivacy,
+    subscribers: Vec<Subscriber>,
+}
+
+impl ObserverHub {
+    /// A hub without subscribers, redacting at `privacy`
+    pub fn new(privacy: ObserverPrivacy) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HubState {
+                privacy,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, HubState> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take over a non-blocking connection that subscribed with `filter`;
+    /// `pending` holds what it sent after the subscription line
+    #[cfg(any(feature = "pure-rust", test))]
+    pub(crate) fn add(&self, stream: UnixStream, filter: u8, pending: Vec<u8>) {
+        log::debug!("Observer subscriber connected");
+        self.state().subscribers.push(Subscriber { stream, filter, pending });
+    }
+
+    /// Number of connected subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.state().subscribers.len()
+    }
+
+    /// Mirror an event to every subscriber whose filter includes its kind.
+    ///
+    /// `key` is the emitted key for `Key` events (used by the privacy filter).
+    pub fn publish(&self, event: ObserverEvent, key: Option<Key>) {
+        let mut state = self.state();
+        if state.subscribers.is_empty() {
+            return;
+        }
+
+        let event = apply_privacy(event, state.privacy, key);
+        let line = event.to_json_line();
+        let bit = event.kind.bit();
+        state.subscribers.retain_mut(|subscriber| {
+            if !subscriber.read_subscriptions() {
+                log::debug!("Observer subscriber disconnected");
+                return false;
+            }
+            if subscriber.filter & bit == 0 {
+                return true;
+            }
+            match subscriber.stream.write_all(line.as_bytes()) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::debug!("Dropping observer subscriber: {}", e);
+                    false
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_parse_subscription() {
+        assert_eq!(parse_subscription("subscribe"), Some(Subscriber::ALL));
+        assert_eq!(parse_subscription("subscribe all"), Some(Subscriber::ALL));
+        assert_eq!(parse_subscription("subscribe key,text"), Some(1 | 4));
+        assert_eq!(parse_subscription("subscribe mouse"), None);
+        assert_eq!(parse_subscription("hello"), None);
+    }
+
+    #[test]
+    fn test_event_json_line_escapes_label() {
+        let event = ObserverEvent::new(ObserverEventKind::Text, Action::Press, "a\"b\\c\n");
+        assert_eq!(
+            event.to_json_line(),
+            "{\"type\":\"text\",\"action\":\"press\",\"label\":\"a\\\"b\\\\c\\n\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_privacy_redaction() {
+        let text = ObserverEvent::new(ObserverEventKind::Text, Action::Press, "secret");
+        assert_eq!(apply_privacy(text.clone(), ObserverPrivacy::Off, None).label, "secret");
+        assert_eq!(apply_privacy(text, ObserverPrivacy::Text, None).label, REDACTED_LABEL);
+
+        let a = ObserverEvent::new(ObserverEventKind::Key, Action::Press, "A");
+        assert_eq!(apply_privacy(a.clone(), ObserverPrivacy::Text, Some(Key::from(30))).label, "A");
+        assert_eq!(
+            apply_privacy(a, ObserverPrivacy::Typing, Some(Key::from(30))).label,
+            REDACTED_LABEL
+        );
+        let enter = ObserverEvent::new(ObserverEventKind::Key, Action::Press, "ENTER");
+        assert_eq!(
+            apply_privacy(enter, ObserverPrivacy::Typing, Some(Key::from(28))).label,
+            "ENTER"
+        );
+    }
+
+    #[test]
+    fn test_hub_mirrors_events_to_filtered_subscribers() {
+        let hub = ObserverHub::new(ObserverPrivacy::Off);
+
+        let (mut keys_only, server_end) = UnixStream::pair().expect("socket pair");
+        server_end.set_nonblocking(true).unwrap();
+        hub.add(server_end, parse_subscription("subscribe key").unwrap(), Vec::new());
+        let (everything, server_end) = UnixStream::pair().expect("socket pair");
+        server_end.set_nonblocking(true).unwrap();
+        hub.add(server_end, Subscriber::ALL, b"subscribe combo\n".to_vec());
+
+        hub.publish(ObserverEvent::new(ObserverEventKind::Combo, Action::Press, "Ctrl-C"), None);
+        hub.publish(ObserverEvent::new(ObserverEventKind::Key, Action::Press, "ENTER"), Some(Key::from(28)));
+        assert_eq!(hub.subscriber_count(), 2);
+        drop(hub);
+
+        let mut received = String::new();
+        keys_only.read_to_string(&mut received).expect("read");
+        assert_eq!(received, "{\"type\":\"key\",\"action\":\"press\",\"label\":\"ENTER\"}\n");
+
+        // The line sent after subscribing narrowed the filter
+        let lines: Vec<String> = BufReader::new(everything).lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("Ctrl-C"));
+    }
+}
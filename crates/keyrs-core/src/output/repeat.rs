@@ -0,0 +1,56 @@
+// Keyrs Repeat Policy
+// Which key repeat events the virtual device forwards
+
+use crate::{Key, Modifier};
+
+/// What happens to repeat events of remapped (modmapped) keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemapRepeat {
+    /// Forward repeats so held remapped keys auto-repeat (default)
+    #[default]
+    Forward,
+    /// Drop repeats; the target application's own repeat (if any) applies
+    Drop,
+}
+
+impl RemapRepeat {
+    /// Parse a policy name as used in `[output].remap_repeat`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "forward" => Some(Self::Forward),
+            "drop" => Some(Self::Drop),
+            _ => None,
+        }
+    }
+
+    /// Whether a repeat of `key` is written to the virtual device.
+    ///
+    /// Modifiers never repeat: a held Ctrl (physical or Caps→Ctrl) would
+    /// otherwise write a repeat event per kernel repeat tick for nothing.
+    pub fn forwards(self, key: Key, remapped: bool) -> bool {
+        if Modifier::is_key_modifier(key) {
+            return false;
+        }
+        !remapped || self == Self::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remap_repeat_policy() {
+        let ctrl = Key::from(29);
+        let a = Key::from(30);
+
+        assert_eq!(RemapRepeat::from_name(" Drop "), Some(RemapRepeat::Drop));
+        assert_eq!(RemapRepeat::from_name("always"), None);
+
+        assert!(!RemapRepeat::Forward.forwards(ctrl, true));
+        assert!(!RemapRepeat::Forward.forwards(ctrl, false));
+        assert!(RemapRepeat::Forward.forwards(a, true));
+        assert!(!RemapRepeat::Drop.forwards(a, true));
+        assert!(RemapRepeat::Drop.forwards(a, false));
+    }
+}
@@ -0,0 +1,100 @@
+// Keyrs Output Schedule
+// Deferred sequence steps (`After(ms, ...)`) waiting for the output timer
+
+use std::time::{Duration, Instant};
+
+use crate::mapping::ActionStep;
+
+/// Steps queued to run once their deadline passes
+#[derive(Debug, Clone, PartialEq)]
+struct ScheduledSteps {
+    due: Instant,
+    steps: Vec<ActionStep>,
+}
+
+/// Queue of deferred output steps, ordered by deadline
+#[derive(Debug, Default)]
+pub struct OutputSchedule {
+    entries: Vec<ScheduledSteps>,
+}
+
+impl OutputSchedule {
+    /// Create an empty schedule
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `steps` to run `delay_ms` after `now`
+    pub fn schedule(&mut self, now: Instant, delay_ms: u64, steps: Vec<ActionStep>) {
+        let due = now + Duration::from_millis(delay_ms);
+        // Keep entries sorted by deadline; equal deadlines run in insertion order.
+        let index = self.entries.partition_point(|entry| entry.due <= due);
+        self.entries.insert(index, ScheduledSteps { due, steps });
+    }
+
+    /// Remove and return every step list whose deadline has passed
+    pub fn take_due(&mut self, now: Instant) -> Vec<Vec<ActionStep>> {
+        let count = self.entries.partition_point(|entry| entry.due <= now);
+        self.entries.drain(..count).map(|entry| entry.steps).collect()
+    }
+
+    /// Time until the next deadline (zero if one is already due)
+    pub fn next_due_in(&self, now: Instant) -> Option<Duration> {
+        self.entries
+            .first()
+            .map(|entry| entry.due.saturating_duration_since(now))
+    }
+
+    /// Drop every pending entry, returning how many were cancelled
+    pub fn cancel_all(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.clear();
+        count
+    }
+
+    /// Number of pending entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether nothing is scheduled
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_runs_in_deadline_order() {
+        let now = Instant::now();
+        let mut schedule = OutputSchedule::new();
+        schedule.schedule(now, 200, vec![ActionStep::Text("late".to_string())]);
+        schedule.schedule(now, 50, vec![ActionStep::Text("early".to_string())]);
+
+        assert!(schedule.take_due(now).is_empty());
+        assert_eq!(schedule.next_due_in(now), Some(Duration::from_millis(50)));
+
+        let due = schedule.take_due(now + Duration::from_millis(100));
+        assert_eq!(due, vec![vec![ActionStep::Text("early".to_string())]]);
+        assert_eq!(schedule.len(), 1);
+
+        let due = schedule.take_due(now + Duration::from_millis(200));
+        assert_eq!(due, vec![vec![ActionStep::Text("late".to_string())]]);
+        assert!(schedule.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_all_drops_pending_entries() {
+        let now = Instant::now();
+        let mut schedule = OutputSchedule::new();
+        schedule.schedule(now, 10, vec![ActionStep::Ignore]);
+        schedule.schedule(now, 20, vec![ActionStep::Ignore]);
+
+        assert_eq!(schedule.cancel_all(), 2);
+        assert!(schedule.take_due(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(schedule.next_due_in(now), None);
+    }
+}
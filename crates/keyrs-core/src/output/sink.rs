@@ -0,0 +1,74 @@
+// Keyrs Output Sink
+// Where written events go: uinput devices of this process or the input helper
+
+use std::sync::{Arc, Mutex};
+
+use evdev::InputEvent;
+
+use super::devices::OutputDeviceKind;
+use crate::privsep::{HelperCommand, HelperLink};
+
+/// Events kept by a recording `VirtualDevice`, as (device, type, code, value)
+pub type RecordedEvents = Arc<Mutex<Vec<(OutputDeviceKind, u16, u16, i32)>>>;
+
+/// Destination of the events a `VirtualDevice` writes
+pub(crate) enum OutputSink {
+    /// uinput devices owned by this process; only the keyboard is required
+    Uinput {
+        keyboard: evdev::uinput::VirtualDevice,
+        mouse: Option<evdev::uinput::VirtualDevice>,
+        consumer: Option<evdev::uinput::VirtualDevice>,
+    },
+    /// The privileged input helper, which owns the uinput devices
+    Helper(HelperLink),
+    /// Memory only: events are appended to a shared list (tests, no uinput needed)
+    Recording(RecordedEvents),
+}
+
+impl OutputSink {
+    /// Whether `kind` has a device of its own (rather than falling back to the keyboard)
+    pub(crate) fn has(&self, kind: OutputDeviceKind) -> bool {
+        match self {
+            Self::Uinput { mouse, consumer, .. } => match kind {
+                OutputDeviceKind::Keyboard => true,
+                OutputDeviceKind::Mouse => mouse.is_some(),
+                OutputDeviceKind::Consumer => consumer.is_some(),
+            },
+            Self::Helper(_) | Self::Recording(_) => true,
+        }
+    }
+
+    /// Write `events` to the device for `kind` (the keyboard if it wasn't created)
+    pub(crate) fn emit(&mut self, kind: OutputDeviceKind, events: &[InputEvent]) -> std::io::Result<()> {
+        match self {
+            Self::Uinput {
+                keyboard,
+                mouse,
+                consumer,
+            } => {
+                let device = match kind {
+                    OutputDeviceKind::Keyboard => None,
+                    OutputDeviceKind::Mouse => mouse.as_mut(),
+                    OutputDeviceKind::Consumer => consumer.as_mut(),
+                };
+                device.unwrap_or(keyboard).emit(events)
+            }
+            Self::Helper(link) => link.send(&HelperCommand::Emit(
+                kind,
+                events
+                    .iter()
+                    .map(|event| (event.event_type().0, event.code(), event.value()))
+                    .collect(),
+            )),
+            Self::Recording(events_out) => {
+                let mut recorded = events_out.lock().unwrap_or_else(|e| e.into_inner());
+                recorded.extend(
+                    events
+                        .iter()
+                        .map(|event| (kind, event.event_type().0, event.code(), event.value())),
+                );
+                Ok(())
+            }
+        }
+    }
+}
@@ -1,26 +1,87 @@
 // Keyrs Pure Rust uinput Output Layer
 // Virtual device creation and key event emission
 
+use super::batch::{KeyBatch, MAX_EVENTS_PER_WRITE};
 use super::cache::OutputCache;
-use super::combo::calculate_combo_actions;
+use super::clipboard::{ClipboardPaster, ClipboardTool, UnicodeBatch, DEFAULT_UNICODE_BATCH_MIN};
+use super::combo::{calculate_combo_actions, ComboStyle};
+use super::command::run_command;
+use super::delays::OutputDelays;
+use super::devices::OutputDeviceKind;
+use super::observer::{ObserverEvent, ObserverEventKind, ObserverHub};
+use super::repeat::RemapRepeat;
+use super::schedule::OutputSchedule;
+#[cfg(feature = "pure-rust")]
+use super::sink::{OutputSink, RecordedEvents};
 use super::state::PressedKeyState;
+use super::wayland_unicode::{UnicodeBackend, WaylandUnicodeError, WaylandUnicodeOutput};
+use super::watchdog::StuckKeyWatchdog;
 use crate::key::{ascii_to_key, key_from_name};
-use crate::mapping::ActionStep;
+use crate::layout::KeyboardLayout;
+use crate::mapping::{ActionStep, MAX_REPEAT_TIMES};
+#[cfg(feature = "pure-rust")]
+use crate::privsep::HelperLink;
 use crate::{Action, Combo, ComboHint, Key, Modifier};
-use std::sync::OnceLock;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "pure-rust")]
 use evdev::{EventType, InputEvent};
 
-/// Virtual uinput device for key output
+/// Virtual uinput devices for key output.
+///
+/// Keyboard keys go to a virtual keyboard; mouse buttons and consumer keys
+/// above 255 go to a virtual mouse and consumer-control device when those
+/// could be created. With privilege separation the devices live in the
+/// input helper and events are sent there instead.
 #[cfg(feature = "pure-rust")]
 pub struct VirtualDevice {
-    device: evdev::uinput::VirtualDevice,
+    sink: OutputSink,
     pressed_keys: PressedKeyState,
     pressed_modifiers: PressedKeyState,
     cache: OutputCache,
     key_pre_delay_ms: u64,
     key_post_delay_ms: u64,
+    /// Pause between the events of one combo (`[delays] combo_delay_ms`)
+    combo_delay_ms: u64,
+    /// Emission style of the keymap that produced the current output
+    combo_style: ComboStyle,
+    schedule: OutputSchedule,
+    observer: Option<ObserverHub>,
+    wayland_unicode: Option<WaylandUnicodeOutput>,
+    unicode_batch: UnicodeBatch,
+    unicode_batch_min: usize,
+    paste_combo: Option<Combo>,
+    /// Sets the clipboard for `unicode_batch = "clipboard"`
+    clipboard: Option<ClipboardPaster>,
+    layout: Option<KeyboardLayout>,
+    remap_repeat: RemapRepeat,
+    watchdog: StuckKeyWatchdog,
+    /// Output being processed, named in stuck-key reports
+    current_output: Option<TransformResultOutput>,
+    /// Raised from another thread to cut the output in progress short
+    abort: Option<Arc<AtomicBool>>,
+}
+
+/// Pause between the writes of one batch so clients can drain their buffers
+#[cfg(feature = "pure-rust")]
+const BATCH_WRITE_GAP: Duration = Duration::from_millis(1);
+
+/// Longest uninterrupted sleep of a `Delay(...)` or `Hold(...)`, so an abort is noticed quickly
+#[cfg(feature = "pure-rust")]
+const ABORT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// State threaded through the steps of one sequence
+#[derive(Debug, Default)]
+struct SequenceState {
+    /// The next combo keeps held modifiers (`Bind`)
+    bind_next: bool,
+    /// Keys pressed by `Press(...)` and not released yet
+    held: Vec<Key>,
+    /// Held modifiers the sequence leaves down (`KeepModifiers(...)`)
+    keep: Vec<Modifier>,
 }
 
 /// Error types for uinput operations
@@ -34,53 +95,166 @@ pub enum UInputError {
 
     #[error("Device not initialized")]
     NotInitialized,
+
+    #[error("Output aborted")]
+    Aborted,
 }
 
 #[cfg(feature = "pure-rust")]
 impl VirtualDevice {
+    /// Per-event output tracing, enabled with `output=debug` (or this module's path)
     fn debug_output_enabled() -> bool {
-        static DEBUG_OUTPUT: OnceLock<bool> = OnceLock::new();
-        *DEBUG_OUTPUT.get_or_init(|| {
-            std::env::var("KEYRS_DEBUG_OUTPUT")
-                .map(|v| matches!(v.as_str(), "1" | "true" | "TRUE" | "yes" | "on"))
-                .unwrap_or(false)
-        })
+        log::log_enabled!(log::Level::Debug)
     }
 
     fn debug_output_log(&self, message: &str) {
         if Self::debug_output_enabled() {
-            log::debug!("[OUTPUT] {}", message);
+            log::debug!("{}", message);
         }
     }
 
-    /// Create a new virtual uinput device
-    pub fn new() -> Result<Self, UInputError> {
+    /// Build the uinput device for one kind of output; the keyboard also
+    /// carries scancodes and `switches` (`SW_*` codes) forwarded from grabbed devices
+    fn build_device(kind: OutputDeviceKind, switches: &[u16]) -> Result<evdev::uinput::VirtualDevice, UInputError> {
         use evdev::uinput::VirtualDeviceBuilder;
-        use evdev::AttributeSet;
+        use evdev::{AttributeSet, MiscType, RelativeAxisType, SwitchType};
 
-        // Build the virtual device with keyboard support
         let mut keys = AttributeSet::new();
-        // Add all standard keyboard keys (0-255)
-        for code in 0..256u16 {
+        for code in kind.key_codes() {
             keys.insert(evdev::Key::new(code));
         }
 
-        let device = VirtualDeviceBuilder::new()
+        let mut builder = VirtualDeviceBuilder::new()
             .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?
-            .name("Keyrs (virtual) Keyboard")
+            .name(kind.device_name())
             .with_keys(&keys)
-            .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?
-            .build()
             .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?;
+        if kind == OutputDeviceKind::Mouse {
+            // Relative axes make clients treat the device as a pointer
+            let mut axes = AttributeSet::new();
+            for axis in [
+                RelativeAxisType::REL_X,
+                RelativeAxisType::REL_Y,
+                RelativeAxisType::REL_WHEEL,
+                RelativeAxisType::REL_HWHEEL,
+            ] {
+                axes.insert(axis);
+            }
+            builder = builder
+                .with_relative_axes(&axes)
+                .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?;
+        }
+        if kind == OutputDeviceKind::Keyboard {
+            let mut misc = AttributeSet::new();
+            misc.insert(MiscType::MSC_SCAN);
+            builder = builder
+                .with_msc(&misc)
+                .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?;
+            if !switches.is_empty() {
+                let mut set = AttributeSet::new();
+                for code in switches {
+                    set.insert(SwitchType(*code));
+                }
+                builder = builder
+                    .with_switches(&set)
+                    .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))?;
+            }
+        }
+        builder
+            .build()
+            .map_err(|e: std::io::Error| UInputError::DeviceCreation(e.to_string()))
+    }
+
+    /// Create the virtual keyboard, plus the mouse and consumer-control devices.
+    ///
+    /// Only the keyboard is required; without the others, their keys are
+    /// written to the keyboard, which drops them.
+    pub fn new() -> Result<Self, UInputError> {
+        Self::with_switches(&[])
+    }
+
+    /// Like `new`, with a keyboard that also reports `switches` (`SW_*` codes)
+    /// so switch events of grabbed devices can be forwarded
+    pub fn with_switches(switches: &[u16]) -> Result<Self, UInputError> {
+        let keyboard = Self::build_device(OutputDeviceKind::Keyboard, switches)?;
+        let optional = |kind: OutputDeviceKind| match Self::build_device(kind, &[]) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                log::warn!("Could not create {}: {}", kind.device_name(), e);
+                None
+            }
+        };
 
-        Ok(Self {
-            device,
+        Ok(Self::with_sink(OutputSink::Uinput {
+            keyboard,
+            mouse: optional(OutputDeviceKind::Mouse),
+            consumer: optional(OutputDeviceKind::Consumer),
+        }))
+    }
+
+    /// Output through the input helper at the other end of `link`, which
+    /// owns the uinput devices (privilege separation)
+    pub fn remote(link: HelperLink) -> Self {
+        Self::with_sink(OutputSink::Helper(link))
+    }
+
+    /// Output kept in memory instead of written anywhere; every event lands
+    /// in the returned list. No uinput access is needed.
+    pub fn recording() -> (Self, RecordedEvents) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        (Self::with_sink(OutputSink::Recording(events.clone())), events)
+    }
+
+    fn with_sink(sink: OutputSink) -> Self {
+        Self {
+            sink,
             pressed_keys: PressedKeyState::new(),
             pressed_modifiers: PressedKeyState::new(),
             cache: OutputCache::new(),
             key_pre_delay_ms: 0,
             key_post_delay_ms: 0,
-        })
+            combo_delay_ms: 0,
+            combo_style: ComboStyle::default(),
+            schedule: OutputSchedule::new(),
+            observer: None,
+            wayland_unicode: None,
+            unicode_batch: UnicodeBatch::default(),
+            unicode_batch_min: DEFAULT_UNICODE_BATCH_MIN,
+            paste_combo: None,
+            clipboard: None,
+            layout: None,
+            remap_repeat: RemapRepeat::default(),
+            watchdog: StuckKeyWatchdog::new(None),
+            current_output: None,
+            abort: None,
+        }
+    }
+
+    /// Stop sequences, text and delays between steps once `flag` is raised.
+    /// The output cut short fails with `UInputError::Aborted`; the owner
+    /// lowers the flag after releasing the keys.
+    pub fn set_abort_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.abort = Some(flag);
+    }
+
+    fn check_abort(&self) -> Result<(), UInputError> {
+        match &self.abort {
+            Some(flag) if flag.load(Ordering::SeqCst) => Err(UInputError::Aborted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sleep for `duration`, waking early with `UInputError::Aborted` on an abort
+    fn pause(&self, duration: Duration) -> Result<(), UInputError> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check_abort()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            std::thread::sleep(left.min(ABORT_CHECK_INTERVAL));
+        }
     }
 
     /// Configure output throttle delays in milliseconds.
@@ -89,6 +263,92 @@ impl VirtualDevice {
         self.key_post_delay_ms = key_post_delay_ms;
     }
 
+    /// Pause between the events of one combo, in milliseconds
+    pub fn set_combo_delay(&mut self, combo_delay_ms: u64) {
+        self.combo_delay_ms = combo_delay_ms;
+    }
+
+    /// Apply `[delays]` (or the override for the current window) to the following output
+    pub fn set_delays(&mut self, delays: OutputDelays) {
+        self.set_throttle_delays(delays.key_pre_delay_ms, delays.key_post_delay_ms);
+        self.set_combo_delay(delays.combo_delay_ms);
+    }
+
+    /// Modifier order and delay for the following combos, from the matched keymap
+    pub fn set_combo_style(&mut self, style: ComboStyle) {
+        self.combo_style = style;
+    }
+
+    /// Write `events` to the device that emits `kind` output
+    fn emit(&mut self, kind: OutputDeviceKind, events: &[InputEvent]) -> Result<(), UInputError> {
+        self.sink
+            .emit(kind, events)
+            .map_err(|e: std::io::Error| UInputError::WriteError(e.to_string()))
+    }
+
+    /// Write events a remapping process sent to the input helper, as (type,
+    /// code, value) triples; key events are tracked so `release_all` can
+    /// release them when that process goes away
+    pub fn emit_events(&mut self, kind: OutputDeviceKind, events: &[(u16, u16, i32)]) -> Result<(), UInputError> {
+        let list: Vec<InputEvent> = events
+            .iter()
+            .map(|(event_type, code, value)| InputEvent::new(EventType(*event_type), *code, *value))
+            .collect();
+        self.emit(kind, &list)?;
+        for (event_type, code, value) in events {
+            let action = match value {
+                0 => Action::Release,
+                1 => Action::Press,
+                _ => continue,
+            };
+            if *event_type == EventType::KEY.0 {
+                self.track_pressed(Key::from(*code), action);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write relative pointer motion or scrolling (an evdev `REL_*` code) to the virtual mouse
+    pub fn send_relative(&mut self, axis: u16, value: i32) -> Result<(), UInputError> {
+        self.send_relative_frame(&[(axis, value)])
+    }
+
+    /// Write one frame of relative motion/scrolling, as (`REL_*` code, value) pairs
+    pub fn send_relative_frame(&mut self, frame: &[(u16, i32)]) -> Result<(), UInputError> {
+        let events: Vec<InputEvent> = frame
+            .iter()
+            .map(|(axis, value)| InputEvent::new(EventType::RELATIVE, *axis, *value))
+            .chain([InputEvent::new(EventType::SYNCHRONIZATION, 0, 0)])
+            .collect();
+        if !self.sink.has(OutputDeviceKind::Mouse) {
+            return Err(UInputError::NotInitialized);
+        }
+        self.emit(OutputDeviceKind::Mouse, &events)
+    }
+
+    /// Re-emit one frame of non-key events from a grabbed device, as (type,
+    /// code, value) triples: motion goes to the virtual mouse, the rest
+    /// (scancodes, switches) to the virtual keyboard
+    pub fn forward_frame(&mut self, frame: &[(u16, u16, i32)]) -> Result<(), UInputError> {
+        let relative: Vec<(u16, i32)> = frame
+            .iter()
+            .filter(|(event_type, _, _)| *event_type == EventType::RELATIVE.0)
+            .map(|(_, code, value)| (*code, *value))
+            .collect();
+        if !relative.is_empty() {
+            self.send_relative_frame(&relative)?;
+        }
+        let events: Vec<InputEvent> = frame
+            .iter()
+            .filter(|(event_type, _, _)| *event_type != EventType::RELATIVE.0)
+            .map(|(event_type, code, value)| InputEvent::new(EventType(*event_type), *code, *value))
+            .collect();
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.emit(OutputDeviceKind::Keyboard, &events)
+    }
+
     /// Write a single key event to the virtual device
     fn write_key_event(&mut self, key: Key, action: Action) -> Result<(), UInputError> {
         let value = match action {
@@ -102,11 +362,19 @@ impl VirtualDevice {
         // SYN event is required for the kernel to process the key event
         let syn_event = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
 
-        self.device
-            .emit(&[key_event, syn_event])
-            .map_err(|e: std::io::Error| UInputError::WriteError(e.to_string()))?;
+        self.emit(OutputDeviceKind::for_key(key), &[key_event, syn_event])?;
+
+        self.track_pressed(key, action);
+        Ok(())
+    }
 
-        // Update pressed state
+    /// Update pressed state after a key event was written
+    fn track_pressed(&mut self, key: Key, action: Action) {
+        let current_output = &self.current_output;
+        self.watchdog.track(key, action, Instant::now(), || match current_output {
+            Some(output) => output.to_string(),
+            None => "a scheduled After(...) step".to_string(),
+        });
         if Modifier::is_key_modifier(key) {
             match action {
                 Action::Press => self.pressed_modifiers.add(key),
@@ -120,7 +388,71 @@ impl VirtualDevice {
                 Action::Repeat => {}
             }
         }
+    }
+
+    /// Send a batch of key events with one SYN report per frame.
+    ///
+    /// Frames go out in as few writes as the client buffer allows, with a short
+    /// gap between writes. With throttle delays configured every event is sent
+    /// on its own so the delays still apply.
+    pub fn send_batch(&mut self, batch: &KeyBatch) -> Result<(), UInputError> {
+        if self.key_pre_delay_ms > 0 || self.key_post_delay_ms > 0 {
+            for frame in batch.frames() {
+                self.check_abort()?;
+                for (key, action) in frame {
+                    self.send_key_action(*key, *action)?;
+                }
+            }
+            return Ok(());
+        }
 
+        let writes = batch.writes(MAX_EVENTS_PER_WRITE);
+        if Self::debug_output_enabled() {
+            self.debug_output_log(&format!(
+                "send_batch events={} frames={} writes={}",
+                batch.len(),
+                batch.frames().len(),
+                writes.len()
+            ));
+        }
+        for (index, range) in writes.into_iter().enumerate() {
+            // Some apps/shells drop characters when virtual key events arrive
+            // with zero gap, so give readers time to drain between writes.
+            if index > 0 {
+                std::thread::sleep(BATCH_WRITE_GAP);
+                self.check_abort()?;
+            }
+            let frames = &batch.frames()[range];
+            // One event list per device; a frame spanning devices gets a SYN on each
+            let mut events: [Vec<InputEvent>; 3] = Default::default();
+            for frame in frames {
+                let mut touched = [false; 3];
+                for (key, action) in frame {
+                    let value = match action {
+                        Action::Press => 1,
+                        Action::Release => 0,
+                        Action::Repeat => 2,
+                    };
+                    let index = OutputDeviceKind::for_key(*key).index();
+                    events[index].push(InputEvent::new(EventType::KEY, key.code(), value));
+                    touched[index] = true;
+                }
+                for (list, touched) in events.iter_mut().zip(touched) {
+                    if touched {
+                        list.push(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0));
+                    }
+                }
+            }
+            for kind in OutputDeviceKind::ALL {
+                let list = &events[kind.index()];
+                if !list.is_empty() {
+                    self.emit(kind, list)?;
+                }
+            }
+            for (key, action) in frames.iter().flatten() {
+                self.track_pressed(*key, *action);
+            }
+        }
         Ok(())
     }
 
@@ -218,103 +550,287 @@ impl VirtualDevice {
         }
     }
 
-    fn send_ascii_char(&mut self, ch: char) -> Result<bool, UInputError> {
-        let Some((key, needs_shift)) = Self::ascii_key_and_shift(ch) else {
+    /// Key for a text character under `layout` (US QWERTY when None)
+    fn text_key_and_shift(layout: Option<&KeyboardLayout>, ch: char) -> Option<(Key, bool)> {
+        match layout {
+            Some(layout) if ch != '\n' && ch != '\t' => layout.key_for_char(ch),
+            _ => Self::ascii_key_and_shift(ch),
+        }
+    }
+
+    /// Queue the key taps for a text character; false when it needs Unicode output
+    fn push_ascii_char(&self, batch: &mut KeyBatch, ch: char, shift_held: bool) -> Result<bool, UInputError> {
+        let Some((key, needs_shift)) = Self::text_key_and_shift(self.layout.as_ref(), ch) else {
             return Ok(false);
         };
 
-        if needs_shift {
+        if needs_shift && !shift_held {
             let left_shift = Self::key_required("LEFT_SHIFT")?;
-            self.send_key_action(left_shift, Action::Press)?;
-            self.tap_key(key)?;
-            self.send_key_action(left_shift, Action::Release)?;
+            batch.press(left_shift);
+            batch.tap(key);
+            batch.release(left_shift);
         } else {
-            self.tap_key(key)?;
+            batch.tap(key);
         }
 
         Ok(true)
     }
 
-    /// Send a Unicode character via Linux's Ctrl+Shift+U compose sequence.
-    pub fn send_unicode(&mut self, codepoint: u32) -> Result<(), UInputError> {
-        if char::from_u32(codepoint).is_none() {
-            return Err(UInputError::WriteError(format!(
-                "Invalid Unicode codepoint: 0x{codepoint:X}"
-            )));
-        }
-
+    /// Queue Linux's Ctrl+Shift+U compose sequence for `codepoint`.
+    ///
+    /// Held modifiers must already be released in `batch`.
+    fn push_unicode_compose(&self, batch: &mut KeyBatch, codepoint: u32) -> Result<(), UInputError> {
         let hex = format!("{codepoint:x}");
         let left_ctrl = Self::key_required("LEFT_CTRL")?;
         let left_shift = Self::key_required("LEFT_SHIFT")?;
         let u_key = Self::key_required("U")?;
         let enter = Self::key_required("ENTER")?;
 
-        // Prevent currently held modifiers from interfering with Unicode composition.
-        let held_modifiers = self.pressed_modifiers.get_all();
-        for modifier in held_modifiers.iter().rev() {
-            self.send_key_action(*modifier, Action::Release)?;
-        }
-
         // Trigger compose mode: Ctrl+Shift+U
-        self.send_key_action(left_ctrl, Action::Press)?;
-        self.send_key_action(left_shift, Action::Press)?;
-        self.tap_key(u_key)?;
-        self.send_key_action(left_shift, Action::Release)?;
-        self.send_key_action(left_ctrl, Action::Release)?;
+        batch.press(left_ctrl);
+        batch.press(left_shift);
+        batch.tap(u_key);
+        batch.release(left_shift);
+        batch.release(left_ctrl);
 
         // Type hexadecimal codepoint and commit with Enter.
         for ch in hex.chars() {
-            let digit_key = Self::key_for_unicode_digit(ch)?;
-            self.tap_key(digit_key)?;
+            match self.layout.as_ref().and_then(|layout| layout.key_for_char(ch)) {
+                Some((digit_key, true)) => {
+                    batch.press(left_shift);
+                    batch.tap(digit_key);
+                    batch.release(left_shift);
+                }
+                Some((digit_key, false)) => batch.tap(digit_key),
+                None => batch.tap(Self::key_for_unicode_digit(ch)?),
+            }
+        }
+        batch.tap(enter);
+        Ok(())
+    }
+
+    /// Type text for the given keyboard layout instead of US QWERTY.
+    ///
+    /// Characters the layout types at level 1 or 2 (including accented ones
+    /// such as `é` on AZERTY) become plain key taps; the rest use Unicode output.
+    pub fn set_layout(&mut self, layout: Option<KeyboardLayout>) {
+        self.layout = layout;
+    }
+
+    /// Choose whether repeats of remapped keys are forwarded.
+    ///
+    /// Repeats of modifiers are dropped either way.
+    pub fn set_remap_repeat(&mut self, remap_repeat: RemapRepeat) {
+        self.remap_repeat = remap_repeat;
+    }
+
+    /// Select how Unicode characters are emitted.
+    ///
+    /// `VirtualKeyboard` connects to the Wayland compositor now; on failure the
+    /// device keeps using Ctrl+Shift+U and the error is returned.
+    pub fn set_unicode_backend(&mut self, backend: UnicodeBackend) -> Result<(), WaylandUnicodeError> {
+        self.wayland_unicode = match backend {
+            UnicodeBackend::Compose => None,
+            UnicodeBackend::VirtualKeyboard => Some(WaylandUnicodeOutput::connect()?),
+        };
+        Ok(())
+    }
+
+    /// Paste `Text(...)` output with at least `min` non-ASCII characters
+    /// through the clipboard (`paste` is the combo sent after copying)
+    /// instead of typing it character by character.
+    ///
+    /// Without a graphical session to pick a clipboard tool for, text is typed.
+    pub fn set_unicode_batch(&mut self, batch: UnicodeBatch, min: usize, paste: Combo) {
+        self.unicode_batch = batch;
+        self.unicode_batch_min = min;
+        self.paste_combo = Some(paste);
+        self.clipboard = None;
+        if batch != UnicodeBatch::Clipboard {
+            return;
+        }
+        let Some(tool) = ClipboardTool::detect() else {
+            log::info!("No WAYLAND_DISPLAY or DISPLAY for the clipboard; long Unicode text is typed");
+            return;
+        };
+        match ClipboardPaster::spawn(tool) {
+            Ok(paster) => self.clipboard = Some(paster),
+            Err(e) => log::warn!("Could not start the clipboard thread; long Unicode text is typed: {}", e),
         }
-        self.tap_key(enter)?;
+    }
+
+    /// Copy `text` to the clipboard and paste it; false (text not sent)
+    /// when the clipboard could not be set
+    fn paste_text(&mut self, text: &str) -> Result<bool, UInputError> {
+        let (Some(clipboard), Some(paste)) = (self.clipboard.as_ref(), self.paste_combo.clone()) else {
+            return Ok(false);
+        };
+        if let Err(e) = clipboard.copy(text) {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                log::warn!("Clipboard tool not found, typing long Unicode text from now on: {}", e);
+                self.clipboard = None;
+            } else {
+                log::warn!("Clipboard paste failed, typing text instead: {}", e);
+            }
+            return Ok(false);
+        }
+
+        // Held modifiers would turn the paste combo into another shortcut
+        let held_modifiers = self.pressed_modifiers.get_all();
+        if !held_modifiers.is_empty() {
+            let mut batch = KeyBatch::new();
+            for modifier in held_modifiers.iter().rev() {
+                batch.release(*modifier);
+            }
+            self.send_batch(&batch)?;
+        }
+        self.send_combo(&paste)?;
+        if !held_modifiers.is_empty() {
+            let mut batch = KeyBatch::new();
+            for modifier in &held_modifiers {
+                batch.press(*modifier);
+            }
+            self.send_batch(&batch)?;
+        }
+        Ok(true)
+    }
+
+    /// Type a run of non-ASCII characters with one Wayland virtual keyboard
+    /// call, falling back to one Ctrl+Shift+U cycle per character
+    fn send_unicode_run(&mut self, run: &str) -> Result<(), UInputError> {
+        if let Some(wayland) = self.wayland_unicode.as_mut() {
+            match wayland.send_text(run) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::warn!("Wayland Unicode output failed, falling back to Ctrl+Shift+U: {}", e);
+                    self.wayland_unicode = None;
+                }
+            }
+        }
+        for ch in run.chars() {
+            self.send_unicode(ch as u32)?;
+        }
+        Ok(())
+    }
+
+    /// Send a Unicode character, through the Wayland virtual keyboard when
+    /// configured, otherwise via Linux's Ctrl+Shift+U compose sequence.
+    pub fn send_unicode(&mut self, codepoint: u32) -> Result<(), UInputError> {
+        let Some(ch) = char::from_u32(codepoint) else {
+            return Err(UInputError::WriteError(format!(
+                "Invalid Unicode codepoint: 0x{codepoint:X}"
+            )));
+        };
+
+        if let Some(wayland) = self.wayland_unicode.as_mut() {
+            match wayland.send_text(ch.encode_utf8(&mut [0; 4])) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::warn!("Wayland Unicode output failed, falling back to Ctrl+Shift+U: {}", e);
+                    self.wayland_unicode = None;
+                }
+            }
+        }
+
+        // Prevent currently held modifiers from interfering with Unicode composition.
+        let held_modifiers = self.pressed_modifiers.get_all();
+        let mut batch = KeyBatch::new();
+        for modifier in held_modifiers.iter().rev() {
+            batch.release(*modifier);
+        }
+
+        self.push_unicode_compose(&mut batch, codepoint)?;
 
         // Restore modifiers that were held before Unicode entry.
         for modifier in &held_modifiers {
-            self.send_key_action(*modifier, Action::Press)?;
+            batch.press(*modifier);
         }
 
-        Ok(())
+        self.send_batch(&batch)
     }
 
     /// Send text using direct ASCII key events when possible, with Unicode compose fallback.
     pub fn send_text(&mut self, text: &str) -> Result<(), UInputError> {
+        self.send_text_keeping(text, &[])
+    }
+
+    /// Send text like `send_text`, but leave held modifiers matching `keep`
+    /// down while typing: a kept Shift types shifted characters. Kept
+    /// modifiers are lifted only around Unicode compose sequences.
+    pub fn send_text_keeping(&mut self, text: &str, keep: &[Modifier]) -> Result<(), UInputError> {
         self.debug_output_log(&format!("send_text start len={} text='{}'", text.len(), text));
+        // The Wayland virtual keyboard already sends a run of characters at once
+        if self.wayland_unicode.is_none()
+            && self.unicode_batch.applies(text, self.unicode_batch_min)
+            && self.paste_text(text)?
+        {
+            self.debug_output_log("send_text end path=clipboard");
+            return Ok(());
+        }
         // Prevent currently held modifiers from interfering with text emission.
-        let held_modifiers = self.pressed_modifiers.get_all();
+        let (kept, held_modifiers): (Vec<Key>, Vec<Key>) = self
+            .pressed_modifiers
+            .get_all()
+            .into_iter()
+            .partition(|key| keep.iter().any(|modifier| modifier.keys().contains(key)));
+        let shift_held = kept
+            .iter()
+            .any(|key| Modifier::from_alias("Shift").is_some_and(|shift| shift.keys().contains(key)));
         if Self::debug_output_enabled() {
             self.debug_output_log(&format!(
                 "send_text releasing held modifiers: {:?}",
                 held_modifiers
             ));
         }
+        let mut batch = KeyBatch::new();
         for modifier in held_modifiers.iter().rev() {
-            self.send_key_action(*modifier, Action::Release)?;
+            batch.release(*modifier);
         }
 
+        // Non-ASCII characters waiting to go to the Wayland virtual keyboard together
+        let mut run = String::new();
         for (idx, ch) in text.chars().enumerate() {
-            if !self.send_ascii_char(ch)? {
+            if !run.is_empty() && Self::text_key_and_shift(self.layout.as_ref(), ch).is_some() {
+                self.send_unicode_run(&std::mem::take(&mut run))?;
+            }
+            if self.push_ascii_char(&mut batch, ch, shift_held)? {
+                if Self::debug_output_enabled() {
+                    self.debug_output_log(&format!("send_text char[{}]='{}' path=ascii", idx, ch));
+                }
+            } else if self.wayland_unicode.is_some() {
+                self.debug_output_log(&format!(
+                    "send_text char[{}]='{}' path=wayland",
+                    idx, ch
+                ));
+                // Keys typed so far must land before the compositor inserts the characters
+                if run.is_empty() {
+                    self.send_batch(&std::mem::take(&mut batch))?;
+                }
+                run.push(ch);
+            } else {
                 self.debug_output_log(&format!(
                     "send_text char[{}]='{}' path=unicode",
                     idx, ch
                 ));
-                self.send_unicode(ch as u32)?;
-            } else if Self::debug_output_enabled() {
-                self.debug_output_log(&format!("send_text char[{}]='{}' path=ascii", idx, ch));
+                for modifier in kept.iter().rev() {
+                    batch.release(*modifier);
+                }
+                self.push_unicode_compose(&mut batch, ch as u32)?;
+                for modifier in &kept {
+                    batch.press(*modifier);
+                }
             }
+        }
 
-            // Some apps/shells drop characters when virtual key events arrive
-            // with zero gap. Add a minimal pacing fallback unless a post delay
-            // is already configured.
-            if self.key_post_delay_ms == 0 {
-                std::thread::sleep(std::time::Duration::from_millis(1));
-            }
+        if !run.is_empty() {
+            self.send_unicode_run(&run)?;
         }
 
         // Restore previously held modifiers.
         for modifier in &held_modifiers {
-            self.send_key_action(*modifier, Action::Press)?;
+            batch.press(*modifier);
         }
+        self.send_batch(&batch)?;
         self.debug_output_log("send_text end");
         Ok(())
     }
@@ -328,32 +844,46 @@ impl VirtualDevice {
         let pressed_mods = self.pressed_modifiers.get_all();
 
         // Calculate the action sequence
-        let actions = calculate_combo_actions(&modifiers, main_key, &pressed_mods);
+        let mut actions = calculate_combo_actions(&modifiers, main_key, &pressed_mods);
+        self.combo_style.order_modifiers(&mut actions.modifiers_to_press);
 
+        let mut events = Vec::with_capacity(actions.total_actions() + actions.modifiers_to_press.len());
         // Release modifiers that need to be lifted
         for key in &actions.modifiers_to_release {
-            self.send_key_action(*key, Action::Release)?;
+            events.push((*key, Action::Release));
         }
 
         // Press modifiers that need to be pressed
         for key in &actions.modifiers_to_press {
-            self.send_key_action(*key, Action::Press)?;
+            events.push((*key, Action::Press));
         }
 
         // Press and release the main key
-        self.send_key_action(main_key, Action::Press)?;
-        self.send_key_action(main_key, Action::Release)?;
+        events.push((main_key, Action::Press));
+        events.push((main_key, Action::Release));
 
         // Release the pressed modifiers
         for key in actions.modifiers_to_press.iter().rev() {
-            self.send_key_action(*key, Action::Release)?;
+            events.push((*key, Action::Release));
         }
 
         // Restore modifiers that were released
         for key in &actions.modifiers_to_restore {
-            self.send_key_action(*key, Action::Press)?;
+            events.push((*key, Action::Press));
         }
 
+        self.send_combo_events(&events)
+    }
+
+    /// Send the events of one combo, pausing between them when a combo delay is set
+    fn send_combo_events(&mut self, events: &[(Key, Action)]) -> Result<(), UInputError> {
+        let delay = self.combo_style.delay_ms.unwrap_or(self.combo_delay_ms);
+        for (index, (key, action)) in events.iter().enumerate() {
+            if index > 0 && delay > 0 {
+                std::thread::sleep(Duration::from_millis(delay));
+            }
+            self.send_key_action(*key, *action)?;
+        }
         Ok(())
     }
 
@@ -366,64 +896,168 @@ impl VirtualDevice {
         let pressed_mods = self.pressed_modifiers.get_all();
 
         // Press only missing target modifiers; keep existing held modifiers as-is.
-        let mut newly_pressed = Vec::new();
-        for modifier in modifiers {
-            let already_pressed = modifier.keys().iter().any(|k| pressed_mods.contains(k));
-            if !already_pressed {
-                let key_to_press = modifier.key();
-                self.send_key_action(key_to_press, Action::Press)?;
-                newly_pressed.push(key_to_press);
-            }
-        }
+        let mut newly_pressed: Vec<Key> = modifiers
+            .iter()
+            .filter(|modifier| !modifier.keys().iter().any(|k| pressed_mods.contains(k)))
+            .map(|modifier| modifier.key())
+            .collect();
+        self.combo_style.order_modifiers(&mut newly_pressed);
 
-        self.send_key_action(main_key, Action::Press)?;
-        self.send_key_action(main_key, Action::Release)?;
+        let mut events: Vec<(Key, Action)> = newly_pressed.iter().map(|key| (*key, Action::Press)).collect();
+        events.push((main_key, Action::Press));
+        events.push((main_key, Action::Release));
 
         // Release only modifiers we introduced for this bound combo.
-        for key in newly_pressed.into_iter().rev() {
-            self.send_key_action(key, Action::Release)?;
-        }
+        events.extend(newly_pressed.iter().rev().map(|key| (*key, Action::Release)));
 
-        Ok(())
+        self.send_combo_events(&events)
     }
 
-    fn execute_sequence_step(&mut self, step: &ActionStep, bind_next: &mut bool) -> Result<(), UInputError> {
+    fn execute_sequence_step(&mut self, step: &ActionStep, state: &mut SequenceState) -> Result<(), UInputError> {
         if Self::debug_output_enabled() {
             self.debug_output_log(&format!("execute_sequence_step {:?}", step));
         }
+        self.check_abort()?;
         match step {
             ActionStep::Combo(combo) => {
-                if *bind_next {
-                    *bind_next = false;
+                // Modifiers held by Press(...) stay down, like Bind
+                let holds_modifier = state.held.iter().any(|k| Modifier::is_key_modifier(*k));
+                if state.bind_next || holds_modifier {
+                    state.bind_next = false;
                     self.send_combo_bound(combo)
                 } else {
                     self.send_combo(combo)
                 }
             }
-            ActionStep::Text(text) => self.send_text(text),
-            ActionStep::DelayMs(ms) => {
-                std::thread::sleep(std::time::Duration::from_millis(*ms));
-                Ok(())
-            }
+            ActionStep::Text(text) => self.send_text_keeping(text, &state.keep),
+            ActionStep::DelayMs(ms) => self.pause(Duration::from_millis(*ms)),
             ActionStep::Ignore => Ok(()),
             ActionStep::Bind => {
-                *bind_next = true;
+                state.bind_next = true;
+                Ok(())
+            }
+            ActionStep::SetSetting { .. }
+            | ActionStep::SetMark(_)
+            | ActionStep::KeepModifiers(_)
+            | ActionStep::Profile(_) => Ok(()),
+            ActionStep::Run(command) => {
+                // A command that can't start is the user's problem, not an output failure
+                if let Err(e) = run_command(command) {
+                    log::warn!("Could not run '{}': {}", command, e);
+                }
+                Ok(())
+            }
+            ActionStep::After { delay_ms, steps } => {
+                self.schedule.schedule(Instant::now(), *delay_ms, steps.clone());
+                Ok(())
+            }
+            ActionStep::Repeat { times, steps } => {
+                // Every emitted key still goes through send_key_action, so the
+                // configured throttle delays pace each repetition.
+                for _ in 0..(*times).min(MAX_REPEAT_TIMES) {
+                    for step in steps {
+                        self.execute_sequence_step(step, state)?;
+                    }
+                }
+                Ok(())
+            }
+            ActionStep::Press(key) => {
+                self.send_key_action(*key, Action::Press)?;
+                self.watchdog.exempt(*key);
+                if !state.held.contains(key) {
+                    state.held.push(*key);
+                }
                 Ok(())
             }
-            ActionStep::SetSetting { .. } => Ok(()),
+            ActionStep::Release(key) => {
+                state.held.retain(|k| k != key);
+                self.send_key_action(*key, Action::Release)
+            }
+            ActionStep::Hold { key, ms } => {
+                self.send_key_action(*key, Action::Press)?;
+                // Released even when aborted; the abort is reported after
+                let held = self.pause(Duration::from_millis(*ms));
+                self.send_key_action(*key, Action::Release)?;
+                held
+            }
+        }
+    }
+
+    /// Run scheduled `After(...)` steps whose delay has elapsed
+    pub fn run_due_scheduled(&mut self) -> Result<(), UInputError> {
+        self.current_output = None;
+        for steps in self.schedule.take_due(Instant::now()) {
+            self.execute_sequence(&steps)?;
+        }
+        Ok(())
+    }
+
+    /// Time until the next scheduled step is due, if any
+    pub fn next_scheduled_in(&self) -> Option<Duration> {
+        self.schedule.next_due_in(Instant::now())
+    }
+
+    /// Time until the next scheduled step or stuck-key check, if any
+    pub fn next_deadline_in(&self) -> Option<Duration> {
+        let now = Instant::now();
+        match (self.schedule.next_due_in(now), self.watchdog.next_due_in(now)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
         }
     }
 
+    /// Force-release output keys held longer than `timeout` without a repeat
+    /// (`None`, the default, turns the watchdog off).
+    ///
+    /// A real key press keeps repeating while held, so this only catches keys
+    /// a mapping pressed and never released. Keys pressed by `Press(...)`
+    /// steps are held on purpose and not watched.
+    pub fn set_stuck_key_timeout(&mut self, timeout: Option<Duration>) {
+        self.watchdog.set_timeout(timeout);
+    }
+
+    /// Release every key the stuck-key watchdog has given up on
+    pub fn release_stuck_keys(&mut self) -> Result<(), UInputError> {
+        for stuck in self.watchdog.take_stuck(Instant::now()) {
+            log::warn!(
+                "Force-releasing {} held for {}s without repeat (pressed by {})",
+                stuck.key,
+                stuck.held_for.as_secs(),
+                stuck.source
+            );
+            self.send_key_action(stuck.key, Action::Release)?;
+        }
+        Ok(())
+    }
+
+    /// Cancel all scheduled steps (e.g. when the active profile changes)
+    pub fn cancel_scheduled(&mut self) -> usize {
+        self.schedule.cancel_all()
+    }
+
     fn execute_sequence(&mut self, steps: &[ActionStep]) -> Result<(), UInputError> {
         self.debug_output_log(&format!("execute_sequence start steps={}", steps.len()));
         let has_bind = steps.iter().any(|step| matches!(step, ActionStep::Bind));
         // For non-bind sequences, release held modifiers for the whole sequence
         // to avoid compositor/app shortcuts consuming macro steps.
         // For bind sequences, keep held modifiers because bind semantics depend on them.
-        let held_modifiers = if has_bind {
+        // `KeepModifiers(...)` exempts the modifiers it names.
+        let keep: Vec<Modifier> = steps
+            .iter()
+            .filter_map(|step| match step {
+                ActionStep::KeepModifiers(modifiers) => Some(modifiers.iter().cloned()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let held_modifiers: Vec<Key> = if has_bind {
             Vec::new()
         } else {
-            self.pressed_modifiers.get_all()
+            self.pressed_modifiers
+                .get_all()
+                .into_iter()
+                .filter(|key| !keep.iter().any(|modifier| modifier.keys().contains(key)))
+                .collect()
         };
         if Self::debug_output_enabled() {
             self.debug_output_log(&format!(
@@ -435,14 +1069,26 @@ impl VirtualDevice {
             self.send_key_action(*modifier, Action::Release)?;
         }
 
-        let sequence_result = (|| -> Result<(), UInputError> {
-            let mut bind_next = false;
+        let mut state = SequenceState {
+            keep,
+            ..SequenceState::default()
+        };
+        let mut sequence_result = (|| -> Result<(), UInputError> {
             for step in steps {
-                self.execute_sequence_step(step, &mut bind_next)?;
+                self.execute_sequence_step(step, &mut state)?;
             }
             Ok(())
         })();
 
+        // A sequence never leaves keys down: release what Press(...) left held
+        for key in state.held.iter().rev() {
+            if let Err(e) = self.send_key_action(*key, Action::Release) {
+                if sequence_result.is_ok() {
+                    sequence_result = Err(e);
+                }
+            }
+        }
+
         let mut restore_error: Option<UInputError> = None;
         for modifier in &held_modifiers {
             if let Err(e) = self.send_key_action(*modifier, Action::Press) {
@@ -461,6 +1107,67 @@ impl VirtualDevice {
         }
     }
 
+    /// Mirror emitted output to the subscribers of `observer` (key overlay tools)
+    pub fn set_observer(&mut self, observer: Option<ObserverHub>) {
+        self.observer = observer;
+    }
+
+    fn mirror_output(&mut self, result: &TransformResultOutput, action: Action) {
+        let Some(observer) = self.observer.as_ref() else {
+            return;
+        };
+        match result {
+            TransformResultOutput::Passthrough(key) | TransformResultOutput::Remapped(key) => {
+                observer.publish(ObserverEvent::new(ObserverEventKind::Key, action, key.to_string()), Some(*key));
+            }
+            TransformResultOutput::ComboKey(key) if action == Action::Press => {
+                observer.publish(ObserverEvent::new(ObserverEventKind::Key, action, key.to_string()), Some(*key));
+            }
+            TransformResultOutput::Combo(combo) if action == Action::Press => {
+                observer.publish(ObserverEvent::new(ObserverEventKind::Combo, action, combo.to_string()), None);
+            }
+            TransformResultOutput::Sequence(steps) if action == Action::Press => {
+                for step in steps {
+                    match step {
+                        ActionStep::Combo(combo) => observer.publish(
+                            ObserverEvent::new(ObserverEventKind::Combo, action, combo.to_string()),
+                            None,
+                        ),
+                        ActionStep::Text(text) => observer.publish(
+                            ObserverEvent::new(ObserverEventKind::Text, action, text.clone()),
+                            None,
+                        ),
+                        ActionStep::Press(key) => observer.publish(
+                            ObserverEvent::new(ObserverEventKind::Key, Action::Press, key.to_string()),
+                            Some(*key),
+                        ),
+                        ActionStep::Hold { key, .. } => {
+                            for key_action in [Action::Press, Action::Release] {
+                                observer.publish(
+                                    ObserverEvent::new(ObserverEventKind::Key, key_action, key.to_string()),
+                                    Some(*key),
+                                );
+                            }
+                        }
+                        ActionStep::Release(key) => observer.publish(
+                            ObserverEvent::new(ObserverEventKind::Key, Action::Release, key.to_string()),
+                            Some(*key),
+                        ),
+                        _ => {}
+                    }
+                }
+            }
+            TransformResultOutput::Unicode(codepoint) if action == Action::Press => {
+                let label = char::from_u32(*codepoint).map(String::from).unwrap_or_default();
+                observer.publish(ObserverEvent::new(ObserverEventKind::Text, action, label), None);
+            }
+            TransformResultOutput::Text(text) if action == Action::Press => {
+                observer.publish(ObserverEvent::new(ObserverEventKind::Text, action, text.clone()), None);
+            }
+            _ => {}
+        }
+    }
+
     /// Process a transform result and send appropriate output
     /// 
     /// # Arguments
@@ -471,6 +1178,19 @@ impl VirtualDevice {
         result: &TransformResultOutput,
         action: Action,
     ) -> Result<(), UInputError> {
+        if action == Action::Repeat {
+            self.watchdog.refresh_all(Instant::now());
+            let dropped = match result {
+                TransformResultOutput::Passthrough(key) => !self.remap_repeat.forwards(*key, false),
+                TransformResultOutput::Remapped(key) => !self.remap_repeat.forwards(*key, true),
+                _ => false,
+            };
+            if dropped {
+                return Ok(());
+            }
+        }
+        self.current_output = Some(result.clone());
+        self.mirror_output(result, action);
         match result {
             TransformResultOutput::Passthrough(key) => {
                 // Check if this is a regular key (not a modifier) and modifiers are held
@@ -643,6 +1363,26 @@ pub enum TransformResultOutput {
     Text(String),
 }
 
+impl fmt::Display for TransformResultOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passthrough(key) => write!(f, "passthrough of {}", key),
+            Self::Remapped(key) => write!(f, "remap to {}", key),
+            Self::ComboKey(key) => write!(f, "combo output {}", key),
+            Self::Combo(combo) => write!(f, "combo {}", combo),
+            Self::Sequence(steps) => {
+                let steps: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+                write!(f, "sequence [{}]", steps.join(", "))
+            }
+            Self::Hint(hint) => write!(f, "hint {}", hint),
+            Self::Suppress => write!(f, "suppress"),
+            Self::Suspend => write!(f, "suspend"),
+            Self::Unicode(codepoint) => write!(f, "Unicode(U+{:04X})", codepoint),
+            Self::Text(text) => write!(f, "text {:?}", text),
+        }
+    }
+}
+
 impl TransformResultOutput {
     /// Create from the transform engine's TransformResult
     pub fn from_transform_result(result: &crate::transform::TransformResult) -> Self {
@@ -805,4 +1545,16 @@ mod tests {
         assert_eq!(VirtualDevice::ascii_key_and_shift('_'), Some((Key::from(12), true)));
         assert_eq!(VirtualDevice::ascii_key_and_shift('!'), Some((Key::from(2), true)));
     }
+
+    #[test]
+    fn test_text_key_and_shift_follows_layout() {
+        let symbols = "key <K16> { [ a, A ] }; key <K3> { [ eacute, 2 ] };";
+        let azerty = KeyboardLayout::from_keymap("fr", &crate::layout::test_keymap(symbols)).unwrap();
+        let layout = Some(&azerty);
+        assert_eq!(VirtualDevice::text_key_and_shift(layout, 'A'), Some((Key::from(16), true)));
+        assert_eq!(VirtualDevice::text_key_and_shift(layout, 'é'), Some((Key::from(3), false)));
+        assert_eq!(VirtualDevice::text_key_and_shift(layout, '\n'), Some((Key::from(28), false)));
+        assert_eq!(VirtualDevice::text_key_and_shift(layout, 'z'), None);
+        assert_eq!(VirtualDevice::text_key_and_shift(None, 'a'), Some((Key::from(30), false)));
+    }
 }
@@ -0,0 +1,189 @@
+// Keyrs Stuck Key Watchdog
+// Force-release output keys held far longer than any real key press
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{Action, Key};
+
+/// An output key the watchdog gave up on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StuckKey {
+    pub key: Key,
+    /// Time since its press or last repeat
+    pub held_for: Duration,
+    /// The output that pressed it, for the log
+    pub source: String,
+}
+
+#[derive(Debug, Clone)]
+struct HeldKey {
+    since: Instant,
+    source: String,
+}
+
+/// Tracks when each emitted key went down.
+///
+/// A key the user really holds keeps repeating, which resets its timer; a key
+/// left down by a logic bug goes quiet and is reported by `take_stuck`. Only
+/// keyboard keys are watched: mouse buttons and consumer keys never repeat,
+/// so a long hold of one says nothing.
+#[derive(Debug, Default)]
+pub struct StuckKeyWatchdog {
+    timeout: Option<Duration>,
+    held: HashMap<Key, HeldKey>,
+}
+
+impl StuckKeyWatchdog {
+    /// Create a watchdog; `None` disables it (the default)
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Change the timeout; `None` disables the watchdog and forgets held keys
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        if timeout.is_none() {
+            self.held.clear();
+        }
+    }
+
+    /// Record an emitted key event; `source` is only evaluated on press
+    pub fn track(&mut self, key: Key, action: Action, now: Instant, source: impl FnOnce() -> String) {
+        if self.timeout.is_none() || !repeats(key) {
+            return;
+        }
+        match action {
+            Action::Press => {
+                self.held.insert(key, HeldKey { since: now, source: source() });
+            }
+            Action::Release => {
+                self.held.remove(&key);
+            }
+            Action::Repeat => self.refresh(key, now),
+        }
+    }
+
+    /// Reset the timer of a held key (a repeat that was not written still
+    /// shows the key is held)
+    pub fn refresh(&mut self, key: Key, now: Instant) {
+        if let Some(held) = self.held.get_mut(&key) {
+            held.since = now;
+        }
+    }
+
+    /// Reset the timer of every held key. A physical key still repeating
+    /// means the user is holding keys, and the output keys held with it
+    /// (a mod-tap's modifier, a key whose repeats are suppressed) stay.
+    pub fn refresh_all(&mut self, now: Instant) {
+        for held in self.held.values_mut() {
+            held.since = now;
+        }
+    }
+
+    /// Stop watching a key held on purpose (an explicit `Press(...)` step)
+    /// until it is pressed again
+    pub fn exempt(&mut self, key: Key) {
+        self.held.remove(&key);
+    }
+
+    /// Remove and return every key held past the timeout
+    pub fn take_stuck(&mut self, now: Instant) -> Vec<StuckKey> {
+        let Some(timeout) = self.timeout else {
+            return Vec::new();
+        };
+        let stuck: Vec<Key> = self
+            .held
+            .iter()
+            .filter(|(_, held)| now.saturating_duration_since(held.since) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut stuck: Vec<StuckKey> = stuck
+            .into_iter()
+            .filter_map(|key| {
+                let held = self.held.remove(&key)?;
+                Some(StuckKey {
+                    key,
+                    held_for: now.saturating_duration_since(held.since),
+                    source: held.source,
+                })
+            })
+            .collect();
+        stuck.sort_by_key(|stuck| stuck.key.code());
+        stuck
+    }
+
+    /// Time until the next held key times out (zero if one already has)
+    pub fn next_due_in(&self, now: Instant) -> Option<Duration> {
+        let timeout = self.timeout?;
+        self.held
+            .values()
+            .map(|held| (held.since + timeout).saturating_duration_since(now))
+            .min()
+    }
+
+    /// Forget every held key
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+/// Whether the kernel repeats `key` while it is held: keyboard keys, not buttons
+fn repeats(key: Key) -> bool {
+    key.code() < 0x100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stuck_key_watchdog() {
+        let start = Instant::now();
+        let ctrl = Key::from(29);
+        let a = Key::from(30);
+        let mut watchdog = StuckKeyWatchdog::new(Some(Duration::from_secs(30)));
+        assert_eq!(watchdog.next_due_in(start), None);
+
+        watchdog.track(ctrl, Action::Press, start, || "remap to LEFT_CTRL".to_string());
+        watchdog.track(a, Action::Press, start, || "passthrough".to_string());
+        watchdog.track(a, Action::Repeat, start + Duration::from_secs(20), String::new);
+        assert_eq!(watchdog.next_due_in(start + Duration::from_secs(20)), Some(Duration::from_secs(10)));
+
+        // Only the key that went quiet is reported, once
+        let stuck = watchdog.take_stuck(start + Duration::from_secs(31));
+        assert_eq!(
+            stuck,
+            vec![StuckKey {
+                key: ctrl,
+                held_for: Duration::from_secs(31),
+                source: "remap to LEFT_CTRL".to_string(),
+            }]
+        );
+        assert!(watchdog.take_stuck(start + Duration::from_secs(31)).is_empty());
+
+        watchdog.track(a, Action::Release, start + Duration::from_secs(40), String::new);
+        assert!(watchdog.take_stuck(start + Duration::from_secs(100)).is_empty());
+
+        // Held on purpose, or a button that never repeats: not watched
+        let left_button = Key::from(0x110);
+        watchdog.track(ctrl, Action::Press, start, String::new);
+        watchdog.exempt(ctrl);
+        watchdog.track(left_button, Action::Press, start, String::new);
+        assert!(watchdog.take_stuck(start + Duration::from_secs(3600)).is_empty());
+
+        // Any physical repeat keeps every held key alive
+        watchdog.track(a, Action::Press, start, String::new);
+        watchdog.refresh_all(start + Duration::from_secs(25));
+        assert!(watchdog.take_stuck(start + Duration::from_secs(50)).is_empty());
+        assert_eq!(watchdog.take_stuck(start + Duration::from_secs(55)).len(), 1);
+
+        // Disabled, nothing is tracked
+        let mut off = StuckKeyWatchdog::new(None);
+        off.track(ctrl, Action::Press, start, || unreachable!());
+        assert!(off.take_stuck(start + Duration::from_secs(3600)).is_empty());
+    }
+}
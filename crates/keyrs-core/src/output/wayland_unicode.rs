@@ -0,0 +1,290 @@
+// Keyrs Wayland Unicode Output
+// Commits Unicode text through a zwp_virtual_keyboard_v1 keymap instead of Ctrl+Shift+U
+
+use std::io::Write;
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+use std::time::Instant;
+
+use wayland_client::{
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_keyboard, wl_registry, wl_seat},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+
+#[allow(dead_code, non_camel_case_types, unused_imports, missing_docs, clippy::all)]
+mod protocol {
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/virtual-keyboard-unstable-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/virtual-keyboard-unstable-v1.xml");
+}
+
+use protocol::{zwp_virtual_keyboard_manager_v1, zwp_virtual_keyboard_v1};
+
+/// How Unicode and non-ASCII text output is produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnicodeBackend {
+    /// GTK/IBus Ctrl+Shift+U hex entry through the uinput device (default)
+    #[default]
+    Compose,
+    /// Wayland virtual keyboard with a keymap carrying the characters
+    VirtualKeyboard,
+}
+
+impl UnicodeBackend {
+    /// Parse a backend name as used in `[output].unicode`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "compose" | "ctrl_shift_u" => Some(Self::Compose),
+            "virtual_keyboard" | "wayland" => Some(Self::VirtualKeyboard),
+            _ => None,
+        }
+    }
+}
+
+/// Errors from the Wayland Unicode backend
+#[derive(Debug, thiserror::Error)]
+pub enum WaylandUnicodeError {
+    #[error("Wayland connection failed: {0}")]
+    Connect(String),
+
+    #[error("Compositor does not support {0}")]
+    Unsupported(&'static str),
+
+    #[error("Keymap upload failed: {0}")]
+    Keymap(String),
+
+    #[error("Wayland dispatch failed: {0}")]
+    Dispatch(String),
+}
+
+/// First evdev keycode handed out to characters (xkb keycode 9)
+const FIRST_KEYCODE: u32 = 1;
+/// Characters mapped per uploaded keymap (stays below xkb's 255 keycode limit)
+const KEYMAP_CHUNK: usize = 200;
+
+/// Build an xkb keymap that maps consecutive keycodes to `chars`
+pub fn build_keymap(chars: &[char]) -> String {
+    let mut keymap = String::from("xkb_keymap {\nxkb_keycodes \"keyrs\" {\nminimum = 8;\n");
+    keymap.push_str(&format!("maximum = {};\n", chars.len() as u32 + FIRST_KEYCODE + 8));
+    for index in 0..chars.len() {
+        keymap.push_str(&format!("<K{}> = {};\n", index + 1, index as u32 + FIRST_KEYCODE + 8));
+    }
+    keymap.push_str("};\n");
+    keymap.push_str("xkb_types \"keyrs\" { include \"complete\" };\n");
+    keymap.push_str("xkb_compatibility \"keyrs\" { include \"complete\" };\n");
+    keymap.push_str("xkb_symbols \"keyrs\" {\n");
+    for (index, ch) in chars.iter().enumerate() {
+        keymap.push_str(&format!("key <K{}> {{[ U{:04X} ]}};\n", index + 1, *ch as u32));
+    }
+    keymap.push_str("};\n};\n");
+    keymap
+}
+
+/// Split text into chunks of at most `KEYMAP_CHUNK` distinct characters,
+/// returning each chunk's character table and the table index of every char
+fn plan_chunks(text: &str) -> Vec<(Vec<char>, Vec<usize>)> {
+    let mut chunks = Vec::new();
+    let mut table: Vec<char> = Vec::new();
+    let mut indices = Vec::new();
+    for ch in text.chars() {
+        let index = match table.iter().position(|c| *c == ch) {
+            Some(index) => index,
+            None => {
+                if table.len() == KEYMAP_CHUNK {
+                    chunks.push((std::mem::take(&mut table), std::mem::take(&mut indices)));
+                }
+                table.push(ch);
+                table.len() - 1
+            }
+        };
+        indices.push(index);
+    }
+    if !indices.is_empty() {
+        chunks.push((table, indices));
+    }
+    chunks
+}
+
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _globals: &GlobalListContents,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _seat: &wl_seat::WlSeat,
+        _event: wl_seat::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _manager: &zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+        _event: zwp_virtual_keyboard_manager_v1::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _keyboard: &zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+        _event: zwp_virtual_keyboard_v1::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Unicode output through a Wayland virtual keyboard.
+///
+/// Each string is typed by uploading a keymap whose keys produce exactly the
+/// needed characters, then tapping those keys. Works in any client that reads
+/// the keymap (Qt, Electron, terminals), unlike Ctrl+Shift+U.
+pub struct WaylandUnicodeOutput {
+    event_queue: EventQueue<State>,
+    keyboard: zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+    started: Instant,
+}
+
+impl WaylandUnicodeOutput {
+    /// Connect to the compositor and create a virtual keyboard on the first seat
+    pub fn connect() -> Result<Self, WaylandUnicodeError> {
+        let connection =
+            Connection::connect_to_env().map_err(|e| WaylandUnicodeError::Connect(e.to_string()))?;
+        let (globals, mut event_queue) = registry_queue_init::<State>(&connection)
+            .map_err(|e| WaylandUnicodeError::Connect(e.to_string()))?;
+        let qhandle = event_queue.handle();
+
+        let seat: wl_seat::WlSeat = globals
+            .bind(&qhandle, 1..=7, ())
+            .map_err(|_| WaylandUnicodeError::Unsupported("wl_seat"))?;
+        let manager: zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1 = globals
+            .bind(&qhandle, 1..=1, ())
+            .map_err(|_| WaylandUnicodeError::Unsupported("zwp_virtual_keyboard_manager_v1"))?;
+        let keyboard = manager.create_virtual_keyboard(&seat, &qhandle, ());
+
+        event_queue
+            .roundtrip(&mut State)
+            .map_err(|e| WaylandUnicodeError::Dispatch(e.to_string()))?;
+
+        Ok(Self {
+            event_queue,
+            keyboard,
+            started: Instant::now(),
+        })
+    }
+
+    fn timestamp(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    fn upload_keymap(&mut self, chars: &[char]) -> Result<(), WaylandUnicodeError> {
+        let mut keymap = build_keymap(chars).into_bytes();
+        keymap.push(0);
+
+        let name = c"keyrs-keymap";
+        let raw = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+        if raw < 0 {
+            return Err(WaylandUnicodeError::Keymap(std::io::Error::last_os_error().to_string()));
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        std::fs::File::from(fd.try_clone().map_err(|e| WaylandUnicodeError::Keymap(e.to_string()))?)
+            .write_all(&keymap)
+            .map_err(|e| WaylandUnicodeError::Keymap(e.to_string()))?;
+
+        self.keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1.into(),
+            fd.as_fd(),
+            keymap.len() as u32,
+        );
+        Ok(())
+    }
+
+    /// Type `text` through the virtual keyboard
+    pub fn send_text(&mut self, text: &str) -> Result<(), WaylandUnicodeError> {
+        for (chars, indices) in plan_chunks(text) {
+            self.upload_keymap(&chars)?;
+            for index in indices {
+                let key = index as u32 + FIRST_KEYCODE;
+                let time = self.timestamp();
+                self.keyboard.key(time, key, wl_keyboard::KeyState::Pressed.into());
+                self.keyboard.key(time, key, wl_keyboard::KeyState::Released.into());
+            }
+        }
+        self.event_queue
+            .roundtrip(&mut State)
+            .map_err(|e| WaylandUnicodeError::Dispatch(e.to_string()))?;
+        Ok(())
+    }
+}
+
+impl Drop for WaylandUnicodeOutput {
+    fn drop(&mut self) {
+        self.keyboard.destroy();
+        let _ = self.event_queue.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_backend_from_name() {
+        assert_eq!(UnicodeBackend::from_name("compose"), Some(UnicodeBackend::Compose));
+        assert_eq!(UnicodeBackend::from_name("Wayland"), Some(UnicodeBackend::VirtualKeyboard));
+        assert_eq!(UnicodeBackend::from_name("ibus"), None);
+    }
+
+    #[test]
+    fn test_build_keymap_maps_chars_to_consecutive_keycodes() {
+        let keymap = build_keymap(&['é', '→']);
+        assert!(keymap.contains("maximum = 11;"));
+        assert!(keymap.contains("<K1> = 9;"));
+        assert!(keymap.contains("<K2> = 10;"));
+        assert!(keymap.contains("key <K1> {[ U00E9 ]};"));
+        assert!(keymap.contains("key <K2> {[ U2192 ]};"));
+    }
+
+    #[test]
+    fn test_plan_chunks_reuses_keys_and_splits_large_tables() {
+        let chunks = plan_chunks("abca");
+        assert_eq!(chunks, vec![(vec!['a', 'b', 'c'], vec![0, 1, 2, 0])]);
+
+        let text: String = (0..KEYMAP_CHUNK as u32 + 5)
+            .map(|i| char::from_u32(0x4E00 + i).unwrap())
+            .collect();
+        let chunks = plan_chunks(&text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].0.len(), KEYMAP_CHUNK);
+        assert_eq!(chunks[1].1, vec![0, 1, 2, 3, 4]);
+        assert!(plan_chunks("").is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+// Keyrs Output Thread
+// Owns the virtual device so slow output never stalls event processing
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, SendTimeoutError, Sender, TrySendError};
+
+use super::combo::ComboStyle;
+use super::delays::OutputDelays;
+use super::uinput::{TransformResultOutput, UInputError, VirtualDevice};
+use crate::Action;
+
+/// Output requests buffered before further output is dropped
+pub const OUTPUT_QUEUE_CAPACITY: usize = 1024;
+
+/// Queue room only key releases may use, so a flood of other output never
+/// leaves a key held
+const RELEASE_RESERVE: usize = 64;
+
+/// Queue length from which forwarded frames (pointer motion, scrolling)
+/// are dropped, before any key output
+const FORWARD_LIMIT: usize = OUTPUT_QUEUE_CAPACITY / 2;
+
+/// How long queuing a release waits for room before the output is aborted
+const RELEASE_WAIT: Duration = Duration::from_millis(50);
+
+/// Work sent to the output thread, executed in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputCommand {
+    /// Emit a transform result with the triggering key action
+    Emit(TransformResultOutput, Action),
+    /// Drop pending `After(...)` steps
+    CancelScheduled,
+    /// Release every key the virtual device holds
+    ReleaseAll,
+    /// Re-emit a frame of non-key input events, as (type, code, value) triples
+    Forward(Vec<(u16, u16, i32)>),
+    /// Use a keymap's modifier order and delay for the following combos
+    SetComboStyle(ComboStyle),
+    /// Use these key delays for the following output
+    SetDelays(OutputDelays),
+}
+
+/// Dedicated thread writing to the virtual device.
+///
+/// `Text(...)`, `Delay(...)` and similar slow output run here, so the event
+/// loop keeps reading and transforming keys meanwhile. Deferred `After(...)`
+/// steps and the stuck-key watchdog are timed by this thread as well.
+///
+/// Queuing does not block: with the queue filling up, forwarded frames are
+/// dropped first, then other output, and both are logged. Key releases are
+/// never dropped: they have room of their own, and should that run out
+/// they wait briefly, then abort the output, which releases every key.
+/// `abort` reaches the thread out of band, ahead of whatever is queued.
+pub struct OutputThread {
+    sender: Sender<OutputCommand>,
+    handle: JoinHandle<VirtualDevice>,
+    /// Shared with the device: raised by `abort`, lowered by the thread once handled
+    abort: Arc<AtomicBool>,
+    /// Commands dropped since the queue last had room
+    dropped: AtomicUsize,
+}
+
+impl OutputThread {
+    /// Move `device` to a new output thread
+    pub fn spawn(mut device: VirtualDevice) -> std::io::Result<Self> {
+        let (sender, receiver) = bounded(OUTPUT_QUEUE_CAPACITY);
+        let abort = Arc::new(AtomicBool::new(false));
+        device.set_abort_flag(abort.clone());
+        let thread_abort = abort.clone();
+        let handle = std::thread::Builder::new()
+            .name("keyrs-output".to_string())
+            .spawn(move || run(device, receiver, thread_abort))?;
+        Ok(Self {
+            sender,
+            handle,
+            abort,
+            dropped: AtomicUsize::new(0),
+        })
+    }
+
+    /// Queue a command without waiting; dropped with a warning while the
+    /// queue is full, except for key releases
+    pub fn send(&self, command: OutputCommand) {
+        let release = matches!(
+            command,
+            OutputCommand::Emit(_, Action::Release) | OutputCommand::ReleaseAll
+        );
+        let limit = match command {
+            _ if release => OUTPUT_QUEUE_CAPACITY,
+            OutputCommand::Forward(_) => FORWARD_LIMIT,
+            _ => OUTPUT_QUEUE_CAPACITY - RELEASE_RESERVE,
+        };
+        if self.sender.len() >= limit {
+            self.drop_command();
+            return;
+        }
+        let result = match self.sender.try_send(command) {
+            Err(TrySendError::Full(command)) if release => match self.sender.send_timeout(command, RELEASE_WAIT) {
+                Ok(()) => Ok(()),
+                Err(SendTimeoutError::Timeout(_)) => {
+                    log::error!("Output queue still full after {:?}; aborting output to release every key", RELEASE_WAIT);
+                    self.abort();
+                    return;
+                }
+                Err(SendTimeoutError::Disconnected(command)) => Err(TrySendError::Disconnected(command)),
+            },
+            result => result,
+        };
+        match result {
+            Ok(()) => {
+                let dropped = self.dropped.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    log::warn!("Output queue has room again; {} output command(s) were dropped", dropped);
+                }
+            }
+            Err(TrySendError::Full(_)) => self.drop_command(),
+            Err(TrySendError::Disconnected(_)) => {
+                log::error!("Output thread has stopped; dropping output");
+            }
+        }
+    }
+
+    /// Count a command dropped for lack of room, warning on the first
+    fn drop_command(&self) {
+        if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+            log::warn!("Output queue full; dropping output until it drains");
+        }
+    }
+
+    /// Cut the output in progress short, drop everything queued and release
+    /// every held key. Takes effect between steps, without waiting for the
+    /// queue to drain (panic chord, shutdown on error).
+    pub fn abort(&self) {
+        self.abort.store(true, Ordering::SeqCst);
+        // Wakes an idle thread; a busy one sees the flag at its next step
+        let _ = self.sender.try_send(OutputCommand::ReleaseAll);
+    }
+
+    /// Queue a transform result
+    pub fn emit(&self, output: TransformResultOutput, action: Action) {
+        self.send(OutputCommand::Emit(output, action));
+    }
+
+    /// Queue cancellation of pending `After(...)` steps
+    pub fn cancel_scheduled(&self) {
+        self.send(OutputCommand::CancelScheduled);
+    }
+
+    /// Queue a release of every held key
+    pub fn release_all(&self) {
+        self.send(OutputCommand::ReleaseAll);
+    }
+
+    /// Queue a frame of non-key events read from a grabbed device
+    pub fn forward(&self, frame: Vec<(u16, u16, i32)>) {
+        self.send(OutputCommand::Forward(frame));
+    }
+
+    /// Queue a combo style change, applied before the output queued after it
+    pub fn set_combo_style(&self, style: ComboStyle) {
+        self.send(OutputCommand::SetComboStyle(style));
+    }
+
+    /// Queue a change of key delays, applied before the output queued after it
+    pub fn set_delays(&self, delays: OutputDelays) {
+        self.send(OutputCommand::SetDelays(delays));
+    }
+
+    /// Finish the queued output and hand the device back
+    pub fn join(self) -> Option<VirtualDevice> {
+        drop(self.sender);
+        match self.handle.join() {
+            Ok(device) => Some(device),
+            Err(_) => {
+                log::error!("Output thread panicked");
+                None
+            }
+        }
+    }
+}
+
+fn run(mut device: VirtualDevice, receiver: Receiver<OutputCommand>, abort: Arc<AtomicBool>) -> VirtualDevice {
+    loop {
+        // Sleep until the next command, scheduled step or stuck-key check, whichever is first
+        let command = match device.next_deadline_in() {
+            Some(due) => match receiver.recv_timeout(due) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            },
+        };
+
+        if abort.load(Ordering::SeqCst) {
+            abort_output(&mut device, &receiver, &abort);
+            continue;
+        }
+
+        match command {
+            Some(OutputCommand::Emit(output, action)) => match device.process_transform_result(&output, action) {
+                Ok(()) | Err(UInputError::Aborted) => {}
+                Err(e) => log::error!("Error sending output: {}", e),
+            },
+            Some(OutputCommand::CancelScheduled) => {
+                let cancelled = device.cancel_scheduled();
+                if cancelled > 0 {
+                    log::debug!("Cancelled {} scheduled output step(s) on context change", cancelled);
+                }
+            }
+            Some(OutputCommand::ReleaseAll) => {
+                if let Err(e) = device.release_all() {
+                    log::error!("Error releasing keys: {}", e);
+                }
+            }
+            Some(OutputCommand::SetComboStyle(style)) => device.set_combo_style(style),
+            Some(OutputCommand::SetDelays(delays)) => device.set_delays(delays),
+            Some(OutputCommand::Forward(frame)) => {
+                if let Err(e) = device.forward_frame(&frame) {
+                    log::debug!("Error forwarding input events: {}", e);
+                }
+            }
+            None => {}
+        }
+
+        if abort.load(Ordering::SeqCst) {
+            abort_output(&mut device, &receiver, &abort);
+            continue;
+        }
+
+        match device.run_due_scheduled() {
+            Ok(()) | Err(UInputError::Aborted) => {}
+            Err(e) => log::error!("Error sending scheduled output: {}", e),
+        }
+        if let Err(e) = device.release_stuck_keys() {
+            log::error!("Error releasing stuck keys: {}", e);
+        }
+    }
+    device
+}
+
+/// Drop the queued and scheduled output, release every key and lower the flag
+fn abort_output(device: &mut VirtualDevice, receiver: &Receiver<OutputCommand>, abort: &AtomicBool) {
+    let dropped = receiver.try_iter().count();
+    let cancelled = device.cancel_scheduled();
+    abort.store(false, Ordering::SeqCst);
+    if let Err(e) = device.release_all() {
+        log::error!("Error releasing keys: {}", e);
+    }
+    log::warn!(
+        "Output aborted: dropped {} queued command(s) and {} scheduled step(s)",
+        dropped,
+        cancelled
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::ActionStep;
+    use crate::Key;
+
+    #[test]
+    fn test_output_thread_returns_device() {
+        let (device, events) = VirtualDevice::recording();
+        let thread = OutputThread::spawn(device).expect("spawn output thread");
+        thread.emit(TransformResultOutput::Passthrough(Key::from(30)), Action::Press);
+        thread.cancel_scheduled();
+        thread.release_all();
+        let device = thread.join().expect("device handed back");
+        assert_eq!(device.pressed_key_count(), 0);
+        let keys: Vec<(u16, i32)> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, kind, _, _)| *kind == 1)
+            .map(|(_, _, code, value)| (*code, *value))
+            .collect();
+        assert_eq!(keys, vec![(30, 1), (30, 0)]);
+    }
+
+    #[test]
+    fn test_abort_cuts_delay_and_drops_queue() {
+        let (device, events) = VirtualDevice::recording();
+        let thread = OutputThread::spawn(device).expect("spawn output thread");
+        thread.emit(TransformResultOutput::Passthrough(Key::from(30)), Action::Press);
+        thread.emit(TransformResultOutput::Sequence(vec![ActionStep::DelayMs(60_000)]), Action::Press);
+        // Far more than fits behind the delay: sending must not block
+        for _ in 0..OUTPUT_QUEUE_CAPACITY * 2 {
+            thread.emit(TransformResultOutput::Passthrough(Key::from(31)), Action::Press);
+        }
+        thread.abort();
+        let device = thread.join().expect("device handed back");
+        assert_eq!(device.pressed_key_count(), 0);
+        let events = events.lock().unwrap();
+        assert!(!events.iter().any(|(_, kind, code, _)| *kind == 1 && *code == 31));
+    }
+
+    #[test]
+    fn test_full_queue_keeps_releases() {
+        let (device, events) = VirtualDevice::recording();
+        let thread = OutputThread::spawn(device).expect("spawn output thread");
+        thread.emit(TransformResultOutput::Sequence(vec![ActionStep::DelayMs(200)]), Action::Press);
+        // Motion is dropped first, then key output, leaving room for releases
+        for _ in 0..OUTPUT_QUEUE_CAPACITY {
+            thread.forward(vec![(2, 0, 1)]);
+        }
+        assert!(thread.sender.len() <= FORWARD_LIMIT);
+        for _ in 0..OUTPUT_QUEUE_CAPACITY {
+            thread.emit(TransformResultOutput::Passthrough(Key::from(30)), Action::Press);
+        }
+        assert!(thread.sender.len() <= OUTPUT_QUEUE_CAPACITY - RELEASE_RESERVE);
+        thread.emit(TransformResultOutput::Passthrough(Key::from(30)), Action::Release);
+        let device = thread.join().expect("device handed back");
+        assert_eq!(device.pressed_key_count(), 0);
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|(_, kind, code, value)| *kind == 1 && *code == 30 && *value == 0));
+    }
+}
\ No newline at end of file
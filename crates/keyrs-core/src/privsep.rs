@@ -0,0 +1,795 @@
+// Keyrs Privilege Separation
+// Device I/O in a small privileged helper, remapping in an unprivileged process
+//
+// The input helper (`keyrs --input-helper`) is the only process that opens
+// /dev/input and /dev/uinput. It grabs the devices, sends their events over
+// a Unix socket and writes the events it gets back to its virtual devices.
+// The remapping process (`keyrs --connect-helper`) has the config, window
+// provider and settings, but no raw input access of its own.
+//
+// Run per user, the socket is owner-only. Run as a system service, the
+// socket belongs to a dedicated group and every connection's credentials
+// are checked against it. Either way the remapping process can only write
+// key, sync and pointer motion events.
+
+#![cfg(feature = "pure-rust")]
+
+use std::ffi::{CStr, CString};
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use evdev::{EventType, InputEvent};
+
+use crate::event::{Epoll, EventLoop, EventLoopError, EventLoopResult, InputSource, PolledEvent, Waker};
+use crate::input::{KeyboardDeviceInfo, LockState};
+use crate::output::{OutputDeviceKind, VirtualDevice};
+use crate::runtime::{bind_socket, remove_socket, runtime_path, socket_id, SocketId};
+
+/// How long either side waits on the other before giving up on it
+pub const HELPER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Socket of a helper running as a system service (`RuntimeDirectory=keyrs`)
+pub const SYSTEM_SOCKET_PATH: &str = "/run/keyrs/input.sock";
+
+/// A message from the input helper, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HelperMessage {
+    /// `devices <name>\t<name>...`: devices read, on connect and whenever they change
+    Devices(Vec<String>),
+    /// `locks <num> <caps>`: lock state shown on the keyboard LEDs, on connect
+    Locks(LockState),
+    /// `event <type> <code> <value> <device>`: an event of a grabbed device
+    Event {
+        device: String,
+        event_type: u16,
+        code: u16,
+        value: i32,
+    },
+}
+
+/// A command to the input helper, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HelperCommand {
+    /// `emit <kind> <type>:<code>:<value>...`: write events to a virtual device
+    Emit(OutputDeviceKind, Vec<(u16, u16, i32)>),
+    /// `pause <bool>`: release the devices and drop their events, or grab them again
+    Pause(bool),
+    /// `leds <num> <caps>`: show a lock state on the keyboards' LEDs
+    Leds(LockState),
+}
+
+/// Device names go on one line, tab-separated
+fn clean_name(name: &str) -> String {
+    name.replace(['\t', '\n', '\r'], " ")
+}
+
+fn parse_number<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+    let word = word.ok_or_else(|| "missing argument".to_string())?;
+    word.parse().map_err(|_| format!("'{}' is not a number", word))
+}
+
+fn parse_flag(word: Option<&str>) -> Result<bool, String> {
+    match word {
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(word) => Err(format!("'{}' is not a boolean", word)),
+        None => Err("missing argument".to_string()),
+    }
+}
+
+fn parse_locks<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<LockState, String> {
+    Ok(LockState {
+        num_lock: parse_flag(words.next())?,
+        caps_lock: parse_flag(words.next())?,
+    })
+}
+
+impl HelperMessage {
+    /// Parse a message line
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "devices" if rest.is_empty() => Ok(Self::Devices(Vec::new())),
+            "devices" => Ok(Self::Devices(rest.split('\t').map(str::to_string).collect())),
+            "locks" => Ok(Self::Locks(parse_locks(rest.split_whitespace())?)),
+            "event" => {
+                let mut words = rest.splitn(4, ' ');
+                Ok(Self::Event {
+                    event_type: parse_number(words.next())?,
+                    code: parse_number(words.next())?,
+                    value: parse_number(words.next())?,
+                    device: words.next().unwrap_or_default().to_string(),
+                })
+            }
+            _ => Err(format!("unknown message '{}'", command)),
+        }
+    }
+
+    /// Encode as a message line
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Devices(names) if names.is_empty() => "devices\n".to_string(),
+            Self::Devices(names) => format!(
+                "devices {}\n",
+                names.iter().map(|name| clean_name(name)).collect::<Vec<_>>().join("\t")
+            ),
+            Self::Locks(state) => format!("locks {} {}\n", state.num_lock, state.caps_lock),
+            Self::Event {
+                device,
+                event_type,
+                code,
+                value,
+            } => format!("event {} {} {} {}\n", event_type, code, value, clean_name(device)),
+        }
+    }
+}
+
+impl HelperCommand {
+    /// Parse a command line
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("emit") => {
+                let kind = words
+                    .next()
+                    .and_then(OutputDeviceKind::from_name)
+                    .ok_or_else(|| "unknown device kind".to_string())?;
+                let events = words
+                    .map(|event| {
+                        let mut parts = event.split(':');
+                        let triple = (
+                            parse_number(parts.next())?,
+                            parse_number(parts.next())?,
+                            parse_number(parts.next())?,
+                        );
+                        match parts.next() {
+                            None => Ok(triple),
+                            Some(_) => Err(format!("bad event '{}'", event)),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                if let Some((event_type, code, _)) = events.iter().find(|event| !emit_allowed(kind, **event)) {
+                    return Err(format!("event {}:{} may not be emitted on the {}", event_type, code, kind.name()));
+                }
+                Ok(Self::Emit(kind, events))
+            }
+            Some("pause") => Ok(Self::Pause(parse_flag(words.next())?)),
+            Some("leds") => Ok(Self::Leds(parse_locks(words)?)),
+            Some(command) => Err(format!("unknown command '{}'", command)),
+            None => Err("empty command".to_string()),
+        }
+    }
+
+    /// Encode as a command line
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Emit(kind, events) => {
+                let mut line = format!("emit {}", kind.name());
+                for (event_type, code, value) in events {
+                    line.push_str(&format!(" {}:{}:{}", event_type, code, value));
+                }
+                line.push('\n');
+                line
+            }
+            Self::Pause(paused) => format!("pause {}\n", paused),
+            Self::Leds(state) => format!("leds {} {}\n", state.num_lock, state.caps_lock),
+        }
+    }
+}
+
+/// Whether a remapping process may have the helper write `event` to the
+/// `kind` device: keys (press, release, repeat) and sync everywhere,
+/// relative motion on the mouse. Everything else the helper forwards itself.
+fn emit_allowed(kind: OutputDeviceKind, (event_type, _, value): (u16, u16, i32)) -> bool {
+    match EventType(event_type) {
+        EventType::SYNCHRONIZATION => true,
+        EventType::KEY => (0..=2).contains(&value),
+        EventType::RELATIVE => kind == OutputDeviceKind::Mouse,
+        _ => false,
+    }
+}
+
+/// Who may connect to the input helper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HelperAccess {
+    /// The helper's own user only, on an owner-only socket (per-user setup)
+    #[default]
+    Owner,
+    /// Members of this group, on a socket owned by it with mode 0660 (a
+    /// helper running as a system service)
+    Group(u32),
+}
+
+impl HelperAccess {
+    /// Access for the members of the group `name`
+    pub fn group(name: &str) -> std::io::Result<Self> {
+        group_id(name).map(Self::Group)
+    }
+
+    /// Whether a peer with these credentials may connect
+    pub fn allows(self, uid: u32, gid: u32) -> bool {
+        let own = unsafe { libc::geteuid() };
+        match self {
+            Self::Owner => uid == own,
+            Self::Group(group) => uid == own || uid == 0 || gid == group || user_in_group(uid, group),
+        }
+    }
+}
+
+/// uid and gid of the process at the other end of `stream` (`SO_PEERCRED`)
+fn peer_credentials(stream: &UnixStream) -> std::io::Result<(u32, u32)> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((cred.uid, cred.gid))
+}
+
+/// Call a `get*_r` lookup with a growing buffer; `None` when there is no entry
+fn lookup<T>(mut call: impl FnMut(&mut Vec<libc::c_char>) -> (libc::c_int, Option<T>)) -> std::io::Result<Option<T>> {
+    let mut buf = vec![0; 1024];
+    loop {
+        match call(&mut buf) {
+            (0, found) => return Ok(found),
+            (libc::ERANGE, _) if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            (code, _) => return Err(std::io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+/// gid of the group `name`
+fn group_id(name: &str) -> std::io::Result<u32> {
+    let name_c = CString::new(name).map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+    let gid = lookup(|buf| {
+        let mut group: libc::group = unsafe { std::mem::zeroed() };
+        let mut found: *mut libc::group = std::ptr::null_mut();
+        let code = unsafe { libc::getgrnam_r(name_c.as_ptr(), &mut group, buf.as_mut_ptr(), buf.len(), &mut found) };
+        (code, (!found.is_null()).then_some(group.gr_gid))
+    })?;
+    gid.ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, format!("no group named '{}'", name)))
+}
+
+/// Whether the user `uid` is a member of `gid` in the group database
+fn user_in_group(uid: u32, gid: u32) -> bool {
+    let user = lookup(|buf| {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found: *mut libc::passwd = std::ptr::null_mut();
+        let code = unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found) };
+        let user = (!found.is_null()).then(|| (unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned(), passwd.pw_gid));
+        (code, user)
+    });
+    let Ok(Some((name, primary))) = user else {
+        return false;
+    };
+    if primary == gid {
+        return true;
+    }
+    let mut groups: Vec<libc::gid_t> = vec![0; 64];
+    loop {
+        let mut count = groups.len() as libc::c_int;
+        let result = unsafe { libc::getgrouplist(name.as_ptr(), primary, groups.as_mut_ptr(), &mut count) };
+        if result >= 0 {
+            return groups[..count as usize].contains(&gid);
+        }
+        if count as usize <= groups.len() {
+            return false;
+        }
+        groups.resize(count as usize, 0);
+    }
+}
+
+/// Append whatever `stream` has buffered to `pending` without blocking;
+/// false once the other end closed the connection
+fn read_available(stream: &UnixStream, pending: &mut Vec<u8>) -> bool {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = unsafe { libc::recv(stream.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), libc::MSG_DONTWAIT) };
+        if n > 0 {
+            pending.extend_from_slice(&buf[..n as usize]);
+            continue;
+        }
+        if n == 0 {
+            return false;
+        }
+        match std::io::Error::last_os_error().kind() {
+            ErrorKind::WouldBlock => return true,
+            ErrorKind::Interrupted => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// Complete lines in `pending`, removed from it
+fn take_lines(pending: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=pos).collect();
+        lines.push(String::from_utf8_lossy(&line).into_owned());
+    }
+    lines
+}
+
+/// Write end of the connection to the input helper, shared by the output
+/// thread and the main loop
+#[derive(Debug, Clone)]
+pub struct HelperLink {
+    stream: Arc<Mutex<UnixStream>>,
+}
+
+impl HelperLink {
+    /// Send a command (whole lines, so writers never interleave)
+    pub fn send(&self, command: &HelperCommand) -> std::io::Result<()> {
+        let mut stream = self.stream.lock().unwrap_or_else(PoisonError::into_inner);
+        stream.write_all(command.to_line().as_bytes())
+    }
+}
+
+/// Input read by the input helper, for a remapping process without access
+/// to the devices
+pub struct RemoteInput {
+    link: HelperLink,
+    reader: UnixStream,
+    pending: Vec<u8>,
+    epoll: Epoll,
+    waker: Waker,
+    watched: Vec<RawFd>,
+    devices: Vec<String>,
+    locks: Option<LockState>,
+}
+
+impl RemoteInput {
+    /// Connect to the input helper listening on `path` and wait for it to
+    /// introduce its devices
+    pub fn connect(path: &Path) -> EventLoopResult<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_write_timeout(Some(HELPER_TIMEOUT))?;
+        let reader = stream.try_clone()?;
+        let epoll = Epoll::new()?;
+        let waker = Waker::new()?;
+        epoll.add(waker.as_raw_fd())?;
+        epoll.add(reader.as_raw_fd())?;
+        let mut input = Self {
+            link: HelperLink {
+                stream: Arc::new(Mutex::new(stream)),
+            },
+            reader,
+            pending: Vec::new(),
+            epoll,
+            waker,
+            watched: Vec::new(),
+            devices: Vec::new(),
+            locks: None,
+        };
+
+        // The device list comes first; keyboard type detection needs it
+        input.reader.set_read_timeout(Some(HELPER_TIMEOUT))?;
+        let mut introduced = false;
+        let mut buf = [0u8; 4096];
+        while !introduced {
+            let n = match input.reader.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(EventLoopError::HelperDisconnected(format!(
+                        "no device list from the input helper: {}",
+                        e
+                    )))
+                }
+            };
+            if n == 0 {
+                return Err(EventLoopError::HelperDisconnected(
+                    "the input helper closed the connection (is another process connected?)".to_string(),
+                ));
+            }
+            input.pending.extend_from_slice(&buf[..n]);
+            for line in take_lines(&mut input.pending) {
+                introduced |= matches!(HelperMessage::parse(&line), Ok(HelperMessage::Devices(_)));
+                input.handle_line(&line, &mut Vec::new());
+            }
+        }
+        input.reader.set_read_timeout(None)?;
+        Ok(input)
+    }
+
+    /// Sender for commands to the helper (for `VirtualDevice::remote`)
+    pub fn link(&self) -> HelperLink {
+        self.link.clone()
+    }
+
+    fn send(&self, command: HelperCommand) {
+        if let Err(e) = self.link.send(&command) {
+            log::warn!("Could not reach the input helper: {}", e);
+        }
+    }
+
+    fn handle_line(&mut self, line: &str, events: &mut Vec<PolledEvent>) {
+        match HelperMessage::parse(line) {
+            Ok(HelperMessage::Devices(names)) => self.devices = names,
+            Ok(HelperMessage::Locks(state)) => self.locks = Some(state),
+            Ok(HelperMessage::Event {
+                device,
+                event_type,
+                code,
+                value,
+            }) => events.push(PolledEvent {
+                event: InputEvent::new(EventType(event_type), code, value),
+                device_name: device,
+            }),
+            Err(e) => log::debug!("Ignoring input helper message: {}", e),
+        }
+    }
+}
+
+impl InputSource for RemoteInput {
+    fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    fn set_watched_fds(&mut self, fds: &[RawFd]) {
+        let owned = [self.reader.as_raw_fd(), self.waker.as_raw_fd()];
+        for fd in &self.watched {
+            if !fds.contains(fd) && !owned.contains(fd) {
+                self.epoll.remove(*fd);
+            }
+        }
+        for fd in fds {
+            if !self.watched.contains(fd) && !owned.contains(fd) {
+                if let Err(e) = self.epoll.add(*fd) {
+                    log::debug!("Could not watch fd {}: {}", fd, e);
+                }
+            }
+        }
+        self.watched = fds.to_vec();
+    }
+
+    fn wait_for_events(&mut self, timeout: Option<Duration>) -> EventLoopResult<Vec<PolledEvent>> {
+        let mut events = Vec::new();
+        let ready = self.epoll.wait(timeout)?;
+        if ready.iter().any(|r| r.fd == self.waker.as_raw_fd()) {
+            self.waker.drain();
+        }
+        let Some(readiness) = ready.iter().find(|r| r.fd == self.reader.as_raw_fd()) else {
+            return Ok(events);
+        };
+        let open = read_available(&self.reader, &mut self.pending) && !readiness.hangup;
+        for line in take_lines(&mut self.pending) {
+            self.handle_line(&line, &mut events);
+        }
+        if !open {
+            return Err(EventLoopError::HelperDisconnected(
+                "the input helper closed the connection".to_string(),
+            ));
+        }
+        Ok(events)
+    }
+
+    /// The helper re-emits scancodes, switches and pointer motion itself
+    fn take_forwarded(&mut self) -> Vec<Vec<(u16, u16, i32)>> {
+        Vec::new()
+    }
+
+    fn device_names(&self) -> Vec<String> {
+        self.devices.clone()
+    }
+
+    fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+
+    fn keyboard_detection_infos(&self) -> Vec<KeyboardDeviceInfo> {
+        self.devices.iter().map(|name| KeyboardDeviceInfo::new(name.clone())).collect()
+    }
+
+    fn lock_state(&self) -> Option<LockState> {
+        self.locks
+    }
+
+    fn set_lock_leds(&mut self, state: LockState) {
+        self.send(HelperCommand::Leds(state));
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.send(HelperCommand::Pause(paused));
+    }
+
+    /// The helper releases the devices until it is resumed or the connection closes
+    fn ungrab_all(&mut self) {
+        self.send(HelperCommand::Pause(true));
+    }
+}
+
+/// The remapping process connected to the helper
+struct Client {
+    stream: UnixStream,
+    pending: Vec<u8>,
+    /// Device list last sent
+    devices: Vec<String>,
+}
+
+impl Client {
+    fn send(&mut self, message: &HelperMessage) -> bool {
+        self.stream.write_all(message.to_line().as_bytes()).is_ok()
+    }
+}
+
+/// Privileged side of privilege separation: owns the devices and the
+/// virtual output devices, and serves one remapping process at a time.
+///
+/// Devices stay released while no remapping process is connected, so the
+/// keyboard keeps working unmapped rather than going dead.
+pub struct InputHelper {
+    listener: UnixListener,
+    path: PathBuf,
+    /// The socket file bound, removed on drop only while it is still there
+    socket: Option<SocketId>,
+    access: HelperAccess,
+    client: Option<Client>,
+}
+
+impl InputHelper {
+    /// Default socket path (`$XDG_RUNTIME_DIR/keyrs-input.sock`); fails without `XDG_RUNTIME_DIR`
+    pub fn default_path() -> std::io::Result<PathBuf> {
+        runtime_path("keyrs-input.sock")
+    }
+
+    /// Bind the helper socket, replacing a stale socket file: owner-only,
+    /// or owned by the group of `HelperAccess::Group` with mode 0660
+    pub fn bind(path: &Path, access: HelperAccess) -> std::io::Result<Self> {
+        let listener = match access {
+            HelperAccess::Owner => bind_socket(path, 0o600)?,
+            HelperAccess::Group(gid) => {
+                let listener = bind_socket(path, 0o660)?;
+                std::os::unix::fs::chown(path, None, Some(gid))?;
+                listener
+            }
+        };
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            socket: socket_id(path),
+            access,
+            client: None,
+        })
+    }
+
+    /// Socket path this helper is bound to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Relay events and commands until `running` is cleared; `shutdown`
+    /// ends a wait so the flag is seen at once
+    pub fn serve(
+        &mut self,
+        event_loop: &mut EventLoop,
+        device: &mut VirtualDevice,
+        running: &AtomicBool,
+        shutdown: &Waker,
+    ) {
+        event_loop.set_paused(true);
+        while running.load(Ordering::SeqCst) {
+            let mut watched = vec![shutdown.as_raw_fd(), self.listener.as_raw_fd()];
+            watched.extend(self.client.as_ref().map(|client| client.stream.as_raw_fd()));
+            event_loop.set_watched_fds(&watched);
+
+            let events = match event_loop.wait_for_events(None) {
+                Ok(events) => events,
+                Err(e) => {
+                    log::warn!("Waiting for input failed: {}", e);
+                    std::thread::sleep(Duration::from_millis(10));
+                    Vec::new()
+                }
+            };
+            for frame in event_loop.take_forwarded() {
+                if let Err(e) = device.forward_frame(&frame) {
+                    log::debug!("Could not forward events: {}", e);
+                }
+            }
+            self.accept(event_loop);
+
+            let Some(client) = self.client.as_mut() else {
+                continue;
+            };
+            let mut open = read_available(&client.stream, &mut client.pending);
+            for line in take_lines(&mut client.pending) {
+                match HelperCommand::parse(&line) {
+                    Ok(HelperCommand::Emit(kind, events)) => {
+                        if let Err(e) = device.emit_events(kind, &events) {
+                            log::warn!("Could not write output: {}", e);
+                        }
+                    }
+                    Ok(HelperCommand::Pause(paused)) => event_loop.set_paused(paused),
+                    Ok(HelperCommand::Leds(state)) => event_loop.set_lock_leds(state),
+                    Err(e) => log::debug!("Ignoring command from the remapping process: {}", e),
+                }
+            }
+
+            let devices = event_loop.device_names();
+            if open && devices != client.devices {
+                open = client.send(&HelperMessage::Devices(devices.clone()));
+                client.devices = devices;
+            }
+            for event in events {
+                if !open {
+                    break;
+                }
+                open = client.send(&HelperMessage::Event {
+                    device: event.device_name,
+                    event_type: event.event.event_type().0,
+                    code: event.event.code(),
+                    value: event.event.value(),
+                });
+            }
+
+            if !open {
+                log::warn!("Remapping process disconnected: releasing the devices");
+                self.client = None;
+                event_loop.set_paused(true);
+                if let Err(e) = device.release_all() {
+                    log::warn!("Could not release keys: {}", e);
+                }
+            }
+        }
+        if let Err(e) = device.release_all() {
+            log::warn!("Could not release keys: {}", e);
+        }
+    }
+
+    /// Take a waiting connection; a second one is turned away
+    fn accept(&mut self, event_loop: &mut EventLoop) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return,
+            };
+            match peer_credentials(&stream) {
+                Ok((uid, gid)) if self.access.allows(uid, gid) => {}
+                Ok((uid, gid)) => {
+                    log::warn!("Refusing a connection from uid {} gid {} on {}", uid, gid, self.path.display());
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Refusing a connection with unknown credentials: {}", e);
+                    continue;
+                }
+            }
+            if self.client.is_some() {
+                log::warn!("Refusing a second remapping process on {}", self.path.display());
+                continue;
+            }
+            if stream.set_nonblocking(false).is_err() || stream.set_write_timeout(Some(HELPER_TIMEOUT)).is_err() {
+                continue;
+            }
+            let mut client = Client {
+                stream,
+                pending: Vec::new(),
+                devices: event_loop.device_names(),
+            };
+            let locks = event_loop.lock_state();
+            let introduced = locks.is_none_or(|state| client.send(&HelperMessage::Locks(state)))
+                && client.send(&HelperMessage::Devices(client.devices.clone()));
+            if introduced {
+                log::info!("Remapping process connected: grabbing the devices");
+                event_loop.set_paused(false);
+                self.client = Some(client);
+            }
+        }
+    }
+}
+
+impl Drop for InputHelper {
+    fn drop(&mut self) {
+        remove_socket(&self.path, self.socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helper_messages_round_trip() {
+        let messages = [
+            HelperMessage::Devices(vec!["AT Translated Set 2 keyboard".to_string(), "Logitech USB Receiver".to_string()]),
+            HelperMessage::Devices(Vec::new()),
+            HelperMessage::Locks(LockState {
+                num_lock: true,
+                caps_lock: false,
+            }),
+            HelperMessage::Event {
+                device: "AT Translated Set 2 keyboard".to_string(),
+                event_type: 1,
+                code: 30,
+                value: 1,
+            },
+        ];
+        for message in messages {
+            let line = message.to_line();
+            assert_eq!(line.matches('\n').count(), 1, "{:?}", line);
+            assert_eq!(HelperMessage::parse(&line), Ok(message));
+        }
+        assert!(HelperMessage::parse("event 1 x 1 kbd").is_err());
+        assert!(HelperMessage::parse("hello").is_err());
+    }
+
+    #[test]
+    fn test_helper_commands_round_trip() {
+        let commands = [
+            HelperCommand::Emit(OutputDeviceKind::Keyboard, vec![(1, 30, 1), (0, 0, 0)]),
+            HelperCommand::Emit(OutputDeviceKind::Mouse, vec![(2, 8, -1)]),
+            HelperCommand::Pause(true),
+            HelperCommand::Leds(LockState {
+                num_lock: false,
+                caps_lock: true,
+            }),
+        ];
+        for command in commands {
+            assert_eq!(HelperCommand::parse(&command.to_line()), Ok(command));
+        }
+        assert!(HelperCommand::parse("emit tablet 1:30:1").is_err());
+        assert!(HelperCommand::parse("emit keyboard 1:30").is_err());
+        // Only keys, sync and (on the mouse) relative motion may be written
+        assert!(HelperCommand::parse("emit keyboard 5:0:1").is_err());
+        assert!(HelperCommand::parse("emit keyboard 4:4:30").is_err());
+        assert!(HelperCommand::parse("emit keyboard 2:0:5").is_err());
+        assert!(HelperCommand::parse("emit keyboard 1:30:7").is_err());
+        assert!(HelperCommand::parse("pause maybe").is_err());
+    }
+
+    #[test]
+    fn test_helper_access_checks_peer() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let (uid, gid) = peer_credentials(&a).unwrap();
+        assert_eq!(uid, unsafe { libc::geteuid() });
+        assert!(HelperAccess::Owner.allows(uid, gid));
+        assert!(!HelperAccess::Owner.allows(uid.wrapping_add(1), gid));
+
+        // Primary group membership counts; an unrelated user does not
+        let group = HelperAccess::Group(gid);
+        assert!(group.allows(uid.wrapping_add(1), gid));
+        assert!(!HelperAccess::Group(u32::MAX - 1).allows(u32::MAX - 1, u32::MAX - 2));
+        assert!(HelperAccess::group("no-such-group-keyrs").is_err());
+    }
+
+    #[test]
+    fn test_second_helper_leaves_the_socket_alone() {
+        let path = std::env::temp_dir().join(format!("keyrs-helper-test-{}.sock", std::process::id()));
+        let helper = InputHelper::bind(&path, HelperAccess::Owner).unwrap();
+        let err = InputHelper::bind(&path, HelperAccess::Owner).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+
+        drop(helper);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_read_available_lines() {
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let mut pending = Vec::new();
+        b.write_all(b"pause true\nleds fal").unwrap();
+        assert!(read_available(&a, &mut pending));
+        assert_eq!(take_lines(&mut pending), vec!["pause true\n".to_string()]);
+        assert_eq!(pending, b"leds fal");
+
+        drop(b);
+        assert!(!read_available(&a, &mut pending));
+    }
+}
@@ -0,0 +1,137 @@
+// Keyrs Runtime Files
+// Sockets and files the daemon keeps under $XDG_RUNTIME_DIR
+
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Serializes umask changes between threads of this process
+static UMASK: Mutex<()> = Mutex::new(());
+
+/// Path of `name` in `$XDG_RUNTIME_DIR`.
+///
+/// There is deliberately no fallback: in a shared directory such as /tmp
+/// another user could create the path first or race its creation.
+pub fn runtime_path(name: &str) -> io::Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join(name)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("XDG_RUNTIME_DIR is not set; cannot place {}", name),
+        )),
+    }
+}
+
+/// Identity of a socket file, to tell it from one bound later at the same
+/// path: inode numbers alone are reused at once on tmpfs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketId {
+    dev: u64,
+    ino: u64,
+    ctime: (i64, i64),
+}
+
+/// Bind a non-blocking listening socket at `path` with permissions `mode`,
+/// replacing a stale socket left by a previous run.
+///
+/// The umask is set for the `bind` itself, so the socket never exists with
+/// wider permissions, not even briefly. A socket some process still accepts
+/// connections on is not replaced, and anything at `path` that isn't a
+/// socket is left alone; the bind fails for both.
+pub fn bind_socket(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by a running process", path.display()),
+                ))
+            }
+            // Nobody listens any more
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        },
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let listener = {
+        let _guard = UMASK.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = unsafe { libc::umask(!mode as libc::mode_t & 0o777) };
+        let bound = UnixListener::bind(path);
+        unsafe { libc::umask(previous) };
+        bound?
+    };
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// `bind_socket` with owner-only permissions
+pub fn bind_private_socket(path: &Path) -> io::Result<UnixListener> {
+    bind_socket(path, 0o600)
+}
+
+/// Identity of the socket file at `path`, if there is one
+pub fn socket_id(path: &Path) -> Option<SocketId> {
+    std::fs::symlink_metadata(path).ok().map(|meta| SocketId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+        ctime: (meta.ctime(), meta.ctime_nsec()),
+    })
+}
+
+/// Remove the socket at `path` unless it was replaced since `id` was taken,
+/// so a process never deletes the socket another one bound after it
+pub fn remove_socket(path: &Path, id: Option<SocketId>) {
+    if id.is_some() && socket_id(path) == id {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_bind_private_socket() {
+        let dir = std::env::temp_dir().join(format!("keyrs-runtime-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.sock");
+
+        let listener = bind_private_socket(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        // A live socket is not taken over
+        let id = socket_id(&path);
+        let err = bind_private_socket(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(socket_id(&path), id);
+        // A stale socket is replaced, and only the new owner removes it
+        drop(listener);
+        // ctime ticks coarsely; a later socket differs from the first by more than its inode
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let _listener = bind_private_socket(&path).unwrap();
+        remove_socket(&path, id);
+        assert!(path.exists());
+        remove_socket(&path, socket_id(&path));
+        assert!(!path.exists());
+
+        // Anything else is not
+        let file = dir.join("regular");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(bind_private_socket(&file).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,8 +4,14 @@
 #![cfg(feature = "pure-rust")]
 
 use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::fmt;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
+use crate::input::{format_device_id, parse_device_id, KeyboardType};
+
 /// Settings for keyrs that control feature toggles
 /// 
 /// These settings are loaded from a TOML file (default: ~/.config/keyrs/settings.toml)
@@ -16,12 +22,22 @@ use std::path::{Path, PathBuf};
 pub struct Settings {
     /// Feature toggles (e.g., Enter2Ent_Cmd, Caps2Esc_Cmd)
     features: HashMap<String, bool>,
+
+    /// Values of features settings.toml leaves out, registered by the config
+    /// (`toggle_setting` on keymaps and multipurpose entries)
+    defaults: HashMap<String, bool>,
+
+    /// Number and text features (`[features] tab_width = 4`)
+    values: HashMap<String, SettingValue>,
     
     /// Layout setting (e.g., "ABC" or "US")
     optspec_layout: String,
     
     /// Keyboard type override (optional)
     keyboard_override: Option<String>,
+
+    /// Keyboard type per device, by `vendor:product` id
+    device_types: HashMap<String, KeyboardType>,
     
     /// Path to the settings file (for reload)
     source_path: Option<PathBuf>,
@@ -40,6 +56,93 @@ pub enum SettingsError {
     InvalidValue(String),
 }
 
+/// Value of a setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Bool(value) => write!(f, "{}", value),
+            SettingValue::Int(value) => write!(f, "{}", value),
+            SettingValue::Text(value) => f.write_str(value),
+        }
+    }
+}
+
+/// Type of a setting and the values it allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Bool,
+    Int { min: i64, max: i64 },
+    /// One of these names (matched case-insensitively)
+    Enum(&'static [&'static str]),
+}
+
+/// Settings with a fixed type; other features take the type of their value
+pub const SETTING_SCHEMA: &[(&str, SettingKind)] = &[
+    // Option-key special character layout (`[layout]`)
+    ("optspec_layout", SettingKind::Enum(&["ABC", "US"])),
+];
+
+/// Declared type of setting `name`
+pub fn setting_kind(name: &str) -> Option<SettingKind> {
+    SETTING_SCHEMA
+        .iter()
+        .find(|(setting, _)| *setting == name)
+        .map(|(_, kind)| *kind)
+}
+
+impl SettingKind {
+    /// `value` if this kind allows it; enum names take their listed spelling
+    pub fn check(&self, value: SettingValue) -> Result<SettingValue, String> {
+        match (self, value) {
+            (SettingKind::Bool, SettingValue::Bool(value)) => Ok(SettingValue::Bool(value)),
+            (SettingKind::Int { min, max }, SettingValue::Int(value)) => {
+                if (*min..=*max).contains(&value) {
+                    Ok(SettingValue::Int(value))
+                } else {
+                    Err(format!("must be {}..{}, got {}", min, max, value))
+                }
+            }
+            (SettingKind::Enum(names), SettingValue::Text(value)) => names
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(value.trim()))
+                .map(|name| SettingValue::Text(name.to_string()))
+                .ok_or_else(|| format!("must be one of {}, got '{}'", names.join(", "), value)),
+            (kind, value) => Err(format!("expected {}, got '{}'", kind.describe(), value)),
+        }
+    }
+
+    /// Read `text` (from a condition or a TOML string) as a value of this kind
+    pub fn parse_str(&self, text: &str) -> Result<SettingValue, String> {
+        let value = match self {
+            SettingKind::Bool => parse_bool_str(text)
+                .map(SettingValue::Bool)
+                .ok_or_else(|| format!("expected a boolean, got '{}'", text))?,
+            SettingKind::Int { .. } => text
+                .trim()
+                .parse()
+                .map(SettingValue::Int)
+                .map_err(|_| format!("expected an integer, got '{}'", text))?,
+            SettingKind::Enum(_) => SettingValue::Text(text.to_string()),
+        };
+        self.check(value)
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            SettingKind::Bool => "a boolean".to_string(),
+            SettingKind::Int { .. } => "an integer".to_string(),
+            SettingKind::Enum(names) => format!("one of {}", names.join(", ")),
+        }
+    }
+}
+
 /// TOML representation for deserializing settings
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 struct SettingsToml {
@@ -63,6 +166,9 @@ struct LayoutSettings {
 struct KeyboardSettings {
     #[serde(default)]
     override_type: Option<String>,
+    /// `"vendor:product" = "Type"` per-device overrides
+    #[serde(default)]
+    devices: HashMap<String, String>,
 }
 
 impl Settings {
@@ -70,8 +176,11 @@ impl Settings {
     pub fn new() -> Self {
         Self {
             features: HashMap::new(),
+            defaults: HashMap::new(),
+            values: HashMap::new(),
             optspec_layout: "ABC".to_string(),
             keyboard_override: None,
+            device_types: HashMap::new(),
             source_path: None,
         }
     }
@@ -94,21 +203,30 @@ impl Settings {
         // Parse features section
         if let Some(features) = toml_settings.features {
             for (key, value) in features {
-                let bool_value = parse_bool_value(&value)?;
-                settings.features.insert(key, bool_value);
+                let value = parse_feature_value(&key, &value)?;
+                settings.set_value(&key, value)?;
             }
         }
         
         // Parse layout section
         if let Some(layout) = toml_settings.layout {
             if let Some(optspec) = layout.optspec_layout {
-                settings.optspec_layout = optspec;
+                settings.set_str("optspec_layout", &optspec)?;
             }
         }
         
         // Parse keyboard section
         if let Some(keyboard) = toml_settings.keyboard {
             settings.keyboard_override = keyboard.override_type;
+            for (id, type_name) in keyboard.devices {
+                let (vendor_id, product_id) = parse_device_id(&id).ok_or_else(|| {
+                    SettingsError::InvalidValue(format!("keyboard.devices: '{}' is not a vendor:product id", id))
+                })?;
+                let kb_type = KeyboardType::from_str(&type_name).ok_or_else(|| {
+                    SettingsError::InvalidValue(format!("keyboard.devices: unknown keyboard type '{}' for {}", type_name, id))
+                })?;
+                settings.device_types.insert(format_device_id(vendor_id, product_id), kb_type);
+            }
         }
         
         Ok(settings)
@@ -132,13 +250,84 @@ impl Settings {
     
     /// Get a boolean feature value
     pub fn get_bool(&self, name: &str) -> bool {
-        self.features.get(name).copied().unwrap_or(false)
+        match self.get(name) {
+            Some(SettingValue::Bool(value)) => value,
+            Some(SettingValue::Int(value)) => value != 0,
+            _ => false,
+        }
     }
     
     /// Set a boolean feature value
     pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.values.remove(name);
         self.features.insert(name.to_string(), value);
     }
+
+    /// Value of setting `name`, whatever its type (`None` when unset)
+    pub fn get(&self, name: &str) -> Option<SettingValue> {
+        if name == "optspec_layout" {
+            return Some(SettingValue::Text(self.optspec_layout.clone()));
+        }
+        self.values.get(name).cloned().or_else(|| {
+            self.features
+                .get(name)
+                .or_else(|| self.defaults.get(name))
+                .map(|value| SettingValue::Bool(*value))
+        })
+    }
+
+    /// Get an integer feature value
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(SettingValue::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Get a text feature value (`optspec_layout` included)
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        if name == "optspec_layout" {
+            return Some(&self.optspec_layout);
+        }
+        match self.values.get(name) {
+            Some(SettingValue::Text(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Set an integer feature value, checked against `SETTING_SCHEMA`
+    pub fn set_int(&mut self, name: &str, value: i64) -> Result<(), SettingsError> {
+        self.set_value(name, SettingValue::Int(value))
+    }
+
+    /// Set a text feature value, checked against `SETTING_SCHEMA`
+    pub fn set_str(&mut self, name: &str, value: &str) -> Result<(), SettingsError> {
+        self.set_value(name, SettingValue::Text(value.to_string()))
+    }
+
+    /// Set setting `name`, rejecting values its `SETTING_SCHEMA` entry doesn't allow
+    pub fn set_value(&mut self, name: &str, value: SettingValue) -> Result<(), SettingsError> {
+        let value = match setting_kind(name) {
+            Some(kind) => kind
+                .check(value)
+                .map_err(|e| SettingsError::InvalidValue(format!("{}: {}", name, e)))?,
+            None => value,
+        };
+        match value {
+            SettingValue::Bool(value) => self.set_bool(name, value),
+            SettingValue::Text(value) if name == "optspec_layout" => self.optspec_layout = value,
+            value => {
+                self.features.remove(name);
+                self.values.insert(name.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number and text features
+    pub fn typed_values(&self) -> &HashMap<String, SettingValue> {
+        &self.values
+    }
     
     /// Get the optspec layout
     pub fn optspec_layout(&self) -> &str {
@@ -150,6 +339,17 @@ impl Settings {
         self.keyboard_override.as_deref()
     }
     
+    /// Keyboard type override for the device with this `vendor:product` id
+    pub fn device_keyboard_type(&self, device_id: &str) -> Option<KeyboardType> {
+        let (vendor_id, product_id) = parse_device_id(device_id)?;
+        self.device_types.get(&format_device_id(vendor_id, product_id)).copied()
+    }
+
+    /// All per-device keyboard type overrides, by `vendor:product` id
+    pub fn device_keyboard_types(&self) -> &HashMap<String, KeyboardType> {
+        &self.device_types
+    }
+
     /// Check if a setting exists
     pub fn has_setting(&self, name: &str) -> bool {
         self.features.contains_key(name)
@@ -159,6 +359,20 @@ impl Settings {
     pub fn features(&self) -> &HashMap<String, bool> {
         &self.features
     }
+
+    /// Register `value` as the default of feature `name`, used while
+    /// settings.toml doesn't set it
+    pub fn register_default(&mut self, name: &str, value: bool) {
+        self.defaults.insert(name.to_string(), value);
+    }
+
+    /// Every known feature with its current value: the ones settings.toml
+    /// sets and the registered defaults of the others
+    pub fn values(&self) -> HashMap<String, bool> {
+        let mut values = self.defaults.clone();
+        values.extend(self.features.iter().map(|(name, value)| (name.clone(), *value)));
+        values
+    }
     
     /// Reload settings from the original file
     pub fn reload(&mut self) -> Result<(), SettingsError> {
@@ -197,24 +411,161 @@ impl Settings {
     }
 }
 
-/// Parse a TOML value as a boolean
-fn parse_bool_value(value: &toml::Value) -> Result<bool, SettingsError> {
-    match value {
-        toml::Value::Boolean(b) => Ok(*b),
-        toml::Value::Integer(1) => Ok(true),
-        toml::Value::Integer(0) => Ok(false),
-        toml::Value::String(s) => {
-            match s.to_lowercase().as_str() {
-                "true" | "yes" | "on" | "1" => Ok(true),
-                "false" | "no" | "off" | "0" => Ok(false),
-                _ => Err(SettingsError::InvalidValue(
-                    format!("Cannot convert '{}' to boolean", s)
-                )),
+/// Watches a settings file for changes with inotify.
+///
+/// The parent directory is watched rather than the file, so editors that save
+/// by renaming a new file over the old one (and a file created or deleted
+/// after startup) are noticed as well.
+#[derive(Debug)]
+pub struct SettingsWatcher {
+    fd: OwnedFd,
+    file_name: OsString,
+}
+
+impl SettingsWatcher {
+    /// Start watching `path`; its directory must exist
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let file_name = path.file_name().map(|name| name.to_os_string()).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "settings path has no file name")
+        })?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // Not IN_CREATE: a file is read once its writer closes it, never half-written
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_DELETE;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { fd, file_name })
+    }
+
+    /// Drain queued notifications; true if any of them concerned the settings file
+    pub fn changed(&mut self) -> bool {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut buf = [0u8; 4096];
+        let mut changed = false;
+        loop {
+            let read = unsafe {
+                libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len())
+            };
+            if read <= 0 {
+                return changed;
             }
+            let read = read as usize;
+            let mut offset = 0;
+            while offset + HEADER <= read {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf.as_ptr().add(offset).cast()) };
+                let name_end = (offset + HEADER + event.len as usize).min(read);
+                let name = &buf[offset + HEADER..name_end];
+                let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                changed |= name == self.file_name.as_bytes();
+                offset = name_end;
+            }
+        }
+    }
+}
+
+impl AsRawFd for SettingsWatcher {
+    /// Readable when `changed` has notifications to drain
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Set (or with `None`, remove) the keyboard type of a device in the settings
+/// file at `path`, keeping the rest of the file and its comments as they are.
+/// The file is replaced by rename, so a running daemon reloads it.
+pub fn write_device_keyboard_type(
+    path: &Path,
+    device_id: &str,
+    kb_type: Option<KeyboardType>,
+) -> Result<(), SettingsError> {
+    let (vendor_id, product_id) = parse_device_id(device_id)
+        .ok_or_else(|| SettingsError::InvalidValue(format!("'{}' is not a vendor:product id", device_id)))?;
+    let device_id = format_device_id(vendor_id, product_id);
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut doc = content
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| SettingsError::TomlParse(e.to_string()))?;
+
+    let keyboard = doc
+        .entry("keyboard")
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| SettingsError::InvalidValue("[keyboard] is not a table".to_string()))?;
+    match kb_type {
+        Some(kb_type) => {
+            let devices = keyboard
+                .entry("devices")
+                .or_insert_with(toml_edit::table)
+                .as_table_mut()
+                .ok_or_else(|| SettingsError::InvalidValue("[keyboard.devices] is not a table".to_string()))?;
+            devices.insert(&device_id, toml_edit::value(kb_type.as_str()));
+        }
+        None => {
+            if let Some(devices) = keyboard.get_mut("devices").and_then(|d| d.as_table_mut()) {
+                devices.retain(|id, _| parse_device_id(id) != Some((vendor_id, product_id)));
+                if devices.is_empty() {
+                    keyboard.remove("devices");
+                }
+            }
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("toml.tmp");
+    std::fs::write(&temp, doc.to_string())?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Parse a `[features]` value: as its `SETTING_SCHEMA` type, or else as a
+/// flag (`true`, `"on"`, `1`), an integer or text
+fn parse_feature_value(name: &str, value: &toml::Value) -> Result<SettingValue, SettingsError> {
+    let invalid = |e: String| SettingsError::InvalidValue(format!("{}: {}", name, e));
+    if let Some(kind) = setting_kind(name) {
+        return match value {
+            toml::Value::Boolean(b) => kind.check(SettingValue::Bool(*b)),
+            toml::Value::Integer(n) if kind != SettingKind::Bool => kind.check(SettingValue::Int(*n)),
+            toml::Value::String(s) => kind.parse_str(s),
+            _ => kind.parse_str(&value.to_string()),
         }
-        _ => Err(SettingsError::InvalidValue(
-            format!("Cannot convert {:?} to boolean", value)
-        )),
+        .map_err(invalid);
+    }
+    match value {
+        toml::Value::Boolean(b) => Ok(SettingValue::Bool(*b)),
+        toml::Value::Integer(1) => Ok(SettingValue::Bool(true)),
+        toml::Value::Integer(0) => Ok(SettingValue::Bool(false)),
+        toml::Value::Integer(n) => Ok(SettingValue::Int(*n)),
+        toml::Value::String(s) => Ok(parse_bool_str(s)
+            .map(SettingValue::Bool)
+            .unwrap_or_else(|| SettingValue::Text(s.clone()))),
+        _ => Err(invalid(format!("cannot use {} as a setting", value))),
+    }
+}
+
+/// Boolean spelled as text (`"yes"`, `"off"`, `"1"`)
+fn parse_bool_str(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
     }
 }
 
@@ -234,6 +585,8 @@ Caps2Cmd = false
 media_arrows_fix = false
 forced_numpad = false
 multi_lang = false
+# Chromebook top row sends F1..F10 ([chromebook] in config.toml)
+chromebook_fkeys = false
 
 [layout]
 # Optional special character layout: "ABC" or "US"
@@ -243,6 +596,10 @@ optspec_layout = "ABC"
 # Optional keyboard type override (auto-detected if not set)
 # Valid values: "IBM", "Chromebook", "Windows", "Apple"
 # override_type = "Apple"
+
+# Per-keyboard types by vendor:product id, set with `keyrs devices set-type`
+# [keyboard.devices]
+# "05ac:024f" = "Mac"
 "#
 }
 
@@ -287,6 +644,53 @@ optspec_layout = "US"
         assert_eq!(settings.evaluate_condition("not settings.Caps2Esc_Cmd"), true);
     }
 
+    #[test]
+    fn test_registered_defaults() {
+        let mut settings = Settings::from_toml("[features]\nCaps2Esc_Cmd = false\n").unwrap();
+        settings.register_default("Caps2Esc_Cmd", true);
+        settings.register_default("HomeRowMods", true);
+        // settings.toml wins; the default fills in what it leaves out
+        assert!(!settings.get_bool("Caps2Esc_Cmd"));
+        assert!(settings.get_bool("HomeRowMods"));
+        assert!(!settings.has_setting("HomeRowMods"));
+        assert_eq!(settings.values().get("HomeRowMods"), Some(&true));
+        assert_eq!(settings.values().get("Caps2Esc_Cmd"), Some(&false));
+    }
+
+    #[test]
+    fn test_typed_settings() {
+        let toml = r#"
+[features]
+Caps2Esc_Cmd = 1
+tab_width = 4
+terminal = "kitty"
+
+[layout]
+optspec_layout = "us"
+"#;
+        let mut settings = Settings::from_toml(toml).unwrap();
+        assert!(settings.get_bool("Caps2Esc_Cmd"));
+        assert_eq!(settings.get_int("tab_width"), Some(4));
+        assert_eq!(settings.get_str("terminal"), Some("kitty"));
+        assert_eq!(settings.get("optspec_layout"), Some(SettingValue::Text("US".to_string())));
+        assert!(!settings.features().contains_key("tab_width"));
+
+        assert!(settings.set_str("optspec_layout", "Dvorak").is_err());
+        assert_eq!(settings.optspec_layout(), "US");
+        settings.set_str("optspec_layout", "abc").unwrap();
+        assert_eq!(settings.optspec_layout(), "ABC");
+        assert!(settings.set_int("optspec_layout", 1).is_err());
+
+        // A flag set to a number is a number from then on, and back
+        settings.set_int("Caps2Esc_Cmd", 2).unwrap();
+        assert_eq!(settings.get_int("Caps2Esc_Cmd"), Some(2));
+        settings.set_bool("Caps2Esc_Cmd", false);
+        assert_eq!(settings.get("Caps2Esc_Cmd"), Some(SettingValue::Bool(false)));
+
+        let err = Settings::from_toml("[layout]\noptspec_layout = \"UK\"\n").unwrap_err();
+        assert!(err.to_string().contains("must be one of ABC, US"), "{}", err);
+    }
+
     #[test]
     fn test_settings_with_string_values() {
         let toml = r#"
@@ -312,4 +716,74 @@ override_type = "Apple"
         let settings = Settings::from_toml(toml).unwrap();
         assert_eq!(settings.keyboard_override(), Some("Apple"));
     }
+
+    #[test]
+    fn test_device_keyboard_types() {
+        let toml = r#"
+[keyboard]
+override_type = "IBM"
+
+[keyboard.devices]
+"05AC:024F" = "Apple"
+"17ef:6047" = "IBM"
+"#;
+
+        let settings = Settings::from_toml(toml).unwrap();
+        assert_eq!(settings.keyboard_override(), Some("IBM"));
+        assert_eq!(settings.device_keyboard_type("05ac:024f"), Some(KeyboardType::Mac));
+        assert_eq!(settings.device_keyboard_type("17ef:6047"), Some(KeyboardType::IBM));
+        assert_eq!(settings.device_keyboard_type("046d:c31c"), None);
+
+        assert!(Settings::from_toml("[keyboard.devices]\n\"Magic\" = \"Mac\"\n").is_err());
+        assert!(Settings::from_toml("[keyboard.devices]\n\"05ac:024f\" = \"Amiga\"\n").is_err());
+    }
+
+    #[test]
+    fn test_write_device_keyboard_type_keeps_the_rest() {
+        let dir = std::env::temp_dir().join(format!("keyrs-settings-devices-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        std::fs::write(&path, "# my toggles\n[features]\nforced_numpad = true\n").unwrap();
+
+        write_device_keyboard_type(&path, "05AC:024F", Some(KeyboardType::Mac)).unwrap();
+        write_device_keyboard_type(&path, "17ef:6047", Some(KeyboardType::IBM)).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my toggles\n"), "{}", content);
+        let settings = Settings::from_toml(&content).unwrap();
+        assert!(settings.get_bool("forced_numpad"));
+        assert_eq!(settings.device_keyboard_type("05ac:024f"), Some(KeyboardType::Mac));
+        assert_eq!(settings.device_keyboard_type("17ef:6047"), Some(KeyboardType::IBM));
+
+        write_device_keyboard_type(&path, "05ac:024f", None).unwrap();
+        write_device_keyboard_type(&path, "17ef:6047", None).unwrap();
+        let settings = Settings::from_file(&path).unwrap();
+        assert!(settings.device_keyboard_types().is_empty());
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("devices"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_settings_watcher_reports_only_its_file() {
+        let dir = std::env::temp_dir().join(format!("keyrs-settings-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        let mut watcher = SettingsWatcher::new(&path).unwrap();
+        assert!(!watcher.changed());
+
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        assert!(!watcher.changed());
+
+        std::fs::write(&path, "[features]\nforced_numpad = true\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // Saved by rename, as many editors do
+        let temp = dir.join("settings.toml.tmp");
+        std::fs::write(&temp, "[features]\n").unwrap();
+        std::fs::rename(&temp, &path).unwrap();
+        assert!(watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
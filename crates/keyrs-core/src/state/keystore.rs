@@ -55,9 +55,11 @@ impl Keystore {
     pub fn update(&mut self, inkey: Key, action: Action, key: Option<Key>) {
         let key_code = inkey.code();
 
-        // Create new keystate, potentially with prior state snapshot
-        let new_keystate = if let Some(prior) = self.states.get(&key_code) {
-            Keystate::new(inkey, action).with_prior(prior.clone())
+        // Create new keystate, potentially with prior state snapshot. Only one
+        // level is kept; a chain would grow and be copied on every event.
+        let new_keystate = if let Some(mut prior) = self.states.remove(&key_code) {
+            prior.prior = None;
+            Keystate::new(inkey, action).with_prior(prior)
         } else {
             Keystate::new(inkey, action)
         };
@@ -298,6 +300,20 @@ mod tests {
         assert_eq!(store.len(), 1);
     }
 
+    #[test]
+    fn test_keystore_update_keeps_one_prior() {
+        let mut store = Keystore::new();
+        for _ in 0..3 {
+            store.update(Key::from(30), Action::Press, None);
+            store.update(Key::from(30), Action::Release, None);
+        }
+
+        let ks = store.get(30).unwrap();
+        let prior = ks.prior.as_ref().unwrap();
+        assert_eq!(prior.action, Action::Press);
+        assert!(prior.prior.is_none());
+    }
+
     #[test]
     fn test_keystore_get_pressed_mods_keys() {
         let mut store = Keystore::new();
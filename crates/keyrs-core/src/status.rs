@@ -0,0 +1,312 @@
+// Keyrs Daemon Status
+// State published for status bars and the TUI: a JSON file and a D-Bus signal
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use zbus::blocking::Connection;
+use zbus::names::BusName;
+
+/// Object path of the `StateChanged` signal
+pub const STATUS_DBUS_PATH: &str = "/io/github/keyrs";
+/// Interface of the `StateChanged` signal
+pub const STATUS_DBUS_INTERFACE: &str = "io.github.keyrs.Daemon";
+/// Signal member, with arguments `(b enabled, b suspended, s config, as devices)`
+pub const STATUS_DBUS_SIGNAL: &str = "StateChanged";
+
+/// What the running daemon is doing
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    /// The daemon is running (false once it has exited)
+    pub enabled: bool,
+    /// Suspend mode is on: keys pass through untouched
+    pub suspended: bool,
+    /// Config file in use
+    pub config: Option<PathBuf>,
+    /// Names of the grabbed input devices
+    pub devices: Vec<String>,
+}
+
+impl DaemonStatus {
+    /// Default state file (`$XDG_STATE_HOME/keyrs/state.json`)
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::state_dir().map(|dir| dir.join("keyrs").join("state.json"))
+    }
+
+    /// Read a state file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Read the default state file, if the daemon has written one
+    pub fn load_default() -> Option<Self> {
+        Self::default_path().and_then(|path| Self::load(&path).ok())
+    }
+
+    /// Write the state file, replacing it atomically so readers never see a partial file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json + "\n")?;
+        fs::rename(&tmp, path)
+    }
+
+    fn signal_args(&self) -> (bool, bool, String, Vec<String>) {
+        (
+            self.enabled,
+            self.suspended,
+            self.config
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            self.devices.clone(),
+        )
+    }
+}
+
+/// Live daemon state answered over the control socket (`keyrs status`)
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LiveStatus {
+    #[serde(flatten)]
+    pub daemon: DaemonStatus,
+    /// Keyboard type used by `keyboard_type` conditions
+    pub keyboard_type: Option<String>,
+    /// Class of the focused window
+    pub wm_class: Option<String>,
+    /// Dead key waiting for its letter (`´`), or compose sequence (`⎄ minus`)
+    #[serde(default)]
+    pub dead_key: Option<String>,
+    /// Active `[profiles]` config
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Output format of `keyrs status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusFormat {
+    /// One `name: value` line per field (default)
+    #[default]
+    Text,
+    /// Waybar custom module JSON (`return-type: json`)
+    Waybar,
+    /// A single line for polybar `custom/script`
+    Polybar,
+}
+
+impl StatusFormat {
+    /// Parse a format name as used in `keyrs status --format`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "text" | "plain" => Some(Self::Text),
+            "waybar" | "json" => Some(Self::Waybar),
+            "polybar" => Some(Self::Polybar),
+            _ => None,
+        }
+    }
+}
+
+impl LiveStatus {
+    /// Bar class: `active`, `suspended` or `stopped`
+    pub fn class(status: Option<&Self>) -> &'static str {
+        match status {
+            Some(status) if status.daemon.suspended => "suspended",
+            Some(status) if status.daemon.enabled => "active",
+            _ => "stopped",
+        }
+    }
+
+    /// Render `status` (`None` when no daemon answered) in `format`
+    pub fn render(status: Option<&Self>, format: StatusFormat) -> String {
+        let class = Self::class(status);
+        let summary = match status {
+            Some(status) if !status.daemon.suspended && status.dead_key.is_some() => {
+                format!("{} pending…", status.dead_key.as_deref().unwrap_or_default())
+            }
+            Some(status) if !status.daemon.suspended && (status.keyboard_type.is_some() || status.wm_class.is_some()) => {
+                [status.keyboard_type.as_deref(), status.wm_class.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" · ")
+            }
+            _ => class.to_string(),
+        };
+        match format {
+            StatusFormat::Text => {
+                let mut lines = vec![format!("state: {}", class)];
+                if let Some(status) = status {
+                    let unknown = || "(unknown)".to_string();
+                    lines.push(format!("keyboard_type: {}", status.keyboard_type.clone().unwrap_or_else(unknown)));
+                    lines.push(format!("wm_class: {}", status.wm_class.clone().unwrap_or_else(unknown)));
+                    if let Some(dead_key) = &status.dead_key {
+                        lines.push(format!("dead_key: {} pending", dead_key));
+                    }
+                    if let Some(profile) = &status.profile {
+                        lines.push(format!("profile: {}", profile));
+                    }
+                    if let Some(config) = &status.daemon.config {
+                        lines.push(format!("config: {}", config.display()));
+                    }
+                    lines.push(format!("devices: {}", status.daemon.devices.join(", ")));
+                }
+                lines.join("\n")
+            }
+            StatusFormat::Waybar => {
+                let tooltip = match status {
+                    Some(status) => format!(
+                        "keyrs {}\nconfig: {}\ndevices: {}",
+                        class,
+                        status
+                            .daemon
+                            .config
+                            .as_ref()
+                            .map(|path| path.display().to_string())
+                            .unwrap_or_default(),
+                        status.daemon.devices.join(", ")
+                    ),
+                    None => "keyrs is not running".to_string(),
+                };
+                serde_json::json!({
+                    "text": summary,
+                    "alt": class,
+                    "class": class,
+                    "tooltip": tooltip,
+                })
+                .to_string()
+            }
+            StatusFormat::Polybar => format!("keyrs: {}", summary),
+        }
+    }
+}
+
+/// Publishes [`DaemonStatus`] changes to the state file and the session bus
+///
+/// Both outputs are best effort: a missing state directory or session bus
+/// is logged once and never stops the daemon.
+pub struct StatusPublisher {
+    path: Option<PathBuf>,
+    bus: Option<Connection>,
+    published: Option<DaemonStatus>,
+}
+
+impl StatusPublisher {
+    /// Publish to `path` (no file when `None`) and to the session bus, if reachable
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let bus = match Connection::session() {
+            Ok(bus) => Some(bus),
+            Err(e) => {
+                log::info!("Not emitting {} signals: {}", STATUS_DBUS_SIGNAL, e);
+                None
+            }
+        };
+        Self {
+            path,
+            bus,
+            published: None,
+        }
+    }
+
+    /// State file being written
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Publish `status` if it differs from the last one; returns whether it did
+    pub fn publish(&mut self, status: &DaemonStatus) -> bool {
+        if self.published.as_ref() == Some(status) {
+            return false;
+        }
+        if let Some(path) = &self.path {
+            if let Err(e) = status.save(path) {
+                log::warn!("Could not write state file {}: {}", path.display(), e);
+            }
+        }
+        if let Some(bus) = self.bus.as_ref() {
+            let args = status.signal_args();
+            if let Err(e) =
+                bus.emit_signal(None::<BusName>, STATUS_DBUS_PATH, STATUS_DBUS_INTERFACE, STATUS_DBUS_SIGNAL, &args)
+            {
+                log::warn!("Could not emit {} signal, giving up on D-Bus: {}", STATUS_DBUS_SIGNAL, e);
+                self.bus = None;
+            }
+        }
+        self.published = Some(status.clone());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("keyrs-status-{}", std::process::id()));
+        let path = dir.join("keyrs").join("state.json");
+        let status = DaemonStatus {
+            enabled: true,
+            suspended: true,
+            config: Some(PathBuf::from("/home/me/.config/keyrs/config.toml")),
+            devices: vec!["AT Translated Set 2 keyboard".to_string()],
+        };
+
+        let mut publisher = StatusPublisher {
+            path: Some(path.clone()),
+            bus: None,
+            published: None,
+        };
+        assert!(publisher.publish(&status));
+        assert!(!publisher.publish(&status));
+        assert_eq!(DaemonStatus::load(&path).unwrap(), status);
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"suspended\": true"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_waybar_status() {
+        let mut status = LiveStatus {
+            daemon: DaemonStatus {
+                enabled: true,
+                suspended: false,
+                config: Some(PathBuf::from("/etc/keyrs.toml")),
+                devices: vec!["kbd".to_string()],
+            },
+            keyboard_type: Some("Apple".to_string()),
+            wm_class: Some("firefox".to_string()),
+            dead_key: None,
+            profile: Some("mac-style".to_string()),
+        };
+        let render = |status: Option<&LiveStatus>| -> serde_json::Value {
+            serde_json::from_str(&LiveStatus::render(status, StatusFormat::Waybar)).unwrap()
+        };
+
+        let active = render(Some(&status));
+        assert_eq!(active["text"], "Apple · firefox");
+        assert_eq!(active["class"], "active");
+        assert!(active["tooltip"].as_str().unwrap().contains("/etc/keyrs.toml"));
+        assert!(LiveStatus::render(Some(&status), StatusFormat::Text).contains("profile: mac-style"));
+
+        status.dead_key = Some("´".to_string());
+        assert_eq!(render(Some(&status))["text"], "´ pending…");
+        assert!(LiveStatus::render(Some(&status), StatusFormat::Text).contains("dead_key: ´ pending"));
+        assert_eq!(LiveStatus::render(Some(&status), StatusFormat::Polybar), "keyrs: ´ pending…");
+
+        status.daemon.suspended = true;
+        assert_eq!(render(Some(&status))["class"], "suspended");
+        assert_eq!(render(None)["class"], "stopped");
+        assert_eq!(LiveStatus::render(None, StatusFormat::Polybar), "keyrs: stopped");
+
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.contains("\"suspended\":true") && json.contains("\"wm_class\":\"firefox\""));
+        assert_eq!(serde_json::from_str::<LiveStatus>(&json).unwrap(), status);
+    }
+}
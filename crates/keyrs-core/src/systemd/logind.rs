@@ -0,0 +1,224 @@
+// Keyrs Session Monitor
+// Poll logind so remapping can pause while the screen is locked or the
+// session is in the background (fast user switching)
+
+use std::time::{Duration, Instant};
+
+use zbus::blocking::Connection;
+use zbus::proxy;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedObjectPath;
+
+use super::SystemdError;
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    gen_async = false
+)]
+trait Session {
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn seat(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+/// Default interval between `LockedHint` queries
+pub const DEFAULT_LOCK_POLL_MS: u64 = 1000;
+
+/// Object path of a logind session.
+///
+/// Uses `$XDG_SESSION_ID` when set; otherwise `auto`, which logind resolves
+/// to the caller's session or the user's graphical session (the case for a
+/// systemd user service).
+pub fn session_object_path(session_id: Option<&str>) -> String {
+    match session_id.filter(|id| !id.is_empty()) {
+        Some(id) => format!("/org/freedesktop/login1/session/{}", encode_path_label(id)),
+        None => "/org/freedesktop/login1/session/auto".to_string(),
+    }
+}
+
+/// Escape a string as one object path element (sd_bus_path_encode rules)
+fn encode_path_label(label: &str) -> String {
+    let mut out = String::with_capacity(label.len());
+    for (i, byte) in label.bytes().enumerate() {
+        if byte.is_ascii_alphabetic() || (byte.is_ascii_digit() && i > 0) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    out
+}
+
+/// Seat id from a logind `Seat` property value (`(so)`, empty id = no seat)
+fn seat_id((id, _path): (String, OwnedObjectPath)) -> Option<String> {
+    (!id.is_empty()).then_some(id)
+}
+
+/// Lock and foreground state of the login session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionState {
+    /// logind `LockedHint`
+    pub locked: bool,
+    /// logind `Active`: the session is in the foreground of its seat
+    pub active: bool,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            locked: false,
+            active: true,
+        }
+    }
+}
+
+/// Tracks whether the login session is locked (logind `LockedHint`) and
+/// whether it is the active session of its seat (logind `Active`).
+///
+/// Desktop lockers (GNOME, KDE, and `loginctl lock-session` setups) set the
+/// hint; lockers that don't are not detected.
+pub struct SessionMonitor {
+    session: Option<SessionProxy<'static>>,
+    session_path: String,
+    interval: Duration,
+    last_poll: Option<Instant>,
+    watch_lock: bool,
+    watch_active: bool,
+    state: SessionState,
+    /// Avoid repeating the same warning every poll while the bus is down
+    warned: bool,
+}
+
+impl SessionMonitor {
+    /// Monitor the current session, querying at most every `interval`
+    /// whichever of the lock and active state are watched
+    pub fn new(interval: Duration, watch_lock: bool, watch_active: bool) -> Self {
+        let session_id = std::env::var("XDG_SESSION_ID").ok();
+        Self {
+            session: None,
+            session_path: session_object_path(session_id.as_deref()),
+            interval,
+            last_poll: None,
+            watch_lock,
+            watch_active,
+            state: SessionState::default(),
+            warned: false,
+        }
+    }
+
+    /// State at the last query
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Whether the session was locked at the last query
+    pub fn is_locked(&self) -> bool {
+        self.state.locked
+    }
+
+    /// Seat the session runs on (`None` without one, e.g. over SSH, or
+    /// when logind can't be asked)
+    pub fn seat(&mut self) -> Option<String> {
+        match self.session().and_then(|session| session.seat().map_err(SystemdError::from)) {
+            Ok(seat) => seat_id(seat),
+            Err(e) => {
+                log::debug!("Cannot read the session seat from logind: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Time until `poll` queries logind again
+    pub fn next_poll_in(&self) -> Duration {
+        self.last_poll
+            .map_or(Duration::ZERO, |last| self.interval.saturating_sub(last.elapsed()))
+    }
+
+    /// Query logind when the interval has elapsed; returns the new state when it changed
+    pub fn poll(&mut self) -> Option<SessionState> {
+        if self
+            .last_poll
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return None;
+        }
+        self.last_poll = Some(Instant::now());
+
+        let state = match self.query() {
+            Ok(state) => {
+                self.warned = false;
+                state
+            }
+            Err(e) => {
+                self.session = None;
+                if !self.warned {
+                    log::warn!("Cannot read the session state from logind: {}", e);
+                    self.warned = true;
+                }
+                // Unknown: keep remapping rather than stay paused indefinitely
+                SessionState::default()
+            }
+        };
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        Some(state)
+    }
+
+    fn query(&mut self) -> Result<SessionState, SystemdError> {
+        let (watch_lock, watch_active) = (self.watch_lock, self.watch_active);
+        let session = self.session()?;
+        let mut state = SessionState::default();
+        if watch_lock {
+            state.locked = session.locked_hint()?;
+        }
+        if watch_active {
+            state.active = session.active()?;
+        }
+        Ok(state)
+    }
+
+    /// Proxy for the session on the system bus, connecting on first use
+    fn session(&mut self) -> Result<&SessionProxy<'static>, SystemdError> {
+        if self.session.is_none() {
+            let bus = Connection::system()?;
+            // Properties are read on each poll; don't keep a PropertiesChanged subscription
+            let session = SessionProxy::builder(&bus)
+                .path(self.session_path.clone())?
+                .cache_properties(CacheProperties::No)
+                .build()?;
+            self.session = Some(session);
+        }
+        self.session
+            .as_ref()
+            .ok_or_else(|| SystemdError::Bus("not connected".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_object_path() {
+        assert_eq!(session_object_path(None), "/org/freedesktop/login1/session/auto");
+        assert_eq!(session_object_path(Some("")), "/org/freedesktop/login1/session/auto");
+        assert_eq!(session_object_path(Some("2")), "/org/freedesktop/login1/session/_32");
+        assert_eq!(session_object_path(Some("c1")), "/org/freedesktop/login1/session/c1");
+        assert_eq!(session_object_path(Some("a-b")), "/org/freedesktop/login1/session/a_2db");
+    }
+
+    #[test]
+    fn test_seat_id() {
+        let seat = |id: &str, path: &str| (id.to_string(), OwnedObjectPath::try_from(path).unwrap());
+        assert_eq!(seat_id(seat("seat0", "/org/freedesktop/login1/seat/seat0")), Some("seat0".to_string()));
+        assert_eq!(seat_id(seat("", "/")), None);
+    }
+}
@@ -0,0 +1,269 @@
+// Keyrs Systemd Integration
+// Control the keyrs user service through the systemd D-Bus API
+
+mod logind;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zbus::blocking::Connection;
+use zbus::proxy;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedObjectPath;
+
+pub use logind::{session_object_path, SessionMonitor, SessionState, DEFAULT_LOCK_POLL_MS};
+
+/// Name of the keyrs user unit
+pub const UNIT_NAME: &str = "keyrs.service";
+
+/// Errors talking to the systemd user manager
+#[derive(Debug, thiserror::Error)]
+pub enum SystemdError {
+    #[error("Cannot reach the session bus: {0}")]
+    Bus(String),
+
+    #[error("{name}: {message}")]
+    Call { name: String, message: String },
+
+    #[error("D-Bus protocol error: {0}")]
+    Protocol(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<zbus::Error> for SystemdError {
+    fn from(e: zbus::Error) -> Self {
+        match e {
+            zbus::Error::MethodError(name, message, _) => SystemdError::Call {
+                name: name.to_string(),
+                message: message.unwrap_or_default(),
+            },
+            zbus::Error::Variant(e) => SystemdError::Protocol(e.to_string()),
+            zbus::Error::InputOutput(e) => SystemdError::Io(std::io::Error::new(e.kind(), e.to_string())),
+            e => SystemdError::Bus(e.to_string()),
+        }
+    }
+}
+
+/// `(type, file, destination)` of each symlink change made by `EnableUnitFiles`/`DisableUnitFiles`
+type UnitFileChanges = Vec<(String, String, String)>;
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1",
+    gen_async = false
+)]
+trait Manager {
+    fn start_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn stop_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn restart_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn enable_unit_files(
+        &self,
+        files: &[&str],
+        runtime: bool,
+        force: bool,
+    ) -> zbus::Result<(bool, UnitFileChanges)>;
+
+    fn disable_unit_files(&self, files: &[&str], runtime: bool) -> zbus::Result<UnitFileChanges>;
+
+    fn reload(&self) -> zbus::Result<()>;
+
+    fn load_unit(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn get_unit_file_state(&self, file: &str) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1",
+    gen_async = false
+)]
+trait Unit {
+    #[zbus(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn sub_state(&self) -> zbus::Result<String>;
+}
+
+/// Runtime and install state of a unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitState {
+    /// `active`, `inactive`, `failed`, `activating`, ...
+    pub active_state: String,
+    /// `running`, `dead`, `auto-restart`, ...
+    pub sub_state: String,
+    /// `enabled`, `disabled`, `static`, ... (empty when the unit file is missing)
+    pub unit_file_state: String,
+}
+
+/// Connection to the per-user systemd instance
+pub struct SystemdUser {
+    bus: Connection,
+    manager: ManagerProxy<'static>,
+}
+
+impl SystemdUser {
+    /// Connect to the user manager over the session bus
+    pub fn connect() -> Result<Self, SystemdError> {
+        let bus = Connection::session()?;
+        let manager = ManagerProxy::builder(&bus).cache_properties(CacheProperties::No).build()?;
+        Ok(Self { bus, manager })
+    }
+
+    /// Queue a start job for `unit`
+    pub fn start(&mut self, unit: &str) -> Result<(), SystemdError> {
+        self.manager.start_unit(unit, "replace")?;
+        Ok(())
+    }
+
+    /// Queue a stop job for `unit`
+    pub fn stop(&mut self, unit: &str) -> Result<(), SystemdError> {
+        self.manager.stop_unit(unit, "replace")?;
+        Ok(())
+    }
+
+    /// Queue a restart job for `unit` (starts it when inactive)
+    pub fn restart(&mut self, unit: &str) -> Result<(), SystemdError> {
+        self.manager.restart_unit(unit, "replace")?;
+        Ok(())
+    }
+
+    /// Enable `unit` so it starts with the session
+    pub fn enable(&mut self, unit: &str) -> Result<(), SystemdError> {
+        self.manager.enable_unit_files(&[unit], false, true)?;
+        Ok(())
+    }
+
+    /// Disable `unit`
+    pub fn disable(&mut self, unit: &str) -> Result<(), SystemdError> {
+        self.manager.disable_unit_files(&[unit], false)?;
+        Ok(())
+    }
+
+    /// Reload unit files (`systemctl --user daemon-reload`)
+    pub fn reload(&mut self) -> Result<(), SystemdError> {
+        self.manager.reload()?;
+        Ok(())
+    }
+
+    /// Read `ActiveState`, `SubState` and the unit file state of `unit`
+    pub fn unit_state(&mut self, unit: &str) -> Result<UnitState, SystemdError> {
+        let path = self.manager.load_unit(unit)?;
+        let proxy = UnitProxy::builder(&self.bus)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()?;
+        let active_state = proxy.active_state()?;
+        let sub_state = proxy.sub_state()?;
+        let unit_file_state = match self.manager.get_unit_file_state(unit) {
+            Ok(state) => state,
+            // No unit file installed
+            Err(zbus::Error::MethodError(..)) => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(UnitState {
+            active_state,
+            sub_state,
+            unit_file_state,
+        })
+    }
+}
+
+/// Contents of the keyrs user unit running `keyrs_path` with `config_path`
+pub fn unit_file_contents(keyrs_path: &Path, config_path: &Path) -> String {
+    format!(
+        "[Unit]
+Description=keyrs keyboard remapper
+After=graphical-session.target
+Wants=graphical-session.target
+
+[Service]
+Type=simple
+ExecStart={} --config {}
+Restart=on-failure
+RestartSec=2
+StandardOutput=journal
+StandardError=journal
+
+[Install]
+WantedBy=default.target
+",
+        unit_path_arg(keyrs_path),
+        unit_path_arg(config_path)
+    )
+}
+
+/// Quote a path for `ExecStart=` when it contains whitespace or quotes
+fn unit_path_arg(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if text.chars().any(|c| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        text.into_owned()
+    }
+}
+
+/// `$XDG_CONFIG_HOME/systemd/user/keyrs.service`
+pub fn user_unit_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("systemd").join("user").join(UNIT_NAME))
+}
+
+/// `$XDG_CONFIG_HOME/keyrs/config.toml`, the config the unit runs by default
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("keyrs").join("config.toml"))
+}
+
+/// Write the user unit, reload systemd and enable it; returns the unit path
+///
+/// An existing unit with different contents is kept as `keyrs.service.bak`.
+pub fn install_user_unit(keyrs_path: &Path, config_path: &Path) -> Result<PathBuf, SystemdError> {
+    let path = user_unit_path()
+        .ok_or_else(|| SystemdError::Bus("cannot determine the user config directory".to_string()))?;
+    let contents = unit_file_contents(keyrs_path, config_path);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::read_to_string(&path) {
+        Ok(existing) if existing != contents => {
+            fs::copy(&path, path.with_extension("service.bak"))?;
+        }
+        _ => {}
+    }
+    fs::write(&path, contents)?;
+
+    let mut systemd = SystemdUser::connect()?;
+    systemd.reload()?;
+    systemd.enable(UNIT_NAME)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_file_contents() {
+        let unit = unit_file_contents(
+            Path::new("/home/u/.local/bin/keyrs"),
+            Path::new("/home/u/.config/keyrs/config.toml"),
+        );
+        assert!(unit.contains(
+            "ExecStart=/home/u/.local/bin/keyrs --config /home/u/.config/keyrs/config.toml\n"
+        ));
+        assert!(unit.contains("Restart=on-failure"));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+
+    #[test]
+    fn test_unit_file_quotes_paths_with_spaces() {
+        let unit = unit_file_contents(Path::new("/opt/key rs/keyrs"), Path::new("/c.toml"));
+        assert!(unit.contains("ExecStart=\"/opt/key rs/keyrs\" --config /c.toml\n"));
+    }
+}
@@ -0,0 +1,104 @@
+// Keyrs Transform Bindings
+// Conditional keymaps, modmaps and layers active in the current context
+
+use crate::input::KeyboardType;
+
+use super::engine::{TransformConfig, WindowContext};
+
+/// Which keymaps, modmaps and layers apply in one condition context
+///
+/// Conditions only depend on the window context and settings, so they are
+/// evaluated once per change of either instead of on every key event.
+#[derive(Debug, Clone)]
+pub struct ActiveBindings {
+    wm_class: Option<String>,
+    wm_name: Option<String>,
+    device_name: Option<String>,
+    keyboard_type: Option<KeyboardType>,
+    layout: Option<String>,
+    numlock_on: bool,
+    capslock_on: bool,
+    /// Engine binding generation (bumped by settings changes)
+    generation: u64,
+    /// Per `config.keymaps` entry
+    keymaps: Vec<bool>,
+    /// Per `config.modmaps` entry
+    modmaps: Vec<bool>,
+    /// Per `config.layers` entry
+    layers: Vec<bool>,
+    /// The window matches an `[ignore]` pattern
+    ignored: bool,
+    /// `suspend_when` holds
+    suspended: bool,
+}
+
+impl ActiveBindings {
+    /// Evaluate every condition in `config` against `context`
+    pub fn evaluate(config: &TransformConfig, context: &WindowContext, generation: u64) -> Self {
+        let holds = |condition: Option<&crate::Condition>| condition.is_none_or(|c| context.matches(c));
+        Self {
+            wm_class: context.wm_class.clone(),
+            wm_name: context.wm_name.clone(),
+            device_name: context.device_name.clone(),
+            keyboard_type: context.keyboard_type,
+            layout: context.layout.clone(),
+            numlock_on: context.numlock_on,
+            capslock_on: context.capslock_on,
+            generation,
+            keymaps: config.keymaps.iter().map(|k| holds(k.condition())).collect(),
+            modmaps: config.modmaps.iter().map(|m| holds(m.condition())).collect(),
+            layers: config.layers.iter().map(|l| holds(l.condition())).collect(),
+            ignored: context.is_ignored(&config.ignore_wm_class, &config.ignore_wm_name),
+            suspended: config.suspend_when.as_ref().is_some_and(|c| context.matches(c)),
+        }
+    }
+
+    /// Whether these results still hold for `context`
+    pub fn is_current(&self, context: &WindowContext, generation: u64) -> bool {
+        self.generation == generation
+            && self.numlock_on == context.numlock_on
+            && self.capslock_on == context.capslock_on
+            && self.keyboard_type == context.keyboard_type
+            && self.wm_class == context.wm_class
+            && self.wm_name == context.wm_name
+            && self.device_name == context.device_name
+            && self.layout == context.layout
+    }
+
+    pub fn ignored(&self) -> bool {
+        self.ignored
+    }
+
+    pub fn suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn keymap(&self, index: usize) -> bool {
+        self.keymaps.get(index).copied().unwrap_or(false)
+    }
+
+    pub fn modmap(&self, index: usize) -> bool {
+        self.modmaps.get(index).copied().unwrap_or(false)
+    }
+
+    pub fn layer(&self, index: usize) -> bool {
+        self.layers.get(index).copied().unwrap_or(false)
+    }
+
+    /// Names of the active keymaps, conditional modmaps and layers
+    pub fn names(&self, config: &TransformConfig) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let keymaps = config.keymaps.iter().enumerate()
+            .filter(|(i, _)| self.keymap(*i))
+            .map(|(_, k)| k.name().to_string())
+            .collect();
+        let modmaps = config.modmaps.iter().enumerate()
+            .filter(|(i, m)| self.modmap(*i) && m.condition().is_some())
+            .map(|(_, m)| m.name().to_string())
+            .collect();
+        let layers = config.layers.iter().enumerate()
+            .filter(|(i, _)| self.layer(*i))
+            .map(|(_, l)| l.name().to_string())
+            .collect();
+        (keymaps, modmaps, layers)
+    }
+}
@@ -1,7 +1,7 @@
 // Keyrs Transform Combo Matching
 // Core combo matching logic
 
-use crate::{Combo, ComboHint, Key, Keymap, KeymapValue, Modifier};
+use crate::{Combo, ComboHint, Key, Keymap, KeymapValue, LayerAction, Modifier};
 
 /// Result of a combo match operation
 #[derive(Debug, Clone, PartialEq)]
@@ -20,6 +20,12 @@ pub enum ComboMatchResult {
     FoundUnicode(u32),
     /// Found a combo with text output
     FoundText(String),
+    /// Found a combo that activates a layer
+    FoundLayer(LayerAction),
+    /// Found a combo that enters a nested keymap
+    FoundEnterKeymap(String),
+    /// Found a combo explicitly left unmapped
+    FoundPassthrough,
 }
 
 /// Try to find a matching combo in the keymaps
@@ -43,6 +49,9 @@ pub fn find_combo_match(modifiers: &[Modifier], key: Key, keymaps: &[Keymap]) ->
                 KeymapValue::ComboHint(h) => ComboMatchResult::FoundHint(*h),
                 KeymapValue::Unicode(codepoint) => ComboMatchResult::FoundUnicode(*codepoint),
                 KeymapValue::Text(text) => ComboMatchResult::FoundText(text.clone()),
+                KeymapValue::Layer(layer) => ComboMatchResult::FoundLayer(layer.clone()),
+                KeymapValue::EnterKeymap(name) => ComboMatchResult::FoundEnterKeymap(name.clone()),
+                KeymapValue::Passthrough => ComboMatchResult::FoundPassthrough,
             };
         }
     }
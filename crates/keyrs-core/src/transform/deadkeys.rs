@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::clock::{system_clock, Clock};
 use crate::Key;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +27,29 @@ impl DeadKeyKind {
         }
     }
 
+    /// Dead key named in an optspec table (`acute`, `grave`, ...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "acute" => Some(Self::Acute),
+            "grave" => Some(Self::Grave),
+            "tilde" => Some(Self::Tilde),
+            "umlaut" => Some(Self::Umlaut),
+            "circumflex" => Some(Self::Circumflex),
+            _ => None,
+        }
+    }
+
+    /// XCompose keysym name of this dead key
+    pub fn keysym_name(self) -> &'static str {
+        match self {
+            Self::Acute => "dead_acute",
+            Self::Grave => "dead_grave",
+            Self::Tilde => "dead_tilde",
+            Self::Umlaut => "dead_diaeresis",
+            Self::Circumflex => "dead_circumflex",
+        }
+    }
+
     pub fn display_codepoint(self) -> u32 {
         match self {
             Self::Acute => 0x00B4,
@@ -34,9 +61,264 @@ impl DeadKeyKind {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Keysym name that starts a compose-key sequence
+const MULTI_KEY: &str = "Multi_key";
+/// Nested `include` depth limit for XCompose files
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+struct ComposeNode {
+    children: HashMap<String, ComposeNode>,
+    output: Option<String>,
+}
+
+/// Result of looking up a keysym sequence in a compose table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeLookup<'a> {
+    /// The sequence is complete
+    Output(&'a str),
+    /// The sequence is the start of a longer one
+    Prefix,
+    /// No sequence starts this way
+    NoMatch,
+}
+
+/// Compose sequences from XCompose files, stored as a trie of keysym names
+#[derive(Debug, Clone, Default)]
+pub struct ComposeTable {
+    root: ComposeNode,
+    len: usize,
+}
+
+impl ComposeTable {
+    /// Parse XCompose content, ignoring `include` lines
+    pub fn parse(content: &str) -> Self {
+        let mut table = Self::default();
+        for line in content.lines() {
+            if let Some((sequence, output)) = parse_compose_line(line) {
+                table.insert(&sequence, output);
+            }
+        }
+        table
+    }
+
+    /// Load an XCompose file, following `include` directives
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let mut table = Self::default();
+        table.load_into(path, 0)?;
+        Ok(table)
+    }
+
+    /// Load the user's compose table: `$XCOMPOSEFILE`, then `~/.XCompose`,
+    /// then the system table for the current locale
+    pub fn load_default() -> Option<Self> {
+        let user_file = std::env::var_os("XCOMPOSEFILE")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".XCompose")))
+            .filter(|path| path.exists());
+        let path = user_file.or_else(system_compose_path)?;
+        match Self::load(&path) {
+            Ok(table) => {
+                log::info!("Loaded {} compose sequences from {}", table.len(), path.display());
+                Some(table)
+            }
+            Err(e) => {
+                log::warn!("Could not load compose table {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn load_into(&mut self, path: &Path, depth: usize) -> std::io::Result<()> {
+        let content = std::fs::read_to_string(path)?;
+        for line in content.lines() {
+            if let Some(include) = parse_include_line(line) {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    log::warn!("Ignoring nested compose include {}", include);
+                    continue;
+                }
+                match expand_include_path(&include) {
+                    Some(included) => {
+                        if let Err(e) = self.load_into(&included, depth + 1) {
+                            log::warn!("Skipping compose include {}: {}", included.display(), e);
+                        }
+                    }
+                    None => log::warn!("Cannot resolve compose include {}", include),
+                }
+            } else if let Some((sequence, output)) = parse_compose_line(line) {
+                self.insert(&sequence, output);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add or replace a sequence (later definitions win, as in libX11)
+    pub fn insert(&mut self, sequence: &[String], output: String) {
+        let mut node = &mut self.root;
+        for keysym in sequence {
+            node = node.children.entry(keysym.clone()).or_default();
+        }
+        if node.output.replace(output).is_none() {
+            self.len += 1;
+        }
+    }
+
+    /// Look up a keysym sequence
+    pub fn lookup(&self, sequence: &[String]) -> ComposeLookup<'_> {
+        let mut node = &self.root;
+        for keysym in sequence {
+            match node.children.get(keysym) {
+                Some(child) => node = child,
+                None => return ComposeLookup::NoMatch,
+            }
+        }
+        if !node.children.is_empty() {
+            ComposeLookup::Prefix
+        } else if let Some(output) = &node.output {
+            ComposeLookup::Output(output)
+        } else {
+            ComposeLookup::NoMatch
+        }
+    }
+
+    /// Whether any sequence starts with the compose (`Multi_key`) key
+    pub fn has_multi_key(&self) -> bool {
+        self.root.children.contains_key(MULTI_KEY)
+    }
+
+    /// Number of sequences
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the table has no sequences
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Parse `<a> <b> : "out" keysym  # comment` into its keysyms and output string
+fn parse_compose_line(line: &str) -> Option<(Vec<String>, String)> {
+    let (lhs, rhs) = line.split_once(':')?;
+    let lhs = lhs.trim();
+    if !lhs.starts_with('<') {
+        return None;
+    }
+
+    let mut sequence = Vec::new();
+    for token in lhs.split_whitespace() {
+        let name = token.strip_prefix('<')?.strip_suffix('>')?;
+        sequence.push(name.to_string());
+    }
+
+    let output = parse_quoted(rhs.trim_start())?;
+    if sequence.is_empty() || output.is_empty() {
+        return None;
+    }
+    Some((sequence, output))
+}
+
+/// Parse a leading double-quoted XCompose string with its escapes
+fn parse_quoted(s: &str) -> Option<String> {
+    let mut chars = s.strip_prefix('"')?.chars().peekable();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'x' | 'X' => {
+                    let mut value = 0u32;
+                    while let Some(digit) = chars.peek().and_then(|d| d.to_digit(16)) {
+                        value = value * 16 + digit;
+                        chars.next();
+                    }
+                    out.push(char::from_u32(value)?);
+                }
+                d @ '0'..='7' => {
+                    let mut value = d.to_digit(8)?;
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|d| d.to_digit(8)) {
+                            Some(digit) => {
+                                value = value * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    out.push(char::from_u32(value)?);
+                }
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+fn parse_include_line(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix("include")?;
+    parse_quoted(rest.trim_start())
+}
+
+/// Expand `%H` (home), `%L` (locale Compose file) and `%S` (system locale dir)
+fn expand_include_path(include: &str) -> Option<PathBuf> {
+    if include.trim() == "%L" {
+        return system_compose_path();
+    }
+    let mut path = include.to_string();
+    if path.contains("%H") {
+        path = path.replace("%H", &dirs::home_dir()?.to_string_lossy());
+    }
+    if path.contains("%S") {
+        path = path.replace("%S", SYSTEM_LOCALE_DIR);
+    }
+    Some(PathBuf::from(path))
+}
+
+const SYSTEM_LOCALE_DIR: &str = "/usr/share/X11/locale";
+
+/// System Compose file for the current locale (via `compose.dir`)
+fn system_compose_path() -> Option<PathBuf> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "en_US.UTF-8".to_string());
+    let compose_dir = std::fs::read_to_string(Path::new(SYSTEM_LOCALE_DIR).join("compose.dir")).ok()?;
+    let relative = compose_dir_lookup(&compose_dir, &locale)?;
+    Some(Path::new(SYSTEM_LOCALE_DIR).join(relative))
+}
+
+/// Find the Compose file for `locale` in `compose.dir` content
+fn compose_dir_lookup(content: &str, locale: &str) -> Option<String> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .find(|(_, name)| name.trim() == locale)
+        .map(|(file, _)| file.trim().to_string())
+}
+
+/// Outcome of feeding a key press to an active dead key or compose sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeStep {
+    /// The sequence produced this text
+    Composed(String),
+    /// The sequence continues; swallow the key
+    Pending,
+    /// The sequence was abandoned; process the key normally
+    NoMatch,
+}
+
+#[derive(Debug, Clone)]
 struct ActiveDeadKey {
-    kind: DeadKeyKind,
+    /// Built-in dead key that started the sequence (None for the compose key)
+    kind: Option<DeadKeyKind>,
+    /// Keysym names typed so far, starting with the dead/compose key
+    sequence: Vec<String>,
     activated_at: Instant,
 }
 
@@ -44,6 +326,8 @@ struct ActiveDeadKey {
 pub struct DeadKeyState {
     active: Option<ActiveDeadKey>,
     timeout: Duration,
+    compose: Option<Arc<ComposeTable>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Default for DeadKeyState {
@@ -57,14 +341,27 @@ impl DeadKeyState {
         Self {
             active: None,
             timeout,
+            compose: None,
+            clock: system_clock(),
         }
     }
 
+    /// Time the sequence timeout with `clock`
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Use XCompose sequences; built-in accents stay as fallback
+    pub fn set_compose_table(&mut self, table: Option<Arc<ComposeTable>>) {
+        self.compose = table;
+    }
+
     pub fn activate_from_codepoint(&mut self, codepoint: u32) -> bool {
         if let Some(kind) = DeadKeyKind::from_codepoint(codepoint) {
             self.active = Some(ActiveDeadKey {
-                kind,
-                activated_at: Instant::now(),
+                kind: Some(kind),
+                sequence: vec![kind.keysym_name().to_string()],
+                activated_at: self.clock.now(),
             });
             true
         } else {
@@ -72,37 +369,156 @@ impl DeadKeyState {
         }
     }
 
+    /// Whether the compose table defines compose-key (`Multi_key`) sequences
+    pub fn has_multi_key(&self) -> bool {
+        self.compose.as_ref().is_some_and(|table| table.has_multi_key())
+    }
+
+    /// Start a compose-key sequence; false when the table has none
+    pub fn activate_multi_key(&mut self) -> bool {
+        if !self.has_multi_key() {
+            return false;
+        }
+        self.active = Some(ActiveDeadKey {
+            kind: None,
+            sequence: vec![MULTI_KEY.to_string()],
+            activated_at: self.clock.now(),
+        });
+        true
+    }
+
     pub fn is_active(&self) -> bool {
         self.active.is_some()
     }
 
+    /// What the active sequence shows while it waits: the accent of a dead
+    /// key (`´`), or `⎄` and the keysyms typed so far for the compose key
+    pub fn pending_label(&self) -> Option<String> {
+        let active = self.active.as_ref()?;
+        let mut label = match active.kind {
+            Some(kind) => char::from_u32(kind.display_codepoint())?.to_string(),
+            None => "⎄".to_string(),
+        };
+        for keysym in &active.sequence[1..] {
+            label.push(' ');
+            label.push_str(keysym);
+        }
+        Some(label)
+    }
+
+    /// Time until the active sequence times out
+    pub fn expires_in(&self) -> Option<Duration> {
+        let active = self.active.as_ref()?;
+        let elapsed = self.clock.now().saturating_duration_since(active.activated_at);
+        Some(self.timeout.saturating_sub(elapsed))
+    }
+
+    /// End a sequence that has timed out; returns the bare accent of a dead
+    /// key still waiting for its letter (other sequences just end)
+    pub fn expire(&mut self) -> Option<char> {
+        if self.expires_in() != Some(Duration::ZERO) {
+            return None;
+        }
+        let active = self.active.take()?;
+        let kind = active.kind.filter(|_| active.sequence.len() == 1)?;
+        char::from_u32(kind.display_codepoint())
+    }
+
     pub fn clear(&mut self) {
         self.active = None;
     }
 
     pub fn try_compose(&mut self, key: Key, shift_pressed: bool) -> Option<u32> {
-        let active = self.active?;
+        match self.feed(key, shift_pressed) {
+            ComposeStep::Composed(text) => single_codepoint(&text),
+            _ => None,
+        }
+    }
 
-        if active.activated_at.elapsed() > self.timeout {
-            self.clear();
-            return None;
+    /// Feed the next (non-modifier) key press to the active sequence
+    pub fn feed(&mut self, key: Key, shift_pressed: bool) -> ComposeStep {
+        let Some(mut active) = self.active.take() else {
+            return ComposeStep::NoMatch;
+        };
+
+        if self.clock.now().saturating_duration_since(active.activated_at) > self.timeout {
+            return ComposeStep::NoMatch;
         }
 
+        if let (Some(table), Some(keysym)) = (&self.compose, keysym_name(key, shift_pressed)) {
+            active.sequence.push(keysym);
+            match table.lookup(&active.sequence) {
+                ComposeLookup::Output(text) => return ComposeStep::Composed(text.to_string()),
+                ComposeLookup::Prefix => {
+                    active.activated_at = self.clock.now();
+                    self.active = Some(active);
+                    return ComposeStep::Pending;
+                }
+                ComposeLookup::NoMatch => {}
+            }
+        }
+
+        // Built-in accents apply only to a dead key followed by a single key.
+        let Some(kind) = active.kind.filter(|_| active.sequence.len() <= 2) else {
+            return ComposeStep::NoMatch;
+        };
         let result = if key.code() == 57 {
             // Space commits the accent character itself.
-            Some(active.kind.display_codepoint())
+            char::from_u32(kind.display_codepoint())
         } else {
-            key_to_ascii_letter(key, shift_pressed)
-                .and_then(|ch| compose_letter(active.kind, ch))
-                .map(|ch| ch as u32)
+            key_to_ascii_letter(key, shift_pressed).and_then(|ch| compose_letter(kind, ch))
         };
 
         // Dead key is always consumed after the next press attempt.
-        self.clear();
-        result
+        match result {
+            Some(ch) => ComposeStep::Composed(ch.to_string()),
+            None => ComposeStep::NoMatch,
+        }
+    }
+}
+
+/// The codepoint of a one-character string
+pub fn single_codepoint(text: &str) -> Option<u32> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch as u32),
+        _ => None,
     }
 }
 
+/// XCompose keysym name for a key on a US layout
+fn keysym_name(key: Key, shift: bool) -> Option<String> {
+    if let Some(letter) = key_to_ascii_letter(key, shift) {
+        return Some(letter.to_string());
+    }
+    let (plain, shifted) = match key.code() {
+        2 => ("1", "exclam"),
+        3 => ("2", "at"),
+        4 => ("3", "numbersign"),
+        5 => ("4", "dollar"),
+        6 => ("5", "percent"),
+        7 => ("6", "asciicircum"),
+        8 => ("7", "ampersand"),
+        9 => ("8", "asterisk"),
+        10 => ("9", "parenleft"),
+        11 => ("0", "parenright"),
+        12 => ("minus", "underscore"),
+        13 => ("equal", "plus"),
+        26 => ("bracketleft", "braceleft"),
+        27 => ("bracketright", "braceright"),
+        39 => ("semicolon", "colon"),
+        40 => ("apostrophe", "quotedbl"),
+        41 => ("grave", "asciitilde"),
+        43 => ("backslash", "bar"),
+        51 => ("comma", "less"),
+        52 => ("period", "greater"),
+        53 => ("slash", "question"),
+        57 => ("space", "space"),
+        _ => return None,
+    };
+    Some(if shift { shifted } else { plain }.to_string())
+}
+
 fn key_to_ascii_letter(key: Key, uppercase: bool) -> Option<char> {
     let name = key.to_string();
     if name.len() == 1 {
@@ -217,6 +633,71 @@ mod tests {
         assert_eq!(out, Some('Ñ' as u32));
     }
 
+    const SAMPLE_COMPOSE: &str = r#"
+# custom sequences
+<dead_acute> <c>            : "ć" cacute
+<dead_acute> <space>        : "'" apostrophe
+<Multi_key> <minus> <greater> : "→" rightarrow  # arrow
+<Multi_key> <s> <h> <r> <u> <g> : "\xaf\\_(ツ)_/\xaf"
+include "%L"
+"#;
+
+    #[test]
+    fn test_compose_table_parse_and_lookup() {
+        let table = ComposeTable::parse(SAMPLE_COMPOSE);
+        assert_eq!(table.len(), 4);
+        assert!(table.has_multi_key());
+
+        let seq = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(table.lookup(&seq(&["dead_acute", "c"])), ComposeLookup::Output("ć"));
+        assert_eq!(table.lookup(&seq(&["Multi_key", "minus"])), ComposeLookup::Prefix);
+        assert_eq!(table.lookup(&seq(&["Multi_key", "minus", "greater"])), ComposeLookup::Output("→"));
+        assert_eq!(
+            table.lookup(&seq(&["Multi_key", "s", "h", "r", "u", "g"])),
+            ComposeLookup::Output("¯\\_(ツ)_/¯")
+        );
+        assert_eq!(table.lookup(&seq(&["dead_grave", "a"])), ComposeLookup::NoMatch);
+    }
+
+    #[test]
+    fn test_compose_table_overrides_and_falls_back_to_builtin() {
+        let mut state = DeadKeyState::default();
+        state.set_compose_table(Some(Arc::new(ComposeTable::parse(SAMPLE_COMPOSE))));
+
+        assert!(state.activate_from_codepoint(0x00B4));
+        assert_eq!(state.try_compose(Key::from(46), false), Some('ć' as u32)); // C
+        assert!(state.activate_from_codepoint(0x00B4));
+        assert_eq!(state.try_compose(Key::from(57), false), Some('\'' as u32)); // SPACE
+        assert!(state.activate_from_codepoint(0x00B4));
+        assert_eq!(state.try_compose(Key::from(18), false), Some('é' as u32)); // E (built-in)
+    }
+
+    #[test]
+    fn test_multi_key_sequence_waits_for_completion() {
+        let mut state = DeadKeyState::default();
+        assert!(!state.activate_multi_key());
+        state.set_compose_table(Some(Arc::new(ComposeTable::parse(SAMPLE_COMPOSE))));
+
+        assert!(state.activate_multi_key());
+        assert_eq!(state.feed(Key::from(12), false), ComposeStep::Pending); // MINUS
+        assert_eq!(state.feed(Key::from(52), true), ComposeStep::Composed("→".to_string())); // Shift-DOT
+        assert!(!state.is_active());
+
+        assert!(state.activate_multi_key());
+        assert_eq!(state.feed(Key::from(30), false), ComposeStep::NoMatch); // A
+        assert!(!state.is_active());
+    }
+
+    #[test]
+    fn test_compose_dir_lookup() {
+        let compose_dir = "# comment\nen_US.UTF-8/Compose:\t\ten_US.UTF-8\npt_BR.UTF-8/Compose:\tpt_BR.UTF-8\n";
+        assert_eq!(
+            compose_dir_lookup(compose_dir, "pt_BR.UTF-8").as_deref(),
+            Some("pt_BR.UTF-8/Compose")
+        );
+        assert_eq!(compose_dir_lookup(compose_dir, "C"), None);
+    }
+
     #[test]
     fn test_dead_key_timeout_clears_state() {
         let mut state = DeadKeyState::new(Duration::from_millis(1));
@@ -226,4 +707,44 @@ mod tests {
         assert_eq!(out, None);
         assert!(!state.is_active());
     }
+
+    #[test]
+    fn test_dead_key_expire_types_accent() {
+        let clock = crate::clock::ManualClock::new();
+        let mut state = DeadKeyState::new(Duration::from_millis(100));
+        state.set_clock(Arc::new(clock.clone()));
+
+        assert!(state.activate_from_codepoint(0x00A8));
+        assert_eq!(state.pending_label().as_deref(), Some("¨"));
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(state.expires_in(), Some(Duration::from_millis(60)));
+        assert_eq!(state.expire(), None);
+        clock.advance(Duration::from_millis(60));
+        assert_eq!(state.expire(), Some('¨'));
+        assert!(!state.is_active());
+        assert_eq!(state.expires_in(), None);
+
+        state.set_compose_table(Some(Arc::new(ComposeTable::parse(SAMPLE_COMPOSE))));
+        assert!(state.activate_multi_key());
+        assert_eq!(state.feed(Key::from(12), false), ComposeStep::Pending); // MINUS
+        assert_eq!(state.pending_label().as_deref(), Some("⎄ minus"));
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(state.expire(), None);
+        assert!(!state.is_active());
+    }
+
+    #[test]
+    fn test_dead_key_timeout_follows_clock() {
+        let clock = crate::clock::ManualClock::new();
+        let mut state = DeadKeyState::new(Duration::from_millis(100));
+        state.set_clock(Arc::new(clock.clone()));
+
+        assert!(state.activate_from_codepoint(0x00B4));
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(state.try_compose(Key::from(18), false), Some(0x00E9)); // é
+
+        assert!(state.activate_from_codepoint(0x00B4));
+        clock.advance(Duration::from_millis(101));
+        assert_eq!(state.try_compose(Key::from(18), false), None);
+    }
 }
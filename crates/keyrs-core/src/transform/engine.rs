@@ -16,7 +16,7 @@ use std::sync::Arc;
 #[cfg(feature = "pure-rust")]
 use std::time::{Duration, Instant};
 #[cfg(feature = "pure-rust")]
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[cfg(feature = "pure-rust")]
 use parking_lot::RwLock;
@@ -24,12 +24,26 @@ use parking_lot::RwLock;
 #[cfg(feature = "pure-rust")]
 use smallvec::SmallVec;
 
-use crate::mapping::{ActionStep, Keymap, KeymapValue, Modmap, MultiModmap, MultipurposeManager, MultipurposeResult};
-use crate::transform::deadkeys::DeadKeyState;
+use crate::audit::{AuditContext, AuditDump, AuditLog, AUDIT_DUMP_VERSION};
+use crate::clock::{system_clock, Clock};
+use crate::condition::{Condition, ConditionExpr, ConditionOp};
+use crate::mapping::{ActionStep, Keymap, KeymapValue, LayerMode, Modmap, MultiModmap, MultipurposeManager, MultipurposeResult, RepeatRate, TapDance, TapDanceResult};
+use crate::metrics::{Metrics, Stats};
+use crate::output::{ComboStyle, DelayOverride, OutputDelays};
+use crate::transform::bindings::ActiveBindings;
+use crate::transform::deadkeys::{single_codepoint, ComposeStep, ComposeTable, DeadKeyState};
+use crate::transform::layers::LayerState;
+use crate::transform::mark;
+use crate::transform::media::{is_media_key, MediaMapping};
+use crate::transform::numpad::{self, NUMPAD_NAV_SETTING};
+use crate::transform::trace::TraceStep;
 use crate::transform::ComboMatchResult;
-use crate::window::WindowContextProvider;
+use crate::window::{ChangeNotifier, WindowContextProvider};
 use crate::{Action, Combo, ComboHint, Key, Keystore, Modifier};
 
+/// Profile name of a config without `[profiles].name`
+pub const DEFAULT_PROFILE: &str = "default";
+
 /// Configuration for transform engine
 #[derive(Debug, Clone)]
 pub struct TransformConfig {
@@ -37,14 +51,58 @@ pub struct TransformConfig {
     pub modmaps: Vec<Modmap>,
     /// Multi-modifier maps
     pub multimodmaps: Vec<MultiModmap>,
+    /// Tap-dance entries (N taps -> Nth output, optional hold)
+    pub tapdances: Vec<TapDance>,
+    /// Modifiers that emit a key or combo when tapped alone
+    pub modtaps: Vec<(Key, Combo)>,
     /// Keymaps for combo matching
     pub keymaps: Vec<Keymap>,
+    /// Layer keymaps, consulted before `keymaps` while the layer of the same name is active
+    pub layers: Vec<Keymap>,
     /// Suspend key (optional)
     pub suspend_key: Option<Key>,
     /// Multipurpose timeout (milliseconds)
     pub multipurpose_timeout: Option<u64>,
     /// Suspend timeout (milliseconds)
     pub suspend_timeout: Option<u64>,
+    /// How long a nested keymap stays entered (milliseconds, `None` = until left);
+    /// a keymap's own `nested_timeout` takes precedence
+    pub nested_keymap_timeout: Option<u64>,
+    /// Longest modifier tap that still counts (milliseconds)
+    pub modtap_timeout: Option<u64>,
+    /// Window in which a momentary layer trigger rolled over by the next key
+    /// types both keys instead of using the layer (milliseconds, off when `None`)
+    pub layer_rollover_timeout: Option<u64>,
+    /// Keys that type their shifted form when held (sorted, empty = autoshift off)
+    pub autoshift_keys: Vec<Key>,
+    /// Hold time before an autoshift key types its shifted form (milliseconds)
+    pub autoshift_timeout: Option<u64>,
+    /// A dead key left this long without a letter types its bare accent
+    /// (milliseconds); `None` drops it silently after 2 s, at the next key
+    pub dead_key_timeout: Option<u64>,
+    /// `[ignore]` patterns: windows whose class matches get every key untouched
+    pub ignore_wm_class: Vec<String>,
+    /// `[ignore]` patterns: windows whose title matches get every key untouched
+    pub ignore_wm_name: Vec<String>,
+    /// `suspend_when`: while it holds, remapping is suspended as with the
+    /// suspend key, and held keys are released on entering
+    pub suspend_when: Option<Condition>,
+    /// Right Alt is AltGr in the keyboard layout: while it is held, generic
+    /// `Alt-` combos don't match, so unmapped keys still type level 3 symbols
+    pub altgr: bool,
+    /// `[delays]` output pacing
+    pub output_delays: OutputDelays,
+    /// `[[delays.overrides]]`, first matching one wins
+    pub delay_overrides: Vec<DelayOverride>,
+    /// Defaults of the settings flags entries are gated on (`toggle_setting`),
+    /// applied to every `Settings` the engine is given
+    pub setting_defaults: Vec<(String, bool)>,
+    /// `[mediamap]` entries, applied before every other mapping
+    pub mediamap: HashMap<Key, MediaMapping>,
+    /// Media keys skip modmaps, keymaps and layers (after `mediamap`)
+    pub media_passthrough: bool,
+    /// Name of this config among the profiles the engine switches between
+    pub profile: String,
 }
 
 impl Default for TransformConfig {
@@ -53,10 +111,29 @@ impl Default for TransformConfig {
         Self {
             modmaps: vec![Modmap::new("default", HashMap::new())],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: Some(500),
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: Some(175),
+            dead_key_timeout: None,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            profile: DEFAULT_PROFILE.to_string(),
         }
     }
 }
@@ -101,237 +178,12 @@ pub struct WindowContext {
     pub capslock_on: bool,
     /// Keyboard type for keyboard-specific modmaps
     pub keyboard_type: Option<crate::input::KeyboardType>,
+    /// Keyboard layout active in the desktop (`us`, `de(nodeadkeys)`)
+    pub layout: Option<String>,
     /// Settings for feature toggles
     pub settings: crate::settings::Settings,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ConditionToken {
-    LParen,
-    RParen,
-    And,
-    Or,
-    Not,
-    Eq,
-    Match,
-    Ident(String),
-    StringLit(String),
-    Bool(bool),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ConditionOp {
-    Eq,
-    Match,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ConditionExpr {
-    And(Box<ConditionExpr>, Box<ConditionExpr>),
-    Or(Box<ConditionExpr>, Box<ConditionExpr>),
-    Not(Box<ConditionExpr>),
-    Predicate {
-        field: String,
-        op: Option<ConditionOp>,
-        value: Option<String>,
-    },
-}
-
-struct ConditionParser {
-    tokens: Vec<ConditionToken>,
-    pos: usize,
-}
-
-impl ConditionParser {
-    fn new(tokens: Vec<ConditionToken>) -> Self {
-        Self { tokens, pos: 0 }
-    }
-
-    fn parse(mut self) -> Option<ConditionExpr> {
-        let expr = self.parse_or()?;
-        if self.peek().is_some() {
-            return None;
-        }
-        Some(expr)
-    }
-
-    fn parse_or(&mut self) -> Option<ConditionExpr> {
-        let mut left = self.parse_and()?;
-        while matches!(self.peek(), Some(ConditionToken::Or)) {
-            self.next();
-            let right = self.parse_and()?;
-            left = ConditionExpr::Or(Box::new(left), Box::new(right));
-        }
-        Some(left)
-    }
-
-    fn parse_and(&mut self) -> Option<ConditionExpr> {
-        let mut left = self.parse_not()?;
-        while matches!(self.peek(), Some(ConditionToken::And)) {
-            self.next();
-            let right = self.parse_not()?;
-            left = ConditionExpr::And(Box::new(left), Box::new(right));
-        }
-        Some(left)
-    }
-
-    fn parse_not(&mut self) -> Option<ConditionExpr> {
-        if matches!(self.peek(), Some(ConditionToken::Not)) {
-            self.next();
-            let inner = self.parse_not()?;
-            return Some(ConditionExpr::Not(Box::new(inner)));
-        }
-        self.parse_primary()
-    }
-
-    fn parse_primary(&mut self) -> Option<ConditionExpr> {
-        match self.peek()? {
-            ConditionToken::LParen => {
-                self.next();
-                let expr = self.parse_or()?;
-                if !matches!(self.next(), Some(ConditionToken::RParen)) {
-                    return None;
-                }
-                Some(expr)
-            }
-            ConditionToken::Ident(_) => self.parse_predicate(),
-            _ => None,
-        }
-    }
-
-    fn parse_predicate(&mut self) -> Option<ConditionExpr> {
-        let field = match self.next()? {
-            ConditionToken::Ident(s) => s,
-            _ => return None,
-        };
-
-        match self.peek() {
-            Some(ConditionToken::Eq) => {
-                self.next();
-                let value = self.parse_value()?;
-                Some(ConditionExpr::Predicate {
-                    field,
-                    op: Some(ConditionOp::Eq),
-                    value: Some(value),
-                })
-            }
-            Some(ConditionToken::Match) => {
-                self.next();
-                let value = self.parse_value()?;
-                Some(ConditionExpr::Predicate {
-                    field,
-                    op: Some(ConditionOp::Match),
-                    value: Some(value),
-                })
-            }
-            _ => Some(ConditionExpr::Predicate {
-                field,
-                op: None,
-                value: None,
-            }),
-        }
-    }
-
-    fn parse_value(&mut self) -> Option<String> {
-        match self.next()? {
-            ConditionToken::StringLit(s) => Some(s),
-            ConditionToken::Ident(s) => Some(s),
-            ConditionToken::Bool(b) => Some(if b { "true".to_string() } else { "false".to_string() }),
-            _ => None,
-        }
-    }
-
-    fn peek(&self) -> Option<&ConditionToken> {
-        self.tokens.get(self.pos)
-    }
-
-    fn next(&mut self) -> Option<ConditionToken> {
-        let token = self.tokens.get(self.pos).cloned();
-        if token.is_some() {
-            self.pos += 1;
-        }
-        token
-    }
-}
-
-fn tokenize_condition(condition: &str) -> Option<Vec<ConditionToken>> {
-    let chars: Vec<char> = condition.chars().collect();
-    let mut i = 0usize;
-    let mut out = Vec::new();
-
-    while i < chars.len() {
-        let ch = chars[i];
-        if ch.is_whitespace() {
-            i += 1;
-            continue;
-        }
-
-        match ch {
-            '(' => {
-                out.push(ConditionToken::LParen);
-                i += 1;
-            }
-            ')' => {
-                out.push(ConditionToken::RParen);
-                i += 1;
-            }
-            '=' => {
-                if i + 1 >= chars.len() {
-                    return None;
-                }
-                if chars[i + 1] == '=' {
-                    out.push(ConditionToken::Eq);
-                    i += 2;
-                } else if chars[i + 1] == '~' {
-                    out.push(ConditionToken::Match);
-                    i += 2;
-                } else {
-                    return None;
-                }
-            }
-            '\'' | '"' => {
-                let quote = ch;
-                i += 1;
-                let start = i;
-                while i < chars.len() && chars[i] != quote {
-                    i += 1;
-                }
-                if i >= chars.len() {
-                    return None;
-                }
-                let value: String = chars[start..i].iter().collect();
-                out.push(ConditionToken::StringLit(value));
-                i += 1;
-            }
-            _ => {
-                let start = i;
-                while i < chars.len() {
-                    let c = chars[i];
-                    if c.is_whitespace() || c == '(' || c == ')' || c == '=' {
-                        break;
-                    }
-                    i += 1;
-                }
-                if start == i {
-                    return None;
-                }
-                let word: String = chars[start..i].iter().collect();
-                let lowered = word.to_lowercase();
-                match lowered.as_str() {
-                    "and" => out.push(ConditionToken::And),
-                    "or" => out.push(ConditionToken::Or),
-                    "not" => out.push(ConditionToken::Not),
-                    "true" => out.push(ConditionToken::Bool(true)),
-                    "false" => out.push(ConditionToken::Bool(false)),
-                    _ => out.push(ConditionToken::Ident(word)),
-                }
-            }
-        }
-    }
-
-    Some(out)
-}
-
 fn contains_pattern(value: &str, pattern: &str) -> bool {
     let value_lower = value.to_lowercase();
     pattern.split('|').any(|raw| {
@@ -368,22 +220,29 @@ impl WindowContext {
         Self::default()
     }
 
+    /// Whether the window matches any `[ignore]` pattern
+    pub fn is_ignored(&self, wm_class: &[String], wm_name: &[String]) -> bool {
+        let any = |value: &Option<String>, patterns: &[String]| {
+            value.as_deref().is_some_and(|value| patterns.iter().any(|p| contains_pattern(value, p)))
+        };
+        any(&self.wm_class, wm_class) || any(&self.wm_name, wm_name)
+    }
+
     /// Check if condition matches this window context
     /// Conditions are in the form:
     ///   - "wm_class =~ 'Firefox'" or "wm_name =~ 'Chrome'"
     ///   - "keyboard_type =~ 'IBM'" or "keyboard_type =~ 'IBM, Chromebook'"
     ///   - "settings.Enter2Ent_Cmd" or "settings.Caps2Esc_Cmd"
+    ///
+    /// Parses `condition` on every call; mappings hold a pre-parsed
+    /// [`Condition`] for [`matches`](Self::matches) instead.
     pub fn matches_condition(&self, condition: &str) -> bool {
-        let condition = condition.trim();
-        let tokens = match tokenize_condition(condition) {
-            Some(tokens) => tokens,
-            None => return false,
-        };
-        let expr = match ConditionParser::new(tokens).parse() {
-            Some(expr) => expr,
-            None => return false,
-        };
-        self.eval_expr(&expr)
+        self.matches(&Condition::new(condition))
+    }
+
+    /// Check a pre-parsed condition against this window context
+    pub fn matches(&self, condition: &Condition) -> bool {
+        condition.expr().is_some_and(|expr| self.eval_expr(expr))
     }
 
     fn eval_expr(&self, expr: &ConditionExpr) -> bool {
@@ -419,8 +278,14 @@ impl WindowContext {
         let expected_lower = expected.to_lowercase();
 
         if let Some(setting_name) = field.strip_prefix("settings.") {
-            let expected_bool = matches!(expected_lower.as_str(), "true" | "1" | "yes" | "on");
-            return self.settings.get_bool(setting_name) == expected_bool;
+            return match self.settings.get(setting_name) {
+                Some(crate::settings::SettingValue::Text(value)) => value.eq_ignore_ascii_case(expected.trim()),
+                Some(crate::settings::SettingValue::Int(value)) => expected.trim().parse() == Ok(value),
+                _ => {
+                    let expected_bool = matches!(expected_lower.as_str(), "true" | "1" | "yes" | "on");
+                    self.settings.get_bool(setting_name) == expected_bool
+                }
+            };
         }
 
         match field.to_lowercase().as_str() {
@@ -445,6 +310,11 @@ impl WindowContext {
                 .keyboard_type
                 .map(|kb| kb.as_str().eq_ignore_ascii_case(expected))
                 .unwrap_or(false),
+            "layout" => self
+                .layout
+                .as_ref()
+                .map(|v| v.eq_ignore_ascii_case(expected))
+                .unwrap_or(false),
             _ => false,
         }
     }
@@ -454,6 +324,13 @@ impl WindowContext {
     }
 
     fn eval_match(&self, field: &str, pattern: &str) -> bool {
+        if let Some(setting_name) = field.strip_prefix("settings.") {
+            return self
+                .settings
+                .get(setting_name)
+                .is_some_and(|value| contains_pattern(&value.to_string(), pattern));
+        }
+
         match field.to_lowercase().as_str() {
             "wm_class" => self
                 .wm_class
@@ -474,6 +351,11 @@ impl WindowContext {
                 .keyboard_type
                 .map(|kb| kb.matches(pattern))
                 .unwrap_or(false),
+            "layout" => self
+                .layout
+                .as_ref()
+                .map(|v| contains_pattern(v, pattern))
+                .unwrap_or(false),
             _ => false,
         }
     }
@@ -523,6 +405,8 @@ pub struct KeymapStack {
     pub stack: Vec<String>,
     /// When this keymap was entered (for timeout)
     pub timeout_start: Option<Instant>,
+    /// How long the entered keymap stays active
+    pub timeout: Option<Duration>,
     /// Current active hints
     pub active_hints: Vec<ComboHint>,
 }
@@ -542,13 +426,14 @@ impl KeymapStack {
     fn clear(&mut self) {
         self.stack.clear();
         self.timeout_start = None;
+        self.timeout = None;
         self.active_hints.clear();
     }
 
     /// Check if we should timeout
-    fn should_timeout(&self, timeout: Duration) -> bool {
+    fn should_timeout(&self, now: Instant, timeout: Duration) -> bool {
         self.timeout_start
-            .map(|t| t.elapsed() >= timeout)
+            .map(|t| now.saturating_duration_since(t) >= timeout)
             .unwrap_or(false)
     }
 }
@@ -577,6 +462,58 @@ impl RepeatCache {
     }
 }
 
+/// Output repeated on a timer while the mapping's input is held
+#[derive(Debug, Clone)]
+struct SyntheticRepeat {
+    /// Physical key whose release ends the repeat
+    key: Key,
+    result: TransformResult,
+    interval: Duration,
+    next: Instant,
+}
+
+/// A keymap combo with a `hold` output, pressed and not yet known to be a
+/// tap or a hold; its tap output waits in `release_bindings`
+#[derive(Debug, Clone)]
+struct PendingTapHold {
+    key: Key,
+    hold: ComboMatchResult,
+    pressed_at: Instant,
+}
+
+/// A `[modtap]` modifier pressed on its own, waiting to see if it is tapped
+#[cfg(feature = "pure-rust")]
+#[derive(Debug, Clone)]
+struct PendingModTap {
+    trigger: Key,
+    output: Combo,
+    pressed_at: Instant,
+    /// Another key was pressed while the trigger was down
+    interrupted: bool,
+}
+
+/// A momentary layer trigger inside its roll-over window, not yet known to be
+/// a layer hold or a typed key
+#[cfg(feature = "pure-rust")]
+#[derive(Debug, Clone)]
+struct PendingLayer {
+    trigger: Key,
+    name: String,
+    pressed_at: Instant,
+    /// Key pressed after the trigger, held back until the roll-over is resolved
+    deferred: Option<Key>,
+}
+
+/// An `[autoshift]` key held down, not yet known to be a tap or a hold
+#[cfg(feature = "pure-rust")]
+#[derive(Debug, Clone)]
+struct PendingAutoShift {
+    key: Key,
+    pressed_at: Instant,
+    /// The shifted form was typed; the rest of the hold is swallowed
+    shifted: bool,
+}
+
 /// Pure Rust transform engine
 ///
 /// This contains all the transform logic implemented in pure Rust for maximum performance.
@@ -593,55 +530,105 @@ pub struct TransformEngine {
     multipurpose_manager: MultipurposeManager,
     /// Keymap stack for nested keymaps
     keymap_stack: KeymapStack,
-    /// Current hint state
-    escape_next: bool,
-    /// Current mark value
+    /// Keymaps some mapping enters with `keymap(name)`; they apply only once entered
+    nested_keymaps: HashSet<String>,
+    /// Armed `EscapeNextKey`/`EscapeNextCombo` hint
+    escape: Option<ComboHint>,
+    /// Physical keys whose press was escaped, until they are released
+    escaped_keys: HashSet<Key>,
+    /// Keypad keys pressed as navigation keys (`numpad_nav`), until released
+    numpad_nav_keys: HashMap<Key, Key>,
+    /// `Some(true)` while the mark is set (`SetMark`)
     mark: Option<bool>,
     /// Whether suspend mode is active
     suspend_mode: bool,
     /// Last time suspend key was pressed (for double-tap detection)
     last_suspend_press: Option<Instant>,
-    /// Track combos that have been matched on Press to prevent duplicate matches on Release
-    /// Stores (modifier_keys, output_key) tuples
-    active_combos: HashSet<(Vec<Key>, Key)>,
+    /// Physical keys whose press emitted a combo, until they are released.
+    /// The combo is tapped on press, so their release emits nothing, whatever
+    /// modifiers are held by then.
+    active_combos: HashSet<Key>,
     /// Dead key state for accent composition
     deadkeys: DeadKeyState,
+    /// Outputs produced as a side effect of the last event, to be emitted
+    /// before that event's result (e.g. a tap-dance resolved by an interrupt)
+    pending_outputs: Vec<(Key, Action)>,
+    /// Combo matching decision path (only recorded when tracing is enabled)
+    trace: Option<Vec<TraceStep>>,
+    /// Counters behind the diagnostics key's statistics table (always kept)
+    stats: Stats,
+    /// Event counters and latency histogram (only collected when enabled)
+    metrics: Option<Metrics>,
+    /// Last inputs and the outputs they produced (only recorded when enabled)
+    audit: Option<AuditLog>,
+    /// Repeat rate of the mapping matched by the current event
+    matched_repeat: Option<RepeatRate>,
+    /// Combo style of the keymap matched by the current event
+    matched_combo_style: Option<ComboStyle>,
+    /// Whether the mapping matched by the current event fires on release (`release:`)
+    matched_release: bool,
+    /// Hold output of the mapping matched by the current event
+    matched_hold: Option<ComboMatchResult>,
+    /// Keymap tap/hold mapping currently held undecided
+    tap_hold: Option<PendingTapHold>,
+    /// Keys held on a `release:` mapping and the match their release fires;
+    /// `None` once another key was pressed meanwhile
+    release_bindings: HashMap<Key, Option<ComboMatchResult>>,
+    /// Combo style of the last matched keymap, and whether the output has seen it
+    combo_style: ComboStyle,
+    combo_style_changed: bool,
+    /// Output delays last returned by `take_output_delays`
+    output_delays: OutputDelays,
+    /// Active `repeat = {...}` output, if a mapping with one is held
+    synthetic_repeat: Option<SyntheticRepeat>,
+    /// `[modtap]` trigger currently held alone
+    modtap: Option<PendingModTap>,
+    /// Setting changes requested by the current event, committed at the event boundary
+    pending_settings: Vec<(String, bool)>,
+    /// Incremented whenever a setting value or the set of active layers changes
+    binding_generation: u64,
+    /// Binding generation at press time and whether a keymap matched, per held key
+    press_generations: HashMap<Key, (u64, bool)>,
+    /// Active layers
+    layers: LayerState,
+    /// Momentary layer trigger waiting out its roll-over window
+    pending_layer: Option<PendingLayer>,
+    /// Results of events held back by `pending_layer`, resolved by the last
+    /// event and emitted before its pending outputs and result
+    deferred_results: Vec<(TransformResult, Action)>,
+    /// `[autoshift]` key currently held back
+    autoshift: Option<PendingAutoShift>,
+    /// Lock state comes from device LEDs, not from lock key presses
+    follow_lock_leds: bool,
+    /// Keyboard type per device name, applied as events arrive from that device
+    device_keyboard_types: HashMap<String, crate::input::KeyboardType>,
+    /// Keyboard type of devices without an entry in `device_keyboard_types`
+    default_keyboard_type: Option<crate::input::KeyboardType>,
+    /// Time source for tap/hold windows, timeouts and repeats
+    clock: Arc<dyn Clock>,
+    /// Condition results for the current context, refreshed when it changes
+    active_bindings: Option<ActiveBindings>,
+    /// Keys pressed in an ignored window, passed through until released
+    ignored_keys: HashSet<Key>,
+    /// Whether the focused window suspends remapping (`suspend_when`)
+    window_suspended: bool,
+    /// Keys held when `suspend_when` started to hold or the profile changed;
+    /// released then, so their repeats and releases are swallowed
+    suspended_held_keys: HashSet<Key>,
+    /// Profiles by name, in switching order; the active one's config is `config`
+    profiles: Vec<(String, Option<TransformConfig>)>,
+    /// Index of the active profile
+    active_profile: usize,
+    /// Profile switch requested by the current event (`Some(None)` = next),
+    /// made at the event boundary
+    pending_profile: Option<Option<String>>,
 }
 
 #[cfg(feature = "pure-rust")]
 impl TransformEngine {
     /// Create a new transform engine with given configuration
     pub fn new(config: TransformConfig) -> Self {
-        let timeout = config.multipurpose_timeout.unwrap_or(200);
-        let mut multipurpose_manager = MultipurposeManager::with_timeout(timeout);
-        
-        // Load multipurpose modmaps from config
-        for multimodmap in &config.multimodmaps {
-            multipurpose_manager.add_modmap(multimodmap.clone());
-        }
-        
-        // Load settings from default location
-        let settings = crate::settings::Settings::load_default()
-            .unwrap_or_else(|_| crate::settings::Settings::new());
-        
-        let mut window_context = WindowContext::new();
-        window_context.set_settings(settings);
-        
-        Self {
-            config,
-            keystore: Arc::new(RwLock::new(Keystore::new())),
-            repeat_cache: None,
-            window_context: Arc::new(RwLock::new(window_context)),
-            window_manager: None,
-            multipurpose_manager,
-            keymap_stack: KeymapStack::default(),
-            escape_next: false,
-            mark: None,
-            suspend_mode: false,
-            last_suspend_press: None,
-            active_combos: HashSet::new(),
-            deadkeys: DeadKeyState::default(),
-        }
+        Self::with_window_manager(config, None)
     }
 
     /// Create a new transform engine with window context provider
@@ -649,38 +636,91 @@ impl TransformEngine {
         config: TransformConfig,
         window_manager: Option<Box<dyn WindowContextProvider>>,
     ) -> Self {
-        let timeout = config.multipurpose_timeout.unwrap_or(200);
-        let mut multipurpose_manager = MultipurposeManager::with_timeout(timeout);
-        
-        // Load multipurpose modmaps from config
-        for multimodmap in &config.multimodmaps {
-            multipurpose_manager.add_modmap(multimodmap.clone());
-        }
-        
         // Load settings from default location
         let settings = crate::settings::Settings::load_default()
             .unwrap_or_else(|_| crate::settings::Settings::new());
-        
+        let mut engine = Self::with_clock(config, settings, system_clock());
+        engine.window_manager = window_manager;
+        engine
+    }
+
+    /// Create a transform engine that reads nothing from disk and takes time
+    /// from `clock`; with a `ManualClock` its output depends only on its inputs
+    pub fn with_clock(
+        config: TransformConfig,
+        settings: crate::settings::Settings,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let multipurpose_manager = multipurpose_manager_for(&config, &clock);
+        let mut settings = settings;
+        for (name, value) in &config.setting_defaults {
+            settings.register_default(name, *value);
+        }
         let mut window_context = WindowContext::new();
         window_context.set_settings(settings);
+        let output_delays = config.output_delays;
+        let deadkeys = dead_keys_for(&config, &clock);
+        let nested_keymaps = nested_keymaps_for(&config);
+        let profiles = vec![(config.profile.clone(), None)];
         
         Self {
             config,
             keystore: Arc::new(RwLock::new(Keystore::new())),
             repeat_cache: None,
             window_context: Arc::new(RwLock::new(window_context)),
-            window_manager,
+            window_manager: None,
             multipurpose_manager,
             keymap_stack: KeymapStack::default(),
-            escape_next: false,
+            nested_keymaps,
+            escape: None,
+            escaped_keys: HashSet::new(),
+            numpad_nav_keys: HashMap::new(),
             mark: None,
             suspend_mode: false,
             last_suspend_press: None,
             active_combos: HashSet::new(),
-            deadkeys: DeadKeyState::default(),
+            deadkeys,
+            pending_outputs: Vec::new(),
+            trace: None,
+            stats: Stats::new(),
+            metrics: None,
+            audit: None,
+            matched_repeat: None,
+            matched_combo_style: None,
+            matched_release: false,
+            matched_hold: None,
+            tap_hold: None,
+            release_bindings: HashMap::new(),
+            combo_style: ComboStyle::default(),
+            combo_style_changed: false,
+            output_delays,
+            synthetic_repeat: None,
+            modtap: None,
+            pending_settings: Vec::new(),
+            binding_generation: 0,
+            press_generations: HashMap::new(),
+            layers: LayerState::default(),
+            pending_layer: None,
+            deferred_results: Vec::new(),
+            autoshift: None,
+            follow_lock_leds: false,
+            device_keyboard_types: HashMap::new(),
+            default_keyboard_type: None,
+            clock,
+            active_bindings: None,
+            ignored_keys: HashSet::new(),
+            window_suspended: false,
+            suspended_held_keys: HashSet::new(),
+            profiles,
+            active_profile: 0,
+            pending_profile: None,
         }
     }
 
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
     /// Add a multipurpose modmap entry to the engine
     pub fn add_multipurpose(&mut self, trigger: Key, tap: Key, hold: Key) {
         use crate::mapping::MultiModmap;
@@ -692,1276 +732,4210 @@ impl TransformEngine {
         self.multipurpose_manager.add_modmap(modmap);
     }
 
-    fn apply_sequence_side_effects(&mut self, steps: &[ActionStep]) -> Vec<ActionStep> {
-        let mut output_steps = Vec::with_capacity(steps.len());
-        for step in steps {
-            match step {
-                ActionStep::SetSetting { name, value } => {
-                    self.set_setting(name, *value);
-                }
-                _ => output_steps.push(step.clone()),
-            }
+    /// Add a tap-dance entry to the engine
+    pub fn add_tapdance(&mut self, tapdance: TapDance) {
+        self.multipurpose_manager.add_tapdance(tapdance);
+    }
+
+    /// Take outputs that must be emitted before the result of the last
+    /// `process_event` call. A `Release` action releases a key the output
+    /// still holds (a `[modtap]` trigger) and otherwise means "emit as a tap".
+    pub fn take_pending_outputs(&mut self) -> Vec<(Key, Action)> {
+        std::mem::take(&mut self.pending_outputs)
+    }
+
+    /// Take the combo style of the last matched keymap when it differs from
+    /// the one returned before; apply it to the output before emitting the
+    /// result of the last `process_event` call
+    pub fn take_combo_style(&mut self) -> Option<ComboStyle> {
+        std::mem::take(&mut self.combo_style_changed).then(|| self.combo_style.clone())
+    }
+
+    /// Key delays for the current window context (the first matching
+    /// `[[delays.overrides]]` entry over `[delays]`) when they differ from
+    /// the ones returned before; call after the context changes
+    pub fn take_output_delays(&mut self) -> Option<OutputDelays> {
+        let base = self.config.output_delays;
+        let context = self.window_context.read();
+        let delays = self
+            .config
+            .delay_overrides
+            .iter()
+            .find(|delay_override| context.matches(&delay_override.condition))
+            .map_or(base, |delay_override| delay_override.apply(base));
+        drop(context);
+        (delays != self.output_delays).then(|| {
+            self.output_delays = delays;
+            delays
+        })
+    }
+
+    /// Take results of key events held back by a layer trigger's roll-over
+    /// window; emit them, in order, before the pending outputs
+    pub fn take_deferred_results(&mut self) -> Vec<(TransformResult, Action)> {
+        std::mem::take(&mut self.deferred_results)
+    }
+
+    /// Suspend or resume remapping after a focus change, by `suspend_when`;
+    /// returns the results to emit, like `take_deferred_results`
+    pub fn check_window_suspend(&mut self) -> Vec<(TransformResult, Action)> {
+        self.sync_window_suspend();
+        self.take_timer_results()
+    }
+
+    /// `take_deferred_results` for a timer or focus change, recorded in the
+    /// audit log as an entry without input
+    fn take_timer_results(&mut self) -> Vec<(TransformResult, Action)> {
+        let results = self.take_deferred_results();
+        if !results.is_empty() {
+            self.record_audit(None, &results);
         }
-        output_steps
+        results
     }
 
-    /// Process a single key event
-    ///
-    /// This is the main entry point for event processing.
-    /// It handles modmap lookup, combo matching, and state updates.
-    pub fn process_event(&mut self, key: Key, action: Action) -> TransformResult {
-        // Handle suspend mode - if active, only the suspend key double-tap can resume
-        if self.suspend_mode {
-            // Check if this is the suspend key being pressed (for resume)
-            if let Some(suspend_key) = self.config.suspend_key {
-                if key == suspend_key && action.is_pressed() {
-                    // Check for double-tap to resume
-                    let now = Instant::now();
-                    let timeout = Duration::from_millis(self.config.suspend_timeout.unwrap_or(1000));
-                    
-                    if let Some(last_press) = self.last_suspend_press {
-                        if now.duration_since(last_press) < timeout {
-                            // Double-tap detected - resume
-                            self.suspend_mode = false;
-                            self.last_suspend_press = None;
-                            return TransformResult::Suspend;
-                        }
-                    }
-                    // Not a double-tap, update last press time
-                    self.last_suspend_press = Some(now);
-                }
-            }
-            
-            if action.is_released() {
-                // Don't clear suspend_mode on release when we're checking for double-tap
-                // The suspend_mode is only toggled by double-tap
-            }
-            return TransformResult::Suppress;
+    /// Follow `suspend_when`. On entering a suspending window every held key
+    /// is released (a deferred `Suspend`) and forgotten.
+    fn sync_window_suspend(&mut self) {
+        if self.config.suspend_when.is_none() {
+            return;
+        }
+        self.refresh_active_bindings();
+        let suspended = self.active_bindings.as_ref().is_some_and(|bindings| bindings.suspended());
+        if suspended == self.window_suspended {
+            return;
+        }
+        self.window_suspended = suspended;
+        if !suspended {
+            log::info!("Remapping resumed: suspend_when no longer holds");
+            return;
         }
+        log::info!("Remapping suspended: suspend_when holds for this window");
+        self.release_held_keys();
+    }
 
-        // Check for suspend key double-tap (when not suspended)
-        if let Some(suspend_key) = self.config.suspend_key {
-            if key == suspend_key && action.is_pressed() {
-                let now = Instant::now();
-                let timeout = Duration::from_millis(self.config.suspend_timeout.unwrap_or(1000));
-                
-                if let Some(last_press) = self.last_suspend_press {
-                    if now.duration_since(last_press) < timeout {
-                        // Double-tap detected - suspend
-                        self.suspend_mode = true;
-                        self.last_suspend_press = None;
-                        return TransformResult::Suspend;
-                    }
-                }
-                // Not a double-tap, update last press time and pass through
-                self.last_suspend_press = Some(now);
+    /// Release every held key on the output (a deferred `Suspend`) and
+    /// forget them, swallowing their repeats and releases
+    fn release_held_keys(&mut self) {
+        let held: Vec<Key> = self.keystore.read().get_pressed_states().iter().map(|state| state.inkey).collect();
+        self.suspended_held_keys.extend(held);
+        self.keystore.write().clear();
+        self.repeat_cache = None;
+        self.active_combos.clear();
+        self.release_bindings.clear();
+        self.press_generations.clear();
+        self.tap_hold = None;
+        self.synthetic_repeat = None;
+        self.autoshift = None;
+        self.modtap = None;
+        self.deferred_results.push((TransformResult::Suspend, Action::Press));
+    }
+
+    /// Profile names, in switching order
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Name of the active profile
+    pub fn profile(&self) -> &str {
+        &self.profiles[self.active_profile].0
+    }
+
+    /// Preload `config` as the profile `config.profile`, so switching to it
+    /// costs no parsing; an inactive profile of that name is replaced
+    pub fn add_profile(&mut self, config: TransformConfig) {
+        let name = config.profile.clone();
+        match self.profiles.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, slot @ Some(_))) => *slot = Some(config),
+            Some(_) => log::warn!("Not replacing the active profile '{}'", name),
+            None => self.profiles.push((name, Some(config))),
+        }
+    }
+
+    /// Switch to the profile `name`, or the next one when `None`; false when
+    /// there is no such profile. Every held key is released, as on entering
+    /// `suspend_when`, and layers, nested keymaps and the mark start over.
+    pub fn switch_profile(&mut self, name: Option<&str>) -> bool {
+        let target = match name {
+            Some(name) => match self.profiles.iter().position(|(existing, _)| existing == name) {
+                Some(index) => index,
+                None => return false,
+            },
+            None => (self.active_profile + 1) % self.profiles.len(),
+        };
+        if target == self.active_profile {
+            return true;
+        }
+        let Some(config) = self.profiles[target].1.take() else {
+            return false;
+        };
+        let previous = std::mem::replace(&mut self.config, config);
+        self.profiles[self.active_profile].1 = Some(previous);
+        self.active_profile = target;
+        log::info!("Switched to profile '{}'", self.profile());
+
+        self.release_held_keys();
+        self.multipurpose_manager = multipurpose_manager_for(&self.config, &self.clock);
+        self.deadkeys = dead_keys_for(&self.config, &self.clock);
+        self.nested_keymaps = nested_keymaps_for(&self.config);
+        {
+            let mut context = self.window_context.write();
+            for (name, value) in &self.config.setting_defaults {
+                context.settings.register_default(name, *value);
             }
         }
+        self.keymap_stack = KeymapStack::default();
+        self.layers = LayerState::default();
+        self.pending_layer = None;
+        self.escape = None;
+        self.mark = None;
+        self.window_suspended = false;
+        self.active_bindings = None;
+        self.binding_generation += 1;
+        self.sync_window_suspend();
+        true
+    }
 
-        // Track lock states for condition evaluation (numlock/capslock).
-        self.update_lock_state_from_event(key, action);
+    /// Resolve a keymap tap/hold mapping held past the multipurpose timeout
+    /// as a hold; returns the results to emit, like `take_deferred_results`
+    pub fn check_tap_hold(&mut self) -> Vec<(TransformResult, Action)> {
+        if self.next_tap_hold_in() == Some(Duration::ZERO) {
+            self.resolve_tap_hold();
+            self.commit_pending_settings();
+        }
+        self.take_timer_results()
+    }
 
-        // Handle multipurpose (tap/hold) logic first
-        if self.multipurpose_manager.has_active() {
-            // Check if this is the same key as the active multipurpose
-            let is_same_key = self.multipurpose_manager.get_trigger_key() == Some(key);
-            
-            if is_same_key {
-                // This is the multipurpose key being released or repeating
-                match action {
-                    Action::Release => {
-                        // Key released - determine tap vs hold
-                        match self.multipurpose_manager.release() {
-                            Some(MultipurposeResult::Tap(tap_key)) => {
-                                self.keystore.write().update(key, action, None);
-                                return TransformResult::Remapped(tap_key);
-                            }
-                            Some(MultipurposeResult::HoldRelease(hold_key)) => {
-                                self.keystore.write().update(hold_key, action, None);
-                                return TransformResult::Remapped(hold_key);
-                            }
-                            None => {
-                                // No active multipurpose - fall through to normal processing
-                            }
-                        }
-                    }
-                    Action::Repeat => {
-                        // Only repeat when the multipurpose key is already in hold state.
-                        // While still pending (pre-timeout), repeat must stay suppressed.
-                        if self.multipurpose_manager.is_hold_state() {
-                            if let Some(hold_key) = self.multipurpose_manager.get_hold_key() {
-                                return TransformResult::Remapped(hold_key);
-                            }
-                        }
-                        return TransformResult::Suppress;
-                    }
-                    Action::Press => {
-                        // Shouldn't happen for same key - fall through to normal processing
-                    }
+    /// Time until the held tap/hold mapping turns into its hold output
+    pub fn next_tap_hold_in(&self) -> Option<Duration> {
+        let pending = self.tap_hold.as_ref()?;
+        let timeout = self.multipurpose_manager.timeout();
+        Some(timeout.saturating_sub(self.now().saturating_duration_since(pending.pressed_at)))
+    }
+
+    /// Emit the hold output of the pending tap/hold mapping; its key's
+    /// repeats and release are swallowed from now on
+    fn resolve_tap_hold(&mut self) {
+        let Some(pending) = self.tap_hold.take() else {
+            return;
+        };
+        self.release_bindings.insert(pending.key, None);
+        let result = self.fire_match(pending.hold);
+        if result != TransformResult::Suppress {
+            self.deferred_results.push((result, Action::Press));
+        }
+    }
+
+    /// Output of a key, combo, sequence, unicode or text match, as on its press
+    fn fire_match(&mut self, combo_result: ComboMatchResult) -> TransformResult {
+        match combo_result {
+            ComboMatchResult::FoundKey(output_key) => TransformResult::ComboKey(output_key),
+            ComboMatchResult::FoundCombo(combo) => TransformResult::Combo(combo),
+            ComboMatchResult::FoundSequence(steps) => {
+                let output_steps = self.apply_sequence_side_effects(&steps);
+                if output_steps.is_empty() {
+                    TransformResult::Suppress
+                } else {
+                    TransformResult::Sequence(output_steps)
                 }
-            } else {
-                // Different key pressed while multipurpose is active
-                // This triggers the interrupt behavior (immediate hold)
-                if action.is_pressed() {
-                    if let Some((hold_key, new_key)) = self.multipurpose_manager.interrupt_with_key(key) {
-                        // Output hold key press
-                        self.keystore.write().update(hold_key, Action::Press, None);
-                        // Continue processing the new key
-                        return self.process_interrupting_key(new_key, action);
-                    }
+            }
+            ComboMatchResult::FoundUnicode(codepoint) => {
+                if self.deadkeys.activate_from_codepoint(codepoint) {
+                    TransformResult::Suppress
+                } else {
+                    TransformResult::Unicode(codepoint)
                 }
             }
+            ComboMatchResult::FoundText(text) => TransformResult::Text(text),
+            _ => TransformResult::Suppress,
         }
+    }
 
-        // Check if this key starts a multipurpose sequence
-        if action.is_pressed() && self.multipurpose_manager.is_trigger(key) {
-            // Multipurpose triggers are for standalone key usage. If another
-            // modifier is already held (e.g. RAlt-Enter), skip multipurpose so
-            // regular combo/keymap handling can win.
-            let has_held_modifier = self
-                .keystore
-                .read()
-                .get_pressed_mods_keys()
-                .iter()
-                .any(|m| *m != key);
-            if has_held_modifier {
-                // Fall through to normal processing.
-            } else {
-            // Check if there's a conditional and evaluate it
-            let condition = self.multipurpose_manager.get_conditional(key);
-            
-            let should_activate = if let Some(cond) = condition {
-                // Evaluate the condition against window context
-                self.window_context.read().matches_condition(cond)
-            } else {
-                // No condition, always activate
-                true
-            };
-            
-            if should_activate {
-                let _started = self.multipurpose_manager.start(key);
-                // Suppress the original key until we know tap vs hold
-                return TransformResult::Suppress;
-            }
-            // Condition is false, fall through to normal processing
-            }
+    /// Resolve a layer trigger whose roll-over window has closed as a layer
+    /// hold; returns the results to emit, like `take_deferred_results`
+    pub fn check_layer_rollover(&mut self) -> Vec<(TransformResult, Action)> {
+        if self.layer_rollover_expired() {
+            self.commit_pending_layer();
         }
+        self.take_timer_results()
+    }
 
-        // Get current modifier state BEFORE processing this key
-        let modifier_snapshot = self.keystore.read().get_modifier_snapshot();
+    /// Time until the pending layer trigger's roll-over window closes
+    pub fn next_layer_rollover_in(&self) -> Option<Duration> {
+        let pending = self.pending_layer.as_ref()?;
+        let window = Duration::from_millis(self.config.layer_rollover_timeout?);
+        Some(window.saturating_sub(self.now().saturating_duration_since(pending.pressed_at)))
+    }
 
-        // Modmap lookup with conditional support (do this BEFORE updating keystore)
-        let modmapped_key = self.lookup_modmap(key, &modifier_snapshot);
+    fn layer_rollover_expired(&self) -> bool {
+        self.next_layer_rollover_in() == Some(Duration::ZERO)
+    }
 
-        // Keep physical modifier identity for combo matching.
-        // Modmap output still applies to emitted events, but using remapped
-        // modifiers in keystore can make Super-* keymaps miss when Super is
-        // remapped to Ctrl by modmap.
-        let keystore_key = if Modifier::is_key_modifier(key) {
-            key
-        } else {
-            modmapped_key
+    /// Activate the pending layer and replay the key held back meanwhile through it
+    fn commit_pending_layer(&mut self) {
+        let Some(pending) = self.pending_layer.take() else {
+            return;
         };
-        self.keystore.write().update(key, action, Some(keystore_key));
-
-        // Handle hints
-        if self.handle_hints(key, &action) {
-            return TransformResult::Suppress;
+        self.layers.activate(pending.trigger, &pending.name, LayerMode::Momentary);
+        self.binding_generation += 1;
+        if let Some(deferred) = pending.deferred {
+            let result = self.process_event_inner(deferred, Action::Press);
+            self.deferred_results.push((result, Action::Press));
         }
+    }
 
-        // Update window context if needed
-        // (In production, this would come from Wayland/X11 events)
-
-        // Check for escape_next hint
-        if self.escape_next {
-            if action.is_pressed() || action.is_repeat() {
-                // Let the key through, but don't transform
-                self.escape_next = false;
-                return TransformResult::Passthrough(key);
-            } else {
-                self.escape_next = false;
-            }
+    /// Follow a momentary layer trigger inside its roll-over window.
+    ///
+    /// The first key pressed after the trigger is held back. Releasing the
+    /// trigger before that key types the trigger and the key (a typing roll);
+    /// releasing the key first, pressing another one or outlasting the window
+    /// uses the layer. Returns the event's result when it was consumed here.
+    fn track_layer_rollover(&mut self, key: Key, action: Action) -> Option<TransformResult> {
+        if self.layer_rollover_expired() {
+            self.commit_pending_layer();
+            return None;
         }
+        let pending = self.pending_layer.as_mut()?;
 
-        // Get updated modifier state (modifiers are stored as physical keys in keystore).
-        let pressed_mods = self.keystore.read().get_pressed_mods_keys();
-        // Also compute logical (modmapped) modifiers for fallback matching, so default
-        // Super->Ctrl behavior works unless an explicit Super-* mapping is present.
-        let logical_pressed_mods: Vec<Key> = pressed_mods
-            .iter()
-            .map(|k| self.lookup_modmap(*k, &modifier_snapshot))
-            .collect();
-        let shift_pressed = logical_pressed_mods
-            .iter()
-            .any(|k| *k == Key::from(42) || *k == Key::from(54));
-
-        // If a dead key is active, next key press may compose into Unicode.
-        if action == Action::Press && self.deadkeys.is_active() {
-            if let Some(composed) = self.deadkeys.try_compose(modmapped_key, shift_pressed) {
-                return TransformResult::Unicode(composed);
+        if key == pending.trigger {
+            if action != Action::Release {
+                return Some(TransformResult::Suppress);
             }
+            let pending = self.pending_layer.take()?;
+            self.keystore.write().update(key, action, None);
+            self.press_generations.remove(&key);
+            self.deferred_results
+                .push((TransformResult::Passthrough(key), Action::Press));
+            let Some(deferred) = pending.deferred else {
+                return Some(TransformResult::Passthrough(key));
+            };
+            self.deferred_results
+                .push((TransformResult::Passthrough(key), Action::Release));
+            let result = self.process_event_inner(deferred, Action::Press);
+            self.deferred_results.push((result, Action::Press));
+            return Some(TransformResult::Suppress);
         }
 
-        // Check keymap stack timeout
-        if let Some(timeout_val) = self.config.suspend_timeout {
-            if self
-                .keymap_stack
-                .should_timeout(Duration::from_millis(timeout_val))
-            {
-                self.exit_keymap();
+        match (pending.deferred, action) {
+            (None, Action::Press) if !Modifier::is_key_modifier(key) => {
+                pending.deferred = Some(key);
+                Some(TransformResult::Suppress)
             }
+            (Some(deferred), Action::Repeat) if deferred == key => Some(TransformResult::Suppress),
+            (Some(deferred), _) if deferred == key || action == Action::Press => {
+                self.commit_pending_layer();
+                None
+            }
+            _ => None,
         }
+    }
 
-        // Combo matching with precedence:
-        // 1) physical modifiers (explicit Super-* exceptions)
-        // 2) logical/modmapped modifiers (default Super->Ctrl behavior)
-        let mut combo_result = self.find_combo_expanded(&pressed_mods, modmapped_key);
-        let mut combo_mods = pressed_mods.clone();
-        if matches!(combo_result, ComboMatchResult::NotFound) && logical_pressed_mods != pressed_mods
-        {
-            let logical_result = self.find_combo_expanded(&logical_pressed_mods, modmapped_key);
-            if !matches!(logical_result, ComboMatchResult::NotFound) {
-                combo_result = logical_result;
-                combo_mods = logical_pressed_mods.clone();
-            }
+    /// Type the shifted form of an `[autoshift]` key held past its timeout;
+    /// returns the results to emit, like `take_deferred_results`
+    pub fn check_autoshift(&mut self) -> Vec<(TransformResult, Action)> {
+        if self.autoshift_expired() {
+            self.shift_pending_autoshift();
         }
+        self.take_timer_results()
+    }
 
-        let result = match combo_result {
-            ComboMatchResult::FoundKey(output_key) => {
-                if action == Action::Repeat {
-                    return TransformResult::Suppress;
-                }
-                // Check if this is a release of a key that was already matched as a combo
-                // This prevents duplicate paste events when releasing a key while modifiers are held
-                if action == Action::Release {
-                    let combo_key = (combo_mods.clone(), output_key);
-                    if self.active_combos.contains(&combo_key) {
-                        // Already matched this combo on press, just pass through the release
-                        // This prevents duplicate output on release
-                        // Remove from active_combos since we're done with this combo
-                        self.active_combos.remove(&combo_key);
-                        return TransformResult::Suppress;
-                    }
-                }
+    /// Time until the held autoshift key types its shifted form
+    pub fn next_autoshift_in(&self) -> Option<Duration> {
+        let pending = self.autoshift.as_ref().filter(|pending| !pending.shifted)?;
+        let timeout = Duration::from_millis(self.config.autoshift_timeout.unwrap_or(175));
+        Some(timeout.saturating_sub(self.now().saturating_duration_since(pending.pressed_at)))
+    }
 
-                // Track this combo as active on Press (but not for modifier-only combos)
-                if action == Action::Press {
-                    let combo_key = (combo_mods.clone(), output_key);
-                    self.active_combos.insert(combo_key);
-                }
+    fn autoshift_expired(&self) -> bool {
+        self.next_autoshift_in() == Some(Duration::ZERO)
+    }
 
-                // Check if this enters a nested keymap
-                if self.is_keymap_entry(output_key) {
-                    self.enter_keymap(output_key);
-                }
-                TransformResult::ComboKey(output_key)
-            }
-            ComboMatchResult::FoundCombo(combo) => {
-                if action == Action::Repeat {
-                    return TransformResult::Suppress;
-                }
-                // Same fix for FoundCombo - prevent duplicate on Release
-                if action == Action::Release {
-                    let combo_key = (combo_mods.clone(), combo.key());
-                    if self.active_combos.contains(&combo_key) {
-                        self.active_combos.remove(&combo_key);
-                        return TransformResult::Suppress;
-                    }
-                }
+    /// Type Shift plus the held key (after modmaps) as a single tap
+    fn shift_pending_autoshift(&mut self) {
+        let Some(pending) = self.autoshift.as_mut() else {
+            return;
+        };
+        pending.shifted = true;
+        let key = pending.key;
+        let modifier_snapshot = self.keystore.read().get_modifier_snapshot();
+        let output = self.lookup_modmap(key, &modifier_snapshot);
+        let shift = Modifier::from_key(Key::from(42)).expect("LEFT_SHIFT is a modifier");
+        self.deferred_results
+            .push((TransformResult::Combo(Combo::new(vec![shift], output)), Action::Press));
+    }
 
-                // Track this combo as active on Press
-                if action == Action::Press {
-                    let combo_key = (combo_mods.clone(), combo.key());
-                    self.active_combos.insert(combo_key);
-                }
+    /// Whether a press of `key` should be held back as an autoshift candidate.
+    ///
+    /// Only plain presses qualify: no modifier held, nothing else pending and
+    /// no layer active, so the shifted form cannot hide another binding.
+    fn starts_autoshift(&self, key: Key) -> bool {
+        self.config.autoshift_keys.binary_search(&key).is_ok()
+            && self.autoshift.is_none()
+            && !self.suspend_mode
+            && self.escape.is_none()
+            && self.pending_layer.is_none()
+            && self.layers.is_empty()
+            && !self.deadkeys.is_active()
+            && !self.multipurpose_manager.has_active()
+            && !self.multipurpose_manager.has_active_tapdance()
+            && self.keystore.read().get_pressed_mods_keys().is_empty()
+    }
 
-                TransformResult::Combo(combo)
-            }
-            ComboMatchResult::FoundSequence(steps) => {
-                if action == Action::Press {
-                    let output_steps = self.apply_sequence_side_effects(&steps);
-                    if output_steps.is_empty() {
-                        TransformResult::Suppress
-                    } else {
-                        TransformResult::Sequence(output_steps)
-                    }
-                } else {
-                    TransformResult::Suppress
-                }
-            }
-            ComboMatchResult::FoundHint(hint) => TransformResult::Hint(hint),
-            ComboMatchResult::FoundUnicode(codepoint) => {
-                if action == Action::Press {
-                    if self.deadkeys.activate_from_codepoint(codepoint) {
-                        TransformResult::Suppress
-                    } else {
-                        TransformResult::Unicode(codepoint)
-                    }
-                } else {
-                    TransformResult::Suppress
+    /// Follow `[autoshift]` keys.
+    ///
+    /// A press is held back. Releasing it before the timeout types the key as
+    /// usual; holding it past the timeout types the shifted form once
+    /// (`check_autoshift`) and swallows the hardware repeat and the release.
+    /// Pressing another key first types the held key as usual. Returns the
+    /// event's result when it was consumed here.
+    fn track_autoshift(&mut self, key: Key, action: Action) -> Option<TransformResult> {
+        if self.autoshift_expired() {
+            self.shift_pending_autoshift();
+        }
+
+        if let Some(pending) = &self.autoshift {
+            if pending.key == key {
+                if action != Action::Release {
+                    return Some(TransformResult::Suppress);
                 }
-            }
-            ComboMatchResult::FoundText(text) => {
-                if action == Action::Press {
-                    TransformResult::Text(text)
-                } else {
-                    TransformResult::Suppress
+                let pending = self.autoshift.take()?;
+                if pending.shifted {
+                    return Some(TransformResult::Suppress);
                 }
+                let press = self.process_event_inner(key, Action::Press);
+                self.deferred_results.push((press, Action::Press));
+                return Some(self.process_event_inner(key, Action::Release));
             }
-            ComboMatchResult::NotFound => {
-                // No combo match, use modmapped key
-                // On Release, clean up any active combos involving this key
-                if action == Action::Release {
-                    self.active_combos.retain(|(mods, _)| {
-                        // Keep only combos whose modifiers don't include this key
-                        !mods.contains(&key)
-                    });
-                }
-
-                if modmapped_key != key {
-                    TransformResult::Remapped(modmapped_key)
-                } else {
-                    TransformResult::Passthrough(key)
-                }
+            if action != Action::Press || pending.shifted {
+                return None;
             }
-        };
+            let pending = self.autoshift.take()?;
+            let press = self.process_event_inner(pending.key, Action::Press);
+            self.deferred_results.push((press, Action::Press));
+        }
 
-        // Update repeat cache for REPEAT events
-        if action == Action::Repeat {
-            if let Some(cache) = &self.repeat_cache {
-                if cache.is_valid(key, &pressed_mods) {
-                    return cache.result.clone();
-                }
-            }
-            // Convert modifier_snapshot (u16) to Vec<Key>
-            let mod_keys: Vec<Key> = modifier_snapshot
-                .iter()
-                .map(|&code| Key::from(code))
-                .collect();
-            self.repeat_cache = Some(RepeatCache::new(key, result.clone(), mod_keys));
-        } else {
-            self.repeat_cache = None;
+        if action != Action::Press || !self.starts_autoshift(key) {
+            return None;
         }
+        self.autoshift = Some(PendingAutoShift {
+            key,
+            pressed_at: self.now(),
+            shifted: false,
+        });
+        Some(TransformResult::Suppress)
+    }
 
-        result
+    /// Use XCompose sequences for dead keys and the Compose key
+    pub fn set_compose_table(&mut self, table: Option<ComposeTable>) {
+        self.deadkeys.set_compose_table(table.map(Arc::new));
     }
 
-    fn update_lock_state_from_event(&mut self, key: Key, action: Action) {
-        // Toggle on press events, matching lock-key behavior.
-        if action != Action::Press {
-            return;
-        }
+    /// Dead key or compose sequence waiting for its next key, as shown by
+    /// status bars (`´`, or `⎄ minus` for a compose sequence)
+    pub fn pending_dead_key(&self) -> Option<String> {
+        self.deadkeys.pending_label()
+    }
 
-        let mut context = self.window_context.write();
-        match key.code() {
-            69 => context.numlock_on = !context.numlock_on, // NUMLOCK
-            58 => context.capslock_on = !context.capslock_on, // CAPSLOCK
-            _ => {}
-        }
+    /// Time until `check_dead_key_timeout` cancels the pending dead key
+    /// (only with `[timeouts] dead_key`)
+    pub fn next_dead_key_timeout_in(&self) -> Option<Duration> {
+        self.config.dead_key_timeout?;
+        self.deadkeys.expires_in()
     }
 
-    /// Process a key that interrupted a multipurpose sequence
-    fn process_interrupting_key(&mut self, key: Key, action: Action) -> TransformResult {
-        // First output the hold key press
-        if let Some(hold_key) = self.multipurpose_manager.get_hold_key() {
-            self.keystore.write().update(hold_key, Action::Press, None);
-        }
-        
-        // Now process the interrupting key normally
-        self.process_event(key, action)
+    /// Cancel a dead key pending past `[timeouts] dead_key`; returns its bare
+    /// accent to type
+    pub fn check_dead_key_timeout(&mut self) -> Option<TransformResult> {
+        self.config.dead_key_timeout?;
+        let accent = self.deadkeys.expire()?;
+        let result = TransformResult::Unicode(accent as u32);
+        self.record_audit(None, &[(result.clone(), Action::Press)]);
+        Some(result)
     }
 
-    /// Check if any multipurpose keys have timed out and should transition to hold
-    /// This should be called periodically (e.g., in the event loop)
-    pub fn check_multipurpose_timeouts(&mut self) -> Option<(Key, Action)> {
-        if self.multipurpose_manager.is_pending_state() {
-            if let Some(hold_key) = self.multipurpose_manager.check_timeout() {
-                // Keep internal state in sync with emitted hold press.
-                self.keystore.write().update(hold_key, Action::Press, None);
-                return Some((hold_key, Action::Press));
-            }
-        }
-        None
+    /// Enable or disable recording of the combo matching decision path
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = if enabled { Some(Vec::new()) } else { None };
     }
 
-    /// Check if a key is currently an active multipurpose hold key
-    pub fn is_multipurpose_hold_active(&self) -> bool {
-        self.multipurpose_manager.is_hold_state()
+    /// Take the decision path recorded since the last call (empty when tracing is off)
+    pub fn take_trace(&mut self) -> Vec<TraceStep> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
     }
 
-    /// Look up a key through modmaps with conditional evaluation
-    fn lookup_modmap(&self, key: Key, _modifier_snapshot: &[u16]) -> Key {
-        // Check conditional modmaps first so specific rules can override defaults.
-        let context = self.window_context.read();
-        for modmap in self.config.modmaps.iter().skip(1) {
-            if let Some(condition) = modmap.conditional() {
-                if context.matches_condition(condition) {
-                    if let Some(remapped) = modmap.get(key) {
-                        return remapped;
-                    }
-                }
-            }
+    /// Start collecting event counts, keymap hits and transform latency
+    pub fn enable_metrics(&mut self) {
+        if self.metrics.is_none() {
+            self.metrics = Some(Metrics::new());
         }
+    }
 
-        // Fallback to default modmap.
-        if let Some(modmap) = self.config.modmaps.first() {
-            if let Some(remapped) = modmap.get(key) {
-                return remapped;
-            }
-        }
+    /// Metrics collected so far (`None` unless enabled)
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
 
-        key
+    /// Counters kept for the statistics table, whether or not metrics are enabled
+    pub fn stats(&self) -> &Stats {
+        &self.stats
     }
 
-    /// Find a matching combo with full modifier expansion
-    ///
-    /// This implements proper handling of non-specific modifiers.
-    /// For example, if user defines "ctrl-a" and presses LEFT_CTRL,
-    /// it should match because LEFT_CTRL is a Ctrl modifier.
-    fn find_combo_expanded(&self, pressed_mods: &[Key], key: Key) -> ComboMatchResult {
-        // Convert pressed keys to modifiers
-        let mut pressed_modifiers: Vec<Modifier> = pressed_mods
-            .iter()
-            .filter_map(|k| Modifier::from_key(*k))
-            .collect();
+    /// Statistics for the diagnostics key: the counters table with the `top`
+    /// most matched combos, then the entered keymaps, active layers and
+    /// pending or held multipurpose keys
+    pub fn render_stats(&self, top: usize) -> String {
+        let mut out = self.stats.render_table(top);
+        let list = |items: Vec<String>| if items.is_empty() { "none".to_string() } else { items.join(", ") };
+        out.push_str(&format!("Keymap stack: {}\n", list(self.keymap_stack.stack.clone())));
+        out.push_str(&format!("Layers: {}\n", list(self.layers.active().map(str::to_string).collect())));
+        out.push_str(&format!("Multipurpose: {}\n", list(self.multipurpose_manager.describe_active())));
+        out
+    }
 
-        // Also add the current key if it's a modifier
-        if let Some(key_mod) = Modifier::from_key(key) {
-            // Check if this modifier is not already in pressed list
-            if !pressed_modifiers.contains(&key_mod) {
-                pressed_modifiers.push(key_mod);
-            }
+    /// Start recording the last `capacity` inputs and timer firings with
+    /// the outputs they produced
+    pub fn enable_audit(&mut self, capacity: usize) {
+        if self.audit.is_none() {
+            self.audit = Some(AuditLog::new(capacity, self.now()));
         }
+    }
 
-        let combo = Combo::new(pressed_modifiers.clone(), key);
-        
-        // Get window context for conditional evaluation
-        let window_context = self.window_context.read();
-
-        // Try exact match first
-        for keymap in &self.config.keymaps {
-            // Check if keymap has a condition and if it matches
-            if let Some(condition) = keymap.conditional() {
-                if !window_context.matches_condition(condition) {
-                    continue; // Skip this keymap - condition doesn't match
-                }
-            }
-
-            if let Some(value) = keymap.get(&combo) {
-                return match value {
-                    KeymapValue::Key(k) => ComboMatchResult::FoundKey(*k),
-                    KeymapValue::Combo(c) => ComboMatchResult::FoundCombo(c.clone()),
-                    KeymapValue::Sequence(steps) => ComboMatchResult::FoundSequence(steps.clone()),
-                    KeymapValue::ComboHint(h) => ComboMatchResult::FoundHint(*h),
-                    KeymapValue::Unicode(codepoint) => ComboMatchResult::FoundUnicode(*codepoint),
-                    KeymapValue::Text(text) => ComboMatchResult::FoundText(text.clone()),
-                };
-            }
-        }
+    /// Audit log recorded so far (`None` unless enabled)
+    pub fn audit(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
 
-        // Try with non-specific modifier expansion
-        // For each modifier in the combo, try replacing with specific variants
-        let expansion_attempts = self.expand_modifiers(&combo);
+    /// The audit log with the profile, keyboard type and settings needed to
+    /// replay it (`None` unless enabled)
+    pub fn audit_dump(&self) -> Option<AuditDump> {
+        let log = self.audit.as_ref()?;
+        Some(AuditDump {
+            version: AUDIT_DUMP_VERSION,
+            profile: self.profile().to_string(),
+            keyboard_type: self.default_keyboard_type.map(|kb_type| kb_type.as_str().to_string()),
+            settings: self.settings().values().into_iter().collect(),
+            entries: log.entries().cloned().collect(),
+        })
+    }
 
-        for expanded_combo in expansion_attempts {
-            for keymap in &self.config.keymaps {
-                // Check if keymap has a condition and if it matches
-                if let Some(condition) = keymap.conditional() {
-                    if !window_context.matches_condition(condition) {
-                        continue; // Skip this keymap - condition doesn't match
-                    }
-                }
+    fn record_audit(&mut self, input: Option<(Key, Action)>, outputs: &[(TransformResult, Action)]) {
+        let now = self.now();
+        let Some(log) = self.audit.as_mut() else {
+            return;
+        };
+        let context = self.window_context.read();
+        let context = AuditContext {
+            device: context.device_name.clone(),
+            wm_class: context.wm_class.clone(),
+            wm_name: context.wm_name.clone(),
+        };
+        log.record(now, input, context, outputs);
+    }
 
-                if let Some(value) = keymap.get(&expanded_combo) {
-                    return match value {
-                        KeymapValue::Key(k) => ComboMatchResult::FoundKey(*k),
-                        KeymapValue::Combo(c) => ComboMatchResult::FoundCombo(c.clone()),
-                        KeymapValue::Sequence(steps) => {
-                            ComboMatchResult::FoundSequence(steps.clone())
-                        }
-                        KeymapValue::ComboHint(h) => ComboMatchResult::FoundHint(*h),
-                        KeymapValue::Unicode(codepoint) => {
-                            ComboMatchResult::FoundUnicode(*codepoint)
-                        }
-                        KeymapValue::Text(text) => ComboMatchResult::FoundText(text.clone()),
-                    };
-                }
-            }
+    /// Output of a held `repeat = {...}` mapping that is due now; emit it as a press
+    pub fn check_synthetic_repeat(&mut self) -> Option<TransformResult> {
+        let now = self.now();
+        let repeat = self.synthetic_repeat.as_mut()?;
+        if now < repeat.next {
+            return None;
         }
+        // Skip missed ticks instead of bursting after a stall
+        repeat.next = (repeat.next + repeat.interval).max(now + repeat.interval / 2);
+        let result = repeat.result.clone();
+        self.record_audit(None, &[(result.clone(), Action::Press)]);
+        Some(result)
+    }
 
-        ComboMatchResult::NotFound
+    /// Time until the next synthetic repeat (None when nothing is repeating)
+    pub fn next_synthetic_repeat_in(&self) -> Option<Duration> {
+        self.synthetic_repeat
+            .as_ref()
+            .map(|repeat| repeat.next.saturating_duration_since(self.now()))
     }
 
-    /// Expand a combo by replacing non-specific modifiers with specific variants
-    ///
-    /// For example: [Ctrl, A] becomes:
-    /// - [LCtrl, A], [RCtrl, A]
-    fn expand_modifiers(&self, combo: &Combo) -> SmallVec<[Combo; 4]> {
-        let mut expansions = SmallVec::new();
+    /// Like hardware repeat, stop when the key or a modifier is released or another key is pressed
+    fn stop_synthetic_repeat_for(&mut self, key: Key, action: Action) {
+        let Some(repeat) = &self.synthetic_repeat else {
+            return;
+        };
+        let stop = match action {
+            Action::Release => key == repeat.key || Modifier::is_key_modifier(key),
+            Action::Press => key != repeat.key,
+            Action::Repeat => false,
+        };
+        if stop {
+            self.synthetic_repeat = None;
+        }
+    }
 
-        // For each modifier in the combo that could be non-specific...
-        for (i, modifier) in combo.modifiers().iter().enumerate() {
-            if !modifier.is_specific() {
-                // Create variants with this modifier replaced
-                let specific_left = modifier.to_left();
-                let specific_right = modifier.to_right();
+    fn is_repeatable_output(result: &TransformResult) -> bool {
+        !matches!(
+            result,
+            TransformResult::Passthrough(_)
+                | TransformResult::Suppress
+                | TransformResult::Suspend
+                | TransformResult::Hint(_)
+        )
+    }
 
-                if let Some(left) = specific_left {
-                    let mut new_mods: Vec<Modifier> = combo.modifiers().to_vec();
-                    new_mods[i] = left;
-                    expansions.push(Combo::new(new_mods, combo.key()));
+    /// Follow `[modtap]` triggers; returns the tap output when a trigger is
+    /// released in time without any other key pressed while it was down
+    fn track_modtap(&mut self, key: Key, action: Action) -> Option<Combo> {
+        if action == Action::Repeat {
+            return None;
+        }
+        if let Some(pending) = self.modtap.as_mut() {
+            if pending.trigger != key {
+                if action == Action::Press {
+                    pending.interrupted = true;
                 }
-
-                if let Some(right) = specific_right {
-                    let mut new_mods: Vec<Modifier> = combo.modifiers().to_vec();
-                    new_mods[i] = right;
-                    expansions.push(Combo::new(new_mods, combo.key()));
+                return None;
+            }
+            if action == Action::Release {
+                let pending = self.modtap.take()?;
+                let timeout = Duration::from_millis(self.config.modtap_timeout.unwrap_or(500));
+                if !pending.interrupted && self.now().saturating_duration_since(pending.pressed_at) <= timeout {
+                    return Some(pending.output);
                 }
             }
+            return None;
         }
 
-        expansions
+        if action != Action::Press
+            || self.suspend_mode
+            || self.multipurpose_manager.is_trigger(key)
+            || self.multipurpose_manager.is_tapdance_trigger(key)
+            || !self.keystore.read().get_pressed_states().is_empty()
+        {
+            return None;
+        }
+        let (trigger, output) = self.config.modtaps.iter().find(|(trigger, _)| *trigger == key)?;
+        self.modtap = Some(PendingModTap {
+            trigger: *trigger,
+            output: output.clone(),
+            pressed_at: self.now(),
+            interrupted: false,
+        });
+        None
     }
 
-    /// Handle special hints
-    fn handle_hints(&mut self, key: Key, action: &Action) -> bool {
-        // Check for SetMark hints
-        if let Some(name) = self.get_keymap_name_for_key(key) {
-            if name == "set_mark" || name == "mark" {
-                match action {
-                    Action::Press | Action::Repeat => {
-                        self.mark = Some(true);
-                        return true;
-                    }
-                    Action::Release => {
-                        self.mark = None;
-                        return true;
-                    }
-                }
+    /// Replace the trigger's release with release-then-tap. Releases the
+    /// engine swallowed (suspend, layers, combos) don't produce a tap.
+    fn finish_modtap(&mut self, result: TransformResult, output: Combo) -> TransformResult {
+        match result {
+            TransformResult::Passthrough(released) | TransformResult::Remapped(released) => {
+                self.pending_outputs.push((released, Action::Release));
+                TransformResult::Combo(output)
             }
+            other => other,
         }
-
-        false
     }
 
-    /// Check if a key is a keymap entry (for nesting)
-    fn is_keymap_entry(&self, key: Key) -> bool {
-        // Check if any keymap has this as a target
-        for keymap in &self.config.keymaps {
-            for (_combo, value) in keymap.mappings() {
-                if let KeymapValue::Key(k) = value {
-                    if *k == key {
-                        return true;
-                    }
+    /// Press or release every component key of a compound modifier: all but
+    /// the last go out as deferred results ahead of the returned one
+    fn compound_modifier_output(&mut self, compound: &Modifier, action: Action) -> TransformResult {
+        let keys: Vec<Key> = compound.components().iter().map(Modifier::key).collect();
+        let Some((&last, rest)) = keys.split_last() else {
+            return TransformResult::Suppress;
+        };
+        match action {
+            Action::Press => {
+                for &key in rest {
+                    self.deferred_results.push((TransformResult::Remapped(key), Action::Press));
                 }
             }
-        }
-        false
-    }
-
-    /// Get the keymap name that contains this key
-    fn get_keymap_name_for_key(&self, key: Key) -> Option<String> {
-        for keymap in &self.config.keymaps {
-            for (_combo_str, value) in keymap.mappings() {
-                if let KeymapValue::Key(k) = value {
-                    if *k == key {
-                        return Some(keymap.name().to_string());
-                    }
+            Action::Release => {
+                for &key in rest.iter().rev() {
+                    self.deferred_results.push((TransformResult::Remapped(key), Action::Release));
                 }
             }
+            Action::Repeat => {}
         }
-        None
+        TransformResult::Remapped(last)
     }
 
-    /// Enter a nested keymap
-    fn enter_keymap(&mut self, key: Key) {
-        // Push the keymap onto stack
-        if let Some(name) = self.get_keymap_name_for_key(key) {
-            self.keymap_stack.push(name.clone());
+    /// Whether a modifier other than `key` is currently held
+    fn has_other_held_modifier(&self, key: Key) -> bool {
+        self.keystore
+            .read()
+            .get_pressed_mods_keys()
+            .iter()
+            .any(|m| *m != key)
+    }
 
-            // Set timeout for nested keymap
-            if let Some(_timeout) = self.config.suspend_timeout {
-                self.keymap_stack.timeout_start = Some(Instant::now());
+    fn apply_sequence_side_effects(&mut self, steps: &[ActionStep]) -> Vec<ActionStep> {
+        let mut output_steps = Vec::with_capacity(steps.len());
+        for step in steps {
+            match step {
+                ActionStep::SetSetting { name, value } => {
+                    self.pending_settings.push((name.clone(), *value));
+                }
+                ActionStep::SetMark(value) => {
+                    let set = value.unwrap_or(self.mark.is_none());
+                    self.mark = set.then_some(true);
+                }
+                ActionStep::Profile(name) => self.pending_profile = Some(name.clone()),
+                _ => output_steps.push(step.clone()),
             }
         }
+        output_steps
     }
 
-    /// Exit the current nested keymap
-    fn exit_keymap(&mut self) {
-        self.keymap_stack.pop();
-        self.keymap_stack.timeout_start = None;
-    }
-
-    /// Update window context
-    /// Returns Some(hold_key) if a multipurpose hold was active and should be released.
-    pub fn update_window_context(&mut self, wm_class: Option<String>, wm_name: Option<String>) -> Option<Key> {
-        let mut context = self.window_context.write();
-        context.update(wm_class, wm_name);
-
-        // Clear keymap stack when window changes
-        self.keymap_stack.clear();
-
-        // Clear multipurpose state and get hold key to release
-        if let Some(hold_key) = self.multipurpose_manager.clear_and_get_hold_key() {
-            // Update keystore to release the hold key
-            self.keystore.write().update(hold_key, Action::Release, None);
-            return Some(hold_key);
+    /// Apply setting changes and the profile switch queued by the event
+    /// that just finished
+    fn commit_pending_settings(&mut self) {
+        for (name, value) in std::mem::take(&mut self.pending_settings) {
+            self.set_setting(&name, value);
+        }
+        if let Some(name) = self.pending_profile.take() {
+            if !self.switch_profile(name.as_deref()) {
+                log::warn!("Unknown profile '{}'", name.unwrap_or_default());
+            }
         }
-        None
     }
 
-    /// Set the window context provider
+    /// Process a single key event
     ///
-    /// This allows the engine to periodically query the active window
-    /// for conditional modmap evaluation.
-    pub fn set_window_manager(&mut self, window_manager: Option<Box<dyn WindowContextProvider>>) {
-        self.window_manager = window_manager;
+    /// This is the main entry point for event processing.
+    /// It handles modmap lookup, combo matching, and state updates.
+    ///
+    /// Setting changes made by a sequence (`SetSetting`) take effect at the
+    /// next event boundary: the whole event that triggered them, including
+    /// every step of its sequence, is resolved against the settings in force
+    /// when it arrived. A key held across a change keeps its press-time
+    /// binding, so its repeat/release cannot fire a different keymap.
+    pub fn process_event(&mut self, key: Key, action: Action) -> TransformResult {
+        let result = self.transform_event(key, action);
+        if self.audit.is_some() {
+            // Everything this event emits, in the order the frontends emit it
+            let mut outputs = self.deferred_results.clone();
+            outputs.extend(
+                self.pending_outputs
+                    .iter()
+                    .map(|&(pending, pending_action)| (TransformResult::Remapped(pending), pending_action)),
+            );
+            outputs.push((result.clone(), action));
+            self.record_audit(Some((key, action)), &outputs);
+        }
+        result
     }
 
-    /// Set current event-source device name for condition evaluation.
-    pub fn set_device_name(&mut self, device_name: Option<String>) {
-        self.window_context.write().set_device_name(device_name);
+    fn transform_event(&mut self, key: Key, action: Action) -> TransformResult {
+        self.sync_window_suspend();
+        if action != Action::Press && self.suspended_held_keys.contains(&key) {
+            if action == Action::Release {
+                self.suspended_held_keys.remove(&key);
+            }
+            return TransformResult::Suppress;
+        }
+        if self.pass_ignored(key, action) || self.pass_escaped(key, action) {
+            return TransformResult::Passthrough(key);
+        }
+        let key = self.numpad_nav(key, action);
+        let key = match self.map_media_key(key, action) {
+            Ok(key) => key,
+            Err(result) => return result,
+        };
+        let started = Instant::now();
+        let result = self.dispatch_event(key, action);
+        let latency = started.elapsed();
+        self.stats.record_event(&result, latency);
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.record_event(&result, latency);
+        }
+        result
     }
 
-    /// Set lock state flags for condition evaluation.
-    pub fn set_lock_states(&mut self, numlock_on: bool, capslock_on: bool) {
-        self.window_context
-            .write()
-            .set_lock_states(numlock_on, capslock_on);
+    /// Resolve an event whose key is already remapped by the numpad and
+    /// media rows
+    fn dispatch_event(&mut self, key: Key, action: Action) -> TransformResult {
+        self.stop_synthetic_repeat_for(key, action);
+        self.matched_repeat = None;
+        self.matched_combo_style = None;
+        self.matched_release = false;
+        self.matched_hold = None;
+        if action == Action::Press && !Modifier::is_key_modifier(key) {
+            // A key pressed while a tap/hold mapping is held makes it a hold,
+            // emitted before that key's own output
+            if self.tap_hold.as_ref().is_some_and(|pending| pending.key != key) {
+                self.resolve_tap_hold();
+            }
+            // A key pressed while a `release:` trigger is held makes it a chord
+            for (_, binding) in self.release_bindings.iter_mut().filter(|(held, _)| **held != key) {
+                *binding = None;
+            }
+        }
+        let modtap = self.track_modtap(key, action);
+        let marked = self.mark;
+        let mut result = match self.track_autoshift(key, action) {
+            Some(result) => result,
+            None => self.process_event_inner(key, action),
+        };
+        if let Some(output) = modtap {
+            result = self.finish_modtap(result, output);
+        }
+        // A mark set or unset by this very event applies from the next one
+        if marked.is_some() && self.mark.is_some() {
+            result = self.apply_mark(key, action, result);
+        }
+        self.commit_pending_settings();
+        if let Some(style) = self.matched_combo_style.take() {
+            if action == Action::Press && style != self.combo_style {
+                self.combo_style = style;
+                self.combo_style_changed = true;
+            }
+        }
+        if let Some(rate) = self.matched_repeat.take() {
+            if action == Action::Press && Self::is_repeatable_output(&result) {
+                self.synthetic_repeat = Some(SyntheticRepeat {
+                    key,
+                    result: result.clone(),
+                    interval: Duration::from_millis(rate.interval_ms),
+                    next: self.now() + Duration::from_millis(rate.delay_ms),
+                });
+            }
+        }
+        if result == TransformResult::Suspend {
+            self.synthetic_repeat = None;
+        }
+        result
     }
 
-    /// Set keyboard type for condition evaluation.
-    pub fn set_keyboard_type(&mut self, kb_type: crate::input::KeyboardType) {
-        self.window_context.write().set_keyboard_type(kb_type);
+    /// Process a sequence of events and return everything they emit, in
+    /// emission order: each event's deferred results, then its pending
+    /// outputs (as `Remapped`), then its own result with its action.
+    ///
+    /// Timers are not checked; with an engine from `with_clock` the output
+    /// is a pure function of the config, settings, clock and `events`.
+    pub fn process_events(&mut self, events: &[(Key, Action)]) -> Vec<(TransformResult, Action)> {
+        let mut emitted = Vec::new();
+        for &(key, action) in events {
+            let result = self.process_event(key, action);
+            emitted.extend(self.take_deferred_results());
+            emitted.extend(
+                self.take_pending_outputs()
+                    .into_iter()
+                    .map(|(pending, pending_action)| (TransformResult::Remapped(pending), pending_action)),
+            );
+            emitted.push((result, action));
+        }
+        emitted
     }
 
-    /// Clear keyboard type from condition context.
-    pub fn clear_keyboard_type(&mut self) {
-        self.window_context.write().clear_keyboard_type();
+    /// Whether the event belongs to an `[ignore]`d window and must pass
+    /// through untouched
+    ///
+    /// A key pressed there stays passed through until its release, even if
+    /// focus moves first, so no half-transformed press/release pair reaches
+    /// the output.
+    fn pass_ignored(&mut self, key: Key, action: Action) -> bool {
+        match action {
+            Action::Press => {
+                if self.config.ignore_wm_class.is_empty()
+                    && self.config.ignore_wm_name.is_empty()
+                    && !self.window_suspended
+                {
+                    return false;
+                }
+                self.refresh_active_bindings();
+                let ignored = self.window_suspended
+                    || self.active_bindings.as_ref().is_some_and(|bindings| bindings.ignored());
+                if ignored {
+                    self.ignored_keys.insert(key);
+                }
+                ignored
+            }
+            Action::Repeat => self.ignored_keys.contains(&key),
+            Action::Release => self.ignored_keys.remove(&key),
+        }
     }
 
-    /// Update window context from window manager
+    /// Whether the event is escaped by an armed `EscapeNextKey`/`EscapeNextCombo`
+    /// hint and must pass through untouched, skipping modmaps and keymaps
     ///
-    /// This should be called periodically (e.g., every 100ms) to
-    /// update window context for conditional modmap evaluation.
-    /// Returns (changed, hold_key_to_release) where:
-    /// - changed: true if window context changed
-    /// - hold_key_to_release: Some(hold_key) if a multipurpose hold was active and should be released
-    pub fn update_from_window_manager(&mut self) -> (bool, Option<Key>) {
-        if let Some(ref mut manager) = self.window_manager {
-            match manager.get_active_window() {
-                Ok(info) => {
-                    fn normalize_window_field(value: Option<String>) -> Option<String> {
-                        let v = value?;
-                        let trimmed = v.trim();
-                        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("(none)") {
-                            None
-                        } else {
-                            Some(trimmed.to_string())
-                        }
-                    }
-
-                    let new_wm_class = normalize_window_field(info.wm_class);
-                    let new_wm_name = normalize_window_field(info.wm_name);
-
-                    // Keep the last stable window context when the provider returns no active
-                    // window details (common transient state during focus switches).
-                    if new_wm_class.is_none() && new_wm_name.is_none() {
-                        return (false, None);
-                    }
-
-                    let mut context = self.window_context.write();
-
-                    // Check if window changed
-                    let changed =
-                        context.wm_class != new_wm_class || context.wm_name != new_wm_name;
-
-                    // Update context
-                    context.wm_class = new_wm_class;
-                    context.wm_name = new_wm_name;
-
-                    // Clear keymap stack when window changes
-                    if changed {
-                        self.keymap_stack.clear();
-                        
-                        // Clear multipurpose state and get hold key to release
-                        if let Some(hold_key) = self.multipurpose_manager.clear_and_get_hold_key() {
-                            // Update keystore to release the hold key
-                            self.keystore.write().update(hold_key, Action::Release, None);
-                            return (true, Some(hold_key));
-                        }
+    /// `EscapeNextKey` escapes the next non-modifier key press; modifiers
+    /// pressed before it are handled as usual. `EscapeNextCombo` also escapes
+    /// the modifiers pressed before that key. Either way the hint is spent by
+    /// the non-modifier key, and escaped keys stay escaped until released.
+    fn pass_escaped(&mut self, key: Key, action: Action) -> bool {
+        let escaped = match action {
+            Action::Press => {
+                let modifier = Modifier::is_key_modifier(key);
+                let escaped = match self.escape {
+                    Some(ComboHint::EscapeNextKey) => !modifier,
+                    Some(ComboHint::EscapeNextCombo) => true,
+                    _ => false,
+                };
+                if escaped {
+                    self.escaped_keys.insert(key);
+                    if !modifier {
+                        self.escape = None;
                     }
-
-                    (changed, None)
                 }
-                Err(_) => {
-                    // If the window provider is disconnected, attempt to reconnect.
-                    // This lets keyrs recover when startup ordering races the desktop session.
-                    if !manager.is_connected() {
-                        let _ = manager.connect();
+                escaped
+            }
+            Action::Repeat => self.escaped_keys.contains(&key),
+            Action::Release => self.escaped_keys.remove(&key),
+        };
+        if escaped {
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(TraceStep::Escaped);
+            }
+        }
+        escaped
+    }
+
+    /// With `settings.numpad_nav` on and NumLock off, a keypad key becomes
+    /// the navigation key printed on it, so modifiers apply as they would to
+    /// that key (Shift-KP4 selects left). It keeps its press-time meaning
+    /// until released, even if NumLock changes meanwhile.
+    fn numpad_nav(&mut self, key: Key, action: Action) -> Key {
+        match action {
+            Action::Press => {
+                let context = self.window_context.read();
+                let nav = (context.settings.get_bool(NUMPAD_NAV_SETTING) && !context.effective_numlock_on())
+                    .then(|| numpad::nav_key(key))
+                    .flatten();
+                drop(context);
+                match nav {
+                    Some(nav) => {
+                        self.numpad_nav_keys.insert(key, nav);
+                        nav
                     }
-                    // Window query failed, keep current context
-                    (false, None)
+                    None => key,
                 }
             }
-        } else {
-            (false, None)
+            Action::Repeat => self.numpad_nav_keys.get(&key).copied().unwrap_or(key),
+            Action::Release => self.numpad_nav_keys.remove(&key).unwrap_or(key),
         }
     }
 
-    /// Print current window context for debugging
-    pub fn print_window_context(&self) {
-        let context = self.window_context.read();
-        log::debug!(
-            "WINDOW: wm_class={:?} wm_name={:?} device_name={:?} keyboard_type={:?} numlock={} capslock={}",
-            context.wm_class.as_deref().unwrap_or("(none)"),
-            context.wm_name.as_deref().unwrap_or("(none)"),
-            context.device_name.as_deref().unwrap_or("(none)"),
-            context.keyboard_type,
-            context.numlock_on,
-            context.capslock_on
-        );
+    /// Apply `[mediamap]` to a media key: a key entry renames it, a `Run(...)`
+    /// entry starts its command on press. Returns the key to process, or the
+    /// result when the key is done with: its command, or the key itself with
+    /// `passthrough`.
+    fn map_media_key(&mut self, key: Key, action: Action) -> Result<Key, TransformResult> {
+        if !is_media_key(key) {
+            return Ok(key);
+        }
+        let key = match self.config.mediamap.get(&key) {
+            Some(MediaMapping::Run(command)) => {
+                return Err(match action {
+                    Action::Press => TransformResult::Sequence(vec![ActionStep::Run(command.clone())]),
+                    _ => TransformResult::Suppress,
+                });
+            }
+            Some(MediaMapping::Key(target)) => *target,
+            None => key,
+        };
+        if self.config.media_passthrough {
+            Err(TransformResult::Passthrough(key))
+        } else {
+            Ok(key)
+        }
     }
 
-    /// Suspend transformation (for suspend_key)
-    pub fn suspend(&mut self) {
-        self.suspend_mode = true;
+    /// Armed escape hint, shown by diagnostics: `EscapeNextKey` or `EscapeNextCombo`
+    pub fn pending_escape(&self) -> Option<ComboHint> {
+        self.escape
     }
 
-    /// Resume transformation
-    pub fn resume(&mut self) {
-        self.suspend_mode = false;
+    fn process_event_inner(&mut self, key: Key, action: Action) -> TransformResult {
+        if let Some(result) = self.track_layer_rollover(key, action) {
+            return result;
+        }
+
+        // Handle suspend mode - if active, only the suspend key double-tap can resume
+        if self.suspend_mode {
+            // Check if this is the suspend key being pressed (for resume)
+            if let Some(suspend_key) = self.config.suspend_key {
+                if key == suspend_key && action.is_pressed() {
+                    // Check for double-tap to resume
+                    let now = self.now();
+                    let timeout = Duration::from_millis(self.config.suspend_timeout.unwrap_or(1000));
+                    
+                    if let Some(last_press) = self.last_suspend_press {
+                        if now.duration_since(last_press) < timeout {
+                            // Double-tap detected - resume
+                            self.suspend_mode = false;
+                            self.last_suspend_press = None;
+                            return TransformResult::Suspend;
+                        }
+                    }
+                    // Not a double-tap, update last press time
+                    self.last_suspend_press = Some(now);
+                }
+            }
+            
+            if action.is_released() {
+                // Don't clear suspend_mode on release when we're checking for double-tap
+                // The suspend_mode is only toggled by double-tap
+            }
+            return TransformResult::Suppress;
+        }
+
+        // Check for suspend key double-tap (when not suspended)
+        if let Some(suspend_key) = self.config.suspend_key {
+            if key == suspend_key && action.is_pressed() {
+                let now = self.now();
+                let timeout = Duration::from_millis(self.config.suspend_timeout.unwrap_or(1000));
+                
+                if let Some(last_press) = self.last_suspend_press {
+                    if now.duration_since(last_press) < timeout {
+                        // Double-tap detected - suspend
+                        self.suspend_mode = true;
+                        self.last_suspend_press = None;
+                        return TransformResult::Suspend;
+                    }
+                }
+                // Not a double-tap, update last press time and pass through
+                self.last_suspend_press = Some(now);
+            }
+        }
+
+        // Track lock states for condition evaluation (numlock/capslock).
+        self.update_lock_state_from_event(key, action);
+        self.refresh_active_bindings();
+
+        // Handle tap-dance logic first
+        if self.multipurpose_manager.has_active_tapdance() {
+            let is_same_key = self.multipurpose_manager.get_tapdance_trigger_key() == Some(key);
+
+            if is_same_key {
+                let result = match action {
+                    Action::Press => self.multipurpose_manager.press_tapdance(key),
+                    Action::Release => self.multipurpose_manager.release_tapdance(),
+                    Action::Repeat => {
+                        // Only the hold output repeats; counting taps stay silent.
+                        if let Some(hold_key) = self.multipurpose_manager.get_tapdance_hold_key() {
+                            return TransformResult::Remapped(hold_key);
+                        }
+                        return TransformResult::Suppress;
+                    }
+                };
+                match result {
+                    Some(TapDanceResult::Tap(tap_key)) => {
+                        self.keystore.write().update(key, action, None);
+                        return TransformResult::Remapped(tap_key);
+                    }
+                    Some(TapDanceResult::HoldRelease(hold_key)) => {
+                        self.keystore.write().update(hold_key, action, None);
+                        return TransformResult::Remapped(hold_key);
+                    }
+                    Some(TapDanceResult::Pending) | Some(TapDanceResult::HoldPress(_)) => {
+                        return TransformResult::Suppress;
+                    }
+                    None => {
+                        // Not handled as tap-dance - fall through to normal processing
+                    }
+                }
+            } else if action.is_pressed() {
+                // Different key pressed while counting: resolve before processing it
+                match self.multipurpose_manager.interrupt_tapdance() {
+                    Some(TapDanceResult::Tap(tap_key)) => {
+                        self.pending_outputs.push((tap_key, Action::Release));
+                    }
+                    Some(TapDanceResult::HoldPress(hold_key)) => {
+                        self.keystore.write().update(hold_key, Action::Press, None);
+                        self.pending_outputs.push((hold_key, Action::Press));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Check if this key starts a tap-dance. A repeat never does: the
+        // key was passed through on press and must be released the same way.
+        if action == Action::Press
+            && self.multipurpose_manager.is_tapdance_trigger(key)
+            && !self.multipurpose_manager.has_active_tapdance()
+            && !self.has_other_held_modifier(key)
+        {
+            let should_activate = match self.multipurpose_manager.get_tapdance_conditional(key) {
+                Some(cond) => self.window_context.read().matches(cond),
+                None => true,
+            };
+
+            if should_activate && self.multipurpose_manager.press_tapdance(key).is_some() {
+                // Suppress the original key until the tap count is known
+                return TransformResult::Suppress;
+            }
+        }
+
+        // Handle multipurpose (tap/hold) logic
+        if self.multipurpose_manager.has_active() {
+            // Check if this is the same key as the active multipurpose
+            let is_same_key = self.multipurpose_manager.get_trigger_key() == Some(key);
+            
+            if is_same_key {
+                // This is the multipurpose key being released or repeating
+                match action {
+                    Action::Release => {
+                        // Key released - determine tap vs hold
+                        match self.multipurpose_manager.release() {
+                            Some(MultipurposeResult::Tap(tap_key)) => {
+                                self.keystore.write().update(key, action, None);
+                                return TransformResult::Remapped(tap_key);
+                            }
+                            Some(MultipurposeResult::HoldRelease(hold_key)) => {
+                                self.keystore.write().update(hold_key, action, None);
+                                return TransformResult::Remapped(hold_key);
+                            }
+                            None => {
+                                // No active multipurpose - fall through to normal processing
+                            }
+                        }
+                    }
+                    Action::Repeat => {
+                        // Only repeat when the multipurpose key is already in hold state.
+                        // While still pending (pre-timeout), repeat must stay suppressed.
+                        if self.multipurpose_manager.is_hold_state() {
+                            if let Some(hold_key) = self.multipurpose_manager.get_hold_key() {
+                                return TransformResult::Remapped(hold_key);
+                            }
+                        }
+                        return TransformResult::Suppress;
+                    }
+                    Action::Press => {
+                        // Shouldn't happen for same key - fall through to normal processing
+                    }
+                }
+            } else {
+                // Different key pressed while multipurpose is active
+                // This triggers the interrupt behavior (immediate hold).
+                // A repeat of a key held since before the trigger is not an
+                // interrupt: its release must still match its press.
+                if action == Action::Press {
+                    if let Some((hold_key, new_key)) = self.multipurpose_manager.interrupt_with_key(key) {
+                        // Output hold key press
+                        self.keystore.write().update(hold_key, Action::Press, None);
+                        // Continue processing the new key
+                        return self.process_interrupting_key(new_key, action);
+                    }
+                }
+            }
+        }
+
+        // Check if this key starts a multipurpose sequence (on press only, like tap-dance)
+        if action == Action::Press && self.multipurpose_manager.is_trigger(key) {
+            // Multipurpose triggers are for standalone key usage. If another
+            // modifier is already held (e.g. RAlt-Enter), skip multipurpose so
+            // regular combo/keymap handling can win.
+            if self.has_other_held_modifier(key) {
+                // Fall through to normal processing.
+            } else {
+            // Check if there's a conditional and evaluate it
+            let condition = self.multipurpose_manager.get_conditional(key);
+            
+            let should_activate = if let Some(cond) = condition {
+                // Evaluate the condition against window context
+                self.window_context.read().matches(cond)
+            } else {
+                // No condition, always activate
+                true
+            };
+            
+            if should_activate {
+                let _started = self.multipurpose_manager.start(key);
+                // Suppress the original key until we know tap vs hold
+                return TransformResult::Suppress;
+            }
+            // Condition is false, fall through to normal processing
+            }
+        }
+
+        // Get current modifier state BEFORE processing this key
+        let modifier_snapshot = self.keystore.read().get_modifier_snapshot();
+
+        // Modmap lookup with conditional support (do this BEFORE updating keystore)
+        let modmapped_key = self.lookup_modmap(key, &modifier_snapshot);
+
+        // Keep physical modifier identity for combo matching.
+        // Modmap output still applies to emitted events, but using remapped
+        // modifiers in keystore can make Super-* keymaps miss when Super is
+        // remapped to Ctrl by modmap. A key modmapped to a compound modifier
+        // (`Hyper`) is held as that modifier.
+        let compound = Modifier::compound_for_key(modmapped_key);
+        let keystore_key = if Modifier::is_key_modifier(key) && compound.is_none() {
+            key
+        } else {
+            modmapped_key
+        };
+        self.keystore.write().update(key, action, Some(keystore_key));
+
+        // Update window context if needed
+        // (In production, this would come from Wayland/X11 events)
+
+        // Get updated modifier state (modifiers are stored as physical keys in keystore).
+        // A held compound modifier counts as each of its component keys.
+        let pressed_mods: Vec<Key> = self
+            .keystore
+            .read()
+            .get_pressed_mods_keys()
+            .into_iter()
+            .flat_map(Modifier::expand_key)
+            .collect();
+        // Also compute logical (modmapped) modifiers for fallback matching, so default
+        // Super->Ctrl behavior works unless an explicit Super-* mapping is present.
+        let logical_pressed_mods: Vec<Key> = pressed_mods
+            .iter()
+            .map(|k| self.lookup_modmap(*k, &modifier_snapshot))
+            .collect();
+        let shift_pressed = logical_pressed_mods
+            .iter()
+            .any(|k| *k == Key::from(42) || *k == Key::from(54));
+
+        // If a dead key or compose sequence is active, the next key press may
+        // compose into Unicode. Modifiers pass through so Shift can select case.
+        if action == Action::Press
+            && self.deadkeys.is_active()
+            && !Modifier::is_key_modifier(modmapped_key)
+        {
+            match self.deadkeys.feed(modmapped_key, shift_pressed) {
+                ComposeStep::Composed(text) => {
+                    return match single_codepoint(&text) {
+                        Some(codepoint) => TransformResult::Unicode(codepoint),
+                        None => TransformResult::Text(text),
+                    };
+                }
+                ComposeStep::Pending => return TransformResult::Suppress,
+                ComposeStep::NoMatch => {}
+            }
+        }
+
+        // The Compose key starts an XCompose `<Multi_key>` sequence.
+        if modmapped_key == Key::from(127) && self.deadkeys.has_multi_key() {
+            if action == Action::Press {
+                self.deadkeys.activate_multi_key();
+            }
+            return TransformResult::Suppress;
+        }
+
+        if let Some(compound) = compound {
+            return self.compound_modifier_output(&compound, action);
+        }
+
+        // Check keymap stack timeout
+        if let Some(timeout) = self.keymap_stack.timeout {
+            if self.keymap_stack.should_timeout(self.now(), timeout) {
+                self.exit_keymap();
+            }
+        }
+
+        // A momentary layer trigger only ends its layer; it never reaches combo matching.
+        if self.layers.is_held_trigger(key) {
+            if action == Action::Release {
+                self.layers.release(key);
+                self.press_generations.remove(&key);
+                self.binding_generation += 1;
+            }
+            return TransformResult::Suppress;
+        }
+        let one_shot_armed =
+            action == Action::Press && !Modifier::is_key_modifier(key) && self.layers.has_one_shot();
+
+        let mut combo_result = self.match_combo(&pressed_mods, &logical_pressed_mods, modmapped_key);
+
+        // An entered nested keymap takes the next key press on its own. A key
+        // it maps finishes the nesting once handled; any other key leaves it
+        // and is matched as usual.
+        let mut leave_nested = false;
+        if !self.keymap_stack.stack.is_empty() && action == Action::Press && !Modifier::is_key_modifier(key) {
+            match combo_result {
+                ComboMatchResult::FoundEnterKeymap(_) => {}
+                ComboMatchResult::NotFound => {
+                    self.leave_nested_keymaps();
+                    combo_result = self.match_combo(&pressed_mods, &logical_pressed_mods, modmapped_key);
+                }
+                _ => leave_nested = true,
+            }
+        }
+
+        // A `release:` mapping, or the tap of a tap/hold mapping, fires when
+        // its key comes back up, with the match made on press
+        if action != Action::Press && self.release_bindings.contains_key(&key) {
+            if action == Action::Repeat {
+                return TransformResult::Suppress;
+            }
+            self.press_generations.remove(&key);
+            if self.tap_hold.as_ref().is_some_and(|pending| pending.key == key) {
+                self.tap_hold = None;
+            }
+            if let Some(pressed) = self.release_bindings.remove(&key).flatten() {
+                let result = self.fire_match(pressed);
+                if result != TransformResult::Suppress {
+                    self.deferred_results.push((result, Action::Press));
+                }
+            }
+            return TransformResult::Suppress;
+        }
+
+        // A key keeps its press-time binding until released, even if a settings
+        // or layer change, or a modifier pressed or released since then, would
+        // select a different mapping. A key that was passed through on press
+        // is passed through on release.
+        if action == Action::Release && self.active_combos.remove(&key) {
+            self.press_generations.remove(&key);
+            return TransformResult::Suppress;
+        }
+        if action == Action::Press {
+            let matched = !matches!(combo_result, ComboMatchResult::NotFound | ComboMatchResult::FoundPassthrough);
+            self.press_generations
+                .insert(key, (self.binding_generation, matched));
+        } else if let Some(&(generation, matched)) = self.press_generations.get(&key) {
+            if action == Action::Release {
+                self.press_generations.remove(&key);
+            }
+            if !matched {
+                combo_result = ComboMatchResult::NotFound;
+            } else if generation != self.binding_generation {
+                return TransformResult::Suppress;
+            }
+        }
+
+        // An armed one-shot layer applies to this key press only.
+        if one_shot_armed && !matches!(combo_result, ComboMatchResult::FoundLayer(_)) {
+            self.layers.consume_one_shot();
+            self.binding_generation += 1;
+        }
+
+        if action == Action::Press
+            && (self.matched_release || self.matched_hold.is_some())
+            && !matches!(combo_result, ComboMatchResult::NotFound | ComboMatchResult::FoundPassthrough)
+        {
+            if let Some(hold) = self.matched_hold.take() {
+                self.tap_hold = Some(PendingTapHold {
+                    key,
+                    hold,
+                    pressed_at: self.now(),
+                });
+            }
+            self.release_bindings.insert(key, Some(combo_result));
+            if leave_nested {
+                self.leave_nested_keymaps();
+            }
+            return TransformResult::Suppress;
+        }
+
+        let result = match combo_result {
+            found @ (ComboMatchResult::FoundKey(_) | ComboMatchResult::FoundCombo(_)) => {
+                // Combos fire on press only; a release without a tracked
+                // press (held across a clear) must not fire them again
+                if action != Action::Press {
+                    return TransformResult::Suppress;
+                }
+                self.active_combos.insert(key);
+                self.fire_match(found)
+            }
+            found @ (ComboMatchResult::FoundSequence(_)
+            | ComboMatchResult::FoundUnicode(_)
+            | ComboMatchResult::FoundText(_)) => {
+                if action == Action::Press {
+                    self.fire_match(found)
+                } else {
+                    TransformResult::Suppress
+                }
+            }
+            ComboMatchResult::FoundHint(ComboHint::Reset) => {
+                if action == Action::Press {
+                    self.leave_nested_keymaps();
+                }
+                TransformResult::Suppress
+            }
+            ComboMatchResult::FoundHint(hint @ (ComboHint::EscapeNextKey | ComboHint::EscapeNextCombo)) => {
+                if action != Action::Press {
+                    return TransformResult::Suppress;
+                }
+                self.active_combos.insert(key);
+                log::info!("{}: the next {} passes through untouched", hint, match hint {
+                    ComboHint::EscapeNextKey => "key",
+                    _ => "combo",
+                });
+                self.escape = Some(hint);
+                TransformResult::Suppress
+            }
+            ComboMatchResult::FoundEnterKeymap(name) => {
+                if action != Action::Press {
+                    return TransformResult::Suppress;
+                }
+                self.active_combos.insert(key);
+                self.enter_keymap(name);
+                TransformResult::Suppress
+            }
+            ComboMatchResult::FoundHint(hint) => TransformResult::Hint(hint),
+            ComboMatchResult::FoundLayer(layer) => {
+                if action == Action::Press {
+                    if layer.mode == LayerMode::Momentary && self.config.layer_rollover_timeout.is_some() {
+                        self.pending_layer = Some(PendingLayer {
+                            trigger: key,
+                            name: layer.name.clone(),
+                            pressed_at: self.now(),
+                            deferred: None,
+                        });
+                    } else {
+                        self.layers.activate(key, &layer.name, layer.mode);
+                        self.binding_generation += 1;
+                    }
+                }
+                TransformResult::Suppress
+            }
+            ComboMatchResult::NotFound | ComboMatchResult::FoundPassthrough => {
+                // No combo match, use modmapped key
+                if modmapped_key != key {
+                    TransformResult::Remapped(modmapped_key)
+                } else {
+                    TransformResult::Passthrough(key)
+                }
+            }
+        };
+        if leave_nested {
+            self.leave_nested_keymaps();
+        }
+
+        // Update repeat cache for REPEAT events
+        if action == Action::Repeat {
+            if let Some(cache) = &self.repeat_cache {
+                if cache.is_valid(key, &pressed_mods) {
+                    return cache.result.clone();
+                }
+            }
+            // Convert modifier_snapshot (u16) to Vec<Key>
+            let mod_keys: Vec<Key> = modifier_snapshot
+                .iter()
+                .map(|&code| Key::from(code))
+                .collect();
+            self.repeat_cache = Some(RepeatCache::new(key, result.clone(), mod_keys));
+        } else {
+            self.repeat_cache = None;
+        }
+
+        result
+    }
+
+    fn update_lock_state_from_event(&mut self, key: Key, action: Action) {
+        // Toggle on press events, matching lock-key behavior.
+        if action != Action::Press || self.follow_lock_leds {
+            return;
+        }
+
+        let mut context = self.window_context.write();
+        match key.code() {
+            69 => context.numlock_on = !context.numlock_on, // NUMLOCK
+            58 => context.capslock_on = !context.capslock_on, // CAPSLOCK
+            _ => {}
+        }
+    }
+
+    /// Process a key that interrupted a multipurpose sequence
+    fn process_interrupting_key(&mut self, key: Key, action: Action) -> TransformResult {
+        // First output the hold key press
+        if let Some(hold_key) = self.multipurpose_manager.get_hold_key() {
+            self.keystore.write().update(hold_key, Action::Press, None);
+        }
+        
+        // Now process the interrupting key normally. It belongs to the event
+        // that interrupted, which already remapped it and records it.
+        self.dispatch_event(key, action)
+    }
+
+    /// Check if any multipurpose keys have timed out and should transition to hold
+    /// This should be called periodically (e.g., in the event loop)
+    /// A returned `Release` action means the key should be emitted as a tap
+    /// (a tap-dance whose tap window closed).
+    pub fn check_multipurpose_timeouts(&mut self) -> Option<(Key, Action)> {
+        let (key, action) = self.multipurpose_timeout()?;
+        self.record_audit(None, &[(TransformResult::Remapped(key), action)]);
+        Some((key, action))
+    }
+
+    fn multipurpose_timeout(&mut self) -> Option<(Key, Action)> {
+        match self.multipurpose_manager.check_tapdance_timeout() {
+            Some(TapDanceResult::HoldPress(hold_key)) => {
+                self.keystore.write().update(hold_key, Action::Press, None);
+                return Some((hold_key, Action::Press));
+            }
+            Some(TapDanceResult::Tap(tap_key)) => {
+                return Some((tap_key, Action::Release));
+            }
+            _ => {}
+        }
+
+        if self.multipurpose_manager.is_pending_state() {
+            if let Some(hold_key) = self.multipurpose_manager.check_timeout() {
+                // Keep internal state in sync with emitted hold press.
+                self.keystore.write().update(hold_key, Action::Press, None);
+                return Some((hold_key, Action::Press));
+            }
+        }
+        None
+    }
+
+    /// Time until `check_multipurpose_timeouts` has a tap/hold or tap-dance decision to make
+    pub fn next_multipurpose_timeout_in(&self) -> Option<Duration> {
+        self.multipurpose_manager.next_timeout_in()
+    }
+
+    /// Check if a key is currently an active multipurpose hold key
+    pub fn is_multipurpose_hold_active(&self) -> bool {
+        self.multipurpose_manager.is_hold_state()
+    }
+
+    /// Re-evaluate keymap, modmap and layer conditions if the context or
+    /// settings changed since they were last evaluated
+    fn refresh_active_bindings(&mut self) {
+        let context = self.window_context.read();
+        if self.current_bindings(&context).is_none() {
+            self.active_bindings = Some(ActiveBindings::evaluate(&self.config, &context, self.binding_generation));
+        }
+    }
+
+    /// Cached condition results, if they were evaluated for `context`
+    fn current_bindings(&self, context: &WindowContext) -> Option<&ActiveBindings> {
+        self.active_bindings
+            .as_ref()
+            .filter(|bindings| bindings.is_current(context, self.binding_generation))
+    }
+
+    /// Names of the keymaps, conditional modmaps and layers whose conditions
+    /// hold in the current context
+    pub fn active_binding_names(&mut self) -> (Vec<String>, Vec<String>, Vec<String>) {
+        self.refresh_active_bindings();
+        match &self.active_bindings {
+            Some(bindings) => bindings.names(&self.config),
+            None => Default::default(),
+        }
+    }
+
+    /// Look up a key through modmaps with conditional evaluation
+    fn lookup_modmap(&self, key: Key, _modifier_snapshot: &[u16]) -> Key {
+        // Check conditional modmaps first so specific rules can override defaults.
+        let context = self.window_context.read();
+        let bindings = self.current_bindings(&context);
+        for (index, modmap) in self.config.modmaps.iter().enumerate().skip(1) {
+            if let Some(condition) = modmap.condition() {
+                let active = match bindings {
+                    Some(bindings) => bindings.modmap(index),
+                    None => context.matches(condition),
+                };
+                if active {
+                    if let Some(remapped) = modmap.get(key) {
+                        return remapped;
+                    }
+                }
+            }
+        }
+
+        // Fallback to default modmap.
+        if let Some(modmap) = self.config.modmaps.first() {
+            if let Some(remapped) = modmap.get(key) {
+                return remapped;
+            }
+        }
+
+        key
+    }
+
+    /// Find a matching combo with full modifier expansion
+    ///
+    /// This implements proper handling of non-specific modifiers.
+    /// For example, if user defines "ctrl-a" and presses LEFT_CTRL,
+    /// it should match because LEFT_CTRL is a Ctrl modifier.
+    /// Combo matching with precedence:
+    /// 1) physical modifiers (explicit Super-* exceptions)
+    /// 2) logical/modmapped modifiers (default Super->Ctrl behavior)
+    fn match_combo(&mut self, pressed_mods: &[Key], logical_pressed_mods: &[Key], key: Key) -> ComboMatchResult {
+        let combo_result = self.find_combo_expanded(pressed_mods, key);
+        if matches!(combo_result, ComboMatchResult::NotFound) && logical_pressed_mods != pressed_mods {
+            let logical_result = self.find_combo_expanded(logical_pressed_mods, key);
+            if !matches!(logical_result, ComboMatchResult::NotFound) {
+                return logical_result;
+            }
+        }
+        combo_result
+    }
+
+    fn find_combo_expanded(&mut self, pressed_mods: &[Key], key: Key) -> ComboMatchResult {
+        // Convert pressed keys to modifiers
+        let mut pressed_modifiers: Vec<Modifier> = pressed_mods
+            .iter()
+            .filter_map(|k| Modifier::from_key(*k))
+            .collect();
+
+        // Also add the current key if it's a modifier
+        if let Some(key_mod) = Modifier::from_key(key) {
+            // Check if this modifier is not already in pressed list
+            if !pressed_modifiers.contains(&key_mod) {
+                pressed_modifiers.push(key_mod);
+            }
+        }
+
+        let combo = Combo::new(pressed_modifiers, key);
+
+        // Exact match first, then non-specific modifier expansion. Without
+        // Right Alt or Fn held the exact combo comes first anyway, so unless
+        // tracing, its expansions are only built when it misses.
+        let mut exact_only = self.trace.is_none()
+            && !pressed_mods.contains(&Key::from(100))
+            && !pressed_mods.contains(&Key::from(crate::input::KEY_FN));
+        let mut attempts: SmallVec<[Combo; 4]> = if exact_only {
+            SmallVec::from_elem(combo.clone(), 1)
+        } else {
+            self.combo_attempts(&combo, pressed_mods)
+        };
+
+        // Conditions were evaluated once for the current context
+        self.refresh_active_bindings();
+        let bindings = self.active_bindings.as_ref();
+
+        // An entered nested keymap is the only one consulted. Otherwise active
+        // layers take precedence over regular keymaps; keys they leave
+        // unmapped fall through. Nested keymaps apply only once entered.
+        let entered = self.keymap_stack.stack.last();
+        let layer_keymaps: SmallVec<[(&Keymap, bool); 4]> = self
+            .layers
+            .active()
+            .filter(|_| entered.is_none())
+            .filter_map(|name| {
+                let index = self.config.layers.iter().position(|layer| layer.name() == name)?;
+                Some((&self.config.layers[index], bindings.is_some_and(|b| b.layer(index))))
+            })
+            .collect();
+        let nested = &self.nested_keymaps;
+        let keymaps = self
+            .config
+            .keymaps
+            .iter()
+            .enumerate()
+            .filter(|(_, keymap)| match entered {
+                Some(name) => keymap.name() == name,
+                None => !nested.contains(keymap.name()),
+            })
+            .map(|(index, keymap)| (keymap, bindings.is_some_and(|b| b.keymap(index))));
+
+        loop {
+            for (attempt_index, attempt) in attempts.iter().enumerate() {
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.push(TraceStep::ComboTried {
+                        combo: attempt.to_string(),
+                        expanded: attempt_index > 0,
+                    });
+                }
+
+                for (keymap, matched) in layer_keymaps.iter().copied().chain(keymaps.clone()) {
+                    // Check if keymap has a condition and if it matches
+                    if let Some(condition) = keymap.condition() {
+                        if let Some(trace) = self.trace.as_mut() {
+                            trace.push(TraceStep::Condition {
+                                keymap: keymap.name().to_string(),
+                                condition: condition.source().to_string(),
+                                matched,
+                            });
+                        }
+                        if !matched {
+                            continue; // Skip this keymap - condition doesn't match
+                        }
+                    }
+
+                    if let Some(value) = keymap.get(attempt) {
+                        self.matched_repeat = keymap.repeat(attempt);
+                        self.stats.record_match(keymap.name(), attempt);
+                        self.matched_release = keymap.fires_on_release(attempt);
+                        self.matched_hold = keymap.hold(attempt).map(match_result);
+                        self.matched_combo_style = Some(keymap.combo_style().cloned().unwrap_or_default());
+                        if let Some(metrics) = self.metrics.as_mut() {
+                            metrics.record_keymap_hit(keymap.name());
+                        }
+                        if let Some(trace) = self.trace.as_mut() {
+                            trace.push(TraceStep::KeymapMatched {
+                                keymap: keymap.name().to_string(),
+                                combo: attempt.to_string(),
+                            });
+                        }
+                        return match_result(value);
+                    }
+                }
+            }
+            if !exact_only {
+                break;
+            }
+            exact_only = false;
+            attempts = self.expand_modifiers(&combo);
+        }
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceStep::NoMatch);
+        }
+        ComboMatchResult::NotFound
+    }
+
+    /// Every combo to try for `combo`, in precedence order: on an Apple
+    /// keyboard the Fn-translated key, `AltGr-` combos with Right Alt held,
+    /// then the combo itself; each followed by its non-specific modifier
+    /// expansions (for each modifier, its specific variants)
+    fn combo_attempts(&self, combo: &Combo, pressed_mods: &[Key]) -> SmallVec<[Combo; 4]> {
+        let key = combo.key();
+        let mut attempts: SmallVec<[Combo; 4]> = SmallVec::new();
+        let right_alt = Key::from(100);
+        if pressed_mods.contains(&right_alt) {
+            let altgr = Modifier::altgr();
+            let mut modifiers: Vec<Modifier> = Vec::new();
+            for modifier in pressed_mods
+                .iter()
+                .filter_map(|k| if *k == right_alt { Some(altgr.clone()) } else { Modifier::from_key(*k) })
+            {
+                if !modifiers.contains(&modifier) {
+                    modifiers.push(modifier);
+                }
+            }
+            let altgr_combo = Combo::new(modifiers, key);
+            attempts.extend(self.expand_modifiers(&altgr_combo));
+            attempts.insert(0, altgr_combo);
+        }
+        let mut generic: SmallVec<[Combo; 4]> = SmallVec::new();
+        generic.push(combo.clone());
+        generic.extend(self.expand_modifiers(combo));
+        if self.config.altgr && pressed_mods.contains(&right_alt) {
+            // On an AltGr layout Right Alt only matches as itself (`RAlt-`)
+            let right = Modifier::from_alias("RAlt");
+            generic.retain(|attempt| right.as_ref().is_some_and(|right| attempt.modifiers().contains(right)));
+        }
+        attempts.extend(generic);
+
+        // hid_apple sends HOME for Fn+Left and so on; on an Apple keyboard
+        // `Fn-Left` mappings see the key that was pressed, ahead of `Fn-Home`
+        if pressed_mods.contains(&Key::from(crate::input::KEY_FN)) {
+            if let Some(physical) = crate::input::fn_physical_key(key) {
+                if self.window_context.read().keyboard_type == Some(crate::input::KeyboardType::Mac) {
+                    let physical_combo = Combo::new(combo.modifiers().to_vec(), physical);
+                    let mut fn_attempts: SmallVec<[Combo; 4]> = self.expand_modifiers(&physical_combo).into_iter().collect();
+                    fn_attempts.insert(0, physical_combo);
+                    fn_attempts.extend(attempts);
+                    attempts = fn_attempts;
+                }
+            }
+        }
+        attempts
+    }
+
+    /// Expand a combo by replacing non-specific modifiers with specific variants
+    ///
+    /// For example: [Ctrl, A] becomes:
+    /// - [LCtrl, A], [RCtrl, A]
+    fn expand_modifiers(&self, combo: &Combo) -> SmallVec<[Combo; 4]> {
+        let mut expansions = SmallVec::new();
+
+        // For each modifier in the combo that could be non-specific...
+        for (i, modifier) in combo.modifiers().iter().enumerate() {
+            if !modifier.is_specific() {
+                // Create variants with this modifier replaced
+                let specific_left = modifier.to_left();
+                let specific_right = modifier.to_right();
+
+                if let Some(left) = specific_left {
+                    let mut new_mods: Vec<Modifier> = combo.modifiers().to_vec();
+                    new_mods[i] = left;
+                    expansions.push(Combo::new(new_mods, combo.key()));
+                }
+
+                if let Some(right) = specific_right {
+                    let mut new_mods: Vec<Modifier> = combo.modifiers().to_vec();
+                    new_mods[i] = right;
+                    expansions.push(Combo::new(new_mods, combo.key()));
+                }
+            }
+        }
+
+        expansions
+    }
+
+    /// While the mark is set, movement outputs extend the selection; any
+    /// other output (typing, cutting, ...) unsets the mark
+    fn apply_mark(&mut self, key: Key, action: Action, result: TransformResult) -> TransformResult {
+        if action != Action::Press || Modifier::is_key_modifier(key) {
+            return result;
+        }
+        if let Some(extended) = mark::extend_selection(&result) {
+            return extended;
+        }
+        match result {
+            TransformResult::Suppress | TransformResult::Suspend | TransformResult::Hint(_) => {}
+            TransformResult::Passthrough(output) | TransformResult::Remapped(output)
+                if Modifier::is_key_modifier(output) => {}
+            _ => self.mark = None,
+        }
+        result
+    }
+
+    /// Enter a nested keymap
+    fn enter_keymap(&mut self, name: String) {
+        // Set timeout for nested keymap
+        let timeout = self
+            .config
+            .keymaps
+            .iter()
+            .find(|keymap| keymap.name() == name)
+            .and_then(Keymap::nested_timeout)
+            .or(self.config.nested_keymap_timeout);
+        self.keymap_stack.push(name);
+        self.keymap_stack.timeout = timeout.map(Duration::from_millis);
+        self.keymap_stack.timeout_start = timeout.map(|_| self.now());
+        self.binding_generation += 1;
+    }
+
+    /// Exit the current nested keymap
+    fn exit_keymap(&mut self) {
+        self.keymap_stack.pop();
+        self.keymap_stack.timeout_start = None;
+        self.keymap_stack.timeout = None;
+        self.binding_generation += 1;
+    }
+
+    /// Back to the top-level keymaps; keys pressed inside a nested keymap
+    /// keep their binding until released
+    fn leave_nested_keymaps(&mut self) {
+        if !self.keymap_stack.stack.is_empty() {
+            self.keymap_stack.clear();
+            self.binding_generation += 1;
+        }
+    }
+
+    /// Update window context
+    /// Returns Some(hold_key) if a multipurpose hold was active and should be released.
+    pub fn update_window_context(&mut self, wm_class: Option<String>, wm_name: Option<String>) -> Option<Key> {
+        let mut context = self.window_context.write();
+        context.update(wm_class, wm_name);
+
+        // Clear keymap stack and mark when window changes
+        self.keymap_stack.clear();
+        self.mark = None;
+
+        // Clear multipurpose state and get hold key to release
+        if let Some(hold_key) = self.multipurpose_manager.clear_and_get_hold_key() {
+            // Update keystore to release the hold key
+            self.keystore.write().update(hold_key, Action::Release, None);
+            return Some(hold_key);
+        }
+        None
+    }
+
+    /// Set the window context provider
+    ///
+    /// This allows the engine to periodically query the active window
+    /// for conditional modmap evaluation.
+    pub fn set_window_manager(&mut self, window_manager: Option<Box<dyn WindowContextProvider>>) {
+        self.window_manager = window_manager;
+    }
+
+    /// Set current event-source device name for condition evaluation.
+    /// With per-device keyboard types set, this also switches `keyboard_type`
+    /// to the type of that device.
+    pub fn set_device_name(&mut self, device_name: Option<String>) {
+        let mut context = self.window_context.write();
+        if !self.device_keyboard_types.is_empty() {
+            context.keyboard_type = device_name
+                .as_ref()
+                .and_then(|name| self.device_keyboard_types.get(name).copied())
+                .or(self.default_keyboard_type);
+        }
+        context.set_device_name(device_name);
+    }
+
+    /// Keyboard type of each device by name, for keyboards that differ from
+    /// the one set with `set_keyboard_type` (e.g. a Mac keyboard plugged into
+    /// a ThinkPad)
+    pub fn set_device_keyboard_types(&mut self, types: HashMap<String, crate::input::KeyboardType>) {
+        self.device_keyboard_types = types;
+    }
+
+    /// Set lock state flags for condition evaluation.
+    pub fn set_lock_states(&mut self, numlock_on: bool, capslock_on: bool) {
+        self.window_context
+            .write()
+            .set_lock_states(numlock_on, capslock_on);
+    }
+
+    /// Take the lock state only from `set_lock_states` (fed from device LEDs)
+    /// instead of toggling it on lock key presses, which misses changes made
+    /// before startup or on another keyboard and remapped lock keys
+    pub fn follow_lock_leds(&mut self, enabled: bool) {
+        self.follow_lock_leds = enabled;
+    }
+
+    /// Current (numlock, capslock) flags used for condition evaluation
+    pub fn lock_states(&self) -> (bool, bool) {
+        let context = self.window_context.read();
+        (context.numlock_on, context.capslock_on)
+    }
+
+    /// Keyboard type used for condition evaluation, if resolved
+    pub fn keyboard_type(&self) -> Option<crate::input::KeyboardType> {
+        self.window_context.read().keyboard_type
+    }
+
+    /// Class of the focused window, if known
+    pub fn window_class(&self) -> Option<String> {
+        self.window_context.read().wm_class.clone()
+    }
+
+    /// Set keyboard type for condition evaluation.
+    pub fn set_keyboard_type(&mut self, kb_type: crate::input::KeyboardType) {
+        self.default_keyboard_type = Some(kb_type);
+        self.window_context.write().set_keyboard_type(kb_type);
+    }
+
+    /// Clear keyboard type from condition context.
+    pub fn clear_keyboard_type(&mut self) {
+        self.default_keyboard_type = None;
+        self.window_context.write().clear_keyboard_type();
+    }
+
+    /// Whether the window manager reported a focus or title change since the
+    /// last call; when true, call `update_from_window_manager` without waiting
+    pub fn window_changed(&self) -> bool {
+        self.window_manager
+            .as_ref()
+            .is_some_and(|manager| manager.take_window_changed())
+    }
+
+    /// Have the window manager call `notifier` on focus and title changes.
+    ///
+    /// Returns false when there is no window manager or it cannot notify,
+    /// in which case `update_from_window_manager` must still be polled.
+    pub fn set_window_change_notifier(&mut self, notifier: ChangeNotifier) -> bool {
+        self.window_manager
+            .as_ref()
+            .is_some_and(|manager| manager.set_change_notifier(notifier))
+    }
+
+    /// Whether a window manager is set and connected
+    pub fn window_manager_connected(&self) -> bool {
+        self.window_manager.as_ref().is_some_and(|manager| manager.is_connected())
+    }
+
+    /// Update window context from window manager
+    ///
+    /// This should be called periodically (e.g., every 100ms) to
+    /// update window context for conditional modmap evaluation.
+    /// Returns (changed, hold_key_to_release) where:
+    /// - changed: true if window context changed
+    /// - hold_key_to_release: Some(hold_key) if a multipurpose hold was active and should be released
+    pub fn update_from_window_manager(&mut self) -> (bool, Option<Key>) {
+        let layout_changed = self.update_layout_from_window_manager();
+        let (changed, hold_key) = self.update_window_from_window_manager();
+        (changed || layout_changed, hold_key)
+    }
+
+    /// Take the keyboard layout the window manager reports, returning whether it changed
+    fn update_layout_from_window_manager(&mut self) -> bool {
+        let layout = self.window_manager.as_ref().and_then(|manager| manager.keyboard_layout());
+        let mut context = self.window_context.write();
+        if context.layout == layout {
+            return false;
+        }
+        log::debug!("Keyboard layout: {}", layout.as_deref().unwrap_or("(unknown)"));
+        context.layout = layout;
+        drop(context);
+        // Layout conditions are evaluated once per change, like window conditions
+        self.refresh_active_bindings();
+        true
+    }
+
+    fn update_window_from_window_manager(&mut self) -> (bool, Option<Key>) {
+        if let Some(ref mut manager) = self.window_manager {
+            match manager.get_active_window() {
+                Ok(info) => {
+                    fn normalize_window_field(value: Option<String>) -> Option<String> {
+                        let v = value?;
+                        let trimmed = v.trim();
+                        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("(none)") {
+                            None
+                        } else {
+                            Some(trimmed.to_string())
+                        }
+                    }
+
+                    let new_wm_class = normalize_window_field(info.wm_class);
+                    let new_wm_name = normalize_window_field(info.wm_name);
+
+                    // Keep the last stable window context when the provider returns no active
+                    // window details (common transient state during focus switches).
+                    if new_wm_class.is_none() && new_wm_name.is_none() {
+                        return (false, None);
+                    }
+
+                    let mut context = self.window_context.write();
+
+                    // Check if window changed
+                    let changed =
+                        context.wm_class != new_wm_class || context.wm_name != new_wm_name;
+
+                    // Update context
+                    context.wm_class = new_wm_class;
+                    context.wm_name = new_wm_name;
+                    drop(context);
+
+                    // Clear keymap stack when window changes
+                    if changed {
+                        self.keymap_stack.clear();
+                        // Evaluate conditions for the new window once, not per key
+                        self.refresh_active_bindings();
+                        
+                        // Clear multipurpose state and get hold key to release
+                        if let Some(hold_key) = self.multipurpose_manager.clear_and_get_hold_key() {
+                            // Update keystore to release the hold key
+                            self.keystore.write().update(hold_key, Action::Release, None);
+                            return (true, Some(hold_key));
+                        }
+                    }
+
+                    (changed, None)
+                }
+                Err(_) => {
+                    // If the window provider is disconnected, attempt to reconnect.
+                    // This lets keyrs recover when startup ordering races the desktop session.
+                    if !manager.is_connected() {
+                        let _ = manager.connect();
+                    }
+                    // Window query failed, keep current context
+                    (false, None)
+                }
+            }
+        } else {
+            (false, None)
+        }
+    }
+
+    /// Print current window context for debugging
+    pub fn print_window_context(&self) {
+        let context = self.window_context.read();
+        log::debug!(
+            "WINDOW: wm_class={:?} wm_name={:?} layout={:?} device_name={:?} keyboard_type={:?} numlock={} capslock={}",
+            context.wm_class.as_deref().unwrap_or("(none)"),
+            context.wm_name.as_deref().unwrap_or("(none)"),
+            context.layout.as_deref().unwrap_or("(none)"),
+            context.device_name.as_deref().unwrap_or("(none)"),
+            context.keyboard_type,
+            context.numlock_on,
+            context.capslock_on
+        );
+        if let Some(bindings) = self.current_bindings(&context) {
+            let (keymaps, modmaps, layers) = bindings.names(&self.config);
+            log::debug!(
+                "ACTIVE: keymaps={:?} modmaps={:?} layers={:?}",
+                keymaps,
+                modmaps,
+                layers
+            );
+        }
+        if let Some(hint) = self.escape {
+            log::debug!("ESCAPE: {} armed", hint);
+        }
+    }
+
+    /// Suspend transformation (for suspend_key)
+    pub fn suspend(&mut self) {
+        self.suspend_mode = true;
+    }
+
+    /// Resume transformation
+    pub fn resume(&mut self) {
+        self.suspend_mode = false;
+    }
+
+    /// Whether suspend mode is on, by the suspend key or `suspend_when`
+    pub fn is_suspended(&self) -> bool {
+        self.suspend_mode || self.window_suspended
+    }
+
+    /// Clear all state
+    pub fn clear(&mut self) {
+        self.keystore.write().clear();
+        self.repeat_cache = None;
+        self.keymap_stack.clear();
+        self.escape = None;
+        self.escaped_keys.clear();
+        self.mark = None;
+        self.suspend_mode = false;
+        self.last_suspend_press = None;
+        self.active_combos.clear();
+        self.release_bindings.clear();
+        self.tap_hold = None;
+        self.suspended_held_keys.clear();
+        self.pending_outputs.clear();
+        self.layers.clear();
+        self.pending_layer = None;
+        self.deferred_results.clear();
+        self.autoshift = None;
+        self.synthetic_repeat = None;
+        self.modtap = None;
+    }
+
+    /// Get keystore for external inspection
+    pub fn keystore(&self) -> &Arc<RwLock<Keystore>> {
+        &self.keystore
+    }
+
+    /// Names of the active layers, highest precedence first
+    pub fn active_layers(&self) -> Vec<String> {
+        self.layers.active().map(str::to_string).collect()
+    }
+
+    /// Get current mark value
+    pub fn get_mark(&self) -> Option<bool> {
+        self.mark
+    }
+    
+    /// Get settings reference
+    pub fn settings(&self) -> crate::settings::Settings {
+        self.window_context.read().settings.clone()
+    }
+    
+    /// Update settings
+    pub fn set_settings(&mut self, mut settings: crate::settings::Settings) {
+        for (name, value) in &self.config.setting_defaults {
+            settings.register_default(name, *value);
+        }
+        self.window_context.write().set_settings(settings);
+        self.binding_generation += 1;
+    }
+    
+    /// Reload settings from disk
+    pub fn reload_settings(&mut self) -> Result<(), crate::settings::SettingsError> {
+        let settings = crate::settings::Settings::load_default()?;
+        self.set_settings(settings);
+        Ok(())
+    }
+    
+    /// Get a boolean setting value
+    pub fn get_setting(&self, name: &str) -> bool {
+        self.window_context.read().settings.get_bool(name)
+    }
+    
+    /// Set a boolean setting value
+    pub fn set_setting(&mut self, name: &str, value: bool) {
+        let mut context = self.window_context.write();
+        if context.settings.get_bool(name) != value {
+            context.settings.set_bool(name, value);
+            self.binding_generation += 1;
+        }
+    }
+}
+
+/// Tap/hold keys and tap dances of `config`
+fn multipurpose_manager_for(config: &TransformConfig, clock: &Arc<dyn Clock>) -> MultipurposeManager {
+    let mut manager = MultipurposeManager::with_timeout(config.multipurpose_timeout.unwrap_or(200));
+    manager.set_clock(clock.clone());
+    for multimodmap in &config.multimodmaps {
+        manager.add_modmap(multimodmap.clone());
+    }
+    for tapdance in &config.tapdances {
+        manager.add_tapdance(tapdance.clone());
+    }
+    manager
+}
+
+/// Dead key state with the timeout of `config`
+fn dead_keys_for(config: &TransformConfig, clock: &Arc<dyn Clock>) -> DeadKeyState {
+    let mut deadkeys = match config.dead_key_timeout {
+        Some(ms) => DeadKeyState::new(Duration::from_millis(ms)),
+        None => DeadKeyState::default(),
+    };
+    deadkeys.set_clock(clock.clone());
+    deadkeys
+}
+
+/// Keymaps some mapping of `config` enters with `keymap(name)`
+fn nested_keymaps_for(config: &TransformConfig) -> HashSet<String> {
+    config
+        .keymaps
+        .iter()
+        .chain(&config.layers)
+        .flat_map(|keymap| keymap.mappings().values())
+        .filter_map(|value| match value {
+            KeymapValue::EnterKeymap(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// What matching a keymap value finds
+fn match_result(value: &KeymapValue) -> ComboMatchResult {
+    match value {
+        KeymapValue::Key(k) => ComboMatchResult::FoundKey(*k),
+        KeymapValue::Combo(c) => ComboMatchResult::FoundCombo(c.clone()),
+        KeymapValue::Sequence(steps) => ComboMatchResult::FoundSequence(steps.clone()),
+        KeymapValue::ComboHint(h) => ComboMatchResult::FoundHint(*h),
+        KeymapValue::Unicode(codepoint) => ComboMatchResult::FoundUnicode(*codepoint),
+        KeymapValue::Text(text) => ComboMatchResult::FoundText(text.clone()),
+        KeymapValue::Layer(layer) => ComboMatchResult::FoundLayer(layer.clone()),
+        KeymapValue::EnterKeymap(name) => ComboMatchResult::FoundEnterKeymap(name.clone()),
+        KeymapValue::Passthrough => ComboMatchResult::FoundPassthrough,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::{WindowContextProvider, WindowError, WindowInfo};
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    struct ScriptedWindowProvider {
+        windows: Mutex<Vec<WindowInfo>>,
+        layout: Arc<Mutex<Option<String>>>,
+    }
+
+    impl ScriptedWindowProvider {
+        fn new(windows: Vec<WindowInfo>) -> Self {
+            Self {
+                windows: Mutex::new(windows),
+                layout: Arc::new(Mutex::new(None)),
+            }
+        }
+    }
+
+    impl WindowContextProvider for ScriptedWindowProvider {
+        fn connect(&mut self) -> Result<(), WindowError> {
+            Ok(())
+        }
+
+        fn disconnect(&mut self) {}
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn get_active_window(&self) -> Result<WindowInfo, WindowError> {
+            let mut guard = self.windows.lock();
+            if guard.is_empty() {
+                Ok(WindowInfo::new())
+            } else {
+                Ok(guard.remove(0))
+            }
+        }
+
+        fn keyboard_layout(&self) -> Option<String> {
+            self.layout.lock().clone()
+        }
+    }
+
+    struct ReconnectingScriptedWindowProvider {
+        connected: Mutex<bool>,
+        window: WindowInfo,
+    }
+
+    impl ReconnectingScriptedWindowProvider {
+        fn new(window: WindowInfo) -> Self {
+            Self {
+                connected: Mutex::new(false),
+                window,
+            }
+        }
+    }
+
+    impl WindowContextProvider for ReconnectingScriptedWindowProvider {
+        fn connect(&mut self) -> Result<(), WindowError> {
+            *self.connected.lock() = true;
+            Ok(())
+        }
+
+        fn disconnect(&mut self) {
+            *self.connected.lock() = false;
+        }
+
+        fn is_connected(&self) -> bool {
+            *self.connected.lock()
+        }
+
+        fn get_active_window(&self) -> Result<WindowInfo, WindowError> {
+            if !*self.connected.lock() {
+                return Err(WindowError::NotConnected);
+            }
+            Ok(self.window.clone())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_config_default() {
+        let config = TransformConfig::default();
+        assert!(!config.modmaps.is_empty());
+        assert!(config.keymaps.is_empty());
+        assert!(config.multipurpose_timeout.is_some());
+        assert!(config.suspend_timeout.is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_update_from_window_manager_ignores_transient_none_none() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        engine.update_window_context(Some("kitty".to_string()), Some("terminal".to_string()));
+        engine.set_window_manager(Some(Box::new(ScriptedWindowProvider::new(vec![
+            WindowInfo::new(),
+            WindowInfo::with_details(
+                Some("firefox".to_string()),
+                Some("Mozilla Firefox".to_string()),
+            ),
+        ]))));
+
+        // First update is transient empty context and must be ignored.
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(!changed);
+        let ctx = engine.window_context.read().clone();
+        assert_eq!(ctx.wm_class.as_deref(), Some("kitty"));
+        assert_eq!(ctx.wm_name.as_deref(), Some("terminal"));
+
+        // Second update contains real data and should apply.
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(changed);
+        let ctx = engine.window_context.read().clone();
+        assert_eq!(ctx.wm_class.as_deref(), Some("firefox"));
+        assert_eq!(ctx.wm_name.as_deref(), Some("Mozilla Firefox"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_update_from_window_manager_tracks_layout() {
+        let config = TransformConfig {
+            keymaps: vec![Keymap::with_conditional("german", HashMap::new(), "layout =~ 'de'".to_string())],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        let provider = ScriptedWindowProvider::new(Vec::new());
+        let layout = provider.layout.clone();
+        *layout.lock() = Some("us".to_string());
+        engine.set_window_manager(Some(Box::new(provider)));
+
+        // A layout change counts even while no window is reported
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(changed);
+        assert!(engine.window_context.read().matches_condition("layout == 'us'"));
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(!changed);
+
+        *layout.lock() = Some("de(nodeadkeys)".to_string());
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(changed);
+        let ctx = engine.window_context.read().clone();
+        assert!(ctx.matches_condition("layout =~ 'de'"));
+        assert!(!ctx.matches_condition("layout == 'de'"));
+        assert!(!ctx.matches_condition("layout == 'us'"));
+        assert_eq!(engine.active_binding_names().0, vec!["german".to_string()]);
+
+        *layout.lock() = Some("us".to_string());
+        engine.update_from_window_manager();
+        assert!(engine.active_binding_names().0.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_update_from_window_manager_reconnects_provider() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        engine.set_window_manager(Some(Box::new(ReconnectingScriptedWindowProvider::new(
+            WindowInfo::with_details(
+                Some("kitty".to_string()),
+                Some("terminal".to_string()),
+            ),
+        ))));
+
+        // First call fails query and triggers reconnect attempt.
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(!changed);
+
+        // Second call should return the window context after reconnect.
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(changed);
+        let ctx = engine.window_context.read().clone();
+        assert_eq!(ctx.wm_class.as_deref(), Some("kitty"));
+        assert_eq!(ctx.wm_name.as_deref(), Some("terminal"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_matches() {
+        let mut ctx = WindowContext::new();
+        ctx.wm_class = Some("Firefox".to_string());
+
+        // Should match regex
+        assert!(ctx.matches_condition("wm_class =~ 'fire'"));
+        assert!(ctx.matches_condition("wm_class =~ 'Firefox'"));
+
+        // Should not match different class
+        assert!(!ctx.matches_condition("wm_class =~ 'Chrome'"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_matches_inline_case_and_anchor_patterns() {
+        let mut ctx = WindowContext::new();
+        ctx.wm_class = Some("firefox".to_string());
+
+        assert!(ctx.matches_condition("wm_class =~ '(?i)Firefox'"));
+        assert!(ctx.matches_condition("wm_class =~ '(?i)^FIREFOX$'"));
+        assert!(!ctx.matches_condition("wm_class =~ '^fire$'"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_condition_boolean_and_device_fields() {
+        let mut ctx = WindowContext::new();
+        ctx.wm_class = Some("Alacritty".to_string());
+        ctx.set_device_name(Some("Telink Wireless Gaming Keyboard".to_string()));
+        ctx.set_lock_states(false, true);
+
+        assert!(ctx.matches_condition("device_name =~ 'Telink'"));
+        assert!(ctx.matches_condition("devn =~ 'Gaming'"));
+        assert!(ctx.matches_condition("capslock == true"));
+        assert!(ctx.matches_condition("numlk == false"));
+        assert!(!ctx.matches_condition("numlock == true"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_condition_logical_composition() {
+        let mut ctx = WindowContext::new();
+        ctx.wm_class = Some("Alacritty".to_string());
+        ctx.wm_name = Some("server".to_string());
+        ctx.set_device_name(Some("Telink Keyboard".to_string()));
+        ctx.set_lock_states(false, false);
+        ctx.settings.set_bool("forced_numpad", true);
+
+        assert!(ctx.matches_condition(
+            "settings.forced_numpad and (wm_class =~ 'alacritty' or wm_name =~ 'kitty')"
+        ));
+        assert!(ctx.matches_condition("numlock and device_name =~ 'telink'"));
+        assert!(!ctx.matches_condition("not numlock and device_name =~ 'telink'"));
+        assert!(!ctx.matches_condition("settings.forced_numpad and not (wm_class =~ 'alacritty')"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_condition_typed_settings() {
+        let mut ctx = WindowContext::new();
+        ctx.settings.set_str("optspec_layout", "us").unwrap();
+        ctx.settings.set_int("tab_width", 4).unwrap();
+
+        assert!(ctx.matches_condition("settings.optspec_layout == 'US'"));
+        assert!(!ctx.matches_condition("settings.optspec_layout == 'ABC'"));
+        assert!(ctx.matches_condition("settings.optspec_layout =~ '^U'"));
+        assert!(ctx.matches_condition("settings.tab_width == 4 and settings.tab_width"));
+        assert!(!ctx.matches_condition("settings.tab_width == 8"));
+        // Flags compare as before
+        assert!(ctx.matches_condition("settings.unset == false"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_forced_numpad_forces_numlock_conditions_true() {
+        let mut ctx = WindowContext::new();
+        ctx.set_lock_states(false, false);
+        ctx.settings.set_bool("forced_numpad", true);
+
+        assert!(ctx.matches_condition("numlock"));
+        assert!(ctx.matches_condition("numlk == true"));
+        assert!(!ctx.matches_condition("not numlk"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_keyboard_type_follows_event_device() {
+        use crate::input::KeyboardType;
+        use crate::Combo;
+
+        let mut mac = Keymap::with_conditional("mac", HashMap::new(), "keyboard_type =~ 'Mac'".to_string());
+        mac.insert(Combo::new(vec![], Key::from(30)), KeymapValue::Key(Key::from(48))); // A -> B
+        let config = TransformConfig {
+            keymaps: vec![mac],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.set_keyboard_type(KeyboardType::IBM);
+        engine.set_device_keyboard_types(HashMap::from([("Magic Keyboard".to_string(), KeyboardType::Mac)]));
+
+        engine.set_device_name(Some("Magic Keyboard".to_string()));
+        assert_eq!(engine.keyboard_type(), Some(KeyboardType::Mac));
+        assert_eq!(engine.process_event(Key::from(30), Action::Press), TransformResult::ComboKey(Key::from(48)));
+        engine.process_event(Key::from(30), Action::Release);
+
+        engine.set_device_name(Some("AT Translated Set 2 keyboard".to_string()));
+        assert_eq!(engine.keyboard_type(), Some(KeyboardType::IBM));
+        assert_eq!(engine.process_event(Key::from(30), Action::Press), TransformResult::Passthrough(Key::from(30)));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_fn_combo_matches_key_translated_by_hid_apple() {
+        use crate::input::KeyboardType;
+        use crate::Combo;
+
+        let fn_mod = Modifier::from_alias("Fn").expect("Fn modifier should exist");
+        let mut apple = Keymap::new("apple");
+        apple.insert(Combo::new(vec![fn_mod.clone()], Key::from(105)), KeymapValue::Key(Key::from(107))); // Fn-Left -> END
+        apple.insert(Combo::new(vec![fn_mod], Key::from(59)), KeymapValue::Key(Key::from(113))); // Fn-F1 -> MUTE
+        let config = TransformConfig {
+            keymaps: vec![apple],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        let fn_key = Key::from(crate::input::KEY_FN);
+
+        // Elsewhere HOME with Fn held is just Fn+Home
+        engine.process_event(fn_key, Action::Press);
+        assert_eq!(engine.process_event(Key::from(102), Action::Press), TransformResult::Passthrough(Key::from(102)));
+        engine.process_event(Key::from(102), Action::Release);
+        engine.process_event(fn_key, Action::Release);
+
+        engine.set_keyboard_type(KeyboardType::Mac);
+        engine.process_event(fn_key, Action::Press);
+        assert_eq!(engine.process_event(Key::from(102), Action::Press), TransformResult::ComboKey(Key::from(107)));
+        engine.process_event(Key::from(102), Action::Release);
+        // fnmode=2 sends BRIGHTNESSDOWN for Fn+F1
+        assert_eq!(engine.process_event(Key::from(224), Action::Press), TransformResult::ComboKey(Key::from(113)));
+        engine.process_event(Key::from(224), Action::Release);
+        engine.process_event(fn_key, Action::Release);
+
+        // Without Fn the translated key is left alone
+        assert_eq!(engine.process_event(Key::from(102), Action::Press), TransformResult::Passthrough(Key::from(102)));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_window_context_keyboard_type() {
+        use crate::input::KeyboardType;
+        
+        let mut ctx = WindowContext::new();
+        ctx.set_keyboard_type(KeyboardType::IBM);
+
+        // Should match single keyboard type
+        assert!(ctx.matches_condition("keyboard_type =~ 'IBM'"));
+        assert!(!ctx.matches_condition("keyboard_type =~ 'Mac'"));
+
+        // Should match in list
+        assert!(ctx.matches_condition("keyboard_type =~ 'IBM, Chromebook'"));
+        assert!(ctx.matches_condition("keyboard_type =~ 'Mac, Windows, IBM'"));
+        assert!(!ctx.matches_condition("keyboard_type =~ 'Mac, Chromebook'"));
+
+        // Test with different keyboard type
+        ctx.set_keyboard_type(KeyboardType::Chromebook);
+        assert!(ctx.matches_condition("keyboard_type =~ 'Chromebook'"));
+        assert!(ctx.matches_condition("keyboard_type =~ 'IBM, Chromebook'"));
+        assert!(!ctx.matches_condition("keyboard_type =~ 'IBM'"));
+
+        // Test without keyboard type set
+        ctx.clear_keyboard_type();
+        assert!(!ctx.matches_condition("keyboard_type =~ 'IBM'"));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_repeat_cache() {
+        let cache = RepeatCache::new(
+            Key::from(30),
+            TransformResult::Passthrough(Key::from(30)),
+            vec![Key::from(29)], // Left Ctrl
+        );
+
+        // Should be valid with same modifiers
+        assert!(cache.is_valid(Key::from(30), &[Key::from(29)]));
+
+        // Should be invalid with different modifiers
+        assert!(!cache.is_valid(
+            Key::from(30),
+            &[Key::from(56)] // Left Alt
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_conditional_modmap_overrides_default_when_condition_matches() {
+        let kp1 = Key::from(79);
+        let default_end = Key::from(107);
+        let forced_digit_1 = Key::from(2);
+
+        let mut default_map = HashMap::new();
+        default_map.insert(kp1, default_end);
+
+        let mut forced_map = HashMap::new();
+        forced_map.insert(kp1, forced_digit_1);
+
+        let config = TransformConfig {
+            modmaps: vec![
+                Modmap::new("default", default_map),
+                Modmap::with_conditional(
+                    "forced_numpad",
+                    forced_map,
+                    "settings.forced_numpad".to_string(),
+                ),
+            ],
+            ..TransformConfig::default()
+        };
+
+        let mut engine = TransformEngine::new(config);
+        engine.set_setting("forced_numpad", false);
+
+        // Default path when setting is false.
+        let result = engine.process_event(kp1, Action::Press);
+        assert_eq!(result, TransformResult::Remapped(default_end));
+
+        // Conditional path should override default.
+        engine.set_setting("forced_numpad", true);
+        let result = engine.process_event(kp1, Action::Press);
+        assert_eq!(result, TransformResult::Remapped(forced_digit_1));
+    }
+
+    // Tests for MultipurposeManager integration
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_with_multipurpose() {
+        let config = TransformConfig::default();
+        let mut engine = TransformEngine::new(config);
+        
+        // Add a multipurpose entry (CAPSLOCK -> ESCAPE tap, RIGHT_CTRL hold)
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        
+        // Press the multipurpose key
+        let result = engine.process_event(Key::from(58), Action::Press);
+        assert_eq!(result, TransformResult::Suppress, "Should suppress initial press");
+        
+        // Release quickly (tap)
+        let result = engine.process_event(Key::from(58), Action::Release);
+        assert_eq!(result, TransformResult::Remapped(Key::from(1)), "Should output ESCAPE on tap");
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_multipurpose_interrupt() {
+        let config = TransformConfig::default();
+        let mut engine = TransformEngine::new(config);
+        
+        // Add a multipurpose entry
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        
+        // Press the multipurpose key
+        let _ = engine.process_event(Key::from(58), Action::Press);
+        
+        // Verify we're in pending state before interrupt
+        assert!(engine.multipurpose_manager.is_pending_state());
+        
+        // Press another key (interrupt)
+        let _result = engine.process_event(Key::from(30), Action::Press); // 'A' key
+        
+        // After interrupt, should still be active but in hold state
+        assert!(engine.multipurpose_manager.has_active(), "Multipurpose should still be active after interrupt");
+        assert!(engine.multipurpose_manager.is_hold_state(), "Multipurpose should be in hold state after interrupt");
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_multipurpose_interrupt_audited_once() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        engine.enable_audit(16);
+
+        engine.process_event(Key::from(58), Action::Press);
+        engine.process_event(Key::from(30), Action::Press); // interrupts
+
+        let audit = engine.audit().expect("audit enabled");
+        assert_eq!(audit.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_multipurpose_timeout_check() {
+        let config = TransformConfig {
+            multipurpose_timeout: Some(10), // 10ms timeout
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        
+        // Add a multipurpose entry
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        
+        // Press the multipurpose key
+        let _ = engine.process_event(Key::from(58), Action::Press);
+        
+        // Wait for timeout
+        std::thread::sleep(Duration::from_millis(50));
+        
+        // Check timeout
+        let timeout_result = engine.check_multipurpose_timeouts();
+        assert!(timeout_result.is_some(), "Should detect timeout");
+        assert_eq!(timeout_result.unwrap().0, Key::from(97), "Should output RIGHT_CTRL");
+
+        // Timeout transition should also update internal modifier state.
+        let pressed_mods = engine.keystore.read().get_pressed_mods_keys();
+        assert!(
+            pressed_mods.contains(&Key::from(97)),
+            "RIGHT_CTRL should be tracked as pressed after timeout hold activation"
+        );
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn manual_clock_engine(config: TransformConfig) -> (TransformEngine, crate::clock::ManualClock) {
+        let clock = crate::clock::ManualClock::new();
+        let engine = TransformEngine::with_clock(config, crate::settings::Settings::new(), Arc::new(clock.clone()));
+        (engine, clock)
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_multipurpose_timeout_follows_clock() {
+        let config = TransformConfig {
+            multipurpose_timeout: Some(200),
+            ..TransformConfig::default()
+        };
+        let (mut engine, clock) = manual_clock_engine(config);
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+
+        // Released just inside the window: tap
+        engine.process_event(Key::from(58), Action::Press);
+        clock.advance(Duration::from_millis(199));
+        assert_eq!(engine.check_multipurpose_timeouts(), None);
+        assert_eq!(
+            engine.process_event(Key::from(58), Action::Release),
+            TransformResult::Remapped(Key::from(1))
+        );
+
+        // Held to the timeout: hold
+        engine.process_event(Key::from(58), Action::Press);
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(engine.check_multipurpose_timeouts(), Some((Key::from(97), Action::Press)));
+        assert_eq!(
+            engine.process_event(Key::from(58), Action::Release),
+            TransformResult::Remapped(Key::from(97))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_suspend_double_tap_follows_clock() {
+        let config = TransformConfig {
+            suspend_key: Some(Key::from(88)), // F12
+            suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            ..TransformConfig::default()
+        };
+        let (mut engine, clock) = manual_clock_engine(config);
+        let tap = |engine: &mut TransformEngine| {
+            let result = engine.process_event(Key::from(88), Action::Press);
+            engine.process_event(Key::from(88), Action::Release);
+            result
+        };
+
+        // Too slow for a double tap
+        tap(&mut engine);
+        clock.advance(Duration::from_millis(1000));
+        assert_eq!(tap(&mut engine), TransformResult::Passthrough(Key::from(88)));
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(tap(&mut engine), TransformResult::Suspend);
+        assert!(engine.suspend_mode);
+
+        tap(&mut engine);
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(tap(&mut engine), TransformResult::Suspend);
+        assert!(!engine.suspend_mode);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_keymap_stack_timeout_uses_given_time() {
+        let clock = crate::clock::ManualClock::new();
+        let mut stack = KeymapStack::default();
+        stack.push("nested".to_string());
+        stack.timeout_start = Some(clock.now());
+
+        clock.advance(Duration::from_millis(999));
+        assert!(!stack.should_timeout(clock.now(), Duration::from_millis(1000)));
+        clock.advance(Duration::from_millis(1));
+        assert!(stack.should_timeout(clock.now(), Duration::from_millis(1000)));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_modmap_to_compound_modifier() {
+        use crate::config::parse_combo_string;
+        use crate::modifier::HYPER_KEY_CODE;
+        use crate::Combo;
+
+        let (capslock, a, b) = (Key::from(58), Key::from(30), Key::from(48));
+        let (ctrl, alt, shift, meta) = (Key::from(29), Key::from(56), Key::from(42), Key::from(125));
+        let hyper_a = parse_combo_string("Hyper-a").unwrap();
+        let mut keymap = Keymap::new("general");
+        keymap.insert(Combo::new(hyper_a.modifiers, hyper_a.key), KeymapValue::Key(b));
+        let config = TransformConfig {
+            modmaps: vec![Modmap::new("default", HashMap::from([(capslock, Key::from(HYPER_KEY_CODE))]))],
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+
+        // The one key presses all four modifiers, and Hyper-a matches
+        assert_eq!(engine.process_event(capslock, Action::Press), TransformResult::Remapped(meta));
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![
+                (TransformResult::Remapped(ctrl), Action::Press),
+                (TransformResult::Remapped(alt), Action::Press),
+                (TransformResult::Remapped(shift), Action::Press),
+            ]
+        );
+        assert_eq!(engine.process_event(capslock, Action::Repeat), TransformResult::Remapped(meta));
+        assert!(engine.take_deferred_results().is_empty());
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::ComboKey(b));
+        engine.process_event(a, Action::Release);
+
+        assert_eq!(engine.process_event(capslock, Action::Release), TransformResult::Remapped(meta));
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![
+                (TransformResult::Remapped(shift), Action::Release),
+                (TransformResult::Remapped(alt), Action::Release),
+                (TransformResult::Remapped(ctrl), Action::Release),
+            ]
+        );
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_nested_keymap_timeout_and_reset() {
+        use crate::Combo;
+
+        let meta = Modifier::from_name("META").unwrap();
+        let mut general = Keymap::new("general");
+        general.insert(
+            Combo::new(vec![meta.clone()], Key::from(45)), // Meta-X
+            KeymapValue::EnterKeymap("cx".to_string()),
+        );
+        let mut cx = Keymap::new("cx");
+        cx.insert(Combo::new(vec![meta], Key::from(19)), KeymapValue::ComboHint(ComboHint::Reset)); // Meta-R
+        cx.set_nested_timeout(Some(3000));
+        let config = TransformConfig {
+            keymaps: vec![general, cx],
+            suspend_timeout: Some(100),
+            nested_keymap_timeout: Some(500),
+            ..TransformConfig::default()
+        };
+        let (mut engine, clock) = manual_clock_engine(config);
+        let (meta, x, r) = (Key::from(125), Key::from(45), Key::from(19));
+
+        engine.process_event(meta, Action::Press);
+        assert_eq!(engine.process_event(x, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(x, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.keymap_stack.stack, vec!["cx".to_string()]);
+        assert_eq!(engine.keymap_stack.timeout, Some(Duration::from_millis(3000)));
+
+        // The keymap's own timeout wins over both global timeouts
+        clock.advance(Duration::from_millis(2999));
+        engine.process_event(meta, Action::Repeat);
+        assert_eq!(engine.keymap_stack.stack.len(), 1);
+        clock.advance(Duration::from_millis(1));
+        engine.process_event(meta, Action::Repeat);
+        assert!(engine.keymap_stack.stack.is_empty());
+
+        // Reset leaves the stack without output
+        engine.process_event(x, Action::Press);
+        engine.process_event(x, Action::Release);
+        assert_eq!(engine.keymap_stack.stack.len(), 1);
+        assert_eq!(engine.process_event(r, Action::Press), TransformResult::Suppress);
+        assert!(engine.keymap_stack.stack.is_empty());
+        assert_eq!(engine.process_event(r, Action::Release), TransformResult::Suppress);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_altgr_combos() {
+        use crate::Combo;
+
+        let (left_alt, right_alt, e, d) = (Key::from(56), Key::from(100), Key::from(18), Key::from(32));
+        let mut keymap = Keymap::new("general");
+        keymap.insert(
+            Combo::new(vec![Modifier::from_name("ALT").unwrap()], e),
+            KeymapValue::Key(Key::from(30)),
+        );
+        keymap.insert(Combo::new(vec![Modifier::altgr()], e), KeymapValue::Key(Key::from(48)));
+        keymap.insert(
+            Combo::new(vec![Modifier::from_name("ALT").unwrap()], d),
+            KeymapValue::Key(Key::from(46)),
+        );
+        let config = TransformConfig {
+            keymaps: vec![keymap.clone()],
+            ..TransformConfig::default()
+        };
+        let (mut engine, _clock) = manual_clock_engine(config);
+
+        // AltGr-e wins over Alt-e on Right Alt; Left Alt still gets Alt-e
+        engine.process_event(right_alt, Action::Press);
+        assert_eq!(engine.process_event(e, Action::Press), TransformResult::ComboKey(Key::from(48)));
+        engine.process_event(e, Action::Release);
+        // Without an AltGr- mapping, Right Alt is still Alt
+        assert_eq!(engine.process_event(d, Action::Press), TransformResult::ComboKey(Key::from(46)));
+        engine.process_event(d, Action::Release);
+        engine.process_event(right_alt, Action::Release);
+        engine.process_event(left_alt, Action::Press);
+        assert_eq!(engine.process_event(e, Action::Press), TransformResult::ComboKey(Key::from(30)));
+        engine.process_event(e, Action::Release);
+        engine.process_event(left_alt, Action::Release);
+
+        // On an AltGr layout generic Alt- combos leave Right Alt alone
+        let config = TransformConfig {
+            keymaps: vec![keymap],
+            altgr: true,
+            ..TransformConfig::default()
+        };
+        let (mut engine, _clock) = manual_clock_engine(config);
+        engine.process_event(right_alt, Action::Press);
+        assert_eq!(engine.process_event(e, Action::Press), TransformResult::ComboKey(Key::from(48)));
+        engine.process_event(e, Action::Release);
+        assert_eq!(engine.process_event(d, Action::Press), TransformResult::Passthrough(d));
+        engine.process_event(d, Action::Release);
+        engine.process_event(right_alt, Action::Release);
+        engine.process_event(left_alt, Action::Press);
+        assert_eq!(engine.process_event(d, Action::Press), TransformResult::ComboKey(Key::from(46)));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_escape_next_key_and_combo() {
+        use crate::Combo;
+
+        let ctrl_mod = Modifier::from_name("CONTROL").unwrap();
+        let (ctrl, alt, q, w, c, a, b) = (
+            Key::from(29),
+            Key::from(56),
+            Key::from(16),
+            Key::from(17),
+            Key::from(46),
+            Key::from(30),
+            Key::from(48),
+        );
+        let mut keymap = Keymap::new("general");
+        keymap.insert(Combo::new(vec![ctrl_mod.clone()], q), KeymapValue::ComboHint(ComboHint::EscapeNextKey));
+        keymap.insert(Combo::new(vec![ctrl_mod.clone()], w), KeymapValue::ComboHint(ComboHint::EscapeNextCombo));
+        keymap.insert(Combo::new(vec![ctrl_mod.clone()], c), KeymapValue::Key(Key::from(57)));
+        let config = TransformConfig {
+            modmaps: vec![Modmap::new("default", HashMap::from([(a, b), (alt, ctrl)]))],
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        };
+        let (mut engine, _clock) = manual_clock_engine(config);
+
+        // C-q, then C-c with Ctrl held throughout: c skips its keymap
+        engine.process_event(ctrl, Action::Press);
+        assert_eq!(engine.process_event(q, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(q, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.pending_escape(), Some(ComboHint::EscapeNextKey));
+        engine.set_trace(true);
+        assert_eq!(engine.process_event(c, Action::Press), TransformResult::Passthrough(c));
+        assert_eq!(engine.take_trace(), vec![TraceStep::Escaped]);
+        assert_eq!(engine.pending_escape(), None);
+        assert_eq!(engine.process_event(c, Action::Repeat), TransformResult::Passthrough(c));
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Passthrough(c));
+        // Spent: the next C-c is remapped again
+        assert_eq!(engine.process_event(c, Action::Press), TransformResult::ComboKey(Key::from(57)));
+        engine.process_event(c, Action::Release);
+        engine.process_event(ctrl, Action::Release);
+
+        // The escaped key also skips modmaps; modifiers pressed first don't spend it
+        engine.process_event(ctrl, Action::Press);
+        engine.process_event(q, Action::Press);
+        engine.process_event(q, Action::Release);
+        engine.process_event(ctrl, Action::Release);
+        assert_eq!(engine.process_event(alt, Action::Press), TransformResult::Remapped(ctrl));
+        assert_eq!(engine.pending_escape(), Some(ComboHint::EscapeNextKey));
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+        engine.process_event(a, Action::Release);
+        engine.process_event(alt, Action::Release);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Remapped(b));
+        engine.process_event(a, Action::Release);
+
+        // A whole combo: its modifiers skip modmaps too, until released
+        engine.process_event(ctrl, Action::Press);
+        engine.process_event(w, Action::Press);
+        engine.process_event(w, Action::Release);
+        engine.process_event(ctrl, Action::Release);
+        assert_eq!(engine.process_event(alt, Action::Press), TransformResult::Passthrough(alt));
+        assert_eq!(engine.pending_escape(), Some(ComboHint::EscapeNextCombo));
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+        assert_eq!(engine.pending_escape(), None);
+        assert_eq!(engine.process_event(a, Action::Release), TransformResult::Passthrough(a));
+        assert_eq!(engine.process_event(alt, Action::Repeat), TransformResult::Passthrough(alt));
+        assert_eq!(engine.process_event(alt, Action::Release), TransformResult::Passthrough(alt));
+        assert_eq!(engine.process_event(alt, Action::Press), TransformResult::Remapped(ctrl));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_mark_extends_selection() {
+        use crate::Combo;
+
+        let ctrl_mod = Modifier::from_name("CONTROL").unwrap();
+        let shift_mod = Modifier::from_name("SHIFT").unwrap();
+        let (ctrl, space, f, w, g, a) = (
+            Key::from(29),
+            Key::from(57),
+            Key::from(33),
+            Key::from(17),
+            Key::from(34),
+            Key::from(30),
+        );
+        let (right, x, esc) = (Key::from(106), Key::from(45), Key::from(1));
+        let mut keymap = Keymap::new("emacs");
+        keymap.insert(Combo::new(vec![ctrl_mod.clone()], space), KeymapValue::Sequence(vec![ActionStep::SetMark(None)]));
+        keymap.insert(Combo::new(vec![ctrl_mod.clone()], f), KeymapValue::Key(right));
+        keymap.insert(
+            Combo::new(vec![ctrl_mod.clone()], w),
+            KeymapValue::Combo(Combo::new(vec![ctrl_mod.clone()], x)),
+        );
+        keymap.insert(
+            Combo::new(vec![ctrl_mod.clone()], g),
+            KeymapValue::Sequence(vec![ActionStep::SetMark(Some(false)), ActionStep::Combo(Combo::new(vec![], esc))]),
+        );
+        let config = TransformConfig {
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        };
+        let (mut engine, _clock) = manual_clock_engine(config);
+        let tap = |engine: &mut TransformEngine, key: Key| {
+            let result = engine.process_event(key, Action::Press);
+            engine.process_event(key, Action::Release);
+            result
+        };
+
+        engine.process_event(ctrl, Action::Press);
+        assert_eq!(tap(&mut engine, f), TransformResult::ComboKey(right));
+        assert_eq!(tap(&mut engine, space), TransformResult::Suppress);
+        assert_eq!(engine.get_mark(), Some(true));
+
+        // Movement selects; anything else unsets the mark after it is emitted
+        let select = TransformResult::Combo(Combo::new(vec![shift_mod], right));
+        assert_eq!(tap(&mut engine, f), select);
+        assert_eq!(tap(&mut engine, f), select);
+        assert_eq!(tap(&mut engine, w), TransformResult::Combo(Combo::new(vec![ctrl_mod], x)));
+        assert_eq!(engine.get_mark(), None);
+        assert_eq!(tap(&mut engine, f), TransformResult::ComboKey(right));
+
+        // SetMark toggles; SetMark(off) unsets before the rest of its sequence
+        tap(&mut engine, space);
+        tap(&mut engine, space);
+        assert_eq!(engine.get_mark(), None);
+        tap(&mut engine, space);
+        assert!(matches!(tap(&mut engine, g), TransformResult::Sequence(_)));
+        assert_eq!(engine.get_mark(), None);
+        engine.process_event(ctrl, Action::Release);
+
+        // Typing unsets it too
+        engine.process_event(ctrl, Action::Press);
+        tap(&mut engine, space);
+        engine.process_event(ctrl, Action::Release);
+        assert_eq!(engine.get_mark(), Some(true));
+        assert_eq!(tap(&mut engine, a), TransformResult::Passthrough(a));
+        assert_eq!(engine.get_mark(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_nested_keymap_prefix() {
+        use crate::Combo;
+
+        let ctrl = Modifier::from_name("CONTROL").unwrap();
+        let mut general = Keymap::new("general");
+        general.insert(
+            Combo::new(vec![ctrl.clone()], Key::from(45)), // C-x
+            KeymapValue::EnterKeymap("emacs-cx".to_string()),
+        );
+        let mut cx = Keymap::new("emacs-cx");
+        cx.insert(
+            Combo::new(vec![ctrl.clone()], Key::from(31)), // C-x C-s -> C-s
+            KeymapValue::Combo(Combo::new(vec![ctrl], Key::from(31))),
+        );
+        cx.insert(Combo::new(vec![], Key::from(23)), KeymapValue::Key(Key::from(24))); // C-x i -> o
+        let config = TransformConfig {
+            keymaps: vec![general, cx],
+            ..TransformConfig::default()
+        };
+        let (mut engine, _clock) = manual_clock_engine(config);
+        let (ctrl, x, s, i) = (Key::from(29), Key::from(45), Key::from(31), Key::from(23));
+
+        // Nested keymaps don't apply at top level
+        assert_eq!(engine.process_event(i, Action::Press), TransformResult::Passthrough(i));
+        assert_eq!(engine.process_event(i, Action::Release), TransformResult::Passthrough(i));
+
+        // C-x C-s: the modifier stays down through the prefix
+        engine.process_event(ctrl, Action::Press);
+        assert_eq!(engine.process_event(x, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(x, Action::Release), TransformResult::Suppress);
+        assert!(matches!(engine.process_event(s, Action::Press), TransformResult::Combo(_)));
+        assert!(engine.keymap_stack.stack.is_empty());
+        assert_eq!(engine.process_event(s, Action::Release), TransformResult::Suppress);
+        engine.process_event(ctrl, Action::Release);
+
+        // C-x i, with i released after the nesting finished
+        engine.process_event(ctrl, Action::Press);
+        engine.process_event(x, Action::Press);
+        engine.process_event(x, Action::Release);
+        engine.process_event(ctrl, Action::Release);
+        assert_eq!(engine.process_event(i, Action::Press), TransformResult::ComboKey(Key::from(24)));
+        assert_eq!(engine.process_event(i, Action::Release), TransformResult::Suppress);
+
+        // A key the nested keymap doesn't map leaves it and is handled as usual
+        engine.process_event(ctrl, Action::Press);
+        engine.process_event(x, Action::Press);
+        engine.process_event(x, Action::Release);
+        engine.process_event(ctrl, Action::Release);
+        assert_eq!(engine.process_event(s, Action::Press), TransformResult::Passthrough(s));
+        assert!(engine.keymap_stack.stack.is_empty());
+        assert_eq!(engine.process_event(s, Action::Release), TransformResult::Passthrough(s));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_tapdance_single_and_double_tap() {
+        let config = TransformConfig {
+            multipurpose_timeout: Some(10),
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.add_tapdance(TapDance::new(
+            "CapsDance",
+            Key::from(58),
+            vec![Key::from(1), Key::from(58)],
+            Some(Key::from(29)),
+        ));
+
+        // Single tap resolves to ESCAPE once the tap window closes
+        assert_eq!(engine.process_event(Key::from(58), Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(Key::from(58), Action::Release), TransformResult::Suppress);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(engine.check_multipurpose_timeouts(), Some((Key::from(1), Action::Release)));
+
+        // Double tap resolves to CAPSLOCK on the second release
+        engine.process_event(Key::from(58), Action::Press);
+        engine.process_event(Key::from(58), Action::Release);
+        engine.process_event(Key::from(58), Action::Press);
+        assert_eq!(
+            engine.process_event(Key::from(58), Action::Release),
+            TransformResult::Remapped(Key::from(58))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_tapdance_interrupt_queues_tap() {
+        let config = TransformConfig::default();
+        let mut engine = TransformEngine::new(config);
+        engine.add_tapdance(TapDance::new(
+            "CapsDance",
+            Key::from(58),
+            vec![Key::from(1), Key::from(58)],
+            Some(Key::from(29)),
+        ));
+
+        engine.process_event(Key::from(58), Action::Press);
+        engine.process_event(Key::from(58), Action::Release);
+
+        // Typing another key resolves the pending single tap first
+        let result = engine.process_event(Key::from(30), Action::Press);
+        assert_eq!(result, TransformResult::Passthrough(Key::from(30)));
+        assert_eq!(engine.take_pending_outputs(), vec![(Key::from(1), Action::Release)]);
+        assert!(engine.take_pending_outputs().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_multipurpose_repeat_suppressed_before_hold() {
+        let config = TransformConfig::default();
+        let mut engine = TransformEngine::new(config);
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97)); // Caps -> Esc/Ctrl
+
+        let press = engine.process_event(Key::from(58), Action::Press);
+        let repeat = engine.process_event(Key::from(58), Action::Repeat);
+
+        assert_eq!(press, TransformResult::Suppress);
+        assert_eq!(
+            repeat,
+            TransformResult::Suppress,
+            "Repeat before hold transition must not emit hold key"
+        );
     }
 
-    /// Clear all state
-    pub fn clear(&mut self) {
-        self.keystore.write().clear();
-        self.repeat_cache = None;
-        self.keymap_stack.clear();
-        self.escape_next = false;
-        self.mark = None;
-        self.suspend_mode = false;
-        self.last_suspend_press = None;
-        self.active_combos.clear();
-    }
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_multipurpose_repeat_emits_hold_after_timeout_transition() {
+        let config = TransformConfig {
+            multipurpose_timeout: Some(10),
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97)); // Caps -> Esc/Ctrl
 
-    /// Get keystore for external inspection
-    pub fn keystore(&self) -> &Arc<RwLock<Keystore>> {
-        &self.keystore
-    }
+        let _ = engine.process_event(Key::from(58), Action::Press);
+        std::thread::sleep(Duration::from_millis(50));
+        let timeout_result = engine.check_multipurpose_timeouts();
+        assert!(timeout_result.is_some(), "Expected hold transition after timeout");
 
-    /// Get current mark value
-    pub fn get_mark(&self) -> Option<bool> {
-        self.mark
-    }
-    
-    /// Get settings reference
-    pub fn settings(&self) -> crate::settings::Settings {
-        self.window_context.read().settings.clone()
-    }
-    
-    /// Update settings
-    pub fn set_settings(&mut self, settings: crate::settings::Settings) {
-        self.window_context.write().set_settings(settings);
-    }
-    
-    /// Reload settings from disk
-    pub fn reload_settings(&mut self) -> Result<(), crate::settings::SettingsError> {
-        let settings = crate::settings::Settings::load_default()?;
-        self.set_settings(settings);
-        Ok(())
-    }
-    
-    /// Get a boolean setting value
-    pub fn get_setting(&self, name: &str) -> bool {
-        self.window_context.read().settings.get_bool(name)
+        let repeat = engine.process_event(Key::from(58), Action::Repeat);
+        assert_eq!(
+            repeat,
+            TransformResult::Remapped(Key::from(97)),
+            "Repeat after hold transition should repeat hold key"
+        );
     }
-    
-    /// Set a boolean setting value
-    pub fn set_setting(&mut self, name: &str, value: bool) {
-        self.window_context.write().settings.set_bool(name, value);
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_transform_engine_is_multipurpose_hold() {
+        let config = TransformConfig::default();
+        let mut engine = TransformEngine::new(config);
+        
+        // Initially not in hold state
+        assert!(!engine.is_multipurpose_hold_active());
+        
+        // Add a multipurpose entry
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        
+        // Press the multipurpose key
+        let _ = engine.process_event(Key::from(58), Action::Press);
+        
+        // Still not in hold state (pending)
+        assert!(!engine.is_multipurpose_hold_active());
+        
+        // Trigger interrupt to enter hold state
+        let _ = engine.process_event(Key::from(30), Action::Press);
+        
+        // Now should be in hold state
+        assert!(engine.is_multipurpose_hold_active());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::window::{WindowContextProvider, WindowError, WindowInfo};
-    use parking_lot::Mutex;
-    use std::collections::HashMap;
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_combo_no_duplicate_on_release() {
+        use crate::Combo;
 
-    struct ScriptedWindowProvider {
-        windows: Mutex<Vec<WindowInfo>>,
+        // This test verifies the fix for the double-paste bug
+        // where Cmd+V would paste twice because the combo matched
+        // on both Press and Release events
+
+        // Get META modifier (Cmd key)
+        let meta_mod = Modifier::from_name("META").expect("META modifier should exist");
+
+        let mut keymap = Keymap::new("test");
+
+        // Create a META+V -> V combo (simulating Cmd+V paste)
+        let combo = Combo::new(vec![meta_mod], Key::from(47)); // V key
+        keymap.insert(combo, KeymapValue::Key(Key::from(47))); // V
+
+        let config = TransformConfig {
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        };
+
+        let mut engine = TransformEngine::new(config);
+
+        // First, press META (Cmd/Meta key)
+        let meta_key = Key::from(125); // LEFT_META
+        let meta_result = engine.process_event(meta_key, Action::Press);
+        assert!(matches!(meta_result, TransformResult::Passthrough(_) | TransformResult::Remapped(_)));
+
+        // Now press V with META held
+        let v_key = Key::from(47); // V key
+        let v_press_result = engine.process_event(v_key, Action::Press);
+
+        // Should find the combo match
+        assert!(matches!(v_press_result, TransformResult::ComboKey(_)));
+
+        // Now release V with META still held
+        let v_release_result = engine.process_event(v_key, Action::Release);
+
+        // Should NOT find the combo match again.
+        // Release may be suppressed (preferred) or passed through/remapped depending on state.
+        assert!(matches!(
+            v_release_result,
+            TransformResult::Suppress | TransformResult::Passthrough(_) | TransformResult::Remapped(_)
+        ));
+        assert!(!matches!(v_release_result, TransformResult::ComboKey(_)),
+            "Release should NOT return ComboKey - this would cause double paste");
     }
 
-    impl ScriptedWindowProvider {
-        fn new(windows: Vec<WindowInfo>) -> Self {
-            Self {
-                windows: Mutex::new(windows),
-            }
-        }
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_combo_release_after_modifier_release() {
+        use crate::Combo;
+
+        let meta_mod = Modifier::from_name("META").expect("META modifier should exist");
+        let mut keymap = Keymap::new("test");
+        keymap.insert(
+            Combo::new(vec![meta_mod.clone()], Key::from(46)), // Meta-C
+            KeymapValue::Combo(Combo::new(vec![Modifier::from_name("CONTROL").unwrap()], Key::from(46))),
+        );
+        keymap.insert(Combo::new(vec![meta_mod], Key::from(47)), KeymapValue::Key(Key::from(48))); // Meta-V -> B
+        let config = TransformConfig {
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        let meta = Key::from(125);
+        let c = Key::from(46);
+        let v = Key::from(47);
+
+        // Meta down, C down, Meta up, C up: the C release emits nothing
+        engine.process_event(meta, Action::Press);
+        assert!(matches!(engine.process_event(c, Action::Press), TransformResult::Combo(_)));
+        engine.process_event(meta, Action::Release);
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Suppress);
+
+        // Nothing is left behind: a plain C afterwards is a normal key
+        assert_eq!(engine.process_event(c, Action::Press), TransformResult::Passthrough(c));
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Passthrough(c));
+
+        // Two combos released in reverse order, modifier first
+        engine.process_event(meta, Action::Press);
+        assert!(matches!(engine.process_event(c, Action::Press), TransformResult::Combo(_)));
+        assert_eq!(engine.process_event(v, Action::Press), TransformResult::ComboKey(Key::from(48)));
+        engine.process_event(meta, Action::Release);
+        assert_eq!(engine.process_event(v, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Suppress);
+
+        // A combo key held across a clear does not fire again on release
+        engine.process_event(meta, Action::Press);
+        engine.process_event(c, Action::Press);
+        engine.clear();
+        engine.process_event(meta, Action::Press);
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Suppress);
     }
 
-    impl WindowContextProvider for ScriptedWindowProvider {
-        fn connect(&mut self) -> Result<(), WindowError> {
-            Ok(())
-        }
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_active_bindings_follow_window_and_settings_changes() {
+        use crate::Combo;
 
-        fn disconnect(&mut self) {}
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let combo = Combo::new(vec![ctrl], Key::from(30)); // Ctrl-A
+        let mut mappings = HashMap::new();
+        mappings.insert(combo, KeymapValue::Key(Key::from(48)));
+        let browser = Keymap::with_conditional("browser", mappings, "wm_class =~ 'firefox'".to_string());
+        let mut caps = HashMap::new();
+        caps.insert(Key::from(58), Key::from(1));
 
-        fn is_connected(&self) -> bool {
-            true
-        }
+        let config = TransformConfig {
+            modmaps: vec![
+                Modmap::new("default", HashMap::new()),
+                Modmap::with_conditional("caps", caps, "settings.caps_esc".to_string()),
+            ],
+            keymaps: vec![browser, Keymap::new("general")],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::with_clock(
+            config,
+            crate::settings::Settings::new(),
+            Arc::new(crate::clock::ManualClock::new()),
+        );
+        engine.set_window_manager(Some(Box::new(ScriptedWindowProvider::new(vec![
+            WindowInfo::with_details(Some("firefox".to_string()), Some("Mozilla Firefox".to_string())),
+        ]))));
 
-        fn get_active_window(&self) -> Result<WindowInfo, WindowError> {
-            let mut guard = self.windows.lock();
-            if guard.is_empty() {
-                Ok(WindowInfo::new())
-            } else {
-                Ok(guard.remove(0))
-            }
-        }
+        assert_eq!(engine.active_binding_names().0, vec!["general".to_string()]);
+
+        let (changed, _) = engine.update_from_window_manager();
+        assert!(changed);
+        let (keymaps, modmaps, _) = engine.active_binding_names();
+        assert_eq!(keymaps, vec!["browser".to_string(), "general".to_string()]);
+        assert!(modmaps.is_empty());
+
+        // A cached result must not outlive the setting it depends on
+        assert_eq!(engine.process_event(Key::from(58), Action::Press), TransformResult::Passthrough(Key::from(58)));
+        engine.process_event(Key::from(58), Action::Release);
+        engine.set_setting("caps_esc", true);
+        assert_eq!(engine.active_binding_names().1, vec!["caps".to_string()]);
+        assert_eq!(engine.process_event(Key::from(58), Action::Press), TransformResult::Remapped(Key::from(1)));
+        engine.process_event(Key::from(58), Action::Release);
+
+        engine.process_event(Key::from(29), Action::Press);
+        assert_eq!(engine.process_event(Key::from(30), Action::Press), TransformResult::ComboKey(Key::from(48)));
     }
 
-    struct ReconnectingScriptedWindowProvider {
-        connected: Mutex<bool>,
-        window: WindowInfo,
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_ignored_window_passes_events_through() {
+        let mut default = HashMap::new();
+        default.insert(Key::from(58), Key::from(1)); // CAPSLOCK -> ESC
+        let config = TransformConfig {
+            modmaps: vec![Modmap::new("default", default)],
+            ignore_wm_class: vec!["virt-manager|VirtualBox".to_string()],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::with_clock(
+            config,
+            crate::settings::Settings::new(),
+            Arc::new(crate::clock::ManualClock::new()),
+        );
+        engine.set_window_manager(Some(Box::new(ScriptedWindowProvider::new(vec![
+            WindowInfo::with_details(Some("virt-manager".to_string()), Some("win10 on QEMU".to_string())),
+            WindowInfo::with_details(Some("firefox".to_string()), Some("Mozilla Firefox".to_string())),
+        ]))));
+        let caps = Key::from(58);
+
+        assert!(engine.update_from_window_manager().0);
+        assert_eq!(engine.process_event(caps, Action::Press), TransformResult::Passthrough(caps));
+
+        // Focus leaves while the key is held: its repeat and release still pass through
+        assert!(engine.update_from_window_manager().0);
+        assert_eq!(engine.process_event(caps, Action::Repeat), TransformResult::Passthrough(caps));
+        assert_eq!(engine.process_event(caps, Action::Release), TransformResult::Passthrough(caps));
+
+        assert_eq!(engine.process_event(caps, Action::Press), TransformResult::Remapped(Key::from(1)));
+        assert_eq!(engine.process_event(caps, Action::Release), TransformResult::Remapped(Key::from(1)));
     }
 
-    impl ReconnectingScriptedWindowProvider {
-        fn new(window: WindowInfo) -> Self {
-            Self {
-                connected: Mutex::new(false),
-                window,
-            }
-        }
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_trace_records_condition_and_winning_keymap() {
+        use crate::Combo;
+
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let combo = Combo::new(vec![ctrl], Key::from(30)); // A key
+
+        let mut mappings = HashMap::new();
+        mappings.insert(combo.clone(), KeymapValue::Key(Key::from(48)));
+        let browser = Keymap::with_conditional("browser", mappings, "wm_class =~ 'Chrome'".to_string());
+        let mut general = Keymap::new("general");
+        general.insert(combo, KeymapValue::Key(Key::from(46)));
+
+        let config = TransformConfig {
+            keymaps: vec![browser, general],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+
+        // Tracing is off by default
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        assert!(engine.take_trace().is_empty());
+
+        engine.set_trace(true);
+        engine.process_event(Key::from(30), Action::Press);
+        let trace = engine.take_trace();
+        assert!(trace.contains(&TraceStep::Condition {
+            keymap: "browser".to_string(),
+            condition: "wm_class =~ 'Chrome'".to_string(),
+            matched: false,
+        }));
+        assert!(matches!(
+            trace.last(),
+            Some(TraceStep::KeymapMatched { keymap, .. }) if keymap == "general"
+        ));
+        assert!(engine.take_trace().is_empty());
     }
 
-    impl WindowContextProvider for ReconnectingScriptedWindowProvider {
-        fn connect(&mut self) -> Result<(), WindowError> {
-            *self.connected.lock() = true;
-            Ok(())
-        }
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_metrics_count_results_and_keymap_hits() {
+        use crate::metrics::ResultKind;
+        use crate::Combo;
 
-        fn disconnect(&mut self) {
-            *self.connected.lock() = false;
-        }
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let mut general = Keymap::new("general");
+        general.insert(Combo::new(vec![ctrl], Key::from(30)), KeymapValue::Key(Key::from(46)));
+        let config = TransformConfig {
+            keymaps: vec![general],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        assert!(engine.metrics().is_none());
 
-        fn is_connected(&self) -> bool {
-            *self.connected.lock()
-        }
+        engine.enable_metrics();
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        engine.process_event(Key::from(30), Action::Press); // Ctrl+A -> combo
+
+        let metrics = engine.metrics().expect("metrics enabled");
+        assert_eq!(metrics.events(), 2);
+        assert_eq!(metrics.result_count(ResultKind::Combo), 1);
+        assert_eq!(metrics.keymap_hits("general"), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_metrics_count_multipurpose_interrupt_once() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
+        engine.enable_metrics();
+
+        engine.process_event(Key::from(58), Action::Press);
+        engine.process_event(Key::from(30), Action::Press); // interrupts
 
-        fn get_active_window(&self) -> Result<WindowInfo, WindowError> {
-            if !*self.connected.lock() {
-                return Err(WindowError::NotConnected);
-            }
-            Ok(self.window.clone())
-        }
+        assert_eq!(engine.metrics().expect("metrics enabled").events(), 2);
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_transform_config_default() {
-        let config = TransformConfig::default();
-        assert!(!config.modmaps.is_empty());
-        assert!(config.keymaps.is_empty());
-        assert!(config.multipurpose_timeout.is_some());
-        assert!(config.suspend_timeout.is_some());
+    fn test_stats_kept_without_metrics() {
+        use crate::Combo;
+
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let mut general = Keymap::new("general");
+        general.insert(Combo::new(vec![ctrl], Key::from(30)), KeymapValue::Key(Key::from(46)));
+        let config = TransformConfig {
+            keymaps: vec![general],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        engine.process_event(Key::from(30), Action::Press); // Ctrl+A -> combo
+
+        assert!(engine.metrics().is_none());
+        assert_eq!(engine.stats().events(), 2);
+        assert_eq!(engine.stats().keymap_hits("general"), 1);
+        assert_eq!(engine.stats().top_combos(20), [("Ctrl-A".to_string(), 1)]);
+        let stats = engine.render_stats(20);
+        assert!(stats.starts_with("Events: 2\n"));
+        assert!(stats.contains("\nKeymap stack: "));
+        assert!(stats.ends_with("\nLayers: none\nMultipurpose: none\n"));
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_update_from_window_manager_ignores_transient_none_none() {
+    fn test_stats_count_multipurpose_interrupt_once() {
         let mut engine = TransformEngine::new(TransformConfig::default());
-        engine.update_window_context(Some("kitty".to_string()), Some("terminal".to_string()));
-        engine.set_window_manager(Some(Box::new(ScriptedWindowProvider::new(vec![
-            WindowInfo::new(),
-            WindowInfo::with_details(
-                Some("firefox".to_string()),
-                Some("Mozilla Firefox".to_string()),
-            ),
-        ]))));
+        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
 
-        // First update is transient empty context and must be ignored.
-        let (changed, _) = engine.update_from_window_manager();
-        assert!(!changed);
-        let ctx = engine.window_context.read().clone();
-        assert_eq!(ctx.wm_class.as_deref(), Some("kitty"));
-        assert_eq!(ctx.wm_name.as_deref(), Some("terminal"));
+        engine.process_event(Key::from(58), Action::Press);
+        engine.process_event(Key::from(30), Action::Press); // interrupts
 
-        // Second update contains real data and should apply.
-        let (changed, _) = engine.update_from_window_manager();
-        assert!(changed);
-        let ctx = engine.window_context.read().clone();
-        assert_eq!(ctx.wm_class.as_deref(), Some("firefox"));
-        assert_eq!(ctx.wm_name.as_deref(), Some("Mozilla Firefox"));
+        assert_eq!(engine.stats().events(), 2);
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_update_from_window_manager_reconnects_provider() {
-        let mut engine = TransformEngine::new(TransformConfig::default());
-        engine.set_window_manager(Some(Box::new(ReconnectingScriptedWindowProvider::new(
-            WindowInfo::with_details(
-                Some("kitty".to_string()),
-                Some("terminal".to_string()),
-            ),
-        ))));
+    fn test_synthetic_repeat_for_held_mapping() {
+        use crate::mapping::RepeatRate;
+        use crate::Combo;
 
-        // First call fails query and triggers reconnect attempt.
-        let (changed, _) = engine.update_from_window_manager();
-        assert!(!changed);
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let combo = Combo::new(vec![ctrl], Key::from(36)); // Ctrl+J
+        let mut nav = Keymap::new("nav");
+        nav.insert(combo.clone(), KeymapValue::Key(Key::from(108))); // DOWN
+        nav.set_repeat(combo, RepeatRate { delay_ms: 0, interval_ms: 1000 });
+        let config = TransformConfig {
+            keymaps: vec![nav],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
 
-        // Second call should return the window context after reconnect.
-        let (changed, _) = engine.update_from_window_manager();
-        assert!(changed);
-        let ctx = engine.window_context.read().clone();
-        assert_eq!(ctx.wm_class.as_deref(), Some("kitty"));
-        assert_eq!(ctx.wm_name.as_deref(), Some("terminal"));
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        assert_eq!(
+            engine.process_event(Key::from(36), Action::Press),
+            TransformResult::ComboKey(Key::from(108))
+        );
+        // Hardware repeat is replaced by the timer
+        assert_eq!(engine.process_event(Key::from(36), Action::Repeat), TransformResult::Suppress);
+        assert_eq!(engine.check_synthetic_repeat(), Some(TransformResult::ComboKey(Key::from(108))));
+        assert_eq!(engine.check_synthetic_repeat(), None);
+        assert!(engine.next_synthetic_repeat_in().unwrap() > Duration::from_millis(400));
+
+        engine.process_event(Key::from(36), Action::Release);
+        assert!(engine.next_synthetic_repeat_in().is_none());
+        assert_eq!(engine.check_synthetic_repeat(), None);
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_window_context_matches() {
-        let mut ctx = WindowContext::new();
-        ctx.wm_class = Some("Firefox".to_string());
+    fn test_release_trigger_fires_on_release() {
+        use crate::Combo;
 
-        // Should match regex
-        assert!(ctx.matches_condition("wm_class =~ 'fire'"));
-        assert!(ctx.matches_condition("wm_class =~ 'Firefox'"));
+        let meta = Modifier::from_alias("Super").expect("Super modifier should exist");
+        let (left_meta, space, a) = (Key::from(125), Key::from(57), Key::from(30));
+        let combo = Combo::new(vec![meta.clone()], space);
+        let layout_switch = Combo::new(vec![meta], Key::from(59)); // Super-F1
+        let mut keymap = Keymap::new("layouts");
+        keymap.insert(combo.clone(), KeymapValue::Combo(layout_switch.clone()));
+        keymap.set_release_trigger(combo);
+        let mut engine = TransformEngine::new(TransformConfig {
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        });
 
-        // Should not match different class
-        assert!(!ctx.matches_condition("wm_class =~ 'Chrome'"));
+        engine.process_event(left_meta, Action::Press);
+        assert_eq!(engine.process_event(space, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(space, Action::Repeat), TransformResult::Suppress);
+        assert!(engine.take_deferred_results().is_empty());
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Suppress);
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Combo(layout_switch), Action::Press)]
+        );
+
+        // Another key pressed meanwhile cancels it
+        engine.process_event(space, Action::Press);
+        engine.process_event(a, Action::Press);
+        engine.process_event(a, Action::Release);
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Suppress);
+        assert!(engine.take_deferred_results().is_empty());
+        engine.process_event(left_meta, Action::Release);
+
+        // Without Super, Space is untouched
+        assert_eq!(engine.process_event(space, Action::Press), TransformResult::Passthrough(space));
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Passthrough(space));
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_window_context_matches_inline_case_and_anchor_patterns() {
-        let mut ctx = WindowContext::new();
-        ctx.wm_class = Some("firefox".to_string());
+    fn test_keymap_tap_hold() {
+        use crate::Combo;
 
-        assert!(ctx.matches_condition("wm_class =~ '(?i)Firefox'"));
-        assert!(ctx.matches_condition("wm_class =~ '(?i)^FIREFOX$'"));
-        assert!(!ctx.matches_condition("wm_class =~ '^fire$'"));
+        let meta = Modifier::from_alias("Super").expect("Super modifier should exist");
+        let (left_meta, enter, a) = (Key::from(125), Key::from(28), Key::from(30));
+        let combo = Combo::new(vec![meta.clone()], enter);
+        let maximize = Combo::new(vec![meta], Key::from(103)); // Super-Up
+        let mut keymap = Keymap::new("windows");
+        keymap.insert(combo.clone(), KeymapValue::Text("kitty".to_string()));
+        keymap.set_hold(combo, KeymapValue::Combo(maximize.clone()));
+        let (mut engine, clock) = manual_clock_engine(TransformConfig {
+            keymaps: vec![keymap],
+            multipurpose_timeout: Some(200),
+            ..TransformConfig::default()
+        });
+        engine.process_event(left_meta, Action::Press);
+
+        // Tapped: the tap output on release
+        assert_eq!(engine.process_event(enter, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.next_tap_hold_in(), Some(Duration::from_millis(200)));
+        clock.advance(Duration::from_millis(100));
+        assert!(engine.check_tap_hold().is_empty());
+        assert_eq!(engine.process_event(enter, Action::Release), TransformResult::Suppress);
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Text("kitty".to_string()), Action::Press)]
+        );
+        assert_eq!(engine.next_tap_hold_in(), None);
+
+        // Held past the timeout: the hold output, once
+        engine.process_event(enter, Action::Press);
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(engine.check_tap_hold(), vec![(TransformResult::Combo(maximize.clone()), Action::Press)]);
+        assert_eq!(engine.process_event(enter, Action::Repeat), TransformResult::Suppress);
+        assert_eq!(engine.process_event(enter, Action::Release), TransformResult::Suppress);
+        assert!(engine.take_deferred_results().is_empty());
+
+        // Another key pressed meanwhile makes it a hold, emitted first
+        engine.process_event(enter, Action::Press);
+        engine.process_event(a, Action::Press);
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Combo(maximize), Action::Press)]
+        );
+        engine.process_event(a, Action::Release);
+        assert_eq!(engine.process_event(enter, Action::Release), TransformResult::Suppress);
+        assert!(engine.take_deferred_results().is_empty());
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_window_context_condition_boolean_and_device_fields() {
-        let mut ctx = WindowContext::new();
-        ctx.wm_class = Some("Alacritty".to_string());
-        ctx.set_device_name(Some("Telink Wireless Gaming Keyboard".to_string()));
-        ctx.set_lock_states(false, true);
+    fn test_passthrough_overrides_later_keymaps() {
+        use crate::Combo;
 
-        assert!(ctx.matches_condition("device_name =~ 'Telink'"));
-        assert!(ctx.matches_condition("devn =~ 'Gaming'"));
-        assert!(ctx.matches_condition("capslock == true"));
-        assert!(ctx.matches_condition("numlk == false"));
-        assert!(!ctx.matches_condition("numlock == true"));
+        let meta = Modifier::from_name("META").expect("META modifier should exist");
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let super_c = Combo::new(vec![meta], Key::from(46));
+        let terminals = Keymap::with_conditional(
+            "terminals",
+            HashMap::from([(super_c.clone(), KeymapValue::Passthrough)]),
+            "wm_class =~ 'kitty'".to_string(),
+        );
+        let general = Keymap::with_mappings(
+            "general",
+            HashMap::from([(super_c, KeymapValue::Combo(Combo::new(vec![ctrl], Key::from(46))))]),
+        );
+        let config = TransformConfig {
+            keymaps: vec![terminals, general],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        let (super_key, c) = (Key::from(125), Key::from(46));
+
+        engine.process_event(super_key, Action::Press);
+        assert!(matches!(engine.process_event(c, Action::Press), TransformResult::Combo(_)));
+        engine.process_event(c, Action::Release);
+
+        engine.update_window_context(Some("kitty".to_string()), None);
+        assert_eq!(engine.process_event(c, Action::Press), TransformResult::Passthrough(c));
+        // Leaving the window while the key is held keeps it passed through
+        engine.update_window_context(Some("firefox".to_string()), None);
+        assert_eq!(engine.process_event(c, Action::Release), TransformResult::Passthrough(c));
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_window_context_condition_logical_composition() {
-        let mut ctx = WindowContext::new();
-        ctx.wm_class = Some("Alacritty".to_string());
-        ctx.wm_name = Some("server".to_string());
-        ctx.set_device_name(Some("Telink Keyboard".to_string()));
-        ctx.set_lock_states(false, false);
-        ctx.settings.set_bool("forced_numpad", true);
+    fn test_suspend_when_follows_window() {
+        let config = TransformConfig {
+            modmaps: vec![Modmap::new("caps", HashMap::from([(Key::from(58), Key::from(29))]))],
+            suspend_when: Some(Condition::new("wm_class =~ 'remmina'".to_string())),
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        let (caps, ctrl, a) = (Key::from(58), Key::from(29), Key::from(30));
+
+        assert_eq!(engine.process_event(caps, Action::Press), TransformResult::Remapped(ctrl));
+        engine.update_window_context(Some("org.remmina.Remmina".to_string()), None);
+        assert_eq!(engine.check_window_suspend(), vec![(TransformResult::Suspend, Action::Press)]);
+        assert!(engine.is_suspended());
+        // The key held on entering was released; its release is swallowed
+        assert_eq!(engine.process_event(caps, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(caps, Action::Press), TransformResult::Passthrough(caps));
+        assert_eq!(engine.process_event(caps, Action::Release), TransformResult::Passthrough(caps));
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+        engine.process_event(a, Action::Release);
+
+        engine.update_window_context(Some("firefox".to_string()), None);
+        assert!(engine.check_window_suspend().is_empty());
+        assert!(!engine.is_suspended());
+        assert_eq!(engine.process_event(caps, Action::Press), TransformResult::Remapped(ctrl));
+    }
 
-        assert!(ctx.matches_condition(
-            "settings.forced_numpad and (wm_class =~ 'alacritty' or wm_name =~ 'kitty')"
-        ));
-        assert!(ctx.matches_condition("numlock and device_name =~ 'telink'"));
-        assert!(!ctx.matches_condition("not numlock and device_name =~ 'telink'"));
-        assert!(!ctx.matches_condition("settings.forced_numpad and not (wm_class =~ 'alacritty')"));
+    #[test]
+    fn test_switch_profile() {
+        let (a, b, f12) = (Key::from(30), Key::from(48), Key::from(88));
+        let switch = KeymapValue::Sequence(vec![ActionStep::Profile(None)]);
+        let keymap = |name: &str| {
+            let mut keymap = Keymap::new(name);
+            keymap.insert(Combo::new(vec![], f12), switch.clone());
+            keymap
+        };
+        let mac = TransformConfig {
+            modmaps: vec![Modmap::new("default", HashMap::from([(a, b)]))],
+            keymaps: vec![keymap("mac")],
+            profile: "mac".to_string(),
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(mac);
+        engine.add_profile(TransformConfig {
+            keymaps: vec![keymap("vanilla")],
+            profile: "vanilla".to_string(),
+            ..TransformConfig::default()
+        });
+        assert_eq!(engine.profile_names(), ["mac", "vanilla"]);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Remapped(b));
+        engine.process_event(a, Action::Release);
+
+        // The switch applies after its own event and releases every held key
+        engine.process_event(f12, Action::Press);
+        assert_eq!(engine.profile(), "vanilla");
+        assert_eq!(engine.take_deferred_results(), vec![(TransformResult::Suspend, Action::Press)]);
+        assert_eq!(engine.process_event(f12, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+        engine.process_event(a, Action::Release);
+
+        assert!(!engine.switch_profile(Some("qwerty")));
+        assert!(engine.switch_profile(Some("mac")));
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Remapped(b));
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_forced_numpad_forces_numlock_conditions_true() {
-        let mut ctx = WindowContext::new();
-        ctx.set_lock_states(false, false);
-        ctx.settings.set_bool("forced_numpad", true);
+    fn test_output_delays_follow_window() {
+        let base = OutputDelays {
+            key_post_delay_ms: 2,
+            ..OutputDelays::default()
+        };
+        let config = TransformConfig {
+            output_delays: base,
+            delay_overrides: vec![DelayOverride {
+                key_post_delay_ms: Some(20),
+                ..DelayOverride::new(Condition::new("wm_class =~ 'remmina'"))
+            }],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        assert_eq!(engine.take_output_delays(), None);
 
-        assert!(ctx.matches_condition("numlock"));
-        assert!(ctx.matches_condition("numlk == true"));
-        assert!(!ctx.matches_condition("not numlk"));
+        engine.update_window_context(Some("org.remmina.Remmina".to_string()), None);
+        let slow = engine.take_output_delays().unwrap();
+        assert_eq!(slow.key_post_delay_ms, 20);
+        engine.update_window_context(Some("org.remmina.Remmina".to_string()), Some("other".to_string()));
+        assert_eq!(engine.take_output_delays(), None);
+
+        engine.update_window_context(Some("firefox".to_string()), None);
+        assert_eq!(engine.take_output_delays(), Some(base));
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_window_context_keyboard_type() {
-        use crate::input::KeyboardType;
-        
-        let mut ctx = WindowContext::new();
-        ctx.set_keyboard_type(KeyboardType::IBM);
+    fn test_combo_style_follows_matched_keymap() {
+        use crate::output::ComboStyle;
+        use crate::Combo;
 
-        // Should match single keyboard type
-        assert!(ctx.matches_condition("keyboard_type =~ 'IBM'"));
-        assert!(!ctx.matches_condition("keyboard_type =~ 'Mac'"));
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let alt = Modifier::from_name("ALT").expect("ALT modifier should exist");
+        let style = ComboStyle {
+            modifier_order: vec!["alt".to_string(), "ctrl".to_string()],
+            delay_ms: Some(5),
+        };
+        let mut styled = Keymap::new("styled");
+        styled.insert(
+            Combo::new(vec![ctrl.clone()], Key::from(36)), // Ctrl+J
+            KeymapValue::Combo(Combo::new(vec![ctrl.clone(), alt], Key::from(108))),
+        );
+        styled.set_combo_style(Some(style.clone()));
+        let mut plain = Keymap::new("plain");
+        plain.insert(
+            Combo::new(vec![ctrl], Key::from(37)), // Ctrl+K
+            KeymapValue::Key(Key::from(103)),
+        );
+        let config = TransformConfig {
+            keymaps: vec![styled, plain],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
 
-        // Should match in list
-        assert!(ctx.matches_condition("keyboard_type =~ 'IBM, Chromebook'"));
-        assert!(ctx.matches_condition("keyboard_type =~ 'Mac, Windows, IBM'"));
-        assert!(!ctx.matches_condition("keyboard_type =~ 'Mac, Chromebook'"));
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        engine.process_event(Key::from(36), Action::Press);
+        assert_eq!(engine.take_combo_style(), Some(style));
+        engine.process_event(Key::from(36), Action::Release);
+        engine.process_event(Key::from(36), Action::Press);
+        assert_eq!(engine.take_combo_style(), None);
+
+        // A keymap without a style switches back to the default
+        engine.process_event(Key::from(37), Action::Press);
+        assert_eq!(engine.take_combo_style(), Some(ComboStyle::default()));
+        engine.process_event(Key::from(37), Action::Press);
+        assert_eq!(engine.take_combo_style(), None);
+    }
 
-        // Test with different keyboard type
-        ctx.set_keyboard_type(KeyboardType::Chromebook);
-        assert!(ctx.matches_condition("keyboard_type =~ 'Chromebook'"));
-        assert!(ctx.matches_condition("keyboard_type =~ 'IBM, Chromebook'"));
-        assert!(!ctx.matches_condition("keyboard_type =~ 'IBM'"));
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_modtap_emits_output_only_when_tapped_alone() {
+        use crate::Combo;
 
-        // Test without keyboard type set
-        ctx.clear_keyboard_type();
-        assert!(!ctx.matches_condition("keyboard_type =~ 'IBM'"));
+        let meta = Key::from(125); // LEFT_META
+        let menu = Combo::new(vec![], Key::from(139));
+        let config = TransformConfig {
+            modtaps: vec![(meta, menu.clone())],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+
+        // Tapped alone: the modifier passes through, its release is followed by the tap output
+        assert_eq!(engine.process_event(meta, Action::Press), TransformResult::Passthrough(meta));
+        assert_eq!(
+            engine.process_event(meta, Action::Release),
+            TransformResult::Combo(menu)
+        );
+        assert_eq!(engine.take_pending_outputs(), vec![(meta, Action::Release)]);
+
+        // Used as a modifier: no tap output
+        engine.process_event(meta, Action::Press);
+        engine.process_event(Key::from(18), Action::Press); // E
+        engine.process_event(Key::from(18), Action::Release);
+        assert_eq!(engine.process_event(meta, Action::Release), TransformResult::Passthrough(meta));
+        assert!(engine.take_pending_outputs().is_empty());
+
+        // Pressed while another key is already held: not a lone tap
+        engine.process_event(Key::from(30), Action::Press); // A
+        engine.process_event(meta, Action::Press);
+        engine.process_event(Key::from(30), Action::Release);
+        assert_eq!(engine.process_event(meta, Action::Release), TransformResult::Passthrough(meta));
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_repeat_cache() {
-        let cache = RepeatCache::new(
-            Key::from(30),
-            TransformResult::Passthrough(Key::from(30)),
-            vec![Key::from(29)], // Left Ctrl
+    fn test_modtap_suppressed_when_modifier_is_part_of_a_combo() {
+        use crate::Combo;
+
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let mut keymap = Keymap::new("edit");
+        keymap.insert(
+            Combo::new(vec![ctrl], Key::from(30)), // Ctrl+A
+            KeymapValue::Key(Key::from(102)),      // HOME
         );
+        let config = TransformConfig {
+            keymaps: vec![keymap],
+            modtaps: vec![(Key::from(29), Combo::new(vec![], Key::from(1)))], // LEFT_CTRL -> ESC
+            modtap_timeout: Some(100),
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
 
-        // Should be valid with same modifiers
-        assert!(cache.is_valid(Key::from(30), &[Key::from(29)]));
+        engine.process_event(Key::from(29), Action::Press);
+        assert_eq!(
+            engine.process_event(Key::from(30), Action::Press),
+            TransformResult::ComboKey(Key::from(102))
+        );
+        engine.process_event(Key::from(30), Action::Release);
+        assert_eq!(
+            engine.process_event(Key::from(29), Action::Release),
+            TransformResult::Passthrough(Key::from(29))
+        );
 
-        // Should be invalid with different modifiers
-        assert!(!cache.is_valid(
-            Key::from(30),
-            &[Key::from(56)] // Left Alt
-        ));
+        // Held past the timeout: no tap either
+        engine.process_event(Key::from(29), Action::Press);
+        std::thread::sleep(Duration::from_millis(120));
+        assert_eq!(
+            engine.process_event(Key::from(29), Action::Release),
+            TransformResult::Passthrough(Key::from(29))
+        );
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_conditional_modmap_overrides_default_when_condition_matches() {
-        let kp1 = Key::from(79);
-        let default_end = Key::from(107);
-        let forced_digit_1 = Key::from(2);
+    fn test_compose_key_runs_xcompose_sequence() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        let compose = Key::from(127);
+        assert_eq!(engine.process_event(compose, Action::Press), TransformResult::Passthrough(compose));
+        engine.process_event(compose, Action::Release);
+
+        engine.set_compose_table(Some(ComposeTable::parse(
+            "<Multi_key> <o> <c> : \"©\" copyright\n",
+        )));
+        assert_eq!(engine.process_event(compose, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(compose, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(Key::from(24), Action::Press), TransformResult::Suppress); // O
+        engine.process_event(Key::from(24), Action::Release);
+        assert_eq!(
+            engine.process_event(Key::from(46), Action::Press), // C
+            TransformResult::Unicode('©' as u32)
+        );
+    }
 
-        let mut default_map = HashMap::new();
-        default_map.insert(kp1, default_end);
+    #[cfg(feature = "pure-rust")]
+    fn layer_engine() -> TransformEngine {
+        use crate::mapping::{LayerAction, LayerMode};
+        use crate::Combo;
 
-        let mut forced_map = HashMap::new();
-        forced_map.insert(kp1, forced_digit_1);
+        let layer = |name: &str, mode| {
+            KeymapValue::Layer(LayerAction {
+                name: name.to_string(),
+                mode,
+            })
+        };
+        let mut base = Keymap::new("base");
+        base.insert(Combo::new(vec![], Key::from(57)), layer("nav", LayerMode::Momentary)); // SPACE
+        base.insert(Combo::new(vec![], Key::from(183)), layer("nav", LayerMode::Toggle)); // F13
+        base.insert(Combo::new(vec![], Key::from(184)), layer("nav", LayerMode::OneShot)); // F14
+        let mut nav = Keymap::new("nav");
+        nav.insert(Combo::new(vec![], Key::from(35)), KeymapValue::Key(Key::from(105))); // H -> LEFT
 
         let config = TransformConfig {
-            modmaps: vec![
-                Modmap::new("default", default_map),
-                Modmap::with_conditional(
-                    "forced_numpad",
-                    forced_map,
-                    "settings.forced_numpad".to_string(),
-                ),
-            ],
+            keymaps: vec![base],
+            layers: vec![nav],
             ..TransformConfig::default()
         };
+        TransformEngine::new(config)
+    }
 
-        let mut engine = TransformEngine::new(config);
-        engine.set_setting("forced_numpad", false);
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_momentary_layer_active_while_trigger_held() {
+        let mut engine = layer_engine();
+        let h = Key::from(35);
 
-        // Default path when setting is false.
-        let result = engine.process_event(kp1, Action::Press);
-        assert_eq!(result, TransformResult::Remapped(default_end));
+        assert_eq!(engine.process_event(Key::from(57), Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.active_layers(), vec!["nav".to_string()]);
+        assert_eq!(engine.process_event(h, Action::Press), TransformResult::ComboKey(Key::from(105)));
 
-        // Conditional path should override default.
-        engine.set_setting("forced_numpad", true);
-        let result = engine.process_event(kp1, Action::Press);
-        assert_eq!(result, TransformResult::Remapped(forced_digit_1));
+        // Releasing the trigger first must not leak a release of H through the base layer.
+        assert_eq!(engine.process_event(Key::from(57), Action::Release), TransformResult::Suppress);
+        assert!(engine.active_layers().is_empty());
+        assert_eq!(engine.process_event(h, Action::Release), TransformResult::Suppress);
+
+        assert_eq!(engine.process_event(h, Action::Press), TransformResult::Passthrough(h));
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn rollover_engine(window_ms: u64) -> TransformEngine {
+        let mut engine = layer_engine();
+        engine.config.layer_rollover_timeout = Some(window_ms);
+        engine
     }
 
-    // Tests for MultipurposeManager integration
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_transform_engine_with_multipurpose() {
-        let config = TransformConfig::default();
-        let mut engine = TransformEngine::new(config);
-        
-        // Add a multipurpose entry (CAPSLOCK -> ESCAPE tap, RIGHT_CTRL hold)
-        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
-        
-        // Press the multipurpose key
-        let result = engine.process_event(Key::from(58), Action::Press);
-        assert_eq!(result, TransformResult::Suppress, "Should suppress initial press");
-        
-        // Release quickly (tap)
-        let result = engine.process_event(Key::from(58), Action::Release);
-        assert_eq!(result, TransformResult::Remapped(Key::from(1)), "Should output ESCAPE on tap");
+    fn test_layer_rollover_types_trigger_and_rolled_key() {
+        let mut engine = rollover_engine(200);
+        let (space, a) = (Key::from(57), Key::from(30));
+
+        assert_eq!(engine.process_event(space, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Suppress);
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![
+                (TransformResult::Passthrough(space), Action::Press),
+                (TransformResult::Passthrough(space), Action::Release),
+                (TransformResult::Passthrough(a), Action::Press),
+            ]
+        );
+        assert!(engine.active_layers().is_empty());
+        assert_eq!(engine.process_event(a, Action::Release), TransformResult::Passthrough(a));
+
+        // Tapped alone, the trigger types itself
+        engine.process_event(space, Action::Press);
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Passthrough(space));
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Passthrough(space), Action::Press)]
+        );
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_transform_engine_multipurpose_interrupt() {
-        let config = TransformConfig::default();
-        let mut engine = TransformEngine::new(config);
-        
-        // Add a multipurpose entry
-        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
-        
-        // Press the multipurpose key
-        let _ = engine.process_event(Key::from(58), Action::Press);
-        
-        // Verify we're in pending state before interrupt
-        assert!(engine.multipurpose_manager.is_pending_state());
-        
-        // Press another key (interrupt)
-        let _result = engine.process_event(Key::from(30), Action::Press); // 'A' key
-        
-        // After interrupt, should still be active but in hold state
-        assert!(engine.multipurpose_manager.has_active(), "Multipurpose should still be active after interrupt");
-        assert!(engine.multipurpose_manager.is_hold_state(), "Multipurpose should be in hold state after interrupt");
+    fn test_layer_rollover_uses_layer_when_key_released_first() {
+        let mut engine = rollover_engine(200);
+        let (space, h) = (Key::from(57), Key::from(35));
+        let left = TransformResult::ComboKey(Key::from(105));
+
+        engine.process_event(space, Action::Press);
+        assert_eq!(engine.process_event(h, Action::Press), TransformResult::Suppress);
+        assert_eq!(engine.process_event(h, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.take_deferred_results(), vec![(left.clone(), Action::Press)]);
+        assert_eq!(engine.active_layers(), vec!["nav".to_string()]);
+
+        // Once the layer is in use, keys resolve through it immediately
+        assert_eq!(engine.process_event(h, Action::Press), left);
+        engine.process_event(h, Action::Release);
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Suppress);
+        assert!(engine.active_layers().is_empty());
+        assert!(engine.take_deferred_results().is_empty());
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_transform_engine_multipurpose_timeout_check() {
-        let config = TransformConfig {
-            multipurpose_timeout: Some(10), // 10ms timeout
-            ..TransformConfig::default()
-        };
-        let mut engine = TransformEngine::new(config);
-        
-        // Add a multipurpose entry
-        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
-        
-        // Press the multipurpose key
-        let _ = engine.process_event(Key::from(58), Action::Press);
-        
-        // Wait for timeout
-        std::thread::sleep(Duration::from_millis(50));
-        
-        // Check timeout
-        let timeout_result = engine.check_multipurpose_timeouts();
-        assert!(timeout_result.is_some(), "Should detect timeout");
-        assert_eq!(timeout_result.unwrap().0, Key::from(97), "Should output RIGHT_CTRL");
+    fn test_layer_rollover_window_expiry_uses_layer() {
+        let mut engine = rollover_engine(20);
+        let (space, h) = (Key::from(57), Key::from(35));
 
-        // Timeout transition should also update internal modifier state.
-        let pressed_mods = engine.keystore.read().get_pressed_mods_keys();
-        assert!(
-            pressed_mods.contains(&Key::from(97)),
-            "RIGHT_CTRL should be tracked as pressed after timeout hold activation"
+        engine.process_event(space, Action::Press);
+        engine.process_event(h, Action::Press);
+        assert!(engine.check_layer_rollover().is_empty());
+        assert!(engine.next_layer_rollover_in().is_some());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(
+            engine.check_layer_rollover(),
+            vec![(TransformResult::ComboKey(Key::from(105)), Action::Press)]
         );
+        assert_eq!(engine.next_layer_rollover_in(), None);
+        assert_eq!(engine.process_event(space, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(h, Action::Release), TransformResult::Suppress);
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_multipurpose_repeat_suppressed_before_hold() {
-        let config = TransformConfig::default();
-        let mut engine = TransformEngine::new(config);
-        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97)); // Caps -> Esc/Ctrl
+    fn test_lock_keys_toggle_state_unless_following_leds() {
+        let mut engine = TransformEngine::new(TransformConfig::default());
+        let capslock = Key::from(58);
+
+        engine.process_event(capslock, Action::Press);
+        engine.process_event(capslock, Action::Release);
+        assert_eq!(engine.lock_states(), (false, true));
+
+        // With LEDs reported, only `set_lock_states` changes the state
+        engine.follow_lock_leds(true);
+        engine.set_lock_states(true, false);
+        engine.process_event(capslock, Action::Press);
+        assert_eq!(engine.lock_states(), (true, false));
+        assert!(engine.window_context.read().matches_condition("numlock"));
+    }
 
-        let press = engine.process_event(Key::from(58), Action::Press);
-        let repeat = engine.process_event(Key::from(58), Action::Repeat);
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_numpad_nav_when_numlock_off() {
+        use crate::Combo;
 
-        assert_eq!(press, TransformResult::Suppress);
-        assert_eq!(
-            repeat,
-            TransformResult::Suppress,
-            "Repeat before hold transition must not emit hold key"
-        );
+        let shift = Modifier::from_alias("Shift").expect("Shift modifier should exist");
+        let mut keymap = Keymap::new("select");
+        keymap.insert(Combo::new(vec![shift], Key::from(105)), KeymapValue::Key(Key::from(102))); // Shift-LEFT -> HOME
+        let mut engine = TransformEngine::new(TransformConfig {
+            keymaps: vec![keymap],
+            ..TransformConfig::default()
+        });
+        let (kp4, kp5, left) = (Key::from(75), Key::from(76), Key::from(105));
+
+        assert_eq!(engine.process_event(kp4, Action::Press), TransformResult::Passthrough(kp4));
+        engine.process_event(kp4, Action::Release);
+
+        engine.set_setting(NUMPAD_NAV_SETTING, true);
+        assert_eq!(engine.process_event(kp4, Action::Press), TransformResult::Passthrough(left));
+        assert_eq!(engine.process_event(kp4, Action::Repeat), TransformResult::Passthrough(left));
+        // NumLock turned on while held: the release still belongs to LEFT
+        engine.set_lock_states(true, false);
+        assert_eq!(engine.process_event(kp4, Action::Release), TransformResult::Passthrough(left));
+        assert_eq!(engine.process_event(kp4, Action::Press), TransformResult::Passthrough(kp4));
+        engine.process_event(kp4, Action::Release);
+
+        // Modifiers apply as they would to the navigation key
+        engine.set_lock_states(false, false);
+        engine.process_event(Key::from(42), Action::Press); // LEFT_SHIFT
+        assert_eq!(engine.process_event(kp4, Action::Press), TransformResult::ComboKey(Key::from(102)));
+        engine.process_event(kp4, Action::Release);
+        assert_eq!(engine.process_event(kp5, Action::Press), TransformResult::Passthrough(kp5));
+        engine.process_event(kp5, Action::Release);
+        engine.process_event(Key::from(42), Action::Release);
+
+        // forced_numpad keeps the keypad typing digits
+        engine.set_setting("forced_numpad", true);
+        assert_eq!(engine.process_event(kp4, Action::Press), TransformResult::Passthrough(kp4));
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_multipurpose_repeat_emits_hold_after_timeout_transition() {
-        let config = TransformConfig {
-            multipurpose_timeout: Some(10),
+    fn test_mediamap() {
+        let (volume_up, brightness_up, play) = (Key::from(115), Key::from(225), Key::from(164));
+        let mut mediamap = HashMap::new();
+        mediamap.insert(volume_up, MediaMapping::Key(brightness_up));
+        mediamap.insert(brightness_up, MediaMapping::Key(volume_up));
+        mediamap.insert(play, MediaMapping::Run("playerctl play-pause".to_string()));
+        let mut engine = TransformEngine::new(TransformConfig {
+            mediamap,
             ..TransformConfig::default()
-        };
-        let mut engine = TransformEngine::new(config);
-        engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97)); // Caps -> Esc/Ctrl
+        });
 
-        let _ = engine.process_event(Key::from(58), Action::Press);
-        std::thread::sleep(Duration::from_millis(50));
-        let timeout_result = engine.check_multipurpose_timeouts();
-        assert!(timeout_result.is_some(), "Expected hold transition after timeout");
+        // Swapped rows
+        assert_eq!(engine.process_event(volume_up, Action::Press), TransformResult::Passthrough(brightness_up));
+        assert_eq!(engine.process_event(volume_up, Action::Release), TransformResult::Passthrough(brightness_up));
+        assert_eq!(engine.process_event(brightness_up, Action::Press), TransformResult::Passthrough(volume_up));
+        engine.process_event(brightness_up, Action::Release);
 
-        let repeat = engine.process_event(Key::from(58), Action::Repeat);
+        // Commands start on press only
         assert_eq!(
-            repeat,
-            TransformResult::Remapped(Key::from(97)),
-            "Repeat after hold transition should repeat hold key"
+            engine.process_event(play, Action::Press),
+            TransformResult::Sequence(vec![ActionStep::Run("playerctl play-pause".to_string())])
         );
+        assert_eq!(engine.process_event(play, Action::Repeat), TransformResult::Suppress);
+        assert_eq!(engine.process_event(play, Action::Release), TransformResult::Suppress);
     }
 
     #[test]
     #[cfg(feature = "pure-rust")]
-    fn test_transform_engine_is_multipurpose_hold() {
-        let config = TransformConfig::default();
-        let mut engine = TransformEngine::new(config);
-        
-        // Initially not in hold state
-        assert!(!engine.is_multipurpose_hold_active());
-        
-        // Add a multipurpose entry
+    fn test_mediamap_applied_once_when_interrupting_multipurpose() {
+        let (volume_up, brightness_up) = (Key::from(115), Key::from(225));
+        let mut mediamap = HashMap::new();
+        mediamap.insert(volume_up, MediaMapping::Key(brightness_up));
+        mediamap.insert(brightness_up, MediaMapping::Key(volume_up));
+        let mut engine = TransformEngine::new(TransformConfig {
+            mediamap,
+            ..TransformConfig::default()
+        });
         engine.add_multipurpose(Key::from(58), Key::from(1), Key::from(97));
-        
-        // Press the multipurpose key
-        let _ = engine.process_event(Key::from(58), Action::Press);
-        
-        // Still not in hold state (pending)
-        assert!(!engine.is_multipurpose_hold_active());
-        
-        // Trigger interrupt to enter hold state
-        let _ = engine.process_event(Key::from(30), Action::Press);
-        
-        // Now should be in hold state
-        assert!(engine.is_multipurpose_hold_active());
+
+        engine.process_event(Key::from(58), Action::Press);
+        assert_eq!(engine.process_event(volume_up, Action::Press), TransformResult::Passthrough(brightness_up));
+        assert!(engine.multipurpose_manager.is_hold_state());
     }
 
     #[test]
-    #[cfg(feature = "pure-rust")]
-    fn test_combo_no_duplicate_on_release() {
+    fn test_media_passthrough_skips_keymaps() {
         use crate::Combo;
 
-        // This test verifies the fix for the double-paste bug
-        // where Cmd+V would paste twice because the combo matched
-        // on both Press and Release events
-
-        // Get META modifier (Cmd key)
-        let meta_mod = Modifier::from_name("META").expect("META modifier should exist");
-
-        let mut keymap = Keymap::new("test");
-
-        // Create a META+V -> V combo (simulating Cmd+V paste)
-        let combo = Combo::new(vec![meta_mod], Key::from(47)); // V key
-        keymap.insert(combo, KeymapValue::Key(Key::from(47))); // V
+        let mute = Key::from(113);
+        let mut keymap = Keymap::new("media");
+        keymap.insert(Combo::new(vec![], mute), KeymapValue::Key(Key::from(30)));
+        let mut engine = TransformEngine::new(TransformConfig {
+            keymaps: vec![keymap.clone()],
+            ..TransformConfig::default()
+        });
+        assert_eq!(engine.process_event(mute, Action::Press), TransformResult::ComboKey(Key::from(30)));
+        engine.process_event(mute, Action::Release);
 
-        let config = TransformConfig {
+        let mut engine = TransformEngine::new(TransformConfig {
             keymaps: vec![keymap],
+            media_passthrough: true,
             ..TransformConfig::default()
-        };
+        });
+        assert_eq!(engine.process_event(mute, Action::Press), TransformResult::Passthrough(mute));
+    }
 
-        let mut engine = TransformEngine::new(config);
+    #[cfg(feature = "pure-rust")]
+    fn autoshift_engine(timeout_ms: u64) -> TransformEngine {
+        TransformEngine::new(TransformConfig {
+            autoshift_keys: vec![Key::from(2), Key::from(30)], // 1, A
+            autoshift_timeout: Some(timeout_ms),
+            ..TransformConfig::default()
+        })
+    }
 
-        // First, press META (Cmd/Meta key)
-        let meta_key = Key::from(125); // LEFT_META
-        let meta_result = engine.process_event(meta_key, Action::Press);
-        assert!(matches!(meta_result, TransformResult::Passthrough(_) | TransformResult::Remapped(_)));
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_autoshift_tap_types_plain_key() {
+        let mut engine = autoshift_engine(200);
+        let a = Key::from(30);
 
-        // Now press V with META held
-        let v_key = Key::from(47); // V key
-        let v_press_result = engine.process_event(v_key, Action::Press);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Suppress);
+        assert!(engine.next_autoshift_in().is_some());
+        assert_eq!(engine.process_event(a, Action::Release), TransformResult::Passthrough(a));
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Passthrough(a), Action::Press)]
+        );
+        assert_eq!(engine.next_autoshift_in(), None);
+
+        // Keys outside the list and keys pressed with a modifier are left alone
+        let b = Key::from(48);
+        assert_eq!(engine.process_event(b, Action::Press), TransformResult::Passthrough(b));
+        engine.process_event(b, Action::Release);
+        engine.process_event(Key::from(29), Action::Press);
+        assert_eq!(engine.process_event(a, Action::Press), TransformResult::Passthrough(a));
+    }
 
-        // Should find the combo match
-        assert!(matches!(v_press_result, TransformResult::ComboKey(_)));
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_autoshift_hold_types_shifted_key_once() {
+        let mut engine = autoshift_engine(20);
+        let one = Key::from(2);
+
+        engine.process_event(one, Action::Press);
+        assert!(engine.check_autoshift().is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        let shift = Modifier::from_key(Key::from(42)).unwrap();
+        assert_eq!(
+            engine.check_autoshift(),
+            vec![(TransformResult::Combo(Combo::new(vec![shift], one)), Action::Press)]
+        );
+        assert_eq!(engine.next_autoshift_in(), None);
+        // Hardware repeat and the release stay silent
+        assert_eq!(engine.process_event(one, Action::Repeat), TransformResult::Suppress);
+        assert_eq!(engine.process_event(one, Action::Release), TransformResult::Suppress);
+        assert!(engine.check_autoshift().is_empty());
+    }
 
-        // Now release V with META still held
-        let v_release_result = engine.process_event(v_key, Action::Release);
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_autoshift_key_interrupted_by_next_key_types_plain() {
+        let mut engine = autoshift_engine(200);
+        let (a, one) = (Key::from(30), Key::from(2));
+
+        engine.process_event(a, Action::Press);
+        assert_eq!(engine.process_event(a, Action::Repeat), TransformResult::Suppress);
+        // The rolled key is itself an autoshift candidate
+        assert_eq!(engine.process_event(one, Action::Press), TransformResult::Suppress);
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Passthrough(a), Action::Press)]
+        );
+        assert_eq!(engine.process_event(a, Action::Release), TransformResult::Passthrough(a));
+        assert_eq!(engine.process_event(one, Action::Release), TransformResult::Passthrough(one));
+        assert_eq!(
+            engine.take_deferred_results(),
+            vec![(TransformResult::Passthrough(one), Action::Press)]
+        );
+    }
 
-        // Should NOT find the combo match again.
-        // Release may be suppressed (preferred) or passed through/remapped depending on state.
-        assert!(matches!(
-            v_release_result,
-            TransformResult::Suppress | TransformResult::Passthrough(_) | TransformResult::Remapped(_)
-        ));
-        assert!(!matches!(v_release_result, TransformResult::ComboKey(_)),
-            "Release should NOT return ComboKey - this would cause double paste");
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_toggle_and_one_shot_layers() {
+        let mut engine = layer_engine();
+        let h = Key::from(35);
+        let left = TransformResult::ComboKey(Key::from(105));
+
+        engine.process_event(Key::from(183), Action::Press);
+        engine.process_event(Key::from(183), Action::Release);
+        assert_eq!(engine.process_event(h, Action::Press), left);
+        engine.process_event(h, Action::Release);
+        assert_eq!(engine.process_event(h, Action::Press), left);
+        engine.process_event(h, Action::Release);
+        engine.process_event(Key::from(183), Action::Press);
+        engine.process_event(Key::from(183), Action::Release);
+        assert!(engine.active_layers().is_empty());
+
+        engine.process_event(Key::from(184), Action::Press);
+        engine.process_event(Key::from(184), Action::Release);
+        // Modifiers do not consume the one-shot layer
+        engine.process_event(Key::from(42), Action::Press);
+        engine.process_event(Key::from(42), Action::Release);
+        assert_eq!(engine.process_event(h, Action::Press), left);
+        assert_eq!(engine.process_event(h, Action::Release), TransformResult::Suppress);
+        assert_eq!(engine.process_event(h, Action::Press), TransformResult::Passthrough(h));
     }
 
     #[test]
@@ -2119,6 +5093,34 @@ mod tests {
         assert_eq!(result, TransformResult::Unicode(0x00B4));
     }
 
+    #[test]
+    fn test_dead_key_timeout_types_accent() {
+        use crate::Combo;
+
+        let ctrl = Modifier::from_alias("Ctrl").expect("Ctrl modifier should exist");
+        let mut keymap = Keymap::new("deadkey-timeout");
+        keymap.insert(Combo::new(vec![ctrl], Key::from(18)), KeymapValue::Unicode(0x00B4));
+        let (mut engine, clock) = manual_clock_engine(TransformConfig {
+            keymaps: vec![keymap],
+            dead_key_timeout: Some(1000),
+            ..TransformConfig::default()
+        });
+
+        engine.process_event(Key::from(29), Action::Press); // LEFT_CTRL
+        engine.process_event(Key::from(18), Action::Press); // E => activate dead key
+        engine.process_event(Key::from(18), Action::Release);
+        engine.process_event(Key::from(29), Action::Release);
+        assert_eq!(engine.pending_dead_key().as_deref(), Some("´"));
+        assert_eq!(engine.next_dead_key_timeout_in(), Some(Duration::from_millis(1000)));
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(engine.check_dead_key_timeout(), None);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(engine.check_dead_key_timeout(), Some(TransformResult::Unicode(0x00B4)));
+        assert_eq!(engine.pending_dead_key(), None);
+        assert_eq!(engine.next_dead_key_timeout_in(), None);
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_unicode_not_emitted_on_repeat() {
@@ -2330,6 +5332,111 @@ mod tests {
         assert_eq!(after_false, TransformResult::Text("FALSE".to_string()));
     }
 
+    fn self_gating_toggle_engine() -> TransformEngine {
+        use crate::mapping::ActionStep;
+        use crate::Combo;
+
+        // F8 flips the setting that gates the keymap it lives in; the other
+        // keymap binds F8 to a combo.
+        let mut off_mappings = std::collections::HashMap::new();
+        off_mappings.insert(
+            Combo::new(vec![], Key::from(66)), // F8
+            KeymapValue::Sequence(vec![
+                ActionStep::SetSetting {
+                    name: "Enter2Ent_Cmd".to_string(),
+                    value: true,
+                },
+                ActionStep::Text("ON".to_string()),
+            ]),
+        );
+        let km_off = Keymap::with_conditional(
+            "toggle_on",
+            off_mappings,
+            "not settings.Enter2Ent_Cmd".to_string(),
+        );
+
+        let ctrl = Modifier::from_name("CONTROL").expect("CONTROL modifier should exist");
+        let mut on_mappings = std::collections::HashMap::new();
+        on_mappings.insert(
+            Combo::new(vec![], Key::from(66)), // F8
+            KeymapValue::Combo(Combo::new(vec![ctrl], Key::from(46))), // Ctrl-C
+        );
+        let km_on = Keymap::with_conditional(
+            "enabled",
+            on_mappings,
+            "settings.Enter2Ent_Cmd".to_string(),
+        );
+
+        let config = TransformConfig {
+            keymaps: vec![km_off, km_on],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.set_setting("Enter2Ent_Cmd", false);
+        engine
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_setting_toggle_keeps_press_binding_for_rest_of_batch() {
+        let mut engine = self_gating_toggle_engine();
+
+        // One poll batch: press, repeat, release of the toggling key.
+        let batch: Vec<TransformResult> = [Action::Press, Action::Repeat, Action::Release]
+            .into_iter()
+            .map(|action| engine.process_event(Key::from(66), action))
+            .collect();
+
+        assert!(matches!(batch[0], TransformResult::Sequence(_)));
+        assert!(engine.get_setting("Enter2Ent_Cmd"));
+        // Repeat/release must not fire the newly enabled keymap's combo.
+        assert_eq!(batch[1], TransformResult::Suppress);
+        assert_eq!(batch[2], TransformResult::Suppress);
+
+        // The next press sees the new setting.
+        let next = engine.process_event(Key::from(66), Action::Press);
+        assert!(matches!(next, TransformResult::Combo(c) if c.key() == Key::from(46)));
+        let _ = engine.process_event(Key::from(66), Action::Release);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_setting_changes_apply_in_order_at_event_boundary() {
+        use crate::mapping::ActionStep;
+        use crate::Combo;
+
+        let mut km = Keymap::new("toggles");
+        km.insert(
+            Combo::new(vec![], Key::from(67)), // F9
+            KeymapValue::Sequence(vec![
+                ActionStep::SetSetting {
+                    name: "Enter2Ent_Cmd".to_string(),
+                    value: true,
+                },
+                ActionStep::SetSetting {
+                    name: "Enter2Ent_Cmd".to_string(),
+                    value: false,
+                },
+                ActionStep::SetSetting {
+                    name: "forced_numpad".to_string(),
+                    value: true,
+                },
+            ]),
+        );
+        let config = TransformConfig {
+            keymaps: vec![km],
+            ..TransformConfig::default()
+        };
+        let mut engine = TransformEngine::new(config);
+        engine.set_setting("Enter2Ent_Cmd", false);
+
+        let result = engine.process_event(Key::from(67), Action::Press);
+        assert_eq!(result, TransformResult::Suppress);
+        // Last write wins within one event.
+        assert!(!engine.get_setting("Enter2Ent_Cmd"));
+        assert!(engine.get_setting("forced_numpad"));
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_multipurpose_cleared_on_window_change() {
@@ -0,0 +1,128 @@
+// Keyrs Transform Layers
+// Activation state for momentary, toggled and one-shot layers
+
+use crate::mapping::LayerMode;
+use crate::Key;
+
+/// Which layers are currently active and what keeps them active
+#[derive(Debug, Clone, Default)]
+pub struct LayerState {
+    /// Momentary layers and the trigger key holding each, in activation order
+    held: Vec<(Key, String)>,
+    /// Toggled-on layers, in activation order
+    toggled: Vec<String>,
+    /// Layer armed for the next non-modifier key press
+    one_shot: Option<String>,
+}
+
+impl LayerState {
+    /// Apply a layer trigger press
+    pub fn activate(&mut self, trigger: Key, name: &str, mode: LayerMode) {
+        match mode {
+            LayerMode::Momentary => {
+                self.held.retain(|(key, _)| *key != trigger);
+                self.held.push((trigger, name.to_string()));
+            }
+            LayerMode::Toggle => {
+                if let Some(index) = self.toggled.iter().position(|n| n == name) {
+                    self.toggled.remove(index);
+                } else {
+                    self.toggled.push(name.to_string());
+                }
+            }
+            LayerMode::OneShot => self.one_shot = Some(name.to_string()),
+        }
+    }
+
+    /// Whether `key` is currently holding a momentary layer
+    pub fn is_held_trigger(&self, key: Key) -> bool {
+        self.held.iter().any(|(trigger, _)| *trigger == key)
+    }
+
+    /// Release a momentary trigger; returns the layer it was holding
+    pub fn release(&mut self, trigger: Key) -> Option<String> {
+        let index = self.held.iter().position(|(key, _)| *key == trigger)?;
+        Some(self.held.remove(index).1)
+    }
+
+    /// Whether a one-shot layer is armed
+    pub fn has_one_shot(&self) -> bool {
+        self.one_shot.is_some()
+    }
+
+    /// Disarm the one-shot layer after it has been used
+    pub fn consume_one_shot(&mut self) -> Option<String> {
+        self.one_shot.take()
+    }
+
+    /// Active layer names, highest precedence first.
+    ///
+    /// A one-shot layer wins over held layers, which win over toggled ones;
+    /// within each group the most recently activated layer comes first.
+    pub fn active(&self) -> impl Iterator<Item = &str> {
+        self.one_shot
+            .iter()
+            .map(String::as_str)
+            .chain(self.held.iter().rev().map(|(_, name)| name.as_str()))
+            .chain(self.toggled.iter().rev().map(String::as_str))
+    }
+
+    /// Whether `name` is active in any mode
+    pub fn is_active(&self, name: &str) -> bool {
+        self.active().any(|n| n == name)
+    }
+
+    /// Whether no layer is active
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty() && self.toggled.is_empty() && self.one_shot.is_none()
+    }
+
+    /// Deactivate every layer
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.toggled.clear();
+        self.one_shot = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_momentary_layer_ends_on_trigger_release() {
+        let mut layers = LayerState::default();
+        let space = Key::from(57);
+        layers.activate(space, "nav", LayerMode::Momentary);
+        assert!(layers.is_held_trigger(space));
+        assert!(layers.is_active("nav"));
+
+        assert_eq!(layers.release(space).as_deref(), Some("nav"));
+        assert!(layers.is_empty());
+        assert_eq!(layers.release(space), None);
+    }
+
+    #[test]
+    fn test_toggle_layer_flips_on_each_press() {
+        let mut layers = LayerState::default();
+        let f13 = Key::from(183);
+        layers.activate(f13, "symbols", LayerMode::Toggle);
+        assert!(layers.is_active("symbols"));
+        assert!(!layers.is_held_trigger(f13));
+        layers.activate(f13, "symbols", LayerMode::Toggle);
+        assert!(layers.is_empty());
+    }
+
+    #[test]
+    fn test_active_order_prefers_one_shot_then_held_then_toggled() {
+        let mut layers = LayerState::default();
+        layers.activate(Key::from(183), "base2", LayerMode::Toggle);
+        layers.activate(Key::from(57), "nav", LayerMode::Momentary);
+        layers.activate(Key::from(58), "sym", LayerMode::OneShot);
+        assert_eq!(layers.active().collect::<Vec<_>>(), vec!["sym", "nav", "base2"]);
+
+        assert_eq!(layers.consume_one_shot().as_deref(), Some("sym"));
+        assert!(!layers.has_one_shot());
+        assert_eq!(layers.active().collect::<Vec<_>>(), vec!["nav", "base2"]);
+    }
+}
@@ -0,0 +1,91 @@
+// Keyrs Transform Mark
+// Emacs-style mark: while it is set, movement outputs gain Shift and select text
+
+use crate::mapping::ActionStep;
+use crate::{Combo, Key, Modifier};
+
+use super::engine::TransformResult;
+
+/// Cursor movement keys: arrows, Home/End, PageUp/PageDown
+fn is_movement(key: Key) -> bool {
+    matches!(key.code(), 102..=109)
+}
+
+/// `combo` with Shift added (kept as is when a Shift is already in it)
+fn shifted(combo: &Combo) -> Combo {
+    let has_shift = combo
+        .modifiers()
+        .iter()
+        .any(|modifier| modifier.keys().iter().any(|key| matches!(key.code(), 42 | 54)));
+    if has_shift {
+        return combo.clone();
+    }
+    let mut modifiers = combo.modifiers().to_vec();
+    modifiers.extend(Modifier::from_name("SHIFT"));
+    Combo::new(modifiers, combo.key())
+}
+
+/// The output that extends the selection instead of moving, or `None` when
+/// `result` is not (only) cursor movement
+pub(crate) fn extend_selection(result: &TransformResult) -> Option<TransformResult> {
+    match result {
+        TransformResult::ComboKey(key) if is_movement(*key) => {
+            Some(TransformResult::Combo(shifted(&Combo::new(vec![], *key))))
+        }
+        TransformResult::Combo(combo) if is_movement(combo.key()) => Some(TransformResult::Combo(shifted(combo))),
+        TransformResult::Sequence(steps)
+            if steps
+                .iter()
+                .all(|step| matches!(step, ActionStep::Combo(combo) if is_movement(combo.key()))) =>
+        {
+            Some(TransformResult::Sequence(
+                steps
+                    .iter()
+                    .map(|step| match step {
+                        ActionStep::Combo(combo) => ActionStep::Combo(shifted(combo)),
+                        step => step.clone(),
+                    })
+                    .collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extend_selection() {
+        let ctrl = Modifier::from_name("CONTROL").unwrap();
+        let shift = Modifier::from_name("SHIFT").unwrap();
+        let left = Key::from(105);
+
+        assert_eq!(
+            extend_selection(&TransformResult::ComboKey(left)),
+            Some(TransformResult::Combo(Combo::new(vec![shift.clone()], left)))
+        );
+        assert_eq!(
+            extend_selection(&TransformResult::Combo(Combo::new(vec![ctrl.clone()], left))),
+            Some(TransformResult::Combo(Combo::new(vec![ctrl.clone(), shift.clone()], left)))
+        );
+        // Already shifted
+        let selecting = Combo::new(vec![shift.clone()], Key::from(107));
+        assert_eq!(
+            extend_selection(&TransformResult::Combo(selecting.clone())),
+            Some(TransformResult::Combo(selecting))
+        );
+
+        // Anything that isn't only movement leaves the output alone
+        assert_eq!(extend_selection(&TransformResult::ComboKey(Key::from(30))), None);
+        assert_eq!(
+            extend_selection(&TransformResult::Sequence(vec![
+                ActionStep::Combo(Combo::new(vec![], Key::from(102))),
+                ActionStep::Text("x".to_string()),
+            ])),
+            None
+        );
+        assert_eq!(extend_selection(&TransformResult::Passthrough(left)), None);
+    }
+}
@@ -0,0 +1,56 @@
+// Keyrs Transform Media Keys
+// `[mediamap]`: media keys renamed or bound to commands before other mappings
+
+use crate::Key;
+
+/// What a `[mediamap]` entry makes of its media key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaMapping {
+    /// Another key, for the whole press (swapping two rows works)
+    Key(Key),
+    /// A shell command started on press (`Run(...)`)
+    Run(String),
+}
+
+/// Volume, playback, brightness and keyboard backlight keys
+const MEDIA_KEYS: [u16; 21] = [
+    113, // MUTE
+    114, // VOLUMEDOWN
+    115, // VOLUMEUP
+    161, // EJECTCD
+    163, // NEXTSONG
+    164, // PLAYPAUSE
+    165, // PREVIOUSSONG
+    166, // STOPCD
+    168, // REWIND
+    200, // PLAYCD
+    201, // PAUSECD
+    207, // PLAY
+    208, // FASTFORWARD
+    224, // BRIGHTNESSDOWN
+    225, // BRIGHTNESSUP
+    226, // MEDIA
+    228, // KBDILLUMTOGGLE
+    229, // KBDILLUMDOWN
+    230, // KBDILLUMUP
+    243, // BRIGHTNESS_CYCLE
+    248, // MICMUTE
+];
+
+/// Whether `[mediamap]` handles `key`
+pub fn is_media_key(key: Key) -> bool {
+    MEDIA_KEYS.contains(&key.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_media_key() {
+        assert!(is_media_key(Key::from(115)));
+        assert!(is_media_key(Key::from(225)));
+        assert!(!is_media_key(Key::from(30)));
+        assert!(!is_media_key(Key::from(59)));
+    }
+}
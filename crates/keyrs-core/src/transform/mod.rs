@@ -5,15 +5,41 @@ pub mod cache;
 pub mod combo;
 pub mod util;
 
+#[cfg(feature = "pure-rust")]
+pub mod bindings;
+
 #[cfg(feature = "pure-rust")]
 pub mod deadkeys;
 
 #[cfg(feature = "pure-rust")]
 pub mod engine;
 
+#[cfg(feature = "pure-rust")]
+pub mod layers;
+
+#[cfg(feature = "pure-rust")]
+mod mark;
+
+#[cfg(feature = "pure-rust")]
+pub mod media;
+
+#[cfg(feature = "pure-rust")]
+pub mod numpad;
+
+#[cfg(feature = "pure-rust")]
+pub mod trace;
+
 pub use cache::{ComboKey, KeymapCache};
 pub use combo::{find_combo_match, ComboMatchResult};
 pub use util::*;
 
 #[cfg(feature = "pure-rust")]
 pub use engine::{TransformConfig, TransformEngine, TransformResult};
+#[cfg(feature = "pure-rust")]
+pub use bindings::ActiveBindings;
+#[cfg(feature = "pure-rust")]
+pub use deadkeys::ComposeTable;
+#[cfg(feature = "pure-rust")]
+pub use layers::LayerState;
+#[cfg(feature = "pure-rust")]
+pub use trace::TraceStep;
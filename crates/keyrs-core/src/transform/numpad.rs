@@ -0,0 +1,42 @@
+// Keyrs Transform Numpad
+// Keypad digits as the navigation keys printed on them while NumLock is off
+
+use crate::Key;
+
+/// Setting that turns the keypad into a navigation cluster while NumLock is off
+pub const NUMPAD_NAV_SETTING: &str = "numpad_nav";
+
+/// Keypad keys and the navigation key each stands for (KP5 has none)
+const NAV_KEYS: [(u16, u16); 10] = [
+    (71, 102), // KP7 -> HOME
+    (72, 103), // KP8 -> UP
+    (73, 104), // KP9 -> PAGE_UP
+    (75, 105), // KP4 -> LEFT
+    (77, 106), // KP6 -> RIGHT
+    (79, 107), // KP1 -> END
+    (80, 108), // KP2 -> DOWN
+    (81, 109), // KP3 -> PAGE_DOWN
+    (82, 110), // KP0 -> INSERT
+    (83, 111), // KPDOT -> DELETE
+];
+
+/// The navigation key `key` stands for, if it is a keypad key that has one
+pub(crate) fn nav_key(key: Key) -> Option<Key> {
+    NAV_KEYS
+        .iter()
+        .find(|(keypad, _)| *keypad == key.code())
+        .map(|(_, nav)| Key::from(*nav))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nav_key() {
+        assert_eq!(nav_key(Key::from(75)).map(|key| key.name()), Some("LEFT"));
+        assert_eq!(nav_key(Key::from(83)).map(|key| key.name()), Some("DELETE"));
+        assert_eq!(nav_key(Key::from(76)), None); // KP5
+        assert_eq!(nav_key(Key::from(30)), None);
+    }
+}
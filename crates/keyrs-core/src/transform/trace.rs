@@ -0,0 +1,47 @@
+// Keyrs Transform Trace
+// Decision path recorded by the engine for `keyrs --trace`
+
+use std::fmt;
+
+/// A single step of the combo matching decision path
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceStep {
+    /// A combo was looked up (`expanded` = L/R modifier expansion pass)
+    ComboTried { combo: String, expanded: bool },
+    /// A keymap condition was evaluated
+    Condition {
+        keymap: String,
+        condition: String,
+        matched: bool,
+    },
+    /// A keymap produced the match
+    KeymapMatched { keymap: String, combo: String },
+    /// No keymap matched the combo
+    NoMatch,
+    /// An escape hint let the key through untouched
+    Escaped,
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceStep::ComboTried { combo, expanded } => {
+                if *expanded {
+                    write!(f, "try {} (expanded)", combo)
+                } else {
+                    write!(f, "try {}", combo)
+                }
+            }
+            TraceStep::Condition {
+                keymap,
+                condition,
+                matched,
+            } => write!(f, "keymap '{}' condition {} -> {}", keymap, condition, matched),
+            TraceStep::KeymapMatched { keymap, combo } => {
+                write!(f, "matched {} in keymap '{}'", combo, keymap)
+            }
+            TraceStep::NoMatch => write!(f, "no keymap matched"),
+            TraceStep::Escaped => write!(f, "escaped, passed through untouched"),
+        }
+    }
+}
@@ -4,6 +4,10 @@
 // which track active window information for conditional keymaps.
 
 use std::fmt;
+use std::sync::Arc;
+
+/// Called from a provider's own thread when the active window changes
+pub type ChangeNotifier = Arc<dyn Fn() + Send + Sync>;
 
 /// Error type for window context operations
 #[derive(Debug, Clone, PartialEq)]
@@ -251,6 +255,32 @@ pub trait WindowContextProvider: Send + Sync {
     /// currently focused window.
     fn get_active_window(&self) -> Result<WindowInfo, WindowError>;
 
+    /// Whether the active window changed since the last call
+    ///
+    /// Providers that learn about focus changes from events return true
+    /// once per change so callers can update without waiting for their
+    /// next poll. The default never reports a change.
+    fn take_window_changed(&self) -> bool {
+        false
+    }
+
+    /// Call `notifier` on every change `take_window_changed` will report,
+    /// so an idle caller can wake up instead of polling.
+    ///
+    /// Returns false when the provider cannot notify; callers then keep
+    /// polling. The default cannot.
+    fn set_change_notifier(&self, _notifier: ChangeNotifier) -> bool {
+        false
+    }
+
+    /// Keyboard layout the desktop currently uses (`us`, `de(nodeadkeys)`)
+    ///
+    /// Changes are reported through `take_window_changed` like window
+    /// changes. The default does not know the layout.
+    fn keyboard_layout(&self) -> Option<String> {
+        None
+    }
+
     /// Check if window context is available
     ///
     /// This is a convenience method that returns true if connected
@@ -2,9 +2,14 @@
 //!
 //! This module handles the connection to Wayland compositors and tracks
 //! window focus, app_id, and title for active windows on wlroots-based compositors.
+//! Compositors without the wlr protocol but with `ext-foreign-toplevel-list-v1`
+//! are supported as a fallback; that protocol carries no focus state, so the
+//! active window is only known while a single toplevel is open.
 
 use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -12,13 +17,19 @@ use wayland_backend::rs::client::ObjectId;
 use wayland_client::{
     event_created_child,
     globals::{registry_queue_init, GlobalListContents},
-    protocol::{wl_display, wl_registry, wl_surface},
+    protocol::{wl_display, wl_keyboard, wl_registry, wl_seat, wl_surface},
+    WEnum,
     Connection, Dispatch, Proxy, QueueHandle,
 };
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+};
 use wayland_protocols_wlr::foreign_toplevel::v1::client::{
     zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
 };
 
+use super::provider::ChangeNotifier;
+
 /// Default error values when window info is not available
 pub const ERR_NO_APP_CLASS: &str = "ERR_no_wlr_app_class";
 pub const ERR_NO_WDW_TITLE: &str = "ERR_no_wlr_wdw_title";
@@ -72,36 +83,109 @@ struct WaylandState {
     active_handle: Option<ObjectId>,
     /// Cached active window info for fast access
     active_window: Arc<Mutex<ActiveWindow>>,
+    /// Set when the cached active window changes
+    changed: Arc<AtomicBool>,
+    /// Told about each change as it is flagged
+    notifier: Arc<Mutex<Option<ChangeNotifier>>>,
+    /// Toplevels come from ext-foreign-toplevel-list (no activation state)
+    list_only: bool,
+    /// Keyboard of the seat, for the keymap and layout group
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    /// Layout names of the current keymap, indexed by group
+    layouts: Vec<String>,
+    /// Active layout group
+    group: u32,
+    /// Name of the active layout
+    layout: Arc<Mutex<Option<String>>>,
 }
 
 impl WaylandState {
-    fn new(active_window: Arc<Mutex<ActiveWindow>>) -> Self {
+    fn new(active_window: Arc<Mutex<ActiveWindow>>, changed: Arc<AtomicBool>) -> Self {
         Self {
             windows: HashMap::new(),
             active_handle: None,
             active_window,
+            changed,
+            notifier: Arc::new(Mutex::new(None)),
+            list_only: false,
+            keyboard: None,
+            layouts: Vec::new(),
+            group: 0,
+            layout: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Flag a change and tell the notifier
+    fn flag_change(&self) {
+        self.changed.store(true, Ordering::Release);
+        if let Some(notify) = self.notifier.lock().unwrap().as_ref() {
+            notify();
         }
     }
 
     /// Update the cached active window info from the current active handle
     fn update_active_window_cache(&self) {
-        if let Some(handle) = &self.active_handle {
-            if let Some(info) = self.windows.get(handle) {
-                let app_id = if info.app_id.is_empty() {
-                    ERR_NO_APP_CLASS
-                } else {
-                    &info.app_id
-                };
-                let title = if info.title.is_empty() {
-                    ERR_NO_WDW_TITLE
-                } else {
-                    &info.title
-                };
-
-                let mut window = self.active_window.lock().unwrap();
-                window.update(app_id.to_string(), title.to_string());
+        let (app_id, title) = match self.active_handle.as_ref().and_then(|h| self.windows.get(h)) {
+            Some(info) => (
+                if info.app_id.is_empty() { ERR_NO_APP_CLASS } else { &info.app_id },
+                if info.title.is_empty() { ERR_NO_WDW_TITLE } else { &info.title },
+            ),
+            None => (ERR_NO_APP_CLASS, ERR_NO_WDW_TITLE),
+        };
+
+        let mut window = self.active_window.lock().unwrap();
+        if window.app_id != app_id || window.title != title {
+            window.update(app_id.to_string(), title.to_string());
+            drop(window);
+            self.flag_change();
+        }
+    }
+
+    /// Publish the layout of the active group, flagging it when it changed
+    fn update_layout(&self) {
+        let current = self.layouts.get(self.group as usize).cloned();
+        let mut layout = self.layout.lock().unwrap();
+        if *layout != current {
+            *layout = current;
+            drop(layout);
+            self.flag_change();
+        }
+    }
+
+    /// Apply a toplevel's state once the compositor has sent all of it (`done`)
+    fn commit(&mut self, handle_id: ObjectId) {
+        if self.list_only {
+            self.active_handle = self.sole_window();
+        } else if let Some(info) = self.windows.get(&handle_id) {
+            if info.activated {
+                self.active_handle = Some(handle_id);
+            } else if self.active_handle.as_ref() == Some(&handle_id) {
+                self.active_handle = None;
             }
         }
+        self.update_active_window_cache();
+    }
+
+    /// Forget a closed toplevel
+    fn close(&mut self, handle_id: &ObjectId) {
+        if self.windows.remove(handle_id).is_none() {
+            return;
+        }
+        if self.list_only {
+            self.active_handle = self.sole_window();
+        } else if self.active_handle.as_ref() == Some(handle_id) {
+            self.active_handle = None;
+        }
+        self.update_active_window_cache();
+    }
+
+    /// The only open toplevel, if exactly one is open
+    fn sole_window(&self) -> Option<ObjectId> {
+        let mut ids = self.windows.keys();
+        match (ids.next(), ids.next()) {
+            (Some(id), None) => Some(id.clone()),
+            _ => None,
+        }
     }
 }
 
@@ -155,55 +239,31 @@ impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()>
         _conn: &Connection,
         _qhandle: &QueueHandle<Self>,
     ) {
+        // Changes take effect together on `done`, so a focus switch never
+        // pairs the new window's app_id with the old window's title.
         match event {
             zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
-                let handle_id = handle.id();
-                if let Some(info) = state.windows.get_mut(&handle_id) {
+                if let Some(info) = state.windows.get_mut(&handle.id()) {
                     info.title = title;
-                    if state.active_handle.as_ref() == Some(&handle_id) {
-                        state.update_active_window_cache();
-                    }
                 }
             }
             zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
-                let handle_id = handle.id();
-                if let Some(info) = state.windows.get_mut(&handle_id) {
+                if let Some(info) = state.windows.get_mut(&handle.id()) {
                     info.app_id = app_id;
-                    if state.active_handle.as_ref() == Some(&handle_id) {
-                        state.update_active_window_cache();
-                    }
                 }
             }
             zwlr_foreign_toplevel_handle_v1::Event::State { state: state_array } => {
                 const ACTIVATED_VALUE: u8 = 2;
                 let activated = state_array.contains(&ACTIVATED_VALUE);
-                let handle_id = handle.id();
-
-                if let Some(info) = state.windows.get_mut(&handle_id) {
+                if let Some(info) = state.windows.get_mut(&handle.id()) {
                     info.activated = activated;
-
-                    if activated {
-                        state.active_handle = Some(handle_id);
-                        state.update_active_window_cache();
-                    } else if state.active_handle.as_ref() == Some(&handle_id) {
-                        state.active_handle = None;
-                        let mut window = state.active_window.lock().unwrap();
-                        window.update(ERR_NO_APP_CLASS.to_string(), ERR_NO_WDW_TITLE.to_string());
-                    }
                 }
             }
             zwlr_foreign_toplevel_handle_v1::Event::Done => {
-                // All pending state changes have been applied
+                state.commit(handle.id());
             }
             zwlr_foreign_toplevel_handle_v1::Event::Closed => {
-                let handle_id = handle.id();
-                if state.windows.remove(&handle_id).is_some() {
-                    if state.active_handle.as_ref() == Some(&handle_id) {
-                        state.active_handle = None;
-                        let mut window = state.active_window.lock().unwrap();
-                        window.update(ERR_NO_APP_CLASS.to_string(), ERR_NO_WDW_TITLE.to_string());
-                    }
-                }
+                state.close(&handle.id());
                 handle.destroy();
             }
             zwlr_foreign_toplevel_handle_v1::Event::Parent { .. } => {
@@ -214,6 +274,104 @@ impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()>
     }
 }
 
+// Implement Dispatch for the ext toplevel list (fallback without wlr)
+impl Dispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _list: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            state.windows.insert(toplevel.id(), WindowInfo::new());
+        }
+    }
+
+    event_created_child!(WaylandState, ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, [
+        0 => (ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        handle: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                if let Some(info) = state.windows.get_mut(&handle.id()) {
+                    info.title = title;
+                }
+            }
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                if let Some(info) = state.windows.get_mut(&handle.id()) {
+                    info.app_id = app_id;
+                }
+            }
+            ext_foreign_toplevel_handle_v1::Event::Done => {
+                state.commit(handle.id());
+            }
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                state.close(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _conn: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(caps) } = event {
+            if caps.contains(wl_seat::Capability::Keyboard) && state.keyboard.is_none() {
+                state.keyboard = Some(seat.get_keyboard(qhandle, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap { format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1), fd, size } => {
+                let mut keymap = vec![0u8; size as usize];
+                if fs::File::from(fd).read_exact(&mut keymap).is_err() {
+                    log::debug!("Could not read the compositor keymap");
+                    return;
+                }
+                let keymap = String::from_utf8_lossy(&keymap);
+                state.layouts = crate::layout::keymap_layout_names(keymap.trim_end_matches('\0'));
+                state.update_layout();
+            }
+            wl_keyboard::Event::Modifiers { group, .. } => {
+                state.group = group;
+                state.update_layout();
+            }
+            _ => {}
+        }
+    }
+}
+
 // Empty dispatch implementations for other types
 impl Dispatch<wl_surface::WlSurface, ()> for WaylandState {
     fn event(
@@ -251,6 +409,12 @@ pub struct WaylandClient {
     connected: Arc<Mutex<bool>>,
     /// Event loop thread handle (thread-safe)
     event_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    /// Set by the event thread when the active window changes
+    changed: Arc<AtomicBool>,
+    /// Called by the event thread when the active window changes
+    notifier: Arc<Mutex<Option<ChangeNotifier>>>,
+    /// Keyboard layout active in the compositor
+    layout: Arc<Mutex<Option<String>>>,
 }
 
 impl WaylandClient {
@@ -290,6 +454,9 @@ impl WaylandClient {
             active_window: Arc::new(Mutex::new(ActiveWindow::new())),
             connected: Arc::new(Mutex::new(false)),
             event_thread: Arc::new(Mutex::new(None)),
+            changed: Arc::new(AtomicBool::new(false)),
+            notifier: Arc::new(Mutex::new(None)),
+            layout: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -338,20 +505,35 @@ impl WaylandClient {
         };
         let qhandle = event_queue.handle();
 
-        // Bind to the toplevel manager
-        let toplevel_manager = match globals
+        // Create the state
+        let mut state = WaylandState::new(self.active_window.clone(), self.changed.clone());
+        state.notifier = self.notifier.clone();
+        state.layout = self.layout.clone();
+
+        // Bind to the toplevel manager, or the toplevel list when the
+        // compositor lacks the wlr protocol
+        let wlr_manager = globals
             .bind::<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, _, _>(
-            &qhandle,
-            3..=3,
-            (),
-        ) {
-            Ok(m) => m,
-            Err(_) => return false,
-        };
-        let _ = toplevel_manager;
+                &qhandle,
+                3..=3,
+                (),
+            );
+        if wlr_manager.is_err() {
+            if globals
+                .bind::<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, _, _>(&qhandle, 1..=1, ())
+                .is_err()
+            {
+                return false;
+            }
+            log::info!("Compositor lacks wlr-foreign-toplevel; window focus is only known with one window open");
+            state.list_only = true;
+        }
 
-        // Create the state
-        let mut state = WaylandState::new(self.active_window.clone());
+        // The seat's keyboard reports the keymap and layout group; window
+        // tracking works without it
+        if globals.bind::<wl_seat::WlSeat, _, _>(&qhandle, 1..=5, ()).is_err() {
+            log::debug!("Compositor has no seat; keyboard layout is unknown");
+        }
 
         // Set up the event processing in a background thread
         let connected_flag = self.connected.clone();
@@ -391,6 +573,21 @@ impl WaylandClient {
         window.update(app_id, title);
     }
 
+    /// Keyboard layout active in the compositor (`us`, `de(nodeadkeys)`)
+    pub fn keyboard_layout(&self) -> Option<String> {
+        self.layout.lock().unwrap().clone()
+    }
+
+    /// Call `notifier` from the event thread whenever the active window changes
+    pub fn set_change_notifier(&self, notifier: ChangeNotifier) {
+        *self.notifier.lock().unwrap() = Some(notifier);
+    }
+
+    /// Whether the active window changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::AcqRel)
+    }
+
     /// Check if connected to Wayland
     pub fn is_connected(&self) -> bool {
         *self.connected.lock().unwrap()
@@ -447,6 +644,83 @@ mod tests {
         assert_eq!(ERR_NO_WDW_TITLE, "ERR_no_wlr_wdw_title");
     }
 
+    #[test]
+    fn test_active_window_change_is_reported_once() {
+        let client = WaylandClient::new();
+        let mut state = WaylandState::new(client.active_window.clone(), client.changed.clone());
+        state.windows.insert(ObjectId::null(), WindowInfo::new());
+        state.windows.get_mut(&ObjectId::null()).unwrap().app_id = "foot".to_string();
+
+        // Activation applies on `done`
+        state.windows.get_mut(&ObjectId::null()).unwrap().activated = true;
+        assert!(!client.take_changed());
+        state.commit(ObjectId::null());
+        assert_eq!(client.active_window(), ("foot".to_string(), ERR_NO_WDW_TITLE.to_string()));
+        assert!(client.take_changed());
+        assert!(!client.take_changed());
+
+        // An unchanged `done` is not a change
+        state.commit(ObjectId::null());
+        assert!(!client.take_changed());
+
+        state.close(&ObjectId::null());
+        assert_eq!(client.active_window().0, ERR_NO_APP_CLASS);
+        assert!(client.take_changed());
+    }
+
+    #[test]
+    fn test_active_window_change_calls_notifier() {
+        use std::sync::atomic::AtomicUsize;
+
+        let client = WaylandClient::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        client.set_change_notifier(Arc::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let mut state = WaylandState::new(client.active_window.clone(), client.changed.clone());
+        state.notifier = client.notifier.clone();
+        state.windows.insert(ObjectId::null(), WindowInfo::new());
+        state.windows.get_mut(&ObjectId::null()).unwrap().app_id = "foot".to_string();
+        state.windows.get_mut(&ObjectId::null()).unwrap().activated = true;
+
+        state.commit(ObjectId::null());
+        state.commit(ObjectId::null());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_layout_follows_group() {
+        let client = WaylandClient::new();
+        let mut state = WaylandState::new(client.active_window.clone(), client.changed.clone());
+        state.layout = client.layout.clone();
+        state.layouts = vec!["us".to_string(), "de".to_string()];
+
+        state.update_layout();
+        assert_eq!(client.keyboard_layout().as_deref(), Some("us"));
+        assert!(client.take_changed());
+
+        state.group = 1;
+        state.update_layout();
+        assert_eq!(client.keyboard_layout().as_deref(), Some("de"));
+        assert!(client.take_changed());
+
+        state.update_layout();
+        assert!(!client.take_changed());
+    }
+
+    #[test]
+    fn test_toplevel_list_tracks_sole_window() {
+        let client = WaylandClient::new();
+        let mut state = WaylandState::new(client.active_window.clone(), client.changed.clone());
+        state.list_only = true;
+        state.windows.insert(ObjectId::null(), WindowInfo::new());
+        state.windows.get_mut(&ObjectId::null()).unwrap().title = "notes".to_string();
+
+        state.commit(ObjectId::null());
+        assert_eq!(client.active_window().1, "notes");
+    }
+
     #[test]
     fn test_wayland_client_update_active_window() {
         let client = WaylandClient::new();
@@ -3,13 +3,14 @@
 // This module wraps the existing WaylandClient to implement
 // the WindowContextProvider trait.
 
-use super::provider::{WindowContextProvider, WindowError, WindowInfo};
+use super::provider::{ChangeNotifier, WindowContextProvider, WindowError, WindowInfo};
 use super::wayland::WaylandClient;
 
 /// Wayland-specific implementation of WindowContextProvider
 ///
 /// This wraps the existing WaylandClient which handles
-/// wlroots-based compositors via the foreign-toplevel protocol.
+/// wlroots-based compositors via the foreign-toplevel protocol,
+/// falling back to ext-foreign-toplevel-list.
 pub struct WaylandContextProvider {
     /// The underlying Wayland client
     client: WaylandClient,
@@ -89,6 +90,24 @@ impl WindowContextProvider for WaylandContextProvider {
 
         Ok(WindowInfo { wm_class, wm_name })
     }
+
+    /// Focus, app_id, title and layout changes arrive as Wayland events
+    fn take_window_changed(&self) -> bool {
+        self.client.take_changed()
+    }
+
+    fn set_change_notifier(&self, notifier: ChangeNotifier) -> bool {
+        self.client.set_change_notifier(notifier);
+        true
+    }
+
+    /// Layout of the compositor's keymap and active group
+    fn keyboard_layout(&self) -> Option<String> {
+        if !self.is_connected() {
+            return None;
+        }
+        self.client.keyboard_layout()
+    }
 }
 
 #[cfg(test)]
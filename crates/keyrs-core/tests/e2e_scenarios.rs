@@ -12,6 +12,7 @@ mod e2e_tests {
     use keyrs_core::input::{KeyboardType, keyboard_type_matches};
     use keyrs_core::mapping::{Keymap, KeymapValue, Modmap, MultiModmap, MultipurposeManager};
     use keyrs_core::settings::Settings;
+    use keyrs_core::output::OutputDelays;
     use keyrs_core::transform::engine::{TransformConfig, TransformEngine, TransformResult, WindowContext};
     use keyrs_core::window::{WindowCondition, WindowInfo};
     use keyrs_core::{Action, Combo, Key, Modifier};
@@ -50,10 +51,29 @@ mod e2e_tests {
         TransformConfig {
             modmaps: vec![Modmap::new("caps2esc", modmap_mappings)],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(200),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         }
     }
 
@@ -94,10 +114,29 @@ mod e2e_tests {
         TransformConfig {
             modmaps: vec![modmap],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![keymap],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(200),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         }
     }
 
@@ -517,6 +556,7 @@ mod performance_tests {
     use std::collections::HashMap;
     use std::time::Instant;
     use keyrs_core::mapping::Modmap;
+    use keyrs_core::output::OutputDelays;
     use keyrs_core::transform::engine::{TransformConfig, TransformEngine};
     use keyrs_core::{Action, Key};
 
@@ -563,10 +603,29 @@ mod performance_tests {
         let config = TransformConfig {
             modmaps: vec![Modmap::new("large", modmap_mappings)],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(200),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         };
 
         let mut engine = TransformEngine::new(config);
@@ -0,0 +1,200 @@
+// Keyrs Transform Engine Invariants
+//
+// Drives a deterministic engine (no settings file, manual clock) with
+// pseudo-random key sequences and checks properties that must hold for any
+// input.
+//
+// Run with: cargo test --features pure-rust --test engine_invariants_test
+
+#[cfg(feature = "pure-rust")]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use keyrs_core::config::Config;
+    use keyrs_core::settings::Settings;
+    use keyrs_core::transform::engine::{TransformConfig, TransformEngine, TransformResult};
+    use keyrs_core::{Action, Key, ManualClock};
+
+    /// Tap/hold, layer and dead keys, plus the keys the config maps
+    const CONFIG: &str = r#"
+[modmap.default]
+RIGHT_CTRL = "ESC"
+
+[[multipurpose]]
+name = "enter_ctrl"
+trigger = "ENTER"
+tap = "ENTER"
+hold = "RIGHT_CTRL"
+
+[[tapdance]]
+name = "caps_dance"
+trigger = "CAPSLOCK"
+taps = ["ESC", "CAPSLOCK"]
+hold = "LEFT_CTRL"
+
+[modtap]
+LEFT_META = "Alt-F1"
+
+[autoshift]
+rows = ["home"]
+
+[[keymap]]
+name = "shortcuts"
+[keymap.mappings]
+"C-c" = "C-Insert"
+"C-v" = ["Shift-Insert", "End"]
+"Alt-f" = "Text(hi)"
+"Space" = "Layer(nav)"
+"F13" = "Layer(nav, toggle)"
+
+[layers.nav.mappings]
+"h" = "Left"
+"j" = "C-Right"
+
+[timeouts]
+multipurpose = 200
+layer_rollover = 200
+"#;
+
+    /// Modifiers, the config's triggers and mapped keys, and a few unmapped ones
+    const KEY_POOL: &[u16] = &[29, 42, 56, 125, 97, 58, 28, 57, 183, 46, 47, 33, 35, 36, 30, 31, 16, 2, 14];
+
+    /// Small deterministic generator so failures reproduce from the seed alone
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % bound
+        }
+    }
+
+    fn test_config() -> TransformConfig {
+        Config::from_toml(CONFIG).expect("invalid test config").to_transform_config()
+    }
+
+    /// Random presses, repeats and releases of held keys, ending with every key released
+    fn random_events(rng: &mut Lcg, len: usize) -> Vec<(Key, Action)> {
+        let mut held: Vec<Key> = Vec::new();
+        let mut events = Vec::new();
+        for _ in 0..len {
+            if !held.is_empty() && rng.next(2) == 0 {
+                let key = held.remove(rng.next(held.len()));
+                events.push((key, Action::Release));
+            } else if !held.is_empty() && rng.next(8) == 0 {
+                events.push((held[rng.next(held.len())], Action::Repeat));
+            } else {
+                let key = Key::from(KEY_POOL[rng.next(KEY_POOL.len())]);
+                if !held.contains(&key) {
+                    held.push(key);
+                    events.push((key, Action::Press));
+                }
+            }
+        }
+        while let Some(key) = held.pop() {
+            events.push((key, Action::Release));
+        }
+        events
+    }
+
+    /// Keys the virtual keyboard would still hold after `emitted`, following
+    /// the output device: only `Passthrough`/`Remapped` presses are held,
+    /// everything else is a tap
+    fn held_outputs(emitted: &[(TransformResult, Action)]) -> HashSet<Key> {
+        let mut held = HashSet::new();
+        for (result, action) in emitted {
+            if let TransformResult::Passthrough(key) | TransformResult::Remapped(key) = result {
+                match action {
+                    Action::Press => {
+                        held.insert(*key);
+                    }
+                    Action::Release => {
+                        held.remove(key);
+                    }
+                    Action::Repeat => {}
+                }
+            }
+        }
+        held
+    }
+
+    #[test]
+    fn process_events_is_deterministic() {
+        let config = test_config();
+        let events = random_events(&mut Lcg(7), 200);
+        let run = || {
+            let mut engine = TransformEngine::with_clock(config.clone(), Settings::new(), Arc::new(ManualClock::new()));
+            engine.process_events(&events)
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn no_key_is_left_pressed_after_all_inputs_are_released() {
+        let config = test_config();
+        for seed in 0..300 {
+            let events = random_events(&mut Lcg(seed), 40);
+            let mut engine = TransformEngine::with_clock(config.clone(), Settings::new(), Arc::new(ManualClock::new()));
+            let emitted = engine.process_events(&events);
+            let held = held_outputs(&emitted);
+            assert!(held.is_empty(), "seed {seed}: {held:?} left pressed by {events:?}");
+        }
+    }
+
+    #[test]
+    fn no_key_is_left_pressed_when_time_passes_between_events() {
+        let config = test_config();
+        for seed in 0..300 {
+            let mut rng = Lcg(seed);
+            let events = random_events(&mut rng, 40);
+            let clock = ManualClock::new();
+            let mut engine = TransformEngine::with_clock(config.clone(), Settings::new(), Arc::new(clock.clone()));
+            let mut emitted = Vec::new();
+            for event in &events {
+                clock.advance(Duration::from_millis(rng.next(400) as u64));
+                if let Some((key, action)) = engine.check_multipurpose_timeouts() {
+                    emitted.push((TransformResult::Remapped(key), action));
+                }
+                emitted.extend(engine.check_layer_rollover());
+                emitted.extend(engine.check_autoshift());
+                emitted.extend(engine.check_tap_hold());
+                emitted.extend(engine.process_events(std::slice::from_ref(event)));
+            }
+            let held = held_outputs(&emitted);
+            assert!(held.is_empty(), "seed {seed}: {held:?} left pressed by {events:?}");
+        }
+    }
+
+    #[test]
+    fn suppress_never_follows_an_unmatched_press_of_the_same_key() {
+        let config = test_config();
+        for seed in 0..300 {
+            let events = random_events(&mut Lcg(seed), 40);
+            let mut engine = TransformEngine::with_clock(config.clone(), Settings::new(), Arc::new(ManualClock::new()));
+            // Input key -> output key its press left held
+            let mut pressed: HashMap<Key, Key> = HashMap::new();
+            for &(key, action) in &events {
+                let result = engine.process_event(key, action);
+                engine.take_deferred_results();
+                engine.take_pending_outputs();
+                match (action, &result) {
+                    (Action::Press, TransformResult::Passthrough(out) | TransformResult::Remapped(out)) => {
+                        pressed.insert(key, *out);
+                    }
+                    (Action::Release, _) => {
+                        if let Some(out) = pressed.remove(&key) {
+                            assert_ne!(
+                                result,
+                                TransformResult::Suppress,
+                                "seed {seed}: release of {key:?} suppressed after it pressed {out:?}"
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -9,6 +9,7 @@
 mod tests {
     use std::collections::HashMap;
     use keyrs_core::mapping::{Keymap, KeymapValue, Modmap};
+    use keyrs_core::output::OutputDelays;
     use keyrs_core::transform::engine::{TransformConfig, TransformEngine, TransformResult};
     use keyrs_core::{Action, Combo, Key, Modifier};
 
@@ -29,10 +30,29 @@ mod tests {
         TransformConfig {
             modmaps: vec![modmap],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![keymap],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         }
     }
 
@@ -250,10 +270,29 @@ mod tests {
         let config = TransformConfig {
             modmaps: vec![modmap],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![keymap],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         };
 
         let mut engine = TransformEngine::new(config);
@@ -284,10 +323,29 @@ mod tests {
         let config = TransformConfig {
             modmaps: vec![modmap1, modmap2],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         };
 
         let mut engine = TransformEngine::new(config);
@@ -326,10 +384,29 @@ mod tests {
         let config = TransformConfig {
             modmaps: vec![modmap],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![keymap],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         };
 
         let mut engine = TransformEngine::new(config);
@@ -359,10 +436,29 @@ mod tests {
         let config = TransformConfig {
             modmaps: vec![modmap],
             multimodmaps: vec![],
+            tapdances: vec![],
+            modtaps: vec![],
             keymaps: vec![keymap],
+            layers: vec![],
             suspend_key: None,
             multipurpose_timeout: Some(500),
             suspend_timeout: Some(1000),
+            nested_keymap_timeout: Some(1000),
+            modtap_timeout: None,
+            layer_rollover_timeout: None,
+            autoshift_keys: vec![],
+            autoshift_timeout: None,
+            dead_key_timeout: None,
+            mediamap: HashMap::new(),
+            media_passthrough: false,
+            ignore_wm_class: vec![],
+            ignore_wm_name: vec![],
+            suspend_when: None,
+            profile: "default".to_string(),
+            altgr: false,
+            output_delays: OutputDelays::default(),
+            delay_overrides: vec![],
+            setting_defaults: vec![],
         };
 
         let mut engine = TransformEngine::new(config);
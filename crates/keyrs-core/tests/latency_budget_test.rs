@@ -0,0 +1,79 @@
+// Latency budget for the transform hot path
+//
+// Guards against regressions in keymap matching on a production-sized
+// config. benches/transform.rs measures the same paths in detail.
+
+#[cfg(feature = "pure-rust")]
+#[path = "../benches/support/mod.rs"]
+mod support;
+
+#[cfg(feature = "pure-rust")]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use keyrs_core::Action;
+
+    use super::support;
+
+    /// Mean time one key event may take through `process_event`. Optimized
+    /// builds take around ten microseconds on this config; the budget leaves
+    /// room for slow CI machines, and debug builds get ten times more.
+    const EVENT_BUDGET: Duration = if cfg!(debug_assertions) {
+        Duration::from_millis(1)
+    } else {
+        Duration::from_micros(100)
+    };
+
+    /// Mean time the first key after a window switch may take, which
+    /// re-evaluates every keymap, modmap and layer condition
+    const WINDOW_SWITCH_BUDGET: Duration = if cfg!(debug_assertions) {
+        Duration::from_millis(20)
+    } else {
+        Duration::from_millis(2)
+    };
+
+    #[test]
+    fn test_process_event_within_latency_budget() {
+        let mut engine = support::production_engine();
+        let burst = support::typing_burst();
+        for &(key, action) in &burst {
+            let _ = engine.process_event(key, action);
+        }
+
+        let rounds = 200;
+        let start = Instant::now();
+        for _ in 0..rounds {
+            for &(key, action) in &burst {
+                std::hint::black_box(engine.process_event(key, action));
+            }
+        }
+        let mean = start.elapsed() / (rounds * burst.len() as u32);
+        eprintln!("process_event mean: {mean:?}");
+        assert!(
+            mean < EVENT_BUDGET,
+            "process_event took {mean:?} per event, budget {EVENT_BUDGET:?}"
+        );
+    }
+
+    #[test]
+    fn test_window_switch_within_latency_budget() {
+        let mut engine = support::production_engine();
+
+        let (key, _) = support::typing_burst()[0];
+
+        let rounds = 200;
+        let start = Instant::now();
+        for i in 0..rounds {
+            let class = if i % 2 == 0 { "app150" } else { "app299" };
+            engine.update_window_context(Some(class.to_string()), None);
+            std::hint::black_box(engine.process_event(key, Action::Press));
+            std::hint::black_box(engine.process_event(key, Action::Release));
+        }
+        let mean = start.elapsed() / rounds;
+        eprintln!("window switch mean: {mean:?}");
+        assert!(
+            mean < WINDOW_SWITCH_BUDGET,
+            "window switch took {mean:?}, budget {WINDOW_SWITCH_BUDGET:?}"
+        );
+    }
+}
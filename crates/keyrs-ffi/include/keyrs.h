@@ -0,0 +1,91 @@
+/*
+ * keyrs C API
+ *
+ * Embed the keyrs transform engine. Feed evdev key events, then read back
+ * what to emit, in order:
+ *
+ *     char *error = NULL;
+ *     KeyrsEngine *engine = keyrs_engine_new(toml, &error);
+ *     keyrs_engine_process_event(engine, code, value);
+ *     KeyrsResult result;
+ *     while (keyrs_engine_next_result(engine, &result)) { ... }
+ *     keyrs_engine_free(engine);
+ *
+ * Call keyrs_engine_tick() between events so tap/hold timeouts fire.
+ * An engine is not thread safe; use it from one thread at a time.
+ * A panic inside keyrs never unwinds into the caller: the call fails
+ * instead (-1, NULL or false).
+ *
+ * Link with -lkeyrs_ffi.
+ */
+
+#ifndef KEYRS_H
+#define KEYRS_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define KEYRS_RESULT_PASSTHROUGH 0 /* emit `key` unchanged */
+#define KEYRS_RESULT_REMAPPED 1    /* emit `key` */
+#define KEYRS_RESULT_COMBO_KEY 2   /* emit `key` */
+#define KEYRS_RESULT_COMBO 3       /* tap the combo in `text`; `key` is its key */
+#define KEYRS_RESULT_SEQUENCE 4    /* run the steps in `text` */
+#define KEYRS_RESULT_HINT 5        /* hint name in `text` */
+#define KEYRS_RESULT_SUPPRESS 6    /* emit nothing */
+#define KEYRS_RESULT_SUSPEND 7     /* suspend mode toggled */
+#define KEYRS_RESULT_UNICODE 8     /* type `codepoint` */
+#define KEYRS_RESULT_TEXT 9        /* type `text` */
+
+typedef struct KeyrsEngine KeyrsEngine;
+
+typedef struct KeyrsResult {
+    uint32_t kind;      /* KEYRS_RESULT_* */
+    int32_t value;      /* 0 release, 1 press, 2 repeat */
+    uint16_t key;       /* evdev key code, 0 when there is none */
+    uint32_t codepoint; /* KEYRS_RESULT_UNICODE only */
+    const char *text;   /* owned by the engine, valid until its next call; may be NULL */
+} KeyrsResult;
+
+/* Library version; static, do not free. */
+const char *keyrs_version(void);
+
+/* Create an engine from config TOML. Returns NULL on failure and, if `error`
+ * is not NULL, a message to free with keyrs_string_free(). Settings start
+ * empty; set them with keyrs_engine_set_setting(). */
+KeyrsEngine *keyrs_engine_new(const char *config_toml, char **error);
+
+/* Destroy an engine; NULL is ignored. */
+void keyrs_engine_free(KeyrsEngine *engine);
+
+/* Free a string returned by this library; NULL is ignored. */
+void keyrs_string_free(char *string);
+
+/* Feed a key event. Returns the number of results queued, -1 on bad arguments. */
+int32_t keyrs_engine_process_event(KeyrsEngine *engine, uint16_t code, int32_t value);
+
+/* Fire due timeouts (tap/hold, layer roll-over, autoshift, dead keys, mapping
+ * repeats). Returns the number of results queued, -1 on bad arguments. */
+int32_t keyrs_engine_tick(KeyrsEngine *engine);
+
+/* Set the focused window (either string may be NULL). Returns the number of
+ * results queued, -1 on bad arguments. */
+int32_t keyrs_engine_update_window(KeyrsEngine *engine, const char *wm_class, const char *wm_name);
+
+/* Set the input device name used by `device_name` conditions (NULL clears it). */
+void keyrs_engine_set_device_name(KeyrsEngine *engine, const char *name);
+
+/* Set a boolean setting used by `settings.<name>` conditions. */
+void keyrs_engine_set_setting(KeyrsEngine *engine, const char *name, bool value);
+
+/* Pop the next queued result into `out`. Returns false when none is left. */
+bool keyrs_engine_next_result(KeyrsEngine *engine, KeyrsResult *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* KEYRS_H */
@@ -0,0 +1,409 @@
+// Keyrs C ABI
+// Embed the transform engine in other remappers, compositors and bindings
+//
+// The engine is driven with evdev key events; everything it emits for an
+// event is queued in emission order and read back with
+// `keyrs_engine_next_result`. See `include/keyrs.h` for the C declarations.
+// A panic never unwinds into the caller: the call fails (-1, NULL or false)
+// instead.
+
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use keyrs_core::clock::system_clock;
+use keyrs_core::config::Config;
+use keyrs_core::settings::Settings;
+use keyrs_core::transform::engine::{TransformEngine, TransformResult};
+use keyrs_core::{Action, Key};
+
+/// `KeyrsResult.kind` values
+pub const KEYRS_RESULT_PASSTHROUGH: u32 = 0;
+pub const KEYRS_RESULT_REMAPPED: u32 = 1;
+pub const KEYRS_RESULT_COMBO_KEY: u32 = 2;
+pub const KEYRS_RESULT_COMBO: u32 = 3;
+pub const KEYRS_RESULT_SEQUENCE: u32 = 4;
+pub const KEYRS_RESULT_HINT: u32 = 5;
+pub const KEYRS_RESULT_SUPPRESS: u32 = 6;
+pub const KEYRS_RESULT_SUSPEND: u32 = 7;
+pub const KEYRS_RESULT_UNICODE: u32 = 8;
+pub const KEYRS_RESULT_TEXT: u32 = 9;
+
+static VERSION: &CStr = match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+    Ok(version) => version,
+    Err(_) => panic!("version contains a nul byte"),
+};
+
+/// One emitted output
+///
+/// `text` is owned by the engine and valid until the next call on it.
+#[repr(C)]
+pub struct KeyrsResult {
+    /// One of the `KEYRS_RESULT_*` values
+    pub kind: u32,
+    /// evdev event value: 0 release, 1 press, 2 repeat
+    pub value: i32,
+    /// Key to emit (for combos, the non-modifier key); 0 when there is none
+    pub key: u16,
+    /// Code point of a unicode result; 0 otherwise
+    pub codepoint: u32,
+    /// Combo (`C-Shift-s`), sequence steps, hint name or text; NULL otherwise
+    pub text: *const c_char,
+}
+
+/// Transform engine plus the outputs not yet read by the caller
+pub struct KeyrsEngine {
+    engine: TransformEngine,
+    queue: VecDeque<(TransformResult, Action)>,
+    text: Option<CString>,
+}
+
+impl KeyrsEngine {
+    fn push(&mut self, results: impl IntoIterator<Item = (TransformResult, Action)>) -> i32 {
+        let before = self.queue.len();
+        self.queue.extend(results);
+        (self.queue.len() - before) as i32
+    }
+
+    /// Queue an event's outputs in the order the daemon emits them
+    fn process_event(&mut self, key: Key, action: Action) -> i32 {
+        let result = self.engine.process_event(key, action);
+        let mut emitted = self.engine.take_deferred_results();
+        emitted.extend(
+            self.engine
+                .take_pending_outputs()
+                .into_iter()
+                .map(|(key, action)| (TransformResult::Remapped(key), action)),
+        );
+        emitted.push((result, action));
+        self.push(emitted)
+    }
+
+    /// Queue the outputs of timeouts that are due
+    fn tick(&mut self) -> i32 {
+        let mut emitted = Vec::new();
+        if let Some((key, action)) = self.engine.check_multipurpose_timeouts() {
+            emitted.push((TransformResult::Remapped(key), action));
+        }
+        emitted.extend(self.engine.check_layer_rollover());
+        emitted.extend(self.engine.check_autoshift());
+        emitted.extend(self.engine.check_tap_hold());
+        emitted.extend(self.engine.check_window_suspend());
+        if let Some(result) = self.engine.check_dead_key_timeout() {
+            emitted.push((result, Action::Press));
+        }
+        if let Some(result) = self.engine.check_synthetic_repeat() {
+            emitted.push((result, Action::Press));
+        }
+        self.push(emitted)
+    }
+
+    fn next_result(&mut self) -> Option<KeyrsResult> {
+        let (result, action) = self.queue.pop_front()?;
+        let (kind, key, codepoint, text) = match result {
+            TransformResult::Passthrough(key) => (KEYRS_RESULT_PASSTHROUGH, key.code(), 0, None),
+            TransformResult::Remapped(key) => (KEYRS_RESULT_REMAPPED, key.code(), 0, None),
+            TransformResult::ComboKey(key) => (KEYRS_RESULT_COMBO_KEY, key.code(), 0, None),
+            TransformResult::Combo(combo) => (KEYRS_RESULT_COMBO, combo.key().code(), 0, Some(combo.to_string())),
+            TransformResult::Sequence(steps) => (
+                KEYRS_RESULT_SEQUENCE,
+                0,
+                0,
+                Some(steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(", ")),
+            ),
+            TransformResult::Hint(hint) => (KEYRS_RESULT_HINT, 0, 0, Some(hint.to_string())),
+            TransformResult::Suppress => (KEYRS_RESULT_SUPPRESS, 0, 0, None),
+            TransformResult::Suspend => (KEYRS_RESULT_SUSPEND, 0, 0, None),
+            TransformResult::Unicode(codepoint) => (KEYRS_RESULT_UNICODE, 0, codepoint, None),
+            TransformResult::Text(text) => (KEYRS_RESULT_TEXT, 0, 0, Some(text)),
+        };
+        // Interior nul bytes cannot cross the C boundary; drop them
+        self.text = text.map(|text| CString::new(text.replace('\0', "")).unwrap_or_default());
+        Some(KeyrsResult {
+            kind,
+            value: action_value(action),
+            key,
+            codepoint,
+            text: self.text.as_ref().map_or(ptr::null(), |text| text.as_ptr()),
+        })
+    }
+}
+
+fn action_from_value(value: i32) -> Option<Action> {
+    match value {
+        0 => Some(Action::Release),
+        1 => Some(Action::Press),
+        2 => Some(Action::Repeat),
+        _ => None,
+    }
+}
+
+fn action_value(action: Action) -> i32 {
+    match action {
+        Action::Release => 0,
+        Action::Press => 1,
+        Action::Repeat => 2,
+    }
+}
+
+/// Optional C string argument; NULL and invalid UTF-8 are `None`
+unsafe fn optional_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok().map(str::to_string)
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: String) {
+    if !error.is_null() {
+        *error = CString::new(message.replace('\0', "")).unwrap_or_default().into_raw();
+    }
+}
+
+/// Run the body of an exported function; `None` if it panicked
+fn guard<T>(body: impl FnOnce() -> T) -> Option<T> {
+    panic::catch_unwind(AssertUnwindSafe(body)).ok()
+}
+
+/// Library version, e.g. `"0.2.1"`; static, do not free
+#[no_mangle]
+pub extern "C" fn keyrs_version() -> *const c_char {
+    guard(|| VERSION.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Create an engine from config TOML (`include` entries are ignored)
+///
+/// Returns NULL on failure and, when `error` is not NULL, stores a message
+/// there that must be freed with `keyrs_string_free`. Settings start empty
+/// (`~/.config/keyrs/settings.toml` is not read); set them with
+/// `keyrs_engine_set_setting`.
+///
+/// # Safety
+///
+/// `config_toml` must be NULL or a nul-terminated string; `error` must be
+/// NULL or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_new(config_toml: *const c_char, error: *mut *mut c_char) -> *mut KeyrsEngine {
+    guard(|| engine_new(config_toml, error)).unwrap_or_else(|| {
+        set_error(error, "keyrs panicked while creating the engine".to_string());
+        ptr::null_mut()
+    })
+}
+
+unsafe fn engine_new(config_toml: *const c_char, error: *mut *mut c_char) -> *mut KeyrsEngine {
+    if config_toml.is_null() {
+        set_error(error, "config is NULL".to_string());
+        return ptr::null_mut();
+    }
+    let source = match CStr::from_ptr(config_toml).to_str() {
+        Ok(source) => source,
+        Err(e) => {
+            set_error(error, format!("config is not UTF-8: {}", e));
+            return ptr::null_mut();
+        }
+    };
+    match Config::from_toml(source) {
+        Ok(config) => Box::into_raw(Box::new(KeyrsEngine {
+            engine: TransformEngine::with_clock(config.to_transform_config(), Settings::new(), system_clock()),
+            queue: VecDeque::new(),
+            text: None,
+        })),
+        Err(e) => {
+            set_error(error, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Destroy an engine; NULL is ignored
+///
+/// # Safety
+///
+/// `engine` must be NULL or come from `keyrs_engine_new`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_free(engine: *mut KeyrsEngine) {
+    if !engine.is_null() {
+        guard(|| drop(Box::from_raw(engine)));
+    }
+}
+
+/// Free a string returned by this library; NULL is ignored
+///
+/// # Safety
+///
+/// `string` must be NULL or a string handed out by this library, freed once.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        guard(|| drop(CString::from_raw(string)));
+    }
+}
+
+/// Feed a key event (`value`: 0 release, 1 press, 2 repeat)
+///
+/// Returns the number of results queued, or -1 for a bad argument or a panic.
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_process_event(engine: *mut KeyrsEngine, code: u16, value: i32) -> i32 {
+    match (engine.as_mut(), action_from_value(value)) {
+        (Some(engine), Some(action)) => guard(|| engine.process_event(Key::from(code), action)).unwrap_or(-1),
+        _ => -1,
+    }
+}
+
+/// Fire due tap/hold, layer roll-over, autoshift, dead key and repeat timeouts
+///
+/// Call it between events (every ~10ms while keys are held). Returns the
+/// number of results queued, or -1 for a NULL engine or a panic.
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_tick(engine: *mut KeyrsEngine) -> i32 {
+    engine.as_mut().and_then(|engine| guard(|| engine.tick())).unwrap_or(-1)
+}
+
+/// Set the focused window; either string may be NULL
+///
+/// Queues the release of a multipurpose hold key held across the change.
+/// Returns the number of results queued, or -1 for a NULL engine or a panic.
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine; the strings must be NULL or nul-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_update_window(
+    engine: *mut KeyrsEngine,
+    wm_class: *const c_char,
+    wm_name: *const c_char,
+) -> i32 {
+    let Some(engine) = engine.as_mut() else {
+        return -1;
+    };
+    guard(|| {
+        let hold_key = engine
+            .engine
+            .update_window_context(optional_str(wm_class), optional_str(wm_name));
+        engine.push(hold_key.map(|key| (TransformResult::Remapped(key), Action::Release)))
+    })
+    .unwrap_or(-1)
+}
+
+/// Set the device the next events come from (for `device_name` conditions)
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine; `name` must be NULL or nul-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_set_device_name(engine: *mut KeyrsEngine, name: *const c_char) {
+    if let Some(engine) = engine.as_mut() {
+        guard(|| engine.engine.set_device_name(optional_str(name)));
+    }
+}
+
+/// Set a boolean setting used by `settings.<name>` conditions
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine; `name` must be NULL or nul-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_set_setting(engine: *mut KeyrsEngine, name: *const c_char, value: bool) {
+    if let (Some(engine), Some(name)) = (engine.as_mut(), optional_str(name)) {
+        guard(|| engine.engine.set_setting(&name, value));
+    }
+}
+
+/// Pop the next queued result into `out`; returns false when the queue is empty
+///
+/// # Safety
+///
+/// `engine` must be NULL or a live engine; `out` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn keyrs_engine_next_result(engine: *mut KeyrsEngine, out: *mut KeyrsResult) -> bool {
+    let (Some(engine), false) = (engine.as_mut(), out.is_null()) else {
+        return false;
+    };
+    match guard(|| engine.next_result()).flatten() {
+        Some(result) => {
+            out.write(result);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &CStr = c"
+[modmap.default]
+CAPSLOCK = \"ESC\"
+
+[[keymap]]
+name = \"editor\"
+condition = \"wm_class =~ 'code'\"
+[keymap.mappings]
+\"C-s\" = \"C-Shift-s\"
+";
+
+    unsafe fn drain(engine: *mut KeyrsEngine) -> Vec<(u32, i32, u16, Option<String>)> {
+        let mut out = KeyrsResult {
+            kind: 0,
+            value: 0,
+            key: 0,
+            codepoint: 0,
+            text: ptr::null(),
+        };
+        let mut results = Vec::new();
+        while keyrs_engine_next_result(engine, &mut out) {
+            let text = (!out.text.is_null()).then(|| CStr::from_ptr(out.text).to_string_lossy().into_owned());
+            results.push((out.kind, out.value, out.key, text));
+        }
+        results
+    }
+
+    #[test]
+    fn test_engine_round_trip() {
+        unsafe {
+            let engine = keyrs_engine_new(CONFIG.as_ptr(), ptr::null_mut());
+            assert!(!engine.is_null());
+
+            assert_eq!(keyrs_engine_process_event(engine, 58, 1), 1);
+            assert_eq!(drain(engine), vec![(KEYRS_RESULT_REMAPPED, 1, 1, None)]);
+
+            keyrs_engine_update_window(engine, c"Code".as_ptr(), ptr::null());
+            for (code, value) in [(29, 1), (31, 1), (31, 0), (29, 0)] {
+                keyrs_engine_process_event(engine, code, value);
+            }
+            let results = drain(engine);
+            assert_eq!(results[1].0, KEYRS_RESULT_COMBO);
+            assert_eq!(results[1].2, 31);
+            assert!(results[1].3.as_deref().unwrap().ends_with("-S"));
+
+            assert_eq!(keyrs_engine_process_event(engine, 30, 7), -1);
+            assert_eq!(keyrs_engine_tick(engine), 0);
+            keyrs_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_engine_new_reports_errors() {
+        unsafe {
+            let mut error: *mut c_char = ptr::null_mut();
+            assert!(keyrs_engine_new(c"[nope]".as_ptr(), &mut error).is_null());
+            assert!(!error.is_null());
+            assert!(!CStr::from_ptr(error).to_bytes().is_empty());
+            keyrs_string_free(error);
+
+            assert!(keyrs_engine_new(ptr::null(), ptr::null_mut()).is_null());
+            assert_eq!(guard(|| -> i32 { panic!("boom") }), None);
+            assert_eq!(CStr::from_ptr(keyrs_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        }
+    }
+}
@@ -0,0 +1,392 @@
+// Keyrs Python Bindings
+// TransformEngine, Config and TransformResult as Python classes
+//
+// Lets the legacy Python stack hand its transform path to Rust piece by
+// piece: Python keeps reading devices and writing output, and asks the
+// engine what each key event becomes.
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use keyrs_core::clock::system_clock;
+use keyrs_core::config::Config;
+use keyrs_core::settings::Settings;
+use keyrs_core::transform::engine::{TransformEngine, TransformResult};
+use keyrs_core::{Action, Key};
+
+/// Version of the Python API; bumped only on incompatible changes
+const API_VERSION: u32 = 1;
+
+/// evdev event value (0 release, 1 press, 2 repeat) to an action
+fn action_from_value(value: i32) -> PyResult<Action> {
+    match value {
+        0 => Ok(Action::Release),
+        1 => Ok(Action::Press),
+        2 => Ok(Action::Repeat),
+        _ => Err(PyValueError::new_err(format!(
+            "action must be 0 (release), 1 (press) or 2 (repeat), got {}",
+            value
+        ))),
+    }
+}
+
+fn action_value(action: Action) -> i32 {
+    match action {
+        Action::Release => 0,
+        Action::Press => 1,
+        Action::Repeat => 2,
+    }
+}
+
+/// Parsed keyrs configuration
+#[pyclass(name = "Config", module = "keyrs_python")]
+#[derive(Clone)]
+struct PyConfig {
+    inner: Config,
+}
+
+#[pymethods]
+impl PyConfig {
+    /// Parse a config from TOML text (`include` entries are ignored)
+    #[staticmethod]
+    fn from_toml(source: &str) -> PyResult<Self> {
+        Config::from_toml(source)
+            .map(|inner| Self { inner })
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Load a config file, resolving its `include` entries
+    #[staticmethod]
+    fn from_file(path: std::path::PathBuf) -> PyResult<Self> {
+        Config::from_toml_path(&path)
+            .map(|inner| Self { inner })
+            .map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Names of the configured keymaps, in match order
+    #[getter]
+    fn keymaps(&self) -> Vec<String> {
+        self.inner.keymaps.iter().map(|keymap| keymap.name.clone()).collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<keyrs_python.Config keymaps={}>", self.inner.keymaps.len())
+    }
+}
+
+/// What one key event becomes
+///
+/// `kind` is one of `passthrough`, `remapped`, `combo_key`, `combo`,
+/// `sequence`, `hint`, `suppress`, `suspend`, `unicode` and `text`.
+#[pyclass(name = "TransformResult", module = "keyrs_python", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyTransformResult {
+    inner: TransformResult,
+}
+
+#[pymethods]
+impl PyTransformResult {
+    #[getter]
+    fn kind(&self) -> &'static str {
+        match &self.inner {
+            TransformResult::Passthrough(_) => "passthrough",
+            TransformResult::Remapped(_) => "remapped",
+            TransformResult::ComboKey(_) => "combo_key",
+            TransformResult::Combo(_) => "combo",
+            TransformResult::Sequence(_) => "sequence",
+            TransformResult::Hint(_) => "hint",
+            TransformResult::Suppress => "suppress",
+            TransformResult::Suspend => "suspend",
+            TransformResult::Unicode(_) => "unicode",
+            TransformResult::Text(_) => "text",
+        }
+    }
+
+    /// Key code to emit (`combo` results: the non-modifier key)
+    #[getter]
+    fn key(&self) -> Option<u16> {
+        match &self.inner {
+            TransformResult::Passthrough(key) | TransformResult::Remapped(key) | TransformResult::ComboKey(key) => {
+                Some(key.code())
+            }
+            TransformResult::Combo(combo) => Some(combo.key().code()),
+            _ => None,
+        }
+    }
+
+    /// Modifier names of a `combo` result
+    #[getter]
+    fn modifiers(&self) -> Vec<String> {
+        match &self.inner {
+            TransformResult::Combo(combo) => combo.modifiers().iter().map(|m| m.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Text of a `text` result, or the character of a `unicode` result
+    #[getter]
+    fn text(&self) -> Option<String> {
+        match &self.inner {
+            TransformResult::Text(text) => Some(text.clone()),
+            TransformResult::Unicode(codepoint) => char::from_u32(*codepoint).map(String::from),
+            _ => None,
+        }
+    }
+
+    /// Hint name of a `hint` result (`BIND`, `ESCAPE_NEXT_KEY`, ...)
+    #[getter]
+    fn hint(&self) -> Option<String> {
+        match &self.inner {
+            TransformResult::Hint(hint) => Some(hint.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Steps of a `sequence` result, in config syntax
+    #[getter]
+    fn steps(&self) -> Vec<String> {
+        match &self.inner {
+            TransformResult::Sequence(steps) => steps.iter().map(|step| step.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        let detail = match &self.inner {
+            TransformResult::Passthrough(key) | TransformResult::Remapped(key) | TransformResult::ComboKey(key) => {
+                key.to_string()
+            }
+            TransformResult::Combo(combo) => combo.to_string(),
+            TransformResult::Sequence(steps) => steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(", "),
+            TransformResult::Hint(hint) => hint.to_string(),
+            TransformResult::Unicode(codepoint) => format!("U+{:04X}", codepoint),
+            TransformResult::Text(text) => format!("{:?}", text),
+            TransformResult::Suppress | TransformResult::Suspend => String::new(),
+        };
+        format!("<TransformResult {} {}>", self.kind(), detail).replace(" >", ">")
+    }
+}
+
+fn results(emitted: Vec<(TransformResult, Action)>) -> Vec<(PyTransformResult, i32)> {
+    emitted
+        .into_iter()
+        .map(|(inner, action)| (PyTransformResult { inner }, action_value(action)))
+        .collect()
+}
+
+/// The keyrs transform engine
+///
+/// Feed it evdev key events (`code`, `value`); after each `process_event`,
+/// emit `take_deferred_results()`, then `take_pending_outputs()`, then the
+/// returned result. Call the `check_*` methods between events so timeouts fire.
+#[pyclass(name = "TransformEngine", module = "keyrs_python", unsendable)]
+struct PyTransformEngine {
+    inner: TransformEngine,
+}
+
+#[pymethods]
+impl PyTransformEngine {
+    /// Create an engine; `settings` replaces `~/.config/keyrs/settings.toml`
+    #[new]
+    #[pyo3(signature = (config, settings = None))]
+    fn new(config: &PyConfig, settings: Option<HashMap<String, bool>>) -> Self {
+        let transform_config = config.inner.to_transform_config();
+        let inner = match settings {
+            Some(values) => {
+                let mut settings = Settings::new();
+                for (name, value) in values {
+                    settings.set_bool(&name, value);
+                }
+                TransformEngine::with_clock(transform_config, settings, system_clock())
+            }
+            None => TransformEngine::new(transform_config),
+        };
+        Self { inner }
+    }
+
+    /// Transform one key event (`value`: 0 release, 1 press, 2 repeat)
+    fn process_event(&mut self, code: u16, value: i32) -> PyResult<PyTransformResult> {
+        let action = action_from_value(value)?;
+        Ok(PyTransformResult {
+            inner: self.inner.process_event(Key::from(code), action),
+        })
+    }
+
+    /// Transform events and return everything they emit, in emission order
+    fn process_events(&mut self, events: Vec<(u16, i32)>) -> PyResult<Vec<(PyTransformResult, i32)>> {
+        let events = events
+            .into_iter()
+            .map(|(code, value)| Ok((Key::from(code), action_from_value(value)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(results(self.inner.process_events(&events)))
+    }
+
+    /// Results resolved by the last event, to emit before its pending outputs
+    fn take_deferred_results(&mut self) -> Vec<(PyTransformResult, i32)> {
+        results(self.inner.take_deferred_results())
+    }
+
+    /// Key events produced as a side effect of the last event, to emit before its result
+    fn take_pending_outputs(&mut self) -> Vec<(u16, i32)> {
+        self.inner
+            .take_pending_outputs()
+            .into_iter()
+            .map(|(key, action)| (key.code(), action_value(action)))
+            .collect()
+    }
+
+    /// Multipurpose key held past its timeout: `(code, value)` to emit
+    fn check_multipurpose_timeouts(&mut self) -> Option<(u16, i32)> {
+        self.inner
+            .check_multipurpose_timeouts()
+            .map(|(key, action)| (key.code(), action_value(action)))
+    }
+
+    /// Layer trigger held past its roll-over window
+    fn check_layer_rollover(&mut self) -> Vec<(PyTransformResult, i32)> {
+        results(self.inner.check_layer_rollover())
+    }
+
+    /// Autoshift key held past its timeout
+    fn check_autoshift(&mut self) -> Vec<(PyTransformResult, i32)> {
+        results(self.inner.check_autoshift())
+    }
+
+    /// Release held keys on entering a `suspend_when` window
+    fn check_window_suspend(&mut self) -> Vec<(PyTransformResult, i32)> {
+        results(self.inner.check_window_suspend())
+    }
+
+    /// Keymap tap/hold mapping held past the multipurpose timeout
+    fn check_tap_hold(&mut self) -> Vec<(PyTransformResult, i32)> {
+        results(self.inner.check_tap_hold())
+    }
+
+    /// Dead key pending past `[timeouts] dead_key`: its accent, to emit as a press
+    fn check_dead_key_timeout(&mut self) -> Option<PyTransformResult> {
+        self.inner
+            .check_dead_key_timeout()
+            .map(|inner| PyTransformResult { inner })
+    }
+
+    /// Output of a held `repeat = {...}` mapping that is due, to emit as a press
+    fn check_synthetic_repeat(&mut self) -> Option<PyTransformResult> {
+        self.inner
+            .check_synthetic_repeat()
+            .map(|inner| PyTransformResult { inner })
+    }
+
+    /// Set the focused window; returns a multipurpose hold key to release, if any
+    #[pyo3(signature = (wm_class, wm_name = None))]
+    fn update_window(&mut self, wm_class: Option<String>, wm_name: Option<String>) -> Option<u16> {
+        self.inner.update_window_context(wm_class, wm_name).map(|key| key.code())
+    }
+
+    /// Set the device the next events come from (for `device_name` conditions)
+    #[pyo3(signature = (name = None))]
+    fn set_device_name(&mut self, name: Option<String>) {
+        self.inner.set_device_name(name);
+    }
+
+    /// Set Num Lock and Caps Lock state
+    fn set_lock_states(&mut self, numlock: bool, capslock: bool) {
+        self.inner.set_lock_states(numlock, capslock);
+    }
+
+    fn get_setting(&self, name: &str) -> bool {
+        self.inner.get_setting(name)
+    }
+
+    fn set_setting(&mut self, name: &str, value: bool) {
+        self.inner.set_setting(name, value);
+    }
+
+    /// Re-read `~/.config/keyrs/settings.toml`
+    fn reload_settings(&mut self) -> PyResult<()> {
+        self.inner.reload_settings().map_err(|e| PyOSError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn suspended(&self) -> bool {
+        self.inner.is_suspended()
+    }
+
+    fn suspend(&mut self) {
+        self.inner.suspend();
+    }
+
+    fn resume(&mut self) {
+        self.inner.resume();
+    }
+
+    /// Forget held keys, layers and pending tap/hold state
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Names of the active layers, highest precedence first
+    fn active_layers(&self) -> Vec<String> {
+        self.inner.active_layers()
+    }
+}
+
+#[pymodule]
+fn keyrs_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("API_VERSION", API_VERSION)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyConfig>()?;
+    m.add_class::<PyTransformResult>()?;
+    m.add_class::<PyTransformEngine>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[modmap.default]
+CAPSLOCK = "ESC"
+
+[[keymap]]
+name = "editor"
+condition = "wm_class =~ 'code'"
+[keymap.mappings]
+"C-s" = "C-Shift-s"
+"#;
+
+    fn engine() -> PyTransformEngine {
+        let config = PyConfig::from_toml(CONFIG).unwrap();
+        PyTransformEngine::new(&config, Some(HashMap::new()))
+    }
+
+    #[test]
+    fn test_engine_maps_events() {
+        let mut engine = engine();
+        let result = engine.process_event(58, 1).unwrap();
+        assert_eq!(result.kind(), "remapped");
+        assert_eq!(result.key(), Some(1));
+        assert_eq!(result.__repr__(), "<TransformResult remapped ESC>");
+
+        assert_eq!(engine.update_window(Some("code".to_string()), None), None);
+        let emitted = engine.process_events(vec![(29, 1), (31, 1), (31, 0), (29, 0)]).unwrap();
+        let (combo, action) = &emitted[1];
+        assert_eq!((combo.kind(), *action), ("combo", 1));
+        assert_eq!(combo.key(), Some(31));
+        assert_eq!(combo.modifiers().len(), 2);
+
+        assert!(engine.process_event(30, 3).is_err());
+    }
+
+    #[test]
+    fn test_config_errors_are_value_errors() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let err = PyConfig::from_toml("[nope]\n").err().unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+        assert_eq!(PyConfig::from_toml(CONFIG).unwrap().keymaps(), vec!["editor".to_string()]);
+    }
+}
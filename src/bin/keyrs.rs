@@ -3,6 +3,7 @@
 
 #![cfg_attr(feature = "pure-rust", allow(dead_code))]
 
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -14,22 +15,50 @@ use clap::Parser;
 #[cfg(feature = "pure-rust")]
 use toml::Value;
 
+#[cfg(feature = "pure-rust")]
+use keyrs_core::config::compose::{merge_config_fragment, read_fragment};
 #[cfg(feature = "pure-rust")]
 use keyrs_core::config::parser::Config;
 #[cfg(feature = "pure-rust")]
+use keyrs_core::config::{config_schema_json, lint_config, migrate_files, render_cheatsheet, CheatsheetFormat, CURRENT_SCHEMA_VERSION};
+#[cfg(feature = "pure-rust")]
+use keyrs::logging::Logging;
+#[cfg(feature = "pure-rust")]
+use keyrs_core::logging::LogFormat;
+#[cfg(feature = "pure-rust")]
+use keyrs_core::event::{stop_on_panic, DeviceGuard, DeviceInfo, EventLoop, EventLoopError, InputSource, Timer, Waker};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::metrics::{MetricsServer, STATS_TOP_COMBOS};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::audit::{first_divergence, replay, AuditDump};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::clock::ManualClock;
+#[cfg(feature = "pure-rust")]
 use keyrs_core::output::TransformResultOutput;
 #[cfg(feature = "pure-rust")]
-use keyrs_core::output::VirtualDevice;
+use keyrs_core::output::{Injection, ObserverHub, OutputThread, UnicodeBackend, VirtualDevice};
 #[cfg(feature = "pure-rust")]
-use keyrs_core::settings::Settings;
+use keyrs_core::control::{ControlClient, ControlReply, ControlRequest, ControlServer};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::privsep::{HelperAccess, InputHelper, RemoteInput, SYSTEM_SOCKET_PATH};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::instance::InstanceLock;
+#[cfg(feature = "pure-rust")]
+use keyrs_core::settings::{write_device_keyboard_type, Settings, SettingsWatcher};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::status::{DaemonStatus, LiveStatus, StatusFormat, StatusPublisher};
+#[cfg(feature = "pure-rust")]
+use keyrs_core::systemd::{SessionMonitor, DEFAULT_LOCK_POLL_MS};
 #[cfg(feature = "pure-rust")]
 use keyrs_core::input::{
-    detect_keyboard_type_simple, KeyboardDeviceInfo, KeyboardType,
+    detect_keyboard_type_simple, format_device_id, parse_device_id, read_fn_mode, write_fn_mode, FnMode,
+    KeyboardDeviceInfo, KeyboardType, LockState, PanicChord, ScancodeRemap, SeatFilter, DEFAULT_PANIC_HOLD_MS,
+    FNMODE_PATH,
 };
 #[cfg(feature = "pure-rust")]
 use keyrs_core::transform::engine::TransformEngine;
 #[cfg(feature = "pure-rust")]
-use keyrs_core::transform::TransformResult;
+use keyrs_core::transform::{ComposeTable, TransformResult};
 #[cfg(feature = "pure-rust")]
 use keyrs_core::window::WaylandContextProvider;
 #[cfg(feature = "pure-rust")]
@@ -45,21 +74,30 @@ use keyrs_core::Key;
 #[command(about = "Pure Rust Wayland key remapper", long_about = None)]
 struct Args {
     /// TOML configuration file
-    #[arg(short, long, value_name = "CONFIG")]
+    #[arg(short, long, value_name = "CONFIG", global = true)]
     config: Option<PathBuf>,
 
     /// Manually specify devices to remap (can be used multiple times)
-    #[arg(short, long, value_name = "DEVICE")]
+    #[arg(short, long, value_name = "DEVICE", global = true)]
     devices: Vec<String>,
 
-    /// Watch for hot-plugged devices
+    /// Watch for hot-plugged devices, and grab an unplugged keyboard again
+    /// when it returns, even at a new device path
     #[arg(short, long)]
     watch: bool,
 
-    /// Enable debug logging
+    /// Enable info logging (default level is warn)
     #[arg(short, long)]
     verbose: bool,
 
+    /// Log filter directives, e.g. "debug" or "info,transform=debug,output=trace"
+    #[arg(long, value_name = "DIRECTIVES")]
+    log: Option<String>,
+
+    /// Log line format: text or json (overrides [logging].format)
+    #[arg(long, value_name = "FORMAT", value_parser = parse_log_format)]
+    log_format: Option<LogFormat>,
+
     /// Validate config and exit
     #[arg(long)]
     check_config: bool,
@@ -68,6 +106,18 @@ struct Args {
     #[arg(long)]
     list_devices: bool,
 
+    /// List key names and modifier aliases accepted in config files (same as `keyrs keys`)
+    #[arg(long)]
+    list_keys: bool,
+
+    /// Read devices without grabbing and print how each key event would be matched
+    #[arg(long)]
+    trace: bool,
+
+    /// Print the raw scancode (MSC_SCAN) of each key press, for [scancodes] entries
+    #[arg(long)]
+    show_scancodes: bool,
+
     /// Compose modular TOML config directory into a single config file and exit
     #[arg(long, value_name = "DIR")]
     compose_config: Option<PathBuf>,
@@ -75,6 +125,237 @@ struct Args {
     /// Output path for --compose-config (default: parent of DIR/config.toml)
     #[arg(long, value_name = "FILE")]
     compose_output: Option<PathBuf>,
+
+    /// Convert a Toshy/Kinto Python config to keyrs TOML on stdout and exit
+    #[arg(long, value_name = "FILE")]
+    import_toshy: Option<PathBuf>,
+
+    /// Write and enable the keyrs systemd user unit for this binary and exit
+    #[arg(long)]
+    install_service: bool,
+
+    /// Only grab devices and write output, for a remapping process started
+    /// with --connect-helper (run this one with input device access)
+    #[arg(long, conflicts_with_all = ["connect_helper", "trace"])]
+    input_helper: bool,
+
+    /// Remap without device access, through a running --input-helper
+    #[arg(long)]
+    connect_helper: bool,
+
+    /// Input helper socket (default: /run/keyrs/input.sock for a helper
+    /// started with --helper-group and when that socket exists, otherwise
+    /// $XDG_RUNTIME_DIR/keyrs-input.sock)
+    #[arg(long, value_name = "PATH")]
+    helper_socket: Option<PathBuf>,
+
+    /// Let members of this group connect to the input helper (a helper
+    /// running as a system service); by default only its own user may
+    #[arg(long, value_name = "GROUP", requires = "input_helper", conflicts_with = "connect_helper")]
+    helper_group: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[cfg(feature = "pure-rust")]
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Print the running daemon's state for status bars and exit
+    Status {
+        /// Output format: text, waybar or polybar
+        #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = parse_status_format)]
+        format: StatusFormat,
+    },
+    /// Type text through the virtual keyboard (like `ydotool type`)
+    Type {
+        /// Text to type; several arguments are joined with spaces
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+    /// Tap key combos through the virtual keyboard, e.g. `ctrl+alt+t` or `C-A-t`
+    Key {
+        /// Combos to tap in order
+        #[arg(required = true)]
+        combos: Vec<String>,
+    },
+    /// Switch the running daemon to a [profiles] config, or to the next one
+    Profile {
+        /// Profile name; omit to cycle
+        name: Option<String>,
+    },
+    /// Print the running daemon's [audit] log as JSON, for `keyrs replay`
+    Audit,
+    /// Feed the inputs of an audit dump through the config again and report where the output differs
+    Replay {
+        /// Dump from `keyrs audit` or the diagnostics key
+        dump: PathBuf,
+    },
+    /// Print the config's bindings per keymap and layer (--config defaults to ~/.config/keyrs/config.toml)
+    Cheatsheet {
+        /// Output format: markdown or html
+        #[arg(long, value_name = "FORMAT", default_value = "markdown", value_parser = parse_cheatsheet_format)]
+        format: CheatsheetFormat,
+    },
+    /// Report mappings that can never apply or do nothing, and output keys missing from the keyboard
+    Lint,
+    /// List key names and modifier aliases, or check combos and suggest corrections
+    Keys {
+        /// Combos to check, e.g. `C-Shift-PAGE_UP`; omit to list every name
+        combos: Vec<String>,
+        /// Print the completions of a partially typed combo instead
+        #[arg(long, value_name = "PARTIAL", conflicts_with = "combos")]
+        complete: Option<String>,
+    },
+    /// Rewrite the config and its fragments in the current format (keeps a .bak copy)
+    MigrateConfig {
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print a JSON Schema of the config format, for editor completion and validation
+    ConfigSchema,
+    /// Manage per-keyboard settings
+    Devices {
+        #[command(subcommand)]
+        command: DevicesCommand,
+    },
+}
+
+#[cfg(feature = "pure-rust")]
+#[derive(clap::Subcommand, Debug)]
+enum DevicesCommand {
+    /// Set a keyboard's type in settings.toml, e.g. `set-type "Magic Keyboard" Mac`
+    SetType {
+        /// Device name, /dev/input path or vendor:product id (see --list-devices)
+        device: String,
+        /// IBM, Chromebook, Windows, Mac, or auto to detect it again
+        keyboard_type: String,
+    },
+    /// Print or set what the F-row of Apple keyboards sends (hid_apple fnmode; setting needs root)
+    Fnmode {
+        /// media (media keys, F1..F12 with Fn), fkeys (the reverse), disabled or auto
+        #[arg(value_parser = parse_fn_mode)]
+        mode: Option<FnMode>,
+    },
+}
+
+/// Parse the other `[profiles]` configs into `engine`; their files are
+/// relative to `config_dir`
+#[cfg(feature = "pure-rust")]
+fn load_profiles(engine: &mut TransformEngine, config: &Config, config_dir: &Path) -> Result<(), String> {
+    for (name, file) in &config.profiles {
+        let path = config_dir.join(file);
+        let mut profile = Config::from_toml_path(&path)
+            .map_err(|e| format!("profile '{}' ({}): {}", name, path.display(), e))?
+            .to_transform_config();
+        profile.profile = name.clone();
+        engine.add_profile(profile);
+    }
+    Ok(())
+}
+
+/// Compose table of an enabled `[compose]` section
+#[cfg(feature = "pure-rust")]
+fn compose_table(config: &Config) -> Option<ComposeTable> {
+    match &config.compose_file {
+        Some(path) => ComposeTable::load(path)
+            .map_err(|e| log::warn!("Could not load compose file {}: {}", path.display(), e))
+            .ok(),
+        None => ComposeTable::load_default(),
+    }
+}
+
+/// Time for the compositor to pick up a freshly created virtual keyboard
+#[cfg(feature = "pure-rust")]
+const INJECT_DEVICE_SETTLE: Duration = Duration::from_millis(300);
+
+/// Emit `injections` through the running daemon, or through a temporary
+/// virtual keyboard when no daemon answers
+#[cfg(feature = "pure-rust")]
+fn inject(injections: &[Injection], requests: &[ControlRequest]) -> Result<(), Box<dyn std::error::Error>> {
+    match ControlServer::default_path().and_then(|path| ControlClient::connect(&path)) {
+        Ok(mut control) => {
+            for request in requests {
+                control.request(request)?;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            log::info!("No daemon to inject through ({}); using a temporary virtual keyboard", e);
+            let mut device = VirtualDevice::new()?;
+            std::thread::sleep(INJECT_DEVICE_SETTLE);
+            for injection in injections {
+                injection.send(&mut device)?;
+            }
+            std::thread::sleep(INJECT_DEVICE_SETTLE);
+            device.close()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+fn parse_status_format(value: &str) -> Result<StatusFormat, String> {
+    StatusFormat::from_name(value).ok_or_else(|| format!("expected text, waybar or polybar, got '{}'", value))
+}
+
+#[cfg(feature = "pure-rust")]
+fn parse_cheatsheet_format(value: &str) -> Result<CheatsheetFormat, String> {
+    CheatsheetFormat::from_name(value).ok_or_else(|| format!("expected markdown or html, got '{}'", value))
+}
+
+#[cfg(feature = "pure-rust")]
+fn parse_fn_mode(value: &str) -> Result<FnMode, String> {
+    FnMode::from_name(value).ok_or_else(|| format!("expected media, fkeys, disabled or auto, got '{}'", value))
+}
+
+#[cfg(feature = "pure-rust")]
+fn parse_log_format(value: &str) -> Result<LogFormat, String> {
+    LogFormat::from_name(value).ok_or_else(|| format!("expected text or json, got '{}'", value))
+}
+
+/// How the daemon and trace loops wait, from `[window]`
+#[cfg(feature = "pure-rust")]
+#[derive(Debug, Clone, Copy)]
+struct LoopTiming {
+    /// Upper bound on one wait even with nothing due (`poll_timeout_ms`).
+    /// Every deadline wakes the loop by itself, so this only forces periodic
+    /// wakeups; it stays for configs that set it and is unset by default.
+    poll_timeout: Option<Duration>,
+    /// Focus polling interval for window providers that can't report focus changes
+    window_update_interval: Duration,
+    /// Back-off after a failed wait (`idle_sleep_ms`), so a persistent error
+    /// doesn't spin a core
+    idle_sleep: Duration,
+}
+
+#[cfg(feature = "pure-rust")]
+impl LoopTiming {
+    fn new(config: &Config) -> Self {
+        Self {
+            poll_timeout: config.poll_timeout_ms.map(Duration::from_millis),
+            window_update_interval: Duration::from_millis(config.window_poll_interval_ms()),
+            idle_sleep: Duration::from_millis(config.idle_sleep_ms.unwrap_or(10)),
+        }
+    }
+}
+
+/// What the daemon's main loop runs with, besides the input and the engine
+#[cfg(feature = "pure-rust")]
+struct MainLoop<'a> {
+    output_thread: &'a OutputThread,
+    metrics_server: Option<&'a MetricsServer>,
+    session_monitor: Option<&'a mut SessionMonitor>,
+    status: &'a mut StatusPublisher,
+    panic_chord: PanicChord,
+    scancodes: ScancodeRemap,
+    settings_watcher: Option<SettingsWatcher>,
+    control: Option<ControlServer>,
+    mirror_lock_leds: bool,
+    diagnostics_key: Option<Key>,
+    emergency_eject_key: Option<Key>,
+    timing: LoopTiming,
 }
 
 /// Main application state
@@ -84,6 +365,8 @@ struct Application {
     args: Args,
     /// Flag to signal event loop to stop
     running: Arc<AtomicBool>,
+    /// Wakes the event loop once `running` is cleared from another thread
+    shutdown: Waker,
 }
 
 #[cfg(feature = "pure-rust")]
@@ -95,7 +378,10 @@ fn resolve_keyboard_type(settings: &Settings, devices: &[KeyboardDeviceInfo]) ->
     }
 
     for device in devices {
-        let detected = detect_keyboard_type_simple(device);
+        let detected = device
+            .device_id()
+            .and_then(|id| settings.device_keyboard_type(&id))
+            .unwrap_or_else(|| detect_keyboard_type_simple(device));
         if detected != KeyboardType::Unknown {
             return detected;
         }
@@ -104,73 +390,84 @@ fn resolve_keyboard_type(settings: &Settings, devices: &[KeyboardDeviceInfo]) ->
     KeyboardType::Unknown
 }
 
+/// Keyboard type of each device by name, with precedence:
+/// per-device setting > settings override > auto-detected. Devices that
+/// resolve to unknown are left out and use the default type.
 #[cfg(feature = "pure-rust")]
-fn default_compose_output(dir: &Path) -> PathBuf {
-    let base = dir.parent().unwrap_or_else(|| Path::new("."));
-    base.join("config.toml")
+fn resolve_device_keyboard_types(
+    settings: &Settings,
+    devices: &[KeyboardDeviceInfo],
+) -> HashMap<String, KeyboardType> {
+    devices
+        .iter()
+        .filter_map(|device| {
+            let kb_type = device
+                .device_id()
+                .and_then(|id| settings.device_keyboard_type(&id))
+                .or_else(|| settings.keyboard_override().and_then(KeyboardType::from_str))
+                .unwrap_or_else(|| detect_keyboard_type_simple(device));
+            (kb_type != KeyboardType::Unknown).then(|| (device.name.clone(), kb_type))
+        })
+        .collect()
 }
 
+/// Resolve the default and per-device keyboard types from the engine's
+/// settings and hand them to the engine; returns the default type
 #[cfg(feature = "pure-rust")]
-fn merge_table_entries(dst: &mut toml::map::Map<String, Value>, src: toml::map::Map<String, Value>) {
-    for (k, v) in src {
-        dst.insert(k, v);
+fn apply_keyboard_types(engine: &mut TransformEngine, devices: &[KeyboardDeviceInfo]) -> KeyboardType {
+    let settings = engine.settings();
+    let keyboard_type = resolve_keyboard_type(&settings, devices);
+    if keyboard_type == KeyboardType::Unknown {
+        engine.clear_keyboard_type();
+    } else {
+        engine.set_keyboard_type(keyboard_type);
     }
+    engine.set_device_keyboard_types(resolve_device_keyboard_types(&settings, devices));
+    keyboard_type
 }
 
+/// `vendor:product` id of the keyboard a `devices` subcommand names, by id,
+/// device name or /dev/input path
 #[cfg(feature = "pure-rust")]
-fn merge_modmap(root: &mut toml::map::Map<String, Value>, src: toml::map::Map<String, Value>) {
-    let modmap = root
-        .entry("modmap".to_string())
-        .or_insert_with(|| Value::Table(toml::map::Map::new()));
-    let modmap_tbl = modmap.as_table_mut().expect("modmap must be table");
+fn find_device_id(spec: &str, devices: &[DeviceInfo]) -> Result<String, String> {
+    if let Some((vendor_id, product_id)) = parse_device_id(spec) {
+        return Ok(format_device_id(vendor_id, product_id));
+    }
+    devices
+        .iter()
+        .find(|device| device.name == spec || device.path.as_deref() == Some(spec))
+        .or_else(|| devices.iter().find(|device| device.name.eq_ignore_ascii_case(spec)))
+        .map(|device| format_device_id(device.vendor_id, device.product_id))
+        .ok_or_else(|| format!("no keyboard named '{}' (see --list-devices)", spec))
+}
 
-    for (k, v) in src {
-        match (k.as_str(), v) {
-            ("default", Value::Table(default_src)) => {
-                let default_dst = modmap_tbl
-                    .entry("default".to_string())
-                    .or_insert_with(|| Value::Table(toml::map::Map::new()));
-                let default_tbl = default_dst.as_table_mut().expect("modmap.default must be table");
-                merge_table_entries(default_tbl, default_src);
-            }
-            ("conditionals", Value::Array(src_items)) => {
-                let cond_dst = modmap_tbl
-                    .entry("conditionals".to_string())
-                    .or_insert_with(|| Value::Array(Vec::new()));
-                let cond_array = cond_dst.as_array_mut().expect("modmap.conditionals must be array");
-                cond_array.extend(src_items);
-            }
-            (other, value) => {
-                modmap_tbl.insert(other.to_string(), value);
-            }
-        }
+#[cfg(feature = "pure-rust")]
+/// Seed the engine's lock state from the keyboard LEDs, which show the
+/// session's state even if it changed before startup or on another keyboard
+#[cfg(feature = "pure-rust")]
+fn sync_lock_state(event_loop: &dyn InputSource, engine: &mut TransformEngine) {
+    if let Some(locks) = event_loop.lock_state() {
+        log::debug!("Lock state from keyboard LEDs: {:?}", locks);
+        engine.set_lock_states(locks.num_lock, locks.caps_lock);
+        engine.follow_lock_leds(true);
     }
 }
 
+/// Follow an `EV_LED` event reported by a keyboard
 #[cfg(feature = "pure-rust")]
-fn merge_config_fragment(root: &mut toml::map::Map<String, Value>, fragment: toml::map::Map<String, Value>) {
-    for (k, v) in fragment {
-        match (k.as_str(), v) {
-            ("general", Value::Table(src)) | ("timeouts", Value::Table(src)) => {
-                let dst = root
-                    .entry(k.clone())
-                    .or_insert_with(|| Value::Table(toml::map::Map::new()));
-                let dst_tbl = dst.as_table_mut().expect("section must be table");
-                merge_table_entries(dst_tbl, src);
-            }
-            ("modmap", Value::Table(src)) => merge_modmap(root, src),
-            ("multipurpose", Value::Array(items)) | ("keymap", Value::Array(items)) => {
-                let dst = root
-                    .entry(k.clone())
-                    .or_insert_with(|| Value::Array(Vec::new()));
-                let dst_arr = dst.as_array_mut().expect("section must be array");
-                dst_arr.extend(items);
-            }
-            (_, value) => {
-                root.insert(k, value);
-            }
-        }
+fn apply_led_event(engine: &mut TransformEngine, code: u16, value: i32) {
+    let (num_lock, caps_lock) = engine.lock_states();
+    let mut locks = LockState { num_lock, caps_lock };
+    if locks.apply_led_event(code, value) {
+        log::debug!("Lock state changed: {:?}", locks);
+        engine.set_lock_states(locks.num_lock, locks.caps_lock);
     }
+    engine.follow_lock_leds(true);
+}
+
+fn default_compose_output(dir: &Path) -> PathBuf {
+    let base = dir.parent().unwrap_or_else(|| Path::new("."));
+    base.join("config.toml")
 }
 
 #[cfg(feature = "pure-rust")]
@@ -188,13 +485,8 @@ fn compose_config_dir(dir: &Path, output: &Path) -> Result<(), Box<dyn std::erro
     let mut root = toml::map::Map::new();
 
     for path in files {
-        let content = fs::read_to_string(&path)?;
-        let value: Value = toml::from_str(&content)
+        let table = read_fragment(&path)
             .map_err(|e| format!("Failed parsing {}: {}", path.display(), e))?;
-        let table = value
-            .as_table()
-            .ok_or_else(|| format!("Root must be TOML table: {}", path.display()))?
-            .clone();
         merge_config_fragment(&mut root, table);
     }
 
@@ -222,33 +514,21 @@ impl Application {
             config: Some(config),
             args,
             running: Arc::new(AtomicBool::new(true)),
+            shutdown: Waker::new()?,
         })
     }
 
-    /// Validate configuration
-    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // Check if config is valid
-        if let Some(ref config) = self.config {
-            let _transform_config = config.to_transform_config();
-            println!("Configuration is valid");
-        } else {
-            return Err("No configuration loaded".into());
-        }
-        Ok(())
-    }
-
     /// List available keyboard devices
     #[cfg(feature = "pure-rust")]
     fn list_devices() -> Result<(), Box<dyn std::error::Error>> {
-        use keyrs_core::event::EventLoop;
-
         match EventLoop::list_devices() {
             Ok(devices) => {
                 println!("Found {} keyboard device(s):", devices.len());
                 for device in &devices {
+                    let id = format_device_id(device.vendor_id, device.product_id);
                     match &device.path {
-                        Some(path) => println!("  {}: {} ({})", device.index, device.name, path),
-                        None => println!("  {}: {}", device.index, device.name),
+                        Some(path) => println!("  {}: {} [{}] ({})", device.index, device.name, id, path),
+                        None => println!("  {}: {} [{}]", device.index, device.name, id),
                     }
                 }
                 Ok(())
@@ -260,11 +540,179 @@ impl Application {
         }
     }
 
+    /// List key names and modifier aliases accepted in config files
+    #[cfg(feature = "pure-rust")]
+    fn list_keys() {
+        let key_names = keyrs_core::key::all_key_names();
+        println!("Key names ({}):", key_names.len());
+        for name in key_names {
+            println!("  {}", name);
+        }
+
+        let aliases = keyrs_core::Modifier::all_aliases();
+        println!("Modifier aliases ({}):", aliases.len());
+        for alias in aliases {
+            println!("  {}", alias);
+        }
+    }
+
+    /// Print the scancode and resulting key of every key event (read-only)
+    #[cfg(feature = "pure-rust")]
+    fn show_scancodes(devices: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        use evdev::{EventType, MiscType};
+        use std::collections::HashMap;
+
+        let mut event_loop = EventLoop::new_filtered(devices)?;
+        println!(
+            "Showing scancodes for {} device(s). Press keys; Ctrl+C to exit.",
+            event_loop.device_count()
+        );
+
+        // MSC_SCAN comes first in the frame; print it with the key event that follows
+        let mut last_scan: HashMap<String, u32> = HashMap::new();
+        loop {
+            let events = event_loop.poll_for_events_with_device(1000).unwrap_or_default();
+            for event in events {
+                let device = event.device_name;
+                match event.event.event_type() {
+                    EventType::MISC if event.event.code() == MiscType::MSC_SCAN.0 => {
+                        last_scan.insert(device, event.event.value() as u32);
+                    }
+                    EventType::KEY if event.event.value() != 2 => {
+                        let key = Key::from(event.event.code());
+                        let action = if event.event.value() == 1 { "press" } else { "release" };
+                        match last_scan.remove(&device) {
+                            Some(scancode) => {
+                                println!("[{}] scancode 0x{:x} -> {} {}", device, scancode, key, action)
+                            }
+                            None => println!("[{}] (no scancode) -> {} {}", device, key, action),
+                        }
+                    }
+                    EventType::SYNCHRONIZATION => {
+                        if let Some(scancode) = last_scan.remove(&device) {
+                            println!("[{}] scancode 0x{:x} -> (no key event)", device, scancode);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Devices to use: CLI --devices > config [devices].only > autodetect
+    #[cfg(feature = "pure-rust")]
+    fn device_filter(&self, config: &Config) -> Vec<String> {
+        if !self.args.devices.is_empty() {
+            self.args.devices.clone()
+        } else {
+            config.device_filter.clone()
+        }
+    }
+
+    #[cfg(feature = "pure-rust")]
+    fn helper_socket_path(&self) -> std::io::Result<PathBuf> {
+        let system = Path::new(SYSTEM_SOCKET_PATH);
+        match &self.args.helper_socket {
+            Some(path) => Ok(path.clone()),
+            None if self.args.helper_group.is_some() => Ok(system.to_path_buf()),
+            None if self.args.connect_helper && system.exists() => Ok(system.to_path_buf()),
+            None => InputHelper::default_path(),
+        }
+    }
+
+    /// Stop gracefully on SIGINT/SIGTERM/SIGQUIT/SIGABRT or a panic in any
+    /// thread, so the devices are always handed back
+    #[cfg(feature = "pure-rust")]
+    fn handle_signals(&self) {
+        use signal_hook::consts::{SIGABRT, SIGINT, SIGQUIT, SIGTERM};
+        use signal_hook::iterator::Signals;
+        let running = self.running.clone();
+        let shutdown = self.shutdown.clone();
+        stop_on_panic(running.clone(), shutdown.clone());
+
+        // Spawn a thread to handle signals
+        std::thread::spawn(move || {
+            if let Ok(mut signals) = Signals::new([SIGINT, SIGTERM, SIGQUIT, SIGABRT]) {
+                for signal in &mut signals {
+                    match signal {
+                        SIGINT | SIGTERM | SIGQUIT | SIGABRT => {
+                            log::warn!("Received signal, shutting down gracefully...");
+                            running.store(false, Ordering::SeqCst);
+                            shutdown.wake();
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+    }
+
+    /// Open and grab the devices to remap, with the config's device policies
+    #[cfg(feature = "pure-rust")]
+    fn grab_devices(
+        &self,
+        config: &Config,
+        device_filter: &[String],
+        session_monitor: Option<&mut SessionMonitor>,
+    ) -> Result<EventLoop, Box<dyn std::error::Error>> {
+        let mut event_loop = EventLoop::new_with_grab_after_release(
+            device_filter,
+            config.release_wait_ms.map(Duration::from_millis),
+        )?;
+        event_loop.set_internal_keyboard_policy(
+            config.internal_keyboard_policy,
+            &config.internal_keyboards,
+        );
+        event_loop.set_include_gamepads(config.include_gamepads);
+        event_loop.set_grab_policies(config.grab_policies.clone());
+        event_loop.set_regrab_departed(self.args.watch);
+
+        // On multi-seat systems, only the devices of one seat are ours
+        let seat = match &config.seat {
+            SeatFilter::Session => session_monitor
+                .and_then(|monitor| monitor.seat())
+                .or_else(|| std::env::var("XDG_SEAT").ok().filter(|seat| !seat.is_empty())),
+            SeatFilter::Named(seat) => Some(seat.clone()),
+            SeatFilter::Any => None,
+        };
+        if let Some(seat) = &seat {
+            log::info!("Using the devices of {}", seat);
+        }
+        event_loop.set_seat(seat);
+        Ok(event_loop)
+    }
+
+    /// Privileged half of privilege separation: grab the devices and own the
+    /// virtual devices for a remapping process started with --connect-helper
+    #[cfg(feature = "pure-rust")]
+    fn run_input_helper(&self, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        self.handle_signals();
+
+        let socket_path = self.helper_socket_path()?;
+        let access = match &self.args.helper_group {
+            Some(group) => HelperAccess::group(group).map_err(|e| format!("--helper-group {}: {}", group, e))?,
+            None => HelperAccess::Owner,
+        };
+        let mut event_loop = self.grab_devices(config, &self.device_filter(config), None)?;
+        let mut output_device = VirtualDevice::with_switches(&event_loop.supported_switches())?;
+        let mut helper = InputHelper::bind(&socket_path, access)?;
+        log::warn!(
+            "keyrs input helper serving {} device(s) on {}",
+            event_loop.device_count(),
+            helper.path().display()
+        );
+
+        helper.serve(&mut event_loop, &mut output_device, &self.running, &self.shutdown);
+
+        event_loop.ungrab_all();
+        output_device.close()?;
+        Ok(())
+    }
+
     /// Run the main event loop
     #[cfg(feature = "pure-rust")]
     fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
-        use keyrs_core::event::EventLoop;
-
         log::info!("Starting keyrs pure-rust binary");
         if let Some(ref config_path) = self.args.config {
             log::info!("Config: {}", config_path.display());
@@ -276,6 +724,30 @@ impl Application {
             .as_ref()
             .ok_or_else(|| Box::<dyn std::error::Error>::from("No configuration loaded"))?;
 
+        // One process grabs each device: a second one would fight over the
+        // grabs and double the output. With privilege separation that is
+        // the input helper; trace mode grabs nothing. When no keyboard is
+        // found, grabbing reports it below.
+        let _instance = if self.args.connect_helper || self.args.trace {
+            None
+        } else {
+            let devices = EventLoop::keyboard_paths(&self.device_filter(config)).unwrap_or_default();
+            match InstanceLock::acquire_devices(&devices) {
+                Ok(lock) => {
+                    log::debug!("Holding instance locks @{}", lock.names().join(", @"));
+                    Some(lock)
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        };
+
+        if self.args.input_helper {
+            return self.run_input_helper(config);
+        }
+
         // Create transform engine from config
         let transform_config = config.to_transform_config();
         
@@ -304,6 +776,24 @@ impl Application {
         
         let mut engine = TransformEngine::new(transform_config);
 
+        // Other [profiles] configs are parsed now, so switching is instant
+        let config_dir = self.args.config.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+        load_profiles(&mut engine, config, config_dir)?;
+        if engine.profile_names().len() > 1 {
+            log::info!("Profiles: {} (active: {})", engine.profile_names().join(", "), engine.profile());
+        }
+
+        if config.compose_enabled {
+            engine.set_compose_table(compose_table(config));
+        }
+
+        if config.metrics_enabled {
+            engine.enable_metrics();
+        }
+        if let Some(capacity) = config.audit_capacity {
+            engine.enable_audit(capacity);
+        }
+
         // Load settings from ~/.config/keyrs/settings.toml
         match Settings::load_default() {
             Ok(settings) => {
@@ -332,6 +822,27 @@ impl Application {
                 log::warn!("Could not load settings: {}", e);
             }
         }
+        // No private runtime directory, no daemon: the sockets must not land in /tmp
+        let control_path = ControlServer::default_path()?;
+        let mut control = match ControlServer::bind(&control_path) {
+            Ok(control) => {
+                log::info!("Control socket listening on {}", control_path.display());
+                Some(control)
+            }
+            Err(e) => {
+                log::warn!("Could not create control socket {}: {}", control_path.display(), e);
+                None
+            }
+        };
+        let settings_watcher = Settings::default_path().and_then(|path| {
+            match SettingsWatcher::new(&path) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    log::info!("Not watching {:?} for changes: {}", path, e);
+                    None
+                }
+            }
+        });
 
         // Set up window context provider for conditional keymaps
         let mut window_provider = WaylandContextProvider::new();
@@ -342,43 +853,51 @@ impl Application {
         }
         engine.set_window_manager(Some(Box::new(window_provider)));
 
-        // Set up signal handler for graceful shutdown
-        #[cfg(feature = "pure-rust")]
-        {
-            use signal_hook::iterator::Signals;
-            let running = self.running.clone();
-
-            // Spawn a thread to handle signals
-            std::thread::spawn(move || {
-                if let Ok(mut signals) =
-                    Signals::new(&[signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM])
-                {
-                    for signal in &mut signals {
-                        match signal {
-                            signal_hook::consts::SIGINT | signal_hook::consts::SIGTERM => {
-                                log::warn!("Received signal, shutting down gracefully...");
-                                running.store(false, Ordering::SeqCst);
-                                break;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            });
-        }
+        self.handle_signals();
 
         log::info!("Transform engine created");
 
-        // Resolve device filter precedence:
-        // CLI --devices > config [devices].only > autodetect.
-        let active_device_filter = if !self.args.devices.is_empty() {
-            self.args.devices.clone()
-        } else {
-            config.device_filter.clone()
-        };
+        let active_device_filter = self.device_filter(config);
+
+        // Trace mode reads devices without grabbing and never emits output.
+        if self.args.trace {
+            let mut event_loop = EventLoop::new_filtered(&active_device_filter)?;
+            event_loop.set_include_gamepads(config.include_gamepads);
+            sync_lock_state(&event_loop, &mut engine);
+            engine.set_trace(true);
+            return self.run_trace_loop(
+                &mut event_loop,
+                &mut engine,
+                ScancodeRemap::new(&config.scancodes),
+                LoopTiming::new(config),
+            );
+        }
 
-        // Create event loop with grab (prevents original events from reaching system)
-        let mut event_loop = EventLoop::new_with_grab_filtered(&active_device_filter)?;
+        let mut session_monitor = (config.pause_on_lock || config.pause_when_inactive).then(|| {
+            SessionMonitor::new(
+                Duration::from_millis(config.lock_poll_ms.unwrap_or(DEFAULT_LOCK_POLL_MS)),
+                config.pause_on_lock,
+                config.pause_when_inactive,
+            )
+        });
+
+        // With privilege separation the input helper owns the devices and the
+        // virtual devices; otherwise grab the devices here (prevents original
+        // events from reaching the system)
+        let (event_loop, mut output_device): (Box<dyn InputSource>, VirtualDevice) =
+            if self.args.connect_helper {
+                let path = self.helper_socket_path()?;
+                let input = RemoteInput::connect(&path)?;
+                log::info!("Reading input through the input helper at {}", path.display());
+                let output_device = VirtualDevice::remote(input.link());
+                (Box::new(input), output_device)
+            } else {
+                let event_loop =
+                    self.grab_devices(config, &active_device_filter, session_monitor.as_mut())?;
+                let output_device = VirtualDevice::with_switches(&event_loop.supported_switches())?;
+                (Box::new(event_loop), output_device)
+            };
+        sync_lock_state(event_loop.as_ref(), &mut engine);
 
         log::info!(
             "Event loop created with {} device(s)",
@@ -390,14 +909,9 @@ impl Application {
 
         // Resolve keyboard type with precedence:
         // settings override > auto-detected from active devices > unknown.
+        // Each device also gets its own type, used for its events.
         let detection_infos = event_loop.keyboard_detection_infos();
-        let settings_for_kb = engine.settings();
-        let keyboard_type = resolve_keyboard_type(&settings_for_kb, &detection_infos);
-        if keyboard_type == KeyboardType::Unknown {
-            engine.clear_keyboard_type();
-        } else {
-            engine.set_keyboard_type(keyboard_type);
-        }
+        let keyboard_type = apply_keyboard_types(&mut engine, &detection_infos);
         log::info!("Keyboard type resolved: {}", keyboard_type.as_str());
         if keyboard_type == KeyboardType::Unknown {
             for info in &detection_infos {
@@ -408,177 +922,658 @@ impl Application {
             }
         }
 
-        // Create virtual uinput device
-        let mut output_device = VirtualDevice::new()?;
         output_device.set_throttle_delays(
             config.key_pre_delay_ms.unwrap_or(0),
             config.key_post_delay_ms.unwrap_or(0),
         );
+        output_device.set_combo_delay(config.combo_delay_ms.unwrap_or(0));
+
+        // Output mirror subscriptions arrive on the control socket
+        if config.observer_enabled {
+            match control.as_mut() {
+                Some(control) => {
+                    let observers = ObserverHub::new(config.observer_privacy);
+                    control.set_observers(observers.clone());
+                    output_device.set_observer(Some(observers));
+                    log::info!("Output mirror: subscribe on {}", control.path().display());
+                }
+                None => log::warn!("Output mirror unavailable without the control socket"),
+            }
+        }
+
+        if config.unicode_backend != UnicodeBackend::Compose {
+            match output_device.set_unicode_backend(config.unicode_backend) {
+                Ok(()) => log::info!("Unicode output via Wayland virtual keyboard"),
+                Err(e) => log::warn!("Unicode backend unavailable, using Ctrl+Shift+U: {}", e),
+            }
+        }
+
+        output_device.set_unicode_batch(config.unicode_batch, config.unicode_batch_min, config.paste_combo.clone());
+        output_device.set_remap_repeat(config.remap_repeat);
+        output_device.set_stuck_key_timeout(config.stuck_key_timeout.map(Duration::from_millis));
+
+        if let Some(layout) = &config.layout {
+            log::info!("Typing text for keyboard layout '{}'", layout.name());
+            output_device.set_layout(Some(layout.clone()));
+        }
+
+        let metrics_server = config.metrics_listen.and_then(|addr| match MetricsServer::bind(addr) {
+            Ok(server) => {
+                log::info!("Metrics endpoint listening on http://{}/metrics", addr);
+                Some(server)
+            }
+            Err(e) => {
+                log::warn!("Could not serve metrics on {}: {}", addr, e);
+                None
+            }
+        });
 
         log::info!("Virtual uinput device created");
         log::debug!(
-            "Throttle delays: pre={}ms post={}ms",
+            "Throttle delays: pre={}ms post={}ms combo={}ms",
             config.key_pre_delay_ms.unwrap_or(0),
-            config.key_post_delay_ms.unwrap_or(0)
+            config.key_post_delay_ms.unwrap_or(0),
+            config.combo_delay_ms.unwrap_or(0)
+        );
+
+        if !config.panic_chord.is_empty() {
+            log::info!(
+                "Panic chord: hold {} for {}ms to release all keys and exit",
+                config
+                    .panic_chord
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join("+"),
+                config.panic_hold_ms.unwrap_or(DEFAULT_PANIC_HOLD_MS)
+            );
+        }
+        let panic_chord = PanicChord::new(
+            config.panic_chord.clone(),
+            Duration::from_millis(config.panic_hold_ms.unwrap_or(DEFAULT_PANIC_HOLD_MS)),
         );
 
+        // Output runs on its own thread so Delay()/Text() don't stall input.
+        // From here on the guard ungrabs and releases every key however the
+        // loop ends, a panic included.
+        let mut devices = DeviceGuard::new(event_loop, OutputThread::spawn(output_device)?);
+        let (event_loop, output_thread) = devices.parts();
+
+        let mut status = StatusPublisher::new(DaemonStatus::default_path());
+        if let Some(path) = status.path() {
+            log::info!("Publishing daemon state to {}", path.display());
+        }
+
         // Run main loop
         let result = self.run_main_loop(
-            &mut event_loop,
+            event_loop,
             &mut engine,
-            &mut output_device,
-            config.diagnostics_key,
-            config.emergency_eject_key,
-            config.poll_timeout_ms.unwrap_or(100) as i32,
-            config.window_update_interval_ms.unwrap_or(500),
-            config.idle_sleep_ms.unwrap_or(10),
+            MainLoop {
+                output_thread,
+                metrics_server: metrics_server.as_ref(),
+                session_monitor: session_monitor.as_mut(),
+                status: &mut status,
+                panic_chord,
+                scancodes: ScancodeRemap::new(&config.scancodes),
+                settings_watcher,
+                control,
+                mirror_lock_leds: config.mirror_lock_leds,
+                diagnostics_key: config.diagnostics_key,
+                emergency_eject_key: config.emergency_eject_key,
+                timing: LoopTiming::new(config),
+            },
         );
 
         // Cleanup: ungrab devices and release keys
-        event_loop.ungrab_all();
-        let _ = output_device.release_all();
-        output_device.close()?;
+        devices.finish()?;
+        status.publish(&DaemonStatus {
+            config: self.args.config.clone(),
+            ..DaemonStatus::default()
+        });
 
         result
     }
 
-    /// Run the main event processing loop
+    /// Live state answered to `keyrs status`
     #[cfg(feature = "pure-rust")]
-    fn run_main_loop(
+    fn live_status(&self, engine: &TransformEngine, input: &dyn InputSource) -> LiveStatus {
+        LiveStatus {
+            daemon: DaemonStatus {
+                enabled: true,
+                suspended: engine.is_suspended(),
+                config: self.args.config.clone(),
+                devices: input.device_names(),
+            },
+            keyboard_type: engine.keyboard_type().map(|kb| kb.as_str().to_string()),
+            wm_class: engine.window_class(),
+            dead_key: engine.pending_dead_key(),
+            profile: Some(engine.profile().to_string()),
+        }
+    }
+
+    /// Release every virtual key and hand the keyboards back, then stop
+    #[cfg(feature = "pure-rust")]
+    fn panic_exit(&self, event_loop: &mut dyn InputSource, output_thread: &OutputThread) {
+        log::error!("Panic chord held. Releasing all keys, ungrabbing devices and exiting.");
+        // Out of band: queued output may be what's stuck
+        output_thread.abort();
+        event_loop.ungrab_all();
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    /// Print every key event with the combo matching decision path (no remapping)
+    #[cfg(feature = "pure-rust")]
+    fn run_trace_loop(
         &self,
         event_loop: &mut keyrs_core::event::EventLoop,
         engine: &mut TransformEngine,
-        output_device: &mut VirtualDevice,
-        diagnostics_key: Option<Key>,
-        emergency_eject_key: Option<Key>,
-        poll_timeout_ms: i32,
-        window_update_interval_ms: u64,
-        idle_sleep_ms: u64,
+        mut scancodes: ScancodeRemap,
+        timing: LoopTiming,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        use evdev::EventType;
+        use evdev::{EventType, MiscType};
         use keyrs_core::Action;
+        use std::os::fd::AsRawFd;
 
-        log::warn!("keyrs is running. Press Ctrl+C to exit.");
-
-        // Timestamp for periodic window context updates
-        let mut last_window_update = Instant::now();
+        println!(
+            "Tracing {} device(s) read-only. Press Ctrl+C to exit.",
+            event_loop.device_count()
+        );
+        let timer = Timer::new()?;
+        event_loop.set_watched_fds(&[self.shutdown.as_raw_fd(), timer.as_raw_fd()]);
 
         while self.running.load(Ordering::SeqCst) {
-            // Poll for events with configurable timeout
-            match event_loop.poll_for_events_with_device(poll_timeout_ms) {
-                Ok(events) => {
-                    for event in events {
-                        engine.set_device_name(Some(event.device_name.clone()));
-
-                        // Only process key events
-                        if event.event.event_type() == EventType::KEY {
-                            let key_code = event.event.code() as u16;
-                            let value = event.event.value();
-
-                            // Convert evdev value to Action
-                            let action = match value {
-                                0 => Action::Release,
-                                1 => Action::Press,
-                                2 => Action::Repeat,
-                                _ => continue,
-                            };
-
-                            // Process event through transform engine
-                            let key = Key::from(key_code);
-
-                            // Emergency eject key: immediate stop for recovery.
-                            if Some(key) == emergency_eject_key && action == Action::Press {
-                                log::error!("Emergency eject key pressed. Stopping keyrs.");
-                                self.running.store(false, Ordering::SeqCst);
-                                continue;
-                            }
+            timer.arm(engine.next_multipurpose_timeout_in())?;
+            let events = match event_loop.wait_for_events(timing.poll_timeout) {
+                Ok(events) => events,
+                Err(e) => {
+                    // Back off instead of spinning on a persistent error
+                    log::warn!("Waiting for input failed: {}", e);
+                    std::thread::sleep(timing.idle_sleep);
+                    Vec::new()
+                }
+            };
+            timer.drain();
+
+            for event in events {
+                match event.event.event_type() {
+                    EventType::MISC if event.event.code() == MiscType::MSC_SCAN.0 => {
+                        scancodes.observe_scan(&event.device_name, event.event.value() as u32);
+                        continue;
+                    }
+                    EventType::SYNCHRONIZATION => {
+                        scancodes.end_frame(&event.device_name);
+                        continue;
+                    }
+                    EventType::LED => {
+                        apply_led_event(engine, event.event.code(), event.event.value());
+                        continue;
+                    }
+                    EventType::KEY => {}
+                    _ => continue,
+                }
+                let action = match event.event.value() {
+                    0 => Action::Release,
+                    1 => Action::Press,
+                    2 => Action::Repeat,
+                    _ => continue,
+                };
+                let raw_key = Key::from(event.event.code());
+                let key = scancodes.remap(&event.device_name, raw_key);
+                if key != raw_key {
+                    println!("[{}] scancode remap {:?} -> {:?}", event.device_name, raw_key, key);
+                }
 
-                            // Diagnostics key: print current context and continue.
-                            if Some(key) == diagnostics_key && action == Action::Press {
-                                log::warn!("Diagnostics key pressed:");
-                                engine.print_window_context();
-                                continue;
-                            }
+                let (window_changed, _) = engine.update_from_window_manager();
+                if window_changed {
+                    let (keymaps, modmaps, layers) = engine.active_binding_names();
+                    println!("window changed; active keymaps {:?}, modmaps {:?}, layers {:?}", keymaps, modmaps, layers);
+                }
+                engine.set_device_name(Some(event.device_name.clone()));
+                let result = engine.process_event(key, action);
 
-                            let result = engine.process_event(key, action);
+                println!("[{}] {:?} {:?} -> {:?}", event.device_name, key, action, result);
+                for (deferred, deferred_action) in engine.take_deferred_results() {
+                    println!("    deferred: {:?} {:?}", deferred, deferred_action);
+                }
+                for (pending_key, pending_action) in engine.take_pending_outputs() {
+                    println!("    pending: {:?} {:?}", pending_key, pending_action);
+                }
+                for step in engine.take_trace() {
+                    println!("    {}", step);
+                }
+            }
 
-                            // Log the result if verbose
-                            log::debug!("Event: {:?} {:?} -> {:?}", key, action, result);
+            if let Some((key, action)) = engine.check_multipurpose_timeouts() {
+                println!("timeout -> {:?} {:?}", key, action);
+            }
+        }
+        Ok(())
+    }
 
-                            // Convert to output format and send to uinput device
-                            let output = TransformResultOutput::from_transform_result(&result);
-                            if let Err(e) = output_device.process_transform_result(&output, action) {
-                                log::error!("Error sending output: {}", e);
-                            }
+    /// Run the main event processing loop
+    #[cfg(feature = "pure-rust")]
+    fn run_main_loop(
+        &self,
+        event_loop: &mut dyn InputSource,
+        engine: &mut TransformEngine,
+        main: MainLoop<'_>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use evdev::{EventType, MiscType};
+        use keyrs_core::Action;
+        use std::os::fd::AsRawFd;
+
+        let MainLoop {
+            output_thread,
+            metrics_server,
+            mut session_monitor,
+            status,
+            mut panic_chord,
+            mut scancodes,
+            mut settings_watcher,
+            mut control,
+            mirror_lock_leds,
+            diagnostics_key,
+            emergency_eject_key,
+            timing,
+        } = main;
+
+        log::warn!("keyrs is running. Press Ctrl+C to exit.");
+
+        // Focus changes wake the loop; otherwise the window is polled
+        let waker = event_loop.waker();
+        let window_events = engine.set_window_change_notifier(Arc::new(move || waker.wake()));
+
+        // Fires exactly when a pending tap/hold or tap-dance decision times out
+        let multipurpose_timer = Timer::new()?;
+
+        // Timestamp for periodic window context updates
+        let mut last_window_update = Instant::now();
+
+        // Lock state last shown on the keyboards' LEDs, and for how many devices
+        let mut shown_locks: Option<(LockState, usize)> = None;
+
+        // Device count the keyboard types were resolved for
+        let mut typed_devices = event_loop.device_count();
+
+        // Suspend state and device count last published to status readers
+        let mut published: Option<(bool, usize)> = None;
+
+        // Profile the output delays were last set for
+        let mut delays_profile = engine.profile().to_string();
+
+        while self.running.load(Ordering::SeqCst) {
+            // A chord held without key repeats fires when its deadline comes up
+            if panic_chord.triggered() {
+                self.panic_exit(event_loop, output_thread);
+                break;
+            }
+
+            // Keys pass through untouched while the session is locked, so
+            // remapping can't interfere with password/PIN entry.
+            // While another session is in the foreground (fast user switching),
+            // devices are released to it and nothing is read or emitted.
+            if let Some(monitor) = session_monitor.as_mut() {
+                let before = monitor.state();
+                if let Some(state) = monitor.poll() {
+                    if state.locked != before.locked {
+                        if state.locked {
+                            log::info!("Session locked: passing keys through until unlock");
+                        } else {
+                            log::info!("Session unlocked: remapping resumed");
                         }
                     }
-                    
-                    // Check for multipurpose timeouts after processing events
-                    // This handles the case where a key is held longer than the timeout
-                    if let Some((hold_key, action)) = engine.check_multipurpose_timeouts() {
-                        log::debug!("Multipurpose timeout: {:?} {:?}", hold_key, action);
-                        let result = TransformResult::Remapped(hold_key);
-                        let output = TransformResultOutput::from_transform_result(&result);
-                        if let Err(e) = output_device.process_transform_result(&output, action) {
-                            log::error!("Error sending output: {}", e);
+                    if state.active != before.active {
+                        event_loop.set_paused(!state.active);
+                        if state.active {
+                            log::info!("Session active again: devices grabbed, remapping resumed");
+                        } else {
+                            log::info!("Session inactive: devices released until it is active again");
                         }
                     }
-                    
-                    // Update window context periodically.
-                    if last_window_update.elapsed() >= Duration::from_millis(window_update_interval_ms) {
-                        last_window_update = Instant::now();
-                        let (changed, hold_key_to_release) = engine.update_from_window_manager();
-                        if changed {
-                            log::debug!("Window context updated");
-                            // Always print window info for debugging
-                            engine.print_window_context();
-                            
-                            // Release any hold key that was active when window changed
-                            if let Some(hold_key) = hold_key_to_release {
-                                log::debug!("Releasing multipurpose hold key on window change: {:?}", hold_key);
-                                let result = TransformResult::Remapped(hold_key);
-                                let output = TransformResultOutput::from_transform_result(&result);
-                                if let Err(e) = output_device.process_transform_result(&output, Action::Release) {
-                                    log::error!("Error releasing hold key: {}", e);
-                                }
-                            }
+                    // Nothing in flight may survive the transition
+                    engine.clear();
+                    output_thread.cancel_scheduled();
+                    output_thread.release_all();
+                }
+            }
+            let session_locked = session_monitor.as_ref().is_some_and(|m| m.is_locked());
+
+            // settings.toml edited on disk (e.g. from the TUI) applies between events;
+            // held keys keep the binding they were pressed with.
+            if settings_watcher.as_mut().is_some_and(|watcher| watcher.changed()) {
+                match engine.reload_settings() {
+                    Ok(()) => {
+                        log::info!("Reloaded settings from {:?}", Settings::default_path());
+                        apply_keyboard_types(engine, &event_loop.keyboard_detection_infos());
+                        typed_devices = event_loop.device_count();
+                        if let Some(delays) = engine.take_output_delays() {
+                            output_thread.set_delays(delays);
                         }
                     }
+                    Err(e) => log::warn!("Keeping previous settings, reload failed: {}", e),
                 }
-                Err(_e) => {
-                    // No events available, check timeouts anyway (for held keys)
-                    if let Some((hold_key, action)) = engine.check_multipurpose_timeouts() {
-                        log::debug!("Multipurpose timeout (no events): {:?} {:?}", hold_key, action);
-                        let result = TransformResult::Remapped(hold_key);
-                        let output = TransformResultOutput::from_transform_result(&result);
-                        if let Err(e) = output_device.process_transform_result(&output, action) {
-                            log::error!("Error sending output: {}", e);
+            }
+
+            // Control clients (keyrs-tui) read and change live settings between events
+            if let Some(control) = control.as_mut() {
+                control.poll(|request| match request {
+                    ControlRequest::List => Ok(ControlReply::Settings(
+                        engine.settings().values().into_iter().collect(),
+                    )),
+                    ControlRequest::Get(name) => {
+                        let value = engine.get_setting(&name);
+                        Ok(BTreeMap::from([(name, value)]).into())
+                    }
+                    ControlRequest::Set(name, value) => {
+                        log::info!("Control: {} = {}", name, value);
+                        engine.set_setting(&name, value);
+                        if let Some(delays) = engine.take_output_delays() {
+                            output_thread.set_delays(delays);
+                        }
+                        Ok(BTreeMap::from([(name, value)]).into())
+                    }
+                    ControlRequest::Status => Ok(ControlReply::Status(self.live_status(engine, event_loop))),
+                    ControlRequest::Audit => engine
+                        .audit_dump()
+                        .map(ControlReply::Audit)
+                        .ok_or_else(|| "the audit log is off; enable it with [audit]".to_string()),
+                    ControlRequest::Profile(name) => {
+                        if !engine.switch_profile(name.as_deref()) {
+                            return Err(format!(
+                                "unknown profile '{}' (profiles: {})",
+                                name.unwrap_or_default(),
+                                engine.profile_names().join(", ")
+                            ));
                         }
+                        log::info!("Control: profile {}", engine.profile());
+                        for (result, action) in engine.take_deferred_results() {
+                            output_thread.emit(TransformResultOutput::from_transform_result(&result), action);
+                        }
+                        delays_profile = engine.profile().to_string();
+                        if let Some(delays) = engine.take_output_delays() {
+                            output_thread.set_delays(delays);
+                        }
+                        Ok(ControlReply::Status(self.live_status(engine, event_loop)))
+                    }
+                    // Scripted input shares the output thread, so it is ordered
+                    // with remapped output and sees the same held modifiers
+                    ControlRequest::Type(text) => {
+                        output_thread.emit(Injection::Text(text).to_output(), Action::Press);
+                        Ok(ControlReply::Settings(BTreeMap::new()))
+                    }
+                    ControlRequest::Key(spec) => {
+                        let injection = Injection::parse_key(&spec)?;
+                        output_thread.emit(injection.to_output(), Action::Press);
+                        Ok(ControlReply::Settings(BTreeMap::new()))
+                    }
+                });
+            }
+
+            // Sleep until input, a control/metrics request, a settings edit or
+            // a window change arrives, or the next timed decision is due (the
+            // output thread times After(...) steps itself)
+            let window_due = if window_events && engine.window_manager_connected() {
+                None
+            } else {
+                Some(timing.window_update_interval.saturating_sub(last_window_update.elapsed()))
+            };
+            let timeout = [
+                engine.next_synthetic_repeat_in(),
+                engine.next_layer_rollover_in(),
+                engine.next_autoshift_in(),
+                engine.next_tap_hold_in(),
+                engine.next_dead_key_timeout_in(),
+                panic_chord.fires_in(),
+                session_monitor.as_ref().map(|monitor| monitor.next_poll_in()),
+                window_due,
+                timing.poll_timeout,
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+
+            multipurpose_timer.arm(engine.next_multipurpose_timeout_in())?;
+
+            let mut watched = vec![self.shutdown.as_raw_fd(), multipurpose_timer.as_raw_fd()];
+            if let Some(control) = control.as_ref() {
+                watched.extend(control.fds());
+            }
+            if let Some(watcher) = settings_watcher.as_ref() {
+                watched.push(watcher.as_raw_fd());
+            }
+            if let (Some(server), Some(_)) = (metrics_server, engine.metrics()) {
+                watched.push(server.as_raw_fd());
+            }
+            event_loop.set_watched_fds(&watched);
+
+            let events = match event_loop.wait_for_events(timeout) {
+                Ok(events) => events,
+                // Nothing can be read or written without the helper
+                Err(e @ EventLoopError::HelperDisconnected(_)) => return Err(e.into()),
+                Err(e) => {
+                    // Back off instead of spinning on a persistent error
+                    log::warn!("Waiting for input failed: {}", e);
+                    std::thread::sleep(timing.idle_sleep);
+                    Vec::new()
+                }
+            };
+            multipurpose_timer.drain();
+            // Scancodes, switches and pointer motion of keys-only devices
+            // go straight back out, ahead of the keys they came with
+            for frame in event_loop.take_forwarded() {
+                output_thread.forward(frame);
+            }
+            for event in events {
+                engine.set_device_name(Some(event.device_name.clone()));
+
+                // [scancodes] rewrites the key event that follows an MSC_SCAN in the same frame
+                match event.event.event_type() {
+                    EventType::MISC if event.event.code() == MiscType::MSC_SCAN.0 => {
+                        scancodes.observe_scan(&event.device_name, event.event.value() as u32);
+                    }
+                    EventType::SYNCHRONIZATION => scancodes.end_frame(&event.device_name),
+                    EventType::LED => {
+                        apply_led_event(engine, event.event.code(), event.event.value())
+                    }
+                    _ => {}
+                }
+
+                // Only process key events
+                if event.event.event_type() == EventType::KEY {
+                    let key_code = event.event.code();
+                    let value = event.event.value();
+
+                    // Convert evdev value to Action
+                    let action = match value {
+                        0 => Action::Release,
+                        1 => Action::Press,
+                        2 => Action::Repeat,
+                        _ => continue,
+                    };
+
+                    // Process event through transform engine
+                    let key = scancodes.remap(&event.device_name, Key::from(key_code));
+
+                    // The panic chord sees raw events before anything can remap or swallow them
+                    panic_chord.observe(Key::from(key_code), action);
+                    if panic_chord.triggered() {
+                        self.panic_exit(event_loop, output_thread);
+                        return Ok(());
                     }
-                    
-                    // Update window context periodically even when no events.
-                    if last_window_update.elapsed() >= Duration::from_millis(window_update_interval_ms) {
-                        last_window_update = Instant::now();
-                        let (changed, hold_key_to_release) = engine.update_from_window_manager();
-                        if changed {
-                            log::debug!("Window context updated (no events)");
-                            engine.print_window_context();
-                            
-                            // Release any hold key that was active when window changed
-                            if let Some(hold_key) = hold_key_to_release {
-                                log::debug!("Releasing multipurpose hold key on window change: {:?}", hold_key);
-                                let result = TransformResult::Remapped(hold_key);
-                                let output = TransformResultOutput::from_transform_result(&result);
-                                if let Err(e) = output_device.process_transform_result(&output, Action::Release) {
-                                    log::error!("Error releasing hold key: {}", e);
-                                }
+
+                    // Emergency eject key: immediate stop for recovery.
+                    if Some(key) == emergency_eject_key && action == Action::Press {
+                        log::error!("Emergency eject key pressed. Stopping keyrs.");
+                        self.running.store(false, Ordering::SeqCst);
+                        continue;
+                    }
+
+                    if session_locked {
+                        let output = TransformResultOutput::Passthrough(key);
+                        output_thread.emit(output, action);
+                        continue;
+                    }
+
+                    // Diagnostics key: print current context and continue.
+                    if Some(key) == diagnostics_key && action == Action::Press {
+                        log::warn!("Diagnostics key pressed:");
+                        engine.print_window_context();
+                        if let Some(hint) = engine.pending_escape() {
+                            log::warn!("Escape armed: {}", hint);
+                        }
+                        for line in engine.render_stats(STATS_TOP_COMBOS).lines() {
+                            log::warn!("{}", line);
+                        }
+                        if let Some(dump) = engine.audit_dump() {
+                            match AuditDump::default_path().and_then(|path| dump.write(&path).map(|()| path)) {
+                                Ok(path) => log::warn!("Audit log ({} entries) written to {}", dump.entries.len(), path.display()),
+                                Err(e) => log::warn!("Could not write audit log: {}", e),
                             }
                         }
+                        continue;
+                    }
+
+                    let result = engine.process_event(key, action);
+
+                    // Log the result if verbose
+                    log::debug!("Event: {:?} {:?} -> {:?}", key, action, result);
+
+                    // Emit outputs resolved by this event (e.g. a layer trigger's
+                    // roll-over, an interrupted tap-dance) before the event's own result.
+                    for (deferred, deferred_action) in engine.take_deferred_results() {
+                        let output = TransformResultOutput::from_transform_result(&deferred);
+                        output_thread.emit(output, deferred_action);
+                    }
+                    for (pending_key, pending_action) in engine.take_pending_outputs() {
+                        let pending = TransformResult::Remapped(pending_key);
+                        let output = TransformResultOutput::from_transform_result(&pending);
+                        output_thread.emit(output, pending_action);
+                    }
+
+                    // Convert to output format and send to uinput device,
+                    // in the combo style of the keymap that produced it
+                    if let Some(style) = engine.take_combo_style() {
+                        output_thread.set_combo_style(style);
+                    }
+                    let output = TransformResultOutput::from_transform_result(&result);
+                    output_thread.emit(output, action);
+
+                    // A `Profile(...)` mapping brings that profile's [delays]
+                    if engine.profile() != delays_profile {
+                        delays_profile = engine.profile().to_string();
+                        if let Some(delays) = engine.take_output_delays() {
+                            output_thread.set_delays(delays);
+                        }
+                    }
+                }
+            }
+
+            // A multipurpose key held past its timeout becomes its hold key
+            if let Some((hold_key, action)) = engine.check_multipurpose_timeouts() {
+                log::debug!("Multipurpose timeout: {:?} {:?}", hold_key, action);
+                let result = TransformResult::Remapped(hold_key);
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, action);
+            }
+
+            // Update window context on a reported focus change, or periodically
+            // when the window manager can't report changes.
+            if engine.window_changed() || last_window_update.elapsed() >= timing.window_update_interval {
+                last_window_update = Instant::now();
+                let (changed, hold_key_to_release) = engine.update_from_window_manager();
+                if changed {
+                    log::debug!("Window context updated");
+                    output_thread.cancel_scheduled();
+                    // Always print window info for debugging
+                    engine.print_window_context();
+
+                    // Release any hold key that was active when window changed
+                    if let Some(hold_key) = hold_key_to_release {
+                        log::debug!("Releasing multipurpose hold key on window change: {:?}", hold_key);
+                        let result = TransformResult::Remapped(hold_key);
+                        let output = TransformResultOutput::from_transform_result(&result);
+                        output_thread.emit(output, Action::Release);
+                    }
+
+                    // Entering a `suspend_when` window releases every held key
+                    for (result, action) in engine.check_window_suspend() {
+                        let output = TransformResultOutput::from_transform_result(&result);
+                        output_thread.emit(output, action);
+                    }
+
+                    // Per-application `[[delays.overrides]]`
+                    if let Some(delays) = engine.take_output_delays() {
+                        log::debug!("Output delays for this window: {:?}", delays);
+                        output_thread.set_delays(delays);
                     }
-                    
-                    std::thread::sleep(Duration::from_millis(idle_sleep_ms));
                 }
             }
+
+            // A layer trigger held past its roll-over window resolves as a layer hold
+            for (result, action) in engine.check_layer_rollover() {
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, action);
+            }
+
+            // Hotplugged keyboards get their own keyboard type
+            if event_loop.device_count() != typed_devices {
+                apply_keyboard_types(engine, &event_loop.keyboard_detection_infos());
+                typed_devices = event_loop.device_count();
+            }
+
+            // Keep every keyboard's lock LEDs (hotplugged ones included) in step
+            if mirror_lock_leds {
+                let (num_lock, caps_lock) = engine.lock_states();
+                let shown = (LockState { num_lock, caps_lock }, event_loop.device_count());
+                if shown_locks != Some(shown) {
+                    event_loop.set_lock_leds(shown.0);
+                    shown_locks = Some(shown);
+                }
+            }
+
+            // An autoshift key held past its timeout types its shifted form
+            for (result, action) in engine.check_autoshift() {
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, action);
+            }
+
+            // A keymap tap/hold mapping held past the multipurpose timeout emits its hold
+            for (result, action) in engine.check_tap_hold() {
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, action);
+            }
+
+            // A dead key left pending past `[timeouts] dead_key` types its accent
+            if let Some(result) = engine.check_dead_key_timeout() {
+                log::debug!("Dead key timed out: {:?}", result);
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, Action::Press);
+            }
+
+            // Held mappings with `repeat = {...}` re-emit their output on a timer
+            if let Some(result) = engine.check_synthetic_repeat() {
+                let output = TransformResultOutput::from_transform_result(&result);
+                output_thread.emit(output, Action::Press);
+            }
+
+            // Status bars and the TUI follow suspend toggles and hotplugged devices
+            let current = (engine.is_suspended(), event_loop.device_count());
+            if published != Some(current) {
+                if published.is_some_and(|(suspended, _)| suspended != current.0) {
+                    log::info!("Suspend mode {}", if current.0 { "on" } else { "off" });
+                }
+                status.publish(&DaemonStatus {
+                    enabled: true,
+                    suspended: current.0,
+                    config: self.args.config.clone(),
+                    devices: event_loop.device_names(),
+                });
+                published = Some(current);
+            }
+
+            if let (Some(server), Some(metrics)) = (metrics_server, engine.metrics()) {
+                server.serve(metrics);
+            }
         }
         Ok(())
     }
@@ -589,21 +1584,258 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
     // Initialize logger
-    // Default: WARN level (quiet daemon)
-    // --verbose: INFO level
-    // KEYRS_LOG env var overrides everything
-    #[cfg(feature = "pure-rust")]
-    {
-        let mut builder = env_logger::Builder::new();
-        builder.filter_level(if args.verbose {
+    // Default: WARN level (quiet daemon), --verbose: INFO level.
+    // [logging] in the config refines this; KEYRS_LOG and --log override both.
+    let logging = Logging::init(
+        if args.verbose {
             log::LevelFilter::Info
         } else {
             log::LevelFilter::Warn
-        });
-        if std::env::var("KEYRS_LOG").is_ok() {
-            builder.parse_filters(&std::env::var("KEYRS_LOG").unwrap());
+        },
+        args.log.as_deref(),
+        args.log_format,
+    )?;
+
+    // Query the running daemon and exit (does not require config). A bar
+    // module still gets valid output when no daemon is running.
+    if let Some(Command::Status { format }) = args.command {
+        let status = ControlServer::default_path()
+            .and_then(|path| ControlClient::connect(&path))
+            .and_then(|mut control| control.status());
+        if let Err(e) = &status {
+            log::info!("No daemon status: {}", e);
+        }
+        println!("{}", LiveStatus::render(status.as_ref().ok(), format));
+        return Ok(());
+    }
+
+    // Switch the running daemon's profile (does not require config)
+    if let Some(Command::Profile { name }) = &args.command {
+        let status = ControlServer::default_path()
+            .and_then(|path| ControlClient::connect(&path))
+            .and_then(|mut control| control.profile(name.as_deref()))
+            .map_err(|e| format!("Could not switch profile: {}", e))?;
+        println!("profile: {}", status.profile.unwrap_or_default());
+        return Ok(());
+    }
+
+    // The running daemon's audit log (does not require config)
+    if let Some(Command::Audit) = args.command {
+        let dump = ControlServer::default_path()
+            .and_then(|path| ControlClient::connect(&path))
+            .and_then(|mut control| control.audit())
+            .map_err(|e| format!("Could not read the audit log: {}", e))?;
+        println!("{}", dump.to_json());
+        return Ok(());
+    }
+
+    // Reproduce an audit dump against the config (--config defaults to ~/.config/keyrs/config.toml)
+    if let Some(Command::Replay { dump }) = &args.command {
+        let config_path = match &args.config {
+            Some(path) => path.clone(),
+            None => keyrs_core::systemd::default_config_path()
+                .ok_or("cannot determine the user config directory")?,
+        };
+        let dump = AuditDump::read(dump).map_err(|e| format!("{}: {}", dump.display(), e))?;
+        let config = Config::from_toml_path(&config_path)
+            .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        let mut settings = Settings::new();
+        for (name, value) in &dump.settings {
+            settings.set_bool(name, *value);
+        }
+        let clock = ManualClock::new();
+        let mut engine = TransformEngine::with_clock(config.to_transform_config(), settings, Arc::new(clock.clone()));
+        load_profiles(&mut engine, &config, config_path.parent().unwrap_or(Path::new(".")))?;
+        if !engine.switch_profile(Some(&dump.profile)) {
+            eprintln!("Profile '{}' is not in this config; replaying with '{}'", dump.profile, engine.profile());
+        }
+        if config.compose_enabled {
+            engine.set_compose_table(compose_table(&config));
+        }
+        if let Some(kb_type) = dump.keyboard_type.as_deref().and_then(KeyboardType::from_str) {
+            engine.set_keyboard_type(kb_type);
+        }
+
+        let replayed = replay(&mut engine, &clock, &dump)?;
+        for entry in &replayed {
+            println!("{}", entry);
+        }
+        match first_divergence(&dump.entries, &replayed) {
+            None => println!("Replay matches the dump ({} entries)", dump.entries.len()),
+            Some(index) => {
+                let line = |entry: Option<&keyrs_core::audit::AuditEntry>| {
+                    entry.map_or("(none)".to_string(), ToString::to_string)
+                };
+                eprintln!("Replay differs from the dump at entry {}:", index + 1);
+                eprintln!("  recorded: {}", line(dump.entries.get(index)));
+                eprintln!("  replayed: {}", line(replayed.get(index)));
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // ydotool-style scripted input (does not require config)
+    if let Some(Command::Type { text }) = &args.command {
+        let text = text.join(" ");
+        return inject(&[Injection::Text(text.clone())], &[ControlRequest::Type(text)]);
+    }
+    if let Some(Command::Key { combos }) = &args.command {
+        let injections = combos
+            .iter()
+            .map(|spec| Injection::parse_key(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+        let requests: Vec<_> = combos.iter().map(|spec| ControlRequest::Key(spec.clone())).collect();
+        return inject(&injections, &requests);
+    }
+
+    // JSON Schema of the config format (does not require config)
+    if let Some(Command::ConfigSchema) = args.command {
+        println!("{}", config_schema_json());
+        return Ok(());
+    }
+
+    // Cheat sheet of the config's bindings (--config defaults to ~/.config/keyrs/config.toml)
+    if let Some(Command::Cheatsheet { format }) = args.command {
+        let config_path = match &args.config {
+            Some(path) => path.clone(),
+            None => keyrs_core::systemd::default_config_path()
+                .ok_or("cannot determine the user config directory")?,
+        };
+        let config = Config::from_toml_path(&config_path)
+            .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        print!("{}", render_cheatsheet(&config, format));
+        return Ok(());
+    }
+
+    // Key names, combo checks and completions (does not require config)
+    if let Some(Command::Keys { combos, complete }) = &args.command {
+        if let Some(partial) = complete {
+            for completion in keyrs_core::config::complete_combo(partial) {
+                println!("{}", completion);
+            }
+            return Ok(());
+        }
+        if combos.is_empty() {
+            Application::list_keys();
+            return Ok(());
+        }
+        let mut invalid = 0;
+        for combo in combos {
+            match keyrs_core::validate_combo(combo) {
+                Ok(_) => println!("{}: ok", combo),
+                Err(e) => {
+                    println!("{}: {}", combo, e);
+                    invalid += 1;
+                }
+            }
+        }
+        if invalid > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Lint the config against the keyboards it would grab (--config defaults to ~/.config/keyrs/config.toml)
+    if let Some(Command::Lint) = args.command {
+        let config_path = match &args.config {
+            Some(path) => path.clone(),
+            None => keyrs_core::systemd::default_config_path()
+                .ok_or("cannot determine the user config directory")?,
+        };
+        let config = Config::from_toml_path(&config_path)
+            .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        let filter = if args.devices.is_empty() { &config.device_filter } else { &args.devices };
+        let keyboard_keys = match EventLoop::keyboard_keys(filter) {
+            Ok(keys) => Some(keys),
+            Err(e) => {
+                eprintln!("Not checking output keys: {}", e);
+                None
+            }
+        };
+        let findings = lint_config(&config, keyboard_keys.as_ref());
+        if findings.is_empty() {
+            println!("No problems found");
+            return Ok(());
+        }
+        for finding in &findings {
+            println!("{}: {}", config_path.display(), finding);
+        }
+        eprintln!("{} problem(s) found", findings.len());
+        std::process::exit(1);
+    }
+
+    // Upgrade the config files to the current schema (--config defaults to ~/.config/keyrs/config.toml)
+    if let Some(Command::MigrateConfig { dry_run }) = args.command {
+        let config_path = match &args.config {
+            Some(path) => path.clone(),
+            None => keyrs_core::systemd::default_config_path()
+                .ok_or("cannot determine the user config directory")?,
+        };
+        let files = migrate_files(&config_path).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        for (path, migrated) in files {
+            if migrated.source == fs::read_to_string(&path)? {
+                println!("{}: up to date (schema_version {})", path.display(), CURRENT_SCHEMA_VERSION);
+                continue;
+            }
+            println!(
+                "{}: schema_version {} -> {}",
+                path.display(),
+                migrated.from_version,
+                CURRENT_SCHEMA_VERSION
+            );
+            for change in &migrated.changes {
+                println!("  {}", change);
+            }
+            if !dry_run {
+                let mut backup = path.clone().into_os_string();
+                backup.push(".bak");
+                fs::copy(&path, &backup)?;
+                fs::write(&path, &migrated.source)?;
+                println!("  written; previous version saved as {}", PathBuf::from(backup).display());
+            }
+        }
+        return Ok(());
+    }
+
+    // Per-keyboard settings (does not require config)
+    if let Some(Command::Devices { command: DevicesCommand::SetType { device, keyboard_type } }) = &args.command {
+        let kb_type = match keyboard_type.to_lowercase().as_str() {
+            "auto" => None,
+            _ => match KeyboardType::from_str(keyboard_type) {
+                Some(KeyboardType::Unknown) | None => {
+                    return Err(format!(
+                        "unknown keyboard type '{}': expected IBM, Chromebook, Windows, Mac or auto",
+                        keyboard_type
+                    )
+                    .into())
+                }
+                kb_type => kb_type,
+            },
+        };
+        let devices = if parse_device_id(device).is_some() { Vec::new() } else { EventLoop::list_devices()? };
+        let device_id = find_device_id(device, &devices)?;
+        let settings_path = Settings::default_path().ok_or("cannot determine the user config directory")?;
+        write_device_keyboard_type(&settings_path, &device_id, kb_type)?;
+        match kb_type {
+            Some(kb_type) => println!("{}: keyboard type {} ({})", device_id, kb_type, settings_path.display()),
+            None => println!("{}: keyboard type detected automatically ({})", device_id, settings_path.display()),
         }
-        builder.init();
+        return Ok(());
+    }
+
+    if let Some(Command::Devices { command: DevicesCommand::Fnmode { mode } }) = &args.command {
+        let path = Path::new(FNMODE_PATH);
+        if let Some(mode) = mode {
+            write_fn_mode(path, *mode).map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => format!("{}: hid_apple is not loaded", path.display()),
+                std::io::ErrorKind::PermissionDenied => format!("{}: setting fnmode needs root", path.display()),
+                _ => format!("{}: {}", path.display(), e),
+            })?;
+        }
+        let current = read_fn_mode(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("{}", current);
+        return Ok(());
     }
 
     // Handle list-devices flag (does not require config)
@@ -611,6 +1843,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Application::list_devices();
     }
 
+    // Handle list-keys flag (does not require config)
+    if args.list_keys {
+        Application::list_keys();
+        return Ok(());
+    }
+
+    // Print scancodes until interrupted (does not require config)
+    if args.show_scancodes {
+        return Application::show_scancodes(&args.devices);
+    }
+
     // Compose modular config and exit (does not require --config).
     if let Some(compose_dir) = args.compose_config.clone() {
         let output = args
@@ -622,17 +1865,72 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    // Convert a Toshy/Kinto config and exit (does not require --config).
+    if let Some(python_config) = &args.import_toshy {
+        let source = fs::read_to_string(python_config)?;
+        let import = keyrs_core::config::import_toshy(&source);
+        print!("{}", import.toml);
+        for warning in &import.warnings {
+            eprintln!("{}:{}: {}", python_config.display(), warning.line, warning.message);
+        }
+        return Ok(());
+    }
+
+    // Install the user unit and exit (--config defaults to ~/.config/keyrs/config.toml).
+    if args.install_service {
+        let keyrs_path = std::env::current_exe()?;
+        let config_path = match &args.config {
+            Some(path) => fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+            None => keyrs_core::systemd::default_config_path()
+                .ok_or("cannot determine the user config directory")?,
+        };
+        let unit_path = keyrs_core::systemd::install_user_unit(&keyrs_path, &config_path)?;
+        println!("Installed and enabled {}", unit_path.display());
+        return Ok(());
+    }
+
     // Get config path (required for runtime/check mode).
     let config_path = args.config.clone().ok_or_else(|| {
-        Box::<dyn std::error::Error>::from("--config is required when not using --list-devices or --compose-config")
+        Box::<dyn std::error::Error>::from("--config is required when not using --list-devices, --list-keys, --compose-config, --import-toshy or --install-service")
     })?;
 
+    // Handle check-config flag: report every problem instead of stopping at the first
+    if args.check_config {
+        let diagnostics = keyrs_core::config::validate_file(&config_path);
+        if diagnostics.is_empty() {
+            // Shadowed mappings are legal, so they warn without failing the check
+            if let Ok(config) = Config::from_toml_path(&config_path) {
+                let (dead, overlapping): (Vec<_>, Vec<_>) =
+                    config.keymap_conflicts().into_iter().partition(|conflict| conflict.unconditional);
+                for conflict in &dead {
+                    eprintln!("{}: warning: {}", config_path.display(), conflict);
+                }
+                if args.verbose {
+                    for conflict in &overlapping {
+                        eprintln!("{}: note: {}", config_path.display(), conflict);
+                    }
+                } else if !overlapping.is_empty() {
+                    eprintln!(
+                        "{}: note: {} keymap pair(s) map the same combos differently under overlapping conditions; run with --verbose to list them",
+                        config_path.display(),
+                        overlapping.len()
+                    );
+                }
+            }
+            println!("Configuration is valid");
+            return Ok(());
+        }
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        eprintln!("{} problem(s) found", diagnostics.len());
+        std::process::exit(1);
+    }
+
     // Create application
     let app = Application::new_with_config(config_path, args)?;
-
-    // Handle check-config flag
-    if app.args.check_config {
-        return app.validate();
+    if let Some(config) = &app.config {
+        logging.apply_config(&config.logging)?;
     }
 
     // Run main loop
@@ -694,6 +1992,104 @@ mod tests {
         assert_eq!(args.devices[1], "/dev/input/event1");
     }
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_status_subcommand() {
+        let args = Args::parse_from(["keyrs", "status", "--format", "waybar"]);
+        assert!(matches!(args.command, Some(Command::Status { format: StatusFormat::Waybar })));
+
+        let args = Args::parse_from(["keyrs", "status"]);
+        assert!(matches!(args.command, Some(Command::Status { format: StatusFormat::Text })));
+        assert!(Args::try_parse_from(["keyrs", "status", "--format", "xml"]).is_err());
+        assert!(Args::parse_from(["keyrs"]).command.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_inject_subcommands() {
+        let args = Args::parse_from(["keyrs", "type", "hello", "world"]);
+        assert!(matches!(args.command, Some(Command::Type { text }) if text == ["hello", "world"]));
+
+        let args = Args::parse_from(["keyrs", "key", "ctrl+alt+t", "Esc"]);
+        assert!(matches!(args.command, Some(Command::Key { combos }) if combos == ["ctrl+alt+t", "Esc"]));
+        assert!(Args::try_parse_from(["keyrs", "key"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_cheatsheet_subcommand() {
+        let args = Args::parse_from(["keyrs", "cheatsheet", "--format", "html", "--config", "keys.toml"]);
+        assert!(matches!(args.command, Some(Command::Cheatsheet { format: CheatsheetFormat::Html })));
+        assert_eq!(args.config, Some(PathBuf::from("keys.toml")));
+        assert!(Args::try_parse_from(["keyrs", "cheatsheet", "--format", "pdf"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_lint_subcommand() {
+        let args = Args::parse_from(["keyrs", "lint", "-d", "AT Translated Set 2 keyboard"]);
+        assert!(matches!(args.command, Some(Command::Lint)));
+        assert_eq!(args.devices, ["AT Translated Set 2 keyboard"]);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_keys_subcommand() {
+        let args = Args::parse_from(["keyrs", "keys"]);
+        assert!(matches!(args.command, Some(Command::Keys { combos, complete: None }) if combos.is_empty()));
+        let args = Args::parse_from(["keyrs", "keys", "C-a", "Crtl-b"]);
+        assert!(matches!(args.command, Some(Command::Keys { combos, .. }) if combos == ["C-a", "Crtl-b"]));
+        let args = Args::parse_from(["keyrs", "keys", "--complete", "C-PAGE"]);
+        assert!(matches!(args.command, Some(Command::Keys { complete: Some(partial), .. }) if partial == "C-PAGE"));
+        assert!(Args::try_parse_from(["keyrs", "keys", "C-a", "--complete", "C-"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_migrate_config_subcommand() {
+        let args = Args::parse_from(["keyrs", "migrate-config", "--dry-run"]);
+        assert!(matches!(args.command, Some(Command::MigrateConfig { dry_run: true })));
+        let args = Args::parse_from(["keyrs", "--config", "/tmp/test.toml", "migrate-config"]);
+        assert!(matches!(args.command, Some(Command::MigrateConfig { dry_run: false })));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_audit_subcommands() {
+        assert!(matches!(Args::parse_from(["keyrs", "audit"]).command, Some(Command::Audit)));
+        let args = Args::parse_from(["keyrs", "--config", "keys.toml", "replay", "dump.json"]);
+        assert!(matches!(args.command, Some(Command::Replay { dump }) if dump == Path::new("dump.json")));
+        assert!(Args::try_parse_from(["keyrs", "replay"]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_config_schema_subcommand() {
+        let args = Args::parse_from(["keyrs", "config-schema"]);
+        assert!(matches!(args.command, Some(Command::ConfigSchema)));
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_devices_subcommand() {
+        let args = Args::parse_from(["keyrs", "devices", "set-type", "Magic Keyboard", "Mac"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::Devices { command: DevicesCommand::SetType { device, keyboard_type } })
+                if device == "Magic Keyboard" && keyboard_type == "Mac"
+        ));
+        assert!(Args::try_parse_from(["keyrs", "devices", "set-type", "Magic Keyboard"]).is_err());
+
+        let args = Args::parse_from(["keyrs", "devices", "fnmode", "fkeys"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::Devices { command: DevicesCommand::Fnmode { mode: Some(FnMode::FunctionKeys) } })
+        ));
+        let args = Args::parse_from(["keyrs", "devices", "fnmode"]);
+        assert!(matches!(args.command, Some(Command::Devices { command: DevicesCommand::Fnmode { mode: None } })));
+        assert!(Args::try_parse_from(["keyrs", "devices", "fnmode", "sideways"]).is_err());
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_args_list_devices() {
@@ -703,6 +2099,49 @@ mod tests {
         assert!(args.compose_config.is_none());
     }
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_list_keys() {
+        let args = Args::parse_from(["keyrs", "--list-keys"]);
+
+        assert!(args.list_keys);
+        assert!(!args.list_devices);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_show_scancodes() {
+        let args = Args::parse_from(["keyrs", "--show-scancodes", "--devices", "Keychron"]);
+
+        assert!(args.show_scancodes);
+        assert_eq!(args.devices, vec!["Keychron".to_string()]);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_trace() {
+        let args = Args::parse_from(["keyrs", "--config", "/tmp/test.toml", "--trace"]);
+
+        assert!(args.trace);
+        assert!(!args.check_config);
+    }
+
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_args_privilege_separation() {
+        let args = Args::parse_from(["keyrs", "--input-helper", "--helper-socket", "/run/keyrs/input.sock"]);
+        assert!(args.input_helper);
+        assert_eq!(args.helper_socket, Some(PathBuf::from("/run/keyrs/input.sock")));
+
+        let args = Args::parse_from(["keyrs", "--connect-helper"]);
+        assert!(args.connect_helper && !args.input_helper);
+        assert!(Args::try_parse_from(["keyrs", "--input-helper", "--connect-helper"]).is_err());
+
+        let args = Args::parse_from(["keyrs", "--input-helper", "--helper-group", "keyrs"]);
+        assert_eq!(args.helper_group.as_deref(), Some("keyrs"));
+        assert!(Args::try_parse_from(["keyrs", "--connect-helper", "--helper-group", "keyrs"]).is_err());
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_args_check_config() {
@@ -842,6 +2281,40 @@ mod tests {
         assert_eq!(kb_type, KeyboardType::IBM);
     }
 
+    #[test]
+    #[cfg(feature = "pure-rust")]
+    fn test_resolve_device_keyboard_types_per_device() {
+        let settings = Settings::from_toml(
+            r#"
+            [keyboard.devices]
+            "05ac:0250" = "Mac"
+            "#,
+        )
+        .unwrap();
+        let device_infos = vec![
+            KeyboardDeviceInfo::new("AT Translated Set 2 keyboard").with_vendor_id(0x0001).with_product_id(0x0001),
+            KeyboardDeviceInfo::new("Lenovo ThinkPad Compact USB Keyboard"),
+            KeyboardDeviceInfo::new("Keychron K2").with_vendor_id(0x05ac).with_product_id(0x0250),
+        ];
+        let types = resolve_device_keyboard_types(&settings, &device_infos);
+        assert_eq!(types.len(), 2);
+        assert_eq!(types["Lenovo ThinkPad Compact USB Keyboard"], KeyboardType::IBM);
+        assert_eq!(types["Keychron K2"], KeyboardType::Mac);
+        assert_eq!(resolve_keyboard_type(&settings, &device_infos), KeyboardType::IBM);
+
+        let devices = vec![DeviceInfo {
+            index: 0,
+            name: "Keychron K2".to_string(),
+            path: Some("/dev/input/event5".to_string()),
+            vendor_id: 0x05ac,
+            product_id: 0x0250,
+        }];
+        assert_eq!(find_device_id("/dev/input/event5", &devices).unwrap(), "05ac:0250");
+        assert_eq!(find_device_id("keychron k2", &devices).unwrap(), "05ac:0250");
+        assert_eq!(find_device_id("17EF:6047", &devices).unwrap(), "17ef:6047");
+        assert!(find_device_id("Model M", &devices).is_err());
+    }
+
     #[test]
     #[cfg(feature = "pure-rust")]
     fn test_example_config_no_duplicate_keymap_names() {
@@ -1,2 +1,4 @@
 #[cfg(feature = "pure-rust")]
+pub mod logging;
+#[cfg(feature = "pure-rust")]
 pub mod tui;
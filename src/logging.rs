@@ -0,0 +1,147 @@
+// Keyrs Logging Setup
+// tracing subscriber with reloadable per-subsystem filters and text/JSON output
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+use keyrs_core::logging::{normalize_directives, LogFormat, LogSettings};
+use tracing_subscriber::layer::{Layer, Layered, SubscriberExt};
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type FormatLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+/// Installed logger; filters and format can be changed once the config is loaded
+pub struct Logging {
+    filter: reload::Handle<EnvFilter, Registry>,
+    format: reload::Handle<FormatLayer, FilteredRegistry>,
+    default_level: log::LevelFilter,
+    /// `KEYRS_LOG` then `--log`, which override the config file
+    overrides: Vec<String>,
+    format_override: Option<LogFormat>,
+}
+
+impl Logging {
+    /// Install the global subscriber and bridge `log` records from keyrs-core into it.
+    ///
+    /// Precedence, lowest first: `default_level`, `[logging]` (see
+    /// [`Logging::apply_config`]), `KEYRS_LOG`, then `cli_directives`.
+    pub fn init(
+        default_level: log::LevelFilter,
+        cli_directives: Option<&str>,
+        format_override: Option<LogFormat>,
+    ) -> Result<Self, String> {
+        let mut overrides = Vec::new();
+        if let Ok(env) = std::env::var("KEYRS_LOG") {
+            overrides.push(normalize_directives(&env).map_err(|e| format!("KEYRS_LOG: {}", e))?);
+        }
+        if let Some(cli) = cli_directives {
+            overrides.push(normalize_directives(cli).map_err(|e| format!("--log: {}", e))?);
+        }
+
+        let directives = merge_directives(default_level, "", &overrides);
+        let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&directives));
+        let (format, format_handle) =
+            reload::Layer::new(format_layer(format_override.unwrap_or_default()));
+        let subscriber = Registry::default().with(filter).with(format);
+
+        tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())?;
+        tracing_log::LogTracer::init().map_err(|e| e.to_string())?;
+        log::set_max_level(max_level(&directives));
+
+        Ok(Self {
+            filter: filter_handle,
+            format: format_handle,
+            default_level,
+            overrides,
+            format_override,
+        })
+    }
+
+    /// Apply `[logging]` from the loaded config (CLI and environment still win)
+    pub fn apply_config(&self, settings: &LogSettings) -> Result<(), String> {
+        let directives =
+            merge_directives(self.default_level, &settings.directives(), &self.overrides);
+        self.filter
+            .reload(EnvFilter::new(&directives))
+            .map_err(|e| e.to_string())?;
+        log::set_max_level(max_level(&directives));
+
+        if self.format_override.is_none() {
+            if let Some(format) = settings.format {
+                self.format
+                    .reload(format_layer(format))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_layer(format: LogFormat) -> FormatLayer {
+    match format {
+        LogFormat::Text => fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::stderr().is_terminal())
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_writer(std::io::stderr)
+            .boxed(),
+    }
+}
+
+/// Combine normalized directive lists; later entries replace earlier ones for the same target
+fn merge_directives(default_level: log::LevelFilter, config: &str, overrides: &[String]) -> String {
+    let mut order: Vec<String> = Vec::new();
+    let mut levels: HashMap<String, String> = HashMap::new();
+    let default = default_level.to_string().to_ascii_lowercase();
+    let layers = std::iter::once(default.as_str())
+        .chain(std::iter::once(config))
+        .chain(overrides.iter().map(String::as_str));
+    for layer in layers {
+        for directive in layer.split(',').filter(|d| !d.is_empty()) {
+            let (target, level) = directive.split_once('=').unwrap_or(("", directive));
+            if !levels.contains_key(target) {
+                order.push(target.to_string());
+            }
+            levels.insert(target.to_string(), level.to_string());
+        }
+    }
+    order
+        .iter()
+        .map(|target| match target.as_str() {
+            "" => levels[target].clone(),
+            _ => format!("{}={}", target, levels[target]),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Most verbose level named in `directives` (so `log` macros below it stay cheap)
+fn max_level(directives: &str) -> log::LevelFilter {
+    directives
+        .split(',')
+        .filter_map(|d| d.rsplit('=').next())
+        .filter_map(|level| level.parse::<log::LevelFilter>().ok())
+        .max()
+        .unwrap_or(log::LevelFilter::Warn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_directives_precedence() {
+        let merged = merge_directives(
+            log::LevelFilter::Warn,
+            "info,keyrs_core::transform=debug",
+            &["keyrs_core::transform=trace".to_string(), "error".to_string()],
+        );
+        assert_eq!(merged, "error,keyrs_core::transform=trace");
+        assert_eq!(max_level(&merged), log::LevelFilter::Trace);
+        assert_eq!(merge_directives(log::LevelFilter::Info, "", &[]), "info");
+    }
+}
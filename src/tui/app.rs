@@ -3,19 +3,82 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
+use evdev::EventType;
+use keyrs_core::config::edit::{
+    add_keymap_mapping, config_outline, set_keymap_enabled, OutlineEntry, OutlineKind,
+};
+use keyrs_core::config::{complete_combo, validate_combo, validate_file, Config, Diagnostic};
+use keyrs_core::input::ComboCapture;
+use keyrs_core::{EventLoop, Key};
+use keyrs_core::control::{ControlClient, ControlServer};
+use keyrs_core::status::DaemonStatus;
+use keyrs_core::systemd::{SystemdError, SystemdUser, UnitState, UNIT_NAME};
+use log::Level;
 use serde::{Deserialize, Serialize};
 
+use crate::tui::journal::JournalTail;
+
 const MAX_OUTPUT_LINES: usize = 800;
+/// Journal entries shown when the TUI starts
+const JOURNAL_BACKLOG: usize = 200;
+/// A capture nobody completes is abandoned after this long
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(15);
+const KEY_ESC: u16 = 1;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Pane {
     Commands,
     Settings,
+    Keymaps,
+    Config,
     Output,
 }
 
+/// One line of the Config pane, as `--check-config` reports it
+#[derive(Clone, Debug)]
+pub enum ConfigProblem {
+    /// The config does not load
+    Error(Diagnostic),
+    /// A mapping that can never fire
+    Warning(String),
+}
+
+/// A line of the Output pane
+#[derive(Clone, Debug)]
+pub struct OutputLine {
+    /// Level of a daemon log line; `None` for messages of the TUI itself
+    pub level: Option<Level>,
+    pub text: String,
+}
+
+/// Interactive mapping capture started with `c` in the Keymaps pane: the
+/// trigger combo is read from the keyboards, then the output combo
+pub struct KeyCapture {
+    /// `[[keymap]]` index the mapping goes into
+    keymap: usize,
+    pub keymap_name: String,
+    /// Trigger combo, once captured
+    pub trigger: Option<String>,
+    combo: ComboCapture,
+    /// Keyboards opened read-only (not grabbed)
+    source: EventLoop,
+    started: Instant,
+}
+
+/// Mapping typed with `m` in the Keymaps pane: the trigger combo, then the
+/// output combo, with Tab completing key names and modifiers
+pub struct MappingEntry {
+    /// `[[keymap]]` index the mapping goes into
+    keymap: usize,
+    pub keymap_name: String,
+    /// Trigger combo, once entered
+    pub trigger: Option<String>,
+    /// Combo being typed
+    pub text: String,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum PendingAction {
     RunCommand(usize),
@@ -38,12 +101,18 @@ impl ServiceAction {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SettingsDoc {
-    #[serde(default)]
+    /// Feature flags, listed in the Settings pane
+    #[serde(skip)]
     pub features: BTreeMap<String, bool>,
+    /// `[features]` as read; number and text values are kept as they are
+    #[serde(default, rename = "features")]
+    feature_values: BTreeMap<String, toml::Value>,
     #[serde(default)]
     pub layout: LayoutSection,
     #[serde(default)]
     pub keyboard: KeyboardSection,
+    #[serde(default, skip_serializing_if = "TuiSection::is_empty")]
+    pub tui: TuiSection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,6 +133,32 @@ impl Default for LayoutSection {
 pub struct KeyboardSection {
     #[serde(default)]
     pub override_type: Option<String>,
+    /// Per-device types set with `keyrs devices set-type`, kept on save
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub devices: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TuiSection {
+    #[serde(default)]
+    pub theme: ThemeSection,
+}
+
+impl TuiSection {
+    fn is_empty(&self) -> bool {
+        self.theme.palette.is_none() && self.theme.colors.is_empty()
+    }
+}
+
+/// `[tui.theme]`: base palette plus per-color overrides
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ThemeSection {
+    /// `dark` (default) or `light`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+    /// Palette colors by field name (`accent_primary = "#1e66f5"`)
+    #[serde(flatten)]
+    pub colors: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Debug)]
@@ -85,26 +180,63 @@ pub struct App {
     pub setting_index: usize,
     pub settings: SettingsDoc,
     pub settings_path: PathBuf,
+    /// Feature values in the running daemon (`None` when its control socket is unreachable)
+    pub live_settings: Option<BTreeMap<String, bool>>,
+    pub config_path: PathBuf,
+    pub config_entries: Vec<OutlineEntry>,
+    pub config_entry_index: usize,
+    /// Result of the last check of `config_path` (`None` before the first one)
+    pub config_problems: Option<Vec<ConfigProblem>>,
+    pub config_problem_index: usize,
     pub service_ctl: PathBuf,
     pub service_state: String,
+    pub service_sub_state: String,
+    /// State the running daemon last published (suspend mode, devices)
+    pub daemon_status: Option<DaemonStatus>,
     pub status: String,
-    pub output: Vec<String>,
+    /// Daemon journal entries mixed with the TUI's own messages
+    pub output: Vec<OutputLine>,
+    /// Lines scrolled back from the end of the (filtered) output
     pub output_scroll: usize,
+    /// Keep showing the newest lines as they arrive
+    pub output_follow: bool,
+    /// Only lines containing this text (case-insensitive) are shown
+    pub output_search: String,
+    /// `output_search` is being typed
+    pub search_editing: bool,
     pub confirm_prompt: Option<String>,
     pub pending_action: Option<PendingAction>,
+    pub capture: Option<KeyCapture>,
+    pub mapping_entry: Option<MappingEntry>,
     last_service_poll: Instant,
+    last_live_poll: Instant,
+    last_config_poll: Instant,
+    /// Reader following the keyrs unit's journal, restarted when it stops
+    journal: Option<JournalTail>,
+    last_journal_start: Instant,
+    /// A journal tail was started before (only the first shows the backlog)
+    journal_started: bool,
+    /// Modification time of `config_path` at the last check
+    config_checked_mtime: Option<SystemTime>,
+    /// User manager connection, reopened on the next call after a failure
+    systemd: Option<SystemdUser>,
+    /// Daemon control connection, reopened on the next poll after a failure
+    control: Option<ControlClient>,
 }
 
 impl App {
     pub fn new() -> io::Result<Self> {
         let home = home_dir()?;
         let settings_path = home.join(".config/keyrs/settings.toml");
+        let config_path = home.join(".config/keyrs/config.toml");
         let service_ctl = resolve_service_ctl(&home);
 
         let mut settings = load_settings(&settings_path)?;
         ensure_settings_defaults(&mut settings);
+        let theme_warnings =
+            crate::tui::theme::init(settings.tui.theme.palette.as_deref(), &settings.tui.theme.colors);
 
-        Ok(Self {
+        let mut app = Self {
             focused_pane: Pane::Commands,
             commands: build_service_actions(&service_ctl),
             command_index: 0,
@@ -112,21 +244,320 @@ impl App {
             setting_index: 0,
             settings,
             settings_path,
+            live_settings: None,
+            config_path,
+            config_entries: vec![],
+            config_entry_index: 0,
+            config_problems: None,
+            config_problem_index: 0,
             service_ctl,
             service_state: "unknown".to_string(),
+            service_sub_state: String::new(),
+            daemon_status: None,
             status: "Ready".to_string(),
             output: vec![],
             output_scroll: 0,
+            output_follow: true,
+            output_search: String::new(),
+            search_editing: false,
             confirm_prompt: None,
             pending_action: None,
+            capture: None,
+            mapping_entry: None,
             last_service_poll: Instant::now() - Duration::from_secs(10),
-        })
+            last_live_poll: Instant::now() - Duration::from_secs(10),
+            last_config_poll: Instant::now() - Duration::from_secs(10),
+            journal: None,
+            last_journal_start: Instant::now() - Duration::from_secs(10),
+            journal_started: false,
+            config_checked_mtime: None,
+            systemd: None,
+            control: None,
+        };
+        app.reload_config_entries();
+        for warning in theme_warnings {
+            app.push_output(format!("settings.toml [tui.theme]: {}", warning));
+        }
+        Ok(app)
     }
 
     pub fn selected_setting(&self) -> Option<&SettingEntry> {
         self.setting_entries.get(self.setting_index)
     }
 
+    pub fn selected_config_entry(&self) -> Option<&OutlineEntry> {
+        self.config_entries.get(self.config_entry_index)
+    }
+
+    pub fn reload_config_entries(&mut self) {
+        let loaded = fs::read_to_string(&self.config_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| config_outline(&content).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(entries) => {
+                self.config_entries = entries;
+                self.config_entry_index = self
+                    .config_entry_index
+                    .min(self.config_entries.len().saturating_sub(1));
+            }
+            Err(err) => {
+                self.config_entries.clear();
+                self.config_entry_index = 0;
+                self.push_output(format!("Could not read {}: {}", self.config_path.display(), err));
+            }
+        }
+    }
+
+    /// Validate the config file again when it changed on disk (or when forced)
+    pub fn refresh_config_check(&mut self, force: bool) {
+        if !force && self.last_config_poll.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_config_poll = Instant::now();
+        let mtime = fs::metadata(&self.config_path).and_then(|meta| meta.modified()).ok();
+        if !force && self.config_problems.is_some() && mtime == self.config_checked_mtime {
+            return;
+        }
+        self.config_checked_mtime = mtime;
+        let (problems, toggles) = check_config(&self.config_path);
+        self.config_problem_index = self.config_problem_index.min(problems.len().saturating_sub(1));
+        self.config_problems = Some(problems);
+        self.add_toggle_settings(toggles);
+    }
+
+    /// List the flags config entries are gated on (`toggle_setting`) as
+    /// features, with their defaults until settings.toml sets them
+    fn add_toggle_settings(&mut self, toggles: Vec<(String, bool)>) {
+        let known = self.settings.features.len();
+        for (name, default) in toggles {
+            self.settings.features.entry(name).or_insert(default);
+        }
+        if self.settings.features.len() > known {
+            self.setting_entries = build_setting_entries(&self.settings.features);
+        }
+    }
+
+    pub fn toggle_selected_keymap(&mut self) {
+        let Some(entry) = self.selected_config_entry().cloned() else {
+            return;
+        };
+        if entry.kind != OutlineKind::Keymap {
+            self.set_status(format!("Only keymaps can be toggled ({} entry)", entry.kind.as_str()));
+            return;
+        }
+
+        let enabled = !entry.enabled;
+        let result = fs::read_to_string(&self.config_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                set_keymap_enabled(&content, entry.index, enabled).map_err(|e| e.to_string())
+            })
+            .and_then(|rendered| write_atomic(&self.config_path, &rendered).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(()) => {
+                self.reload_config_entries();
+                self.set_status(format!(
+                    "Keymap '{}' {} (restart service to apply)",
+                    entry.name,
+                    if enabled { "enabled" } else { "disabled" }
+                ));
+            }
+            Err(err) => {
+                self.set_status("Keymap toggle failed");
+                self.push_output(format!("Config write error: {}", err));
+            }
+        }
+    }
+
+    /// Start capturing a new mapping for the selected keymap
+    pub fn start_capture(&mut self) {
+        let Some(entry) = self.selected_config_entry().cloned() else {
+            return;
+        };
+        if entry.kind != OutlineKind::Keymap {
+            self.set_status(format!("Mappings can only be added to keymaps ({} entry)", entry.kind.as_str()));
+            return;
+        }
+        match EventLoop::new() {
+            Ok(source) => {
+                self.capture = Some(KeyCapture {
+                    keymap: entry.index,
+                    keymap_name: entry.name.clone(),
+                    trigger: None,
+                    combo: ComboCapture::new(),
+                    source,
+                    started: Instant::now(),
+                });
+                if self.service_state == "active" {
+                    self.push_output("keyrs is running: keys of keyboards it grabs are not seen; stop it to capture them");
+                }
+                self.set_status(format!("Capturing a mapping for keymap '{}'", entry.name));
+            }
+            Err(err) => {
+                self.set_status("Capture failed");
+                self.push_output(format!("Could not open keyboards: {} (is the user in the input group?)", err));
+            }
+        }
+    }
+
+    pub fn cancel_capture(&mut self) {
+        if self.capture.take().is_some() {
+            self.set_status("Capture cancelled");
+        }
+    }
+
+    /// Read pending key events of an active capture; adds the mapping once
+    /// both combos are in
+    pub fn poll_capture(&mut self) {
+        let Some(capture) = self.capture.as_mut() else {
+            return;
+        };
+        if capture.started.elapsed() >= CAPTURE_TIMEOUT {
+            self.capture = None;
+            self.set_status("Capture timed out");
+            return;
+        }
+        let events = match capture.source.poll_for_events(0) {
+            Ok(events) => events,
+            Err(err) => {
+                self.capture = None;
+                self.set_status("Capture failed");
+                self.push_output(format!("Keyboard read error: {}", err));
+                return;
+            }
+        };
+
+        for event in events {
+            if event.event_type() != EventType::KEY {
+                continue;
+            }
+            if event.code() == KEY_ESC && event.value() == 1 {
+                self.cancel_capture();
+                return;
+            }
+            let Some(combo) = capture.combo.feed(Key::from(event.code()), event.value()) else {
+                continue;
+            };
+            let Some(trigger) = capture.trigger.take() else {
+                capture.trigger = Some(combo.clone());
+                capture.combo.reset();
+                capture.started = Instant::now();
+                self.set_status(format!("Trigger: {}", combo));
+                return;
+            };
+            let keymap = capture.keymap;
+            let keymap_name = capture.keymap_name.clone();
+            self.capture = None;
+            self.add_mapping(keymap, &keymap_name, &trigger, &combo);
+            return;
+        }
+    }
+
+    /// Start typing a new mapping for the selected keymap
+    pub fn start_mapping_entry(&mut self) {
+        let Some(entry) = self.selected_config_entry().cloned() else {
+            return;
+        };
+        if entry.kind != OutlineKind::Keymap {
+            self.set_status(format!("Mappings can only be added to keymaps ({} entry)", entry.kind.as_str()));
+            return;
+        }
+        self.mapping_entry = Some(MappingEntry {
+            keymap: entry.index,
+            keymap_name: entry.name.clone(),
+            trigger: None,
+            text: String::new(),
+        });
+        self.set_status(format!("Typing a mapping for keymap '{}'", entry.name));
+    }
+
+    pub fn cancel_mapping_entry(&mut self) {
+        if self.mapping_entry.take().is_some() {
+            self.set_status("Mapping cancelled");
+        }
+    }
+
+    /// Complete the typed combo as far as the known names agree, and list
+    /// the candidates when several remain
+    pub fn complete_mapping_entry(&mut self) {
+        let Some(entry) = self.mapping_entry.as_mut() else {
+            return;
+        };
+        let completions = complete_combo(&entry.text);
+        let Some(first) = completions.first() else {
+            let text = entry.text.clone();
+            self.set_status(format!("No key name or modifier completes '{}'", text));
+            return;
+        };
+        let common = completions.iter().fold(first.clone(), |common, candidate| {
+            common
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                .map(|(a, _)| a)
+                .collect()
+        });
+        if common.len() > entry.text.len() || completions.len() == 1 {
+            entry.text = if completions.len() == 1 { first.clone() } else { common };
+        }
+        if completions.len() > 1 {
+            const SHOWN: usize = 8;
+            let mut status = completions.iter().take(SHOWN).cloned().collect::<Vec<_>>().join("  ");
+            if completions.len() > SHOWN {
+                status.push_str(&format!("  (+{} more)", completions.len() - SHOWN));
+            }
+            self.set_status(status);
+        }
+    }
+
+    /// Accept the typed combo: it becomes the trigger, or with the trigger
+    /// already in, the output of the new mapping
+    pub fn submit_mapping_entry(&mut self) {
+        let Some(entry) = self.mapping_entry.as_mut() else {
+            return;
+        };
+        let combo = entry.text.trim().to_string();
+        if let Err(err) = validate_combo(&combo) {
+            self.set_status(format!("'{}': {}", combo, err));
+            return;
+        }
+        let Some(trigger) = entry.trigger.take() else {
+            entry.trigger = Some(combo.clone());
+            entry.text.clear();
+            self.set_status(format!("Trigger: {}", combo));
+            return;
+        };
+        let keymap = entry.keymap;
+        let keymap_name = entry.keymap_name.clone();
+        self.mapping_entry = None;
+        self.add_mapping(keymap, &keymap_name, &trigger, &combo);
+    }
+
+    fn add_mapping(&mut self, keymap: usize, keymap_name: &str, trigger: &str, output: &str) {
+        let result = fs::read_to_string(&self.config_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                add_keymap_mapping(&content, keymap, trigger, output).map_err(|e| e.to_string())
+            })
+            .and_then(|rendered| write_atomic(&self.config_path, &rendered).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(()) => {
+                self.reload_config_entries();
+                self.refresh_config_check(true);
+                self.set_status(format!(
+                    "Added \"{}\" = \"{}\" to keymap '{}' (restart service to apply)",
+                    trigger, output, keymap_name
+                ));
+            }
+            Err(err) => {
+                self.set_status("Adding mapping failed");
+                self.push_output(format!("Config write error: {}", err));
+            }
+        }
+    }
+
     pub fn selected_command(&self) -> Option<&ServiceAction> {
         self.commands.get(self.command_index)
     }
@@ -137,14 +568,92 @@ impl App {
 
     pub fn push_output<S: AsRef<str>>(&mut self, msg: S) {
         for line in msg.as_ref().lines() {
-            self.output.push(line.to_string());
+            self.push_output_line(None, line.to_string());
         }
+    }
+
+    fn push_output_line(&mut self, level: Option<Level>, text: String) {
+        let line = OutputLine { level, text };
+        // Keep the lines on screen in place while scrolled back
+        if !self.output_follow && self.output_matches(&line) {
+            self.output_scroll += 1;
+        }
+        self.output.push(line);
         if self.output.len() > MAX_OUTPUT_LINES {
             let overflow = self.output.len() - MAX_OUTPUT_LINES;
             self.output.drain(0..overflow);
         }
-        // Auto-scroll to bottom on new output
-        self.output_scroll = self.output.len().saturating_sub(1);
+    }
+
+    /// Whether `line` passes the search filter
+    pub fn output_matches(&self, line: &OutputLine) -> bool {
+        self.output_search.is_empty()
+            || line
+                .text
+                .to_lowercase()
+                .contains(&self.output_search.to_lowercase())
+    }
+
+    /// Output lines passing the search filter
+    pub fn visible_output(&self) -> Vec<&OutputLine> {
+        self.output.iter().filter(|line| self.output_matches(line)).collect()
+    }
+
+    /// Scroll back one line, leaving follow mode
+    pub fn scroll_output_up(&mut self) {
+        let visible = self.visible_output().len();
+        self.output_follow = false;
+        self.output_scroll = (self.output_scroll + 1).min(visible.saturating_sub(1));
+    }
+
+    /// Scroll forward one line, following again at the end
+    pub fn scroll_output_down(&mut self) {
+        self.output_scroll = self.output_scroll.saturating_sub(1);
+        if self.output_scroll == 0 {
+            self.output_follow = true;
+        }
+    }
+
+    pub fn toggle_output_follow(&mut self) {
+        self.output_follow = !self.output_follow;
+        if self.output_follow {
+            self.output_scroll = 0;
+        }
+    }
+
+    /// Read new entries of the keyrs journal, (re)starting the reader as needed
+    pub fn refresh_journal(&mut self) {
+        if self.journal.as_ref().is_some_and(|journal| !journal.is_running()) {
+            // Entries it read before stopping are still queued
+            if let Some(journal) = self.journal.take() {
+                for entry in journal.drain() {
+                    self.push_output_line(Some(entry.level), entry.message);
+                }
+            }
+        }
+        if self.journal.is_none() {
+            if self.last_journal_start.elapsed() < Duration::from_secs(5) {
+                return;
+            }
+            self.last_journal_start = Instant::now();
+            let first = !self.journal_started;
+            self.journal_started = true;
+            // Restarts only pick up new entries
+            let backlog = if first { JOURNAL_BACKLOG } else { 0 };
+            match JournalTail::follow(UNIT_NAME, backlog) {
+                Ok(journal) => self.journal = Some(journal),
+                Err(err) => {
+                    if first {
+                        self.push_output(format!("Cannot follow the {} journal: {}", UNIT_NAME, err));
+                    }
+                    return;
+                }
+            }
+        }
+        let entries = self.journal.as_ref().map(JournalTail::drain).unwrap_or_default();
+        for entry in entries {
+            self.push_output_line(Some(entry.level), entry.message);
+        }
     }
 
     pub fn start_confirm<S: Into<String>>(&mut self, prompt: S, action: PendingAction) {
@@ -162,7 +671,89 @@ impl App {
             return;
         }
         self.last_service_poll = Instant::now();
-        self.service_state = query_service_state();
+        match self.with_systemd(|systemd| systemd.unit_state(UNIT_NAME)) {
+            Ok(state) => {
+                self.service_state = state.active_state;
+                self.service_sub_state = state.sub_state;
+            }
+            Err(_) => {
+                self.service_state = "unknown".to_string();
+                self.service_sub_state.clear();
+            }
+        }
+        self.daemon_status = DaemonStatus::load_default().filter(|status| status.enabled);
+    }
+
+    /// Re-read the daemon's live settings over its control socket
+    pub fn refresh_live_settings(&mut self, force: bool) {
+        if !force && self.last_live_poll.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_live_poll = Instant::now();
+        self.live_settings = self.with_control(|control| control.list()).ok();
+    }
+
+    /// Run `f` against the daemon's control socket, dropping the connection if it fails
+    fn with_control<T>(
+        &mut self,
+        f: impl FnOnce(&mut ControlClient) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let control = match self.control.as_mut() {
+            Some(control) => control,
+            None => self
+                .control
+                .insert(ControlClient::connect(&ControlServer::default_path()?)?),
+        };
+        let result = f(control);
+        if result.is_err() {
+            self.control = None;
+        }
+        result
+    }
+
+    /// Run `f` against the user manager, dropping the connection if it fails
+    fn with_systemd<T>(
+        &mut self,
+        f: impl FnOnce(&mut SystemdUser) -> Result<T, SystemdError>,
+    ) -> Result<T, SystemdError> {
+        let systemd = match self.systemd.as_mut() {
+            Some(systemd) => systemd,
+            None => self.systemd.insert(SystemdUser::connect()?),
+        };
+        let result = f(systemd);
+        if matches!(
+            result,
+            Err(SystemdError::Bus(_) | SystemdError::Protocol(_) | SystemdError::Io(_))
+        ) {
+            self.systemd = None;
+        }
+        result
+    }
+
+    /// Handle unit commands over D-Bus; `None` means the command belongs to the helper script
+    fn run_systemd_command(&mut self, command: &str) -> Option<Result<String, String>> {
+        let result = match command {
+            "status" => self
+                .with_systemd(|systemd| systemd.unit_state(UNIT_NAME))
+                .map(|state| describe_unit_state(&state)),
+            "start" => self
+                .with_systemd(|systemd| systemd.start(UNIT_NAME))
+                .map(|()| String::new()),
+            "stop" => self
+                .with_systemd(|systemd| systemd.stop(UNIT_NAME))
+                .map(|()| String::new()),
+            "restart" => self
+                .with_systemd(|systemd| systemd.restart(UNIT_NAME))
+                .map(|()| String::new()),
+            "enable" => self
+                .with_systemd(|systemd| systemd.enable(UNIT_NAME))
+                .map(|()| String::new()),
+            "disable" => self
+                .with_systemd(|systemd| systemd.disable(UNIT_NAME))
+                .map(|()| String::new()),
+            _ => return None,
+        };
+        Some(result.map_err(|e| e.to_string()))
     }
 
     pub fn run_selected_command(&mut self) {
@@ -187,16 +778,23 @@ impl App {
         let command = action.command;
         self.set_status(format!("Running {}...", label));
 
-        match run_service_command(&self.service_ctl, command) {
+        let (prompt, result) = match self.run_systemd_command(command) {
+            Some(result) => (format!("systemd: {} {}", command, UNIT_NAME), result),
+            None => (
+                format!("$ {} --yes", command),
+                run_service_command(&self.service_ctl, command),
+            ),
+        };
+        match result {
             Ok(out) => {
                 self.set_status(format!("{} succeeded", label));
                 if !out.trim().is_empty() {
-                    self.push_output(format!("$ {} --yes\n{}", command, out.trim_end()));
+                    self.push_output(format!("{}\n{}", prompt, out.trim_end()));
                 }
             }
             Err(err) => {
                 self.set_status(format!("{} failed", label));
-                self.push_output(format!("$ {} --yes\n{}", command, err.trim_end()));
+                self.push_output(format!("{}\n{}", prompt, err.trim_end()));
             }
         }
         self.refresh_service_status(true);
@@ -204,6 +802,22 @@ impl App {
 
     pub fn toggle_selected_feature(&mut self) {
         if let Some(SettingEntry::Feature(key)) = self.selected_setting().cloned() {
+            // With the daemon running, toggles apply to it directly; `w` persists them
+            if let Some(live) = &self.live_settings {
+                let next = !live.get(&key).copied().unwrap_or(false);
+                match self.with_control(|control| control.set(&key, next)) {
+                    Ok(()) => {
+                        self.refresh_live_settings(true);
+                        self.set_status(format!("Live: {} -> {} (w to write to settings.toml)", key, next));
+                    }
+                    Err(err) => {
+                        self.live_settings = None;
+                        self.set_status("Live update failed");
+                        self.push_output(format!("Control socket error: {}", err));
+                    }
+                }
+                return;
+            }
             let next = {
                 let value = self.settings.features.entry(key.clone()).or_insert(false);
                 *value = !*value;
@@ -254,16 +868,13 @@ impl App {
                 self.push_output(format!("Saved {}", self.settings_path.display()));
                 if restart {
                     self.set_status("Saved settings, restarting service...");
-                    match run_service_command(&self.service_ctl, "restart") {
-                        Ok(out) => {
+                    match self.with_systemd(|systemd| systemd.restart(UNIT_NAME)) {
+                        Ok(()) => {
                             self.set_status("Saved settings and restarted service");
-                            if !out.trim().is_empty() {
-                                self.push_output(format!("$ restart --yes\n{}", out.trim_end()));
-                            }
                         }
                         Err(err) => {
                             self.set_status("Settings saved, restart failed");
-                            self.push_output(format!("$ restart --yes\n{}", err.trim_end()));
+                            self.push_output(format!("systemd: restart {}\n{}", UNIT_NAME, err));
                         }
                     }
                     self.refresh_service_status(true);
@@ -276,10 +887,26 @@ impl App {
         }
     }
 
+    /// Copy the daemon's live feature values into settings.toml
+    pub fn write_live_settings(&mut self) {
+        let Some(live) = self.live_settings.clone() else {
+            self.set_status("Daemon not reachable; nothing live to write");
+            return;
+        };
+        for (key, value) in live {
+            self.settings.features.insert(key, value);
+        }
+        self.setting_entries = build_setting_entries(&self.settings.features);
+        self.setting_index = self.setting_index.min(self.setting_entries.len().saturating_sub(1));
+        self.save_settings(false);
+    }
+
     pub fn cycle_pane_forward(&mut self) {
         self.focused_pane = match self.focused_pane {
             Pane::Commands => Pane::Settings,
-            Pane::Settings => Pane::Output,
+            Pane::Settings => Pane::Keymaps,
+            Pane::Keymaps => Pane::Config,
+            Pane::Config => Pane::Output,
             Pane::Output => Pane::Commands,
         };
     }
@@ -288,11 +915,33 @@ impl App {
         self.focused_pane = match self.focused_pane {
             Pane::Commands => Pane::Output,
             Pane::Settings => Pane::Commands,
-            Pane::Output => Pane::Settings,
+            Pane::Keymaps => Pane::Settings,
+            Pane::Config => Pane::Keymaps,
+            Pane::Output => Pane::Config,
         };
     }
 }
 
+/// The problems `keyrs --check-config` reports for `path`
+/// Problems of the config at `path`, and the settings flags it gates entries on
+fn check_config(path: &Path) -> (Vec<ConfigProblem>, Vec<(String, bool)>) {
+    let diagnostics = validate_file(path);
+    if !diagnostics.is_empty() {
+        return (diagnostics.into_iter().map(ConfigProblem::Error).collect(), Vec::new());
+    }
+    Config::from_toml_path(path)
+        .map(|config| {
+            let warnings = config
+                .keymap_conflicts()
+                .into_iter()
+                .filter(|conflict| conflict.unconditional)
+                .map(|conflict| ConfigProblem::Warning(conflict.to_string()))
+                .collect();
+            (warnings, config.toggle_settings)
+        })
+        .unwrap_or_default()
+}
+
 fn home_dir() -> io::Result<PathBuf> {
     std::env::var("HOME")
         .map(PathBuf::from)
@@ -316,18 +965,53 @@ fn load_settings(path: &Path) -> io::Result<SettingsDoc> {
         return Ok(SettingsDoc::default());
     }
     let content = fs::read_to_string(path)?;
-    toml::from_str::<SettingsDoc>(&content)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    let mut settings = toml::from_str::<SettingsDoc>(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let values = std::mem::take(&mut settings.feature_values);
+    for (name, value) in values {
+        match feature_flag(&value) {
+            Some(flag) => {
+                settings.features.insert(name, flag);
+            }
+            None => {
+                settings.feature_values.insert(name, value);
+            }
+        }
+    }
+    Ok(settings)
+}
+
+/// A `[features]` value the daemon reads as a flag (`true`, `1`, `"on"`)
+fn feature_flag(value: &toml::Value) -> Option<bool> {
+    match value {
+        toml::Value::Boolean(flag) => Some(*flag),
+        toml::Value::Integer(1) => Some(true),
+        toml::Value::Integer(0) => Some(false),
+        toml::Value::String(text) => match text.trim().to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(true),
+            "false" | "no" | "off" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 fn save_settings_atomic(path: &Path, settings: &SettingsDoc) -> io::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
-    let temp = path.with_extension("toml.tmp");
-    let rendered = toml::to_string_pretty(settings)
+    let mut settings = settings.clone();
+    for (name, flag) in &settings.features {
+        settings.feature_values.insert(name.clone(), toml::Value::Boolean(*flag));
+    }
+    let rendered = toml::to_string_pretty(&settings)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    fs::write(&temp, rendered)?;
+    write_atomic(path, &rendered)
+}
+
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, content)?;
     fs::rename(&temp, path)?;
     Ok(())
 }
@@ -447,6 +1131,18 @@ fn build_service_actions(service_ctl: &Path) -> Vec<ServiceAction> {
             description: "Restart keyrs service",
             confirm: true,
         },
+        ServiceAction {
+            label: "Enable",
+            command: "enable",
+            description: "Start keyrs with the graphical session",
+            confirm: false,
+        },
+        ServiceAction {
+            label: "Disable",
+            command: "disable",
+            description: "Stop starting keyrs with the session",
+            confirm: true,
+        },
         ServiceAction {
             label: "Apply Config",
             command: "apply-config",
@@ -503,25 +1199,14 @@ fn run_service_command(service_ctl: &Path, command: &str) -> Result<String, Stri
     }
 }
 
-fn query_service_state() -> String {
-    match Command::new("systemctl")
-        .arg("--user")
-        .arg("is-active")
-        .arg("keyrs.service")
-        .output()
-    {
-        Ok(out) => {
-            let state = String::from_utf8_lossy(&out.stdout).trim().to_string();
-            if state.is_empty() {
-                if out.status.success() {
-                    "active".to_string()
-                } else {
-                    "inactive".to_string()
-                }
-            } else {
-                state
-            }
-        }
-        Err(_) => "unknown".to_string(),
-    }
+fn describe_unit_state(state: &UnitState) -> String {
+    let unit_file_state = if state.unit_file_state.is_empty() {
+        "not installed"
+    } else {
+        state.unit_file_state.as_str()
+    };
+    format!(
+        "{}: {} ({}), unit file {}",
+        UNIT_NAME, state.active_state, state.sub_state, unit_file_state
+    )
 }
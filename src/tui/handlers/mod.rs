@@ -10,7 +10,25 @@ pub fn handle_input(app: &mut App, key: KeyCode) -> io::Result<bool> {
         return handle_confirmation(app, key);
     }
 
-    // Global navigation: Tab cycles panes, 1/2/3 jump to specific pane
+    if app.search_editing {
+        handle_search_input(app, key);
+        return Ok(false);
+    }
+
+    if app.mapping_entry.is_some() {
+        handle_mapping_entry_input(app, key);
+        return Ok(false);
+    }
+
+    // The terminal sees the captured keys too; only Esc (cancel) counts
+    if app.capture.is_some() {
+        if key == KeyCode::Esc {
+            app.cancel_capture();
+        }
+        return Ok(false);
+    }
+
+    // Global navigation: Tab cycles panes, 1-5 jump to specific pane
     match key {
         KeyCode::Char('q') => return Ok(true),
         KeyCode::Tab => {
@@ -30,6 +48,14 @@ pub fn handle_input(app: &mut App, key: KeyCode) -> io::Result<bool> {
             return Ok(false);
         }
         KeyCode::Char('3') => {
+            app.focused_pane = Pane::Keymaps;
+            return Ok(false);
+        }
+        KeyCode::Char('4') => {
+            app.focused_pane = Pane::Config;
+            return Ok(false);
+        }
+        KeyCode::Char('5') => {
             app.focused_pane = Pane::Output;
             return Ok(false);
         }
@@ -66,6 +92,8 @@ fn handle_pane_input(app: &mut App, key: KeyCode) {
     match app.focused_pane {
         Pane::Commands => handle_commands_input(app, key),
         Pane::Settings => handle_settings_input(app, key),
+        Pane::Keymaps => handle_keymaps_input(app, key),
+        Pane::Config => handle_config_input(app, key),
         Pane::Output => handle_output_input(app, key),
     }
 }
@@ -103,6 +131,7 @@ fn handle_settings_input(app: &mut App, key: KeyCode) {
         }
         KeyCode::Enter | KeyCode::Char(' ') => app.change_selected_setting(),
         KeyCode::Char('s') => app.save_settings(false),
+        KeyCode::Char('w') => app.write_live_settings(),
         KeyCode::Char('a') | KeyCode::Char('A') => {
             app.start_confirm(
                 "Save settings and restart service?",
@@ -113,15 +142,94 @@ fn handle_settings_input(app: &mut App, key: KeyCode) {
     }
 }
 
-fn handle_output_input(app: &mut App, key: KeyCode) {
-    // Output log scrolling
+fn handle_keymaps_input(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Up | KeyCode::Char('k') if app.config_entry_index > 0 => {
+            app.config_entry_index -= 1;
+        }
+        KeyCode::Down | KeyCode::Char('j') if app.config_entry_index + 1 < app.config_entries.len() => {
+            app.config_entry_index += 1;
+        }
+        KeyCode::Enter | KeyCode::Char(' ') => app.toggle_selected_keymap(),
+        KeyCode::Char('c') => app.start_capture(),
+        KeyCode::Char('m') => app.start_mapping_entry(),
+        KeyCode::Char('r') => {
+            app.reload_config_entries();
+            app.set_status(format!("Reloaded {}", app.config_path.display()));
+        }
+        _ => {}
+    }
+}
+
+fn handle_config_input(app: &mut App, key: KeyCode) {
+    let count = app.config_problems.as_ref().map_or(0, Vec::len);
     match key {
         KeyCode::Up | KeyCode::Char('k') => {
-            app.output_scroll = app.output_scroll.saturating_sub(1);
+            app.config_problem_index = app.config_problem_index.saturating_sub(1);
         }
-        KeyCode::Down | KeyCode::Char('j') => {
-            app.output_scroll = app.output_scroll.saturating_add(1);
+        KeyCode::Down | KeyCode::Char('j') if app.config_problem_index + 1 < count => {
+            app.config_problem_index += 1;
+        }
+        KeyCode::Char('r') => {
+            app.refresh_config_check(true);
+            app.set_status(format!("Checked {}", app.config_path.display()));
+        }
+        _ => {}
+    }
+}
+
+fn handle_output_input(app: &mut App, key: KeyCode) {
+    // Output log scrolling, follow mode and search
+    match key {
+        KeyCode::Up | KeyCode::Char('k') => app.scroll_output_up(),
+        KeyCode::Down | KeyCode::Char('j') => app.scroll_output_down(),
+        KeyCode::Char('f') | KeyCode::End => app.toggle_output_follow(),
+        KeyCode::Char('/') => {
+            app.search_editing = true;
+            app.output_search.clear();
+            app.output_scroll = 0;
+        }
+        KeyCode::Esc => {
+            app.output_search.clear();
+            app.output_scroll = 0;
+            app.output_follow = true;
+        }
+        _ => {}
+    }
+}
+
+fn handle_mapping_entry_input(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Char(c) => {
+            if let Some(entry) = app.mapping_entry.as_mut() {
+                entry.text.push(c);
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some(entry) = app.mapping_entry.as_mut() {
+                entry.text.pop();
+            }
+        }
+        KeyCode::Tab => app.complete_mapping_entry(),
+        KeyCode::Enter => app.submit_mapping_entry(),
+        KeyCode::Esc => app.cancel_mapping_entry(),
+        _ => {}
+    }
+}
+
+fn handle_search_input(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Char(c) => app.output_search.push(c),
+        KeyCode::Backspace => {
+            app.output_search.pop();
+        }
+        KeyCode::Enter => app.search_editing = false,
+        KeyCode::Esc => {
+            app.search_editing = false;
+            app.output_search.clear();
         }
         _ => {}
     }
+    app.output_scroll = 0;
+    app.output_follow = true;
 }
@@ -0,0 +1,347 @@
+// Keyrs Journal Tail
+// Follow the journal of the keyrs user unit with sd-journal (libsystemd, loaded at runtime)
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use libloading::Library;
+use log::Level;
+use serde_json::Value;
+
+/// `SD_JOURNAL_LOCAL_ONLY | SD_JOURNAL_CURRENT_USER`, the files `journalctl --user` reads
+const OPEN_FLAGS: c_int = 1 | 8;
+/// Longest single wait for new entries, bounding how late a stop is noticed
+const WAIT_USEC: u64 = 250_000;
+
+/// `sd_journal *`
+type Handle = *mut c_void;
+
+/// One journal entry of the followed unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Microseconds since the epoch (`__REALTIME_TIMESTAMP`)
+    pub timestamp_us: u64,
+    /// Level of the keyrs log line, or the syslog priority for other output
+    pub level: Level,
+    /// Message without terminal color codes
+    pub message: String,
+}
+
+/// Entries of a unit's journal, read as they are written.
+///
+/// A thread opens the journal and waits for new entries on it, so `drain`
+/// never blocks. libsystemd is loaded when following starts; without it
+/// `follow` fails and the rest of the TUI works as before.
+pub struct JournalTail {
+    entries: Receiver<JournalEntry>,
+    stop: Arc<AtomicBool>,
+    reader: JoinHandle<()>,
+}
+
+impl JournalTail {
+    /// Follow the user unit `unit`, starting with its last `lines` entries
+    pub fn follow(unit: &str, lines: usize) -> io::Result<Self> {
+        let (sender, entries) = mpsc::channel();
+        let (opened_sender, opened) = mpsc::sync_channel(1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let unit = unit.to_string();
+
+        let reader = thread::Builder::new().name("keyrs-journal".to_string()).spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                // An sd_journal object stays on the thread that opened it
+                let opened = Journal::open(&unit)
+                    .and_then(|journal| journal.seek_last(lines).map(|positioned| (journal, positioned)));
+                let (journal, positioned) = match opened {
+                    Ok(opened) => {
+                        let _ = opened_sender.send(Ok(()));
+                        opened
+                    }
+                    Err(e) => {
+                        let _ = opened_sender.send(Err(e));
+                        return;
+                    }
+                };
+                if let Err(e) = journal.follow(positioned, &sender, &stop) {
+                    log::debug!("Stopped following the journal: {}", e);
+                }
+            }
+        })?;
+
+        match opened.recv() {
+            Ok(Ok(())) => Ok(Self { entries, stop, reader }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(io::Error::other("the journal reader exited")),
+        }
+    }
+
+    /// Entries read since the last call
+    pub fn drain(&self) -> Vec<JournalEntry> {
+        self.entries.try_iter().collect()
+    }
+
+    /// Whether the journal is still being read
+    pub fn is_running(&self) -> bool {
+        !self.reader.is_finished()
+    }
+}
+
+impl Drop for JournalTail {
+    fn drop(&mut self) {
+        // The reader notices within one wait and closes the journal itself
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The sd-journal functions used here
+struct SdJournal {
+    open: unsafe extern "C" fn(*mut Handle, c_int) -> c_int,
+    close: unsafe extern "C" fn(Handle),
+    add_match: unsafe extern "C" fn(Handle, *const c_void, usize) -> c_int,
+    add_disjunction: unsafe extern "C" fn(Handle) -> c_int,
+    seek_tail: unsafe extern "C" fn(Handle) -> c_int,
+    previous_skip: unsafe extern "C" fn(Handle, u64) -> c_int,
+    next: unsafe extern "C" fn(Handle) -> c_int,
+    get_data: unsafe extern "C" fn(Handle, *const c_char, *mut *const c_void, *mut usize) -> c_int,
+    get_realtime_usec: unsafe extern "C" fn(Handle, *mut u64) -> c_int,
+    wait: unsafe extern "C" fn(Handle, u64) -> c_int,
+    /// Keeps the functions above loaded
+    _library: Library,
+}
+
+impl SdJournal {
+    fn load() -> io::Result<Self> {
+        let unavailable = |e: libloading::Error| io::Error::other(format!("libsystemd is not available: {}", e));
+        // SAFETY: libsystemd runs no initialization with side effects on load,
+        // and each signature matches sd-journal(3)
+        unsafe {
+            let library = Library::new("libsystemd.so.0").map_err(unavailable)?;
+            Ok(Self {
+                open: *library.get(b"sd_journal_open\0").map_err(unavailable)?,
+                close: *library.get(b"sd_journal_close\0").map_err(unavailable)?,
+                add_match: *library.get(b"sd_journal_add_match\0").map_err(unavailable)?,
+                add_disjunction: *library.get(b"sd_journal_add_disjunction\0").map_err(unavailable)?,
+                seek_tail: *library.get(b"sd_journal_seek_tail\0").map_err(unavailable)?,
+                previous_skip: *library.get(b"sd_journal_previous_skip\0").map_err(unavailable)?,
+                next: *library.get(b"sd_journal_next\0").map_err(unavailable)?,
+                get_data: *library.get(b"sd_journal_get_data\0").map_err(unavailable)?,
+                get_realtime_usec: *library.get(b"sd_journal_get_realtime_usec\0").map_err(unavailable)?,
+                wait: *library.get(b"sd_journal_wait\0").map_err(unavailable)?,
+                _library: library,
+            })
+        }
+    }
+}
+
+/// An open journal, filtered to one user unit
+struct Journal {
+    api: SdJournal,
+    handle: Handle,
+}
+
+impl Journal {
+    fn open(unit: &str) -> io::Result<Self> {
+        let api = SdJournal::load()?;
+        let mut handle = std::ptr::null_mut();
+        check(unsafe { (api.open)(&mut handle, OPEN_FLAGS) })?;
+        let journal = Self { api, handle };
+
+        // Like `journalctl --user --unit`: the unit's own output, or the
+        // user manager's messages about it
+        let uid = unsafe { libc::getuid() };
+        journal.add_match(&format!("_SYSTEMD_USER_UNIT={}", unit))?;
+        journal.add_match(&format!("_UID={}", uid))?;
+        check(unsafe { (journal.api.add_disjunction)(journal.handle) })?;
+        journal.add_match(&format!("USER_UNIT={}", unit))?;
+        journal.add_match(&format!("_UID={}", uid))?;
+        Ok(journal)
+    }
+
+    fn add_match(&self, data: &str) -> io::Result<()> {
+        check(unsafe { (self.api.add_match)(self.handle, data.as_ptr().cast(), data.len()) })?;
+        Ok(())
+    }
+
+    /// Move to the `lines`-th last entry; returns whether it is on an entry
+    /// (otherwise the next entry written is the first read)
+    fn seek_last(&self, lines: usize) -> io::Result<bool> {
+        check(unsafe { (self.api.seek_tail)(self.handle) })?;
+        if lines == 0 {
+            return Ok(false);
+        }
+        let skipped = check(unsafe { (self.api.previous_skip)(self.handle, lines as u64) })?;
+        Ok(skipped > 0)
+    }
+
+    /// Send entries as they are written until `stop` is set or nobody receives them
+    fn follow(&self, mut positioned: bool, sender: &Sender<JournalEntry>, stop: &AtomicBool) -> io::Result<()> {
+        loop {
+            while positioned || check(unsafe { (self.api.next)(self.handle) })? > 0 {
+                positioned = false;
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                if let Some(entry) = self.entry() {
+                    if sender.send(entry).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            check(unsafe { (self.api.wait)(self.handle, WAIT_USEC) })?;
+        }
+    }
+
+    /// The current entry, if it has a message
+    fn entry(&self) -> Option<JournalEntry> {
+        let message = self.field(c"MESSAGE")?;
+        let mut timestamp_us = 0;
+        if unsafe { (self.api.get_realtime_usec)(self.handle, &mut timestamp_us) } < 0 {
+            timestamp_us = 0;
+        }
+        let priority = self.field(c"PRIORITY").and_then(|value| value.parse().ok());
+        Some(journal_entry(timestamp_us, priority, &message))
+    }
+
+    /// Value of field `name` of the current entry
+    fn field(&self, name: &CStr) -> Option<String> {
+        let mut data = std::ptr::null();
+        let mut len = 0;
+        if unsafe { (self.api.get_data)(self.handle, name.as_ptr(), &mut data, &mut len) } < 0 {
+            return None;
+        }
+        // `NAME=value`, valid until the journal moves
+        let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) };
+        let value = data.get(name.to_bytes().len() + 1..)?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        unsafe { (self.api.close)(self.handle) };
+    }
+}
+
+/// sd-journal returns a negative errno on failure
+fn check(result: c_int) -> io::Result<c_int> {
+    if result < 0 {
+        Err(io::Error::from_raw_os_error(-result))
+    } else {
+        Ok(result)
+    }
+}
+
+/// Build an entry from its `MESSAGE` and `PRIORITY` (6, info, when missing)
+fn journal_entry(timestamp_us: u64, priority: Option<u8>, message: &str) -> JournalEntry {
+    let message = strip_ansi(message);
+    let priority = priority.unwrap_or(6);
+
+    let (level, message) = match serde_json::from_str::<Value>(&message) {
+        // `[logging] format = "json"` lines
+        Ok(Value::Object(object)) => {
+            let level = object
+                .get("level")
+                .and_then(Value::as_str)
+                .and_then(|name| name.parse().ok())
+                .unwrap_or_else(|| priority_level(priority));
+            let text = object
+                .get("fields")
+                .and_then(|fields| fields.get("message"))
+                .or_else(|| object.get("message"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or(message);
+            (level, text)
+        }
+        _ => (text_level(&message).unwrap_or_else(|| priority_level(priority)), message),
+    };
+
+    JournalEntry {
+        timestamp_us,
+        level,
+        message,
+    }
+}
+
+/// Level of a text-format log line (`2025-01-01T10:00:00Z  WARN target: ...`)
+fn text_level(message: &str) -> Option<Level> {
+    message
+        .split_whitespace()
+        .take(3)
+        .find_map(|word| match word {
+            "ERROR" => Some(Level::Error),
+            "WARN" => Some(Level::Warn),
+            "INFO" => Some(Level::Info),
+            "DEBUG" => Some(Level::Debug),
+            "TRACE" => Some(Level::Trace),
+            _ => None,
+        })
+}
+
+/// Log level for a syslog priority (0 = emerg .. 7 = debug)
+fn priority_level(priority: u8) -> Level {
+    match priority {
+        0..=3 => Level::Error,
+        4 => Level::Warn,
+        5 | 6 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+/// Drop `ESC [ ... letter` color sequences
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\u{1b}' {
+            if chars.next() == Some('[') {
+                for ch in chars.by_ref() {
+                    if ch.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        out.push(ch);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_entry() {
+        let message = "2025-01-01T10:00:00.000Z \u{1b}[33m WARN\u{1b}[0m keyrs_core::event: Device disconnected";
+        let entry = journal_entry(1_700_000_000_000_000, Some(6), message);
+        assert_eq!(entry.timestamp_us, 1_700_000_000_000_000);
+        assert_eq!(entry.level, Level::Warn);
+        assert_eq!(
+            entry.message,
+            "2025-01-01T10:00:00.000Z  WARN keyrs_core::event: Device disconnected"
+        );
+    }
+
+    #[test]
+    fn test_json_and_other_entries() {
+        let json = r#"{"timestamp":"t","level":"ERROR","fields":{"message":"grab failed"},"target":"keyrs"}"#;
+        let entry = journal_entry(0, Some(6), json);
+        assert_eq!(entry.level, Level::Error);
+        assert_eq!(entry.message, "grab failed");
+
+        // systemd's own lines carry their level in PRIORITY only
+        let entry = journal_entry(0, Some(4), "Started keyrs.service");
+        assert_eq!(entry.level, Level::Warn);
+        assert_eq!(entry.message, "Started keyrs.service");
+        assert_eq!(journal_entry(0, None, "keyrs").level, Level::Info);
+    }
+}
@@ -1,5 +1,6 @@
 mod app;
 mod handlers;
+mod journal;
 mod theme;
 mod ui;
 
@@ -19,6 +20,8 @@ use ui::draw_ui;
 pub fn run() -> io::Result<()> {
     let mut app = App::new()?;
     app.refresh_service_status(true);
+    app.refresh_live_settings(true);
+    app.refresh_config_check(true);
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -28,12 +31,18 @@ pub fn run() -> io::Result<()> {
 
     let run_result = loop {
         app.refresh_service_status(false);
+        app.refresh_live_settings(false);
+        app.refresh_config_check(false);
+        app.poll_capture();
+        app.refresh_journal();
 
         if let Err(err) = terminal.draw(|f| draw_ui(f, &app)) {
             break Err(err);
         }
 
-        if !event::poll(std::time::Duration::from_millis(200))? {
+        // Poll the keyboards often while a capture reads them
+        let wait = if app.capture.is_some() { 20 } else { 200 };
+        if !event::poll(std::time::Duration::from_millis(wait))? {
             continue;
         }
 
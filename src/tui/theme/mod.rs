@@ -1,5 +1,7 @@
 mod palette;
 
+use std::collections::BTreeMap;
+
 use ratatui::prelude::*;
 
 pub use palette::Palette;
@@ -139,6 +141,31 @@ impl Theme {
 /// Global theme instance
 pub static THEME: std::sync::OnceLock<Theme> = std::sync::OnceLock::new();
 
+/// Install the theme from settings.toml `[tui.theme]` before the first draw;
+/// returns a message for every value that was ignored
+pub fn init(palette: Option<&str>, colors: &BTreeMap<String, String>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut base = match palette {
+        None => Palette::new(),
+        Some(name) => Palette::named(name).unwrap_or_else(|| {
+            warnings.push(format!("Unknown theme palette '{}' (use dark or light)", name));
+            Palette::new()
+        }),
+    };
+    for (name, value) in colors {
+        match value.parse::<Color>() {
+            Ok(color) => {
+                if !base.set(name, color) {
+                    warnings.push(format!("Unknown theme color '{}'", name));
+                }
+            }
+            Err(_) => warnings.push(format!("Invalid color '{}' for theme color '{}'", value, name)),
+        }
+    }
+    let _ = THEME.set(Theme { palette: base });
+    warnings
+}
+
 pub fn theme() -> &'static Theme {
     THEME.get_or_init(Theme::new)
 }
@@ -1,6 +1,6 @@
 use ratatui::prelude::*;
 
-/// Catppuccin Mocha inspired color palette for the TUI
+/// Catppuccin Mocha inspired color palette for the TUI (Latte for `light`)
 pub struct Palette {
     // Text colors
     pub text_primary: Color,
@@ -57,4 +57,59 @@ impl Palette {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Catppuccin Latte counterpart of the default palette
+    pub fn light() -> Self {
+        Self {
+            text_primary: Color::Rgb(76, 79, 105),        // Text
+            text_secondary: Color::Rgb(92, 95, 119),      // Subtext1
+            text_muted: Color::Rgb(140, 143, 161),        // Overlay1
+
+            accent_primary: Color::Rgb(30, 102, 245),     // Blue
+            accent_success: Color::Rgb(64, 160, 43),      // Green
+            accent_warning: Color::Rgb(223, 142, 29),     // Yellow
+            accent_danger: Color::Rgb(210, 15, 57),       // Red
+
+            border_default: Color::Rgb(172, 176, 190),    // Surface2
+            border_focused: Color::Rgb(30, 102, 245),     // Blue
+            selection_bg: Color::Rgb(30, 102, 245),       // Blue
+            selection_fg: Color::Rgb(239, 241, 245),      // Base
+
+            bg_status_active: Color::Rgb(64, 160, 43),    // Green
+            bg_status_inactive: Color::Rgb(210, 15, 57),  // Red
+            bg_status_unknown: Color::Rgb(223, 142, 29),  // Yellow
+        }
+    }
+
+    /// Palette for a `[tui.theme] palette` name
+    pub fn named(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "dark" | "default" => Some(Self::default()),
+            "light" => Some(Self::light()),
+            _ => None,
+        }
+    }
+
+    /// Override the color called `name`; false for an unknown name
+    pub fn set(&mut self, name: &str, color: Color) -> bool {
+        let slot = match name {
+            "text_primary" => &mut self.text_primary,
+            "text_secondary" => &mut self.text_secondary,
+            "text_muted" => &mut self.text_muted,
+            "accent_primary" => &mut self.accent_primary,
+            "accent_success" => &mut self.accent_success,
+            "accent_warning" => &mut self.accent_warning,
+            "accent_danger" => &mut self.accent_danger,
+            "border_default" => &mut self.border_default,
+            "border_focused" => &mut self.border_focused,
+            "selection_bg" => &mut self.selection_bg,
+            "selection_fg" => &mut self.selection_fg,
+            "bg_status_active" => &mut self.bg_status_active,
+            "bg_status_inactive" => &mut self.bg_status_inactive,
+            "bg_status_unknown" => &mut self.bg_status_unknown,
+            _ => return false,
+        };
+        *slot = color;
+        true
+    }
 }
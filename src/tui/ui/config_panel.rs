@@ -0,0 +1,98 @@
+use ratatui::prelude::*;
+use ratatui::widgets::*;
+
+use crate::tui::app::{App, ConfigProblem, Pane};
+use crate::tui::theme::theme;
+
+pub fn render(frame: &mut Frame, app: &App, area: Rect) {
+    let t = theme();
+    let focused = app.focused_pane == Pane::Config;
+
+    let block = Block::default()
+        .title(t.panel_title("CONFIG CHECK", focused))
+        .borders(Borders::ALL)
+        .border_style(t.panel_border(focused))
+        .border_type(if focused {
+            BorderType::Thick
+        } else {
+            BorderType::Plain
+        });
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let problems = match &app.config_problems {
+        None => {
+            frame.render_widget(Paragraph::new(Line::styled("Checking...", t.text_muted())), inner);
+            return;
+        }
+        Some(problems) if problems.is_empty() => {
+            let valid = Paragraph::new(Line::styled(
+                format!("{} is valid", app.config_path.display()),
+                Style::default().fg(t.palette.accent_success),
+            ));
+            frame.render_widget(valid, inner);
+            return;
+        }
+        Some(problems) => problems,
+    };
+
+    // Keep the selected problem visible
+    let visible_height = inner.height as usize;
+    let start = app
+        .config_problem_index
+        .saturating_sub(visible_height.saturating_sub(1));
+    let end = (start + visible_height).min(problems.len());
+
+    let lines: Vec<Line> = problems[start..end]
+        .iter()
+        .enumerate()
+        .map(|(offset, problem)| {
+            let sel = focused && start + offset == app.config_problem_index;
+            let prefix = if sel { ">" } else { " " };
+
+            let (label, label_color, location, message) = match problem {
+                ConfigProblem::Error(diagnostic) => {
+                    // Name the file only for problems in included fragments
+                    let mut location = match &diagnostic.file {
+                        Some(file) if *file != app.config_path => file
+                            .file_name()
+                            .map(|name| format!("{}:", name.to_string_lossy()))
+                            .unwrap_or_default(),
+                        _ => String::new(),
+                    };
+                    if diagnostic.line > 0 {
+                        location.push_str(&format!("{}:{}", diagnostic.line, diagnostic.column));
+                    }
+                    ("error", t.palette.accent_danger, location, diagnostic.message.as_str())
+                }
+                ConfigProblem::Warning(message) => {
+                    ("warning", t.palette.accent_warning, String::new(), message.as_str())
+                }
+            };
+
+            let message_style = if sel {
+                Style::default()
+                    .fg(t.palette.selection_fg)
+                    .bg(t.palette.selection_bg)
+            } else {
+                t.text_secondary()
+            };
+
+            let mut spans = vec![
+                Span::styled(
+                    prefix,
+                    Style::default().fg(if sel { t.palette.accent_primary } else { t.palette.text_muted }),
+                ),
+                Span::styled(format!("{:<8}", label), Style::default().fg(label_color)),
+            ];
+            if !location.is_empty() {
+                spans.push(Span::styled(format!("{} ", location), t.text_muted()));
+            }
+            spans.push(Span::styled(message.to_string(), message_style));
+            Line::from(spans)
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
@@ -57,6 +57,9 @@ fn render_status_and_hints(frame: &mut Frame, app: &App, area: Rect, t: &crate::
                 let hint = match entry {
                     crate::tui::app::SettingEntry::LayoutOptspec => "cycle ABC ↔ US",
                     crate::tui::app::SettingEntry::KeyboardOverride => "cycle keyboard type",
+                    crate::tui::app::SettingEntry::Feature(_) if app.live_settings.is_some() => {
+                        "toggle in running daemon"
+                    }
                     crate::tui::app::SettingEntry::Feature(_) => "toggle on/off",
                 };
                 Line::from(vec![
@@ -70,9 +73,110 @@ fn render_status_and_hints(frame: &mut Frame, app: &App, area: Rect, t: &crate::
                 Line::styled(format!("Status: {}", app.status), t.status_message())
             }
         }
+        crate::tui::app::Pane::Keymaps => {
+            if let Some(entry) = app.selected_config_entry() {
+                let hint = if entry.kind == keyrs_core::config::edit::OutlineKind::Keymap {
+                    if entry.enabled { " disable keymap " } else { " enable keymap " }
+                } else {
+                    " (only keymaps can be toggled) "
+                };
+                Line::from(vec![
+                    Span::styled(format!("{} ~ ", entry.name), t.text_muted()),
+                    Span::styled("Enter", t.key_hint()),
+                    Span::styled(hint, t.text_muted()),
+                    Span::styled("c", t.key_hint()),
+                    Span::styled(":capture mapping  ", t.text_muted()),
+                    Span::styled("m", t.key_hint()),
+                    Span::styled(":type mapping  ", t.text_muted()),
+                    Span::styled("r", t.key_hint()),
+                    Span::styled(":reload", t.text_muted()),
+                ])
+            } else {
+                Line::styled(format!("Status: {}", app.status), t.status_message())
+            }
+        }
+        crate::tui::app::Pane::Config => {
+            let summary = match &app.config_problems {
+                None => "not checked yet".to_string(),
+                Some(problems) if problems.is_empty() => "valid".to_string(),
+                Some(problems) => format!("{} problem(s)", problems.len()),
+            };
+            Line::from(vec![
+                Span::styled(format!("Config check ~ {} (rechecked on save) ", summary), t.text_muted()),
+                Span::styled("r", t.key_hint()),
+                Span::styled(":recheck", t.text_muted()),
+            ])
+        }
         crate::tui::app::Pane::Output => {
-            Line::styled(format!("Output log ~ {} lines", app.output.len()), t.text_muted())
+            if app.search_editing {
+                Line::from(vec![
+                    Span::styled(format!("Search: {}_ ", app.output_search), t.text_primary()),
+                    Span::styled("Enter", t.key_hint()),
+                    Span::styled(":keep  ", t.text_muted()),
+                    Span::styled("Esc", t.key_hint()),
+                    Span::styled(":clear", t.text_muted()),
+                ])
+            } else {
+                let filter = if app.output_search.is_empty() {
+                    String::new()
+                } else {
+                    format!(", matching '{}'", app.output_search)
+                };
+                Line::from(vec![
+                    Span::styled(
+                        format!(
+                            "keyrs journal ~ {} lines{}, follow {} ",
+                            app.visible_output().len(),
+                            filter,
+                            if app.output_follow { "on" } else { "off" }
+                        ),
+                        t.text_muted(),
+                    ),
+                    Span::styled("f", t.key_hint()),
+                    Span::styled(":follow  ", t.text_muted()),
+                    Span::styled("/", t.key_hint()),
+                    Span::styled(":search", t.text_muted()),
+                ])
+            }
+        }
+    };
+
+    // A capture in progress replaces the pane hint
+    let line1 = match &app.capture {
+        Some(capture) => {
+            let prompt = match &capture.trigger {
+                None => format!("New mapping in '{}' ~ press the trigger combo ", capture.keymap_name),
+                Some(trigger) => format!("\"{}\" = ? ~ press the output combo ", trigger),
+            };
+            Line::from(vec![
+                Span::styled(prompt, t.confirm_prompt()),
+                Span::styled("Esc", t.key_hint()),
+                Span::styled(":cancel", t.text_muted()),
+            ])
+        }
+        None => line1,
+    };
+
+    // So does a mapping being typed
+    let line1 = match &app.mapping_entry {
+        Some(entry) => {
+            let prompt = match &entry.trigger {
+                None => format!("New mapping in '{}' ~ trigger: {}_ ", entry.keymap_name, entry.text),
+                Some(trigger) => format!("\"{}\" = {}_ ", trigger, entry.text),
+            };
+            Line::from(vec![
+                Span::styled(prompt, t.confirm_prompt()),
+                Span::styled("Tab", t.key_hint()),
+                Span::styled(":complete  ", t.text_muted()),
+                Span::styled("Enter", t.key_hint()),
+                Span::styled(":accept  ", t.text_muted()),
+                Span::styled("Esc", t.key_hint()),
+                Span::styled(":cancel  ", t.text_muted()),
+                // Completions and errors
+                Span::styled(app.status.clone(), t.status_message()),
+            ])
         }
+        None => line1,
     };
 
     // Line 2: Key hints for the 3-block layout
@@ -83,7 +187,11 @@ fn render_status_and_hints(frame: &mut Frame, app: &App, area: Rect, t: &crate::
         Span::styled(" ", t.text_muted()),
         Span::styled("2-Settings", if app.focused_pane == crate::tui::app::Pane::Settings { t.key_hint() } else { t.text_muted() }),
         Span::styled(" ", t.text_muted()),
-        Span::styled("3-Output", if app.focused_pane == crate::tui::app::Pane::Output { t.key_hint() } else { t.text_muted() }),
+        Span::styled("3-Keymaps", if app.focused_pane == crate::tui::app::Pane::Keymaps { t.key_hint() } else { t.text_muted() }),
+        Span::styled(" ", t.text_muted()),
+        Span::styled("4-Config", if app.focused_pane == crate::tui::app::Pane::Config { t.key_hint() } else { t.text_muted() }),
+        Span::styled(" ", t.text_muted()),
+        Span::styled("5-Output", if app.focused_pane == crate::tui::app::Pane::Output { t.key_hint() } else { t.text_muted() }),
         Span::styled("  ", t.text_muted()),
         Span::styled("arrows", t.key_hint()),
         Span::styled(":navigate  ", t.text_muted()),
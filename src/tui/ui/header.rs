@@ -15,7 +15,9 @@ pub fn render(frame: &mut Frame, app: &App, area: Rect) {
     };
 
     let state_upper = app.service_state.to_uppercase();
+    let suspended = app.daemon_status.as_ref().is_some_and(|status| status.suspended);
     let state_label = match app.service_state.as_str() {
+        "active" if suspended => "SUSPENDED",
         "active" => "RUNNING",
         "inactive" => "STOPPED",
         "failed" => "FAILED",
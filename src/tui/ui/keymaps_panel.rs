@@ -0,0 +1,83 @@
+use keyrs_core::config::edit::OutlineKind;
+use ratatui::prelude::*;
+use ratatui::widgets::*;
+
+use crate::tui::app::{App, Pane};
+use crate::tui::theme::theme;
+
+pub fn render(frame: &mut Frame, app: &App, area: Rect) {
+    let t = theme();
+    let focused = app.focused_pane == Pane::Keymaps;
+
+    let block = Block::default()
+        .title(t.panel_title("KEYMAPS", focused))
+        .borders(Borders::ALL)
+        .border_style(t.panel_border(focused))
+        .border_type(if focused {
+            BorderType::Thick
+        } else {
+            BorderType::Plain
+        });
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    if app.config_entries.is_empty() {
+        let empty = Paragraph::new(Line::styled(
+            format!("No entries found in {}", app.config_path.display()),
+            t.text_muted(),
+        ));
+        frame.render_widget(empty, inner);
+        return;
+    }
+
+    // Keep the selected entry visible
+    let visible_height = inner.height as usize;
+    let start = app
+        .config_entry_index
+        .saturating_sub(visible_height.saturating_sub(1));
+    let end = (start + visible_height).min(app.config_entries.len());
+
+    let lines: Vec<Line> = app.config_entries[start..end]
+        .iter()
+        .enumerate()
+        .map(|(offset, entry)| {
+            let sel = focused && start + offset == app.config_entry_index;
+            let prefix = if sel { ">" } else { " " };
+
+            let checkbox = match entry.kind {
+                OutlineKind::Keymap if entry.enabled => "[x]",
+                OutlineKind::Keymap => "[ ]",
+                _ => " - ",
+            };
+
+            let name_style = if sel {
+                Style::default()
+                    .fg(t.palette.selection_fg)
+                    .bg(t.palette.selection_bg)
+                    .add_modifier(Modifier::BOLD)
+            } else if entry.enabled {
+                t.text_secondary()
+            } else {
+                t.text_muted()
+            };
+
+            let mut spans = vec![
+                Span::styled(
+                    prefix,
+                    Style::default().fg(if sel { t.palette.accent_primary } else { t.palette.text_muted }),
+                ),
+                Span::styled(checkbox, t.checkbox(entry.enabled)),
+                Span::styled(format!(" {:<13}", entry.kind.as_str()), t.text_muted()),
+                Span::styled(entry.name.clone(), name_style),
+                Span::styled(format!("  ({} mappings)", entry.mappings), t.text_muted()),
+            ];
+            if let Some(condition) = &entry.condition {
+                spans.push(Span::styled(format!("  when {}", condition), t.text_muted()));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
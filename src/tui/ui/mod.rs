@@ -1,5 +1,7 @@
+mod config_panel;
 mod footer;
 mod header;
+mod keymaps_panel;
 mod output_panel;
 mod settings_panel;
 
@@ -15,6 +17,7 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
             Constraint::Length(1),   // Compact header
             Constraint::Length(6),   // Service control block (status + commands)
             Constraint::Min(8),      // Settings block
+            Constraint::Min(6),      // Keymaps + config check blocks
             Constraint::Length(12),  // Output block (1.5x larger)
             Constraint::Length(2),   // Footer
         ])
@@ -23,8 +26,14 @@ pub fn draw_ui(frame: &mut Frame, app: &App) {
     header::render(frame, app, root[0]);
     render_service_control(frame, app, root[1]);
     settings_panel::render(frame, app, root[2]);
-    output_panel::render(frame, app, root[3]);
-    footer::render(frame, app, root[4]);
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+        .split(root[3]);
+    keymaps_panel::render(frame, app, middle[0]);
+    config_panel::render(frame, app, middle[1]);
+    output_panel::render(frame, app, root[4]);
+    footer::render(frame, app, root[5]);
 }
 
 fn render_service_control(frame: &mut Frame, app: &App, area: Rect) {
@@ -70,6 +79,14 @@ fn render_service_control(frame: &mut Frame, app: &App, area: Rect) {
                 Style::default().fg(t.palette.accent_warning)
             },
         ),
+        Span::styled(
+            if app.service_sub_state.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", app.service_sub_state)
+            },
+            t.text_muted(),
+        ),
     ]);
 
     let status_para = Paragraph::new(status_line);
@@ -1,3 +1,4 @@
+use log::Level;
 use ratatui::prelude::*;
 use ratatui::widgets::*;
 
@@ -8,8 +9,9 @@ pub fn render(frame: &mut Frame, app: &App, area: Rect) {
     let t = theme();
     let focused = app.focused_pane == Pane::Output;
 
+    let title = if app.output_follow { "OUTPUT LOG" } else { "OUTPUT LOG (paused)" };
     let block = Block::default()
-        .title(t.panel_title("OUTPUT LOG", focused))
+        .title(t.panel_title(title, focused))
         .borders(Borders::ALL)
         .border_style(t.panel_border(focused))
         .border_type(if focused {
@@ -21,35 +23,38 @@ pub fn render(frame: &mut Frame, app: &App, area: Rect) {
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
-    if app.output.is_empty() {
-        let empty = Paragraph::new(Line::styled(
-            "No output yet. Run a command to see output here.",
-            t.text_muted(),
-        ));
-        frame.render_widget(empty, inner);
+    let visible = app.visible_output();
+    if visible.is_empty() {
+        let message = if app.output.is_empty() {
+            "No output yet. keyrs journal entries and command output appear here.".to_string()
+        } else {
+            format!("No lines match '{}'.", app.output_search)
+        };
+        frame.render_widget(Paragraph::new(Line::styled(message, t.text_muted())), inner);
         return;
     }
 
     // Calculate visible lines based on inner area height
     let visible_height = inner.height.saturating_sub(1) as usize;
-    let total_lines = app.output.len();
+    let total_lines = visible.len();
 
-    // Clamp scroll position
+    // Scroll counts lines back from the end
     let max_scroll = total_lines.saturating_sub(visible_height);
-    let scroll = app.output_scroll.min(max_scroll);
-
-    // Get the visible slice of output
-    let start = scroll;
+    let start = max_scroll.saturating_sub(app.output_scroll);
     let end = (start + visible_height).min(total_lines);
 
-    let lines: Vec<Line> = app.output[start..end]
+    let lines: Vec<Line> = visible[start..end]
         .iter()
         .map(|l| {
-            if l.starts_with('$') {
-                Line::styled(l.clone(), t.output_command())
-            } else {
-                Line::styled(l.clone(), t.output_text())
-            }
+            let style = match l.level {
+                Some(Level::Error) => Style::default().fg(t.palette.accent_danger),
+                Some(Level::Warn) => Style::default().fg(t.palette.accent_warning),
+                Some(Level::Info) => t.output_text(),
+                Some(Level::Debug | Level::Trace) => t.text_muted(),
+                None if l.text.starts_with('$') => t.output_command(),
+                None => t.text_secondary(),
+            };
+            Line::styled(l.text.clone(), style)
         })
         .collect();
 
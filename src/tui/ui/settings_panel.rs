@@ -74,6 +74,9 @@ fn render_settings_grid(frame: &mut Frame, app: &App, area: Rect, focused: bool)
         },
     ));
     row1_spans.push(Span::raw("   "));
+    if app.live_settings.is_some() {
+        row1_spans.push(Span::styled("live|disk  w:write live  ", t.key_hint()));
+    }
     row1_spans.push(Span::styled("s:save  a:save+restart", t.key_hint()));
 
     let row1_para = Paragraph::new(Line::from(row1_spans));
@@ -88,8 +91,12 @@ fn render_settings_grid(frame: &mut Frame, app: &App, area: Rect, focused: bool)
         return;
     }
 
+    // With the daemon reachable each feature shows its live and on-disk value
+    let live = app.live_settings.as_ref();
+    let col_width = if live.is_some() { COL_WIDTH + 3 } else { COL_WIDTH };
+
     // Calculate number of columns based on available width
-    let num_cols = (chunks[1].width as usize / COL_WIDTH as u16 as usize).max(1).min(3);
+    let num_cols = (chunks[1].width as usize / col_width).clamp(1, 3);
 
     // Build rows of features (row-first order) - this matches linear navigation
     let mut lines: Vec<Line<'static>> = Vec::new();
@@ -122,11 +129,19 @@ fn render_settings_grid(frame: &mut Frame, app: &App, area: Rect, focused: bool)
                 Style::default().fg(if sel { t.palette.accent_primary } else { t.palette.text_muted }),
             ));
 
-            // Checkbox
+            // Checkbox (live value first when the daemon is reachable)
+            if let Some(live) = live {
+                let live_enabled = live.get(key).copied().unwrap_or(false);
+                spans.push(Span::styled(
+                    if live_enabled { "[x]" } else { "[ ]" },
+                    t.checkbox(live_enabled),
+                ));
+            }
             spans.push(Span::styled(checkbox, t.checkbox(enabled)));
 
             // Key name with padding
-            let key_text = format!(" {}{}", short_key, " ".repeat(COL_WIDTH.saturating_sub(5 + short_key.len())));
+            let used = if live.is_some() { 8 } else { 5 };
+            let key_text = format!(" {}{}", short_key, " ".repeat(col_width.saturating_sub(used + short_key.len())));
             spans.push(Span::styled(
                 key_text,
                 Style::default().fg(if sel {